@@ -29,6 +29,7 @@ show_help() {
     echo "  atomic      Run atomic operations and regression tests only"
     echo "  monitoring  Run tests requiring monitoring config (slowlog, monitor, stats)"
     echo "  load        Run high-load stress tests with optimized server"
+    echo "  e2e         Run client library scenarios (redis-py, node-redis) on a random port"
     echo "  all         Run all test configurations"
     echo "  help        Show this help"
     echo ""
@@ -493,6 +494,10 @@ run_unit_tests() {
     
     cargo test --release
     
+    echo ""
+    echo "Checking scripting allocation budgets..."
+    cargo test --release --features alloc-profile --test alloc_budget
+    
     echo ""
     echo "✅ Unit tests completed successfully"
 }
@@ -626,6 +631,15 @@ run_monitoring_tests() {
     echo "✅ Monitoring tests completed (some may be pending full config implementation)"
 }
 
+run_e2e_tests() {
+    echo "========================================="
+    echo "RUNNING END-TO-END CLIENT LIBRARY TESTS"
+    echo "========================================="
+    
+    # The harness boots its own server on a random port
+    ./tests/e2e/run_e2e.sh
+}
+
 run_high_load_tests() {
     echo "========================================="
     echo "RUNNING HIGH LOAD TESTS"
@@ -710,6 +724,9 @@ case "${1:-help}" in
     load)
         run_high_load_tests
         ;;
+    e2e)
+        run_e2e_tests
+        ;;
     all)
         run_unit_tests
         run_default_tests  
@@ -8,7 +8,7 @@ use std::fs;
 use std::io::{self, Write, BufRead};
 use std::path::Path;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use ferrous::storage::engine::StorageEngine;
 use ferrous::storage::commands::lua::handle_eval;
 use ferrous::protocol::resp::RespFrame;
@@ -19,6 +19,9 @@ struct CliConfig {
     instruction_limit: usize,
     timeout_seconds: u64,
     verbose: bool,
+    capture_output: bool,
+    json: bool,
+    filter: Option<String>,
 }
 
 impl Default for CliConfig {
@@ -28,6 +31,9 @@ impl Default for CliConfig {
             instruction_limit: 1_000_000,
             timeout_seconds: 5,
             verbose: false,
+            capture_output: false,
+            json: false,
+            filter: None,
         }
     }
 }
@@ -94,6 +100,22 @@ fn main() {
                 config.verbose = true;
                 i += 1;
             }
+            "--capture-output" => {
+                config.capture_output = true;
+                i += 1;
+            }
+            "--json" => {
+                config.json = true;
+                i += 1;
+            }
+            "--filter" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: --filter requires a pattern");
+                    std::process::exit(1);
+                }
+                config.filter = Some(args[i + 1].clone());
+                i += 2;
+            }
             "-t" | "--test" => {
                 if i + 1 >= args.len() {
                     eprintln!("Error: --test requires a directory");
@@ -330,6 +352,14 @@ fn print_response(response: &RespFrame, verbose: bool) {
                 println!();
             }
         }
+        RespFrame::Push(items) => {
+            println!("Push with {} items:", items.len());
+            for item in items {
+                print!("  ");
+                print_response_inline(item);
+                println!();
+            }
+        }
     }
 }
 
@@ -376,6 +406,9 @@ fn print_response_inline(response: &RespFrame) {
         RespFrame::Set(_) => {
             print!("(set)");
         }
+        RespFrame::Push(_) => {
+            print!("(push)");
+        }
     }
 }
 
@@ -469,9 +502,28 @@ fn print_repl_help() {
     println!("  redis.call/pcall     Basic Redis command placeholders");
 }
 
+/// Wrapper collecting what a test script prints; it replies `{output, result}`
+///
+/// The prelude is one line so error messages keep the script's line numbers.
+const CAPTURE_PRELUDE: &str = "local output = {} \
+    print = function(...) local line = {} for i = 1, select('#', ...) do line[i] = tostring((select(i, ...))) end \
+    output[#output + 1] = table.concat(line, '\\t') .. '\\n' end \
+    local ok, result = pcall(function() ";
+const CAPTURE_EPILOGUE: &str = "\nend)\nif not ok then error(result, 0) end\nreturn {table.concat(output), result}\n";
+
+/// Outcome of one test script
+struct TestOutcome {
+    name: String,
+    passed: bool,
+    elapsed: Duration,
+    /// Why the test failed, or the script's reply when it passed
+    detail: String,
+    /// Printed output, with --capture-output
+    output: Option<String>,
+    memory: usize,
+}
+
 fn run_test_directory(test_dir: &str, config: &CliConfig) {
-    println!("Running all .lua test files in: {}", test_dir);
-    
     let test_path = Path::new(test_dir);
     if !test_path.exists() {
         eprintln!("Error: Test directory {} does not exist", test_dir);
@@ -482,12 +534,14 @@ fn run_test_directory(test_dir: &str, config: &CliConfig) {
     
     match fs::read_dir(test_path) {
         Ok(entries) => {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if path.extension().and_then(|s| s.to_str()) == Some("lua") {
-                        test_files.push(path);
-                    }
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().and_then(|s| s.to_str()) != Some("lua") {
+                    continue;
+                }
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                if config.filter.as_ref().is_none_or(|filter| name.contains(filter.as_str())) {
+                    test_files.push(path);
                 }
             }
         }
@@ -499,78 +553,170 @@ fn run_test_directory(test_dir: &str, config: &CliConfig) {
     
     test_files.sort();
     
-    if test_files.is_empty() {
-        println!("No .lua files found in {}", test_dir);
-        return;
+    if !config.json {
+        println!("Running all .lua test files in: {}", test_dir);
+        if test_files.is_empty() {
+            println!("No .lua files found in {}", test_dir);
+            return;
+        }
     }
     
-    let mut passed = 0;
-    let mut failed = 0;
-    
+    let mut outcomes = Vec::new();
     for test_file in test_files {
-        let filename = test_file.file_name().unwrap().to_string_lossy();
-        print!("Running test: {} ... ", filename);
-        io::stdout().flush().unwrap();
+        if !config.json {
+            print!("Running test: {} ... ", test_file.file_name().unwrap().to_string_lossy());
+            io::stdout().flush().unwrap();
+        }
         
-        match fs::read_to_string(&test_file) {
-            Ok(script) => {
-                let storage = Arc::new(StorageEngine::new_in_memory());
-                let parts = vec![
-                    RespFrame::BulkString(Some(Arc::new("EVAL".as_bytes().to_vec()))),
-                    RespFrame::BulkString(Some(Arc::new(script.as_bytes().to_vec()))),
-                    RespFrame::Integer(0),
-                ];
-                
-                let start = Instant::now();
-                let result = handle_eval(&storage, &parts);
-                let elapsed = start.elapsed();
-                
-                match result {
-                    Ok(response) => {
-                        let test_passed = match response {
-                            RespFrame::Integer(1) => true, // Lua true -> Redis integer 1
-                            RespFrame::BulkString(Some(ref bytes)) if bytes.as_ref() == b"PASS" => true,
-                            RespFrame::SimpleString(ref bytes) if bytes.as_ref() == b"PASS" => true,
-                            RespFrame::Error(_) => false,
-                            _ => true, // No explicit pass/fail, assume pass if no error
-                        };
-                        
-                        if test_passed {
-                            println!("PASS ({:?})", elapsed);
-                            passed += 1;
-                        } else {
-                            println!("FAIL - Script returned: {:?}", response);
-                            failed += 1;
-                        }
-                        
-                        if config.verbose {
-                            println!("  Result: {:?}", response);
-                            println!("  Memory: {} bytes", storage.memory_usage());
-                        }
-                    }
-                    Err(e) => {
-                        println!("FAIL - Error: {}", e);
-                        failed += 1;
-                    }
-                }
+        let outcome = run_test_file(&test_file, config);
+        if !config.json {
+            if outcome.passed {
+                println!("PASS ({:?})", outcome.elapsed);
+            } else {
+                println!("FAIL - {}", outcome.detail);
             }
-            Err(e) => {
-                println!("FAIL - Cannot read file: {}", e);
-                failed += 1;
+            if config.verbose {
+                println!("  Result: {}", outcome.detail);
+                if let Some(output) = &outcome.output {
+                    println!("  Output: {:?}", output);
+                }
+                println!("  Memory: {} bytes", outcome.memory);
             }
         }
+        outcomes.push(outcome);
     }
     
-    println!("\nTest Results:");
-    println!("  Passed: {}", passed);
-    println!("  Failed: {}", failed);
-    println!("  Total:  {}", passed + failed);
+    let passed = outcomes.iter().filter(|outcome| outcome.passed).count();
+    let failed = outcomes.len() - passed;
+    
+    if config.json {
+        println!("{}", results_json(&outcomes));
+    } else {
+        println!("\nTest Results:");
+        println!("  Passed: {}", passed);
+        println!("  Failed: {}", failed);
+        println!("  Total:  {}", passed + failed);
+    }
     
     if failed > 0 {
         std::process::exit(1);
     }
 }
 
+/// Run one test script in a fresh storage engine
+///
+/// A test fails when it raises an error or returns an error reply. With
+/// --capture-output its printed output must also match `<name>.out` when that
+/// file exists.
+fn run_test_file(test_file: &Path, config: &CliConfig) -> TestOutcome {
+    let name = test_file.file_name().unwrap().to_string_lossy().into_owned();
+    let mut outcome = TestOutcome { name, passed: false, elapsed: Duration::ZERO, detail: String::new(), output: None, memory: 0 };
+    
+    let script = match fs::read_to_string(test_file) {
+        Ok(script) => script,
+        Err(e) => {
+            outcome.detail = format!("Cannot read file: {}", e);
+            return outcome;
+        }
+    };
+    let script = if config.capture_output { format!("{}{}{}", CAPTURE_PRELUDE, script, CAPTURE_EPILOGUE) } else { script };
+    
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    let parts = vec![
+        RespFrame::BulkString(Some(Arc::new("EVAL".as_bytes().to_vec()))),
+        RespFrame::BulkString(Some(Arc::new(script.into_bytes()))),
+        RespFrame::Integer(0),
+    ];
+    
+    let start = Instant::now();
+    let result = handle_eval(&storage, &parts);
+    outcome.elapsed = start.elapsed();
+    outcome.memory = storage.memory_usage();
+    
+    let response = match result {
+        Ok(response) if config.capture_output => match response {
+            RespFrame::Array(Some(mut items)) if !items.is_empty() => {
+                let reply = if items.len() > 1 { items.remove(1) } else { RespFrame::BulkString(None) };
+                if let RespFrame::BulkString(Some(output)) = &items[0] {
+                    outcome.output = Some(String::from_utf8_lossy(output).into_owned());
+                }
+                reply
+            }
+            other => other,
+        },
+        Ok(response) => response,
+        Err(e) => {
+            outcome.detail = format!("Error: {}", e);
+            return outcome;
+        }
+    };
+    
+    outcome.passed = match response {
+        RespFrame::Integer(1) => true, // Lua true -> Redis integer 1
+        RespFrame::BulkString(Some(ref bytes)) if bytes.as_ref() == b"PASS" => true,
+        RespFrame::SimpleString(ref bytes) if bytes.as_ref() == b"PASS" => true,
+        RespFrame::Error(_) => false,
+        _ => true, // No explicit pass/fail, assume pass if no error
+    };
+    outcome.detail = if outcome.passed { describe(&response) } else { format!("Script returned: {}", describe(&response)) };
+    
+    if let (true, Some(output)) = (outcome.passed, &outcome.output) {
+        let expected_file = test_file.with_extension("out");
+        if let Ok(expected) = fs::read_to_string(&expected_file) {
+            if *output != expected {
+                outcome.passed = false;
+                outcome.detail = format!("Output differs from {}: expected {:?}, got {:?}", expected_file.display(), expected, output);
+            }
+        }
+    }
+    
+    outcome
+}
+
+/// One-line description of a script reply
+fn describe(response: &RespFrame) -> String {
+    match response {
+        RespFrame::SimpleString(bytes) | RespFrame::BulkString(Some(bytes)) => String::from_utf8_lossy(bytes).into_owned(),
+        RespFrame::Error(bytes) => format!("Error: {}", String::from_utf8_lossy(bytes)),
+        RespFrame::Integer(n) => n.to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Render test outcomes as a JSON document for CI
+fn results_json(outcomes: &[TestOutcome]) -> String {
+    let passed = outcomes.iter().filter(|outcome| outcome.passed).count();
+    let tests: Vec<String> = outcomes.iter().map(|outcome| {
+        let output = match &outcome.output {
+            Some(output) => json_string(output),
+            None => "null".to_string(),
+        };
+        format!("{{\"name\":{},\"status\":\"{}\",\"duration_us\":{},\"detail\":{},\"output\":{},\"memory\":{}}}",
+            json_string(&outcome.name), if outcome.passed { "pass" } else { "fail" }, outcome.elapsed.as_micros(),
+            json_string(&outcome.detail), output, outcome.memory)
+    }).collect();
+    format!("{{\"passed\":{},\"failed\":{},\"tests\":[{}]}}", passed, outcomes.len() - passed, tests.join(","))
+}
+
+/// Quote a string as a JSON string literal
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
 fn print_usage() {
     println!("Ferrous Lua CLI Tool (MLua Lua 5.1)");
     println!("Usage: {} [options]", env::args().next().unwrap_or_else(|| "lua_cli".to_string()));
@@ -583,6 +729,9 @@ fn print_usage() {
     println!("  -i, --interactive             Start interactive REPL mode");
     println!("  -v, --verbose                 Verbose output");
     println!("  -t, --test <dir>              Run all .lua test files in directory");
+    println!("  --filter <pattern>            Only run test files whose name contains <pattern>");
+    println!("  --capture-output              Capture print() output; compare it to <test>.out if present");
+    println!("  --json                        Report test results as JSON");
     println!("  --memory-limit <mb>           Set memory limit in MB (default: 50)");
     println!("  --instruction-limit <count>   Set instruction limit (default: 1M)");
     println!("  --timeout <seconds>           Set timeout in seconds (default: 5)");
@@ -593,4 +742,5 @@ fn print_usage() {
     println!("  {} -f script.lua -k key1,key2 -a val1,val2", env::args().next().unwrap_or_else(|| "lua_cli".to_string()));
     println!("  {} -i", env::args().next().unwrap_or_else(|| "lua_cli".to_string()));
     println!("  {} -t tests/lua_scripts/", env::args().next().unwrap_or_else(|| "lua_cli".to_string()));
+    println!("  {} --capture-output --json --filter string -t tests/lua_scripts/", env::args().next().unwrap_or_else(|| "lua_cli".to_string()));
 }
\ No newline at end of file
@@ -0,0 +1,100 @@
+//! Ferrous Lua Replay Tool
+//! 
+//! Re-executes a script tape recorded by the server (see `lua-record-dir`)
+//! offline. redis.call replies come from the tape, so the original failure
+//! reproduces without access to the dataset it ran against.
+
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use ferrous::storage::lua_record::{replay, ScriptTape};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut tape_path = None;
+    let mut step = false;
+    
+    for arg in &args[1..] {
+        match arg.as_str() {
+            "-s" | "--step" => step = true,
+            "-h" | "--help" => {
+                print_usage();
+                return;
+            }
+            path => tape_path = Some(path.to_string()),
+        }
+    }
+    
+    let tape_path = match tape_path {
+        Some(path) => path,
+        None => {
+            print_usage();
+            std::process::exit(1);
+        }
+    };
+    
+    let tape = match ScriptTape::load(Path::new(&tape_path)) {
+        Ok(tape) => tape,
+        Err(e) => {
+            eprintln!("Error: cannot read tape {}: {}", tape_path, e);
+            std::process::exit(1);
+        }
+    };
+    
+    println!("Script ({} bytes), db {}, {} key(s), {} arg(s), {} recorded call(s)",
+             tape.script.len(), tape.db, tape.keys.len(), tape.args.len(), tape.calls.len());
+    for (i, call) in tape.calls.iter().enumerate() {
+        println!("  #{} {} -> {:?}", i + 1, render(&call.args), call.reply);
+    }
+    println!();
+    
+    let source: Vec<String> = tape.script.lines().map(|l| l.to_string()).collect();
+    let on_line: Option<Box<dyn Fn(usize) + Send>> = if step {
+        Some(Box::new(move |line| {
+            let text = source.get(line.wrapping_sub(1)).map(|s| s.as_str()).unwrap_or("");
+            print!("{:>4} | {}  [enter]", line, text);
+            let _ = io::stdout().flush();
+            let mut input = String::new();
+            let _ = io::stdin().lock().read_line(&mut input);
+        }))
+    } else {
+        None
+    };
+    
+    let replayed = replay(&tape, on_line).map_err(|e| e.to_string());
+    
+    println!("Recorded: {}", render_outcome(&tape.outcome));
+    println!("Replayed: {}", render_outcome(&replayed));
+    
+    if replayed == tape.outcome {
+        println!("✅ Replay reproduces the recorded outcome");
+    } else {
+        println!("❌ Replay outcome differs from the recording");
+        std::process::exit(2);
+    }
+}
+
+fn render(args: &[Vec<u8>]) -> String {
+    args.iter().map(|a| String::from_utf8_lossy(a).to_string()).collect::<Vec<_>>().join(" ")
+}
+
+fn render_outcome(outcome: &Result<ferrous::protocol::RespFrame, String>) -> String {
+    match outcome {
+        Ok(frame) => format!("{:?}", frame),
+        Err(e) => format!("error: {}", e),
+    }
+}
+
+fn print_usage() {
+    println!("Ferrous Lua Replay Tool");
+    println!();
+    println!("USAGE:");
+    println!("    lua_replay [OPTIONS] <TAPE>");
+    println!();
+    println!("OPTIONS:");
+    println!("    -s, --step    Pause before each executed source line");
+    println!("    -h, --help    Show this help");
+    println!();
+    println!("Tapes are written for failing scripts when the server runs with");
+    println!("`lua-record-dir <dir>` in its configuration.");
+}
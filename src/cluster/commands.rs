@@ -0,0 +1,256 @@
+//! CLUSTER command handlers
+
+use std::fmt::Write;
+use std::sync::Arc;
+use crate::error::Result;
+use crate::protocol::RespFrame;
+use crate::storage::StorageEngine;
+use super::slots::{key_hash_slot, parse_slot, SLOT_COUNT};
+use super::state::{ClusterNode, ClusterState, SlotMigration};
+
+/// Port offset of the cluster bus, reported in CLUSTER NODES
+const BUS_PORT_OFFSET: u32 = 10000;
+
+/// Handle CLUSTER command
+pub fn handle_cluster(
+    parts: &[RespFrame],
+    cluster: &ClusterState,
+    storage: &Arc<StorageEngine>,
+    db: usize,
+) -> Result<RespFrame> {
+    if parts.len() < 2 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'cluster' command"));
+    }
+    if !cluster.is_enabled() {
+        return Ok(RespFrame::error("ERR This instance has cluster support disabled"));
+    }
+    
+    let mut args = Vec::with_capacity(parts.len());
+    for part in parts {
+        match part {
+            RespFrame::BulkString(Some(bytes)) => args.push(bytes.as_slice()),
+            _ => return Ok(RespFrame::error("ERR syntax error")),
+        }
+    }
+    
+    let subcommand = String::from_utf8_lossy(args[1]).to_uppercase();
+    match (subcommand.as_str(), args.len()) {
+        ("INFO", 2) => Ok(RespFrame::from_string(cluster_info(cluster))),
+        ("MYID", 2) => Ok(RespFrame::from_string(cluster.myself().id)),
+        ("SLOTS", 2) => Ok(cluster_slots(cluster)),
+        ("SHARDS", 2) => Ok(cluster_shards(cluster)),
+        ("NODES", 2) => Ok(RespFrame::from_string(cluster_nodes(cluster))),
+        ("KEYSLOT", 3) => Ok(RespFrame::Integer(key_hash_slot(args[2]) as i64)),
+        ("COUNTKEYSINSLOT", 3) => {
+            let Some(slot) = parse_slot(args[2]) else {
+                return Ok(RespFrame::error("ERR Invalid slot"));
+            };
+            Ok(RespFrame::Integer(keys_in_slot(storage, db, slot, usize::MAX)?.len() as i64))
+        }
+        ("GETKEYSINSLOT", 4) => {
+            let Some(slot) = parse_slot(args[2]) else {
+                return Ok(RespFrame::error("ERR Invalid slot"));
+            };
+            let count = match String::from_utf8_lossy(args[3]).parse::<i64>() {
+                Ok(count) if count >= 0 => count as usize,
+                _ => return Ok(RespFrame::error("ERR Invalid number of keys")),
+            };
+            let keys = keys_in_slot(storage, db, slot, count)?;
+            Ok(RespFrame::array(keys.into_iter().map(RespFrame::from_bytes).collect()))
+        }
+        ("SETSLOT", 4 | 5) => {
+            let Some(slot) = parse_slot(args[2]) else {
+                return Ok(RespFrame::error("ERR Invalid or out of range slot"));
+            };
+            let action = String::from_utf8_lossy(args[3]).to_uppercase();
+            let node_id = args.get(4).map(|id| String::from_utf8_lossy(id).into_owned());
+            match cluster.set_slot(slot, &action, node_id.as_deref()) {
+                Ok(()) => Ok(RespFrame::ok()),
+                Err(error) => Ok(RespFrame::error(error)),
+            }
+        }
+        _ => Ok(RespFrame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try CLUSTER HELP.", subcommand))),
+    }
+}
+
+/// Up to `count` keys of `db` hashing to `slot`
+fn keys_in_slot(storage: &Arc<StorageEngine>, db: usize, slot: u16, count: usize) -> Result<Vec<Vec<u8>>> {
+    let mut keys: Vec<_> = storage.keys(db, b"*")?.into_iter()
+        .filter(|key| key_hash_slot(key) == slot)
+        .collect();
+    keys.sort();
+    keys.truncate(count);
+    Ok(keys)
+}
+
+fn cluster_info(cluster: &ClusterState) -> String {
+    let nodes = cluster.nodes();
+    let assigned = cluster.assigned_slots();
+    let size = nodes.iter().filter(|(_, ranges)| !ranges.is_empty()).count();
+    
+    let mut info = String::new();
+    let state = if assigned == SLOT_COUNT { "ok" } else { "fail" };
+    write!(info, "cluster_state:{}\r\n", state).unwrap();
+    write!(info, "cluster_slots_assigned:{}\r\n", assigned).unwrap();
+    write!(info, "cluster_slots_ok:{}\r\n", assigned).unwrap();
+    write!(info, "cluster_slots_pfail:0\r\n").unwrap();
+    write!(info, "cluster_slots_fail:0\r\n").unwrap();
+    write!(info, "cluster_known_nodes:{}\r\n", nodes.len()).unwrap();
+    write!(info, "cluster_size:{}\r\n", size).unwrap();
+    write!(info, "cluster_current_epoch:0\r\n").unwrap();
+    write!(info, "cluster_my_epoch:0\r\n").unwrap();
+    write!(info, "cluster_stats_messages_sent:0\r\n").unwrap();
+    write!(info, "cluster_stats_messages_received:0\r\n").unwrap();
+    info
+}
+
+/// CLUSTER SLOTS: one entry per contiguous range, in slot order
+fn cluster_slots(cluster: &ClusterState) -> RespFrame {
+    let mut ranges: Vec<(u16, u16, ClusterNode)> = cluster.nodes().into_iter()
+        .flat_map(|(node, ranges)| ranges.into_iter().map(move |(start, end)| (start, end, node.clone())))
+        .collect();
+    ranges.sort_by_key(|(start, _, _)| *start);
+    
+    RespFrame::array(ranges.into_iter().map(|(start, end, node)| RespFrame::array(vec![
+        RespFrame::Integer(start as i64),
+        RespFrame::Integer(end as i64),
+        RespFrame::array(vec![
+            RespFrame::from_string(node.host),
+            RespFrame::Integer(node.port as i64),
+            RespFrame::from_string(node.id),
+        ]),
+    ])).collect())
+}
+
+/// CLUSTER SHARDS: every node is the single master of its own shard
+fn cluster_shards(cluster: &ClusterState) -> RespFrame {
+    RespFrame::array(cluster.nodes().into_iter().map(|(node, ranges)| {
+        let slots = ranges.into_iter()
+            .flat_map(|(start, end)| [RespFrame::Integer(start as i64), RespFrame::Integer(end as i64)])
+            .collect();
+        let description = RespFrame::array(vec![
+            RespFrame::from_string("id"), RespFrame::from_string(node.id),
+            RespFrame::from_string("port"), RespFrame::Integer(node.port as i64),
+            RespFrame::from_string("ip"), RespFrame::from_string(node.host.clone()),
+            RespFrame::from_string("endpoint"), RespFrame::from_string(node.host),
+            RespFrame::from_string("role"), RespFrame::from_string("master"),
+            RespFrame::from_string("replication-offset"), RespFrame::Integer(0),
+            RespFrame::from_string("health"), RespFrame::from_string("online"),
+        ]);
+        RespFrame::array(vec![
+            RespFrame::from_string("slots"), RespFrame::array(slots),
+            RespFrame::from_string("nodes"), RespFrame::array(vec![description]),
+        ])
+    }).collect())
+}
+
+/// CLUSTER NODES, in the line format of nodes.conf
+fn cluster_nodes(cluster: &ClusterState) -> String {
+    let myself = cluster.myself_index();
+    let migrations = cluster.migrations();
+    
+    let mut output = String::new();
+    for (index, (node, ranges)) in cluster.nodes().into_iter().enumerate() {
+        let flags = if index == myself { "myself,master" } else { "master" };
+        write!(output, "{} {}:{}@{} {} - 0 0 0 connected",
+               node.id, node.host, node.port, node.port as u32 + BUS_PORT_OFFSET, flags).unwrap();
+        for (start, end) in ranges {
+            if start == end {
+                write!(output, " {}", start).unwrap();
+            } else {
+                write!(output, " {}-{}", start, end).unwrap();
+            }
+        }
+        if index == myself {
+            for (slot, migration, other) in &migrations {
+                match migration {
+                    SlotMigration::Migrating(_) => write!(output, " [{}->-{}]", slot, other.id).unwrap(),
+                    SlotMigration::Importing(_) => write!(output, " [{}-<-{}]", slot, other.id).unwrap(),
+                }
+            }
+        }
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::{ClusterConfig, ClusterNodeConfig};
+    
+    fn command(args: &[&str]) -> Vec<RespFrame> {
+        args.iter().map(|arg| RespFrame::bulk_string(arg.as_bytes())).collect()
+    }
+    
+    fn cluster() -> ClusterState {
+        let node = |id: &str, port, slots| ClusterNodeConfig {
+            id: id.to_string(), host: "127.0.0.1".to_string(), port, slots,
+        };
+        let config = ClusterConfig {
+            enabled: true,
+            node_id: Some("a".repeat(40)),
+            nodes: vec![
+                node(&"a".repeat(40), 7000, vec![(0, 8191)]),
+                node(&"b".repeat(40), 7001, vec![(8192, 16383)]),
+            ],
+        };
+        ClusterState::from_config(&config, "127.0.0.1", 7000)
+    }
+    
+    #[test]
+    fn test_cluster_introspection() {
+        let cluster = cluster();
+        let storage = StorageEngine::new();
+        let run = |args: &[&str]| handle_cluster(&command(args), &cluster, &storage, 0).unwrap();
+        
+        assert_eq!(run(&["CLUSTER", "MYID"]), RespFrame::from_string("a".repeat(40)));
+        assert_eq!(run(&["CLUSTER", "KEYSLOT", "{user}.name"]), RespFrame::Integer(key_hash_slot(b"user") as i64));
+        
+        let info = run(&["CLUSTER", "INFO"]).as_bulk_string_lossy().unwrap();
+        assert!(info.starts_with("cluster_state:ok\r\ncluster_slots_assigned:16384\r\n"));
+        assert!(info.contains("cluster_known_nodes:2\r\n"));
+        
+        assert_eq!(run(&["CLUSTER", "SLOTS"]), RespFrame::array(vec![
+            RespFrame::array(vec![RespFrame::Integer(0), RespFrame::Integer(8191), RespFrame::array(vec![
+                RespFrame::from_string("127.0.0.1"), RespFrame::Integer(7000), RespFrame::from_string("a".repeat(40)),
+            ])]),
+            RespFrame::array(vec![RespFrame::Integer(8192), RespFrame::Integer(16383), RespFrame::array(vec![
+                RespFrame::from_string("127.0.0.1"), RespFrame::Integer(7001), RespFrame::from_string("b".repeat(40)),
+            ])]),
+        ]));
+        
+        assert_eq!(run(&["CLUSTER", "SETSLOT", "5", "MIGRATING", &"b".repeat(40)]), RespFrame::ok());
+        let nodes = run(&["CLUSTER", "NODES"]).as_bulk_string_lossy().unwrap();
+        assert_eq!(nodes, format!(
+            "{a} 127.0.0.1:7000@17000 myself,master - 0 0 0 connected 0-8191 [5->-{b}]\n\
+             {b} 127.0.0.1:7001@17001 master - 0 0 0 connected 8192-16383\n",
+            a = "a".repeat(40), b = "b".repeat(40)));
+        
+        assert_eq!(run(&["CLUSTER", "SETSLOT", "16384", "STABLE"]), RespFrame::error("ERR Invalid or out of range slot"));
+    }
+    
+    #[test]
+    fn test_keys_in_slot() {
+        let cluster = cluster();
+        let storage = StorageEngine::new();
+        for key in ["{t}1", "{t}2", "{t}3", "other"] {
+            storage.set_string(0, key.as_bytes().to_vec(), b"v".to_vec()).unwrap();
+        }
+        let slot = key_hash_slot(b"t").to_string();
+        let run = |args: &[&str]| handle_cluster(&command(args), &cluster, &storage, 0).unwrap();
+        
+        assert_eq!(run(&["CLUSTER", "COUNTKEYSINSLOT", &slot]), RespFrame::Integer(3));
+        assert_eq!(run(&["CLUSTER", "GETKEYSINSLOT", &slot, "2"]), RespFrame::array(vec![
+            RespFrame::from_string("{t}1"), RespFrame::from_string("{t}2"),
+        ]));
+    }
+    
+    #[test]
+    fn test_cluster_disabled() {
+        let storage = StorageEngine::new();
+        assert_eq!(handle_cluster(&command(&["CLUSTER", "INFO"]), &ClusterState::disabled(), &storage, 0).unwrap(),
+                   RespFrame::error("ERR This instance has cluster support disabled"));
+    }
+}
@@ -0,0 +1,46 @@
+//! Cluster mode for ferrous
+//!
+//! Implements the client-facing side of Redis Cluster:
+//! - Hash slot calculation with hash tags
+//! - A static slot map shared by every node of a shard set
+//! - -MOVED/-ASK redirection of commands on keys served elsewhere
+//! - CLUSTER INFO/MYID/SLOTS/SHARDS/NODES/KEYSLOT and slot migration via SETSLOT
+//!
+//! There is no cluster bus yet: nodes do not gossip or fail over, so every
+//! node must be given the same `cluster-node` lines.
+
+pub mod slots;
+pub mod state;
+pub mod commands;
+
+pub use state::ClusterState;
+pub use commands::handle_cluster;
+
+/// A node of the static slot map, from a `cluster-node` line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterNodeConfig {
+    /// Node ID
+    pub id: String,
+    
+    /// Host clients reach the node on
+    pub host: String,
+    
+    /// Port clients reach the node on
+    pub port: u16,
+    
+    /// Inclusive slot ranges the node serves
+    pub slots: Vec<(u16, u16)>,
+}
+
+/// Cluster configuration
+#[derive(Debug, Clone, Default)]
+pub struct ClusterConfig {
+    /// Run in cluster mode
+    pub enabled: bool,
+    
+    /// ID of this node in the slot map (else the node listening on our port)
+    pub node_id: Option<String>,
+    
+    /// Nodes of the shard set and their slots
+    pub nodes: Vec<ClusterNodeConfig>,
+}
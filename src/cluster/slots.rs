@@ -0,0 +1,89 @@
+//! Hash slot calculation
+//!
+//! Keys map to one of 16384 slots by the CRC16 (XMODEM) of the key, or of its
+//! hash tag: the part between the first `{` and the next `}`, when not empty.
+//! This is the mapping Redis Cluster clients compute on their side.
+
+/// Number of hash slots in a cluster
+pub const SLOT_COUNT: usize = 16384;
+
+/// CRC16-CCITT (XMODEM), polynomial 0x1021
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Part of the key that is hashed: its hash tag if it has a non-empty one
+pub fn hash_tag(key: &[u8]) -> &[u8] {
+    if let Some(open) = key.iter().position(|&b| b == b'{') {
+        if let Some(len) = key[open + 1..].iter().position(|&b| b == b'}') {
+            if len > 0 {
+                return &key[open + 1..open + 1 + len];
+            }
+        }
+    }
+    key
+}
+
+/// Hash slot of a key
+pub fn key_hash_slot(key: &[u8]) -> u16 {
+    crc16(hash_tag(key)) & (SLOT_COUNT as u16 - 1)
+}
+
+/// Parse a slot number, rejecting anything outside 0..16384
+pub fn parse_slot(arg: &[u8]) -> Option<u16> {
+    std::str::from_utf8(arg).ok()?
+        .parse::<u16>().ok()
+        .filter(|&slot| (slot as usize) < SLOT_COUNT)
+}
+
+/// Parse a slot or an inclusive `start-end` range of slots
+pub fn parse_slot_range(arg: &str) -> Option<(u16, u16)> {
+    let (start, end) = match arg.split_once('-') {
+        Some((start, end)) => (parse_slot(start.as_bytes())?, parse_slot(end.as_bytes())?),
+        None => {
+            let slot = parse_slot(arg.as_bytes())?;
+            (slot, slot)
+        }
+    };
+    (start <= end).then_some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_key_hash_slot() {
+        // Values from the Redis Cluster specification and redis-cli
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+        assert_eq!(key_hash_slot(b"foo"), 12182);
+        assert_eq!(key_hash_slot(b"bar"), 5061);
+        assert_eq!(key_hash_slot(b""), 0);
+    }
+    
+    #[test]
+    fn test_hash_tags() {
+        assert_eq!(hash_tag(b"{user1000}.following"), b"user1000");
+        assert_eq!(key_hash_slot(b"{user1000}.following"), key_hash_slot(b"{user1000}.followers"));
+        assert_eq!(hash_tag(b"foo{}{bar}"), b"foo{}{bar}");
+        assert_eq!(hash_tag(b"foo{{bar}}zap"), b"{bar");
+        assert_eq!(hash_tag(b"foo{bar}{zap}"), b"bar");
+        assert_eq!(hash_tag(b"foo{bar"), b"foo{bar");
+    }
+    
+    #[test]
+    fn test_parse_slot_range() {
+        assert_eq!(parse_slot_range("0-5460"), Some((0, 5460)));
+        assert_eq!(parse_slot_range("42"), Some((42, 42)));
+        assert_eq!(parse_slot_range("16384"), None);
+        assert_eq!(parse_slot_range("10-5"), None);
+        assert_eq!(parse_slot_range("a-b"), None);
+    }
+}
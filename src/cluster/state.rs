@@ -0,0 +1,377 @@
+//! Slot map and command redirection
+//!
+//! Phase one of cluster mode has no cluster bus: every node is configured with
+//! the same static map of nodes and the slots they serve. Slot ownership only
+//! changes through CLUSTER SETSLOT, which is also how a slot is marked as
+//! migrating or importing while MIGRATE moves its keys.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+use crate::protocol::RespFrame;
+use crate::storage::commands::flags;
+use super::slots::{key_hash_slot, SLOT_COUNT};
+use super::ClusterConfig;
+
+/// A node of the slot map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterNode {
+    /// 40 character node ID
+    pub id: String,
+    
+    /// Host clients are redirected to
+    pub host: String,
+    
+    /// Port clients are redirected to
+    pub port: u16,
+}
+
+impl ClusterNode {
+    /// `host:port` as used in redirections
+    pub fn endpoint(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+/// Slots being moved between this node and another one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotMigration {
+    /// Keys of the slot are moving from this node to the given one
+    Migrating(usize),
+    
+    /// Keys of the slot are moving from the given node to this one
+    Importing(usize),
+}
+
+struct SlotMap {
+    nodes: Vec<ClusterNode>,
+    owners: Vec<Option<usize>>,
+    migrations: HashMap<u16, SlotMigration>,
+}
+
+/// Cluster mode state of the server
+pub struct ClusterState {
+    enabled: bool,
+    myself: usize,
+    map: RwLock<SlotMap>,
+}
+
+/// Generate a node ID in the format Redis uses: 40 lowercase hex characters
+pub fn generate_node_id() -> String {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    (0..20).map(|_| format!("{:02x}", rng.gen::<u8>())).collect()
+}
+
+impl ClusterState {
+    /// Cluster state of a server running standalone
+    pub fn disabled() -> Self {
+        ClusterState {
+            enabled: false,
+            myself: 0,
+            map: RwLock::new(SlotMap {
+                nodes: Vec::new(),
+                owners: vec![None; SLOT_COUNT],
+                migrations: HashMap::new(),
+            }),
+        }
+    }
+    
+    /// Build the slot map from the configuration
+    ///
+    /// This node is the configured node named by `cluster-myid`, or else the
+    /// one listening on `port`. A node missing from the map joins it with no
+    /// slots, at `host:port`.
+    pub fn from_config(config: &ClusterConfig, host: &str, port: u16) -> Self {
+        if !config.enabled {
+            return Self::disabled();
+        }
+        
+        let mut nodes = Vec::with_capacity(config.nodes.len() + 1);
+        let mut owners = vec![None; SLOT_COUNT];
+        for (index, node) in config.nodes.iter().enumerate() {
+            nodes.push(ClusterNode { id: node.id.clone(), host: node.host.clone(), port: node.port });
+            for &(start, end) in &node.slots {
+                for slot in start..=end {
+                    owners[slot as usize] = Some(index);
+                }
+            }
+        }
+        
+        let found = match &config.node_id {
+            Some(id) => nodes.iter().position(|node| &node.id == id),
+            None => nodes.iter().position(|node| node.port == port),
+        };
+        let myself = found.unwrap_or_else(|| {
+            let id = config.node_id.clone().unwrap_or_else(generate_node_id);
+            nodes.push(ClusterNode { id, host: host.to_string(), port });
+            nodes.len() - 1
+        });
+        
+        ClusterState {
+            enabled: true,
+            myself,
+            map: RwLock::new(SlotMap { nodes, owners, migrations: HashMap::new() }),
+        }
+    }
+    
+    /// Whether the server runs in cluster mode
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+    
+    /// This node
+    pub fn myself(&self) -> ClusterNode {
+        self.map.read().unwrap().nodes[self.myself].clone()
+    }
+    
+    /// Every known node, with the slot ranges it serves
+    pub fn nodes(&self) -> Vec<(ClusterNode, Vec<(u16, u16)>)> {
+        let map = self.map.read().unwrap();
+        let mut ranges = vec![Vec::new(); map.nodes.len()];
+        let mut slot = 0;
+        while slot < SLOT_COUNT {
+            let Some(owner) = map.owners[slot] else {
+                slot += 1;
+                continue;
+            };
+            let start = slot;
+            while slot + 1 < SLOT_COUNT && map.owners[slot + 1] == Some(owner) {
+                slot += 1;
+            }
+            ranges[owner].push((start as u16, slot as u16));
+            slot += 1;
+        }
+        map.nodes.iter().cloned().zip(ranges).collect()
+    }
+    
+    /// Index of this node in `nodes()`
+    pub fn myself_index(&self) -> usize {
+        self.myself
+    }
+    
+    /// Number of slots served by some node
+    pub fn assigned_slots(&self) -> usize {
+        self.map.read().unwrap().owners.iter().filter(|owner| owner.is_some()).count()
+    }
+    
+    /// Slots of this node being migrated or imported, with the other node
+    pub fn migrations(&self) -> Vec<(u16, SlotMigration, ClusterNode)> {
+        let map = self.map.read().unwrap();
+        let mut migrations: Vec<_> = map.migrations.iter()
+            .map(|(&slot, &migration)| {
+                let (SlotMigration::Migrating(node) | SlotMigration::Importing(node)) = migration;
+                (slot, migration, map.nodes[node].clone())
+            })
+            .collect();
+        migrations.sort_by_key(|(slot, _, _)| *slot);
+        migrations
+    }
+    
+    /// Apply CLUSTER SETSLOT
+    ///
+    /// `node_id` names the other node of a migration, or the new owner for
+    /// `NODE`; it is `None` for `STABLE`.
+    pub fn set_slot(&self, slot: u16, action: &str, node_id: Option<&str>) -> std::result::Result<(), String> {
+        let mut map = self.map.write().unwrap();
+        let node = match node_id {
+            Some(id) => match map.nodes.iter().position(|node| node.id == id) {
+                Some(node) => Some(node),
+                None => return Err(format!("ERR I don't know about node {}", id)),
+            },
+            None => None,
+        };
+        let owned = map.owners[slot as usize] == Some(self.myself);
+        
+        match (action, node) {
+            ("MIGRATING", Some(node)) => {
+                if !owned {
+                    return Err(format!("ERR I'm not the owner of hash slot {}", slot));
+                }
+                if node == self.myself {
+                    return Err("ERR I can't migrate a slot to myself".to_string());
+                }
+                map.migrations.insert(slot, SlotMigration::Migrating(node));
+            }
+            ("IMPORTING", Some(node)) => {
+                if owned {
+                    return Err(format!("ERR I'm already the owner of hash slot {}", slot));
+                }
+                if node == self.myself {
+                    return Err("ERR I can't import a slot from myself".to_string());
+                }
+                map.migrations.insert(slot, SlotMigration::Importing(node));
+            }
+            ("STABLE", None) => {
+                map.migrations.remove(&slot);
+            }
+            ("NODE", Some(node)) => {
+                map.owners[slot as usize] = Some(node);
+                map.migrations.remove(&slot);
+            }
+            _ => return Err("ERR syntax error".to_string()),
+        }
+        Ok(())
+    }
+    
+    /// Redirection for a command that this node does not serve
+    ///
+    /// `asking` is set when the client sent ASKING just before, which lets it
+    /// reach a slot being imported. `exists` tells whether a key is still
+    /// present here, for slots being migrated away. Returns `None` when the
+    /// command runs here.
+    pub fn redirect<T: AsRef<[u8]>>(&self, args: &[T], asking: bool, exists: impl Fn(&[u8]) -> bool) -> Option<RespFrame> {
+        let keys = routing_keys(args);
+        let (first, rest) = keys.split_first()?;
+        let slot = key_hash_slot(first);
+        if rest.iter().any(|key| key_hash_slot(key) != slot) {
+            return Some(RespFrame::error("CROSSSLOT Keys in request don't hash to the same slot"));
+        }
+        
+        let map = self.map.read().unwrap();
+        match (map.owners[slot as usize], map.migrations.get(&slot)) {
+            (Some(owner), Some(&SlotMigration::Migrating(target))) if owner == self.myself => {
+                let missing = keys.iter().filter(|key| !exists(key)).count();
+                if missing == keys.len() {
+                    Some(RespFrame::error(format!("ASK {} {}", slot, map.nodes[target].endpoint())))
+                } else if missing > 0 {
+                    Some(RespFrame::error("TRYAGAIN Multiple keys request during rehashing of slot"))
+                } else {
+                    None
+                }
+            }
+            (Some(owner), _) if owner == self.myself => None,
+            (_, Some(SlotMigration::Importing(_))) if asking => None,
+            (Some(owner), _) => Some(RespFrame::error(format!("MOVED {} {}", slot, map.nodes[owner].endpoint()))),
+            (None, _) => Some(RespFrame::error("CLUSTERDOWN Hash slot not served")),
+        }
+    }
+}
+
+/// Keys a command accesses, which decide the node serving it
+///
+/// Data commands take their keys from the shared key extraction used for
+/// access tracking; commands that extraction skips, and commands taking
+/// their keys after a count or a STREAMS marker, are handled here.
+pub fn routing_keys<T: AsRef<[u8]>>(args: &[T]) -> Vec<&[u8]> {
+    let Some(name) = args.first() else {
+        return Vec::new();
+    };
+    let name = String::from_utf8_lossy(name.as_ref()).to_uppercase();
+    let arg = |index: usize| args.get(index).map(|arg| arg.as_ref());
+    
+    match name.as_str() {
+        "EVAL" | "EVALSHA" | "EVAL_RO" | "EVALSHA_RO" | "FCALL" | "FCALL_RO" => {
+            let numkeys = arg(2)
+                .and_then(|numkeys| std::str::from_utf8(numkeys).ok()?.parse::<usize>().ok())
+                .unwrap_or(0);
+            args.iter().skip(3).take(numkeys).map(|key| key.as_ref()).collect()
+        }
+        "TYPE" | "TTL" | "PTTL" | "EXPIRETIME" | "PEXPIRETIME" => arg(1).into_iter().collect(),
+        "OBJECT" | "XGROUP" | "XINFO" => arg(2).into_iter().collect(),
+        "BITOP" => args.iter().skip(2).map(|key| key.as_ref()).collect(),
+        "MEMORY" if arg(1).is_some_and(|sub| sub.eq_ignore_ascii_case(b"USAGE")) => arg(2).into_iter().collect(),
+        "XREAD" | "XREADGROUP" => {
+            let Some(streams) = args.iter().position(|arg| arg.as_ref().eq_ignore_ascii_case(b"STREAMS")) else {
+                return Vec::new();
+            };
+            let rest = &args[streams + 1..];
+            rest[..rest.len() / 2].iter().map(|key| key.as_ref()).collect()
+        }
+        "BLPOP" | "BRPOP" | "BLMOVE" | "BRPOPLPUSH" | "BLMPOP" | "WATCH" | "RESTORE-ASKING" => {
+            crate::storage::lru::command_keys(args)
+        }
+        name if flags::is_write_command(name) || flags::is_read_only_command(name) => {
+            crate::storage::lru::command_keys(args)
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::ClusterNodeConfig;
+    
+    fn node(id: &str, port: u16, slots: Vec<(u16, u16)>) -> ClusterNodeConfig {
+        ClusterNodeConfig { id: id.to_string(), host: "127.0.0.1".to_string(), port, slots }
+    }
+    
+    fn three_nodes(port: u16) -> ClusterState {
+        let config = ClusterConfig {
+            enabled: true,
+            node_id: None,
+            nodes: vec![
+                node("a", 7000, vec![(0, 5460)]),
+                node("b", 7001, vec![(5461, 10922)]),
+                node("c", 7002, vec![(10923, 16383)]),
+            ],
+        };
+        ClusterState::from_config(&config, "127.0.0.1", port)
+    }
+    
+    fn redirect(cluster: &ClusterState, args: &[&str], asking: bool, exists: bool) -> Option<RespFrame> {
+        cluster.redirect(args, asking, |_| exists)
+    }
+    
+    #[test]
+    fn test_moved_and_crossslot() {
+        let cluster = three_nodes(7000);
+        assert_eq!(cluster.myself().id, "a");
+        
+        // "foo" hashes to 12182, "bar" to 5061
+        assert_eq!(redirect(&cluster, &["GET", "foo"], false, true),
+                   Some(RespFrame::error("MOVED 12182 127.0.0.1:7002")));
+        assert_eq!(redirect(&cluster, &["GET", "bar"], false, true), None);
+        assert_eq!(redirect(&cluster, &["MSET", "{bar}1", "x", "{bar}2", "y"], false, true), None);
+        assert_eq!(redirect(&cluster, &["MGET", "foo", "bar"], false, true),
+                   Some(RespFrame::error("CROSSSLOT Keys in request don't hash to the same slot")));
+        assert_eq!(redirect(&cluster, &["EVAL", "return 1", "1", "foo"], false, true),
+                   Some(RespFrame::error("MOVED 12182 127.0.0.1:7002")));
+        assert_eq!(redirect(&cluster, &["XGROUP", "CREATE", "foo", "g", "$"], false, true),
+                   Some(RespFrame::error("MOVED 12182 127.0.0.1:7002")));
+        
+        // Keyless commands always run here
+        assert_eq!(redirect(&cluster, &["PING"], false, true), None);
+        assert_eq!(redirect(&cluster, &["CLUSTER", "KEYSLOT", "foo"], false, true), None);
+    }
+    
+    #[test]
+    fn test_migrating_and_importing_slots() {
+        let source = three_nodes(7000);
+        let target = three_nodes(7002);
+        assert!(target.set_slot(5061, "IMPORTING", Some("a")).is_ok());
+        assert!(source.set_slot(5061, "MIGRATING", Some("c")).is_ok());
+        
+        // Keys still on the source are served there; moved ones are asked for on the target
+        assert_eq!(redirect(&source, &["GET", "bar"], false, true), None);
+        assert_eq!(redirect(&source, &["GET", "bar"], false, false), Some(RespFrame::error("ASK 5061 127.0.0.1:7002")));
+        assert_eq!(redirect(&target, &["GET", "bar"], false, true), Some(RespFrame::error("MOVED 5061 127.0.0.1:7000")));
+        assert_eq!(redirect(&target, &["GET", "bar"], true, true), None);
+        
+        // Handing the slot over ends both migrations
+        assert!(source.set_slot(5061, "NODE", Some("c")).is_ok());
+        assert!(target.set_slot(5061, "NODE", Some("c")).is_ok());
+        assert_eq!(redirect(&source, &["GET", "bar"], false, false), Some(RespFrame::error("MOVED 5061 127.0.0.1:7002")));
+        assert_eq!(redirect(&target, &["GET", "bar"], false, true), None);
+        assert!(target.migrations().is_empty());
+        
+        assert_eq!(source.set_slot(5061, "MIGRATING", Some("b")), Err("ERR I'm not the owner of hash slot 5061".to_string()));
+        assert_eq!(source.set_slot(1, "IMPORTING", Some("zz")), Err("ERR I don't know about node zz".to_string()));
+    }
+    
+    #[test]
+    fn test_unassigned_slots_and_new_node() {
+        let config = ClusterConfig {
+            enabled: true,
+            node_id: Some("new".to_string()),
+            nodes: vec![node("a", 7000, vec![(0, 100)])],
+        };
+        let cluster = ClusterState::from_config(&config, "10.0.0.1", 7005);
+        
+        assert_eq!(cluster.myself(), ClusterNode { id: "new".to_string(), host: "10.0.0.1".to_string(), port: 7005 });
+        assert_eq!(cluster.assigned_slots(), 101);
+        assert_eq!(cluster.nodes()[0].1, vec![(0, 100)]);
+        assert_eq!(redirect(&cluster, &["GET", "foo"], false, true), Some(RespFrame::error("CLUSTERDOWN Hash slot not served")));
+        assert_eq!(generate_node_id().len(), 40);
+    }
+}
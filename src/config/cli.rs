@@ -39,6 +39,18 @@ pub struct CliArgs {
     
     /// Whether to fork and run in the background
     pub daemonize: Option<bool>,
+    
+    /// Run the Lua self-test before serving traffic
+    pub check_lua: Option<LuaCheck>,
+}
+
+/// What to do when the startup Lua self-test fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LuaCheck {
+    /// Refuse to start
+    Refuse,
+    /// Log the failures and start anyway
+    Warn,
 }
 
 impl Default for CliArgs {
@@ -55,6 +67,7 @@ impl Default for CliArgs {
             logfile: None,
             loglevel: None,
             daemonize: None,
+            check_lua: None,
         }
     }
 }
@@ -193,6 +206,16 @@ pub fn parse_cli_args() -> CliArgs {
                     i += 1;
                 }
             }
+            "--check-lua" => {
+                if i + 1 < args.len() && (args[i + 1] == "refuse" || args[i + 1] == "warn") {
+                    cli_args.check_lua = Some(if args[i + 1] == "warn" { LuaCheck::Warn } else { LuaCheck::Refuse });
+                    i += 2;
+                } else {
+                    // Just --check-lua with no argument refuses to start on failure
+                    cli_args.check_lua = Some(LuaCheck::Refuse);
+                    i += 1;
+                }
+            }
             arg => {
                 // Check if it's a config file path without --config flag
                 if arg.ends_with(".conf") {
@@ -230,6 +253,7 @@ fn print_help() {
     println!("  --logfile     <file>    Path to log file (empty for stdout)");
     println!("  --loglevel    <level>   Log level (debug, verbose, notice, warning)");
     println!("  --daemonize   [yes|no]  Run as a daemon in the background");
+    println!("  --check-lua   [refuse|warn] Self-test Lua scripting before serving (default: refuse)");
 }
 
 #[cfg(test)]
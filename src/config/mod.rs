@@ -7,15 +7,50 @@ mod parser;
 mod cli;
 
 pub use parser::{parse_config_file, ConfigParseError};
-pub use cli::{parse_cli_args, CliArgs};
+pub(crate) use parser::{parse_size, parse_yes_no};
+use parser::{apply_config_param, parse_save_rules};
+pub use cli::{parse_cli_args, CliArgs, LuaCheck};
 
 use crate::network::NetworkConfig;
 use crate::storage::{RdbConfig, AofConfig};
-use crate::storage::memory::EvictionPolicy;
+use crate::storage::memory::{EvictionPolicy, DEFAULT_LFU_DECAY_TIME, DEFAULT_LFU_LOG_FACTOR};
+use crate::storage::lua_engine::ReplyLimits;
+use crate::storage::value::EncodingLimits;
 use crate::replication::ReplicationConfig;
+use crate::cluster::ClusterConfig;
+use crate::storage::commands::config::SCRIPTING_PARAMS;
 
+use std::io::Write;
 use std::path::PathBuf;
 
+/// Parameters CONFIG SET can change while the server runs, besides the
+/// scripting ones in [`SCRIPTING_PARAMS`]
+pub const MUTABLE_PARAMS: [&str; 18] = [
+    "maxmemory",
+    "maxmemory-policy",
+    "maxmemory-samples",
+    "lfu-log-factor",
+    "lfu-decay-time",
+    "hash-max-listpack-entries",
+    "hash-max-listpack-value",
+    "list-max-listpack-size",
+    "set-max-intset-entries",
+    "set-max-listpack-entries",
+    "set-max-listpack-value",
+    "zset-max-listpack-entries",
+    "zset-max-listpack-value",
+    "appendfsync",
+    "save",
+    "slowlog-log-slower-than",
+    "slowlog-max-len",
+    "notify-keyspace-events",
+];
+
+/// Whether CONFIG SET can change `param`
+pub fn is_mutable(param: &str) -> bool {
+    MUTABLE_PARAMS.contains(&param) || SCRIPTING_PARAMS.contains(&param)
+}
+
 /// Main configuration structure for Ferrous
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -34,11 +69,20 @@ pub struct Config {
     /// Replication configuration
     pub replication: ReplicationConfig,
     
+    /// Cluster configuration
+    pub cluster: ClusterConfig,
+    
     /// Memory management configuration
     pub memory: MemoryConfig,
     
     /// Monitoring and performance configuration
     pub monitoring: MonitoringConfig,
+    
+    /// Lua scripting configuration
+    pub scripting: ScriptingConfig,
+    
+    /// File the configuration was loaded from, which CONFIG REWRITE updates
+    pub file: Option<PathBuf>,
 }
 
 /// Server-specific configuration
@@ -64,6 +108,9 @@ pub struct ServerConfig {
     
     /// Show logo on startup
     pub show_logo: bool,
+    
+    /// Keyspace event classes to notify, in canonical notify-keyspace-events form
+    pub notify_keyspace_events: String,
 }
 
 /// Memory management configuration
@@ -77,6 +124,15 @@ pub struct MemoryConfig {
     
     /// Sample size for eviction (number of keys to sample)
     pub max_memory_samples: usize,
+    
+    /// How many accesses it takes to saturate a key's LFU counter
+    pub lfu_log_factor: u32,
+    
+    /// Idle minutes after which a key's LFU counter decays by one (0 = never)
+    pub lfu_decay_time: u64,
+    
+    /// Sizes past which collections leave their compact encoding
+    pub encoding_limits: EncodingLimits,
 }
 
 /// Monitoring and performance configuration
@@ -98,8 +154,57 @@ pub struct MonitoringConfig {
     pub slowlog_max_len: u64,
 }
 
+/// Lua scripting configuration
+#[derive(Debug, Clone)]
+pub struct ScriptingConfig {
+    /// Caps on redis.call replies converted into Lua values
+    pub reply_limits: ReplyLimits,
+    
+    /// Expose the Lua 5.3-style `utf8` library to scripts (off for strict 5.1 compatibility)
+    pub enable_utf8: bool,
+    
+    /// Deterministic `pairs` order and PRNG seed so replicas replay scripts identically
+    pub deterministic: bool,
+    
+    /// Directory receiving record/replay tapes of failed scripts (off when unset)
+    pub record_dir: Option<PathBuf>,
+    
+    /// Milliseconds after which a running script is busy: other clients get BUSY and SCRIPT KILL may stop it (0 = never)
+    pub time_limit_ms: u64,
+    
+    /// Lua instructions a script may execute before it is terminated (0 = unlimited)
+    pub max_instructions: u64,
+    
+    /// Deepest Lua call stack a script may reach (0 = Lua's own limit)
+    pub max_call_depth: usize,
+    
+    /// Per-script Lua heap limit in bytes (0 = unlimited)
+    pub memory_limit: usize,
+    
+    /// Leave the `debug` library in script environments (off by default: it breaks the sandbox)
+    pub enable_debug_library: bool,
+    
+    /// Deepest table nesting accepted in a script result
+    pub max_nesting_depth: usize,
+    
+    /// Trigger depth at which events raised by keyspace triggers are dropped
+    pub trigger_max_depth: usize,
+    
+    /// Let `redis.checkpoint()` serve other clients' read-only commands mid-script (off by default)
+    pub checkpoints: bool,
+    
+    /// Minimum milliseconds between two checkpoints that serve other clients
+    pub checkpoint_interval_ms: u64,
+    
+    /// File receiving the audit log of script writes (off when unset)
+    pub audit_log: Option<PathBuf>,
+    
+    /// Size in bytes at which the audit log is rotated (0 = never)
+    pub audit_log_max_size: u64,
+}
+
 /// Log level configuration
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogLevel {
     /// Debug level - most verbose
     Debug,
@@ -122,8 +227,11 @@ impl Default for Config {
             rdb: RdbConfig::default(),
             aof: AofConfig::default(),
             replication: ReplicationConfig::default(),
+            cluster: ClusterConfig::default(),
             memory: MemoryConfig::default(),
             monitoring: MonitoringConfig::default(),
+            scripting: ScriptingConfig::default(),
+            file: None,
         }
     }
 }
@@ -138,6 +246,7 @@ impl Default for ServerConfig {
             log_file: "".to_string(),
             pid_file: None,
             show_logo: true,
+            notify_keyspace_events: String::new(),
         }
     }
 }
@@ -148,6 +257,31 @@ impl Default for MemoryConfig {
             max_memory: 0, // Unlimited
             max_memory_policy: EvictionPolicy::NoEviction,
             max_memory_samples: 5,
+            lfu_log_factor: DEFAULT_LFU_LOG_FACTOR,
+            lfu_decay_time: DEFAULT_LFU_DECAY_TIME,
+            encoding_limits: EncodingLimits::default(),
+        }
+    }
+}
+
+impl Default for ScriptingConfig {
+    fn default() -> Self {
+        ScriptingConfig {
+            reply_limits: ReplyLimits::default(),
+            enable_utf8: false,
+            deterministic: false,
+            record_dir: None,
+            time_limit_ms: 5000,
+            max_instructions: 0, // Unlimited
+            max_call_depth: 0, // Lua's own limit
+            memory_limit: 0, // Unlimited
+            enable_debug_library: false,
+            max_nesting_depth: crate::storage::lua_engine::DEFAULT_MAX_NESTING_DEPTH,
+            trigger_max_depth: crate::storage::lua_triggers::DEFAULT_MAX_DEPTH,
+            checkpoints: false,
+            checkpoint_interval_ms: crate::storage::lua_checkpoint::DEFAULT_INTERVAL_MS,
+            audit_log: None,
+            audit_log_max_size: crate::storage::lua_audit::DEFAULT_MAX_SIZE,
         }
     }
 }
@@ -217,22 +351,43 @@ impl Config {
             "bind" => Some(self.network.bind_addr.clone()),
             "timeout" => Some(self.network.timeout.to_string()),
             "tcp-keepalive" => self.network.tcp_keepalive.map(|v| v.to_string()),
+            "io-threads" => Some(self.network.io_threads.to_string()),
             "protected-mode" => Some("yes".to_string()), // Always enabled
             "databases" => Some(self.server.databases.to_string()),
             "dbfilename" => Some(self.rdb.filename.clone()),
             "dir" => Some(self.rdb.dir.clone()),
             "maxmemory" => Some(self.memory.max_memory.to_string()),
             "maxmemory-policy" => Some(self.memory_policy_str()),
+            "maxmemory-samples" => Some(self.memory.max_memory_samples.to_string()),
+            "lfu-log-factor" => Some(self.memory.lfu_log_factor.to_string()),
+            "lfu-decay-time" => Some(self.memory.lfu_decay_time.to_string()),
+            "hash-max-listpack-entries" => Some(self.memory.encoding_limits.hash_max_listpack_entries.to_string()),
+            "hash-max-listpack-value" => Some(self.memory.encoding_limits.hash_max_listpack_value.to_string()),
+            "list-max-listpack-size" => Some(self.memory.encoding_limits.list_max_listpack_size.to_string()),
+            "set-max-intset-entries" => Some(self.memory.encoding_limits.set_max_intset_entries.to_string()),
+            "set-max-listpack-entries" => Some(self.memory.encoding_limits.set_max_listpack_entries.to_string()),
+            "set-max-listpack-value" => Some(self.memory.encoding_limits.set_max_listpack_value.to_string()),
+            "zset-max-listpack-entries" => Some(self.memory.encoding_limits.zset_max_listpack_entries.to_string()),
+            "zset-max-listpack-value" => Some(self.memory.encoding_limits.zset_max_listpack_value.to_string()),
             "appendonly" => Some(if self.aof.enabled { "yes" } else { "no" }.to_string()),
             "appendfilename" => Some(self.aof.filename.clone()),
             "appendfsync" => Some(self.fsync_policy_str()),
             "save" => Some(self.format_save_rules()),
+            "cluster-enabled" => Some(if self.cluster.enabled { "yes" } else { "no" }.to_string()),
             // Monitoring configuration parameters
             "slowlog-enabled" => Some(if self.monitoring.slowlog_enabled { "yes" } else { "no" }.to_string()),
             "monitor-enabled" => Some(if self.monitoring.monitor_enabled { "yes" } else { "no" }.to_string()),
             "stats-enabled" => Some(if self.monitoring.stats_enabled { "yes" } else { "no" }.to_string()),
             "slowlog-log-slower-than" => Some(self.monitoring.slowlog_threshold_micros.to_string()),
             "slowlog-max-len" => Some(self.monitoring.slowlog_max_len.to_string()),
+            "notify-keyspace-events" => Some(self.server.notify_keyspace_events.clone()),
+            // Scripting parameters CONFIG SET can change
+            "lua-time-limit" => Some(self.scripting.time_limit_ms.to_string()),
+            "lua-max-instructions" => Some(self.scripting.max_instructions.to_string()),
+            "lua-max-call-depth" => Some(self.scripting.max_call_depth.to_string()),
+            "lua-memory-limit" => Some(self.scripting.memory_limit.to_string()),
+            "lua-deterministic" => Some(if self.scripting.deterministic { "yes" } else { "no" }.to_string()),
+            "lua-enable-debug-library" => Some(if self.scripting.enable_debug_library { "yes" } else { "no" }.to_string()),
             _ => None,
         }
     }
@@ -249,6 +404,7 @@ impl Config {
             params.push(("tcp-keepalive".to_string(), keepalive.to_string()));
         }
         params.push(("protected-mode".to_string(), "yes".to_string()));
+        params.push(("io-threads".to_string(), self.network.io_threads.to_string()));
         
         // Server params
         params.push(("databases".to_string(), self.server.databases.to_string()));
@@ -264,9 +420,26 @@ impl Config {
         params.push(("appendfilename".to_string(), self.aof.filename.clone()));
         params.push(("appendfsync".to_string(), self.fsync_policy_str()));
         
+        // Cluster params
+        params.push(("cluster-enabled".to_string(), if self.cluster.enabled { "yes" } else { "no" }.to_string()));
+        
         // Memory params
         params.push(("maxmemory".to_string(), self.memory.max_memory.to_string()));
         params.push(("maxmemory-policy".to_string(), self.memory_policy_str()));
+        params.push(("maxmemory-samples".to_string(), self.memory.max_memory_samples.to_string()));
+        params.push(("lfu-log-factor".to_string(), self.memory.lfu_log_factor.to_string()));
+        params.push(("lfu-decay-time".to_string(), self.memory.lfu_decay_time.to_string()));
+        
+        // Encoding params
+        let limits = &self.memory.encoding_limits;
+        params.push(("hash-max-listpack-entries".to_string(), limits.hash_max_listpack_entries.to_string()));
+        params.push(("hash-max-listpack-value".to_string(), limits.hash_max_listpack_value.to_string()));
+        params.push(("list-max-listpack-size".to_string(), limits.list_max_listpack_size.to_string()));
+        params.push(("set-max-intset-entries".to_string(), limits.set_max_intset_entries.to_string()));
+        params.push(("set-max-listpack-entries".to_string(), limits.set_max_listpack_entries.to_string()));
+        params.push(("set-max-listpack-value".to_string(), limits.set_max_listpack_value.to_string()));
+        params.push(("zset-max-listpack-entries".to_string(), limits.zset_max_listpack_entries.to_string()));
+        params.push(("zset-max-listpack-value".to_string(), limits.zset_max_listpack_value.to_string()));
         
         // Monitoring params
         params.push(("slowlog-enabled".to_string(), if self.monitoring.slowlog_enabled { "yes" } else { "no" }.to_string()));
@@ -274,10 +447,92 @@ impl Config {
         params.push(("stats-enabled".to_string(), if self.monitoring.stats_enabled { "yes" } else { "no" }.to_string()));
         params.push(("slowlog-log-slower-than".to_string(), self.monitoring.slowlog_threshold_micros.to_string()));
         params.push(("slowlog-max-len".to_string(), self.monitoring.slowlog_max_len.to_string()));
+        params.push(("notify-keyspace-events".to_string(), self.server.notify_keyspace_events.clone()));
+        
+        // Scripting params
+        for param in SCRIPTING_PARAMS {
+            params.push((param.to_string(), self.get(param).unwrap_or_default()));
+        }
         
         params
     }
     
+    /// Change a parameter at runtime, as CONFIG SET does
+    ///
+    /// Values are validated like in the config file, except that `save` takes
+    /// every rule at once and replaces the current ones. On error the
+    /// configuration is unchanged.
+    pub fn set(&mut self, param: &str, value: &str) -> Result<(), ConfigParseError> {
+        if !is_mutable(param) {
+            return Err(ConfigParseError::UnknownParam(param.to_string(), 0));
+        }
+        
+        if param == "save" {
+            let rules = parse_save_rules(value)
+                .ok_or_else(|| ConfigParseError::Value(param.to_string(), 0, value.to_string()))?;
+            self.rdb.auto_save = !rules.is_empty();
+            self.rdb.save_rules = rules;
+            return Ok(());
+        }
+        apply_config_param(self, param, value, 0)
+    }
+    
+    /// Write the runtime value of the mutable parameters back to the config file
+    ///
+    /// The first line of each parameter is replaced in place and any repeated
+    /// ones dropped, keeping comments and all other lines as they were.
+    /// Parameters the file doesn't mention are appended when they differ from
+    /// the default. The file is replaced atomically.
+    pub fn rewrite(&self) -> Result<(), ConfigError> {
+        let path = self.file.as_ref()
+            .ok_or_else(|| ConfigError::Other("The server is running without a config file".to_string()))?;
+        let contents = std::fs::read_to_string(path)?;
+        
+        let mut pending: Vec<&str> = MUTABLE_PARAMS.iter().chain(SCRIPTING_PARAMS.iter()).copied().collect();
+        let mut lines = Vec::new();
+        for line in contents.lines() {
+            let param = line.split_whitespace().next().unwrap_or("").to_lowercase();
+            if param.starts_with('#') || !is_mutable(&param) {
+                lines.push(line.to_string());
+            } else if let Some(pos) = pending.iter().position(|&p| p == param) {
+                pending.remove(pos);
+                lines.extend(self.config_lines(&param));
+            }
+        }
+        
+        let defaults = Config::default();
+        let missing: Vec<&str> = pending.into_iter()
+            .filter(|param| self.get(param) != defaults.get(param))
+            .collect();
+        if !missing.is_empty() {
+            lines.push("# Generated by CONFIG REWRITE".to_string());
+            for param in missing {
+                lines.extend(self.config_lines(param));
+            }
+        }
+        
+        crate::storage::atomic_file::write_atomically(path, |writer| {
+            for line in &lines {
+                writeln!(writer, "{}", line)?;
+            }
+            Ok(())
+        }).map_err(|e| ConfigError::Other(e.to_string()))
+    }
+    
+    /// Config file lines setting `param` to its current value
+    fn config_lines(&self, param: &str) -> Vec<String> {
+        if param == "save" && !self.rdb.save_rules.is_empty() {
+            return self.rdb.save_rules.iter()
+                .map(|(seconds, changes)| format!("save {} {}", seconds, changes))
+                .collect();
+        }
+        
+        match self.get(param).unwrap_or_default() {
+            value if value.is_empty() => vec![format!("{} \"\"", param)],
+            value => vec![format!("{} {}", param, value)],
+        }
+    }
+    
     // Helper methods for formatting
     
     fn format_save_rules(&self) -> String {
@@ -299,6 +554,8 @@ impl Config {
             EvictionPolicy::AllKeysRandom => "allkeys-random".to_string(),
             EvictionPolicy::VolatileRandom => "volatile-random".to_string(),
             EvictionPolicy::VolatileTtl => "volatile-ttl".to_string(),
+            EvictionPolicy::AllKeysLfu => "allkeys-lfu".to_string(),
+            EvictionPolicy::VolatileLfu => "volatile-lfu".to_string(),
         }
     }
     
@@ -4,11 +4,13 @@
 
 use std::fs::File;
 use std::io::{self, BufRead, BufReader};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use crate::storage::memory::EvictionPolicy;
 use crate::storage::aof::FsyncPolicy;
+use crate::cluster::ClusterNodeConfig;
+use crate::cluster::slots::parse_slot_range;
 
 use super::{Config, LogLevel};
 
@@ -46,6 +48,9 @@ pub fn parse_config_file(path: &Path) -> Result<Config, ConfigParseError> {
         // Also update any other path-relative configs
     }
     
+    // The first save line replaces the default rules, later ones add to it
+    let mut save_seen = false;
+    
     for (line_num, line_result) in reader.lines().enumerate() {
         let line = line_result?;
         let line = line.trim();
@@ -64,15 +69,21 @@ pub fn parse_config_file(path: &Path) -> Result<Config, ConfigParseError> {
         let param = parts[0].trim().to_lowercase();
         let value = parts[1].trim();
         
+        if param == "save" && !save_seen {
+            config.rdb.save_rules.clear();
+            save_seen = true;
+        }
+        
         // Apply configuration parameter
         apply_config_param(&mut config, &param, value, line_num + 1)?;
     }
     
+    config.file = Some(path.to_path_buf());
     Ok(config)
 }
 
 /// Apply a configuration parameter to the config
-fn apply_config_param(config: &mut Config, param: &str, value: &str, line_num: usize) -> Result<(), ConfigParseError> {
+pub(super) fn apply_config_param(config: &mut Config, param: &str, value: &str, line_num: usize) -> Result<(), ConfigParseError> {
     match param {
         // Network settings
         "bind" => {
@@ -91,6 +102,13 @@ fn apply_config_param(config: &mut Config, param: &str, value: &str, line_num: u
             let keepalive: u64 = parse_value(param, value, line_num)?;
             config.network.tcp_keepalive = if keepalive == 0 { None } else { Some(keepalive) };
         }
+        "io-threads" => {
+            let threads: usize = parse_value(param, value, line_num)?;
+            if !(1..=crate::network::io_threads::MAX_IO_THREADS).contains(&threads) {
+                return Err(ConfigParseError::Value(param.to_string(), line_num, value.to_string()));
+            }
+            config.network.io_threads = threads;
+        }
         "requirepass" => {
             config.network.password = Some(value.to_string());
         }
@@ -136,26 +154,15 @@ fn apply_config_param(config: &mut Config, param: &str, value: &str, line_num: u
             config.aof.dir = value.to_string();
         }
         "save" => {
-            if value.is_empty() || value == "\"\"" {
+            let rules = parse_save_rules(value)
+                .ok_or_else(|| ConfigParseError::Value(param.to_string(), line_num, value.to_string()))?;
+            if rules.is_empty() {
                 // Empty save rule means disable RDB
                 config.rdb.auto_save = false;
                 config.rdb.save_rules.clear();
             } else {
-                // Parse save rule format: "seconds changes"
-                let parts: Vec<&str> = value.split_whitespace().collect();
-                if parts.len() != 2 {
-                    return Err(ConfigParseError::Value(param.to_string(), line_num, value.to_string()));
-                }
-                
-                let seconds: u64 = parts[0].parse()
-                    .map_err(|_| ConfigParseError::Value(param.to_string(), line_num, value.to_string()))?;
-                
-                let changes: u64 = parts[1].parse()
-                    .map_err(|_| ConfigParseError::Value(param.to_string(), line_num, value.to_string()))?;
-                
-                // Add save rule
                 config.rdb.auto_save = true;
-                config.rdb.save_rules.push((seconds, changes));
+                config.rdb.save_rules.extend(rules);
             }
         }
         "rdbcompression" => {
@@ -217,6 +224,27 @@ fn apply_config_param(config: &mut Config, param: &str, value: &str, line_num: u
             config.replication.diskless_sync = parse_yes_no(param, value, line_num)?;
         }
         
+        // Cluster settings
+        "cluster-enabled" => {
+            config.cluster.enabled = parse_yes_no(param, value, line_num)?;
+        }
+        "cluster-myid" => {
+            config.cluster.node_id = Some(value.to_string());
+        }
+        "cluster-node" => {
+            let node = parse_cluster_node(value)
+                .ok_or_else(|| ConfigParseError::Value(param.to_string(), line_num, value.to_string()))?;
+            
+            // Two nodes never serve the same slot
+            let overlaps = config.cluster.nodes.iter()
+                .flat_map(|other| &other.slots)
+                .any(|&(start, end)| node.slots.iter().any(|&(s, e)| s <= end && start <= e));
+            if overlaps || config.cluster.nodes.iter().any(|other| other.id == node.id) {
+                return Err(ConfigParseError::Value(param.to_string(), line_num, value.to_string()));
+            }
+            config.cluster.nodes.push(node);
+        }
+        
         // Memory settings
         "maxmemory" => {
             config.memory.max_memory = parse_size(param, value, line_num)? as usize;
@@ -229,9 +257,132 @@ fn apply_config_param(config: &mut Config, param: &str, value: &str, line_num: u
                 "allkeys-random" => EvictionPolicy::AllKeysRandom,
                 "volatile-random" => EvictionPolicy::VolatileRandom,
                 "volatile-ttl" => EvictionPolicy::VolatileTtl,
+                "allkeys-lfu" => EvictionPolicy::AllKeysLfu,
+                "volatile-lfu" => EvictionPolicy::VolatileLfu,
                 _ => return Err(ConfigParseError::Value(param.to_string(), line_num, value.to_string())),
             };
         }
+        "maxmemory-samples" => {
+            let samples: usize = parse_value(param, value, line_num)?;
+            if !(1..=64).contains(&samples) {
+                return Err(ConfigParseError::Value(param.to_string(), line_num, value.to_string()));
+            }
+            config.memory.max_memory_samples = samples;
+        }
+        "lfu-log-factor" => {
+            config.memory.lfu_log_factor = parse_value(param, value, line_num)?;
+        }
+        "lfu-decay-time" => {
+            config.memory.lfu_decay_time = parse_value(param, value, line_num)?;
+        }
+        
+        // Compact encoding thresholds, also under their pre-7.0 ziplist names
+        "hash-max-listpack-entries" | "hash-max-ziplist-entries" => {
+            config.memory.encoding_limits.hash_max_listpack_entries = parse_value(param, value, line_num)?;
+        }
+        "hash-max-listpack-value" | "hash-max-ziplist-value" => {
+            config.memory.encoding_limits.hash_max_listpack_value = parse_size(param, value, line_num)? as usize;
+        }
+        "list-max-listpack-size" | "list-max-ziplist-size" => {
+            config.memory.encoding_limits.list_max_listpack_size = parse_value(param, value, line_num)?;
+        }
+        "set-max-intset-entries" => {
+            config.memory.encoding_limits.set_max_intset_entries = parse_value(param, value, line_num)?;
+        }
+        "set-max-listpack-entries" => {
+            config.memory.encoding_limits.set_max_listpack_entries = parse_value(param, value, line_num)?;
+        }
+        "set-max-listpack-value" => {
+            config.memory.encoding_limits.set_max_listpack_value = parse_size(param, value, line_num)? as usize;
+        }
+        "zset-max-listpack-entries" | "zset-max-ziplist-entries" => {
+            config.memory.encoding_limits.zset_max_listpack_entries = parse_value(param, value, line_num)?;
+        }
+        "zset-max-listpack-value" | "zset-max-ziplist-value" => {
+            config.memory.encoding_limits.zset_max_listpack_value = parse_size(param, value, line_num)? as usize;
+        }
+        
+        // Monitoring settings
+        "slowlog-enabled" => {
+            config.monitoring.slowlog_enabled = parse_yes_no(param, value, line_num)?;
+        }
+        "monitor-enabled" => {
+            config.monitoring.monitor_enabled = parse_yes_no(param, value, line_num)?;
+        }
+        "stats-enabled" => {
+            config.monitoring.stats_enabled = parse_yes_no(param, value, line_num)?;
+        }
+        "slowlog-log-slower-than" => {
+            config.monitoring.slowlog_threshold_micros = parse_value(param, value, line_num)?;
+        }
+        "slowlog-max-len" => {
+            config.monitoring.slowlog_max_len = parse_value(param, value, line_num)?;
+        }
+        "notify-keyspace-events" => {
+            config.server.notify_keyspace_events = normalize_keyspace_events(value.trim_matches('"'))
+                .ok_or_else(|| ConfigParseError::Value(param.to_string(), line_num, value.to_string()))?;
+        }
+        
+        // Scripting settings
+        "lua-reply-max-elements" => {
+            config.scripting.reply_limits.max_elements = parse_value(param, value, line_num)?;
+        }
+        "lua-reply-max-bytes" => {
+            config.scripting.reply_limits.max_bytes = parse_size(param, value, line_num)? as usize;
+        }
+        "lua-reply-limit-policy" => {
+            config.scripting.reply_limits.truncate = match value.to_lowercase().as_str() {
+                "error" => false,
+                "truncate" => true,
+                _ => return Err(ConfigParseError::Value(param.to_string(), line_num, value.to_string())),
+            };
+        }
+        "lua-enable-utf8" => {
+            config.scripting.enable_utf8 = parse_yes_no(param, value, line_num)?;
+        }
+        "lua-deterministic" => {
+            config.scripting.deterministic = parse_yes_no(param, value, line_num)?;
+        }
+        "lua-record-dir" => {
+            config.scripting.record_dir = if value.is_empty() { None } else { Some(PathBuf::from(value)) };
+        }
+        "lua-time-limit" => {
+            config.scripting.time_limit_ms = parse_value(param, value, line_num)?;
+        }
+        "lua-memory-limit" => {
+            config.scripting.memory_limit = parse_size(param, value, line_num)? as usize;
+        }
+        "lua-max-instructions" => {
+            config.scripting.max_instructions = parse_value(param, value, line_num)?;
+        }
+        "lua-max-call-depth" => {
+            config.scripting.max_call_depth = parse_value(param, value, line_num)?;
+        }
+        "lua-enable-debug-library" => {
+            config.scripting.enable_debug_library = parse_yes_no(param, value, line_num)?;
+        }
+        "lua-max-nesting-depth" => {
+            let depth: usize = parse_value(param, value, line_num)?;
+            if depth == 0 {
+                return Err(ConfigParseError::Value(param.to_string(), line_num, value.to_string()));
+            }
+            config.scripting.max_nesting_depth = depth;
+        }
+        "lua-trigger-max-depth" => {
+            config.scripting.trigger_max_depth = parse_value(param, value, line_num)?;
+        }
+        "lua-checkpoints" => {
+            config.scripting.checkpoints = parse_yes_no(param, value, line_num)?;
+        }
+        "lua-checkpoint-interval" => {
+            config.scripting.checkpoint_interval_ms = parse_value(param, value, line_num)?;
+        }
+        "lua-audit-log" => {
+            config.scripting.audit_log = if value.is_empty() { None } else { Some(PathBuf::from(value)) };
+        }
+        "lua-audit-log-max-size" => {
+            config.scripting.audit_log_max_size = parse_size(param, value, line_num)?;
+        }
         
         // Ignore other parameters
         _ => {
@@ -243,6 +394,56 @@ fn apply_config_param(config: &mut Config, param: &str, value: &str, line_num: u
     Ok(())
 }
 
+/// Parse a `cluster-node <id> <host>:<port> [slot|start-end ...]` value
+fn parse_cluster_node(value: &str) -> Option<ClusterNodeConfig> {
+    let mut fields = value.split_whitespace();
+    let id = fields.next()?.to_string();
+    let (host, port) = fields.next()?.rsplit_once(':')?;
+    let port = port.parse().ok()?;
+    let slots = fields.map(parse_slot_range).collect::<Option<Vec<_>>>()?;
+    Some(ClusterNodeConfig { id, host: host.to_string(), port, slots })
+}
+
+/// Parse save rules: `seconds changes` pairs, or `""` for none
+pub(crate) fn parse_save_rules(value: &str) -> Option<Vec<(u64, u64)>> {
+    if value.is_empty() || value == "\"\"" {
+        return Some(Vec::new());
+    }
+    
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    if !fields.len().is_multiple_of(2) {
+        return None;
+    }
+    fields.chunks(2)
+        .map(|pair| Some((pair[0].parse().ok()?, pair[1].parse().ok()?)))
+        .collect()
+}
+
+/// Validate notify-keyspace-events flags and put them in canonical form
+///
+/// `A` stands for all event classes (`g$lshzxetd`) and is used in place of
+/// them in the result, as Redis reports the setting.
+pub(crate) fn normalize_keyspace_events(value: &str) -> Option<String> {
+    const CLASSES: &str = "g$lshzxetd";
+    
+    let mut flags = String::new();
+    for flag in value.chars() {
+        match flag {
+            'A' => flags.push_str(CLASSES),
+            _ if CLASSES.contains(flag) || "KEmn".contains(flag) => flags.push(flag),
+            _ => return None,
+        }
+    }
+    
+    let mut normalized = if CLASSES.chars().all(|class| flags.contains(class)) {
+        "A".to_string()
+    } else {
+        CLASSES.chars().filter(|&class| flags.contains(class)).collect()
+    };
+    normalized.extend("KEmn".chars().filter(|&flag| flags.contains(flag)));
+    Some(normalized)
+}
+
 /// Parse a value that implements FromStr
 fn parse_value<T: FromStr>(param: &str, value: &str, line_num: usize) -> Result<T, ConfigParseError> {
     value.parse::<T>()
@@ -250,7 +451,7 @@ fn parse_value<T: FromStr>(param: &str, value: &str, line_num: usize) -> Result<
 }
 
 /// Parse a yes/no value
-fn parse_yes_no(param: &str, value: &str, line_num: usize) -> Result<bool, ConfigParseError> {
+pub(crate) fn parse_yes_no(param: &str, value: &str, line_num: usize) -> Result<bool, ConfigParseError> {
     match value.to_lowercase().as_str() {
         "yes" | "1" => Ok(true),
         "no" | "0" => Ok(false),
@@ -259,7 +460,7 @@ fn parse_yes_no(param: &str, value: &str, line_num: usize) -> Result<bool, Confi
 }
 
 /// Parse a size value (e.g., 64mb, 2gb)
-fn parse_size(param: &str, value: &str, line_num: usize) -> Result<u64, ConfigParseError> {
+pub(crate) fn parse_size(param: &str, value: &str, line_num: usize) -> Result<u64, ConfigParseError> {
     let value = value.trim().to_lowercase();
     let mut chars = value.chars();
     
@@ -336,6 +537,145 @@ replicaof 192.168.1.100 6379
         assert_eq!(config.replication.master_port, Some(6379));
     }
     
+    #[test]
+    fn test_parse_scripting_block() {
+        let config_content = r#"
+lua-time-limit 250
+lua-memory-limit 64mb
+lua-max-instructions 1000000
+lua-max-call-depth 200
+lua-deterministic yes
+lua-enable-debug-library yes
+lua-max-nesting-depth 64
+lua-trigger-max-depth 2
+lua-checkpoints yes
+lua-checkpoint-interval 20
+lua-audit-log /var/log/ferrous/scripts.audit
+lua-audit-log-max-size 16mb
+"#;
+        
+        let temp_file = NamedTempFile::new().unwrap();
+        write(temp_file.path(), config_content).unwrap();
+        
+        let config = parse_config_file(temp_file.path()).unwrap();
+        assert_eq!(config.scripting.time_limit_ms, 250);
+        assert_eq!(config.scripting.memory_limit, 64 * 1024 * 1024);
+        assert_eq!(config.scripting.max_instructions, 1_000_000);
+        assert_eq!(config.scripting.max_call_depth, 200);
+        assert!(config.scripting.deterministic);
+        assert!(config.scripting.enable_debug_library);
+        assert_eq!(config.scripting.max_nesting_depth, 64);
+        assert_eq!(config.scripting.trigger_max_depth, 2);
+        assert!(config.scripting.checkpoints);
+        assert_eq!(config.scripting.checkpoint_interval_ms, 20);
+        assert_eq!(config.scripting.audit_log, Some(PathBuf::from("/var/log/ferrous/scripts.audit")));
+        assert_eq!(config.scripting.audit_log_max_size, 16 * 1024 * 1024);
+        
+        // Defaults when the block is absent
+        let defaults = Config::default().scripting;
+        assert_eq!(defaults.time_limit_ms, 5000);
+        assert_eq!(defaults.memory_limit, 0);
+        assert_eq!(defaults.max_instructions, 0);
+        assert_eq!(defaults.max_call_depth, 0);
+        assert!(!defaults.enable_debug_library);
+        assert_eq!(defaults.max_nesting_depth, 1000);
+        assert_eq!(defaults.trigger_max_depth, 4);
+        assert!(!defaults.checkpoints);
+        assert_eq!(defaults.checkpoint_interval_ms, 100);
+        assert_eq!(defaults.audit_log, None);
+        
+        write(temp_file.path(), "lua-time-limit soon\n").unwrap();
+        assert!(parse_config_file(temp_file.path()).is_err());
+    }
+    
+    #[test]
+    fn test_parse_cluster_block() {
+        let config_content = r#"
+cluster-enabled yes
+cluster-myid node-b
+cluster-node node-a 10.0.0.1:7000 0-5460
+cluster-node node-b 10.0.0.2:7000 5461-10922 16383
+"#;
+        
+        let temp_file = NamedTempFile::new().unwrap();
+        write(temp_file.path(), config_content).unwrap();
+        
+        let config = parse_config_file(temp_file.path()).unwrap();
+        assert!(config.cluster.enabled);
+        assert_eq!(config.cluster.node_id, Some("node-b".to_string()));
+        assert_eq!(config.cluster.nodes[1], ClusterNodeConfig {
+            id: "node-b".to_string(),
+            host: "10.0.0.2".to_string(),
+            port: 7000,
+            slots: vec![(5461, 10922), (16383, 16383)],
+        });
+        
+        // Overlapping slots and malformed ranges are rejected
+        write(temp_file.path(), "cluster-node a h:1 0-100\ncluster-node b h:2 100-200\n").unwrap();
+        assert!(parse_config_file(temp_file.path()).is_err());
+        write(temp_file.path(), "cluster-node a h:1 0-16384\n").unwrap();
+        assert!(parse_config_file(temp_file.path()).is_err());
+    }
+    
+    #[test]
+    fn test_parse_save_and_events() {
+        // The first save line replaces the defaults
+        let temp_file = NamedTempFile::new().unwrap();
+        write(temp_file.path(), "save 3600 1 300 100\nsave 60 10000\nnotify-keyspace-events Exg\n").unwrap();
+        let config = parse_config_file(temp_file.path()).unwrap();
+        assert_eq!(config.rdb.save_rules, vec![(3600, 1), (300, 100), (60, 10000)]);
+        assert_eq!(config.server.notify_keyspace_events, "gxE");
+        assert_eq!(config.file.as_deref(), Some(temp_file.path()));
+        
+        write(temp_file.path(), "save \"\"\n").unwrap();
+        let config = parse_config_file(temp_file.path()).unwrap();
+        assert!(!config.rdb.auto_save);
+        assert!(config.rdb.save_rules.is_empty());
+        
+        assert_eq!(parse_save_rules("900"), None);
+        assert_eq!(parse_save_rules("900 x"), None);
+        assert_eq!(normalize_keyspace_events("KA"), Some("AK".to_string()));
+        assert_eq!(normalize_keyspace_events(""), Some(String::new()));
+        assert_eq!(normalize_keyspace_events("Kq"), None);
+    }
+    
+    #[test]
+    fn test_parse_encoding_limits() {
+        let temp_file = NamedTempFile::new().unwrap();
+        write(temp_file.path(), "hash-max-ziplist-entries 256\nhash-max-listpack-value 1kb\nlist-max-listpack-size 100\n").unwrap();
+        let config = parse_config_file(temp_file.path()).unwrap();
+        assert_eq!(config.memory.encoding_limits.hash_max_listpack_entries, 256);
+        assert_eq!(config.memory.encoding_limits.hash_max_listpack_value, 1024);
+        assert_eq!(config.get("list-max-listpack-size"), Some("100".to_string()));
+        assert_eq!(config.get("set-max-intset-entries"), Some("512".to_string()));
+    }
+    
+    #[test]
+    fn test_set_and_rewrite() {
+        let temp_file = NamedTempFile::new().unwrap();
+        write(temp_file.path(), "# Limits\nmaxmemory 1mb\nport 7000\nsave 900 1\nsave 300 10\n").unwrap();
+        let mut config = parse_config_file(temp_file.path()).unwrap();
+        
+        config.set("maxmemory", "2mb").unwrap();
+        config.set("save", "60 5").unwrap();
+        config.set("slowlog-max-len", "64").unwrap();
+        config.set("notify-keyspace-events", "").unwrap();
+        assert!(config.set("maxmemory", "lots").is_err());
+        assert!(config.set("port", "7001").is_err());
+        assert_eq!(config.get("maxmemory"), Some((2 * 1024 * 1024).to_string()));
+        
+        config.rewrite().unwrap();
+        let contents = std::fs::read_to_string(temp_file.path()).unwrap();
+        assert_eq!(contents, "# Limits\nmaxmemory 2097152\nport 7000\nsave 60 5\n\
+            # Generated by CONFIG REWRITE\nslowlog-max-len 64\n");
+        
+        let reloaded = parse_config_file(temp_file.path()).unwrap();
+        assert_eq!(reloaded.get_all(), config.get_all());
+        
+        // Without a config file there is nothing to rewrite
+        assert!(Config::default().rewrite().is_err());
+    }
+    
     #[test]
     fn test_parse_yes_no() {
         assert_eq!(parse_yes_no("test", "yes", 1).unwrap(), true);
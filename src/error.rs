@@ -59,6 +59,12 @@ pub enum CommandError {
     /// Value is not an integer or out of range
     NotInteger,
     
+    /// Value is not a valid float
+    NotFloat,
+    
+    /// Float increment result is not finite
+    NanOrInfinity,
+    
     /// Index out of range
     IndexOutOfRange,
     
@@ -71,6 +77,9 @@ pub enum CommandError {
     /// Invalid argument for command
     InvalidArgument(String),
     
+    /// Expire time is not positive or overflows (with command name)
+    InvalidExpireTime(String),
+    
     /// Invalid stream ID
     InvalidStreamId,
     
@@ -137,6 +146,12 @@ pub enum StorageError {
     
     /// Operation would block but NOWAIT flag was set
     WouldBlock,
+    
+    /// String value used as a HyperLogLog that is not one
+    NotHyperLogLog,
+    
+    /// HyperLogLog whose registers do not decode
+    CorruptedHyperLogLog,
 }
 
 /// Script execution errors
@@ -156,6 +171,27 @@ pub enum ScriptError {
     
     /// Script killed
     Killed,
+    
+    /// SCRIPT KILL refused because the script already wrote to the dataset
+    Unkillable,
+    
+    /// SCRIPT KILL with no busy script to kill
+    NotBusy,
+    
+    /// Script exceeded the Lua memory limit
+    OutOfMemory,
+    
+    /// Script result nested deeper than the configured limit
+    StackOverflow(usize),
+    
+    /// Script result contains a value of this Lua type, which has no reply form
+    UnconvertibleReply(&'static str),
+    
+    /// Script ran past a limit of its execution budget (e.g. "time limit of 5000 ms")
+    BudgetExceeded(String),
+    
+    /// Script refused by a per-user script quota (e.g. "user 'alice' used its 100 ms of script time for this minute")
+    QuotaExceeded(String),
 }
 
 /// Type alias for Results throughout Ferrous
@@ -191,7 +227,7 @@ impl fmt::Display for CommandError {
                 write!(f, "ERR unknown command '{}'", cmd)
             }
             CommandError::WrongNumberOfArgs(cmd) => {
-                write!(f, "ERR wrong number of arguments for '{}' command", cmd)
+                write!(f, "ERR wrong number of arguments for '{}' command", cmd.to_lowercase())
             }
             CommandError::SyntaxError(msg) => write!(f, "ERR syntax error: {}", msg),
             CommandError::WrongType => {
@@ -203,6 +239,10 @@ impl fmt::Display for CommandError {
             CommandError::NotInteger => {
                 write!(f, "ERR value is not an integer or out of range")
             }
+            CommandError::NotFloat => write!(f, "ERR value is not a valid float"),
+            CommandError::NanOrInfinity => {
+                write!(f, "ERR increment would produce NaN or Infinity")
+            }
             CommandError::IndexOutOfRange => write!(f, "ERR index out of range"),
             CommandError::NoSuchKey => write!(f, "ERR no such key"),
             CommandError::InvalidState(msg) => {
@@ -211,6 +251,9 @@ impl fmt::Display for CommandError {
             CommandError::InvalidArgument(msg) => {
                 write!(f, "ERR invalid argument: {}", msg)
             }
+            CommandError::InvalidExpireTime(cmd) => {
+                write!(f, "ERR invalid expire time in '{}' command", cmd.to_lowercase())
+            }
             CommandError::InvalidStreamId => {
                 write!(f, "ERR Invalid stream ID specified as stream command argument")
             }
@@ -239,7 +282,7 @@ impl fmt::Display for CommandError {
                 write!(f, "ERR empty command")
             }
             CommandError::WrongNumberOfArguments(cmd) => {
-                write!(f, "ERR wrong number of arguments for '{}' command", cmd)
+                write!(f, "ERR wrong number of arguments for '{}' command", cmd.to_lowercase())
             }
             CommandError::InvalidCommandFormat => {
                 write!(f, "ERR invalid command format")
@@ -271,6 +314,8 @@ impl fmt::Display for StorageError {
             StorageError::WrongType => write!(f, "Wrong data type"),
             StorageError::InvalidDatabase => write!(f, "ERR invalid DB index"),
             StorageError::WouldBlock => write!(f, "Would block"),
+            StorageError::NotHyperLogLog => write!(f, "WRONGTYPE Key is not a valid HyperLogLog string value."),
+            StorageError::CorruptedHyperLogLog => write!(f, "INVALIDOBJ Corrupted HLL object detected"),
         }
     }
 }
@@ -281,8 +326,15 @@ impl fmt::Display for ScriptError {
             ScriptError::NotFound => write!(f, "NOSCRIPT No matching script. Please use EVAL."),
             ScriptError::ExecutionError(msg) => write!(f, "ERR {}", msg),
             ScriptError::CompilationError(msg) => write!(f, "ERR Error compiling script: {}", msg),
-            ScriptError::Timeout => write!(f, "ERR Script execution time limit exceeded"),
-            ScriptError::Killed => write!(f, "ERR Script killed by user"),
+            ScriptError::Timeout => write!(f, "BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE."),
+            ScriptError::Killed => write!(f, "KILLED Script killed by user with SCRIPT KILL"),
+            ScriptError::Unkillable => write!(f, "UNKILLABLE Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command."),
+            ScriptError::NotBusy => write!(f, "NOTBUSY No scripts in execution right now."),
+            ScriptError::OutOfMemory => write!(f, "OOM Script exceeded the Lua memory limit"),
+            ScriptError::StackOverflow(limit) => write!(f, "ERR Error running script: stack overflow (result nested deeper than {} levels)", limit),
+            ScriptError::UnconvertibleReply(type_name) => write!(f, "ERR Error running script: cannot convert a Lua {} to a reply", type_name),
+            ScriptError::BudgetExceeded(limit) => write!(f, "ERR Script exceeded its {}", limit),
+            ScriptError::QuotaExceeded(reason) => write!(f, "ERR Script quota exceeded: {}", reason),
         }
     }
 }
@@ -331,5 +383,12 @@ mod tests {
         
         let err = ScriptError::NotFound;
         assert_eq!(err.to_string(), "NOSCRIPT No matching script. Please use EVAL.");
+        
+        // Script errors carry the RESP error classes client libraries map to exception types
+        assert!(ScriptError::Timeout.to_string().starts_with("BUSY "));
+        assert!(ScriptError::Killed.to_string().starts_with("KILLED "));
+        assert!(ScriptError::Unkillable.to_string().starts_with("UNKILLABLE "));
+        assert!(ScriptError::NotBusy.to_string().starts_with("NOTBUSY "));
+        assert!(ScriptError::OutOfMemory.to_string().starts_with("OOM "));
     }
 }
\ No newline at end of file
@@ -9,7 +9,10 @@ pub mod storage;
 pub mod monitor;
 pub mod pubsub;
 pub mod replication;
+pub mod cluster;
 pub mod config;
+pub mod logging;
+pub mod signals;
 
 // Re-export commonly used types
 pub use error::FerrousError;
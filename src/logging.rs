@@ -0,0 +1,119 @@
+//! Server log
+//!
+//! Leveled log lines in the Redis format (`pid:role day month year time.ms marker
+//! message`), written to stdout or to the configured `logfile`. Lines below the
+//! configured `loglevel` are dropped before formatting.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::LogLevel;
+
+/// Minimum level written (LogLevel as u8)
+static MIN_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Notice as u8);
+
+/// Log file, or None for stdout
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+/// Path of the log file, empty for stdout
+static LOG_PATH: Mutex<String> = Mutex::new(String::new());
+
+/// Configure the level and destination (an empty path logs to stdout)
+pub fn init(level: LogLevel, path: &str) -> std::io::Result<()> {
+    set_level(level);
+    *LOG_PATH.lock().unwrap() = path.to_string();
+    reopen()
+}
+
+/// Open the log file again, after it was rotated away (SIGHUP)
+pub fn reopen() -> std::io::Result<()> {
+    let file = match LOG_PATH.lock().unwrap().as_str() {
+        "" => None,
+        path => Some(OpenOptions::new().create(true).append(true).open(path)?),
+    };
+    *LOG_FILE.lock().unwrap() = file;
+    Ok(())
+}
+
+/// Change the minimum level written
+pub fn set_level(level: LogLevel) {
+    MIN_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Check whether lines at `level` are currently written
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 >= MIN_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Write a line to the server log if `level` is enabled
+pub fn log(level: LogLevel, message: &str) {
+    if !enabled(level) {
+        return;
+    }
+    
+    let marker = match level {
+        LogLevel::Debug => '.',
+        LogLevel::Verbose => '-',
+        LogLevel::Notice => '*',
+        LogLevel::Warning => '#',
+    };
+    let line = format!("{}:M {} {} {}\n", std::process::id(), timestamp(), marker, message);
+    
+    match LOG_FILE.lock().unwrap().as_mut() {
+        Some(file) => {
+            let _ = file.write_all(line.as_bytes());
+        }
+        None => print!("{}", line),
+    }
+}
+
+/// Current UTC time as `15 Oct 2026 10:00:00.123`
+fn timestamp() -> String {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = now.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let time = secs % 86_400;
+    format!("{:02} {} {} {:02}:{:02}:{:02}.{:03}",
+        day, MONTHS[month as usize - 1], year, time / 3600, time / 60 % 60, time % 60, now.subsec_millis())
+}
+
+/// Convert days since the Unix epoch to a (year, month, day) civil date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        assert_eq!(civil_from_days(20_741), (2026, 10, 15));
+    }
+    
+    #[test]
+    fn test_level_filtering() {
+        set_level(LogLevel::Warning);
+        assert!(!enabled(LogLevel::Notice));
+        assert!(enabled(LogLevel::Warning));
+        set_level(LogLevel::Notice);
+        assert!(enabled(LogLevel::Notice));
+        assert!(!enabled(LogLevel::Verbose));
+    }
+}
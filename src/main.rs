@@ -10,7 +10,10 @@ mod network;
 mod storage;
 mod pubsub;
 mod replication;
+mod cluster;
 mod monitor;
+mod logging;
+mod signals;
 
 use std::process;
 use error::Result;
@@ -24,6 +27,24 @@ fn main() {
     }
 }
 
+/// Run the Lua self-test, exiting when it fails and `check` refuses to start
+fn check_lua(check: config::LuaCheck) {
+    let (count, failures) = storage::lua_selftest::run();
+    if failures.is_empty() {
+        println!("Lua self-test passed ({} checks)", count);
+        return;
+    }
+    
+    for failure in &failures {
+        eprintln!("Lua self-test: {} failed: {}", failure.name, failure.detail);
+    }
+    eprintln!("Lua self-test: {} of {} checks failed", failures.len(), count);
+    if check == config::LuaCheck::Refuse {
+        eprintln!("Refusing to start with a broken scripting subsystem (use --check-lua warn to start anyway)");
+        process::exit(1);
+    }
+}
+
 fn run() -> Result<()> {
     println!("Starting Ferrous - Redis-compatible server in Rust with MLua Lua 5.1 scripting");
     println!("Version: {}", env!("CARGO_PKG_VERSION"));
@@ -31,6 +52,11 @@ fn run() -> Result<()> {
     // Parse command-line arguments
     let cli_args = config::parse_cli_args();
     
+    // Self-test the scripting subsystem before anything else starts
+    if let Some(check) = cli_args.check_lua {
+        check_lua(check);
+    }
+    
     // Load configuration
     let mut config = if let Some(ref config_path) = cli_args.config {
         println!("Loading configuration from: {}", config_path.display());
@@ -60,6 +86,11 @@ fn run() -> Result<()> {
     // Create and run server
     let mut server = Server::from_config(config)?;
     
+    // SIGTERM/SIGINT shut down gracefully, SIGHUP reopens the log file
+    if let Err(e) = signals::install() {
+        eprintln!("Warning: failed to install signal handlers: {}", e);
+    }
+    
     // Run the server
     server.run()
 }
\ No newline at end of file
@@ -98,7 +98,7 @@ impl MonitorSubscribers {
 }
 
 /// Escape a byte string for display in MONITOR output
-fn escape_string(bytes: &[u8]) -> String {
+pub(crate) fn escape_string(bytes: &[u8]) -> String {
     let mut result = String::new();
     
     for &byte in bytes {
@@ -0,0 +1,256 @@
+//! ACL users
+//!
+//! A minimal user model for AUTH and per-user script quotas. Users have
+//! passwords and can be switched on and off, and every user may run every
+//! command on every key and channel: the only permission rules ACL SETUSER
+//! accepts are the ones granting everything. Script quotas are set with the
+//! rules described in [`lua_quota`](crate::storage::lua_quota).
+//!
+//! The `default` user is always on and its password is `requirepass` (none
+//! without one), so only its quotas can be changed. Passwords are kept as SHA1
+//! hashes. Users live in memory and are lost on restart.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock};
+
+use sha1::{Digest, Sha1};
+
+use crate::protocol::RespFrame;
+use crate::storage::commands::client::ConnectionProvider;
+use crate::storage::lua_quota::{Quota, UserQuota};
+
+/// The user clients are authenticated as until AUTH names another
+pub const DEFAULT_USER: &str = "default";
+
+/// Permission rules accepted for compatibility: every user already has them
+const GRANT_ALL_RULES: [&str; 6] = ["allcommands", "+@all", "allkeys", "~*", "allchannels", "&*"];
+
+/// One ACL user
+#[derive(Debug, Clone)]
+struct User {
+    enabled: bool,
+    nopass: bool,
+    
+    /// SHA1 hashes of the user's passwords, hex encoded
+    passwords: BTreeSet<String>,
+    
+    /// Script quotas and usage, shared with the scripts charged to the user
+    scripts: Arc<UserQuota>,
+}
+
+impl User {
+    /// A new user: off, without passwords and without quotas, as in Redis
+    fn new(name: &str) -> Self {
+        User { enabled: false, nopass: false, passwords: BTreeSet::new(), scripts: Arc::new(UserQuota::new(name)) }
+    }
+    
+    /// Whether `password` lets a client authenticate as this user
+    fn accepts(&self, password: &[u8]) -> bool {
+        self.enabled && (self.nopass || self.passwords.contains(&hash_password(password)))
+    }
+    
+    /// Flags reported by ACL GETUSER
+    fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+    
+    /// The user's rules as ACL LIST shows them
+    fn describe(&self, name: &str) -> String {
+        let mut rules = vec![format!("user {}", name)];
+        rules.extend(self.flags().into_iter().map(String::from));
+        rules.extend(self.passwords.iter().map(|hash| format!("#{}", hash)));
+        rules.extend(["~*", "&*", "+@all"].map(String::from));
+        rules.extend(self.scripts.quota().rules());
+        rules.join(" ")
+    }
+}
+
+/// Hex SHA1 of a password, the form passwords are kept in
+fn hash_password(password: &[u8]) -> String {
+    hex::encode(Sha1::digest(password))
+}
+
+/// ACL users, shared by the event loop and the busy script servicer
+#[derive(Debug)]
+pub struct Acl {
+    users: RwLock<BTreeMap<String, User>>,
+}
+
+impl Acl {
+    /// Users with only `default`, whose password is `requirepass`
+    pub fn new(requirepass: Option<&str>) -> Self {
+        let mut default = User::new(DEFAULT_USER);
+        default.enabled = true;
+        match requirepass {
+            Some(password) => {
+                default.passwords.insert(hash_password(password.as_bytes()));
+            }
+            None => default.nopass = true,
+        }
+        Acl { users: RwLock::new(BTreeMap::from([(DEFAULT_USER.to_string(), default)])) }
+    }
+    
+    /// Check a username and password, for AUTH and HELLO
+    pub fn authenticate(&self, username: &str, password: &[u8]) -> bool {
+        self.users.read().unwrap().get(username).is_some_and(|user| user.accepts(password))
+    }
+    
+    /// Script quotas and usage of `username`, if the user exists
+    pub fn quota_of(&self, username: &str) -> Option<Arc<UserQuota>> {
+        self.users.read().unwrap().get(username).map(|user| user.scripts.clone())
+    }
+    
+    /// Users with script quotas, with their quotas and usage, for INFO clients
+    pub fn script_quotas(&self) -> Vec<(String, Quota, crate::storage::lua_quota::Usage)> {
+        self.users.read().unwrap().iter()
+            .map(|(name, user)| (name.clone(), user.scripts.quota(), user.scripts.usage()))
+            .filter(|(_, quota, _)| *quota != Quota::default())
+            .collect()
+    }
+    
+    /// Apply ACL SETUSER rules to `name`, creating the user if needed
+    ///
+    /// Rules are applied in order to a copy, so an invalid rule leaves the
+    /// user unchanged.
+    fn set_user(&self, name: &str, rules: &[&[u8]]) -> Result<(), String> {
+        let mut users = self.users.write().unwrap();
+        let mut user = users.get(name).cloned().unwrap_or_else(|| User::new(name));
+        let mut quota = user.scripts.quota();
+        for &raw in rules {
+            let rule = String::from_utf8_lossy(raw);
+            let lower = rule.to_ascii_lowercase();
+            if GRANT_ALL_RULES.contains(&lower.as_str()) {
+                continue;
+            }
+            match quota.apply_rule(&rule) {
+                Some(Ok(())) => continue,
+                Some(Err(message)) => return Err(format!("ERR Error in ACL SETUSER modifier '{}': {}", rule, message)),
+                None => {}
+            }
+            if name == DEFAULT_USER {
+                return Err(format!("ERR Error in ACL SETUSER modifier '{}': the default user follows requirepass", rule));
+            }
+            match lower.as_str() {
+                "on" => user.enabled = true,
+                "off" => user.enabled = false,
+                "nopass" => {
+                    user.nopass = true;
+                    user.passwords.clear();
+                }
+                "resetpass" => {
+                    user.nopass = false;
+                    user.passwords.clear();
+                }
+                "reset" => {
+                    user.enabled = false;
+                    user.nopass = false;
+                    user.passwords.clear();
+                    quota = Quota::default();
+                }
+                _ if raw.starts_with(b">") => {
+                    user.nopass = false;
+                    user.passwords.insert(hash_password(&raw[1..]));
+                }
+                _ if raw.starts_with(b"<") => {
+                    user.passwords.remove(&hash_password(&raw[1..]));
+                }
+                _ => return Err(format!("ERR Error in ACL SETUSER modifier '{}': Syntax error", rule)),
+            }
+        }
+        user.scripts.set_quota(quota);
+        users.insert(name.to_string(), user);
+        Ok(())
+    }
+}
+
+/// Handle ACL SETUSER, GETUSER, DELUSER, USERS, LIST and WHOAMI
+///
+/// `whoami` is the user of the client sending the command. Deleting a user
+/// closes the connections authenticated as it.
+pub fn handle_acl(parts: &[RespFrame], acl: &Acl, connections: &impl ConnectionProvider, whoami: &str) -> RespFrame {
+    let args: Option<Vec<&[u8]>> = parts.iter()
+        .map(|part| match part {
+            RespFrame::BulkString(Some(bytes)) => Some(bytes.as_slice()),
+            _ => None,
+        })
+        .collect();
+    let Some(args) = args.filter(|args| args.len() >= 2) else {
+        return RespFrame::error("ERR wrong number of arguments for 'acl' command");
+    };
+    let subcommand = String::from_utf8_lossy(args[1]).to_uppercase();
+    let name = |i: usize| String::from_utf8_lossy(args[i]).into_owned();
+    
+    match (subcommand.as_str(), args.len()) {
+        ("WHOAMI", 2) => RespFrame::from_string(whoami),
+        ("USERS", 2) => RespFrame::Array(Some(
+            acl.users.read().unwrap().keys().map(|name| RespFrame::from_string(name.as_str())).collect()
+        )),
+        ("LIST", 2) => RespFrame::Array(Some(
+            acl.users.read().unwrap().iter().map(|(name, user)| RespFrame::from_string(user.describe(name))).collect()
+        )),
+        ("SETUSER", 3..) => match acl.set_user(&name(2), &args[3..]) {
+            Ok(()) => RespFrame::ok(),
+            Err(message) => RespFrame::error(message),
+        },
+        ("GETUSER", 3) => match acl.users.read().unwrap().get(&name(2)) {
+            Some(user) => getuser_reply(user),
+            None => RespFrame::null_bulk(),
+        },
+        ("DELUSER", 3..) => {
+            let names: Vec<String> = (2..args.len()).map(name).collect();
+            if names.iter().any(|name| name == DEFAULT_USER) {
+                return RespFrame::error("ERR The 'default' user cannot be removed");
+            }
+            let mut users = acl.users.write().unwrap();
+            let deleted: Vec<String> = names.into_iter().filter(|name| users.remove(name).is_some()).collect();
+            drop(users);
+            for id in connections.all_connection_ids() {
+                if connections.with_connection(id, |conn| deleted.contains(&conn.user)).unwrap_or(false) {
+                    connections.close_connection(id);
+                }
+            }
+            RespFrame::Integer(deleted.len() as i64)
+        }
+        _ => RespFrame::error(format!("ERR unknown subcommand or wrong number of arguments for '{}'", name(1))),
+    }
+}
+
+/// ACL GETUSER reply: flags, passwords, permissions, then script quotas and usage
+fn getuser_reply(user: &User) -> RespFrame {
+    let strings = |items: Vec<String>| RespFrame::Array(Some(items.into_iter().map(RespFrame::from_string).collect()));
+    let integers = |items: &[(&str, u64)]| RespFrame::Array(Some(items.iter()
+        .flat_map(|&(name, value)| [RespFrame::from_string(name), RespFrame::Integer(value as i64)])
+        .collect()));
+    let quota = user.scripts.quota();
+    let usage = user.scripts.usage();
+    
+    RespFrame::Array(Some(vec![
+        RespFrame::from_string("flags"),
+        strings(user.flags().into_iter().map(String::from).collect()),
+        RespFrame::from_string("passwords"),
+        strings(user.passwords.iter().cloned().collect()),
+        RespFrame::from_string("commands"),
+        RespFrame::from_string("+@all"),
+        RespFrame::from_string("keys"),
+        RespFrame::from_string("~*"),
+        RespFrame::from_string("channels"),
+        RespFrame::from_string("&*"),
+        RespFrame::from_string("script-quota"),
+        integers(&[
+            ("max-concurrent", quota.max_concurrent as u64),
+            ("cpu-ms-per-minute", quota.cpu_ms_per_minute),
+            ("max-memory", quota.max_memory as u64),
+        ]),
+        RespFrame::from_string("script-usage"),
+        integers(&[
+            ("running", usage.running as u64),
+            ("cpu-ms-this-minute", usage.cpu_ms),
+            ("scripts", usage.scripts),
+            ("rejected", usage.rejected),
+        ]),
+    ]))
+}
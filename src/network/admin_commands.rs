@@ -1,7 +1,8 @@
 //! Administrative command implementations
 //! 
 //! This module provides handlers for server administration commands
-//! like COMMAND (introspection) and SHUTDOWN (graceful termination).
+//! like COMMAND (introspection), LOLWUT (version banner) and SHUTDOWN
+//! (graceful termination).
 
 use crate::error::Result;
 use crate::protocol::resp::RespFrame;
@@ -49,34 +50,61 @@ pub fn handle_command(parts: &[RespFrame]) -> Result<RespFrame> {
     }
 }
 
-/// Handle SHUTDOWN command - graceful server shutdown
-pub fn handle_shutdown(parts: &[RespFrame], storage: &Arc<crate::storage::StorageEngine>, rdb_engine: Option<&Arc<crate::storage::RdbEngine>>) -> Result<RespFrame> {
-    let save_before_shutdown = if parts.len() == 1 {
-        true // Default: save on shutdown
-    } else if parts.len() == 2 {
-        match &parts[1] {
+/// Handle LOLWUT [VERSION version] - report the server version
+///
+/// Redis draws version-specific art here; Ferrous only prints the banner line.
+pub fn handle_lolwut(parts: &[RespFrame]) -> Result<RespFrame> {
+    match parts.len() {
+        1 => {}
+        3 if matches!(&parts[1], RespFrame::BulkString(Some(option)) if option.eq_ignore_ascii_case(b"VERSION")) => {}
+        _ => return Ok(RespFrame::error("ERR syntax error")),
+    }
+    Ok(RespFrame::from_string(format!(
+        "Ferrous ver. {} (Redis ver. {})\n", env!("CARGO_PKG_VERSION"), crate::storage::commands::monitor::REDIS_VERSION)))
+}
+
+/// Parse SHUTDOWN [NOSAVE|SAVE]: whether to save an RDB snapshot first
+///
+/// Saving is the default. Returns the error reply for bad arguments.
+pub fn parse_shutdown(parts: &[RespFrame]) -> std::result::Result<bool, RespFrame> {
+    match parts.len() {
+        1 => Ok(true), // Default: save on shutdown
+        2 => match &parts[1] {
             RespFrame::BulkString(Some(bytes)) => {
                 match std::str::from_utf8(bytes).unwrap_or("").to_uppercase().as_str() {
-                    "SAVE" => true,
-                    "NOSAVE" => false,
-                    _ => return Ok(RespFrame::error("ERR Invalid option. Valid values: SAVE, NOSAVE")),
+                    "SAVE" => Ok(true),
+                    "NOSAVE" => Ok(false),
+                    _ => Err(RespFrame::error("ERR Invalid option. Valid values: SAVE, NOSAVE")),
                 }
             }
-            _ => return Ok(RespFrame::error("ERR Invalid option format")),
-        }
-    } else {
-        return Ok(RespFrame::error("ERR wrong number of arguments for 'shutdown' command"));
-    };
+            _ => Err(RespFrame::error("ERR Invalid option format")),
+        },
+        _ => Err(RespFrame::error("ERR wrong number of arguments for 'shutdown' command")),
+    }
+}
+
+/// Make the dataset durable before the server exits
+///
+/// The AOF is flushed and fsynced whatever its fsync policy, then an RDB
+/// snapshot is saved when requested. A failed save is returned so SHUTDOWN can
+/// refuse to exit, as Redis does, rather than lose data.
+pub fn flush_persistence(
+    storage: &Arc<crate::storage::StorageEngine>,
+    rdb_engine: Option<&Arc<crate::storage::RdbEngine>>,
+    aof_engine: Option<&Arc<crate::storage::aof::AofEngine>>,
+    save: bool,
+) -> Result<()> {
+    if let Some(aof) = aof_engine {
+        println!("SHUTDOWN: Syncing the AOF to disk...");
+        aof.fsync()?;
+    }
     
     // Perform save if requested and RDB engine is available
-    if save_before_shutdown {
+    if save {
         if let Some(rdb) = rdb_engine {
             println!("SHUTDOWN: Performing RDB save...");
-            if let Err(e) = rdb.save(storage) {
-                eprintln!("Warning: RDB save failed during shutdown: {}", e);
-            } else {
-                println!("SHUTDOWN: RDB save completed successfully");
-            }
+            rdb.save(storage)?;
+            println!("SHUTDOWN: RDB save completed successfully");
         } else {
             println!("SHUTDOWN: RDB engine not available, skipping save");
         }
@@ -84,15 +112,7 @@ pub fn handle_shutdown(parts: &[RespFrame], storage: &Arc<crate::storage::Storag
         println!("SHUTDOWN: Skipping save (NOSAVE specified)");
     }
     
-    println!("SHUTDOWN: Initiating graceful server termination");
-    
-    // Send no response then exit after brief delay - Redis compliant behavior
-    std::thread::spawn(|| {
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        std::process::exit(0);
-    });
-    
-    Ok(RespFrame::NoResponse)
+    Ok(())
 }
 
 /// Build essential commands response for COMMAND command
@@ -108,8 +128,15 @@ fn build_essential_commands_response() -> RespFrame {
         cmd_info("get", 2, &["readonly", "fast"], 1, 1, 1),
         cmd_info("del", -2, &["write"], 1, -1, 1),
         cmd_info("exists", -2, &["readonly", "fast"], 1, -1, 1),
-        cmd_info("expire", 3, &["write", "fast"], 1, 1, 1),
+        cmd_info("expire", -3, &["write", "fast"], 1, 1, 1),
+        cmd_info("pexpire", -3, &["write", "fast"], 1, 1, 1),
+        cmd_info("expireat", -3, &["write", "fast"], 1, 1, 1),
+        cmd_info("pexpireat", -3, &["write", "fast"], 1, 1, 1),
+        cmd_info("persist", 2, &["write", "fast"], 1, 1, 1),
         cmd_info("ttl", 2, &["readonly", "fast"], 1, 1, 1),
+        cmd_info("pttl", 2, &["readonly", "fast"], 1, 1, 1),
+        cmd_info("expiretime", 2, &["readonly", "fast"], 1, 1, 1),
+        cmd_info("pexpiretime", 2, &["readonly", "fast"], 1, 1, 1),
         cmd_info("type", 2, &["readonly", "fast"], 1, 1, 1),
         cmd_info("shutdown", -1, &["admin", "noscript", "no_async_loading"], 0, 0, 1),
         // List commands
@@ -122,6 +149,10 @@ fn build_essential_commands_response() -> RespFrame {
         // Scripting
         cmd_info("eval", -3, &["noscript", "movablekeys"], 0, 0, 1),
         cmd_info("evalsha", -3, &["noscript", "movablekeys"], 0, 0, 1),
+        cmd_info("eval_ro", -3, &["readonly", "noscript", "movablekeys"], 0, 0, 1),
+        cmd_info("evalsha_ro", -3, &["readonly", "noscript", "movablekeys"], 0, 0, 1),
+        cmd_info("fcall", -3, &["noscript", "movablekeys"], 0, 0, 1),
+        cmd_info("fcall_ro", -3, &["readonly", "noscript", "movablekeys"], 0, 0, 1),
         cmd_info("script", -2, &["noscript"], 0, 0, 1),
     ];
     
@@ -157,6 +188,9 @@ fn get_command_info(cmd_name: &str) -> RespFrame {
         "lpop" => cmd_info("lpop", -2, &["write", "fast"], 1, 1, 1),
         "lrange" => cmd_info("lrange", 4, &["readonly"], 1, 1, 1),
         "eval" => cmd_info("eval", -3, &["noscript", "movablekeys"], 0, 0, 1),
+        "eval_ro" => cmd_info("eval_ro", -3, &["readonly", "noscript", "movablekeys"], 0, 0, 1),
+        "evalsha_ro" => cmd_info("evalsha_ro", -3, &["readonly", "noscript", "movablekeys"], 0, 0, 1),
+        "fcall_ro" => cmd_info("fcall_ro", -3, &["readonly", "noscript", "movablekeys"], 0, 0, 1),
         "shutdown" => cmd_info("shutdown", -1, &["admin", "noscript"], 0, 0, 1),
         _ => RespFrame::null_bulk(),
     }
@@ -1,7 +1,9 @@
-//! Blocking operations implementation for BLPOP/BRPOP commands
+//! Blocking operations implementation for blocking list commands and XREAD BLOCK
 //! 
 //! This module implements the zero-overhead blocking subsystem that enables
 //! Redis blocking list operations while maintaining Ferrous's excellent performance.
+//! Clients are parked per key and woken through a queue drained by the event
+//! loop; nothing polls the keys.
 
 use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, RwLock, Mutex};
@@ -78,18 +80,33 @@ impl BlockingRegistry {
         self.blocked_keys.contains(key)
     }
     
-    /// Pop the first waiting client for a key
-    pub fn pop_first_waiter(&mut self, key: &[u8]) -> Option<BlockedClient> {
-        if let Some(clients) = self.blocked_on_key.get_mut(key) {
-            let client = clients.pop_front();
-            if clients.is_empty() {
-                self.blocked_on_key.remove(key);
-                self.blocked_keys.remove(key);
-            }
-            client
+    /// Check if any client is blocked in this database
+    pub fn is_empty(&self) -> bool {
+        self.blocked_keys.is_empty()
+    }
+    
+    /// Pop the clients to wake for a key that received data
+    ///
+    /// A list element serves a single client, so only the first waiter is
+    /// woken; stream readers do not consume entries, so all of them are.
+    pub fn pop_waiters(&mut self, key: &[u8]) -> Vec<BlockedClient> {
+        let Some(clients) = self.blocked_on_key.get_mut(key) else {
+            return Vec::new();
+        };
+        let is_reader = |client: &BlockedClient| matches!(client.op_type, BlockingOp::XReadBlock(_));
+        let woken = if clients.front().is_some_and(is_reader) {
+            let (readers, rest): (VecDeque<_>, VecDeque<_>) = clients.drain(..).partition(is_reader);
+            *clients = rest;
+            readers.into()
         } else {
-            None
+            clients.pop_front().into_iter().collect()
+        };
+        
+        if clients.is_empty() {
+            self.blocked_on_key.remove(key);
+            self.blocked_keys.remove(key);
         }
+        woken
     }
     
     /// Get expired clients based on current time
@@ -198,30 +215,28 @@ impl BlockingManager {
         registry.has_blocked_clients(key)
     }
     
-    /// Notify that a key has received data (called from LPUSH/RPUSH)
+    /// Check if any client is blocked in a database (fast read-only check)
+    pub fn has_blocked_clients_in(&self, db: DatabaseIndex) -> bool {
+        db < self.registries.len() && !self.registries[db].read().unwrap().is_empty()
+    }
+    
+    /// Notify that a key has received data (called after writes to the key)
     pub fn notify_key_ready(&self, db: DatabaseIndex, key: &[u8]) {
         if db >= self.registries.len() {
             return;
         }
         
-        // Only wake up one client at a time per key to prevent deadlock
-        // When an item is pushed, only the first waiting client should be notified
-        let client = {
-            let mut registry = self.registries[db].write().unwrap();
-            match registry.pop_first_waiter(key) {
-                Some(c) => c,
-                None => return, // No clients waiting on this key
-            }
-        };
-        
-        // Send single wake-up request
-        // Additional items pushed will wake additional clients one by one
-        self.wake_queue.push(WakeupRequest {
-            conn_id: client.conn_id,
-            db,
-            key: key.to_vec(),
-            op_type: client.op_type,
-        });
+        // List waiters are woken one at a time: a served client writes the key
+        // again, which wakes the next one while elements remain
+        let clients = self.registries[db].write().unwrap().pop_waiters(key);
+        for client in clients {
+            self.wake_queue.push(WakeupRequest {
+                conn_id: client.conn_id,
+                db,
+                key: key.to_vec(),
+                op_type: client.op_type,
+            });
+        }
     }
     
     /// Process wake-up queue (called from main server loop)
@@ -269,4 +284,41 @@ impl Default for BlockingRegistry {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn client(conn_id: u64, op_type: BlockingOp) -> BlockedClient {
+        BlockedClient { conn_id, blocked_at: Instant::now(), deadline: None, op_type }
+    }
+    
+    #[test]
+    fn test_list_waiters_wake_one_at_a_time() {
+        let mut registry = BlockingRegistry::new();
+        let keys = [(0, b"queue".to_vec())];
+        registry.register_blocked_client(client(1, BlockingOp::BLPop), &keys);
+        registry.register_blocked_client(client(2, BlockingOp::BRPop), &keys);
+        
+        let woken: Vec<u64> = registry.pop_waiters(b"queue").iter().map(|client| client.conn_id).collect();
+        assert_eq!(woken, vec![1]);
+        assert!(registry.has_blocked_clients(b"queue"));
+        assert_eq!(registry.pop_waiters(b"queue").len(), 1);
+        assert!(registry.is_empty());
+    }
+    
+    #[test]
+    fn test_stream_readers_wake_together() {
+        let mut registry = BlockingRegistry::new();
+        let keys = [(0, b"events".to_vec())];
+        for conn_id in 1..=3 {
+            registry.register_blocked_client(client(conn_id, BlockingOp::XReadBlock(Vec::new())), &keys);
+        }
+        
+        let woken: Vec<u64> = registry.pop_waiters(b"events").iter().map(|client| client.conn_id).collect();
+        assert_eq!(woken, vec![1, 2, 3]);
+        assert!(registry.is_empty());
+        assert!(registry.pop_waiters(b"events").is_empty());
+    }
+}
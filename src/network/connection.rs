@@ -3,13 +3,32 @@
 //! Handles the lifecycle of a client connection including reading, writing,
 //! and protocol parsing.
 
+use std::collections::VecDeque;
 use std::net::{TcpStream, SocketAddr};
-use std::io::{Read, Write, ErrorKind};
+use std::io::{Write, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Instant;
 use crate::error::{FerrousError, Result};
 use crate::protocol::{RespParser, RespFrame, serialize_resp_frame};
+use crate::protocol::serializer::ScratchBuffer;
 use crate::storage::commands::transactions::TransactionState;
 use crate::storage::DatabaseIndex;
+use crate::storage::value::ListEnd;
+use super::tracking::TrackingOptions;
+
+/// Bytes read from all connections, for INFO total_net_input_bytes
+static NET_INPUT_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Bytes written to all connections, for INFO total_net_output_bytes
+static NET_OUTPUT_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Total bytes read from and written to clients since startup
+pub fn net_io_bytes() -> (u64, u64) {
+    (NET_INPUT_BYTES.load(Ordering::Relaxed), NET_OUTPUT_BYTES.load(Ordering::Relaxed))
+}
+
+/// Most bytes [`Connection::read`] takes from a client at once
+const MAX_READ_PER_CALL: usize = 1024 * 1024;
 
 /// Connection state
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -23,6 +42,12 @@ pub enum ConnectionState {
     /// Blocked on a blocking operation
     Blocked(BlockedState),
     
+    /// Waiting for a reply being computed on the worker pool
+    AwaitingWorker,
+    
+    /// Held by CLIENT PAUSE, with the held command first in `deferred_frames`
+    Paused,
+    
     /// Connection is closing
     Closing,
 }
@@ -43,7 +68,19 @@ pub struct BlockedState {
 pub enum BlockingOp {
     BLPop,
     BRPop,
-    XReadBlock(Vec<(DatabaseIndex, Vec<u8>, String)>), // (db, key, last_id)
+    /// BLMOVE (and BRPOPLPUSH): move to `destination` once a source has data
+    BLMove {
+        destination: Vec<u8>,
+        from: ListEnd,
+        to: ListEnd,
+    },
+    /// BLMPOP: pop up to `count` elements from the first key with data
+    BLMPop {
+        end: ListEnd,
+        count: usize,
+    },
+    /// XREAD or XREADGROUP with BLOCK: the non-blocking read re-run on wake-up
+    XReadBlock(Vec<Vec<u8>>),
 }
 
 
@@ -59,18 +96,27 @@ pub struct Connection {
     /// Client address
     pub addr: SocketAddr,
     
+    /// Local address the client connected to
+    pub local_addr: SocketAddr,
+    
     /// Connection state
     pub state: ConnectionState,
     
     /// RESP protocol parser
     parser: RespParser,
     
-    /// Write buffer
-    write_buffer: Vec<u8>,
+    /// Write buffer, reused across requests
+    write_buffer: ScratchBuffer,
     
     /// Write buffer offset (for partial writes)
     write_offset: usize,
     
+    /// Replies not serialized yet, with I/O threads serializing at flush time
+    reply_frames: Vec<RespFrame>,
+    
+    /// Queue replies in `reply_frames` rather than serializing them on send
+    defer_serialization: bool,
+    
     /// Last activity timestamp
     pub last_activity: Instant,
     
@@ -88,6 +134,33 @@ pub struct Connection {
     
     /// Client name (set via CLIENT SETNAME)
     pub name: Option<String>,
+    
+    /// Pipelined frames parsed while the connection was awaiting a worker reply
+    pub deferred_frames: VecDeque<RespFrame>,
+    
+    /// Commands from this client don't update LRU/LFU access clocks (CLIENT NO-TOUCH)
+    pub no_touch: bool,
+    
+    /// Client is exempt from client eviction (CLIENT NO-EVICT)
+    pub no_evict: bool,
+    
+    /// This is a replica streaming our writes, after SYNC or PSYNC
+    pub is_replica: bool,
+    
+    /// Port announced with REPLCONF listening-port, if this is a replica
+    pub replica_listening_port: Option<u16>,
+    
+    /// ASKING was sent: the next command may reach a slot being imported
+    pub asking: bool,
+    
+    /// RESP protocol version chosen with HELLO (2 or 3)
+    pub protocol: u8,
+    
+    /// Client-side caching settings, while CLIENT TRACKING is on
+    pub tracking: Option<TrackingOptions>,
+    
+    /// ACL user the client is authenticated as
+    pub user: String,
 }
 
 impl Connection {
@@ -100,46 +173,77 @@ impl Connection {
         stream.set_nodelay(true)?;
         
         let now = Instant::now();
+        let local_addr = stream.local_addr()?;
         
         Ok(Connection {
             id,
             stream,
             addr,
+            local_addr,
             state: ConnectionState::Connected,
             parser: RespParser::new(),
-            write_buffer: Vec::with_capacity(16384), // Larger initial capacity for better pipelining
+            write_buffer: ScratchBuffer::default(), // Larger initial capacity for better pipelining
             write_offset: 0,
+            reply_frames: Vec::new(),
+            defer_serialization: false,
             last_activity: now,
             created_at: now,
             db_index: 0,
             transaction_state: TransactionState::default(),
             is_monitoring: false,
             name: None,
+            deferred_frames: VecDeque::new(),
+            no_touch: false,
+            no_evict: false,
+            is_replica: false,
+            replica_listening_port: None,
+            asking: false,
+            protocol: 2,
+            tracking: None,
+            user: crate::network::acl::DEFAULT_USER.to_string(),
         })
     }
     
-    /// Read data from the connection
+    /// Read everything the client sent so far
+    ///
+    /// Draining the socket lets a whole pipeline be parsed, executed and
+    /// answered as one batch, up to [`MAX_READ_PER_CALL`] bytes so a client
+    /// streaming requests can't starve the others.
     /// Returns true if data was read, false if would block
     pub fn read(&mut self) -> Result<bool> {
-        let mut buf = [0u8; 8192]; // Larger read buffer for better pipelining
+        let mut total = 0;
         
-        match self.stream.read(&mut buf) {
-            Ok(0) => {
-                // Connection closed by peer
-                self.state = ConnectionState::Closing;
-                Err(FerrousError::Connection("Connection closed by peer".into()))
-            }
-            Ok(n) => {
-                self.last_activity = Instant::now();
-                self.parser.feed(&buf[..n]);
-                Ok(true)
-            }
-            Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                // No data available
-                Ok(false)
+        while total < MAX_READ_PER_CALL {
+            // Read into the parser's buffer, sparing a copy
+            match self.parser.read_from(&mut self.stream) {
+                Ok((0, _)) if total == 0 => {
+                    // Connection closed by peer
+                    self.state = ConnectionState::Closing;
+                    return Err(FerrousError::Connection("Connection closed by peer".into()));
+                }
+                Ok((0, _)) => {
+                    // The next read reports the close, once this input was served
+                    break;
+                }
+                Ok((n, filled)) => {
+                    total += n;
+                    if !filled {
+                        // Short read: the socket is drained
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) if total == 0 => return Err(e.into()),
+                Err(_) => break,
             }
-            Err(e) => Err(e.into()),
         }
+        
+        if total > 0 {
+            self.last_activity = Instant::now();
+            NET_INPUT_BYTES.fetch_add(total as u64, Ordering::Relaxed);
+        }
+        Ok(total > 0)
     }
     
     /// Try to parse a frame from the read buffer
@@ -149,23 +253,59 @@ impl Connection {
     
     /// Send a frame to the client
     pub fn send_frame(&mut self, frame: &RespFrame) -> Result<()> {
+        if self.defer_serialization {
+            self.reply_frames.push(frame.clone());
+            return Ok(());
+        }
+        
         // Serialize directly to the write buffer without clearing it
-        serialize_resp_frame(frame, &mut self.write_buffer)?;
+        serialize_resp_frame(frame, &mut *self.write_buffer)?;
         // Don't flush here - let the caller decide when to flush
         Ok(())
     }
     
+    /// Send a frame the caller no longer needs, sparing a copy when replies
+    /// are serialized later
+    pub fn queue_frame(&mut self, frame: RespFrame) -> Result<()> {
+        if self.defer_serialization {
+            self.reply_frames.push(frame);
+            Ok(())
+        } else {
+            self.send_frame(&frame)
+        }
+    }
+    
+    /// Queue replies and serialize them when flushing, for I/O threads to do
+    /// that work instead of the event loop
+    pub fn set_deferred_serialization(&mut self, deferred: bool) {
+        self.defer_serialization = deferred;
+    }
+    
+    /// Serialize the queued replies into the write buffer, in order
+    fn serialize_queued(&mut self) -> Result<()> {
+        for frame in self.reply_frames.drain(..) {
+            serialize_resp_frame(&frame, &mut *self.write_buffer)?;
+        }
+        Ok(())
+    }
+    
     /// Send raw bytes to the client
     pub fn send_raw(&mut self, data: &[u8]) -> Result<()> {
+        // Queued replies were sent first
+        self.serialize_queued()?;
         self.write_buffer.extend_from_slice(data);
         Ok(())
     }
     
     /// Flush the write buffer with improved error handling for pipelining
+    ///
+    /// All queued replies go out in one write. What the socket can't take
+    /// right now stays buffered for a later flush, without blocking the loop.
     pub fn flush(&mut self) -> Result<()> {
+        self.serialize_queued()?;
         if self.write_offset >= self.write_buffer.len() {
             // Nothing to write
-            self.write_buffer.clear();
+            self.write_buffer.recycle();
             self.write_offset = 0;
             return Ok(());
         }
@@ -182,21 +322,11 @@ impl Connection {
                 Ok(n) => {
                     self.write_offset += n;
                     self.last_activity = Instant::now();
+                    NET_OUTPUT_BYTES.fetch_add(n as u64, Ordering::Relaxed);
                 }
                 Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                    // Can't write more right now, maintain offset
-                    // Improved backoff for pipelining scenarios
-                    attempts += 1;
-                    if attempts < MAX_ATTEMPTS {
-                        // Progressive backoff for better pipelining handling
-                        let wait_time = std::time::Duration::from_millis(attempts as u64 * 10);
-                        std::thread::sleep(wait_time);
-                        continue;
-                    } else {
-                        // After max attempts, return error but don't close connection
-                        // This allows retry on next processing cycle
-                        return Err(FerrousError::Connection("Write would block after max attempts".into()));
-                    }
+                    // Socket buffer full: keep the offset, the rest is written on a later pass
+                    break;
                 }
                 Err(e) if e.kind() == ErrorKind::Interrupted => {
                     // Retry interrupted operations
@@ -226,7 +356,7 @@ impl Connection {
         
         // Clear buffer if everything was written
         if self.write_offset >= self.write_buffer.len() {
-            self.write_buffer.clear();
+            self.write_buffer.recycle();
             self.write_offset = 0;
         }
         
@@ -235,7 +365,12 @@ impl Connection {
     
     /// Check if the connection has data to write
     pub fn has_pending_writes(&self) -> bool {
-        self.write_offset < self.write_buffer.len()
+        !self.reply_frames.is_empty() || self.write_offset < self.write_buffer.len()
+    }
+    
+    /// Number of bytes queued but not yet written to the socket
+    pub fn pending_write_len(&self) -> usize {
+        self.write_buffer.len().saturating_sub(self.write_offset)
     }
     
     /// Close the connection
@@ -261,6 +396,7 @@ impl Connection {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Read;
     
     #[test]
     fn test_connection_state() {
@@ -268,4 +404,54 @@ mod tests {
         assert_eq!(state, ConnectionState::Connected);
         assert_ne!(state, ConnectionState::Authenticated);
     }
+    
+    #[test]
+    fn test_deferred_serialization_keeps_order() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, addr) = listener.accept().unwrap();
+        let mut conn = Connection::new(1, stream, addr).unwrap();
+        conn.set_deferred_serialization(true);
+        
+        conn.queue_frame(RespFrame::ok()).unwrap();
+        conn.send_frame(&RespFrame::Integer(1)).unwrap();
+        assert!(conn.has_pending_writes());
+        assert_eq!(conn.pending_write_len(), 0);
+        
+        // Raw output goes after the replies queued before it
+        conn.send_raw(b"+raw\r\n").unwrap();
+        conn.queue_frame(RespFrame::Integer(2)).unwrap();
+        conn.flush().unwrap();
+        assert!(!conn.has_pending_writes());
+        
+        let expected = b"+OK\r\n:1\r\n+raw\r\n:2\r\n";
+        let mut received = vec![0; expected.len()];
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(received, expected);
+    }
+    
+    #[test]
+    fn test_read_drains_pipeline() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, addr) = listener.accept().unwrap();
+        let mut conn = Connection::new(1, stream, addr).unwrap();
+        
+        // Several reads' worth of requests
+        let pipeline = b"*1\r\n$4\r\nPING\r\n".repeat(5000);
+        client.write_all(&pipeline).unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        
+        assert!(conn.read().unwrap());
+        let mut frames = 0;
+        while conn.parse_frame().unwrap().is_some() {
+            frames += 1;
+        }
+        assert_eq!(frames, 5000);
+        
+        // The close is reported once the pipeline was taken
+        assert!(conn.read().is_err());
+        assert!(conn.is_closing());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,105 @@
+//! I/O threads (io-threads)
+//!
+//! Commands still execute one at a time on the event loop. What the I/O
+//! threads take over is the per-connection work around them, as in Redis 6:
+//! reading sockets and parsing requests before the loop executes them, then
+//! serializing and writing the replies. Each phase is a fork-join: the event
+//! loop hands the same job to every thread, does its own share and waits for
+//! the others, so a connection is never used by the event loop and an I/O
+//! thread at the same time.
+
+use std::sync::Arc;
+use std::thread;
+use crossbeam::channel::{self, Receiver, Sender};
+
+/// Most threads io-threads accepts, as in Redis
+pub const MAX_IO_THREADS: usize = 128;
+
+/// Work run by every thread of a phase, given the thread's index
+pub type IoJob = Arc<dyn Fn(usize) + Send + Sync>;
+
+/// Signals the end of a thread's share, even if the job panicked
+struct Done<'a>(&'a Sender<()>);
+
+impl Drop for Done<'_> {
+    fn drop(&mut self) {
+        let _ = self.0.send(());
+    }
+}
+
+/// I/O threads the event loop forks phases out to
+pub struct IoThreads {
+    jobs: Vec<Sender<IoJob>>,
+    done: Receiver<()>,
+}
+
+impl IoThreads {
+    /// Start the pool for `threads` I/O threads
+    ///
+    /// As with io-threads, the event loop counts as the first one, so
+    /// `threads - 1` threads are spawned.
+    pub fn new(threads: usize) -> Self {
+        let (done_tx, done_rx) = channel::unbounded::<()>();
+        let jobs = (1..threads.clamp(1, MAX_IO_THREADS))
+            .map(|index| {
+                let (job_tx, job_rx) = channel::unbounded::<IoJob>();
+                let done_tx = done_tx.clone();
+                thread::Builder::new()
+                    .name(format!("ferrous-io-{}", index))
+                    .spawn(move || {
+                        // Exits once the pool (and with it the job sender) is dropped
+                        for job in job_rx {
+                            let _done = Done(&done_tx);
+                            job(index);
+                        }
+                    })
+                    .expect("failed to spawn I/O thread");
+                job_tx
+            })
+            .collect();
+        
+        IoThreads { jobs, done: done_rx }
+    }
+    
+    /// Number of threads sharing a phase, the event loop included
+    pub fn threads(&self) -> usize {
+        self.jobs.len() + 1
+    }
+    
+    /// Run `job` on every thread, index 0 being the calling one, and wait
+    /// until all of them are done
+    pub fn run(&self, job: IoJob) {
+        let started = self.jobs.iter()
+            .filter(|jobs| jobs.send(job.clone()).is_ok())
+            .count();
+        job(0);
+        for _ in 0..started {
+            let _ = self.done.recv();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    
+    #[test]
+    fn test_every_thread_runs_its_share() {
+        let pool = IoThreads::new(4);
+        assert_eq!(pool.threads(), 4);
+        
+        for _ in 0..10 {
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let shares = seen.clone();
+            pool.run(Arc::new(move |index| shares.lock().unwrap().push(index)));
+            
+            // Every share finished by the time run returns
+            let mut seen = seen.lock().unwrap().clone();
+            seen.sort();
+            assert_eq!(seen, vec![0, 1, 2, 3]);
+        }
+        
+        assert_eq!(IoThreads::new(0).threads(), 1);
+    }
+}
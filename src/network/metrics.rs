@@ -0,0 +1,234 @@
+//! Live metrics behind INFO commandstats, latencystats and the instantaneous
+//! rates of the stats section
+//!
+//! Per-command counters are only fed while performance monitoring is enabled
+//! (slowlog-enabled, monitor-enabled or stats-enabled), since timing every
+//! command is what monitoring costs; the rates are sampled by the event loop.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Exact buckets below this many microseconds, then 8 buckets per power of two
+const LINEAR_BUCKETS: u64 = 8;
+
+/// Enough buckets for any `u64` latency
+const BUCKET_COUNT: usize = 8 + 61 * 8;
+
+/// Latency distribution of a command, in microseconds
+///
+/// Log-linear buckets keep every percentile within 12.5% of the exact value,
+/// which is plenty for spotting a slow command.
+#[derive(Clone)]
+pub struct LatencyHistogram {
+    buckets: Vec<u64>,
+    count: u64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self { buckets: vec![0; BUCKET_COUNT], count: 0 }
+    }
+    
+    /// Bucket holding a latency
+    fn bucket(usec: u64) -> usize {
+        if usec < LINEAR_BUCKETS {
+            return usec as usize;
+        }
+        let exp = 63 - usec.leading_zeros() as u64;
+        let mantissa = (usec >> (exp - 3)) - LINEAR_BUCKETS;
+        (LINEAR_BUCKETS + (exp - 3) * 8 + mantissa) as usize
+    }
+    
+    /// Highest latency that falls in a bucket
+    fn upper_bound(bucket: usize) -> u64 {
+        let bucket = bucket as u64;
+        if bucket < LINEAR_BUCKETS {
+            return bucket;
+        }
+        let exp = (bucket - LINEAR_BUCKETS) / 8 + 3;
+        let mantissa = (bucket - LINEAR_BUCKETS) % 8;
+        let end = ((LINEAR_BUCKETS + mantissa + 1) as u128) << (exp - 3);
+        (end - 1).min(u64::MAX as u128) as u64
+    }
+    
+    /// Record one call
+    pub fn record(&mut self, usec: u64) {
+        self.buckets[Self::bucket(usec)] += 1;
+        self.count += 1;
+    }
+    
+    /// Latency under which `percentile` percent of the calls completed
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        let rank = ((percentile / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::upper_bound(bucket);
+            }
+        }
+        0
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Counters of one command, as INFO commandstats reports them
+#[derive(Clone, Default)]
+pub struct CommandStat {
+    /// Times the command ran
+    pub calls: u64,
+    
+    /// Total execution time in microseconds
+    pub usec: u64,
+    
+    /// Calls that replied with an error
+    pub failed_calls: u64,
+    
+    /// Latency distribution for INFO latencystats
+    pub latency: LatencyHistogram,
+}
+
+/// Per-command statistics, keyed by lowercase command name
+#[derive(Default)]
+pub struct CommandStats {
+    table: Mutex<HashMap<String, CommandStat>>,
+}
+
+impl CommandStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Record one execution of `command`
+    pub fn record(&self, command: &str, elapsed: Duration, failed: bool) {
+        let usec = elapsed.as_micros() as u64;
+        let mut table = self.table.lock().unwrap();
+        let stat = table.entry(command.to_lowercase()).or_default();
+        stat.calls += 1;
+        stat.usec += usec;
+        stat.failed_calls += failed as u64;
+        stat.latency.record(usec);
+    }
+    
+    /// Statistics of every command that ran, sorted by name
+    pub fn snapshot(&self) -> Vec<(String, CommandStat)> {
+        let mut stats: Vec<_> = self.table.lock().unwrap()
+            .iter()
+            .map(|(name, stat)| (name.clone(), stat.clone()))
+            .collect();
+        stats.sort_by(|a, b| a.0.cmp(&b.0));
+        stats
+    }
+    
+    /// Forget all statistics (CONFIG RESETSTAT)
+    pub fn reset(&self) {
+        self.table.lock().unwrap().clear();
+    }
+}
+
+/// Number of samples averaged by an [`InstantaneousMetric`]
+const METRIC_SAMPLES: usize = 16;
+
+/// Minimum time between two samples
+const SAMPLE_PERIOD: Duration = Duration::from_millis(100);
+
+/// Per-second rate of a growing counter, averaged over the last samples
+///
+/// Like Redis' instantaneous_ops_per_sec: the event loop samples the counter
+/// about every 100ms and the rate is the mean of the last 16 samples.
+#[derive(Default)]
+pub struct InstantaneousMetric {
+    samples: [f64; METRIC_SAMPLES],
+    next: usize,
+    last: Option<(Instant, u64)>,
+}
+
+impl InstantaneousMetric {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Take a sample of `total`, unless the previous one is too recent
+    pub fn sample(&mut self, total: u64, now: Instant) {
+        if let Some((at, previous)) = self.last {
+            let elapsed = now.duration_since(at);
+            if elapsed < SAMPLE_PERIOD {
+                return;
+            }
+            self.samples[self.next] = total.saturating_sub(previous) as f64 / elapsed.as_secs_f64();
+            self.next = (self.next + 1) % METRIC_SAMPLES;
+        }
+        self.last = Some((now, total));
+    }
+    
+    /// Average rate per second
+    pub fn per_second(&self) -> f64 {
+        self.samples.iter().sum::<f64>() / METRIC_SAMPLES as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_latency_percentiles() {
+        let mut histogram = LatencyHistogram::new();
+        for usec in 1..=1000 {
+            histogram.record(usec);
+        }
+        
+        let p50 = histogram.percentile(50.0);
+        assert!((500..=563).contains(&p50), "p50 = {}", p50);
+        let p99 = histogram.percentile(99.0);
+        assert!((990..=1023).contains(&p99), "p99 = {}", p99);
+        assert_eq!(histogram.percentile(0.0), 1);
+        
+        // Bucket bounds cover every value exactly once
+        for usec in [0, 7, 8, 9, 15, 16, 1000, u64::MAX] {
+            let bucket = LatencyHistogram::bucket(usec);
+            assert!(usec <= LatencyHistogram::upper_bound(bucket));
+            if bucket > 0 {
+                assert!(usec > LatencyHistogram::upper_bound(bucket - 1));
+            }
+        }
+    }
+    
+    #[test]
+    fn test_command_stats() {
+        let stats = CommandStats::new();
+        stats.record("GET", Duration::from_micros(10), false);
+        stats.record("get", Duration::from_micros(30), true);
+        stats.record("SET", Duration::from_micros(5), false);
+        
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].0, "get");
+        assert_eq!((snapshot[0].1.calls, snapshot[0].1.usec, snapshot[0].1.failed_calls), (2, 40, 1));
+        
+        stats.reset();
+        assert!(stats.snapshot().is_empty());
+    }
+    
+    #[test]
+    fn test_instantaneous_metric() {
+        let mut metric = InstantaneousMetric::new();
+        let start = Instant::now();
+        metric.sample(0, start);
+        
+        // Samples closer than the period are skipped
+        metric.sample(50, start + Duration::from_millis(10));
+        assert_eq!(metric.per_second(), 0.0);
+        
+        for i in 1..=METRIC_SAMPLES as u64 {
+            metric.sample(i * 100, start + Duration::from_millis(i * 100));
+        }
+        assert!((metric.per_second() - 1000.0).abs() < 1.0);
+    }
+}
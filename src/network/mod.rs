@@ -8,6 +8,12 @@ pub mod server;
 pub mod monitoring;
 pub mod blocking;
 pub mod admin_commands;
+pub mod worker_pool;
+pub mod tracking;
+pub mod pause;
+pub mod acl;
+pub mod metrics;
+pub mod io_threads;
 
 pub use listener::Listener;
 pub use server::Server;
@@ -37,6 +43,10 @@ pub struct NetworkConfig {
     /// Optional password for authentication
     /// If None, no authentication is required
     pub password: Option<String>,
+    
+    /// Threads reading requests and writing replies, the event loop included
+    /// (1 = the event loop does all I/O)
+    pub io_threads: usize,
 }
 
 impl Default for NetworkConfig {
@@ -49,6 +59,7 @@ impl Default for NetworkConfig {
             timeout: 0, // 0 means no timeout
             tcp_keepalive: Some(300), // 5 minutes
             password: None, // No password by default
+            io_threads: 1,
         }
     }
 }
\ No newline at end of file
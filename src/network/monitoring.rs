@@ -16,8 +16,9 @@ pub trait PerformanceMonitoring: Send + Sync {
     /// Start timing a command (returns start time)
     fn start_timing(&self) -> Option<Instant>;
     
-    /// Record command completion with timing
-    fn record_command_timing(&self, start_time: Option<Instant>, command: &str, parts: &[RespFrame], client_addr: &str);
+    /// Record command completion with timing, the effects a script propagated
+    /// and whether the command replied with an error
+    fn record_command_timing(&self, start_time: Option<Instant>, command: &str, parts: &[RespFrame], client_addr: &str, effects: Option<u64>, failed: bool);
     
     /// Record command statistics
     fn record_command_count(&self);
@@ -50,9 +51,10 @@ impl PerformanceMonitoring for ActiveMonitoring {
         Some(Instant::now())
     }
     
-    fn record_command_timing(&self, start_time: Option<Instant>, command: &str, parts: &[RespFrame], client_addr: &str) {
+    fn record_command_timing(&self, start_time: Option<Instant>, command: &str, parts: &[RespFrame], client_addr: &str, effects: Option<u64>, failed: bool) {
         if let Some(start) = start_time {
             let duration = start.elapsed();
+            self.stats.commands.record(command, duration, failed);
             let duration_micros = duration.as_micros() as u64;
             let threshold_micros = self.slowlog.get_threshold_micros();
             
@@ -63,7 +65,7 @@ impl PerformanceMonitoring for ActiveMonitoring {
                     &format!("Adding slow command: {} ({}μs) from {}", command, duration_micros, client_addr)
                 );
                 
-                self.slowlog.add_if_slow(duration, parts, client_addr, None);
+                self.slowlog.add_if_slow(duration, parts, client_addr, None, effects);
             } else {
                 crate::storage::commands::debug::log_slowlog(
                     &format!("Command not slow enough: {} ({}μs, threshold {}μs)", 
@@ -121,7 +123,7 @@ impl PerformanceMonitoring for NullMonitoring {
     }
     
     #[inline(always)]
-    fn record_command_timing(&self, _start_time: Option<Instant>, _command: &str, _parts: &[RespFrame], _client_addr: &str) {
+    fn record_command_timing(&self, _start_time: Option<Instant>, _command: &str, _parts: &[RespFrame], _client_addr: &str, _effects: Option<u64>, _failed: bool) {
         // Zero-cost no-op - compiles away completely
     }
     
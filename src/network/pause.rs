@@ -0,0 +1,159 @@
+//! CLIENT PAUSE gate
+//!
+//! While a pause is in effect, clients issuing a gated command are held: the
+//! command and everything pipelined after it wait on the connection until the
+//! pause ends or CLIENT UNPAUSE is called, then run in order. Nothing is
+//! refused, so clients only see added latency, as with Redis.
+
+use std::time::{Duration, Instant};
+use crate::storage::commands::flags;
+
+/// Which commands a pause holds back
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PauseMode {
+    /// Commands that may modify the dataset
+    Write,
+    
+    /// Every command
+    All,
+}
+
+/// Pause state shared by the event loop and CLIENT PAUSE/UNPAUSE
+#[derive(Debug, Default)]
+pub struct PauseGate {
+    /// End of the pause and what it holds, while one was requested
+    pause: Option<(Instant, PauseMode)>,
+    
+    /// Some client has been held since the pause started
+    holding: bool,
+}
+
+impl PauseGate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Pause clients for `duration`
+    ///
+    /// A pause already in effect is only ever extended: the later end and the
+    /// stricter mode win.
+    pub fn pause(&mut self, duration: Duration, mode: PauseMode) {
+        let until = Instant::now() + duration;
+        self.pause = Some(match self.active_pause() {
+            Some((end, current)) => (end.max(until), current.max(mode)),
+            None => (until, mode),
+        });
+    }
+    
+    /// End the pause
+    pub fn unpause(&mut self) {
+        self.pause = None;
+    }
+    
+    /// Current pause, if it has not expired yet
+    fn active_pause(&self) -> Option<(Instant, PauseMode)> {
+        self.pause.filter(|(until, _)| Instant::now() < *until)
+    }
+    
+    /// Whether a pause is in effect
+    pub fn is_active(&self) -> bool {
+        self.active_pause().is_some()
+    }
+    
+    /// Whether `command` has to wait for the pause to end
+    ///
+    /// `writes` tells whether the command would modify the dataset, for
+    /// commands such as EXEC whose name alone doesn't say. Held commands mark
+    /// the gate so [`PauseGate::release`] knows clients are waiting.
+    pub fn holds(&mut self, command: &str, writes: bool) -> bool {
+        let Some((_, mode)) = self.active_pause() else {
+            return false;
+        };
+        
+        // Clients can always authenticate, leave, and lift the pause
+        if matches!(command, "AUTH" | "HELLO" | "QUIT" | "CLIENT" | "REPLCONF") {
+            return false;
+        }
+        
+        let held = match mode {
+            PauseMode::All => true,
+            PauseMode::Write => writes || is_paused_for_writes(command),
+        };
+        self.holding |= held;
+        held
+    }
+    
+    /// Check whether held clients may resume, once the pause is over
+    pub fn release(&mut self) -> bool {
+        if !self.holding || self.is_active() {
+            return false;
+        }
+        self.pause = None;
+        self.holding = false;
+        true
+    }
+}
+
+/// Commands WRITE mode holds: writes, plus the ones that could write or
+/// reveal replication progress (scripts, PUBLISH, PFCOUNT and WAIT)
+fn is_paused_for_writes(command: &str) -> bool {
+    flags::is_write_command(command)
+        || matches!(command, "EVAL" | "EVALSHA" | "FCALL" | "PUBLISH" | "PFCOUNT" | "WAIT")
+}
+
+/// Parse the mode argument of CLIENT PAUSE
+pub fn parse_mode(arg: &[u8]) -> Option<PauseMode> {
+    if arg.eq_ignore_ascii_case(b"WRITE") {
+        Some(PauseMode::Write)
+    } else if arg.eq_ignore_ascii_case(b"ALL") {
+        Some(PauseMode::All)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_write_pause() {
+        let mut gate = PauseGate::new();
+        assert!(!gate.holds("SET", true));
+        
+        gate.pause(Duration::from_secs(10), PauseMode::Write);
+        assert!(gate.holds("SET", true));
+        assert!(gate.holds("PUBLISH", false));
+        assert!(gate.holds("EXEC", true));
+        assert!(!gate.holds("EXEC", false));
+        assert!(!gate.holds("GET", false));
+        assert!(!gate.holds("CLIENT", false));
+        
+        // Held clients resume once unpaused
+        assert!(!gate.release());
+        gate.unpause();
+        assert!(gate.release());
+        assert!(!gate.release());
+    }
+    
+    #[test]
+    fn test_pause_only_escalates() {
+        let mut gate = PauseGate::new();
+        gate.pause(Duration::from_secs(10), PauseMode::All);
+        gate.pause(Duration::from_millis(1), PauseMode::Write);
+        assert!(gate.holds("GET", false));
+        
+        let mut gate = PauseGate::new();
+        gate.pause(Duration::from_millis(1), PauseMode::Write);
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(!gate.is_active());
+        assert!(!gate.holds("SET", true));
+    }
+    
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode(b"write"), Some(PauseMode::Write));
+        assert_eq!(parse_mode(b"ALL"), Some(PauseMode::All));
+        assert_eq!(parse_mode(b"READ"), None);
+    }
+}
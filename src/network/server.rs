@@ -6,34 +6,54 @@ use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH, Instant};
 use std::thread;
 use std::path::PathBuf;
-use rand;
-use crate::error::{FerrousError, Result};
+use crate::error::{FerrousError, Result, ScriptError, StorageError};
 use crate::protocol::RespFrame;
 use crate::storage::{StorageEngine, RdbEngine, StorageMonitor};
-use crate::storage::commands::transactions;
+use crate::storage::commands::{flags, lists, streams, transactions};
+use crate::storage::value::{ListEnd, Value};
 use crate::storage::aof::AofEngine;
+use crate::storage::memory::MemoryManager;
 use crate::storage::commands::slowlog::Slowlog;
+use crate::storage::commands::executor::{CommandParser, LuaCommandAdapter};
 use crate::storage::lua_cache::{GlobalScriptCache, ScriptCaching};
+use crate::storage::{lua_busy, lua_checkpoint, lua_effects, lua_quota};
+use crate::storage::lua_engine::ReadOnlyScript;
 
 use crate::monitor::MonitorSubscribers;
 use crate::pubsub::{PubSubManager, format_message, format_pmessage, 
                     format_subscribe_response, format_psubscribe_response,
                     format_unsubscribe_response, format_punsubscribe_response};
 use crate::replication::{ReplicationManager, ReplicationConfig};
+use crate::cluster::ClusterState;
 use super::{Listener, Connection, ConnectionState, NetworkConfig};
 use super::monitoring::PerformanceMonitoring;
 use super::blocking::{BlockingManager, WakeupRequest};
+use super::worker_pool::{WorkerPool, OFFLOAD_THRESHOLD_BYTES};
+use super::io_threads::IoThreads;
+use super::tracking::{self, TrackingTable};
+use super::pause::PauseGate;
+use super::acl::{self, Acl};
+use super::metrics::{CommandStats, InstantaneousMetric};
 use super::connection::{BlockedState, BlockingOp};
 use crate::Config as FerrousConfig;
 
 
 
+/// How long SHUTDOWN waits for replicas to take the writes still buffered for them
+const SHUTDOWN_REPLICA_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Connection ID generator
 static CONN_ID_COUNTER: AtomicU64 = AtomicU64::new(1);
 
 /// Number of shards for connection storage
 const CONNECTION_SHARDS: usize = 16;
 
+/// Replica output past which propagation flushes before sending more effects
+const REPLICA_OUTPUT_FLUSH_BYTES: usize = 1024 * 1024;
+
+/// Replica output past which a replica is dropped (Redis' default hard limit)
+const REPLICA_OUTPUT_LIMIT_BYTES: usize = 256 * 1024 * 1024;
+
 /// Sharded connection storage for better concurrency
 struct ShardedConnections {
     shards: Vec<Arc<Mutex<HashMap<u64, Connection>>>>,
@@ -128,6 +148,359 @@ impl crate::storage::commands::client::ConnectionProvider for ShardedConnections
     }
 }
 
+/// Serves other clients' waiting read-only commands at script checkpoints
+///
+/// Runs inside the running script, on the event loop thread, while the
+/// connection being served is locked; see [`lua_checkpoint`].
+struct CheckpointServicer {
+    connections: Arc<ShardedConnections>,
+    storage: Arc<StorageEngine>,
+    pubsub: Arc<PubSubManager>,
+    stats: Arc<ServerStats>,
+    pause_gate: Arc<Mutex<PauseGate>>,
+}
+
+impl CheckpointServicer {
+    /// Command arguments of a frame that may run at a checkpoint
+    fn serviceable_args(frame: &RespFrame) -> Option<Vec<Vec<u8>>> {
+        let RespFrame::Array(Some(parts)) = frame else { return None };
+        let args: Vec<Vec<u8>> = parts.iter()
+            .map(|part| match part {
+                RespFrame::BulkString(Some(bytes)) => Some(bytes.to_vec()),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        let name = String::from_utf8_lossy(args.first()?).to_uppercase();
+        lua_checkpoint::is_serviceable(&name).then_some(args)
+    }
+}
+
+impl lua_checkpoint::CheckpointService for CheckpointServicer {
+    fn service(&self, current: Option<u64>) -> usize {
+        if self.pause_gate.lock().unwrap().is_active() {
+            return 0;
+        }
+        
+        let adapter = LuaCommandAdapter::new(self.storage.clone());
+        let mut served = 0;
+        for id in self.connections.all_connection_ids() {
+            if Some(id) == current || self.pubsub.is_subscribed(id) {
+                continue;
+            }
+            self.connections.with_connection(id, |conn| {
+                // Only idle, authenticated clients with nothing already queued
+                if conn.state != ConnectionState::Authenticated || conn.is_monitoring
+                    || conn.transaction_state.in_transaction || !conn.deferred_frames.is_empty() {
+                    return;
+                }
+                if !matches!(conn.read(), Ok(true)) {
+                    return;
+                }
+                
+                // Everything parsed is queued; served commands are taken off the front
+                while let Ok(Some(frame)) = conn.parse_frame() {
+                    conn.deferred_frames.push_back(frame);
+                }
+                while let Some(args) = conn.deferred_frames.front().and_then(Self::serviceable_args) {
+                    conn.deferred_frames.pop_front();
+                    let reply = match adapter.execute(args, conn.db_index) {
+                        Ok(reply) => RespFrame::from(reply),
+                        Err(e) => RespFrame::error(e.to_string()),
+                    };
+                    if let Err(e) = conn.send_frame(&reply) {
+                        eprintln!("Send error for connection {} at script checkpoint: {}", id, e);
+                    }
+                    served += 1;
+                }
+                let _ = conn.flush();
+            });
+        }
+        
+        self.stats.total_commands_processed.fetch_add(served as u64, Ordering::Relaxed);
+        served
+    }
+}
+
+/// AUTH and HELLO, which clients send before they are authenticated and may
+/// send while a script is busy, so the [`BusyServicer`] runs them too
+struct Handshake {
+    password: Option<String>,
+    acl: Arc<Acl>,
+    stats: Arc<ServerStats>,
+    cluster: Arc<ClusterState>,
+    replication: Arc<ReplicationManager>,
+}
+
+impl Handshake {
+    /// AUTH [username] password
+    fn auth(&self, parts: &[RespFrame], conn: &mut Connection) -> RespFrame {
+        if parts.len() == 3 {
+            return match Server::bulk_args(parts) {
+                Some(args) => self.auth_user(&String::from_utf8_lossy(args[1]), args[2], conn),
+                None => RespFrame::error("ERR invalid password format"),
+            };
+        }
+        if parts.len() != 2 {
+            return RespFrame::error("ERR wrong number of arguments for 'auth' command");
+        }
+        
+        // Extract password
+        let provided_password = match &parts[1] {
+            RespFrame::BulkString(Some(bytes)) => {
+                match String::from_utf8(bytes.to_vec()) {
+                    Ok(s) => s,
+                    Err(_) => return RespFrame::error("ERR invalid password format"),
+                }
+            }
+            _ => return RespFrame::error("ERR invalid password format"),
+        };
+        
+        // Check if server requires authentication
+        match &self.password {
+            Some(server_password) => {
+                if provided_password == *server_password {
+                    self.stats.auth_successes.fetch_add(1, Ordering::Relaxed);
+                    conn.state = ConnectionState::Authenticated;
+                    conn.user = acl::DEFAULT_USER.to_string();
+                    RespFrame::ok()
+                } else {
+                    self.stats.auth_failures.fetch_add(1, Ordering::Relaxed);
+                    RespFrame::error("ERR invalid password")
+                }
+            }
+            None => {
+                // No password set on server
+                RespFrame::error("ERR Client sent AUTH, but no password is set")
+            }
+        }
+    }
+    
+    /// Authenticate the connection as the ACL user `username`
+    fn auth_user(&self, username: &str, password: &[u8], conn: &mut Connection) -> RespFrame {
+        if !self.acl.authenticate(username, password) {
+            self.stats.auth_failures.fetch_add(1, Ordering::Relaxed);
+            return RespFrame::error("WRONGPASS invalid username-password pair or user is disabled.");
+        }
+        self.stats.auth_successes.fetch_add(1, Ordering::Relaxed);
+        conn.state = ConnectionState::Authenticated;
+        conn.user = username.to_string();
+        RespFrame::ok()
+    }
+    
+    /// HELLO [protover [AUTH username password] [SETNAME clientname]]
+    ///
+    /// Switches the connection between RESP2 and RESP3 and replies with the
+    /// server properties, as a map under RESP3.
+    fn hello(&self, parts: &[RespFrame], conn: &mut Connection) -> RespFrame {
+        let args = match Server::bulk_args(parts) {
+            Some(args) => args,
+            None => return RespFrame::error("ERR syntax error"),
+        };
+        
+        let protocol = match args.get(1) {
+            None => None,
+            Some(version) => match std::str::from_utf8(version).ok().and_then(|v| v.parse::<i64>().ok()) {
+                Some(version @ 2..=3) => Some(version as u8),
+                Some(_) => return RespFrame::error("NOPROTO unsupported protocol version"),
+                None => return RespFrame::error("ERR Protocol version is not an integer or out of range"),
+            },
+        };
+        
+        let mut auth = None;
+        let mut name = None;
+        let mut i = 2;
+        while i < args.len() {
+            if args[i].eq_ignore_ascii_case(b"AUTH") && i + 2 < args.len() {
+                auth = Some((args[i + 1], args[i + 2]));
+                i += 3;
+            } else if args[i].eq_ignore_ascii_case(b"SETNAME") && i + 1 < args.len() {
+                name = Some(String::from_utf8_lossy(args[i + 1]).to_string());
+                i += 2;
+            } else {
+                return RespFrame::error(format!(
+                    "ERR Syntax error in HELLO option '{}'", String::from_utf8_lossy(args[i])
+                ));
+            }
+        }
+        
+        if let Some((username, password)) = auth {
+            let reply = self.auth_user(&String::from_utf8_lossy(username), password, conn);
+            if reply.is_error() {
+                return reply;
+            }
+        }
+        
+        if self.password.is_some() && conn.state != ConnectionState::Authenticated {
+            return RespFrame::error(
+                "NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time"
+            );
+        }
+        
+        if let Some(protocol) = protocol {
+            conn.protocol = protocol;
+        }
+        if let Some(name) = name {
+            conn.name = Some(name);
+        }
+        
+        let mode = if self.cluster.is_enabled() { "cluster" } else { "standalone" };
+        let role = if self.replication.is_replica() { "replica" } else { "master" };
+        let fields = vec![
+            (RespFrame::bulk_string("server"), RespFrame::bulk_string("redis")),
+            (RespFrame::bulk_string("version"), RespFrame::bulk_string(crate::storage::commands::monitor::REDIS_VERSION)),
+            (RespFrame::bulk_string("proto"), RespFrame::Integer(conn.protocol as i64)),
+            (RespFrame::bulk_string("id"), RespFrame::Integer(conn.id as i64)),
+            (RespFrame::bulk_string("mode"), RespFrame::bulk_string(mode)),
+            (RespFrame::bulk_string("role"), RespFrame::bulk_string(role)),
+            (RespFrame::bulk_string("modules"), RespFrame::array(Vec::new())),
+        ];
+        
+        if conn.protocol >= 3 {
+            RespFrame::Map(fields)
+        } else {
+            RespFrame::array(fields.into_iter().flat_map(|(k, v)| [k, v]).collect())
+        }
+    }
+}
+
+/// Accept one pending client, returning whether there was one
+///
+/// Runs on the event loop, and inside a busy script so clients that connect
+/// meanwhile can still send SCRIPT KILL.
+fn accept_connection(listener: &Listener, connections: &ShardedConnections, stats: &ServerStats, config: &NetworkConfig) -> Result<bool> {
+    if let Some((stream, addr)) = listener.accept()? {
+        let id = CONN_ID_COUNTER.fetch_add(1, Ordering::Relaxed);
+        
+        // Update statistics
+        stats.total_connections_received.fetch_add(1, Ordering::Relaxed);
+        
+        // Check max clients limit
+        if connections.total_connections() >= config.max_clients {
+            println!("Max clients reached, rejecting connection from {}", addr);
+            stats.rejected_connections.fetch_add(1, Ordering::Relaxed);
+            drop(stream); // Close connection
+            return Ok(true);
+        }
+        
+        // Create new connection
+        match Connection::new(id, stream, addr) {
+            Ok(mut conn) => {
+                // Set initial state based on auth requirement
+                if config.password.is_some() {
+                    conn.state = ConnectionState::Connected; // Requires auth
+                } else {
+                    conn.state = ConnectionState::Authenticated; // No auth required
+                }
+                conn.set_deferred_serialization(config.io_threads > 1);
+                
+                println!("Client {} connected from {}", id, addr);
+                connections.insert(id, conn);
+            }
+            Err(e) => {
+                eprintln!("Failed to create connection: {}", e);
+            }
+        }
+        
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Answers other clients with BUSY while a script runs past `lua-time-limit`
+///
+/// Runs inside the busy script, on the event loop thread, like the
+/// [`CheckpointServicer`]; see [`lua_busy`].
+struct BusyServicer {
+    listener: Arc<Listener>,
+    config: NetworkConfig,
+    connections: Arc<ShardedConnections>,
+    stats: Arc<ServerStats>,
+    handshake: Arc<Handshake>,
+    storage: Arc<StorageEngine>,
+    pubsub: Arc<PubSubManager>,
+    aof_engine: Option<Arc<AofEngine>>,
+}
+
+impl BusyServicer {
+    /// Reply to a command received while a script is busy
+    ///
+    /// AUTH and HELLO run as usual, and clients still unauthenticated get
+    /// NOAUTH for anything else, as they would outside a script.
+    fn reply(&self, frame: &RespFrame, conn: &mut Connection) -> RespFrame {
+        let RespFrame::Array(Some(parts)) = frame else { return RespFrame::error(ScriptError::Timeout.to_string()) };
+        let arg = |i: usize| match parts.get(i) {
+            Some(RespFrame::BulkString(Some(bytes))) => Some(String::from_utf8_lossy(bytes).to_uppercase()),
+            _ => None,
+        };
+        let name = arg(0).unwrap_or_default();
+        match name.as_str() {
+            "AUTH" => return self.handshake.auth(parts, conn),
+            "HELLO" => return self.handshake.hello(parts, conn),
+            _ if conn.state != ConnectionState::Authenticated => return RespFrame::error("NOAUTH Authentication required"),
+            _ => {}
+        }
+        
+        let subcommand = arg(1);
+        if !lua_busy::is_allowed(&name, subcommand.as_deref()) {
+            return RespFrame::error(ScriptError::Timeout.to_string());
+        }
+        
+        if name == "SHUTDOWN" {
+            // Only SHUTDOWN NOSAVE gets here; the busy script can't be unwound, so exit from inside it
+            match crate::network::admin_commands::flush_persistence(&self.storage, None, self.aof_engine.as_ref(), false) {
+                Ok(()) => {
+                    println!("SHUTDOWN: Ferrous is now ready to exit, bye bye...");
+                    std::process::exit(0);
+                }
+                Err(e) => {
+                    eprintln!("SHUTDOWN failed: {}", e);
+                    RespFrame::error("ERR Errors trying to SHUTDOWN. Check logs.")
+                }
+            }
+        } else {
+            crate::storage::commands::lua::handle_script_kill(parts)
+        }
+    }
+}
+
+impl lua_busy::BusyService for BusyServicer {
+    fn service(&self, current: Option<u64>) -> usize {
+        // Clients connecting meanwhile must be able to send SCRIPT KILL too
+        while matches!(accept_connection(&self.listener, &self.connections, &self.stats, &self.config), Ok(true)) {}
+        
+        let mut answered = 0;
+        for id in self.connections.all_connection_ids() {
+            if Some(id) == current || self.pubsub.is_subscribed(id) {
+                continue;
+            }
+            self.connections.with_connection(id, |conn| {
+                // Queued transactions and parked clients wait for the script to finish
+                if !matches!(conn.state, ConnectionState::Authenticated | ConnectionState::Connected)
+                    || conn.is_monitoring || conn.transaction_state.in_transaction {
+                    return;
+                }
+                if !matches!(conn.read(), Ok(true)) && conn.deferred_frames.is_empty() {
+                    return;
+                }
+                
+                while let Ok(Some(frame)) = conn.parse_frame() {
+                    conn.deferred_frames.push_back(frame);
+                }
+                while let Some(frame) = conn.deferred_frames.pop_front() {
+                    let reply = self.reply(&frame, conn);
+                    if let Err(e) = conn.send_frame(&reply) {
+                        eprintln!("Send error for connection {} during busy script: {}", id, e);
+                    }
+                    answered += 1;
+                }
+                let _ = conn.flush();
+            });
+        }
+        answered
+    }
+}
+
 /// Server statistics for monitoring
 pub struct ServerStats {
     /// Total number of connections received
@@ -148,10 +521,22 @@ pub struct ServerStats {
     pub auth_failures: AtomicU64,
     /// Number of pending writes
     pub pending_writes: AtomicU64,
+    /// Total number of write commands scripts propagated
+    pub script_effects: AtomicU64,
+    /// Most write commands a single script run propagated
+    pub script_effects_peak: AtomicU64,
+    /// Connections refused because of maxclients
+    pub rejected_connections: AtomicU64,
+    /// Commands that replied with an error
+    pub total_error_replies: AtomicU64,
+    /// Calls, time and latency of each command, while monitoring is enabled
+    pub commands: CommandStats,
+    /// Recent commands per second, network input and output bytes per second
+    rates: Mutex<[InstantaneousMetric; 3]>,
 }
 
 impl ServerStats {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             total_connections_received: AtomicU64::new(0),
             total_commands_processed: AtomicU64::new(0),
@@ -162,13 +547,55 @@ impl ServerStats {
             auth_successes: AtomicU64::new(0),
             auth_failures: AtomicU64::new(0),
             pending_writes: AtomicU64::new(0),
+            script_effects: AtomicU64::new(0),
+            script_effects_peak: AtomicU64::new(0),
+            rejected_connections: AtomicU64::new(0),
+            total_error_replies: AtomicU64::new(0),
+            commands: CommandStats::new(),
+            rates: Mutex::new(Default::default()),
+        }
+    }
+    
+    /// Sample the instantaneous rates, called from the event loop
+    fn sample_rates(&self) {
+        let (input, output) = super::connection::net_io_bytes();
+        let totals = [self.total_commands_processed.load(Ordering::Relaxed), input, output];
+        let now = Instant::now();
+        let mut rates = self.rates.lock().unwrap();
+        for (metric, total) in rates.iter_mut().zip(totals) {
+            metric.sample(total, now);
+        }
+    }
+    
+    /// Commands per second, input and output bytes per second, over the last samples
+    pub fn instantaneous_rates(&self) -> (f64, f64, f64) {
+        let rates = self.rates.lock().unwrap();
+        (rates[0].per_second(), rates[1].per_second(), rates[2].per_second())
+    }
+    
+    /// Reset the counters CONFIG RESETSTAT clears
+    pub fn reset(&self) {
+        for counter in [
+            &self.total_connections_received,
+            &self.total_commands_processed,
+            &self.keyspace_hits,
+            &self.keyspace_misses,
+            &self.auth_successes,
+            &self.auth_failures,
+            &self.script_effects,
+            &self.script_effects_peak,
+            &self.rejected_connections,
+            &self.total_error_replies,
+        ] {
+            counter.store(0, Ordering::Relaxed);
         }
+        self.commands.reset();
     }
 }
 
 /// Main server struct
 pub struct Server {
-    listener: Listener,
+    listener: Arc<Listener>,
     connections: Arc<ShardedConnections>,
     config: NetworkConfig,
     /// Storage engine for Redis data
@@ -189,18 +616,32 @@ pub struct Server {
     start_time: SystemTime,
     /// Replication manager
     replication: Arc<ReplicationManager>,
+    /// Cluster slot map (disabled outside cluster mode)
+    cluster: Arc<ClusterState>,
     /// Slowlog system
     slowlog: Arc<Slowlog>,
     /// Monitor subscribers
     monitor_subscribers: Arc<MonitorSubscribers>,
-    /// Clients paused until this time
-    clients_paused_until: Arc<Mutex<SystemTime>>,
+    /// CLIENT PAUSE state
+    pause_gate: Arc<Mutex<PauseGate>>,
     /// Zero-overhead performance monitoring backend
     monitoring: Arc<dyn PerformanceMonitoring>,
     /// Global Lua script cache
     script_cache: Arc<dyn ScriptCaching>,
     /// Blocking operations manager
     blocking_manager: Arc<BlockingManager>,
+    /// Worker pool for CPU-heavy replies
+    worker_pool: WorkerPool,
+    /// I/O threads reading requests and writing replies (io-threads > 1)
+    io_threads: Option<IoThreads>,
+    /// Keys and prefixes followed by CLIENT TRACKING clients
+    tracking: TrackingTable,
+    /// AUTH and HELLO, shared with the busy script servicer
+    handshake: Arc<Handshake>,
+    /// SHUTDOWN succeeded: the event loop stops after this iteration
+    shutting_down: bool,
+    /// Configuration as changed by CONFIG SET, for CONFIG GET and REWRITE
+    live_config: FerrousConfig,
 }
 
 impl Server {
@@ -229,9 +670,13 @@ impl Server {
     
     /// Create a new server from a complete configuration
     pub fn from_config(config: FerrousConfig) -> Result<Self> {
-        let listener = Listener::bind(config.network.clone())?;
+        let listener = Arc::new(Listener::bind(config.network.clone())?);
         let connections = Arc::new(ShardedConnections::new());
-        let storage = StorageEngine::new();
+        let storage = StorageEngine::with_config(16, MemoryManager::new(config.memory.max_memory, config.memory.max_memory_policy));
+        storage.set_eviction_samples(config.memory.max_memory_samples);
+        storage.set_lfu_log_factor(config.memory.lfu_log_factor);
+        storage.set_lfu_decay_time(config.memory.lfu_decay_time);
+        storage.set_encoding_limits(config.memory.encoding_limits);
         
         // Resolve RDB file path
         let mut rdb_path = PathBuf::from(&config.rdb.dir);
@@ -251,6 +696,18 @@ impl Server {
             None
         };
         
+        if let Err(e) = crate::logging::init(config.server.log_level, &config.server.log_file) {
+            eprintln!("Failed to open log file {}: {}", config.server.log_file, e);
+        }
+        
+        // Apply scripting limits to every registered script engine
+        for engine in crate::storage::script_engine::engines(&storage)? {
+            engine.configure(&config.scripting);
+        }
+        if let Err(e) = crate::storage::lua_audit::configure(config.scripting.audit_log.clone(), config.scripting.audit_log_max_size) {
+            eprintln!("Failed to open script audit log: {}", e);
+        }
+        
         // Create storage monitor
         let mut storage_monitor = StorageMonitor::new();
         
@@ -260,8 +717,21 @@ impl Server {
         // Create server stats
         let stats = Arc::new(ServerStats::new());
         
-        // Create replication manager
-        let replication = ReplicationManager::new(config.replication.clone());
+        // Create replication manager, reporting the port clients reach us on
+        let replication = ReplicationManager::new(ReplicationConfig {
+            listening_port: config.network.port,
+            ..config.replication.clone()
+        });
+        
+        // Create the cluster slot map, announcing the address clients reach us on
+        let announce_host = match config.network.bind_addr.split_whitespace().next() {
+            Some("0.0.0.0") | Some("*") | None => "127.0.0.1",
+            Some(host) => host,
+        };
+        let cluster = Arc::new(ClusterState::from_config(&config.cluster, announce_host, config.network.port));
+        if cluster.is_enabled() {
+            println!("Cluster mode enabled, node ID {}", cluster.myself().id);
+        }
         
         // Create slowlog
         let slowlog = Arc::new(Slowlog::new());
@@ -269,8 +739,8 @@ impl Server {
         // Create monitor subscribers
         let monitor_subscribers = Arc::new(MonitorSubscribers::new());
         
-        // Initialize client pause to UNIX_EPOCH (not paused)
-        let clients_paused_until = Arc::new(Mutex::new(UNIX_EPOCH));
+        // Clients start unpaused
+        let pause_gate = Arc::new(Mutex::new(PauseGate::new()));
         
         // Create monitoring backend based on configuration
         let monitoring = if config.monitoring.slowlog_enabled || config.monitoring.monitor_enabled || config.monitoring.stats_enabled {
@@ -305,15 +775,45 @@ impl Server {
         slowlog.set_threshold_micros(config.monitoring.slowlog_threshold_micros);
         slowlog.set_max_len(config.monitoring.slowlog_max_len);
         
+        // Let script checkpoints serve other clients, when enabled
+        if config.scripting.checkpoints {
+            lua_checkpoint::set_service(Arc::new(CheckpointServicer {
+                connections: Arc::clone(&connections),
+                storage: Arc::clone(&storage),
+                pubsub: Arc::clone(&pubsub),
+                stats: Arc::clone(&stats),
+                pause_gate: Arc::clone(&pause_gate),
+            }));
+        }
+        
+        // AUTH and HELLO, run by the event loop and while a script is busy
+        let handshake = Arc::new(Handshake {
+            password: config.network.password.clone(),
+            acl: Arc::new(Acl::new(config.network.password.as_deref())),
+            stats: Arc::clone(&stats),
+            cluster: Arc::clone(&cluster),
+            replication: Arc::clone(&replication),
+        });
+        
+        // Answer other clients with BUSY while a script overruns lua-time-limit
+        lua_busy::set_service(Arc::new(BusyServicer {
+            listener: Arc::clone(&listener),
+            config: config.network.clone(),
+            connections: Arc::clone(&connections),
+            stats: Arc::clone(&stats),
+            handshake: Arc::clone(&handshake),
+            storage: Arc::clone(&storage),
+            pubsub: Arc::clone(&pubsub),
+            aof_engine: aof_engine.clone(),
+        }));
+        
         // Load existing RDB if available
         if let Err(e) = rdb_engine.load(&storage) {
             eprintln!("Failed to load RDB file: {}", e);
         }
         
-        // Start background monitoring if auto-save is enabled
-        if config.rdb.auto_save {
-            storage_monitor.start(Arc::clone(&storage), Arc::clone(&rdb_engine), config.rdb.clone());
-        }
+        // Start background monitoring, which saves once auto-save has rules
+        storage_monitor.start(Arc::clone(&storage), Arc::clone(&rdb_engine), config.rdb.clone());
         
         // Check if replication is configured
         if let (Some(host), Some(port)) = (config.replication.master_host.as_ref(), config.replication.master_port) {
@@ -334,7 +834,7 @@ impl Server {
         Ok(Server {
             listener,
             connections,
-            config: config.network,
+            config: config.network.clone(),
             storage,
             rdb_engine: Some(rdb_engine),
             storage_monitor: Some(storage_monitor),
@@ -344,12 +844,19 @@ impl Server {
             stats,
             start_time: SystemTime::now(),
             replication,
+            cluster,
             slowlog,
             monitor_subscribers,
-            clients_paused_until,
+            pause_gate,
             monitoring,
             script_cache,
             blocking_manager,
+            worker_pool: WorkerPool::default(),
+            io_threads: (config.network.io_threads > 1).then(|| IoThreads::new(config.network.io_threads)),
+            tracking: TrackingTable::new(),
+            handshake,
+            shutting_down: false,
+            live_config: config,
         })
     }
     
@@ -376,6 +883,9 @@ impl Server {
             // Process wake-up queue first (very fast, lock-free)
             did_work |= self.process_wakeups()?;
             
+            // Deliver replies computed on the worker pool
+            did_work |= self.process_worker_completions()?;
+            
             // Accept new connections with a limit per iteration
             for _ in 0..10 { // Process up to 10 new connections per iteration
                 if self.accept_single_connection()? {
@@ -395,6 +905,14 @@ impl Server {
                 did_work = true;
             }
             
+            // Sample the INFO instantaneous rates (at most every 100ms)
+            self.stats.sample_rates();
+            
+            // Resume clients held by a pause that ended
+            if self.process_paused_clients() {
+                did_work = true;
+            }
+            
             // Process connections with pending writes
             if self.process_pending_writes()? {
                 did_work = true;
@@ -403,6 +921,21 @@ impl Server {
             // Clean up closed connections
             self.cleanup_connections()?;
             
+            // SIGTERM/SIGINT shut down like SHUTDOWN; SIGHUP reopens the rotated log file
+            if let Some(signal) = crate::signals::shutdown_requested() {
+                self.shutdown_on_signal(signal);
+            }
+            if crate::signals::take_log_reopen() {
+                if let Err(e) = crate::logging::reopen() {
+                    eprintln!("Failed to reopen the log file: {}", e);
+                }
+            }
+            
+            // SHUTDOWN made the dataset durable: stop accepting and serving clients
+            if self.shutting_down {
+                return self.finish_shutdown();
+            }
+            
             // Adaptive sleep to balance CPU usage and responsiveness
             if did_work {
                 cycles_without_work = 0;
@@ -421,44 +954,85 @@ impl Server {
         }
     }
     
+    /// Handle SHUTDOWN [NOSAVE|SAVE]
+    ///
+    /// When the dataset can't be made durable the server keeps running and
+    /// the client gets an error; otherwise nothing is replied and the event
+    /// loop returns once this iteration is over.
+    fn handle_shutdown(&mut self, parts: &[RespFrame]) -> Result<RespFrame> {
+        let save = match crate::network::admin_commands::parse_shutdown(parts) {
+            Ok(save) => save,
+            Err(reply) => return Ok(reply),
+        };
+        
+        println!("SHUTDOWN: User requested shutdown...");
+        if let Err(e) = self.prepare_shutdown(save) {
+            eprintln!("SHUTDOWN: Error trying to save the dataset, can't exit: {}", e);
+            return Ok(RespFrame::error("ERR Errors trying to SHUTDOWN. Check logs."));
+        }
+        Ok(RespFrame::NoResponse)
+    }
+    
+    /// Shut down on SIGTERM or SIGINT, saving like a plain SHUTDOWN
+    fn shutdown_on_signal(&mut self, signal: i32) {
+        println!("SHUTDOWN: Received {} scheduling shutdown...", crate::signals::name(signal));
+        if let Err(e) = self.prepare_shutdown(true) {
+            eprintln!("SHUTDOWN: {} received but errors trying to shut down the server: {}",
+                crate::signals::name(signal), e);
+            crate::signals::clear_shutdown_request();
+        }
+    }
+    
+    /// Make everything written so far durable before the event loop stops
+    ///
+    /// Replicas first get everything propagated to them, then the AOF is
+    /// fsynced and the final snapshot saved when requested.
+    fn prepare_shutdown(&mut self, save: bool) -> Result<()> {
+        let replicas: Vec<u64> = self.connections.all_connection_ids().into_iter()
+            .filter(|&id| self.connections.with_connection(id, |conn| conn.is_replica).unwrap_or(false))
+            .collect();
+        if !replicas.is_empty() {
+            println!("SHUTDOWN: Sending the last writes to {} replica(s)...", replicas.len());
+            self.drain_output(&replicas, SHUTDOWN_REPLICA_TIMEOUT);
+        }
+        
+        crate::network::admin_commands::flush_persistence(
+            &self.storage, self.rdb_engine.as_ref(), self.aof_engine.as_ref(), save
+        )?;
+        
+        self.shutting_down = true;
+        Ok(())
+    }
+    
+    /// Leave the event loop after a successful SHUTDOWN
+    ///
+    /// Replies already computed are written out, briefly, before the
+    /// listening socket and the connections close with the server.
+    fn finish_shutdown(&mut self) -> Result<()> {
+        let ids = self.connections.all_connection_ids();
+        self.drain_output(&ids, Duration::from_millis(100));
+        println!("SHUTDOWN: Ferrous is now ready to exit, bye bye...");
+        Ok(())
+    }
+    
+    /// Write out what connections still have buffered, giving up after `timeout`
+    fn drain_output(&self, ids: &[u64], timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        let mut pending = ids.to_vec();
+        while !pending.is_empty() && Instant::now() < deadline {
+            pending.retain(|&id| self.connections.with_connection(id, |conn| {
+                conn.flush().is_ok() && conn.has_pending_writes()
+            }).unwrap_or(false));
+            if !pending.is_empty() {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+    
     /// Accept a single new connection
     /// Returns true if connection was accepted, false if would block
     fn accept_single_connection(&mut self) -> Result<bool> {
-        if let Some((stream, addr)) = self.listener.accept()? {
-            let id = CONN_ID_COUNTER.fetch_add(1, Ordering::Relaxed);
-            
-            // Update statistics
-            self.stats.total_connections_received.fetch_add(1, Ordering::Relaxed);
-            
-            // Check max clients limit
-            if self.connections.total_connections() >= self.config.max_clients {
-                println!("Max clients reached, rejecting connection from {}", addr);
-                drop(stream); // Close connection
-                return Ok(true);
-            }
-            
-            // Create new connection
-            match Connection::new(id, stream, addr) {
-                Ok(mut conn) => {
-                    // Set initial state based on auth requirement
-                    if self.config.password.is_some() {
-                        conn.state = ConnectionState::Connected; // Requires auth
-                    } else {
-                        conn.state = ConnectionState::Authenticated; // No auth required
-                    }
-                    
-                    println!("Client {} connected from {}", id, addr);
-                    self.connections.insert(id, conn);
-                }
-                Err(e) => {
-                    eprintln!("Failed to create connection: {}", e);
-                }
-            }
-            
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+        accept_connection(&self.listener, &self.connections, &self.stats, &self.config)
     }
     
     /// Process wake-up requests for blocked clients
@@ -476,49 +1050,265 @@ impl Server {
     }
     
     /// Wake up a specific blocked client with data
+    ///
+    /// Only a client still blocked is served, so a wake-up for one already
+    /// served through another key or timed out takes nothing from the list. A
+    /// client that finds nothing to take (the key was emptied again, deleted or
+    /// replaced by another type) waits again on all its keys.
     fn wake_client(&self, wakeup: WakeupRequest) -> Result<()> {
-        // Perform atomic pop based on the operation type
-        let value = match wakeup.op_type {
-            super::connection::BlockingOp::BLPop => self.storage.lpop(wakeup.db, &wakeup.key)?,
-            super::connection::BlockingOp::BRPop => self.storage.rpop(wakeup.db, &wakeup.key)?,
-            super::connection::BlockingOp::XReadBlock(_) => {
-                // XReadBlock not implemented yet, skip for now
-                return Ok(());
-            }
-        };
-        
-        // Critical fix: Only proceed if we actually got data
-        // This prevents race conditions when multiple clients wake up simultaneously
-        if let Some(popped_value) = value {
-            // Try to update connection state - use try_with_connection to avoid deadlock
-            if let Some(result) = self.connections.with_connection(wakeup.conn_id, |conn| -> Result<()> {
-                // Only wake if still in blocked state
-                if let ConnectionState::Blocked(_) = conn.state {
-                    // Send the response with the atomically popped value
-                    let response = RespFrame::Array(Some(vec![
-                        RespFrame::from_bytes(wakeup.key.clone()),
-                        RespFrame::from_bytes(popped_value),
-                    ]));
-                    
-                    // Try to send response - if connection is closed, ignore error
-                    if let Err(_) = conn.send_frame(&response) {
-                        // Connection closed - this is okay, just return
-                        return Ok(());
-                    }
-                    
-                    // Return connection to authenticated state
-                    conn.state = ConnectionState::Authenticated;
+        let blocked = self.connections.with_connection(wakeup.conn_id, |conn| match &conn.state {
+            ConnectionState::Blocked(state) => Some(state.clone()),
+            _ => None,
+        }).flatten();
+        let Some(state) = blocked else {
+            return Ok(());
+        };
+        
+        let response = match self.serve_blocked(wakeup.db, &wakeup.key, &wakeup.op_type) {
+            Ok(Some(response)) => response,
+            Ok(None) | Err(FerrousError::Storage(StorageError::WrongType)) => {
+                let keys = state.keys.into_iter().map(|(_, key)| key).collect();
+                self.blocking_manager.unregister_client(wakeup.db, wakeup.conn_id)?;
+                return self.blocking_manager.register_blocked(wakeup.db, wakeup.conn_id, keys, state.op_type, state.deadline);
+            }
+            Err(e) => Self::list_error_reply(e),
+        };
+        
+        self.blocking_manager.unregister_client(wakeup.db, wakeup.conn_id)?;
+        self.connections.with_connection(wakeup.conn_id, |conn| {
+            // A closed connection is cleaned up by the normal connection pass
+            let _ = conn.send_frame(&response);
+            conn.state = ConnectionState::Authenticated;
+        });
+        Ok(())
+    }
+    
+    /// Serve a blocking command from `key` if it holds data
+    ///
+    /// The effect of a list command is logged and replicated as the
+    /// non-blocking command that reproduces it. A stream read re-runs its
+    /// non-blocking form, reading every stream it waits on.
+    fn serve_blocked(&self, db: usize, key: &[u8], op: &BlockingOp) -> Result<Option<RespFrame>> {
+        let (response, command) = match op {
+            BlockingOp::BLPop | BlockingOp::BRPop => {
+                let (end, name) = match op {
+                    BlockingOp::BLPop => (ListEnd::Left, "LPOP"),
+                    _ => (ListEnd::Right, "RPOP"),
+                };
+                let Some(value) = self.storage.list_pop_count(db, key, end, 1)?.pop() else {
+                    return Ok(None);
+                };
+                let response = RespFrame::Array(Some(vec![
+                    RespFrame::from_bytes(key.to_vec()),
+                    RespFrame::from_bytes(value),
+                ]));
+                (response, vec![name.into(), key.to_vec()])
+            }
+            BlockingOp::BLMove { destination, from, to } => {
+                let Some(value) = self.storage.lmove(db, key, destination.clone(), *from, *to)? else {
+                    return Ok(None);
+                };
+                let command = vec![
+                    b"LMOVE".to_vec(), key.to_vec(), destination.clone(),
+                    from.as_str().into(), to.as_str().into(),
+                ];
+                (RespFrame::from_bytes(value), command)
+            }
+            BlockingOp::BLMPop { end, count } => {
+                let elements = self.storage.list_pop_count(db, key, *end, *count)?;
+                if elements.is_empty() {
+                    return Ok(None);
+                }
+                let command = vec![
+                    b"LMPOP".to_vec(), b"1".to_vec(), key.to_vec(), end.as_str().into(),
+                    b"COUNT".to_vec(), elements.len().to_string().into_bytes(),
+                ];
+                (lists::lmpop_reply(Some((key.to_vec(), elements))), command)
+            }
+            BlockingOp::XReadBlock(command) => {
+                let parts: Vec<RespFrame> = command.iter().cloned().map(RespFrame::from_bytes).collect();
+                let response = self.read_streams(db, &parts)?;
+                return Ok((!streams::is_empty_read(&response)).then_some(response));
+            }
+        };
+        
+        self.propagate_served(db, command);
+        Ok(Some(response))
+    }
+    
+    /// Log, replicate and notify a write made by a blocking command
+    fn propagate_served(&self, db: usize, command: Vec<Vec<u8>>) {
+        let parts: Vec<RespFrame> = command.into_iter().map(RespFrame::from_bytes).collect();
+        if let Some(aof) = &self.aof_engine {
+            if let Err(e) = aof.append_command(db, &parts) {
+                eprintln!("Failed to append to AOF: {}", e);
+            }
+        }
+        let command = [RespFrame::Array(Some(parts))];
+        self.notify_written_keys(db, &command);
+        self.invalidate_tracked_keys(&command, 0);
+        self.propagate_to_replicas(&command);
+    }
+    
+    /// Evict keys while memory is over maxmemory, logging and replicating each
+    /// eviction as a DEL
+    ///
+    /// Returns false if memory is still over the limit.
+    fn evict_keys(&self) -> bool {
+        let eviction = self.storage.evict();
+        for (db, key) in eviction.keys {
+            self.propagate_served(db, vec![b"DEL".to_vec(), key]);
+        }
+        eviction.within_limit
+    }
+    
+    /// Wake the clients blocked on keys written by `commands`
+    ///
+    /// A woken client that finds nothing to take waits again, so any write to a
+    /// watched key notifies it: pushes, moves, XADD and script effects alike.
+    /// A served list pop writes its key in turn, waking the next waiter while
+    /// elements remain.
+    fn notify_written_keys(&self, db: usize, commands: &[RespFrame]) {
+        if !self.blocking_manager.has_blocked_clients_in(db) {
+            return;
+        }
+        
+        for command in commands {
+            let RespFrame::Array(Some(parts)) = command else {
+                continue;
+            };
+            let Some(args) = Self::bulk_args(parts) else {
+                continue;
+            };
+            for key in crate::storage::lru::command_keys(&args) {
+                if self.blocking_manager.has_blocked_clients(db, key) {
+                    self.blocking_manager.notify_key_ready(db, key);
+                }
+            }
+        }
+    }
+    
+    /// Send invalidation messages to the tracking clients of keys written by `commands`
+    ///
+    /// `writer` is the connection that issued the writes (0 when unknown), which
+    /// NOLOOP clients don't hear about. A flush invalidates every key at once.
+    fn invalidate_tracked_keys(&self, commands: &[RespFrame], writer: u64) {
+        if !self.tracking.is_active() {
+            return;
+        }
+        
+        for command in commands {
+            let RespFrame::Array(Some(parts)) = command else {
+                continue;
+            };
+            let Some(args) = Self::bulk_args(parts) else {
+                continue;
+            };
+            let Some(name) = args.first() else {
+                continue;
+            };
+            
+            if name.eq_ignore_ascii_case(b"FLUSHDB") || name.eq_ignore_ascii_case(b"FLUSHALL") {
+                for client in self.tracking.flush() {
+                    self.send_invalidation(client, writer, None);
+                }
+            } else {
+                let keys = crate::cluster::state::routing_keys(&args);
+                for (client, keys) in self.tracking.invalidate(&keys) {
+                    self.send_invalidation(client, writer, Some(keys));
                 }
+            }
+        }
+    }
+    
+    /// Deliver one invalidation to a tracking client, or to its redirect target
+    ///
+    /// RESP3 targets get a push. RESP2 targets only get a pub/sub message while
+    /// subscribed to the invalidation channel, as anything else would end up
+    /// among their replies.
+    fn send_invalidation(&self, client: u64, writer: u64, keys: Option<Vec<Vec<u8>>>) {
+        let target = self.connections.with_connection(client, |conn| match &conn.tracking {
+            Some(options) if !(options.noloop && client == writer) => Some(options.redirect.unwrap_or(client)),
+            _ => None,
+        }).flatten();
+        let Some(target) = target else {
+            return;
+        };
+        
+        let subscribed = self.pubsub.get_subscription_info(target)
+            .is_some_and(|info| info.channels.contains(tracking::INVALIDATE_CHANNEL));
+        
+        // Best effort delivery - ignore errors
+        let _ = self.connections.with_connection(target, |conn| {
+            if conn.protocol >= 3 {
+                conn.send_frame(&tracking::invalidation_frame(keys, true))
+            } else if subscribed {
+                conn.send_frame(&tracking::invalidation_frame(keys, false))
+            } else {
                 Ok(())
-            }) {
-                // Execute the result and ignore any connection errors
-                let _ = result;
             }
+        });
+    }
+    
+    /// Remember the keys a tracking client just read
+    ///
+    /// Also consumes the CLIENT CACHING answer, which only covers the command
+    /// right after it.
+    fn track_read_keys(&self, parts: &[RespFrame], command_name: &str, read: bool, conn_id: u64) {
+        let caching_command = command_name == "CLIENT" && matches!(
+            parts.get(1), Some(RespFrame::BulkString(Some(sub))) if sub.eq_ignore_ascii_case(b"CACHING")
+        );
+        
+        let track = self.connections.with_connection(conn_id, |conn| {
+            let Some(options) = &mut conn.tracking else {
+                return false;
+            };
+            let track = options.tracks_reads();
+            if !caching_command {
+                options.caching = None;
+            }
+            track
+        }).unwrap_or(false);
+        
+        if track && read {
+            if let Some(args) = Self::bulk_args(parts) {
+                self.tracking.remember(conn_id, &crate::cluster::state::routing_keys(&args));
+            }
+        }
+    }
+    
+    /// Reply for a failed list operation of a blocked client
+    fn list_error_reply(e: FerrousError) -> RespFrame {
+        match e {
+            FerrousError::Storage(StorageError::WrongType) => {
+                RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value")
+            }
+            e => RespFrame::error(e.to_string()),
+        }
+    }
+    
+    /// Send finished worker pool replies and resume the parked connections
+    fn process_worker_completions(&self) -> Result<bool> {
+        let completions = self.worker_pool.drain_completions();
+        if completions.is_empty() {
+            return Ok(false);
         }
-        // If value is None (list was empty), the client should be timed out normally
-        // This is correct behavior - multiple wake-ups for same item result in only one getting data
         
-        Ok(())
+        for completion in completions {
+            self.connections.with_connection(completion.conn_id, |conn| {
+                if conn.state != ConnectionState::AwaitingWorker {
+                    return;
+                }
+                
+                // A closed connection is cleaned up by the normal connection pass
+                if conn.send_frame(&completion.response).is_ok() {
+                    let _ = conn.flush();
+                }
+                conn.state = ConnectionState::Authenticated;
+            });
+        }
+        
+        Ok(true)
     }
     
     /// Process timeouts for blocked clients
@@ -544,10 +1334,28 @@ impl Server {
         Ok(true)
     }
     
+    /// Resume the clients held by CLIENT PAUSE once it ended
+    ///
+    /// Their held commands are first in line on the next connection pass.
+    fn process_paused_clients(&self) -> bool {
+        if !self.pause_gate.lock().unwrap().release() {
+            return false;
+        }
+        
+        for id in self.connections.all_connection_ids() {
+            self.connections.with_connection(id, |conn| {
+                if conn.state == ConnectionState::Paused {
+                    conn.state = ConnectionState::Authenticated;
+                }
+            });
+        }
+        true
+    }
+    
     /// Check if connection is blocked (for skipping in main processing)
     fn is_connection_blocked(&self, conn_id: u64) -> bool {
         self.connections.with_connection(conn_id, |conn| {
-            matches!(conn.state, ConnectionState::Blocked(_))
+            matches!(conn.state, ConnectionState::Blocked(_) | ConnectionState::AwaitingWorker | ConnectionState::Paused)
         }).unwrap_or(false)
     }
     
@@ -570,9 +1378,19 @@ impl Server {
             .filter(|&id| !self.is_connection_blocked(id))
             .collect();
         
+        // With I/O threads, all the input is read and parsed before any command runs
+        let mut read_failures = match self.io_threads {
+            Some(_) => self.read_connections(&conn_ids),
+            None => HashMap::new(),
+        };
+        
         for id in conn_ids {
             // Process each connection
-            match self.process_connection(id) {
+            let result = match read_failures.remove(&id) {
+                Some(e) => Err(e),
+                None => self.process_connection(id),
+            };
+            match result {
                 Ok(has_pending_writes) => {
                     did_work = true;
                     if has_pending_writes {
@@ -603,12 +1421,147 @@ impl Server {
                 if let Err(e) = self.pubsub.unsubscribe_all(id) {
                     eprintln!("Error cleaning up subscriptions for connection {}: {}", id, e);
                 }
+                
+                // Forget the keys it was tracking
+                self.tracking.disable(id);
             }
         }
         
         Ok(did_work)
     }
     
+    /// Flush pending output, then read and parse what the client sent
+    ///
+    /// Runs on the event loop, or on an I/O thread when io-threads is set.
+    fn read_input(conn: &mut Connection, frames: &mut Vec<RespFrame>) -> Result<()> {
+        let id = conn.id;
+        
+        // Try to flush any pending writes first to avoid buffer buildup
+        if conn.has_pending_writes() {
+            match conn.flush() {
+                Ok(_) => {},
+                Err(e) => {
+                    // Improved error handling for pipelining: distinguish error types
+                    match e {
+                        FerrousError::Connection(ref msg) if msg.contains("Broken pipe") 
+                            || msg.contains("Connection reset") => {
+                            // Hard connection error - mark for closing
+                            return Err(e);
+                        },
+                        FerrousError::Connection(_) => {
+                            // Soft connection error - log but don't immediately close
+                            // This improves pipelining tolerance
+                            eprintln!("Flush warning for connection {}: {}", id, e);
+                            return Ok(());
+                        },
+                        _ => return Err(e),
+                    }
+                }
+            }
+        }
+        
+        // Read data from connection
+        match conn.read() {
+            Ok(true) => {
+                // Data was read, try to parse all available frames with improved error handling
+                loop {
+                    match conn.parse_frame() {
+                        Ok(Some(frame)) => frames.push(frame),
+                        Ok(None) => break, // No more complete frames
+                        Err(e) => {
+                            // Improved parsing error handling for pipelining
+                            match e {
+                                FerrousError::Protocol(ref msg) if msg.contains("Need more data") => {
+                                    // Incomplete frame - normal in pipelining, continue
+                                    break;
+                                },
+                                FerrousError::Connection(ref msg) if msg.contains("Broken pipe") 
+                                    || msg.contains("Connection reset") => {
+                                    // Hard connection failure
+                                    conn.close()?;
+                                    return Err(e);
+                                },
+                                _ => {
+                                    // Other parsing errors - log but don't immediately close connection
+                                    // This improves tolerance for pipelining edge cases
+                                    eprintln!("Parse warning for connection {}: {}", id, e);
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(false) => {
+                // No data available (would block)
+            }
+            Err(e) => {
+                // Improved read error handling for pipelining
+                match e {
+                    FerrousError::Connection(ref msg) if msg.contains("would block") 
+                        || msg.contains("Resource temporarily unavailable") => {
+                        // Non-blocking I/O - normal, continue
+                    },
+                    FerrousError::Connection(ref msg) if msg.contains("Broken pipe") 
+                        || msg.contains("Connection reset") => {
+                        // Hard connection failure
+                        conn.close()?;
+                        return Err(e);
+                    },
+                    _ => {
+                        // Other read errors - log but be more tolerant for pipelining
+                        eprintln!("Read warning for connection {}: {}", id, e);
+                    }
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Run `work` on each of the connections `ids`, on the I/O threads if any
+    ///
+    /// Connections are spread over the threads by connection shard, so no two
+    /// threads wait on the same shard lock.
+    fn for_each_connection<F>(&self, ids: Vec<u64>, work: F)
+    where
+        F: Fn(&mut Connection) + Send + Sync + 'static,
+    {
+        let Some(io_threads) = &self.io_threads else {
+            for id in ids {
+                self.connections.with_connection(id, &work);
+            }
+            return;
+        };
+        
+        let threads = io_threads.threads();
+        let connections = Arc::clone(&self.connections);
+        io_threads.run(Arc::new(move |index| {
+            for &id in ids.iter().filter(|&&id| (id as usize % CONNECTION_SHARDS) % threads == index) {
+                connections.with_connection(id, &work);
+            }
+        }));
+    }
+    
+    /// Read and parse the input of `ids` on the I/O threads, queuing the
+    /// frames for the event loop to execute
+    ///
+    /// Returns the connections that failed, to be dropped.
+    fn read_connections(&self, ids: &[u64]) -> HashMap<u64, FerrousError> {
+        let failed = Arc::new(Mutex::new(HashMap::new()));
+        let failures = Arc::clone(&failed);
+        self.for_each_connection(ids.to_vec(), move |conn| {
+            let mut frames = Vec::new();
+            let result = Self::read_input(conn, &mut frames);
+            conn.deferred_frames.extend(frames);
+            if let Err(e) = result {
+                failures.lock().unwrap().insert(conn.id, e);
+            }
+        });
+        let failures = std::mem::take(&mut *failed.lock().unwrap());
+        failures
+    }
+    
     /// Process a single connection
     /// Returns Ok(true) if connection has pending writes
     fn process_connection(&mut self, id: u64) -> Result<bool> {
@@ -616,91 +1569,17 @@ impl Server {
         let mut frames_to_process = Vec::new();
         let mut should_close = false;
         let mut timeout_check = false;
-        let mut conn_closed = false;
+        
+        // I/O threads already read the input and queued the frames
+        let pre_read = self.io_threads.is_some();
         
         // First phase: read and parse with the lock
         let read_result = self.connections.with_connection(id, |conn| -> Result<()> {
-            // Try to flush any pending writes first to avoid buffer buildup
-            if conn.has_pending_writes() {
-                match conn.flush() {
-                    Ok(_) => {},
-                    Err(e) => {
-                        // Improved error handling for pipelining: distinguish error types
-                        match e {
-                            FerrousError::Connection(ref msg) if msg.contains("Broken pipe") 
-                                || msg.contains("Connection reset") => {
-                                // Hard connection error - mark for closing
-                                conn_closed = true;
-                                return Err(e);
-                            },
-                            FerrousError::Connection(_) => {
-                                // Soft connection error - log but don't immediately close
-                                // This improves pipelining tolerance
-                                eprintln!("Flush warning for connection {}: {}", id, e);
-                                return Ok(());
-                            },
-                            _ => return Err(e),
-                        }
-                    }
-                }
-            }
+            // Frames held back while a worker computed an earlier reply go first
+            frames_to_process.extend(conn.deferred_frames.drain(..));
             
-            // Read data from connection
-            match conn.read() {
-                Ok(true) => {
-                    // Data was read, try to parse all available frames with improved error handling
-                    loop {
-                        match conn.parse_frame() {
-                            Ok(Some(frame)) => frames_to_process.push(frame),
-                            Ok(None) => break, // No more complete frames
-                            Err(e) => {
-                                // Improved parsing error handling for pipelining
-                                match e {
-                                    FerrousError::Protocol(ref msg) if msg.contains("Need more data") => {
-                                        // Incomplete frame - normal in pipelining, continue
-                                        break;
-                                    },
-                                    FerrousError::Connection(ref msg) if msg.contains("Broken pipe") 
-                                        || msg.contains("Connection reset") => {
-                                        // Hard connection failure
-                                        conn.close()?;
-                                        return Err(e);
-                                    },
-                                    _ => {
-                                        // Other parsing errors - log but don't immediately close connection
-                                        // This improves tolerance for pipelining edge cases
-                                        eprintln!("Parse warning for connection {}: {}", id, e);
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                Ok(false) => {
-                    // No data available (would block)
-                }
-                Err(e) => {
-                    // Improved read error handling for pipelining
-                    match e {
-                        FerrousError::Connection(ref msg) if msg.contains("would block") 
-                            || msg.contains("Resource temporarily unavailable") => {
-                            // Non-blocking I/O - normal, continue
-                            return Ok(());
-                        },
-                        FerrousError::Connection(ref msg) if msg.contains("Broken pipe") 
-                            || msg.contains("Connection reset") => {
-                            // Hard connection failure
-                            conn.close()?;
-                            return Err(e);
-                        },
-                        _ => {
-                            // Other read errors - log but be more tolerant for pipelining
-                            eprintln!("Read warning for connection {}: {}", id, e);
-                            return Ok(());
-                        }
-                    }
-                }
+            if !pre_read {
+                Self::read_input(conn, &mut frames_to_process)?;
             }
             
             // Check for timeout
@@ -712,57 +1591,27 @@ impl Server {
         });
         
         if let Some(Err(e)) = read_result {
-            if conn_closed {
-                // Connection was closed, don't try to process further
-                return Err(e);
-            }
             return Err(e);
         }
         
-        // Second phase: process frames without the lock
+        // Second phase: process frames without the lock (script checkpoints skip this client)
+        let _client = lua_checkpoint::ClientScope::begin(id);
         let mut responses = Vec::new();
-        let mut needs_immediate_flush = false; // Track if any command needs immediate response
-        for frame in frames_to_process {
+        let mut frames_to_process = frames_to_process.into_iter();
+        while let Some(frame) = frames_to_process.next() {
             // Process each frame and increment command counter
             self.stats.total_commands_processed.fetch_add(1, Ordering::Relaxed);
             
             // Check for special commands that need connection access
             let mut sync_response = None;
             if let RespFrame::Array(Some(parts)) = &frame {
-                if !parts.is_empty() {
-                    if let RespFrame::BulkString(Some(bytes)) = &parts[0] {
-                        let command = String::from_utf8_lossy(bytes).to_uppercase();
-                        
-                        // Track commands that require immediate flush for correct Redis semantics
-                        match command.as_str() {
-                            // Transaction commands need immediate response
-                            "EXEC" | "MULTI" | "DISCARD" | "WATCH" | "UNWATCH" => {
-                                needs_immediate_flush = true;
-                            }
-                            // Connection state commands need immediate response  
-                            "AUTH" | "SELECT" | "QUIT" => {
-                                needs_immediate_flush = true;
-                            }
-                            // Real-time/administrative commands need immediate response
-                            "MONITOR" | "SAVE" | "BGSAVE" | "SHUTDOWN" => {
-                                needs_immediate_flush = true;
-                            }
-                            // Pub/sub commands need immediate response for proper timing coordination
-                            "SUBSCRIBE" | "UNSUBSCRIBE" | "PSUBSCRIBE" | "PUNSUBSCRIBE" => {
-                                needs_immediate_flush = true;
-                            }
-                            _ => {}
-                        }
-                        
-                        // Handle QUIT command
-                        if command == "QUIT" {
-                            should_close = true;
-                        }
-                        
+                if let Some(RespFrame::BulkString(Some(bytes))) = parts.first() {
+                    if bytes.eq_ignore_ascii_case(b"QUIT") {
+                        should_close = true;
+                    } else if bytes.eq_ignore_ascii_case(b"SYNC") || bytes.eq_ignore_ascii_case(b"PSYNC") {
                         // Handle SYNC/PSYNC commands that need connection access
-                        if command == "SYNC" || command == "PSYNC" {
-                            sync_response = Some(self.handle_sync_command(&command, parts, id)?);
-                        }
+                        let command = String::from_utf8_lossy(bytes).to_uppercase();
+                        sync_response = Some(self.handle_sync_command(&command, parts, id)?);
                     }
                 }
             }
@@ -773,56 +1622,39 @@ impl Server {
                 self.process_frame(frame, id)?
             };
             responses.push(response);
+            
+            // Nothing runs after a successful SHUTDOWN
+            if self.shutting_down {
+                break;
+            }
+            
+            // Keep pipelined replies in order: frames after an offloaded command wait for its reply
+            if self.is_connection_blocked(id) {
+                let remaining: Vec<RespFrame> = frames_to_process.by_ref().collect();
+                self.connections.with_connection(id, |conn| conn.deferred_frames.extend(remaining));
+                break;
+            }
         }
         
-        // Third phase: send responses with special handling for commands needing immediate delivery
+        // Third phase: send the responses
         let frames_processed_count = responses.len();
         let has_pending_writes = self.connections.with_connection(id, |conn| -> Result<bool> {
-            // For transaction/connection integrity: Commands needing immediate response get individual flush
-            // For performance: All other commands use efficient batching
-            if needs_immediate_flush {
-                // Transaction and connection commands need immediate flush for semantic correctness
-                for response in responses {
-                    if let RespFrame::NoResponse = &response {
-                        continue;
-                    }
-                    
-                    if let Err(e) = conn.send_frame(&response) {
-                        eprintln!("Send error for connection {}: {}", id, e);
-                        continue;
-                    }
-                    
-                    // Immediate flush for semantic correctness (EXEC, AUTH, MULTI, etc.)
-                    if let Err(e) = conn.flush() {
-                        match e {
-                            FerrousError::Connection(ref msg) if msg.contains("Broken pipe") 
-                                || msg.contains("Connection reset") => {
-                                conn.state = ConnectionState::Closing;
-                                return Err(e);
-                            },
-                            _ => {
-                                eprintln!("Immediate flush warning for connection {}: {}", id, e);
-                            }
-                        }
-                    }
-                }
-            } else {
-                // For pipelining optimization: Send all responses then flush once for efficient batching
-                for response in responses {
-                    if let RespFrame::NoResponse = &response {
-                        continue;
-                    }
-                    
-                    if let Err(e) = conn.send_frame(&response) {
-                        eprintln!("Send error for connection {}: {}", id, e);
-                        continue;
-                    }
+            // The replies of the whole batch go out together, in a single write
+            for response in responses {
+                if let RespFrame::NoResponse = &response {
+                    continue;
                 }
                 
-                // Single flush at end for efficient pipelined response batching
+                if let Err(e) = conn.queue_frame(response) {
+                    eprintln!("Send error for connection {}: {}", id, e);
+                }
+            }
+            
+            // I/O threads serialize and write the replies once every connection was served
+            if !pre_read {
                 match conn.flush() {
                     Ok(_) => {
-                        // All responses sent successfully
+                        // All responses sent, or the rest waits for the pending writes pass
                     },
                     Err(e) => {
                         match e {
@@ -881,31 +1713,24 @@ impl Server {
             return Ok(false);
         }
         
-        let mut still_pending = Vec::new();
+        // Flushing serializes the replies queued for I/O threads
         let did_work = !pending_ids.is_empty();
-        
-        for id in &pending_ids {
-            let result = self.connections.with_connection(*id, |conn| -> Result<bool> {
-                match conn.flush() {
-                    Ok(_) => Ok(conn.has_pending_writes()),
-                    Err(e) if matches!(e, FerrousError::Connection(_)) => {
-                        // Connection error - mark for closing
+        let unflushed = Arc::new(Mutex::new(Vec::new()));
+        let remaining = Arc::clone(&unflushed);
+        self.for_each_connection(pending_ids, move |conn| {
+            match conn.flush() {
+                Ok(_) if conn.has_pending_writes() => remaining.lock().unwrap().push(conn.id),
+                Ok(_) => {}
+                Err(e) => {
+                    if matches!(e, FerrousError::Connection(_)) {
+                        // Connection error - will be cleaned up in cleanup phase
                         conn.state = ConnectionState::Closing;
-                        Err(e)
                     }
-                    Err(e) => Err(e),
-                }
-            });
-            
-            match result {
-                Some(Ok(true)) => still_pending.push(*id), // Still has pending writes
-                Some(Err(e)) => {
-                    // Connection error - will be cleaned up in cleanup phase
-                    eprintln!("Error flushing connection {}: {}", id, e);
+                    eprintln!("Error flushing connection {}: {}", conn.id, e);
                 }
-                _ => {} // Connection gone or all data flushed
             }
-        }
+        });
+        let still_pending: Vec<u64> = std::mem::take(&mut *unflushed.lock().unwrap());
         
         // Put back connections that still have pending writes
         if !still_pending.is_empty() {
@@ -938,18 +1763,30 @@ impl Server {
                 let _is_sync_command = matches!(command.as_str(), "SYNC" | "PSYNC");
                 
                 // Get connection state
-                let (db_index, in_transaction, conn_status) = match self.connections.with_connection(conn_id, |conn| {
-                    (conn.db_index, conn.transaction_state.in_transaction, conn.state.clone())
+                let (db_index, in_transaction, conn_status, no_touch) = match self.connections.with_connection(conn_id, |conn| {
+                    (conn.db_index, conn.transaction_state.in_transaction, conn.state.clone(), conn.no_touch)
                 }) {
                     Some(state) => state,
                     None => return Ok(RespFrame::error("ERR connection not found")),
                 };
                 
+                // Everything run on behalf of a NO-TOUCH client (including EXEC and scripts) skips clock updates
+                let _no_touch = crate::storage::lru::NoTouchGuard::new(no_touch);
+                
+                // Scripts the command runs, inside EXEC too, are charged to the client's ACL user
+                let runs_scripts = matches!(command.as_str(), "EVAL" | "EVAL_RO" | "EVALSHA" | "EVALSHA_RO" | "FCALL" | "FCALL_RO" | "EXEC");
+                let _user = runs_scripts
+                    .then(|| self.connections.with_connection(conn_id, |conn| self.handshake.acl.quota_of(&conn.user)))
+                    .flatten()
+                    .flatten()
+                    .map(lua_quota::UserScope::enter);
+                
                 // Check if authentication is required
                 if self.config.password.is_some() && conn_status != ConnectionState::Authenticated {
                     // Only AUTH, PING and QUIT commands are allowed when not authenticated
                     match command.as_str() {
                         "AUTH" => return self.handle_auth(parts, conn_id),
+                        "HELLO" => return self.handle_hello(parts, conn_id),
                         "PING" => return self.handle_ping(parts), // Allow PING for monitoring
                         "QUIT" => return Ok(RespFrame::ok()),
                         _ => return Ok(RespFrame::error("NOAUTH Authentication required")),
@@ -970,6 +1807,26 @@ impl Server {
                     return Ok(RespFrame::ok());
                 }
                 
+                // CLIENT PAUSE holds the command, and whatever was pipelined after it, until the pause ends;
+                // commands queued by MULTI wait for their EXEC instead
+                if !(in_transaction && transactions::should_queue_command(&command)) {
+                    let writes = match command.as_str() {
+                        "EXEC" => self.connections.with_connection(conn_id, |conn| {
+                            conn.transaction_state.queued_commands.iter()
+                                .any(|queued| queued.first().and_then(|name| name.as_string())
+                                    .is_some_and(|name| self.is_write(&name.to_uppercase(), queued)))
+                        }).unwrap_or(false),
+                        _ => self.is_write(&command, parts),
+                    };
+                    if self.pause_gate.lock().unwrap().holds(&command, writes) {
+                        self.connections.with_connection(conn_id, |conn| {
+                            conn.deferred_frames.push_front(frame.clone());
+                            conn.state = ConnectionState::Paused;
+                        });
+                        return Ok(RespFrame::NoResponse);
+                    }
+                }
+                
                 // Handle transaction control commands and connection-specific commands
                 match command.as_str() {
                     "MULTI" => {
@@ -1001,6 +1858,7 @@ impl Server {
                     "PSUBSCRIBE" => return self.handle_psubscribe(parts, conn_id),
                     "PUNSUBSCRIBE" => return self.handle_punsubscribe(parts, conn_id),
                     "AUTH" => return self.handle_auth(parts, conn_id), // Handle AUTH after authentication too
+                    "HELLO" => return self.handle_hello(parts, conn_id),
                     _ => {}
                 }
                 
@@ -1014,6 +1872,25 @@ impl Server {
                     _ => {}
                 }
                 
+                // In cluster mode, commands on keys served by another node are redirected there
+                if self.cluster.is_enabled() {
+                    let asking = self.connections.with_connection(conn_id, |conn| {
+                        std::mem::replace(&mut conn.asking, command == "ASKING")
+                    }).unwrap_or(false);
+                    if command == "ASKING" {
+                        return Ok(RespFrame::ok());
+                    }
+                    
+                    let asking = asking || command == "RESTORE-ASKING";
+                    let args = Self::bulk_args(parts).unwrap_or_default();
+                    let redirect = self.cluster.redirect(&args, asking, |key| {
+                        self.storage.exists(db_index, key).unwrap_or(false)
+                    });
+                    if let Some(redirect) = redirect {
+                        return Ok(redirect);
+                    }
+                }
+                
                 // Check if we should queue the command
                 if in_transaction && transactions::should_queue_command(&command) {
                     return self.connections.with_connection(conn_id, |conn| {
@@ -1022,25 +1899,7 @@ impl Server {
                 }
                 
                 // Process normal command
-                let result = match command.as_str() {
-                    // Check if clients are currently paused
-                    // Special commands like AUTH, CLIENT commands are exempt from pausing
-                    _ if !matches!(command.as_str(), "AUTH" | "CLIENT" | "QUIT") => {
-                        // Check if clients are paused
-                        let now = SystemTime::now();
-                        let paused_until = self.clients_paused_until.lock().unwrap();
-                        if now < *paused_until {
-                            return Ok(RespFrame::error("ERR CLIENT PAUSE active, please retry later"));
-                        }
-                        
-                        // Not paused, continue processing
-                        drop(paused_until);
-                        self.process_normal_command(parts, db_index, conn_id)
-                    },
-                    _ => self.process_normal_command(parts, db_index, conn_id),
-                };
-                
-                result
+                self.process_normal_command(parts, db_index, conn_id)
             }
             _ => Ok(RespFrame::error("ERR invalid request format")),
         };
@@ -1169,15 +2028,46 @@ impl Server {
             None
         };
         
-        // Log to AOF for write commands
+        let is_write = self.is_write(&command_name, parts);
+        
+        // Replicas only take writes from their master, and a failing-over master takes none;
+        // scripts run read-only there
+        let is_script = matches!(command_name.as_str(), "EVAL" | "EVALSHA" | "FCALL");
+        let deny_writes = if self.replication.is_replica() {
+            Some(ReadOnlyScript::REPLICA)
+        } else if self.replication.writes_paused() {
+            Some(ReadOnlyScript::FAILOVER)
+        } else {
+            None
+        };
+        if let Some(error) = deny_writes.filter(|_| is_write && !is_script) {
+            return Ok(RespFrame::error(error));
+        }
+        let _replica_guard = deny_writes.map(ReadOnlyScript::enter);
+        
+        // Over maxmemory, evict what the policy allows, then refuse writes that
+        // would grow the dataset further; replicas leave eviction to their master
+        if self.storage.max_memory() > 0 && !self.replication.is_replica()
+            && !self.evict_keys() && flags::is_denyoom_command(&command_name)
+        {
+            return Ok(RespFrame::error(StorageError::OutOfMemory.to_string()));
+        }
+        
+        // Log to AOF for write commands; scripts log their effects once they ran
         if let Some(aof) = &self.aof_engine {
-            if self.is_write_command(&command_name) {
-                if let Err(e) = aof.append_command(parts) {
+            if is_write && !is_script {
+                if let Err(e) = aof.append_command(db, parts) {
                     eprintln!("Failed to append to AOF: {}", e);
                 }
             }
         }
         
+        // Update LRU access clocks through the shared path used by the script bridge
+        crate::storage::lru::record_frame_access(&self.storage, db, parts);
+        
+        // Collect the writes a script makes, to replicate them instead of the script
+        let effects = is_script.then(lua_effects::EffectsScope::begin);
+        
         // Route to command handler
         let result = match command_name.as_str() {
             "PING" => self.handle_ping(parts),
@@ -1188,9 +2078,13 @@ impl Server {
             "DECR" => self.handle_decr(parts, db),
             "INCRBY" => self.handle_incrby(parts, db),
             "DECRBY" => self.handle_decrby(parts, db),
+            "INCRBYFLOAT" => crate::storage::commands::strings::handle_incrbyfloat(&self.storage, db, parts),
             "DEL" => self.handle_del(parts, db),
             "EXISTS" => self.handle_exists(parts, db),
-            "EXPIRE" => self.handle_expire(parts, db),
+            "TOUCH" => self.handle_touch(parts, db),
+            "SORT" => crate::storage::commands::sort::handle_sort(&self.storage, db, parts, false),
+            "SORT_RO" => crate::storage::commands::sort::handle_sort(&self.storage, db, parts, true),
+            "EXPIRE" => crate::storage::commands::strings::handle_expire(&self.storage, db, parts),
             "TTL" => self.handle_ttl(parts, db),
             "SELECT" => self.handle_select(parts, conn_id),
             "FLUSHDB" => self.handle_flushdb(parts, db),
@@ -1221,56 +2115,7 @@ impl Server {
                 
                 Ok(RespFrame::ok())
             },
-            "CONFIG" => {
-                if parts.len() < 2 {
-                    return Ok(RespFrame::error("ERR wrong number of arguments for 'config' command"));
-                }
-                
-                let subcommand = match &parts[1] {
-                    RespFrame::BulkString(Some(bytes)) => {
-                        String::from_utf8_lossy(bytes).to_uppercase()
-                    },
-                    _ => return Ok(RespFrame::error("ERR invalid subcommand format")),
-                };
-                
-                if subcommand.as_str() == "SET" && parts.len() >= 4 {
-                    let param_name = match &parts[2] {
-                        RespFrame::BulkString(Some(bytes)) => {
-                            String::from_utf8_lossy(bytes).to_string().to_lowercase()
-                        },
-                        _ => return Ok(RespFrame::error("ERR invalid parameter name format")),
-                    };
-                    
-                    let param_value = match &parts[3] {
-                        RespFrame::BulkString(Some(bytes)) => {
-                            String::from_utf8_lossy(bytes).to_string()
-                        },
-                        _ => return Ok(RespFrame::error("ERR invalid parameter value format")),
-                    };
-                    
-                    match param_name.as_str() {
-                        "slowlog-log-slower-than" => {
-                            if let Ok(value) = param_value.parse::<i64>() {
-                                self.slowlog.set_threshold_micros(value);
-                                return Ok(RespFrame::ok());
-                            } else {
-                                return Ok(RespFrame::error("ERR value is not an integer or out of range"));
-                            }
-                        },
-                        "slowlog-max-len" => {
-                            if let Ok(value) = param_value.parse::<u64>() {
-                                self.slowlog.set_max_len(value);
-                                return Ok(RespFrame::ok());
-                            } else {
-                                return Ok(RespFrame::error("ERR value is not an integer or out of range"));
-                            }
-                        },
-                        _ => {}
-                    }
-                }
-                
-                crate::storage::commands::config::handle_config(parts)
-            },
+            "CONFIG" => self.handle_config(parts),
             // Additional string commands
             "MGET" => crate::storage::commands::strings::handle_mget(&self.storage, db, parts),
             "MSET" => crate::storage::commands::strings::handle_mset(&self.storage, db, parts),
@@ -1279,51 +2124,42 @@ impl Server {
             "STRLEN" => crate::storage::commands::strings::handle_strlen(&self.storage, db, parts),
             "GETRANGE" => crate::storage::commands::strings::handle_getrange(&self.storage, db, parts),
             "SETRANGE" => crate::storage::commands::strings::handle_setrange(&self.storage, db, parts),
+            // Bitmap commands
+            "SETBIT" => crate::storage::commands::bitmaps::handle_setbit(&self.storage, db, parts),
+            "GETBIT" => crate::storage::commands::bitmaps::handle_getbit(&self.storage, db, parts),
+            "BITCOUNT" => crate::storage::commands::bitmaps::handle_bitcount(&self.storage, db, parts),
+            "BITPOS" => crate::storage::commands::bitmaps::handle_bitpos(&self.storage, db, parts),
+            "BITOP" => crate::storage::commands::bitmaps::handle_bitop(&self.storage, db, parts),
+            // HyperLogLog commands
+            "PFADD" => crate::storage::commands::hyperloglog::handle_pfadd(&self.storage, db, parts),
+            "PFCOUNT" => crate::storage::commands::hyperloglog::handle_pfcount(&self.storage, db, parts),
+            "PFMERGE" => crate::storage::commands::hyperloglog::handle_pfmerge(&self.storage, db, parts),
+            // Geospatial commands
+            "GEOADD" => crate::storage::commands::geo::handle_geoadd(&self.storage, db, parts),
+            "GEOPOS" => crate::storage::commands::geo::handle_geopos(&self.storage, db, parts),
+            "GEODIST" => crate::storage::commands::geo::handle_geodist(&self.storage, db, parts),
+            "GEOSEARCH" => crate::storage::commands::geo::handle_geosearch(&self.storage, db, parts),
+            "GEOSEARCHSTORE" => crate::storage::commands::geo::handle_geosearchstore(&self.storage, db, parts),
             "TYPE" => crate::storage::commands::strings::handle_type(&self.storage, db, parts),
             "RENAME" => crate::storage::commands::strings::handle_rename(&self.storage, db, parts),
             "RENAMENX" => self.handle_renamenx(parts, db),
             "RANDOMKEY" => self.handle_randomkey(parts, db),
             "BLPOP" => self.handle_blpop(parts, db, conn_id),
             "BRPOP" => self.handle_brpop(parts, db, conn_id),
+            "BLMOVE" => self.handle_blmove(parts, db, conn_id),
+            "BRPOPLPUSH" => self.handle_brpoplpush(parts, db, conn_id),
+            "BLMPOP" => self.handle_blmpop(parts, db, conn_id),
             "KEYS" => crate::storage::commands::strings::handle_keys(&self.storage, db, parts),
             "PEXPIRE" => crate::storage::commands::strings::handle_pexpire(&self.storage, db, parts),
+            "EXPIREAT" => crate::storage::commands::strings::handle_expireat(&self.storage, db, parts),
+            "PEXPIREAT" => crate::storage::commands::strings::handle_pexpireat(&self.storage, db, parts),
+            "EXPIRETIME" => crate::storage::commands::strings::handle_expiretime(&self.storage, db, parts),
+            "PEXPIRETIME" => crate::storage::commands::strings::handle_pexpiretime(&self.storage, db, parts),
             "PTTL" => crate::storage::commands::strings::handle_pttl(&self.storage, db, parts),
             "PERSIST" => crate::storage::commands::strings::handle_persist(&self.storage, db, parts),
             // List commands
-            "LPUSH" => {
-                let result = crate::storage::commands::lists::handle_lpush(&self.storage, db, parts);
-                
-                // Notify blocked clients if LPUSH was successful
-                if let Ok(RespFrame::Integer(count)) = &result {
-                    if *count > 0 && parts.len() >= 3 {
-                        if let RespFrame::BulkString(Some(key_bytes)) = &parts[1] {
-                            if self.blocking_manager.has_blocked_clients(db, key_bytes) {
-                                // Simplified notification - no pre-computed value
-                                self.blocking_manager.notify_key_ready(db, key_bytes);
-                            }
-                        }
-                    }
-                }
-                
-                result
-            },
-            "RPUSH" => {
-                let result = crate::storage::commands::lists::handle_rpush(&self.storage, db, parts);
-                
-                // Notify blocked clients if RPUSH was successful
-                if let Ok(RespFrame::Integer(count)) = &result {
-                    if *count > 0 && parts.len() >= 3 {
-                        if let RespFrame::BulkString(Some(key_bytes)) = &parts[1] {
-                            if self.blocking_manager.has_blocked_clients(db, key_bytes) {
-                                // Simplified notification - no pre-computed value
-                                self.blocking_manager.notify_key_ready(db, key_bytes);
-                            }
-                        }
-                    }
-                }
-                
-                result
-            },
+            "LPUSH" => crate::storage::commands::lists::handle_lpush(&self.storage, db, parts),
+            "RPUSH" => crate::storage::commands::lists::handle_rpush(&self.storage, db, parts),
             "LPOP" => crate::storage::commands::lists::handle_lpop(&self.storage, db, parts),
             "RPOP" => crate::storage::commands::lists::handle_rpop(&self.storage, db, parts),
             "LLEN" => crate::storage::commands::lists::handle_llen(&self.storage, db, parts),
@@ -1332,14 +2168,20 @@ impl Server {
             "LSET" => crate::storage::commands::lists::handle_lset(&self.storage, db, parts),
             "LTRIM" => crate::storage::commands::lists::handle_ltrim(&self.storage, db, parts),
             "LREM" => crate::storage::commands::lists::handle_lrem(&self.storage, db, parts),
+            "LPOS" => crate::storage::commands::lists::handle_lpos(&self.storage, db, parts),
+            "LMPOP" => crate::storage::commands::lists::handle_lmpop(&self.storage, db, parts),
+            "LMOVE" => crate::storage::commands::lists::handle_lmove(&self.storage, db, parts),
+            "RPOPLPUSH" => crate::storage::commands::lists::handle_rpoplpush(&self.storage, db, parts),
             // Set commands
             "SADD" => crate::storage::commands::sets::handle_sadd(&self.storage, db, parts),
             "SREM" => crate::storage::commands::sets::handle_srem(&self.storage, db, parts),
             "SMEMBERS" => crate::storage::commands::sets::handle_smembers(&self.storage, db, parts),
             "SISMEMBER" => crate::storage::commands::sets::handle_sismember(&self.storage, db, parts),
+            "SMISMEMBER" => crate::storage::commands::sets::handle_smismember(&self.storage, db, parts),
             "SCARD" => crate::storage::commands::sets::handle_scard(&self.storage, db, parts),
             "SUNION" => crate::storage::commands::sets::handle_sunion(&self.storage, db, parts),
             "SINTER" => crate::storage::commands::sets::handle_sinter(&self.storage, db, parts),
+            "SINTERCARD" => crate::storage::commands::sets::handle_sintercard(&self.storage, db, parts),
             "SDIFF" => crate::storage::commands::sets::handle_sdiff(&self.storage, db, parts),
             "SRANDMEMBER" => crate::storage::commands::sets::handle_srandmember(&self.storage, db, parts),
             "SPOP" => crate::storage::commands::sets::handle_spop(&self.storage, db, parts),
@@ -1355,6 +2197,14 @@ impl Server {
             "HKEYS" => crate::storage::commands::hashes::handle_hkeys(&self.storage, db, parts),
             "HVALS" => crate::storage::commands::hashes::handle_hvals(&self.storage, db, parts),
             "HINCRBY" => crate::storage::commands::hashes::handle_hincrby(&self.storage, db, parts),
+            "HINCRBYFLOAT" => crate::storage::commands::hashes::handle_hincrbyfloat(&self.storage, db, parts),
+            "HEXPIRE" => crate::storage::commands::hashes::handle_hexpire(&self.storage, db, parts),
+            "HPEXPIRE" => crate::storage::commands::hashes::handle_hpexpire(&self.storage, db, parts),
+            "HEXPIREAT" => crate::storage::commands::hashes::handle_hexpireat(&self.storage, db, parts),
+            "HPEXPIREAT" => crate::storage::commands::hashes::handle_hpexpireat(&self.storage, db, parts),
+            "HPERSIST" => crate::storage::commands::hashes::handle_hpersist(&self.storage, db, parts),
+            "HTTL" => crate::storage::commands::hashes::handle_httl(&self.storage, db, parts),
+            "HPTTL" => crate::storage::commands::hashes::handle_hpttl(&self.storage, db, parts),
             // Sorted set commands
             "ZADD" => self.handle_zadd(parts, db),
             "ZREM" => self.handle_zrem(parts, db),
@@ -1376,13 +2226,13 @@ impl Server {
             "XRANGE" => crate::storage::commands::streams::handle_xrange(&self.storage, db, parts),
             "XREVRANGE" => crate::storage::commands::streams::handle_xrevrange(&self.storage, db, parts),
             "XLEN" => crate::storage::commands::streams::handle_xlen(&self.storage, db, parts),
-            "XREAD" => crate::storage::commands::streams::handle_xread(&self.storage, db, parts),
+            "XREAD" => self.handle_stream_read(parts, db, conn_id),
             "XTRIM" => crate::storage::commands::streams::handle_xtrim(&self.storage, db, parts),
             "XDEL" => crate::storage::commands::streams::handle_xdel(&self.storage, db, parts),
             
             // Consumer group commands
             "XGROUP" => crate::storage::commands::consumer_groups::handle_xgroup(&self.storage, db, parts),
-            "XREADGROUP" => crate::storage::commands::consumer_groups::handle_xreadgroup(&self.storage, db, parts),
+            "XREADGROUP" => self.handle_stream_read(parts, db, conn_id),
             "XACK" => crate::storage::commands::consumer_groups::handle_xack(&self.storage, db, parts),
             "XCLAIM" => crate::storage::commands::consumer_groups::handle_xclaim(&self.storage, db, parts),
             "XPENDING" => crate::storage::commands::consumer_groups::handle_xpending(&self.storage, db, parts),
@@ -1396,6 +2246,12 @@ impl Server {
             "SCAN" => crate::storage::commands::scan::handle_scan(&self.storage, db, parts),
             "HSCAN" => crate::storage::commands::scan::handle_hscan(&self.storage, db, parts),
             "SSCAN" => crate::storage::commands::scan::handle_sscan(&self.storage, db, parts),
+            "ZRANGEBYLEX" => crate::storage::commands::sorted_sets::handle_zrangebylex(&self.storage, db, parts),
+            "ZREVRANGEBYLEX" => crate::storage::commands::sorted_sets::handle_zrevrangebylex(&self.storage, db, parts),
+            "ZLEXCOUNT" => crate::storage::commands::sorted_sets::handle_zlexcount(&self.storage, db, parts),
+            "ZREMRANGEBYRANK" => crate::storage::commands::sorted_sets::handle_zremrangebyrank(&self.storage, db, parts),
+            "ZREMRANGEBYSCORE" => crate::storage::commands::sorted_sets::handle_zremrangebyscore(&self.storage, db, parts),
+            "ZREMRANGEBYLEX" => crate::storage::commands::sorted_sets::handle_zremrangebylex(&self.storage, db, parts),
             "ZSCAN" => crate::storage::commands::scan::handle_zscan(&self.storage, db, parts),
             // AOF commands
             "BGREWRITEAOF" => crate::storage::commands::aof::handle_bgrewriteaof(self.aof_engine.as_ref()),
@@ -1404,65 +2260,93 @@ impl Server {
                 &self.storage,
                 &self.stats,
                 self.start_time,
-                self.connections.total_connections(),
-                self.config.max_clients,
-                &self.replication,
+                self.client_counts(),
+                crate::storage::commands::monitor::Subsystems {
+                    replication: &self.replication,
+                    cluster: &self.cluster,
+                    rdb: self.rdb_engine.as_deref(),
+                    aof: self.aof_engine.as_deref(),
+                    storage_monitor: self.storage_monitor.as_ref(),
+                    pubsub: &self.pubsub,
+                    config: &self.live_config,
+                },
                 parts
             ),
             "SLOWLOG" => crate::storage::commands::slowlog::handle_slowlog(&self.slowlog, parts),
             // Memory commands
-            "MEMORY" => crate::storage::commands::memory::handle_memory(parts, &self.storage, db),
+            "MEMORY" => crate::storage::commands::memory::handle_memory(parts, &self.storage, &self.stats, db),
+            "OBJECT" => crate::storage::commands::object::handle_object(parts, &self.storage, db),
+            "DUMP" => crate::storage::commands::dump::handle_dump(&self.storage, db, parts),
+            "RESTORE" => crate::storage::commands::dump::handle_restore(&self.storage, db, parts),
+            "RESTORE-ASKING" => crate::storage::commands::dump::handle_restore(&self.storage, db, parts),
+            "MIGRATE" => self.handle_migrate(parts, db),
+            "CLUSTER" => crate::cluster::handle_cluster(parts, &self.cluster, &self.storage, db),
+            "ASKING" => Ok(RespFrame::error("ERR This instance has cluster support disabled")),
+            "DEBUG" => crate::storage::commands::debug::handle_debug(parts, &self.storage),
             // Client commands
             "CLIENT" => {
-                // Get a mutable reference to clients_paused_until for CLIENT PAUSE
-                let mut paused_until = self.clients_paused_until.lock().unwrap();
+                // Get a mutable reference to the pause gate for CLIENT PAUSE
+                let mut pause_gate = self.pause_gate.lock().unwrap();
+                let ctx = crate::storage::commands::client::ClientContext {
+                    pause: Some(&mut *pause_gate),
+                    tracking: &self.tracking,
+                    pubsub: &self.pubsub,
+                };
                 // Use &* to get a reference to the ShardedConnections inside the Arc
-                crate::storage::commands::client::handle_client(
-                    parts,
-                    &*self.connections,
-                    conn_id,
-                    Some(&mut *paused_until)
-                )
+                crate::storage::commands::client::handle_client(parts, &*self.connections, conn_id, ctx)
             },
             // Auth command  
             "AUTH" => self.handle_auth(parts, 0), // Special handling for AUTH in process_frame
             // Replication commands
             "REPLICAOF" => crate::replication::handle_replicaof(parts, &self.replication, &self.storage),
             "SLAVEOF" => crate::replication::handle_slaveof(parts, &self.replication, &self.storage),
+            "FAILOVER" => crate::replication::handle_failover(parts, &self.replication, &self.storage),
             "SYNC" => crate::replication::handle_sync(&self.replication, &self.storage, &self.rdb_engine.as_ref().unwrap()),
             "PSYNC" => crate::replication::handle_psync(parts, &self.replication, &self.storage, &self.rdb_engine.as_ref().unwrap()),
             "QUIT" => Ok(RespFrame::ok()),
+            "ACL" => {
+                let whoami = self.connections.with_connection(conn_id, |conn| conn.user.clone())
+                    .unwrap_or_else(|| acl::DEFAULT_USER.to_string());
+                Ok(acl::handle_acl(parts, &self.handshake.acl, &*self.connections, &whoami))
+            }
             "EVAL" => {
                 use crate::storage::commands::lua::handle_eval_with_db;
                 match handle_eval_with_db(&self.storage, parts, db) {
                     Ok(resp) => Ok(resp),
-                    Err(e) => {
-                        eprintln!("[SERVER ERROR] Lua EVAL error: {}", e);
-                        Ok(RespFrame::error(format!("ERR Lua execution error: {}", e)))
-                    }
+                    Err(e) => Ok(crate::storage::commands::lua::script_error_reply(e)),
                 }
             },
+            "EVAL_RO" => crate::storage::commands::lua::handle_eval_ro_with_db(&self.storage, parts, db),
+            "FCALL" => {
+                crate::storage::commands::lua::handle_fcall_with_db(&self.storage, parts, db)
+            },
+            "FCALL_RO" => crate::storage::commands::lua::handle_fcall_ro_with_db(&self.storage, parts, db),
+            "FUNCTION" => {
+                crate::storage::commands::lua::handle_function(&self.storage, parts)
+            },
             "EVALSHA" => {
                 // EVALSHA needs script cache access
-                self.handle_evalsha_command(parts)
+                self.handle_evalsha_command(parts, db, false)
             },
+            "EVALSHA_RO" => self.handle_evalsha_command(parts, db, true),
             "COMMAND" => {
                 // Redis introspection command for client compatibility
                 crate::network::admin_commands::handle_command(parts)
             },
+            "LOLWUT" => crate::network::admin_commands::handle_lolwut(parts),
             "SHUTDOWN" => {
                 // Graceful server shutdown with save option
-                crate::network::admin_commands::handle_shutdown(parts, &self.storage, self.rdb_engine.as_ref())
+                self.handle_shutdown(parts)
             },
             "SCRIPT" => {
                 // SCRIPT commands need script cache access
-                self.handle_script_command(parts)
+                self.handle_script_command(parts, conn_id)
             },
             _ => Ok(RespFrame::error(format!("ERR unknown command '{}'", command_name))),
         };
         
         // Auto-save change recording - always enabled (independent of monitoring)
-        if self.is_write_command(&command_name) {
+        if is_write {
             if let Ok(resp) = &result {
                 if !resp.is_error() {
                     self.record_change();
@@ -1470,89 +2354,260 @@ impl Server {
             }
         }
         
+        let failed = !matches!(&result, Ok(resp) if !resp.is_error());
+        if failed {
+            self.stats.total_error_replies.fetch_add(1, Ordering::Relaxed);
+        }
+        
         // Zero-overhead monitoring completion - only when enabled and timing started
+        // (total_commands_processed is counted by the caller for every command)
         if let Some(start_time) = start_time {
             if self.monitoring.is_enabled() {
                 let client_addr = self.connections.with_connection(conn_id, |conn| {
                     conn.addr.to_string()
                 }).unwrap_or_else(|| "127.0.0.1:unknown".to_string());
                 
-                // Record command count inside conditional
-                self.monitoring.record_command_count();
-                
                 // Use correct trait method for timing completion
-                self.monitoring.record_command_timing(start_time, &command_name, parts, &client_addr);
+                let effect_count = effects.as_ref().map(|effects| effects.count() as u64);
+                self.monitoring.record_command_timing(start_time, &command_name, parts, &client_addr, effect_count, failed);
                 
                 // Use correct trait method for monitor broadcasting with proper signature
                 self.monitoring.broadcast_to_monitors(parts, conn_id, db, SystemTime::now());
             }
         }
         
-        // Replication propagation for write commands
-        if self.is_write_command(&command_name) {
-            if let Ok(resp) = &result {
-                if !resp.is_error() {
-                    if let Ok(replica_ids) = self.replication.propagate_command(&RespFrame::Array(Some(parts.to_vec()))) {
-                        for replica_id in replica_ids {
-                            let propagated = self.connections.with_connection(replica_id, |conn| -> Result<()> {
-                                conn.send_frame(&RespFrame::Array(Some(parts.to_vec())))?;
-                                Ok(())
-                            });
-                            
-                            if let Some(Err(e)) = propagated {
-                                eprintln!("Error propagating to replica {}: {}", replica_id, e);
+        // Keyspace triggers: queue the events of a successful write, then run every pending trigger
+        if let Ok(resp) = &result {
+            if !resp.is_error() {
+                crate::storage::lua_triggers::notify_frame_command(db, parts);
+            }
+        }
+        crate::storage::lua_triggers::dispatch();
+        
+        // Scripts propagate the writes they made, even when they failed halfway;
+        // other write commands propagate as issued once they succeeded
+        let propagated = match effects {
+            Some(effects) => {
+                let count = effects.count() as u64;
+                self.stats.script_effects.fetch_add(count, Ordering::Relaxed);
+                self.stats.script_effects_peak.fetch_max(count, Ordering::Relaxed);
+                
+                let commands = effects.finish();
+                if let Some(aof) = &self.aof_engine {
+                    for command in &commands {
+                        if let RespFrame::Array(Some(command)) = command {
+                            if let Err(e) = aof.append_command(db, command) {
+                                eprintln!("Failed to append to AOF: {}", e);
                             }
                         }
                     }
                 }
+                commands
             }
+            None if is_write && matches!(&result, Ok(resp) if !resp.is_error()) => vec![self.propagated_write(db, parts)],
+            None => Vec::new(),
+        };
+        
+        // Serve clients blocked on the keys just written
+        self.notify_written_keys(db, &propagated);
+        
+        // Client-side caching: remember what tracking clients read, invalidate what was written
+        if self.tracking.is_active() {
+            if conn_id != 0 {
+                let read = crate::storage::commands::flags::is_read_only_command(&command_name)
+                    && matches!(&result, Ok(resp) if !resp.is_error());
+                self.track_read_keys(parts, &command_name, read, conn_id);
+            }
+            self.invalidate_tracked_keys(&propagated, conn_id);
         }
         
+        // Replication propagation for write commands
+        self.propagate_to_replicas(&propagated);
+        
         result
     }
     
+    /// Send propagated commands to the replicas, a chunk at a time
+    ///
+    /// A script deleting keys in a loop can propagate hundreds of thousands of
+    /// commands. Between chunks, replica output buffers past
+    /// `REPLICA_OUTPUT_FLUSH_BYTES` are flushed so they do not grow with the
+    /// whole batch, and a replica still past `REPLICA_OUTPUT_LIMIT_BYTES` is
+    /// dropped, to resynchronize once it reconnects, as Redis does for replicas
+    /// over their output buffer limit.
+    fn propagate_to_replicas(&self, commands: &[RespFrame]) {
+        let chunked = commands.len() > lua_effects::PROPAGATION_CHUNK;
+        for chunk in commands.chunks(lua_effects::PROPAGATION_CHUNK) {
+            let mut replicas = Vec::new();
+            for command in chunk {
+                if let Ok(replica_ids) = self.replication.propagate_command(command) {
+                    for replica_id in replica_ids {
+                        let sent = self.connections.with_connection(replica_id, |conn| -> Result<()> {
+                            if !conn.is_closing() {
+                                conn.send_frame(command)?;
+                            }
+                            Ok(())
+                        });
+                        
+                        if let Some(Err(e)) = sent {
+                            eprintln!("Error propagating to replica {}: {}", replica_id, e);
+                        }
+                        if chunked && !replicas.contains(&replica_id) {
+                            replicas.push(replica_id);
+                        }
+                    }
+                }
+            }
+            
+            for replica_id in replicas {
+                self.connections.with_connection(replica_id, |conn| {
+                    if conn.is_closing() || conn.pending_write_len() < REPLICA_OUTPUT_FLUSH_BYTES {
+                        return;
+                    }
+                    if let Err(e) = conn.flush() {
+                        eprintln!("Error flushing replica {}: {}", replica_id, e);
+                    }
+                    if conn.pending_write_len() >= REPLICA_OUTPUT_LIMIT_BYTES {
+                        eprintln!("Replica {} output buffer over {} bytes while propagating script effects, disconnecting",
+                                  replica_id, REPLICA_OUTPUT_LIMIT_BYTES);
+                        let _ = conn.close();
+                    }
+                });
+            }
+        }
+    }
+    
     /// Handle AUTH command
     fn handle_auth(&self, parts: &[RespFrame], conn_id: u64) -> Result<RespFrame> {
-        // AUTH password
-        if parts.len() != 2 {
-            return Ok(RespFrame::error("ERR wrong number of arguments for 'auth' command"));
+        Ok(self.connections.with_connection(conn_id, |conn| self.handshake.auth(parts, conn))
+            .unwrap_or_else(|| RespFrame::error("ERR connection not found")))
+    }
+    
+    /// Count clients by what they are doing, for INFO clients
+    fn client_counts(&self) -> crate::storage::commands::monitor::ClientCounts {
+        let mut counts = crate::storage::commands::monitor::ClientCounts {
+            max_clients: self.config.max_clients,
+            script_users: self.handshake.acl.script_quotas(),
+            ..Default::default()
+        };
+        for id in self.connections.all_connection_ids() {
+            let subscribed = self.pubsub.is_subscribed(id);
+            self.connections.with_connection(id, |conn| {
+                counts.connected += 1;
+                counts.blocked += matches!(conn.state, ConnectionState::Blocked(_)) as usize;
+                counts.tracking += conn.tracking.is_some() as usize;
+                counts.pubsub += subscribed as usize;
+                counts.watching += !conn.transaction_state.watched_keys.is_empty() as usize;
+            });
         }
-        
-        // Extract password
-        let provided_password = match &parts[1] {
-            RespFrame::BulkString(Some(bytes)) => {
-                match String::from_utf8(bytes.to_vec()) {
-                    Ok(s) => s,
-                    Err(_) => return Ok(RespFrame::error("ERR invalid password format")),
+        counts
+    }
+    
+    /// Handle CONFIG, serving SET, REWRITE and RESETSTAT against the live configuration
+    fn handle_config(&mut self, parts: &[RespFrame]) -> Result<RespFrame> {
+        let args = Self::bulk_args(parts).unwrap_or_default();
+        let subcommand = args.get(1).map(|arg| arg.to_ascii_uppercase());
+        match subcommand.as_deref() {
+            Some(b"SET") if args.len() >= 4 && args.len().is_multiple_of(2) => self.handle_config_set(&args[2..]),
+            Some(b"RESETSTAT") if args.len() == 2 => {
+                self.stats.reset();
+                Ok(RespFrame::ok())
+            }
+            Some(b"REWRITE") if args.len() == 2 => {
+                if self.live_config.file.is_none() {
+                    return Ok(RespFrame::error("ERR The server is running without a config file"));
+                }
+                match self.live_config.rewrite() {
+                    Ok(()) => Ok(RespFrame::ok()),
+                    Err(e) => {
+                        eprintln!("CONFIG REWRITE failed: {}", e);
+                        Ok(RespFrame::error(format!("ERR Rewriting config file: {}", e)))
+                    }
                 }
             }
-            _ => return Ok(RespFrame::error("ERR invalid password format")),
-        };
+            _ => crate::storage::commands::config::handle_config(parts, &self.live_config),
+        }
+    }
+    
+    /// Handle CONFIG SET parameter value [parameter value ...]
+    ///
+    /// All parameters are applied or none: values are validated on a copy of
+    /// the configuration first, and if a subsystem then refuses one, the ones
+    /// already applied are put back to their previous values.
+    fn handle_config_set(&mut self, args: &[&[u8]]) -> Result<RespFrame> {
+        let mut candidate = self.live_config.clone();
+        let mut changed: Vec<String> = Vec::new();
+        for pair in args.chunks(2) {
+            let param = String::from_utf8_lossy(pair[0]).to_lowercase();
+            let value = String::from_utf8_lossy(pair[1]);
+            if changed.contains(&param) {
+                return Ok(RespFrame::error(format!(
+                    "ERR CONFIG SET failed (possibly related to argument '{}') - duplicate parameter", param
+                )));
+            }
+            if let Err(e) = candidate.set(&param, &value) {
+                return Ok(crate::storage::commands::config::config_set_error(&param, &e));
+            }
+            changed.push(param);
+        }
         
-        // Check if server requires authentication
-        match &self.config.password {
-            Some(server_password) => {
-                if provided_password == *server_password {
-                    // Authentication successful
-                    self.stats.auth_successes.fetch_add(1, Ordering::Relaxed);
-                    
-                    // Update connection state
-                    self.connections.with_connection(conn_id, |conn| {
-                        conn.state = ConnectionState::Authenticated;
-                    });
-                    
-                    Ok(RespFrame::ok())
-                } else {
-                    // Authentication failed
-                    self.stats.auth_failures.fetch_add(1, Ordering::Relaxed);
-                    Ok(RespFrame::error("ERR invalid password"))
-                }
+        if let Err(e) = self.apply_config(&candidate, &changed) {
+            let previous = self.live_config.clone();
+            if let Err(rollback) = self.apply_config(&previous, &changed) {
+                eprintln!("CONFIG SET rollback failed: {}", rollback);
             }
-            None => {
-                // No password set on server
-                Ok(RespFrame::error("ERR Client sent AUTH, but no password is set"))
+            return Ok(RespFrame::error(format!("ERR CONFIG SET failed - {}", e)));
+        }
+        
+        self.live_config = candidate;
+        Ok(RespFrame::ok())
+    }
+    
+    /// Push the values of `params` in `config` to the subsystems using them
+    fn apply_config(&self, config: &FerrousConfig, params: &[String]) -> Result<()> {
+        for param in params {
+            match param.as_str() {
+                "maxmemory" => self.storage.set_max_memory(config.memory.max_memory),
+                "maxmemory-policy" => self.storage.set_eviction_policy(config.memory.max_memory_policy),
+                "maxmemory-samples" => self.storage.set_eviction_samples(config.memory.max_memory_samples),
+                "lfu-log-factor" => self.storage.set_lfu_log_factor(config.memory.lfu_log_factor),
+                "lfu-decay-time" => self.storage.set_lfu_decay_time(config.memory.lfu_decay_time),
+                "hash-max-listpack-entries" | "hash-max-listpack-value" | "list-max-listpack-size"
+                | "set-max-intset-entries" | "set-max-listpack-entries" | "set-max-listpack-value"
+                | "zset-max-listpack-entries" | "zset-max-listpack-value" => {
+                    self.storage.set_encoding_limits(config.memory.encoding_limits);
+                }
+                "appendfsync" => {
+                    if let Some(aof) = &self.aof_engine {
+                        aof.set_fsync_policy(config.aof.fsync_policy);
+                    }
+                }
+                "save" => {
+                    if let Some(monitor) = &self.storage_monitor {
+                        monitor.set_save_rules(config.rdb.save_rules.clone());
+                    }
+                }
+                "slowlog-log-slower-than" => self.slowlog.set_threshold_micros(config.monitoring.slowlog_threshold_micros),
+                "slowlog-max-len" => self.slowlog.set_max_len(config.monitoring.slowlog_max_len),
+                // Recorded for CONFIG GET and REWRITE; no events are published yet
+                "notify-keyspace-events" => {}
+                _ => {
+                    let engine = crate::storage::lua_engine::get_lua_engine(Arc::clone(&self.storage))?;
+                    let value = config.get(param).unwrap_or_default();
+                    match crate::storage::commands::config::scripting_config_set(&engine, param, &value) {
+                        Some(reply) if !reply.is_error() => {}
+                        _ => return Err(FerrousError::Config(format!("cannot apply '{}'", param))),
+                    }
+                }
             }
         }
+        Ok(())
+    }
+    
+    /// Handle HELLO [protover [AUTH username password] [SETNAME clientname]]
+    fn handle_hello(&self, parts: &[RespFrame], conn_id: u64) -> Result<RespFrame> {
+        Ok(self.connections.with_connection(conn_id, |conn| self.handshake.hello(parts, conn))
+            .unwrap_or_else(|| RespFrame::error("ERR connection not found")))
     }
     
     /// Record a change for auto-save monitoring
@@ -1563,29 +2618,15 @@ impl Server {
     }
     
     /// Check if a command is a write command that should be logged to AOF
-    fn is_write_command(&self, command: &str) -> bool {
-        if command == "SCRIPT" {
-            // SCRIPT FLUSH is a write command, but other SCRIPT subcommands are not
-            // In a real implementation, we would check the subcommand, but for simplicity
-            // we'll treat all SCRIPT commands as non-write commands for now
-            false
-        } else {
-            matches!(command,
-                "SET" | "DEL" | "EXPIRE" | "INCR" | "DECR" | "INCRBY" | "DECRBY" |
-                "SETNX" | "SETEX" | "PSETEX" | "FLUSHDB" | "FLUSHALL" |
-                "LPUSH" | "RPUSH" | "LPOP" | "RPOP" | "LSET" | "LREM" | "LTRIM" |
-                "SADD" | "SREM" | "SPOP" | 
-                "HSET" | "HDEL" | "HINCRBY" |
-                "ZADD" | "ZREM" | "ZINCRBY" | "ZPOPMIN" | "ZPOPMAX" |
-                "XADD" | "XTRIM" | "XDEL" |  // Stream write commands
-                "XGROUP" | "XACK" | "XCLAIM" |  // Consumer group write commands
-                "MSET" | "APPEND" | "SETRANGE" | "RENAME" | "RENAMENX" | "PERSIST" | "EVAL" | "EVALSHA"
-            )
-        }
+    ///
+    /// SCRIPT LOADLIB and the FUNCTION subcommands that change libraries are
+    /// logged and propagated like writes, so replayed scripts find what they call.
+    fn is_write(&self, command: &str, parts: &[RespFrame]) -> bool {
+        flags::is_write_command(command)
+            || crate::storage::commands::lua::is_script_loadlib(parts)
+            || crate::storage::commands::lua::is_function_write(parts)
     }
-
-
-
+    
     /// Handle PUBLISH command
     fn handle_publish(&self, parts: &[RespFrame]) -> Result<RespFrame> {
         if parts.len() != 3 {
@@ -2438,7 +3479,7 @@ impl Server {
         // Otherwise return PONG
         // Note: We use SimpleString instead of BulkString for better compatibility
         // with redis-benchmark and other clients that may expect this format
-        Ok(RespFrame::SimpleString(Arc::new(b"PONG".to_vec())))
+        Ok(crate::protocol::shared::pong())
     }
     
     /// Handle ECHO command
@@ -2468,8 +3509,9 @@ impl Server {
             _ => return Ok(RespFrame::error("ERR invalid key format")),
         };
         
+        // The argument's buffer becomes the stored value, without a copy
         let value = match &parts[2] {
-            RespFrame::BulkString(Some(bytes)) => bytes.as_ref().clone(),
+            RespFrame::BulkString(Some(bytes)) => Value::shared_string(Arc::clone(bytes)),
             _ => return Ok(RespFrame::error("ERR invalid value format")),
         };
         
@@ -2488,31 +3530,29 @@ impl Server {
                             if i + 1 >= parts.len() {
                                 return Ok(RespFrame::error("ERR syntax error"));
                             }
-                            if let RespFrame::BulkString(Some(seconds_bytes)) = &parts[i + 1] {
-                                if let Ok(seconds_str) = String::from_utf8(seconds_bytes.as_ref().clone()) {
-                                    if let Ok(seconds) = seconds_str.parse::<u64>() {
-                                        expiration = Some(Duration::from_secs(seconds));
-                                        i += 2;
-                                        continue;
-                                    }
-                                }
+                            let raw = match &parts[i + 1] {
+                                RespFrame::BulkString(Some(bytes)) => bytes.as_slice(),
+                                _ => return Ok(RespFrame::error("ERR syntax error")),
+                            };
+                            match CommandParser::parse_expire_time(raw, "set", false) {
+                                Ok(ttl) => expiration = Some(ttl),
+                                Err(e) => return Ok(RespFrame::error(e.to_string())),
                             }
-                            return Ok(RespFrame::error("ERR invalid expire time"));
+                            i += 2;
                         }
                         "PX" => {
                             if i + 1 >= parts.len() {
                                 return Ok(RespFrame::error("ERR syntax error"));
                             }
-                            if let RespFrame::BulkString(Some(millis_bytes)) = &parts[i + 1] {
-                                if let Ok(millis_str) = String::from_utf8(millis_bytes.as_ref().clone()) {
-                                    if let Ok(millis) = millis_str.parse::<u64>() {
-                                        expiration = Some(Duration::from_millis(millis));
-                                        i += 2;
-                                        continue;
-                                    }
-                                }
+                            let raw = match &parts[i + 1] {
+                                RespFrame::BulkString(Some(bytes)) => bytes.as_slice(),
+                                _ => return Ok(RespFrame::error("ERR syntax error")),
+                            };
+                            match CommandParser::parse_expire_time(raw, "set", true) {
+                                Ok(ttl) => expiration = Some(ttl),
+                                Err(e) => return Ok(RespFrame::error(e.to_string())),
                             }
-                            return Ok(RespFrame::error("ERR invalid expire time"));
+                            i += 2;
                         }
                         "NX" => {
                             nx = true;
@@ -2531,12 +3571,7 @@ impl Server {
         
         // Handle NX option (only set if key doesn't exist) - use atomic operation
         if nx {
-            let result = match expiration {
-                Some(expires_in) => self.storage.set_string_nx_ex(db, key, value, expires_in)?,
-                None => self.storage.set_string_nx(db, key, value)?,
-            };
-            
-            if result {
+            if self.storage.set_value_nx(db, key, value, expiration)? {
                 Ok(RespFrame::ok())
             } else {
                 Ok(RespFrame::null_bulk())
@@ -2549,26 +3584,12 @@ impl Server {
             }
             
             // Key exists, proceed with normal set
-            match expiration {
-                Some(expires_in) => {
-                    self.storage.set_string_ex(db, key, value, expires_in)?;
-                }
-                None => {
-                    self.storage.set_string(db, key, value)?;
-                }
-            }
+            self.storage.set_value(db, key, value, expiration)?;
             Ok(RespFrame::ok())
         }
         // Normal SET without conditions
         else {
-            match expiration {
-                Some(expires_in) => {
-                    self.storage.set_string_ex(db, key, value, expires_in)?;
-                }
-                None => {
-                    self.storage.set_string(db, key, value)?;
-                }
-            }
+            self.storage.set_value(db, key, value, expiration)?;
             Ok(RespFrame::ok())
         }
     }
@@ -2590,13 +3611,13 @@ impl Server {
             _ => return Ok(RespFrame::error("ERR invalid key format")),
         };
         
-        // Get the value
-        match self.storage.get_string(db, key)? {
+        // Reply with the stored buffer itself
+        match self.storage.get_string_shared(db, key)? {
             Some(value) => {
                 if self.monitoring.is_enabled() {
                     self.monitoring.record_cache_hit(true);
                 }
-                Ok(RespFrame::from_bytes(value))
+                Ok(RespFrame::BulkString(Some(value)))
             }
             None => {
                 if self.monitoring.is_enabled() {
@@ -2731,35 +3752,58 @@ impl Server {
         Ok(RespFrame::Integer(count))
     }
     
-    /// Handle EXPIRE command
-    fn handle_expire(&self, parts: &[RespFrame], db: usize) -> Result<RespFrame> {
-        if parts.len() != 3 {
-            return Ok(RespFrame::error("ERR wrong number of arguments for 'expire' command"));
+    /// Handle TOUCH command (updates access clocks even for NO-TOUCH clients)
+    fn handle_touch(&self, parts: &[RespFrame], db: usize) -> Result<RespFrame> {
+        if parts.len() < 2 {
+            return Ok(RespFrame::error("ERR wrong number of arguments for 'touch' command"));
         }
         
-        let key = match &parts[1] {
-            RespFrame::BulkString(Some(bytes)) => bytes.as_ref(),
-            _ => return Ok(RespFrame::error("ERR invalid key format")),
-        };
+        let keys: Vec<&[u8]> = parts[1..].iter()
+            .filter_map(|part| match part {
+                RespFrame::BulkString(Some(bytes)) => Some(bytes.as_slice()),
+                _ => None,
+            })
+            .collect();
         
-        let seconds = match &parts[2] {
-            RespFrame::BulkString(Some(bytes)) => {
-                match String::from_utf8_lossy(bytes).parse::<i64>() {
-                    Ok(n) => n,
-                    Err(_) => return Ok(RespFrame::error("ERR value is not an integer or out of range")),
-                }
-            }
-            _ => return Ok(RespFrame::error("ERR invalid seconds format")),
-        };
+        Ok(RespFrame::Integer(self.storage.touch_keys(db, &keys)? as i64))
+    }
+    
+    /// Form in which a successful write reaches replicas: the EXPIRE family is
+    /// sent as PEXPIREAT and the HEXPIRE family as HPEXPIREAT, everything else
+    /// as issued
+    fn propagated_write(&self, db: usize, parts: &[RespFrame]) -> RespFrame {
+        let args: Vec<&[u8]> = parts.iter()
+            .filter_map(|part| match part {
+                RespFrame::BulkString(Some(bytes)) => Some(bytes.as_slice()),
+                _ => None,
+            })
+            .collect();
+        let propagated = crate::storage::commands::strings::propagated_expire(&self.storage, db, &args)
+            .or_else(|| crate::storage::commands::hashes::propagated_field_expire(&args));
+        match propagated {
+            Some(command) => RespFrame::Array(Some(command.into_iter().map(RespFrame::from_bytes).collect())),
+            None => RespFrame::Array(Some(parts.to_vec())),
+        }
+    }
+    
+    /// Handle MIGRATE command
+    ///
+    /// The keys it moved away are logged and replicated as a DEL, so replicas
+    /// and AOF replays drop them without contacting the target themselves.
+    fn handle_migrate(&self, parts: &[RespFrame], db: usize) -> Result<RespFrame> {
+        if self.replication.is_replica() {
+            return Ok(RespFrame::error(ReadOnlyScript::REPLICA));
+        }
         
-        // Handle negative expire values (Redis standard behavior: delete immediately)
-        if seconds <= 0 {
-            let deleted = self.storage.delete(db, key)?;
-            Ok(RespFrame::Integer(if deleted { 1 } else { 0 }))
-        } else {
-            let result = self.storage.expire(db, key, Duration::from_secs(seconds as u64))?;
-            Ok(RespFrame::Integer(if result { 1 } else { 0 }))
+        let asking = self.cluster.is_enabled();
+        let (reply, moved) = crate::storage::commands::migrate::handle_migrate(&self.storage, db, parts, asking)?;
+        if !moved.is_empty() {
+            self.record_change();
+            let mut command = vec![b"DEL".to_vec()];
+            command.extend(moved);
+            self.propagate_served(db, command);
         }
+        Ok(reply)
     }
     
     /// Handle TTL command
@@ -2824,6 +3868,11 @@ impl Server {
             _ => return Ok(RespFrame::error("ERR invalid database index format")),
         };
         
+        // A cluster only has database 0
+        if db_index != 0 && self.cluster.is_enabled() {
+            return Ok(RespFrame::error("ERR SELECT is not allowed in cluster mode"));
+        }
+        
         // Update connection's database index
         self.connections.with_connection(conn_id, |conn| {
             conn.db_index = db_index;
@@ -2866,9 +3915,7 @@ impl Server {
             return Ok(RespFrame::error("ERR wrong number of arguments for 'dbsize' command"));
         }
         
-        let keys = self.storage.get_all_keys(db)?;
-        let size = keys.len() as i64;
-        Ok(RespFrame::Integer(size))
+        Ok(RespFrame::Integer(self.storage.dbsize(db)? as i64))
     }
     
     /// Handle SETNX command (set if not exists)
@@ -2887,13 +3934,8 @@ impl Server {
             _ => return Ok(RespFrame::error("ERR invalid value format")),
         };
         
-        // Check if key exists
-        if self.storage.exists(db, &key)? {
-            Ok(RespFrame::Integer(0)) // Key exists, not set
-        } else {
-            self.storage.set_string(db, key, value)?;
-            Ok(RespFrame::Integer(1)) // Key set
-        }
+        let set = self.storage.set_string_nx(db, key, value)?;
+        Ok(RespFrame::Integer(if set { 1 } else { 0 }))
     }
     
     /// Handle SETEX command (set with expiration in seconds)
@@ -2907,13 +3949,11 @@ impl Server {
             _ => return Ok(RespFrame::error("ERR invalid key format")),
         };
         
-        let seconds = match &parts[2] {
-            RespFrame::BulkString(Some(bytes)) => {
-                match String::from_utf8_lossy(bytes).parse::<u64>() {
-                    Ok(n) => n,
-                    Err(_) => return Ok(RespFrame::error("ERR value is not an integer or out of range")),
-                }
-            }
+        let ttl = match &parts[2] {
+            RespFrame::BulkString(Some(bytes)) => match CommandParser::parse_expire_time(bytes, "setex", false) {
+                Ok(ttl) => ttl,
+                Err(e) => return Ok(RespFrame::error(e.to_string())),
+            },
             _ => return Ok(RespFrame::error("ERR invalid expiration format")),
         };
         
@@ -2922,7 +3962,7 @@ impl Server {
             _ => return Ok(RespFrame::error("ERR invalid value format")),
         };
         
-        self.storage.set_string_ex(db, key, value, std::time::Duration::from_secs(seconds))?;
+        self.storage.set_string_ex(db, key, value, ttl)?;
         Ok(RespFrame::ok())
     }
     
@@ -2937,13 +3977,11 @@ impl Server {
             _ => return Ok(RespFrame::error("ERR invalid key format")),
         };
         
-        let millis = match &parts[2] {
-            RespFrame::BulkString(Some(bytes)) => {
-                match String::from_utf8_lossy(bytes).parse::<u64>() {
-                    Ok(n) => n,
-                    Err(_) => return Ok(RespFrame::error("ERR value is not an integer or out of range")),
-                }
-            }
+        let ttl = match &parts[2] {
+            RespFrame::BulkString(Some(bytes)) => match CommandParser::parse_expire_time(bytes, "psetex", true) {
+                Ok(ttl) => ttl,
+                Err(e) => return Ok(RespFrame::error(e.to_string())),
+            },
             _ => return Ok(RespFrame::error("ERR invalid expiration format")),
         };
         
@@ -2952,7 +3990,7 @@ impl Server {
             _ => return Ok(RespFrame::error("ERR invalid value format")),
         };
         
-        self.storage.set_string_ex(db, key, value, std::time::Duration::from_millis(millis))?;
+        self.storage.set_string_ex(db, key, value, ttl)?;
         Ok(RespFrame::ok())
     }
     
@@ -3021,58 +4059,7 @@ impl Server {
         if parts.len() < 3 {
             return Ok(RespFrame::error("ERR wrong number of arguments for 'blpop' command"));
         }
-        
-        // Parse timeout (last argument) - accept both integer and float values
-        let timeout = match &parts[parts.len() - 1] {
-            RespFrame::BulkString(Some(bytes)) => {
-                let timeout_str = String::from_utf8_lossy(bytes);
-                // Try parsing as float first to handle both integer and decimal values
-                match timeout_str.parse::<f64>() {
-                    Ok(t) if t < 0.0 => return Ok(RespFrame::error("ERR timeout is not a float or out of range")),
-                    Ok(0.0) => None, // 0 means block forever
-                    Ok(t) => Some(std::time::Duration::from_secs_f64(t)),
-                    Err(_) => return Ok(RespFrame::error("ERR timeout is not a float or out of range")),
-                }
-            }
-            _ => return Ok(RespFrame::error("ERR timeout is not a float or out of range")),
-        };
-        
-        // Extract keys (all arguments except command and timeout)
-        let mut keys = Vec::new();
-        for i in 1..parts.len()-1 {
-            match &parts[i] {
-                RespFrame::BulkString(Some(bytes)) => {
-                    keys.push(bytes.as_ref().clone());
-                }
-                _ => return Ok(RespFrame::error("ERR invalid key format")),
-            }
-        }
-        
-        // Try non-blocking first (fast path)
-        for key in &keys {
-            if let Some(value) = self.storage.lpop(db_index, key)? {
-                return Ok(RespFrame::Array(Some(vec![
-                    RespFrame::from_bytes(key.clone()),
-                    RespFrame::from_bytes(value),
-                ])));
-            }
-        }
-        
-        // No data available, register as blocked
-        let deadline = timeout.map(|t| Instant::now() + t);
-        self.blocking_manager.register_blocked(db_index, conn_id, keys.clone(), BlockingOp::BLPop, deadline)?;
-        
-        // Move connection to blocked state
-        self.connections.with_connection(conn_id, |conn| {
-            conn.state = ConnectionState::Blocked(BlockedState {
-                keys: keys.into_iter().map(|k| (db_index, k)).collect(),
-                deadline,
-                op_type: BlockingOp::BLPop,
-            });
-        });
-        
-        // Return the special NoResponse marker - this will not be sent to client
-        Ok(RespFrame::NoResponse)
+        self.handle_blocking_pop(parts, db_index, conn_id, BlockingOp::BLPop)
     }
     
     /// Handle BRPOP command (blocking right pop)  
@@ -3080,53 +4067,126 @@ impl Server {
         if parts.len() < 3 {
             return Ok(RespFrame::error("ERR wrong number of arguments for 'brpop' command"));
         }
+        self.handle_blocking_pop(parts, db_index, conn_id, BlockingOp::BRPop)
+    }
+    
+    /// BLPOP and BRPOP: keys followed by the timeout
+    fn handle_blocking_pop(&self, parts: &[RespFrame], db_index: usize, conn_id: u64, op: BlockingOp) -> Result<RespFrame> {
+        let Some(args) = Self::bulk_args(&parts[1..]) else {
+            return Ok(RespFrame::error("ERR invalid key format"));
+        };
+        let (keys, timeout) = args.split_at(args.len() - 1);
+        let timeout = match lists::parse_timeout(timeout[0]) {
+            Ok(timeout) => timeout,
+            Err(message) => return Ok(RespFrame::error(format!("ERR {}", message))),
+        };
+        let keys = keys.iter().map(|key| key.to_vec()).collect();
+        self.block_on_lists(db_index, conn_id, keys, op, timeout)
+    }
+    
+    /// Handle BLMOVE command (blocking LMOVE)
+    fn handle_blmove(&self, parts: &[RespFrame], db_index: usize, conn_id: u64) -> Result<RespFrame> {
+        if parts.len() != 6 {
+            return Ok(RespFrame::error("ERR wrong number of arguments for 'blmove' command"));
+        }
         
-        // Parse timeout (last argument) - accept both integer and float values
-        let timeout = match &parts[parts.len() - 1] {
-            RespFrame::BulkString(Some(bytes)) => {
-                let timeout_str = String::from_utf8_lossy(bytes);
-                // Try parsing as float first to handle both integer and decimal values
-                match timeout_str.parse::<f64>() {
-                    Ok(t) if t < 0.0 => return Ok(RespFrame::error("ERR timeout is not a float or out of range")),
-                    Ok(0.0) => None, // 0 means block forever
-                    Ok(t) => Some(std::time::Duration::from_secs_f64(t)),
-                    Err(_) => return Ok(RespFrame::error("ERR timeout is not a float or out of range")),
-                }
-            }
-            _ => return Ok(RespFrame::error("ERR timeout is not a float or out of range")),
+        let Some(args) = Self::bulk_args(&parts[1..]) else {
+            return Ok(RespFrame::error("ERR invalid argument format"));
+        };
+        let parsed = lists::parse_lmove_ends(args[2], args[3])
+            .and_then(|ends| Ok((ends, lists::parse_timeout(args[4])?)));
+        let ((from, to), timeout) = match parsed {
+            Ok(parsed) => parsed,
+            Err(message) => return Ok(RespFrame::error(format!("ERR {}", message))),
         };
+        let op = BlockingOp::BLMove { destination: args[1].to_vec(), from, to };
+        self.block_on_lists(db_index, conn_id, vec![args[0].to_vec()], op, timeout)
+    }
+    
+    /// Handle BRPOPLPUSH command (blocking RPOPLPUSH)
+    fn handle_brpoplpush(&self, parts: &[RespFrame], db_index: usize, conn_id: u64) -> Result<RespFrame> {
+        if parts.len() != 4 {
+            return Ok(RespFrame::error("ERR wrong number of arguments for 'brpoplpush' command"));
+        }
         
-        // Extract keys (all arguments except command and timeout)
-        let mut keys = Vec::new();
-        for i in 1..parts.len()-1 {
-            match &parts[i] {
-                RespFrame::BulkString(Some(bytes)) => {
-                    keys.push(bytes.as_ref().clone());
-                }
-                _ => return Ok(RespFrame::error("ERR invalid key format")),
-            }
+        let Some(args) = Self::bulk_args(&parts[1..]) else {
+            return Ok(RespFrame::error("ERR invalid argument format"));
+        };
+        let timeout = match lists::parse_timeout(args[2]) {
+            Ok(timeout) => timeout,
+            Err(message) => return Ok(RespFrame::error(format!("ERR {}", message))),
+        };
+        let op = BlockingOp::BLMove { destination: args[1].to_vec(), from: ListEnd::Right, to: ListEnd::Left };
+        self.block_on_lists(db_index, conn_id, vec![args[0].to_vec()], op, timeout)
+    }
+    
+    /// Handle BLMPOP command (blocking LMPOP)
+    fn handle_blmpop(&self, parts: &[RespFrame], db_index: usize, conn_id: u64) -> Result<RespFrame> {
+        if parts.len() < 5 {
+            return Ok(RespFrame::error("ERR wrong number of arguments for 'blmpop' command"));
         }
         
-        // Try non-blocking first (fast path)  
+        let Some(args) = Self::bulk_args(&parts[1..]) else {
+            return Ok(RespFrame::error("ERR invalid argument format"));
+        };
+        let parsed = lists::parse_timeout(args[0])
+            .and_then(|timeout| Ok((timeout, lists::parse_lmpop(&args[1..])?)));
+        let (timeout, pop) = match parsed {
+            Ok(parsed) => parsed,
+            Err(message) => return Ok(RespFrame::error(format!("ERR {}", message))),
+        };
+        let op = BlockingOp::BLMPop { end: pop.end, count: pop.count };
+        self.block_on_lists(db_index, conn_id, pop.keys, op, timeout)
+    }
+    
+    /// Serve a blocking list command from the first of its keys holding data,
+    /// or block the client on all of them until one does or the timeout passes
+    fn block_on_lists(&self, db_index: usize, conn_id: u64, keys: Vec<Vec<u8>>, op: BlockingOp, timeout: Option<Duration>) -> Result<RespFrame> {
+        // Try non-blocking first (fast path)
         for key in &keys {
-            if let Some(value) = self.storage.rpop(db_index, key)? {
-                return Ok(RespFrame::Array(Some(vec![
-                    RespFrame::from_bytes(key.clone()),
-                    RespFrame::from_bytes(value),
-                ])));
+            match self.serve_blocked(db_index, key, &op) {
+                Ok(Some(response)) => return Ok(response),
+                Ok(None) => {}
+                Err(e) => return Ok(Self::list_error_reply(e)),
             }
         }
-        
-        // No data available, register as blocked
+        self.block_client(db_index, conn_id, keys, op, timeout)
+    }
+    
+    /// Handle XREAD and XREADGROUP, which with BLOCK wait for new entries
+    /// when the read comes back empty
+    fn handle_stream_read(&self, parts: &[RespFrame], db_index: usize, conn_id: u64) -> Result<RespFrame> {
+        let blocking = Self::bulk_args(parts).and_then(|args| streams::parse_blocking_read(&self.storage, db_index, &args));
+        let response = self.read_streams(db_index, parts)?;
+        match blocking {
+            Some(read) if streams::is_empty_read(&response) => {
+                self.block_client(db_index, conn_id, read.keys, BlockingOp::XReadBlock(read.command), read.timeout)
+            }
+            _ => Ok(response),
+        }
+    }
+    
+    /// Run an XREAD or XREADGROUP without blocking
+    fn read_streams(&self, db_index: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+        match parts.first() {
+            Some(RespFrame::BulkString(Some(name))) if name.eq_ignore_ascii_case(b"XREADGROUP") => {
+                crate::storage::commands::consumer_groups::handle_xreadgroup(&self.storage, db_index, parts)
+            }
+            _ => streams::handle_xread(&self.storage, db_index, parts),
+        }
+    }
+    
+    /// Park a client on `keys` until a write wakes it or the timeout passes
+    fn block_client(&self, db_index: usize, conn_id: u64, keys: Vec<Vec<u8>>, op: BlockingOp, timeout: Option<Duration>) -> Result<RespFrame> {
         let deadline = timeout.map(|t| Instant::now() + t);
-        self.blocking_manager.register_blocked(db_index, conn_id, keys.clone(), BlockingOp::BRPop, deadline)?;
+        self.blocking_manager.register_blocked(db_index, conn_id, keys.clone(), op.clone(), deadline)?;
         
         // Move connection to blocked state
         self.connections.with_connection(conn_id, |conn| {
             conn.state = ConnectionState::Blocked(BlockedState {
                 keys: keys.into_iter().map(|k| (db_index, k)).collect(),
                 deadline,
-                op_type: BlockingOp::BRPop,
+                op_type: op,
             });
         });
         
@@ -3134,37 +4194,42 @@ impl Server {
         Ok(RespFrame::NoResponse)
     }
     
+    /// Bulk string arguments, or None if any argument is of another type
+    fn bulk_args(parts: &[RespFrame]) -> Option<Vec<&[u8]>> {
+        parts.iter()
+            .map(|part| match part {
+                RespFrame::BulkString(Some(bytes)) => Some(bytes.as_slice()),
+                _ => None,
+            })
+            .collect()
+    }
+    
     /// Handle RANDOMKEY command
     fn handle_randomkey(&self, parts: &[RespFrame], db: usize) -> Result<RespFrame> {
         if parts.len() != 1 {
             return Ok(RespFrame::error("ERR wrong number of arguments for 'randomkey' command"));
         }
         
-        let keys = self.storage.get_all_keys(db)?;
-        
-        if keys.is_empty() {
-            Ok(RespFrame::null_bulk()) // No keys in database
-        } else {
-            // Select random key
-            let random_index = rand::random::<usize>() % keys.len();
-            let random_key = &keys[random_index];
-            Ok(RespFrame::from_bytes(random_key.clone()))
+        match self.storage.random_key(db)? {
+            Some(key) => Ok(RespFrame::from_bytes(key)),
+            None => Ok(RespFrame::null_bulk()), // No keys in database
         }
     }
     
 
 
     /// Handle EVALSHA command with global script cache
-    fn handle_evalsha_command(&self, parts: &[RespFrame]) -> Result<RespFrame> {
+    fn handle_evalsha_command(&self, parts: &[RespFrame], db: usize, read_only: bool) -> Result<RespFrame> {
         if parts.len() < 3 {
-            return Ok(RespFrame::error("ERR wrong number of arguments for 'evalsha' command"));
+            let name = if read_only { "evalsha_ro" } else { "evalsha" };
+            return Ok(RespFrame::error(format!("ERR wrong number of arguments for '{}' command", name)));
         }
         
-        // Extract SHA1
+        // Extract SHA1 (matched case-insensitively, like Redis)
         let sha1 = match &parts[1] {
             RespFrame::BulkString(Some(bytes)) => {
                 match std::str::from_utf8(bytes) {
-                    Ok(s) => s.to_string(),
+                    Ok(s) => s.to_ascii_lowercase(),
                     Err(_) => return Ok(RespFrame::error("ERR invalid SHA1 hash")),
                 }
             }
@@ -3174,7 +4239,7 @@ impl Server {
         // Get script from global cache
         let script = match self.script_cache.get(&sha1) {
             Ok(Some(script)) => script,
-            Ok(None) => return Ok(RespFrame::error("NOSCRIPT No matching script. Please use EVAL.")),
+            Ok(None) => return Ok(crate::storage::commands::lua::script_error_reply(ScriptError::NotFound.into())),
             Err(e) => return Ok(RespFrame::error(format!("ERR script cache error: {}", e))),
         };
         
@@ -3185,12 +4250,27 @@ impl Server {
         ];
         eval_parts.extend_from_slice(&parts[2..]);
         
-        // Execute as EVAL
-        crate::storage::commands::lua::handle_eval(&self.storage, &eval_parts)
+        // Execute as EVAL (or EVAL_RO) in the client's database
+        if read_only {
+            crate::storage::commands::lua::handle_eval_ro_with_db(&self.storage, &eval_parts, db)
+        } else {
+            crate::storage::commands::lua::handle_eval_with_db(&self.storage, &eval_parts, db)
+        }
+    }
+    
+    /// Compute a reply on the worker pool, parking the connection until it is delivered
+    fn offload<F>(&self, conn_id: u64, work: F)
+    where
+        F: FnOnce() -> RespFrame + Send + 'static,
+    {
+        self.connections.with_connection(conn_id, |conn| {
+            conn.state = ConnectionState::AwaitingWorker;
+        });
+        self.worker_pool.submit(conn_id, work);
     }
     
     /// Handle SCRIPT command with global script cache
-    fn handle_script_command(&self, parts: &[RespFrame]) -> Result<RespFrame> {
+    fn handle_script_command(&self, parts: &[RespFrame], conn_id: u64) -> Result<RespFrame> {
         if parts.len() < 2 {
             return Ok(RespFrame::error("ERR wrong number of arguments for 'script' command"));
         }
@@ -3216,16 +4296,33 @@ impl Server {
                     parts[2].clone(),
                 ];
                 
-                match crate::storage::commands::lua::handle_script_load(&self.storage, &script_load_parts) {
-                    Ok((sha1, script)) => {
-                        if let Err(e) = self.script_cache.insert(sha1.clone(), script) {
-                            return Ok(RespFrame::error(format!("ERR failed to cache script: {}", e)));
+                let storage = Arc::clone(&self.storage);
+                let script_cache = Arc::clone(&self.script_cache);
+                let load = move || {
+                    match crate::storage::commands::lua::handle_script_load(&storage, &script_load_parts) {
+                        Ok((sha1, script)) => {
+                            if let Err(e) = script_cache.insert(sha1.clone(), script) {
+                                return RespFrame::error(format!("ERR failed to cache script: {}", e));
+                            }
+                            RespFrame::bulk_string(sha1)
                         }
-                        Ok(RespFrame::bulk_string(sha1))
+                        Err(e) => crate::storage::commands::lua::script_error_reply(e),
                     }
-                    Err(e) => Ok(RespFrame::error(format!("ERR {}", e))),
+                };
+                
+                // Compiling and hashing a large script happens off the event loop
+                let script_len = match &parts[2] {
+                    RespFrame::BulkString(Some(bytes)) => bytes.len(),
+                    _ => 0,
+                };
+                if script_len >= OFFLOAD_THRESHOLD_BYTES {
+                    self.offload(conn_id, load);
+                    return Ok(RespFrame::NoResponse);
                 }
+                
+                Ok(load())
             },
+            "loadlib" => Ok(crate::storage::commands::lua::handle_script_loadlib(parts)),
             "exists" => {
                 if parts.len() < 3 {
                     return Ok(RespFrame::error("ERR wrong number of arguments for 'script exists' command"));
@@ -3236,7 +4333,7 @@ impl Server {
                     let sha1 = match &parts[i] {
                         RespFrame::BulkString(Some(bytes)) => {
                             match std::str::from_utf8(bytes) {
-                                Ok(s) => s.to_string(),
+                                Ok(s) => s.to_ascii_lowercase(),
                                 Err(_) => {
                                     results.push(RespFrame::Integer(0));
                                     continue;
@@ -3262,16 +4359,35 @@ impl Server {
                     return Ok(RespFrame::error("ERR wrong number of arguments for 'script flush' command"));
                 }
                 
+                if let Ok(engine) = crate::storage::lua_engine::get_lua_engine(Arc::clone(&self.storage)) {
+                    engine.flush_precompiled();
+                }
+                crate::storage::lua_require::flush();
                 match self.script_cache.clear() {
-                    Ok(_) => Ok(RespFrame::SimpleString(std::sync::Arc::new(b"OK".to_vec()))),
+                    Ok(_) => Ok(RespFrame::ok()),
                     Err(e) => Ok(RespFrame::error(format!("ERR failed to flush scripts: {}", e))),
                 }
             },
-            "kill" => {
-                if parts.len() != 2 {
-                    return Ok(RespFrame::error("ERR wrong number of arguments for 'script kill' command"));
+            "kill" => Ok(crate::storage::commands::lua::handle_script_kill(parts)),
+            "check" => {
+                // Compile without caching, so CI can validate script files against the server
+                if parts.len() != 3 {
+                    return Ok(RespFrame::error("ERR wrong number of arguments for 'script check' command"));
+                }
+
+                let script = match &parts[2] {
+                    RespFrame::BulkString(Some(bytes)) => match std::str::from_utf8(bytes) {
+                        Ok(s) => s,
+                        Err(_) => return Ok(RespFrame::error("ERR invalid script - not valid UTF-8")),
+                    },
+                    _ => return Ok(RespFrame::error("ERR invalid script")),
+                };
+
+                let engine = crate::storage::lua_engine::get_lua_engine(Arc::clone(&self.storage))?;
+                match engine.compile(script) {
+                    Ok(_) => Ok(RespFrame::ok()),
+                    Err(e) => Ok(crate::storage::commands::lua::script_error_reply(e)),
                 }
-                Ok(RespFrame::SimpleString(std::sync::Arc::new(b"OK".to_vec())))
             },
             _ => Ok(RespFrame::error(format!("ERR Unknown subcommand '{}'", subcommand))),
         }
@@ -3284,10 +4400,12 @@ impl Server {
                 let response = crate::replication::handle_sync(&self.replication, &self.storage, &self.rdb_engine.as_ref().unwrap())?;
                 
                 // Get connection address and add replica
-                let conn_addr = self.connections.with_connection(conn_id, |conn| conn.addr)
-                    .ok_or_else(|| FerrousError::Connection("Connection not found".into()))?;
+                let (conn_addr, listening_port) = self.connections.with_connection(conn_id, |conn| {
+                    conn.is_replica = true;
+                    (conn.addr, conn.replica_listening_port)
+                }).ok_or_else(|| FerrousError::Connection("Connection not found".into()))?;
                 
-                let replica_info = crate::replication::ReplicaInfo::new(conn_id, conn_addr);
+                let replica_info = crate::replication::ReplicaInfo::with_listening_port(conn_id, conn_addr, listening_port);
                 self.replication.add_replica(replica_info)?;
                 
                 // Send RDB file to replica
@@ -3299,28 +4417,35 @@ impl Server {
                 Ok(response)
             }
             "PSYNC" => {
-                let response = crate::replication::handle_psync(parts, &self.replication, &self.storage, &self.rdb_engine.as_ref().unwrap())?;
+                let rdb_engine = self.rdb_engine.as_ref().unwrap();
+                let (response, backlog) = crate::replication::handle_psync_with_backlog(parts, &self.replication, &self.storage, rdb_engine)?;
+                let full_resync = matches!(&response, RespFrame::SimpleString(data) if data.starts_with(b"FULLRESYNC"));
+                if !full_resync && backlog.is_none() {
+                    return Ok(response);
+                }
                 
-                // Check if we need to send RDB (fullresync)
-                if let RespFrame::SimpleString(ref data) = response {
-                    let response_str = String::from_utf8_lossy(data);
-                    if response_str.starts_with("FULLRESYNC") {
-                        // Get connection address and add replica
-                        let conn_addr = self.connections.with_connection(conn_id, |conn| conn.addr)
-                            .ok_or_else(|| FerrousError::Connection("Connection not found".into()))?;
-                        
-                        let replica_info = crate::replication::ReplicaInfo::new(conn_id, conn_addr);
-                        self.replication.add_replica(replica_info)?;
-                        
-                        // Send RDB file to replica
-                        self.connections.with_connection(conn_id, |conn| -> Result<()> {
-                            crate::replication::sync::SyncProtocol::send_rdb_to_replica(conn, &self.storage, &self.rdb_engine.as_ref().unwrap())?;
-                            Ok(())
-                        }).ok_or_else(|| FerrousError::Connection("Connection not found".into()))??;
+                // Get connection address and add replica
+                let (conn_addr, listening_port) = self.connections.with_connection(conn_id, |conn| {
+                    conn.is_replica = true;
+                    (conn.addr, conn.replica_listening_port)
+                }).ok_or_else(|| FerrousError::Connection("Connection not found".into()))?;
+                
+                let replica_info = crate::replication::ReplicaInfo::with_listening_port(conn_id, conn_addr, listening_port);
+                self.replication.add_replica(replica_info)?;
+                
+                // The reply goes out first, then the RDB file or the missed backlog
+                self.connections.with_connection(conn_id, |conn| -> Result<()> {
+                    conn.send_frame(&response)?;
+                    match &backlog {
+                        Some(data) => {
+                            conn.send_raw(data)?;
+                            conn.flush()
+                        }
+                        None => crate::replication::sync::SyncProtocol::send_rdb_to_replica(conn, &self.storage, rdb_engine),
                     }
-                }
+                }).ok_or_else(|| FerrousError::Connection("Connection not found".into()))??;
                 
-                Ok(response)
+                Ok(RespFrame::NoResponse)
             }
             _ => unreachable!(),
         }
@@ -3367,6 +4492,9 @@ impl Server {
                     eprintln!("Error cleaning up subscriptions for connection {}: {}", id, e);
                 }
                 
+                // Forget the keys it was tracking
+                self.tracking.disable(id);
+                
                 // Clean up monitor subscription
                 if let Err(e) = self.monitor_subscribers.unsubscribe(id) {
                     eprintln!("Error cleaning up monitor subscription for connection {}: {}", id, e);
@@ -0,0 +1,283 @@
+//! Client-side caching support (CLIENT TRACKING)
+//!
+//! In the default mode the server remembers which keys each tracking client
+//! read, and tells it the first time one of them is modified afterwards; the
+//! key is then forgotten until the client reads it again. In BCAST mode
+//! clients register key prefixes instead, and hear about every modified key
+//! matching one of them, whether they read it or not.
+//!
+//! Invalidations reach RESP3 clients as `>2 invalidate [keys]` pushes. RESP2
+//! clients redirect them to a connection subscribed to `__redis__:invalidate`,
+//! where they arrive as pub/sub messages.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use crate::protocol::RespFrame;
+
+/// Channel RESP2 clients subscribe to for redirected invalidations
+pub const INVALIDATE_CHANNEL: &[u8] = b"__redis__:invalidate";
+
+/// Tracking settings of a client, from CLIENT TRACKING ON
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackingOptions {
+    /// Connection that receives the invalidations instead of this one
+    pub redirect: Option<u64>,
+    
+    /// Broadcast mode: invalidate by prefix rather than by keys read
+    pub bcast: bool,
+    
+    /// Prefixes followed in BCAST mode (none means every key)
+    pub prefixes: Vec<Vec<u8>>,
+    
+    /// Only track reads right after CLIENT CACHING YES
+    pub optin: bool,
+    
+    /// Track reads except right after CLIENT CACHING NO
+    pub optout: bool,
+    
+    /// Don't invalidate keys this client modified itself
+    pub noloop: bool,
+    
+    /// CLIENT CACHING answer, applying to the next command only
+    pub caching: Option<bool>,
+}
+
+impl TrackingOptions {
+    /// Whether the keys read by the current command are to be remembered
+    pub fn tracks_reads(&self) -> bool {
+        if self.bcast {
+            false
+        } else if self.optin {
+            self.caching == Some(true)
+        } else if self.optout {
+            self.caching != Some(false)
+        } else {
+            true
+        }
+    }
+}
+
+/// Keys read by tracking clients and prefixes followed in BCAST mode
+#[derive(Default)]
+pub struct TrackingTable {
+    /// Clients with tracking enabled
+    clients: Mutex<HashSet<u64>>,
+    
+    /// Clients to invalidate for each key read in default mode
+    keys: Mutex<HashMap<Vec<u8>, HashSet<u64>>>,
+    
+    /// Prefixes of each BCAST client
+    prefixes: Mutex<HashMap<u64, Vec<Vec<u8>>>>,
+}
+
+impl TrackingTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Whether any client has tracking enabled
+    pub fn is_active(&self) -> bool {
+        !self.clients.lock().unwrap().is_empty()
+    }
+    
+    /// Number of clients with tracking enabled
+    pub fn client_count(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+    
+    /// Number of keys remembered for default-mode clients
+    pub fn key_count(&self) -> usize {
+        self.keys.lock().unwrap().len()
+    }
+    
+    /// Start (or update) tracking for a client
+    pub fn enable(&self, client: u64, options: &TrackingOptions) {
+        self.clients.lock().unwrap().insert(client);
+        if options.bcast {
+            let prefixes = if options.prefixes.is_empty() {
+                vec![Vec::new()]
+            } else {
+                options.prefixes.clone()
+            };
+            self.prefixes.lock().unwrap().insert(client, prefixes);
+        }
+    }
+    
+    /// Stop tracking for a client, forgetting the keys it read
+    pub fn disable(&self, client: u64) {
+        if !self.clients.lock().unwrap().remove(&client) {
+            return;
+        }
+        self.prefixes.lock().unwrap().remove(&client);
+        self.keys.lock().unwrap().retain(|_, clients| {
+            clients.remove(&client);
+            !clients.is_empty()
+        });
+    }
+    
+    /// Remember keys a client read, to invalidate once they change
+    pub fn remember(&self, client: u64, keys: &[&[u8]]) {
+        let mut table = self.keys.lock().unwrap();
+        for key in keys {
+            table.entry(key.to_vec()).or_default().insert(client);
+        }
+    }
+    
+    /// Clients to notify for modified keys, with the keys each must drop
+    ///
+    /// Default-mode entries are consumed: a client hears about a key once per
+    /// read of it.
+    pub fn invalidate(&self, keys: &[&[u8]]) -> Vec<(u64, Vec<Vec<u8>>)> {
+        let mut invalidations: HashMap<u64, Vec<Vec<u8>>> = HashMap::new();
+        
+        {
+            let mut table = self.keys.lock().unwrap();
+            for key in keys {
+                for client in table.remove(*key).into_iter().flatten() {
+                    invalidations.entry(client).or_default().push(key.to_vec());
+                }
+            }
+        }
+        
+        for (client, prefixes) in self.prefixes.lock().unwrap().iter() {
+            for key in keys {
+                if prefixes.iter().any(|prefix| key.starts_with(prefix)) {
+                    let pending = invalidations.entry(*client).or_default();
+                    if !pending.iter().any(|k| k == key) {
+                        pending.push(key.to_vec());
+                    }
+                }
+            }
+        }
+        
+        invalidations.into_iter().collect()
+    }
+    
+    /// Forget every remembered key after a flush, returning all tracking clients
+    pub fn flush(&self) -> Vec<u64> {
+        self.keys.lock().unwrap().clear();
+        self.clients.lock().unwrap().iter().copied().collect()
+    }
+}
+
+/// Invalidation message for some keys, or for every key after a flush
+///
+/// RESP3 connections get a push; RESP2 redirect targets a pub/sub message.
+pub fn invalidation_frame(keys: Option<Vec<Vec<u8>>>, resp3: bool) -> RespFrame {
+    let keys = match keys {
+        Some(keys) => RespFrame::Array(Some(keys.into_iter().map(RespFrame::from_bytes).collect())),
+        None if resp3 => RespFrame::Null,
+        None => RespFrame::null_array(),
+    };
+    
+    if resp3 {
+        RespFrame::Push(vec![RespFrame::bulk_string("invalidate"), keys])
+    } else {
+        RespFrame::array(vec![
+            RespFrame::bulk_string("message"),
+            RespFrame::bulk_string(INVALIDATE_CHANNEL),
+            keys,
+        ])
+    }
+}
+
+/// Check that no prefix of a BCAST client is a prefix of another one
+///
+/// Returns the first overlapping pair.
+pub fn overlapping_prefixes(prefixes: &[Vec<u8>]) -> Option<(&[u8], &[u8])> {
+    for (i, a) in prefixes.iter().enumerate() {
+        for b in &prefixes[i + 1..] {
+            if a.starts_with(b) || b.starts_with(a) {
+                return Some((a, b));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_default_mode_invalidates_once() {
+        let table = TrackingTable::new();
+        table.enable(1, &TrackingOptions::default());
+        table.remember(1, &[b"foo", b"bar"]);
+        assert_eq!(table.key_count(), 2);
+        
+        assert_eq!(table.invalidate(&[b"foo"]), vec![(1, vec![b"foo".to_vec()])]);
+        assert!(table.invalidate(&[b"foo"]).is_empty());
+        assert_eq!(table.key_count(), 1);
+        
+        table.disable(1);
+        assert!(!table.is_active());
+        assert_eq!(table.key_count(), 0);
+    }
+    
+    #[test]
+    fn test_bcast_prefixes() {
+        let table = TrackingTable::new();
+        let options = TrackingOptions {
+            bcast: true,
+            prefixes: vec![b"user:".to_vec(), b"obj:".to_vec()],
+            ..Default::default()
+        };
+        table.enable(7, &options);
+        
+        let invalidations = table.invalidate(&[b"user:1", b"other", b"obj:2"]);
+        assert_eq!(invalidations, vec![(7, vec![b"user:1".to_vec(), b"obj:2".to_vec()])]);
+        
+        // Without prefixes every key matches, and nothing is consumed
+        table.enable(8, &TrackingOptions { bcast: true, ..Default::default() });
+        assert_eq!(table.invalidate(&[b"other"]), vec![(8, vec![b"other".to_vec()])]);
+        assert_eq!(table.invalidate(&[b"other"]), vec![(8, vec![b"other".to_vec()])]);
+    }
+    
+    #[test]
+    fn test_flush_and_opt_modes() {
+        let table = TrackingTable::new();
+        table.enable(1, &TrackingOptions::default());
+        table.enable(2, &TrackingOptions { bcast: true, ..Default::default() });
+        table.remember(1, &[b"foo"]);
+        
+        let mut clients = table.flush();
+        clients.sort();
+        assert_eq!(clients, vec![1, 2]);
+        assert_eq!(table.key_count(), 0);
+        
+        let mut optin = TrackingOptions { optin: true, ..Default::default() };
+        assert!(!optin.tracks_reads());
+        optin.caching = Some(true);
+        assert!(optin.tracks_reads());
+        
+        let mut optout = TrackingOptions { optout: true, ..Default::default() };
+        assert!(optout.tracks_reads());
+        optout.caching = Some(false);
+        assert!(!optout.tracks_reads());
+    }
+    
+    #[test]
+    fn test_overlapping_prefixes() {
+        let prefixes = vec![b"a:".to_vec(), b"b:".to_vec()];
+        assert!(overlapping_prefixes(&prefixes).is_none());
+        
+        let prefixes = vec![b"user".to_vec(), b"obj".to_vec(), b"user:1".to_vec()];
+        assert_eq!(overlapping_prefixes(&prefixes), Some((&b"user"[..], &b"user:1"[..])));
+    }
+    
+    #[test]
+    fn test_invalidation_frames() {
+        let push = invalidation_frame(Some(vec![b"foo".to_vec()]), true);
+        assert_eq!(push, RespFrame::Push(vec![
+            RespFrame::bulk_string("invalidate"),
+            RespFrame::array(vec![RespFrame::bulk_string("foo")]),
+        ]));
+        
+        let RespFrame::Array(Some(message)) = invalidation_frame(None, false) else {
+            panic!("expected a pub/sub message");
+        };
+        assert_eq!(message[1], RespFrame::bulk_string(INVALIDATE_CHANNEL));
+        assert!(message[2].is_null());
+    }
+}
@@ -0,0 +1,116 @@
+//! Worker pool for CPU-heavy command work
+//!
+//! The event loop is single-threaded, so a command that spends milliseconds
+//! hashing or serializing a large payload stalls every other client. Such work
+//! is submitted here instead: the issuing connection is parked, a worker thread
+//! computes the reply, and the event loop picks it up from the completion queue
+//! and sends it while other clients keep being served.
+
+use std::thread;
+use crossbeam::channel::{self, Receiver, Sender};
+use crate::protocol::RespFrame;
+
+/// Payloads at or above this size are computed on the worker pool
+pub const OFFLOAD_THRESHOLD_BYTES: usize = 64 * 1024;
+
+/// Default number of worker threads
+const DEFAULT_WORKERS: usize = 2;
+
+/// Work producing the reply for a parked connection
+type Work = Box<dyn FnOnce() -> RespFrame + Send + 'static>;
+
+/// A unit of offloaded work
+struct Job {
+    conn_id: u64,
+    work: Work,
+}
+
+/// A finished job waiting to be delivered by the event loop
+pub struct Completion {
+    /// Connection that issued the command
+    pub conn_id: u64,
+    
+    /// Reply to send to the connection
+    pub response: RespFrame,
+}
+
+/// Fixed-size pool of worker threads with a completion queue
+pub struct WorkerPool {
+    jobs: Sender<Job>,
+    completions: Receiver<Completion>,
+}
+
+impl WorkerPool {
+    /// Create a pool with the given number of worker threads
+    pub fn new(workers: usize) -> Self {
+        let (job_tx, job_rx) = channel::unbounded::<Job>();
+        let (done_tx, done_rx) = channel::unbounded::<Completion>();
+        
+        for i in 0..workers.max(1) {
+            let job_rx = job_rx.clone();
+            let done_tx = done_tx.clone();
+            thread::Builder::new()
+                .name(format!("ferrous-worker-{}", i))
+                .spawn(move || {
+                    // Exits once the pool (and with it the job sender) is dropped
+                    for job in job_rx {
+                        let response = (job.work)();
+                        if done_tx.send(Completion { conn_id: job.conn_id, response }).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .expect("failed to spawn worker thread");
+        }
+        
+        WorkerPool {
+            jobs: job_tx,
+            completions: done_rx,
+        }
+    }
+    
+    /// Queue work whose result should be delivered to `conn_id`
+    pub fn submit<F>(&self, conn_id: u64, work: F)
+    where
+        F: FnOnce() -> RespFrame + Send + 'static,
+    {
+        // Workers only stop when the pool itself is dropped, so this cannot fail
+        let _ = self.jobs.send(Job { conn_id, work: Box::new(work) });
+    }
+    
+    /// Take all completions that are ready, without blocking
+    pub fn drain_completions(&self) -> Vec<Completion> {
+        self.completions.try_iter().collect()
+    }
+}
+
+impl Default for WorkerPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_WORKERS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+    
+    #[test]
+    fn test_completions_are_delivered() {
+        let pool = WorkerPool::new(2);
+        pool.submit(1, || RespFrame::Integer(1));
+        pool.submit(2, || RespFrame::Integer(2));
+        
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut completions = Vec::new();
+        while completions.len() < 2 && Instant::now() < deadline {
+            completions.extend(pool.drain_completions());
+            thread::sleep(Duration::from_millis(1));
+        }
+        
+        completions.sort_by_key(|c| c.conn_id);
+        assert_eq!(completions.len(), 2);
+        assert_eq!(completions[0].response, RespFrame::Integer(1));
+        assert_eq!(completions[1].response, RespFrame::Integer(2));
+    }
+}
@@ -6,6 +6,7 @@
 pub mod resp;
 pub mod parser;
 pub mod serializer;
+pub mod shared;
 
 pub use resp::RespFrame;
 pub use parser::RespParser;
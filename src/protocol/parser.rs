@@ -3,15 +3,47 @@
 //! Provides efficient parsing of RESP2 and RESP3 protocol frames with zero-copy
 //! optimizations where possible.
 
+use std::io::Read;
 use std::sync::Arc;
 use crate::error::{FerrousError, Result};
 use super::resp::RespFrame;
 
+/// Bulk strings at least this long are read into a buffer of their own
+///
+/// Like Redis' big arguments: the payload is received straight into a buffer
+/// sized for it, which then becomes the argument (and a stored value) without
+/// being copied.
+pub const BIG_BULK_LEN: usize = 32 * 1024;
+
+/// Longest bulk string a request may carry, as Redis' proto-max-bulk-len
+pub const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// Bytes asked of a reader per read, outside big bulk strings
+const READ_CHUNK: usize = 16 * 1024;
+
+/// Request array whose arguments are still arriving
+#[derive(Clone)]
+struct PendingRequest {
+    /// Arguments parsed so far
+    args: Vec<RespFrame>,
+    
+    /// Arguments still to come
+    remaining: usize,
+    
+    /// Length of the big bulk string at the start of the buffer, once its
+    /// header was consumed
+    big_bulk: Option<usize>,
+}
+
 /// Parser state for incremental RESP parsing
+///
+/// Requests are parsed argument by argument across reads, so a request split
+/// over many reads isn't parsed again from the start each time.
 #[derive(Clone)]
 pub struct RespParser {
     buffer: Vec<u8>,
     position: usize,
+    request: Option<PendingRequest>,
 }
 
 impl RespParser {
@@ -20,6 +52,7 @@ impl RespParser {
         RespParser {
             buffer: Vec::with_capacity(4096),
             position: 0,
+            request: None,
         }
     }
     
@@ -28,8 +61,32 @@ impl RespParser {
         self.buffer.extend_from_slice(data);
     }
     
+    /// Read from `reader` straight into the parse buffer
+    ///
+    /// While a big bulk string is arriving only its missing bytes are asked
+    /// for, so it fills its own buffer exactly. Returns the bytes read and
+    /// whether the reader filled all the room it was given, hinting that more
+    /// is waiting.
+    pub fn read_from<R: Read>(&mut self, reader: &mut R) -> std::io::Result<(usize, bool)> {
+        let room = match self.request.as_ref().and_then(|request| request.big_bulk) {
+            Some(len) => (len + 2).saturating_sub(self.buffer.len()).max(1),
+            None => READ_CHUNK,
+        };
+        
+        let start = self.buffer.len();
+        self.buffer.resize(start + room, 0);
+        let result = reader.read(&mut self.buffer[start..]);
+        let read = *result.as_ref().unwrap_or(&0);
+        self.buffer.truncate(start + read);
+        result.map(|n| (n, n == room))
+    }
+    
     /// Try to parse a complete frame from the buffer
     pub fn parse(&mut self) -> Result<Option<RespFrame>> {
+        if self.request.is_some() {
+            return self.parse_request();
+        }
+        
         if self.position >= self.buffer.len() {
             return Ok(None);
         }
@@ -67,36 +124,162 @@ impl RespParser {
             ]))));
         }
         
+        // Requests are arrays: parse them argument by argument
+        if self.buffer[self.position] == b'*' {
+            let Some((line, consumed)) = parse_line(&self.buffer[self.position..], 1)? else {
+                return Ok(None);
+            };
+            let count = parse_length(line, "array")?;
+            if count > 0 {
+                self.position += consumed;
+                self.request = Some(PendingRequest {
+                    args: Vec::with_capacity(count.min(1024) as usize),
+                    remaining: count as usize,
+                    big_bulk: None,
+                });
+                return self.parse_request();
+            }
+        }
+        
         // Handle normal RESP protocol
         match parse_frame(&self.buffer[self.position..])? {
             Some((frame, consumed)) => {
                 self.position += consumed;
-                
-                // Skip any trailing extra CRLF sequences after the parsed frame
-                while self.position < self.buffer.len() && 
-                      (self.buffer[self.position] == b'\r' || 
-                       self.buffer[self.position] == b'\n') {
-                    self.position += 1;
-                }
-                
-                // If we've consumed more than half the buffer, compact it
-                if self.position > self.buffer.len() / 2 {
-                    self.buffer.drain(..self.position);
-                    self.position = 0;
-                }
+                self.finish_frame();
                 Ok(Some(frame))
             }
             None => Ok(None),
         }
     }
     
+    /// Parse the arguments of the pending request that have arrived, and
+    /// return the request once it is complete
+    fn parse_request(&mut self) -> Result<Option<RespFrame>> {
+        let Some(mut request) = self.request.take() else {
+            return Ok(None);
+        };
+        
+        if self.parse_arguments(&mut request)? {
+            self.finish_frame();
+            Ok(Some(RespFrame::Array(Some(request.args))))
+        } else {
+            self.request = Some(request);
+            Ok(None)
+        }
+    }
+    
+    /// Parse arguments of `request` until it is complete (true) or the
+    /// buffer runs out (false)
+    fn parse_arguments(&mut self, request: &mut PendingRequest) -> Result<bool> {
+        while request.remaining > 0 {
+            if let Some(len) = request.big_bulk {
+                // The payload starts the buffer, which was sized for it
+                if self.buffer.len() < len + 2 {
+                    return Ok(false);
+                }
+                if &self.buffer[len..len + 2] != b"\r\n" {
+                    return Err(FerrousError::Protocol("Missing CRLF after bulk string".into()));
+                }
+                let rest = self.buffer.split_off(len + 2);
+                let mut payload = std::mem::replace(&mut self.buffer, rest);
+                payload.truncate(len);
+                request.args.push(RespFrame::BulkString(Some(Arc::new(payload))));
+                request.big_bulk = None;
+                request.remaining -= 1;
+                continue;
+            }
+            
+            let data = &self.buffer[self.position..];
+            if data.is_empty() {
+                return Ok(false);
+            }
+            
+            if data[0] != b'$' {
+                // Not a plain argument: parse it as any frame
+                match parse_frame(data)? {
+                    Some((frame, consumed)) => {
+                        self.position += consumed;
+                        request.args.push(frame);
+                        request.remaining -= 1;
+                        continue;
+                    }
+                    None => return Ok(false),
+                }
+            }
+            
+            let Some((line, header)) = parse_line(data, 1)? else {
+                return Ok(false);
+            };
+            let len = parse_length(line, "bulk string")?;
+            if len == -1 {
+                self.position += header;
+                request.args.push(RespFrame::BulkString(None));
+                request.remaining -= 1;
+                continue;
+            }
+            if len < 0 {
+                return Err(FerrousError::Protocol("Invalid negative bulk string length".into()));
+            }
+            
+            let len = len as usize;
+            if len > MAX_BULK_LEN {
+                // Big bulk strings are allocated upfront: refuse absurd lengths
+                return Err(FerrousError::Protocol("Invalid bulk string length".into()));
+            }
+            if len >= BIG_BULK_LEN && data.len() < header + len + 2 {
+                // Move what arrived of the payload to the start of the buffer
+                // and let the rest be read right after it
+                self.buffer.drain(..self.position + header);
+                self.position = 0;
+                self.buffer.reserve_exact((len + 2).saturating_sub(self.buffer.len()));
+                request.big_bulk = Some(len);
+                continue;
+            }
+            
+            match parse_bulk_string(data)? {
+                Some((frame, consumed)) => {
+                    self.position += consumed;
+                    request.args.push(frame);
+                    request.remaining -= 1;
+                }
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+    
+    /// Move past the frame just parsed, reclaiming the consumed bytes now and then
+    fn finish_frame(&mut self) {
+        // Skip any trailing extra CRLF sequences after the parsed frame
+        while self.position < self.buffer.len() && 
+              (self.buffer[self.position] == b'\r' || 
+               self.buffer[self.position] == b'\n') {
+            self.position += 1;
+        }
+        
+        // If we've consumed more than half the buffer, compact it
+        if self.position > self.buffer.len() / 2 {
+            self.buffer.drain(..self.position);
+            self.position = 0;
+        }
+    }
+    
     /// Clear the parser buffer
     pub fn clear(&mut self) {
         self.buffer.clear();
         self.position = 0;
+        self.request = None;
     }
 }
 
+/// Parse the length in an array or bulk string header
+fn parse_length(line: &[u8], kind: &str) -> Result<i64> {
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|len| len.parse::<i64>().ok())
+        .ok_or_else(|| FerrousError::Protocol(format!("Invalid {} length", kind)))
+}
+
 /// Parse a RESP frame from a byte slice
 /// Returns Some((frame, bytes_consumed)) if a complete frame is found
 pub fn parse_resp_frame(data: &[u8]) -> Result<Option<(RespFrame, usize)>> {
@@ -120,6 +303,7 @@ fn parse_frame(data: &[u8]) -> Result<Option<(RespFrame, usize)>> {
         b',' => parse_double(data),
         b'%' => parse_map(data),
         b'~' => parse_set(data),
+        b'>' => parse_push(data),
         _ => Err(FerrousError::Protocol(format!(
             "Invalid RESP type byte: {}", data[0] as char
         ))),
@@ -309,15 +493,25 @@ fn parse_map(data: &[u8]) -> Result<Option<(RespFrame, usize)>> {
 
 /// Parse set (RESP3): ~2\r\n+elem1\r\n+elem2\r\n
 fn parse_set(data: &[u8]) -> Result<Option<(RespFrame, usize)>> {
+    Ok(parse_elements(data, "set")?.map(|(elements, consumed)| (RespFrame::Set(elements), consumed)))
+}
+
+/// Parse push (RESP3): >2\r\n+invalidate\r\n*1\r\n$3\r\nkey\r\n
+fn parse_push(data: &[u8]) -> Result<Option<(RespFrame, usize)>> {
+    Ok(parse_elements(data, "push")?.map(|(elements, consumed)| (RespFrame::Push(elements), consumed)))
+}
+
+/// Parse the length header and elements of a set or push frame
+fn parse_elements(data: &[u8], kind: &str) -> Result<Option<(Vec<RespFrame>, usize)>> {
     let (len_line, header_consumed) = match parse_line(data, 1)? {
         Some(v) => v,
         None => return Ok(None),
     };
     
     let len_str = std::str::from_utf8(len_line)
-        .map_err(|_| FerrousError::Protocol("Invalid UTF-8 in set length".into()))?;
+        .map_err(|_| FerrousError::Protocol(format!("Invalid UTF-8 in {} length", kind)))?;
     let len = len_str.parse::<usize>()
-        .map_err(|_| FerrousError::Protocol("Invalid set length".into()))?;
+        .map_err(|_| FerrousError::Protocol(format!("Invalid {} length", kind)))?;
     
     let mut elements = Vec::with_capacity(len);
     let mut total_consumed = header_consumed;
@@ -332,7 +526,7 @@ fn parse_set(data: &[u8]) -> Result<Option<(RespFrame, usize)>> {
         }
     }
     
-    Ok(Some((RespFrame::Set(elements), total_consumed)))
+    Ok(Some((elements, total_consumed)))
 }
 
 /// Parse a line ending with \r\n
@@ -423,6 +617,17 @@ mod tests {
         assert!(matches!(result, Some((RespFrame::Boolean(false), 4))));
     }
     
+    #[test]
+    fn test_parse_push() {
+        let data = b">2\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nfoo\r\n";
+        let (frame, consumed) = parse_resp_frame(data).unwrap().unwrap();
+        assert_eq!(consumed, data.len());
+        assert_eq!(frame, RespFrame::Push(vec![
+            RespFrame::bulk_string("invalidate"),
+            RespFrame::array(vec![RespFrame::bulk_string("foo")]),
+        ]));
+    }
+    
     #[test]
     fn test_incremental_parsing() {
         let mut parser = RespParser::new();
@@ -436,4 +641,67 @@ mod tests {
         let frame = parser.parse().unwrap().unwrap();
         assert!(matches!(frame, RespFrame::Array(Some(arr)) if arr.len() == 2));
     }
+    
+    #[test]
+    fn test_requests_split_anywhere() {
+        let data = b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$-1\r\n*2\r\n:1\r\n$2\r\nab\r\n+OK\r\n";
+        let expected = vec![
+            RespFrame::array(vec![RespFrame::bulk_string("SET"), RespFrame::bulk_string("k"), RespFrame::null_bulk()]),
+            RespFrame::array(vec![RespFrame::Integer(1), RespFrame::bulk_string("ab")]),
+            RespFrame::SimpleString(Arc::new(b"OK".to_vec())),
+        ];
+        
+        for split in 0..=data.len() {
+            let mut parser = RespParser::new();
+            let mut frames = Vec::new();
+            for part in [&data[..split], &data[split..]] {
+                parser.feed(part);
+                while let Some(frame) = parser.parse().unwrap() {
+                    frames.push(frame);
+                }
+            }
+            assert_eq!(frames, expected, "split at {}", split);
+        }
+    }
+    
+    /// Reader handing out its data a few bytes at a time, like a socket
+    struct Trickle<'a>(&'a [u8]);
+    
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(7000);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+    
+    #[test]
+    fn test_big_bulk_gets_its_own_buffer() {
+        let value = vec![b'v'; BIG_BULK_LEN + 100];
+        let mut data = format!("*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n${}\r\n", value.len()).into_bytes();
+        data.extend_from_slice(&value);
+        data.extend_from_slice(b"\r\n*1\r\n$4\r\nPING\r\n");
+        
+        let mut parser = RespParser::new();
+        let mut reader = Trickle(&data);
+        let mut frames = Vec::new();
+        while parser.read_from(&mut reader).unwrap().0 > 0 {
+            while let Some(frame) = parser.parse().unwrap() {
+                frames.push(frame);
+            }
+        }
+        
+        assert_eq!(frames.len(), 2);
+        let RespFrame::Array(Some(args)) = &frames[0] else { panic!() };
+        let RespFrame::BulkString(Some(payload)) = &args[2] else { panic!() };
+        assert_eq!(payload.as_slice(), value.as_slice());
+        // Received in place: the buffer was sized for the payload and its CRLF
+        assert_eq!(payload.capacity(), value.len() + 2);
+        assert_eq!(frames[1], RespFrame::array(vec![RespFrame::bulk_string("PING")]));
+        
+        let mut parser = RespParser::new();
+        parser.feed(format!("*2\r\n$3\r\nGET\r\n${}\r\n", MAX_BULK_LEN + 1).as_bytes());
+        assert!(parser.parse().is_err());
+    }
 }
\ No newline at end of file
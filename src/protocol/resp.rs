@@ -43,6 +43,9 @@ pub enum RespFrame {
     
     /// Set: ~2\r\n+first\r\n+second\r\n
     Set(Vec<RespFrame>),
+    
+    /// Out-of-band push: >2\r\n+invalidate\r\n*1\r\n$3\r\nkey\r\n
+    Push(Vec<RespFrame>),
 }
 
 /// Simplified value type for internal use
@@ -71,9 +74,9 @@ pub enum RespValue {
 }
 
 impl RespFrame {
-    /// Create a simple string response
+    /// Create a simple string response (the shared +OK)
     pub fn ok() -> Self {
-        RespFrame::SimpleString(Arc::new(b"OK".to_vec()))
+        super::shared::ok()
     }
     
     /// Create a simple string response
@@ -87,8 +90,15 @@ impl RespFrame {
     }
     
     /// Create an error response
+    ///
+    /// Line breaks would end the error early and desync the client, so they
+    /// become spaces, as in Redis.
     pub fn error(msg: impl Into<Vec<u8>>) -> Self {
-        RespFrame::Error(Arc::new(msg.into()))
+        let mut msg = msg.into();
+        for byte in msg.iter_mut().filter(|byte| matches!(byte, b'\r' | b'\n')) {
+            *byte = b' ';
+        }
+        RespFrame::Error(Arc::new(msg))
     }
     
     /// Create a null bulk string (valid Redis response)
@@ -189,6 +199,8 @@ mod tests {
         
         let err = RespFrame::error("ERR test");
         assert!(matches!(err, RespFrame::Error(_)));
+        let RespFrame::Error(msg) = RespFrame::error("ERR line\r\nbreak") else { panic!() };
+        assert_eq!(msg.as_slice(), b"ERR line  break");
         
         let null = RespFrame::null_bulk();
         assert!(null.is_null());
@@ -4,11 +4,38 @@
 //! transmission.
 
 use std::io::Write;
+use std::ops::{Deref, DerefMut};
 use crate::error::Result;
 use super::resp::RespFrame;
+use super::shared;
+
+/// Write a RESP length or integer without going through a heap-allocated String
+fn write_decimal<W: Write>(writer: &mut W, n: i64) -> Result<()> {
+    let mut digits = [0u8; 20];
+    let mut pos = digits.len();
+    let mut value = n.unsigned_abs();
+    loop {
+        pos -= 1;
+        digits[pos] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    if n < 0 {
+        writer.write_all(b"-")?;
+    }
+    writer.write_all(&digits[pos..])?;
+    Ok(())
+}
 
 /// Serialize a RESP frame to a writer
 pub fn serialize_resp_frame<W: Write>(frame: &RespFrame, writer: &mut W) -> Result<()> {
+    if let Some(wire) = shared::serialized(frame) {
+        writer.write_all(wire)?;
+        return Ok(());
+    }
+    
     match frame {
         RespFrame::SimpleString(bytes) => {
             writer.write_all(b"+")?;
@@ -24,16 +51,21 @@ pub fn serialize_resp_frame<W: Write>(frame: &RespFrame, writer: &mut W) -> Resu
         
         RespFrame::Integer(n) => {
             writer.write_all(b":")?;
-            writer.write_all(n.to_string().as_bytes())?;
+            write_decimal(writer, *n)?;
             writer.write_all(b"\r\n")?;
         }
         
         RespFrame::BulkString(opt) => {
             match opt {
                 Some(bytes) => {
-                    writer.write_all(b"$")?;
-                    writer.write_all(bytes.len().to_string().as_bytes())?;
-                    writer.write_all(b"\r\n")?;
+                    match shared::bulk_header(bytes.len()) {
+                        Some(header) => writer.write_all(header)?,
+                        None => {
+                            writer.write_all(b"$")?;
+                            write_decimal(writer, bytes.len() as i64)?;
+                            writer.write_all(b"\r\n")?;
+                        }
+                    }
                     writer.write_all(bytes)?;
                     writer.write_all(b"\r\n")?;
                 }
@@ -46,9 +78,14 @@ pub fn serialize_resp_frame<W: Write>(frame: &RespFrame, writer: &mut W) -> Resu
         RespFrame::Array(opt) => {
             match opt {
                 Some(frames) => {
-                    writer.write_all(b"*")?;
-                    writer.write_all(frames.len().to_string().as_bytes())?;
-                    writer.write_all(b"\r\n")?;
+                    match shared::array_header(frames.len()) {
+                        Some(header) => writer.write_all(header)?,
+                        None => {
+                            writer.write_all(b"*")?;
+                            write_decimal(writer, frames.len() as i64)?;
+                            writer.write_all(b"\r\n")?;
+                        }
+                    }
                     for frame in frames {
                         serialize_resp_frame(frame, writer)?;
                     }
@@ -87,7 +124,7 @@ pub fn serialize_resp_frame<W: Write>(frame: &RespFrame, writer: &mut W) -> Resu
         
         RespFrame::Map(pairs) => {
             writer.write_all(b"%")?;
-            writer.write_all(pairs.len().to_string().as_bytes())?;
+            write_decimal(writer, pairs.len() as i64)?;
             writer.write_all(b"\r\n")?;
             for (key, value) in pairs {
                 serialize_resp_frame(key, writer)?;
@@ -97,7 +134,16 @@ pub fn serialize_resp_frame<W: Write>(frame: &RespFrame, writer: &mut W) -> Resu
         
         RespFrame::Set(elements) => {
             writer.write_all(b"~")?;
-            writer.write_all(elements.len().to_string().as_bytes())?;
+            write_decimal(writer, elements.len() as i64)?;
+            writer.write_all(b"\r\n")?;
+            for element in elements {
+                serialize_resp_frame(element, writer)?;
+            }
+        }
+        
+        RespFrame::Push(elements) => {
+            writer.write_all(b">")?;
+            write_decimal(writer, elements.len() as i64)?;
             writer.write_all(b"\r\n")?;
             for element in elements {
                 serialize_resp_frame(element, writer)?;
@@ -149,6 +195,75 @@ impl RespSerializer {
     }
 }
 
+/// Initial capacity of a connection's reply scratch buffer
+pub const SCRATCH_BASE_CAPACITY: usize = 16 * 1024;
+
+/// Consecutive small replies after which an oversized scratch buffer is shrunk
+pub const SCRATCH_SHRINK_AFTER: u32 = 32;
+
+/// Reusable reply buffer that keeps its allocation between requests
+///
+/// Replies are serialized into the same buffer for the lifetime of a
+/// connection. After a large reply the buffer stays grown, and is only shrunk
+/// back to its base capacity once `SCRATCH_SHRINK_AFTER` consecutive uses
+/// needed less than a quarter of it, so alternating big and small replies do
+/// not reallocate every time.
+#[derive(Debug)]
+pub struct ScratchBuffer {
+    buf: Vec<u8>,
+    base_capacity: usize,
+    small_uses: u32,
+}
+
+impl ScratchBuffer {
+    /// Create a scratch buffer with the given base capacity
+    pub fn with_capacity(base_capacity: usize) -> Self {
+        ScratchBuffer {
+            buf: Vec::with_capacity(base_capacity),
+            base_capacity,
+            small_uses: 0,
+        }
+    }
+    
+    /// Clear the buffer for the next request, shrinking it if it stayed oversized
+    pub fn recycle(&mut self) {
+        let used = self.buf.len();
+        self.buf.clear();
+        
+        let capacity = self.buf.capacity();
+        if capacity <= self.base_capacity || used > capacity / 4 {
+            self.small_uses = 0;
+            return;
+        }
+        
+        self.small_uses += 1;
+        if self.small_uses >= SCRATCH_SHRINK_AFTER {
+            self.buf.shrink_to(self.base_capacity);
+            self.small_uses = 0;
+        }
+    }
+}
+
+impl Default for ScratchBuffer {
+    fn default() -> Self {
+        ScratchBuffer::with_capacity(SCRATCH_BASE_CAPACITY)
+    }
+}
+
+impl Deref for ScratchBuffer {
+    type Target = Vec<u8>;
+    
+    fn deref(&self) -> &Vec<u8> {
+        &self.buf
+    }
+}
+
+impl DerefMut for ScratchBuffer {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
 /// Helper to create common responses
 pub struct ResponseBuilder;
 
@@ -235,6 +350,46 @@ mod tests {
         assert_eq!(result, b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n");
     }
     
+    #[test]
+    fn test_serialize_integer_extremes() {
+        assert_eq!(serialize_to_vec(&RespFrame::Integer(0)).unwrap(), b":0\r\n");
+        assert_eq!(serialize_to_vec(&RespFrame::Integer(i64::MIN)).unwrap(), b":-9223372036854775808\r\n");
+        assert_eq!(serialize_to_vec(&RespFrame::Integer(i64::MAX)).unwrap(), b":9223372036854775807\r\n");
+    }
+    
+    #[test]
+    fn test_scratch_buffer_hysteresis() {
+        let mut scratch = ScratchBuffer::with_capacity(64);
+        
+        // A large reply grows the buffer
+        serialize_resp_frame(&RespFrame::from_string("x".repeat(4096)), &mut *scratch).unwrap();
+        scratch.recycle();
+        let grown = scratch.capacity();
+        assert!(grown >= 4096);
+        
+        // It survives a run of small replies shorter than the shrink window
+        for _ in 0..SCRATCH_SHRINK_AFTER - 1 {
+            serialize_resp_frame(&RespFrame::ok(), &mut *scratch).unwrap();
+            scratch.recycle();
+        }
+        assert_eq!(scratch.capacity(), grown);
+        
+        // Another large reply resets the window
+        serialize_resp_frame(&RespFrame::from_string("x".repeat(4096)), &mut *scratch).unwrap();
+        scratch.recycle();
+        for _ in 0..SCRATCH_SHRINK_AFTER - 1 {
+            serialize_resp_frame(&RespFrame::ok(), &mut *scratch).unwrap();
+            scratch.recycle();
+        }
+        assert_eq!(scratch.capacity(), grown);
+        
+        // One more small reply completes the window and shrinks back
+        serialize_resp_frame(&RespFrame::ok(), &mut *scratch).unwrap();
+        scratch.recycle();
+        assert!(scratch.capacity() < grown);
+        assert!(scratch.is_empty());
+    }
+    
     #[test]
     fn test_resp_serializer() {
         let mut serializer = RespSerializer::new();
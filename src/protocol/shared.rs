@@ -0,0 +1,123 @@
+//! Shared reply objects
+//!
+//! The status replies most commands end with (+OK, +PONG, +QUEUED) are built
+//! from buffers allocated once, like Redis' shared.ok and friends, so replying
+//! doesn't allocate. The serializer writes these replies, the small integer
+//! replies (:0, :1) and short array and bulk headers from their pre-serialized
+//! form in a single write. String values get the same treatment from
+//! `storage::shared`, which shares the buffers of small integers.
+
+use std::sync::{Arc, OnceLock};
+use super::resp::{Bytes, RespFrame};
+
+/// Array and bulk string headers for lengths below this are pre-serialized
+pub const SHARED_HEADERS: usize = 32;
+
+/// Shared buffer of a status reply, allocated on first use
+struct SharedStatus {
+    text: &'static [u8],
+    wire: &'static [u8],
+    bytes: OnceLock<Bytes>,
+}
+
+impl SharedStatus {
+    const fn new(text: &'static [u8], wire: &'static [u8]) -> Self {
+        SharedStatus { text, wire, bytes: OnceLock::new() }
+    }
+    
+    fn bytes(&self) -> &Bytes {
+        self.bytes.get_or_init(|| Arc::new(self.text.to_vec()))
+    }
+    
+    fn frame(&self) -> RespFrame {
+        RespFrame::SimpleString(Arc::clone(self.bytes()))
+    }
+    
+    /// Whether `bytes` is this reply's shared buffer
+    fn is(&self, bytes: &Bytes) -> bool {
+        self.bytes.get().is_some_and(|shared| Arc::ptr_eq(shared, bytes))
+    }
+}
+
+static OK: SharedStatus = SharedStatus::new(b"OK", b"+OK\r\n");
+static PONG: SharedStatus = SharedStatus::new(b"PONG", b"+PONG\r\n");
+static QUEUED: SharedStatus = SharedStatus::new(b"QUEUED", b"+QUEUED\r\n");
+
+/// +OK
+pub fn ok() -> RespFrame {
+    OK.frame()
+}
+
+/// +PONG
+pub fn pong() -> RespFrame {
+    PONG.frame()
+}
+
+/// +QUEUED
+pub fn queued() -> RespFrame {
+    QUEUED.frame()
+}
+
+/// Serialized `*<len>\r\n` and `$<len>\r\n` headers for short lengths
+struct Headers {
+    array: Vec<Vec<u8>>,
+    bulk: Vec<Vec<u8>>,
+}
+
+static HEADERS: OnceLock<Headers> = OnceLock::new();
+
+fn headers() -> &'static Headers {
+    HEADERS.get_or_init(|| Headers {
+        array: (0..SHARED_HEADERS).map(|len| format!("*{}\r\n", len).into_bytes()).collect(),
+        bulk: (0..SHARED_HEADERS).map(|len| format!("${}\r\n", len).into_bytes()).collect(),
+    })
+}
+
+/// Pre-serialized `*<len>\r\n`, for short arrays
+pub fn array_header(len: usize) -> Option<&'static [u8]> {
+    headers().array.get(len).map(Vec::as_slice)
+}
+
+/// Pre-serialized `$<len>\r\n`, for short bulk strings
+pub fn bulk_header(len: usize) -> Option<&'static [u8]> {
+    headers().bulk.get(len).map(Vec::as_slice)
+}
+
+/// Wire form of `frame` if it is a shared reply
+pub fn serialized(frame: &RespFrame) -> Option<&'static [u8]> {
+    match frame {
+        RespFrame::SimpleString(bytes) => [&OK, &PONG, &QUEUED]
+            .into_iter()
+            .find(|status| status.is(bytes))
+            .map(|status| status.wire),
+        RespFrame::Integer(0) => Some(b":0\r\n"),
+        RespFrame::Integer(1) => Some(b":1\r\n"),
+        RespFrame::Integer(-1) => Some(b":-1\r\n"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::serializer::serialize_to_vec;
+    
+    #[test]
+    fn test_shared_replies() {
+        // Every +OK shares one buffer
+        let (RespFrame::SimpleString(a), RespFrame::SimpleString(b)) = (ok(), ok()) else { panic!() };
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(serialized(&ok()), Some(&b"+OK\r\n"[..]));
+        assert_eq!(serialized(&queued()), Some(&b"+QUEUED\r\n"[..]));
+        
+        // An equal reply built elsewhere is serialized the long way, to the same bytes
+        let built = RespFrame::SimpleString(Arc::new(b"PONG".to_vec()));
+        assert_eq!(serialized(&built), None);
+        assert_eq!(serialize_to_vec(&built).unwrap(), serialize_to_vec(&pong()).unwrap());
+        
+        assert_eq!(serialized(&RespFrame::Integer(1)), Some(&b":1\r\n"[..]));
+        assert_eq!(array_header(3), Some(&b"*3\r\n"[..]));
+        assert_eq!(bulk_header(31), Some(&b"$31\r\n"[..]));
+        assert_eq!(bulk_header(SHARED_HEADERS), None);
+    }
+}
@@ -358,6 +358,16 @@ impl PubSubManager {
         conn_subs.contains_key(&connection_id)
     }
     
+    /// Number of channels with at least one subscriber
+    pub fn channel_count(&self) -> usize {
+        self.channels.lock().unwrap().len()
+    }
+    
+    /// Number of patterns with at least one subscriber
+    pub fn pattern_count(&self) -> usize {
+        self.patterns.lock().unwrap().len()
+    }
+    
     /// Get the number of subscribers for a specific channel
     pub fn channel_subscriber_count(&self, channel: &[u8]) -> usize {
         let channel_subs = self.channels.lock().unwrap();
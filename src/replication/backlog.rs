@@ -1,4 +1,9 @@
 //! Replication backlog for partial resynchronization
+//!
+//! Offsets count bytes of the replication stream since the master took its
+//! current replication ID, the same unit as the master offset reported by
+//! FULLRESYNC and acknowledged with REPLCONF ACK. A replica that reconnects
+//! within the backlog is sent the bytes it missed instead of a new RDB.
 
 use std::sync::Mutex;
 use std::collections::VecDeque;
@@ -31,8 +36,8 @@ impl ReplicationBacklog {
         }
     }
     
-    /// Append a command to the backlog
-    pub fn append_command(&self, cmd: &RespFrame) -> Result<()> {
+    /// Append a command to the backlog, returning its length in bytes
+    pub fn append_command(&self, cmd: &RespFrame) -> Result<u64> {
         let mut serialized = Vec::new();
         serialize_resp_frame(cmd, &mut serialized)?;
         
@@ -41,6 +46,7 @@ impl ReplicationBacklog {
         let mut start_offset = self.start_offset.lock().unwrap();
         
         // Add to buffer
+        let len = serialized.len() as u64;
         for byte in serialized {
             if *current_size >= self.max_size {
                 // Remove oldest byte
@@ -53,10 +59,13 @@ impl ReplicationBacklog {
             buffer.push_back(byte);
         }
         
-        Ok(())
+        Ok(len)
     }
     
     /// Get data from a specific offset
+    ///
+    /// An offset at the end of the backlog yields no data: the replica has
+    /// everything. Offsets the backlog no longer (or never) held are an error.
     pub fn get_data_from_offset(&self, offset: u64) -> Result<Vec<u8>> {
         let buffer = self.buffer.lock().unwrap();
         let start_offset = self.start_offset.lock().unwrap();
@@ -65,7 +74,7 @@ impl ReplicationBacklog {
         // Check if offset is within our range
         let end_offset = *start_offset + *current_size as u64;
         
-        if offset < *start_offset || offset >= end_offset {
+        if offset < *start_offset || offset > end_offset {
             return Err(FerrousError::Command(
                 crate::error::CommandError::Generic("offset out of range".into())
             ));
@@ -83,13 +92,21 @@ impl ReplicationBacklog {
         Ok(data)
     }
     
-    /// Clear the backlog
+    /// Clear the backlog, keeping its end offset
     pub fn clear(&self) {
+        let end_offset = self.end_offset();
+        self.reset(end_offset);
+    }
+    
+    /// Clear the backlog and start it again at `offset`, for a new replication ID
+    pub fn reset(&self, offset: u64) {
         let mut buffer = self.buffer.lock().unwrap();
         let mut current_size = self.current_size.lock().unwrap();
+        let mut start_offset = self.start_offset.lock().unwrap();
         
         buffer.clear();
         *current_size = 0;
+        *start_offset = offset;
     }
     
     /// Get current backlog size
@@ -143,4 +160,25 @@ mod tests {
         // Size should be capped at max_size
         assert!(backlog.size() <= 10);
     }
+    
+    #[test]
+    fn test_backlog_offsets() {
+        let backlog = ReplicationBacklog::new(16);
+        let cmd = RespFrame::bulk_string(b"abc"); // $3\r\nabc\r\n
+        assert_eq!(backlog.append_command(&cmd).unwrap(), 9);
+        assert_eq!(backlog.append_command(&cmd).unwrap(), 9);
+        
+        // Two bytes fell off the front
+        assert_eq!((backlog.start_offset(), backlog.end_offset()), (2, 18));
+        assert_eq!(backlog.get_data_from_offset(9).unwrap(), b"$3\r\nabc\r\n");
+        assert!(backlog.get_data_from_offset(18).unwrap().is_empty());
+        assert!(backlog.get_data_from_offset(1).is_err());
+        assert!(backlog.get_data_from_offset(19).is_err());
+        
+        backlog.clear();
+        assert_eq!((backlog.start_offset(), backlog.end_offset()), (18, 18));
+        backlog.reset(0);
+        assert!(backlog.get_data_from_offset(0).unwrap().is_empty());
+        assert!(backlog.get_data_from_offset(18).is_err());
+    }
 }
\ No newline at end of file
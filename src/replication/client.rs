@@ -90,9 +90,7 @@ impl ReplicationClient {
     
     /// Helper function to get the master password from config
     fn get_master_password(&self) -> Option<String> {
-        // In a real implementation, this would come from a secure configuration
-        // For now, we'll hardcode it for testing
-        Some("mysecretpassword".to_string())
+        master_password()
     }
     
     /// Run the replication client
@@ -240,55 +238,47 @@ impl ReplicationClient {
     }
     
     /// Perform initial synchronization
+    ///
+    /// A replica that already followed this master asks to continue from its
+    /// offset; the master then sends only the missed part of the stream, or
+    /// falls back to a full resynchronization when its backlog no longer has it.
     fn perform_initial_sync(&self, stream: &mut TcpStream) -> Result<(String, u64)> {
-        // Send PSYNC command
-        println!("Replication client: Sending PSYNC ? -1");
-        self.send_command(stream, &["PSYNC", "?", "-1"])?;
+        // PSYNC takes one past the last byte processed, as in Redis
+        let resume = self.repl_manager.resume_point();
+        let (psync_id, psync_offset) = match &resume {
+            Some((repl_id, offset)) => (repl_id.clone(), (offset + 1).to_string()),
+            None => ("?".to_string(), "-1".to_string()),
+        };
+        println!("Replication client: Sending PSYNC {} {}", psync_id, psync_offset);
+        self.send_command(stream, &["PSYNC", &psync_id, &psync_offset])?;
         
-        // Read response (should be +FULLRESYNC <replid> <offset>)
-        let response = self.read_response(stream)?;
-        println!("Replication client: Received response to PSYNC: {:?}", response);
+        // Read only the reply line: the RDB file or the backlog follows on the same stream
+        let response_str = self.read_line(stream)?;
+        println!("Replication client: PSYNC response string: {}", response_str);
         
-        match response {
-            RespFrame::SimpleString(data) => {
-                let response_str = String::from_utf8_lossy(&data);
-                println!("Replication client: PSYNC response string: {}", response_str);
-                
-                if let Some(fullresync) = response_str.strip_prefix("FULLRESYNC ") {
-                    let parts: Vec<&str> = fullresync.split_whitespace().collect();
-                    if parts.len() == 2 {
-                        let repl_id = parts[0].to_string();
-                        let offset = parts[1].parse::<u64>()
-                            .map_err(|_| FerrousError::Protocol("Invalid offset in FULLRESYNC".into()))?;
-                            
-                        // Receive RDB file
-                        self.receive_rdb(stream)?;
-                        
-                        return Ok((repl_id, offset));
-                    } else {
-                        return Err(FerrousError::Protocol("Invalid FULLRESYNC response format".into()));
-                    }
-                } else {
-                    return Err(FerrousError::Protocol("Expected FULLRESYNC response".into()));
-                }
+        if let Some(fullresync) = response_str.strip_prefix("+FULLRESYNC ") {
+            let parts: Vec<&str> = fullresync.split_whitespace().collect();
+            if parts.len() != 2 {
+                return Err(FerrousError::Protocol("Invalid FULLRESYNC response format".into()));
             }
-            // Handle case where the master sends the RDB directly as a bulk string
-            RespFrame::BulkString(Some(data)) => {
-                println!("Replication client: Received RDB data directly ({} bytes)", data.len());
-                
-                // The RDB data is already in the bulk string, no need to read separately
-                println!("Replication client: Processing direct RDB data");
-                
-                // In a real implementation, we would parse and load the RDB data here
-                // For now, we'll just use default values
-                
-                // Return default replication ID and offset
-                let default_repl_id = "ferrous-repl-id".to_string();
-                let default_offset = 0;
-                
-                return Ok((default_repl_id, default_offset));
-            }
-            _ => return Err(FerrousError::Protocol(format!("Invalid PSYNC response type: {:?}", response))),
+            let repl_id = parts[0].to_string();
+            let offset = parts[1].parse::<u64>()
+                .map_err(|_| FerrousError::Protocol("Invalid offset in FULLRESYNC".into()))?;
+            
+            // Receive RDB file
+            self.receive_rdb(stream)?;
+            
+            Ok((repl_id, offset))
+        } else if let Some(continued) = response_str.strip_prefix("+CONTINUE") {
+            let (repl_id, offset) = resume
+                .ok_or_else(|| FerrousError::Protocol("CONTINUE without a replication offset".into()))?;
+            
+            // A new ID continues the same stream under the promoted master's name
+            let repl_id = continued.split_whitespace().next().map_or(repl_id, str::to_string);
+            println!("Replication client: Continuing from offset {}", offset);
+            Ok((repl_id, offset))
+        } else {
+            Err(FerrousError::Protocol(format!("Unexpected PSYNC response: {}", response_str)))
         }
     }
     
@@ -458,6 +448,12 @@ impl ReplicationClient {
                         if let Err(e) = self.process_replication_command(&frame) {
                             eprintln!("Replication client: Error processing command: {}", e);
                         }
+                        
+                        // The offset counts bytes of the stream, as the master's does
+                        let mut bytes = Vec::new();
+                        serialize_resp_frame(&frame, &mut bytes)?;
+                        let current_offset = self.repl_manager.get_repl_offset();
+                        let _ = self.repl_manager.update_replica_offset(current_offset + bytes.len() as u64, None);
                     }
                 }
                 Err(e) if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut => {
@@ -514,16 +510,18 @@ impl ReplicationClient {
                 "RPUSH" => self.handle_replicated_rpush(parts)?,
                 "SADD" => self.handle_replicated_sadd(parts)?,
                 "HSET" => self.handle_replicated_hset(parts)?,
+                "HEXPIRE" | "HPEXPIRE" | "HEXPIREAT" | "HPEXPIREAT" | "HPERSIST" => self.handle_replicated_hash_field_ttl(parts)?,
                 "ZADD" => self.handle_replicated_zadd(parts)?,
-                // Other commands can be added as needed
+                "EVAL" => self.handle_replicated_eval(parts)?,
+                "SCRIPT" => self.handle_replicated_script(parts),
+                "FUNCTION" => self.handle_replicated_function(parts),
+                // Script effects arrive wrapped in MULTI/EXEC and are applied as they come
+                "MULTI" | "EXEC" => {}
+                name if crate::storage::commands::flags::is_write_command(name) => self.handle_replicated_write(parts),
                 _ => {
                     println!("Replication client: Unknown command {}, ignoring", command);
                 }
             }
-            
-            // Update replication offset
-            let current_offset = self.repl_manager.get_repl_offset();
-            let _ = self.repl_manager.update_replica_offset(current_offset + 1, None);
         }
         
         Ok(())
@@ -756,6 +754,78 @@ impl ReplicationClient {
         Ok(())
     }
     
+    /// Handle replicated hash field TTL commands (HEXPIRE family and HPERSIST)
+    fn handle_replicated_hash_field_ttl(&self, parts: &[RespFrame]) -> Result<()> {
+        let command = match &parts[0] {
+            RespFrame::BulkString(Some(cmd)) => String::from_utf8_lossy(cmd).to_uppercase(),
+            _ => return Ok(()),
+        };
+        
+        use crate::storage::commands::hashes;
+        match command.as_str() {
+            "HEXPIRE" => { hashes::handle_hexpire(&self.storage, 0, parts)?; }
+            "HPEXPIRE" => { hashes::handle_hpexpire(&self.storage, 0, parts)?; }
+            "HEXPIREAT" => { hashes::handle_hexpireat(&self.storage, 0, parts)?; }
+            "HPEXPIREAT" => { hashes::handle_hpexpireat(&self.storage, 0, parts)?; }
+            "HPERSIST" => { hashes::handle_hpersist(&self.storage, 0, parts)?; }
+            _ => {}
+        }
+        
+        Ok(())
+    }
+    
+    /// Handle replicated EVAL command by re-running the script
+    ///
+    /// Scripts that iterate tables with `pairs` only replay identically when
+    /// `lua-deterministic` is enabled on both master and replica.
+    fn handle_replicated_eval(&self, parts: &[RespFrame]) -> Result<()> {
+        let response = crate::storage::commands::lua::handle_eval_with_db(&self.storage, parts, 0)?;
+        if response.is_error() {
+            eprintln!("Replication client: replicated EVAL failed: {:?}", response);
+        }
+        Ok(())
+    }
+    
+    /// Apply any other write command, such as a script effect, through the command executor
+    fn handle_replicated_write(&self, parts: &[RespFrame]) {
+        let args: Option<Vec<Vec<u8>>> = parts.iter()
+            .map(|part| match part {
+                RespFrame::BulkString(Some(bytes)) => Some(bytes.to_vec()),
+                _ => None,
+            })
+            .collect();
+        let Some(args) = args else { return };
+        
+        let adapter = crate::storage::commands::executor::LuaCommandAdapter::new(self.storage.clone());
+        match adapter.execute(args, 0) {
+            Ok(reply) if reply.is_error() => eprintln!("Replication client: replicated write failed: {:?}", reply),
+            Ok(_) => {}
+            Err(e) => eprintln!("Replication client: replicated write failed: {}", e),
+        }
+    }
+    
+    /// Handle replicated SCRIPT LOADLIB, so replayed scripts can require the library
+    fn handle_replicated_script(&self, parts: &[RespFrame]) {
+        if crate::storage::commands::lua::is_script_loadlib(parts) {
+            let response = crate::storage::commands::lua::handle_script_loadlib(parts);
+            if response.is_error() {
+                eprintln!("Replication client: replicated SCRIPT LOADLIB failed: {:?}", response);
+            }
+        }
+    }
+    
+    /// Handle replicated FUNCTION LOAD, DELETE and FLUSH, so the replica has the master's libraries
+    fn handle_replicated_function(&self, parts: &[RespFrame]) {
+        if !crate::storage::commands::lua::is_function_write(parts) {
+            return;
+        }
+        match crate::storage::commands::lua::handle_function(&self.storage, parts) {
+            Ok(response) if response.is_error() => eprintln!("Replication client: replicated FUNCTION failed: {:?}", response),
+            Ok(_) => {}
+            Err(e) => eprintln!("Replication client: replicated FUNCTION failed: {}", e),
+        }
+    }
+    
     /// Handle replicated ZADD command
     fn handle_replicated_zadd(&self, parts: &[RespFrame]) -> Result<()> {
         if parts.len() < 4 || parts.len() % 2 != 0 {
@@ -802,6 +872,19 @@ impl ReplicationClient {
         Ok(())
     }
     
+    /// Read one reply line from the master, without reading past it
+    fn read_line(&self, stream: &mut TcpStream) -> Result<String> {
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        while !line.ends_with(b"\r\n") {
+            stream.read_exact(&mut byte)
+                .map_err(|e| FerrousError::Connection(format!("Read error: {}", e)))?;
+            line.push(byte[0]);
+        }
+        line.truncate(line.len() - 2);
+        Ok(String::from_utf8_lossy(&line).into_owned())
+    }
+    
     /// Read a response from the master
     fn read_response(&self, stream: &mut TcpStream) -> Result<RespFrame> {
         let mut parser = RespParser::new();
@@ -862,6 +945,13 @@ impl ReplicationClient {
     }
 }
 
+/// Password for connections to other servers of the replication setup
+pub(super) fn master_password() -> Option<String> {
+    // In a real implementation, this would come from a secure configuration
+    // For now, we'll hardcode it for testing
+    Some("mysecretpassword".to_string())
+}
+
 /// Start background replication with the given master
 pub fn start_background_replication(
     master_addr: SocketAddr,
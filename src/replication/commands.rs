@@ -2,11 +2,12 @@
 
 use std::sync::Arc;
 use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::Duration;
 use crate::error::{FerrousError, Result, CommandError};
 use crate::protocol::RespFrame;
 use crate::storage::{StorageEngine, RdbEngine};
 use crate::network::Connection;
-use super::ReplicationManager;
+use super::{ReplicationManager, FailoverRequest, FailoverState};
 
 /// Handle REPLICAOF command (modern version of SLAVEOF)
 pub fn handle_replicaof(
@@ -19,6 +20,11 @@ pub fn handle_replicaof(
         return Ok(RespFrame::error("ERR wrong number of arguments for 'replicaof' command"));
     }
     
+    // A failover moves this server to its target itself
+    if manager.failover_state() != FailoverState::NoFailover {
+        return Ok(RespFrame::error("ERR REPLICAOF not allowed while failing over."));
+    }
+    
     // Extract host
     let host = match &parts[1] {
         RespFrame::BulkString(Some(bytes)) => {
@@ -98,9 +104,17 @@ pub fn handle_replconf(
     
     match subcommand.as_str() {
         "LISTENING-PORT" => {
-            // Replica is telling us its listening port
-            // For now, just acknowledge
-            Ok(RespFrame::ok())
+            // Replica is telling us its listening port, kept to reach it on FAILOVER
+            match &parts[2] {
+                RespFrame::BulkString(Some(bytes)) => match String::from_utf8_lossy(bytes).parse::<u16>() {
+                    Ok(port) => {
+                        conn.replica_listening_port = Some(port);
+                        Ok(RespFrame::ok())
+                    }
+                    Err(_) => Ok(RespFrame::error("ERR invalid port")),
+                },
+                _ => Ok(RespFrame::error("ERR invalid port")),
+            }
         }
         
         "CAPA" => {
@@ -130,7 +144,8 @@ pub fn handle_replconf(
                 replica.touch();
             }
             
-            Ok(RespFrame::ok())
+            // Never answered: a reply would land in the replica's replication stream
+            Ok(RespFrame::NoResponse)
         }
         
         "GETACK" => {
@@ -170,8 +185,18 @@ pub fn handle_psync(
     storage: &Arc<StorageEngine>,
     rdb_engine: &Arc<RdbEngine>,
 ) -> Result<RespFrame> {
+    handle_psync_with_backlog(parts, manager, storage, rdb_engine).map(|(reply, _)| reply)
+}
+
+/// Handle PSYNC command, also returning the backlog bytes to send after +CONTINUE
+pub fn handle_psync_with_backlog(
+    parts: &[RespFrame],
+    manager: &Arc<ReplicationManager>,
+    storage: &Arc<StorageEngine>,
+    rdb_engine: &Arc<RdbEngine>,
+) -> Result<(RespFrame, Option<Vec<u8>>)> {
     if parts.len() != 3 {
-        return Ok(RespFrame::error("ERR wrong number of arguments for 'psync' command"));
+        return Ok((RespFrame::error("ERR wrong number of arguments for 'psync' command"), None));
     }
     
     // Extract replication ID
@@ -184,7 +209,7 @@ pub fn handle_psync(
                 Some(id.to_string())
             }
         }
-        _ => return Ok(RespFrame::error("ERR invalid replication ID format")),
+        _ => return Ok((RespFrame::error("ERR invalid replication ID format"), None)),
     };
     
     // Extract offset
@@ -196,11 +221,11 @@ pub fn handle_psync(
             } else {
                 match offset_str.parse::<u64>() {
                     Ok(o) => Some(o),
-                    Err(_) => return Ok(RespFrame::error("ERR invalid offset format")),
+                    Err(_) => return Ok((RespFrame::error("ERR invalid offset format"), None)),
                 }
             }
         }
-        _ => return Ok(RespFrame::error("ERR invalid offset format")),
+        _ => return Ok((RespFrame::error("ERR invalid offset format"), None)),
     };
     
     use super::sync::{SyncProtocol, PsyncResult};
@@ -209,12 +234,85 @@ pub fn handle_psync(
         PsyncResult::FullResync { repl_id, offset } => {
             // Return +FULLRESYNC <replid> <offset>
             let response = format!("FULLRESYNC {} {}", repl_id, offset);
-            Ok(RespFrame::SimpleString(Arc::new(response.into_bytes())))
+            Ok((RespFrame::SimpleString(Arc::new(response.into_bytes())), None))
+        }
+        PsyncResult::PartialResync { repl_id, backlog_data } => {
+            // Return +CONTINUE <replid>, then the missed part of the stream
+            let response = format!("CONTINUE {}", repl_id);
+            Ok((RespFrame::SimpleString(Arc::new(response.into_bytes())), Some(backlog_data)))
+        }
+    }
+}
+
+/// Handle FAILOVER command
+///
+/// `FAILOVER [TO host port [FORCE]] [ABORT] [TIMEOUT milliseconds]`. Replies as
+/// soon as the failover started; INFO reports its progress as
+/// `master_failover_state`.
+pub fn handle_failover(
+    parts: &[RespFrame],
+    manager: &Arc<ReplicationManager>,
+    storage: &Arc<StorageEngine>
+) -> Result<RespFrame> {
+    let args: Vec<String> = match parts[1..].iter().map(|part| match part {
+        RespFrame::BulkString(Some(bytes)) => Some(String::from_utf8_lossy(bytes).into_owned()),
+        _ => None,
+    }).collect() {
+        Some(args) => args,
+        None => return Ok(RespFrame::error("ERR syntax error")),
+    };
+    
+    let mut request = FailoverRequest::default();
+    let mut abort = false;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].to_uppercase().as_str() {
+            "TO" if request.target.is_none() && i + 2 < args.len() => {
+                let port = match args[i + 2].parse::<u16>() {
+                    Ok(port) => port,
+                    Err(_) => return Ok(RespFrame::error("ERR Invalid port")),
+                };
+                request.target = match (args[i + 1].as_str(), port).to_socket_addrs().ok().and_then(|mut addrs| addrs.next()) {
+                    Some(addr) => Some(addr),
+                    None => return Ok(RespFrame::error("ERR FAILOVER target HOST and PORT is not a replica.")),
+                };
+                i += 3;
+            }
+            "TIMEOUT" if request.timeout.is_none() && i + 1 < args.len() => {
+                match args[i + 1].parse::<i64>() {
+                    Ok(ms) if ms > 0 => request.timeout = Some(Duration::from_millis(ms as u64)),
+                    Ok(_) => return Ok(RespFrame::error("ERR FAILOVER timeout must be greater than 0")),
+                    Err(_) => return Ok(RespFrame::error("ERR value is not an integer or out of range")),
+                }
+                i += 2;
+            }
+            "FORCE" if !request.force => {
+                request.force = true;
+                i += 1;
+            }
+            "ABORT" if !abort => {
+                abort = true;
+                i += 1;
+            }
+            _ => return Ok(RespFrame::error("ERR syntax error")),
+        }
+    }
+    
+    let started = if abort {
+        if request.target.is_some() || request.timeout.is_some() || request.force {
+            return Ok(RespFrame::error("ERR FAILOVER abort can't be combined with other options"));
         }
-        PsyncResult::PartialResync { .. } => {
-            // Return +CONTINUE
-            Ok(RespFrame::SimpleString(Arc::new(b"CONTINUE".to_vec())))
+        manager.abort_failover()
+    } else {
+        if request.force && (request.target.is_none() || request.timeout.is_none()) {
+            return Ok(RespFrame::error("ERR FAILOVER with force option requires both a timeout and target HOST and IP."));
         }
+        manager.start_failover(request, Arc::clone(storage))
+    };
+    
+    match started {
+        Ok(()) => Ok(RespFrame::ok()),
+        Err(e) => Ok(RespFrame::error(e.to_string())),
     }
 }
 
@@ -247,4 +345,24 @@ mod tests {
         assert!(matches!(result, RespFrame::SimpleString(_)));
         assert!(manager.is_master());
     }
+    
+    #[test]
+    fn test_failover_arguments() {
+        let manager = ReplicationManager::new(super::super::ReplicationConfig::default());
+        let storage = StorageEngine::new();
+        let failover = |args: &[&str]| {
+            let parts: Vec<RespFrame> = std::iter::once("FAILOVER").chain(args.iter().copied())
+                .map(|arg| RespFrame::bulk_string(arg.as_bytes()))
+                .collect();
+            handle_failover(&parts, &manager, &storage).unwrap()
+        };
+        
+        assert_eq!(failover(&["FORCE", "TIMEOUT", "100"]),
+            RespFrame::error("ERR FAILOVER with force option requires both a timeout and target HOST and IP."));
+        assert_eq!(failover(&["TIMEOUT", "0"]), RespFrame::error("ERR FAILOVER timeout must be greater than 0"));
+        assert_eq!(failover(&["TO", "127.0.0.1"]), RespFrame::error("ERR syntax error"));
+        assert_eq!(failover(&["ABORT", "FORCE"]), RespFrame::error("ERR FAILOVER abort can't be combined with other options"));
+        assert_eq!(failover(&["ABORT"]), RespFrame::error("ERR No failover in progress."));
+        assert_eq!(failover(&[]), RespFrame::error("ERR FAILOVER requires connected replicas."));
+    }
 }
\ No newline at end of file
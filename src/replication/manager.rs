@@ -3,14 +3,15 @@
 use std::sync::{Arc, Mutex, RwLock};
 use std::sync::atomic::{AtomicU64, AtomicBool, Ordering};
 use std::collections::HashMap;
-use std::net::SocketAddr;
-use std::time::Duration;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
 use std::thread;
 use crate::error::{FerrousError, Result};
-use crate::protocol::RespFrame;
+use crate::protocol::{RespFrame, serialize_resp_frame};
 use crate::storage::StorageEngine;
 use super::{ReplicaInfo, ReplicationConfig, ReplicationBacklog, generate_repl_id};
-use super::client::{start_background_replication, ReplicationClientConfig};
+use super::client::{start_background_replication, master_password, ReplicationClientConfig};
 
 /// The role of the server in replication
 #[derive(Debug, Clone)]
@@ -91,6 +92,43 @@ pub enum ReplicationState {
     Online,
 }
 
+/// Progress of a coordinated failover (for master)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailoverState {
+    /// No failover is running
+    NoFailover,
+    
+    /// Writes are paused until the target replica has caught up
+    WaitingForSync,
+    
+    /// The target replica is being promoted
+    FailoverInProgress,
+}
+
+impl FailoverState {
+    /// Name reported as `master_failover_state` by INFO
+    pub fn name(self) -> &'static str {
+        match self {
+            FailoverState::NoFailover => "no-failover",
+            FailoverState::WaitingForSync => "waiting-for-sync",
+            FailoverState::FailoverInProgress => "failover-in-progress",
+        }
+    }
+}
+
+/// Options of a FAILOVER command
+#[derive(Debug, Clone, Default)]
+pub struct FailoverRequest {
+    /// Replica to promote; the most up to date one when unset
+    pub target: Option<SocketAddr>,
+    
+    /// How long to wait for the target to catch up; no limit when unset
+    pub timeout: Option<Duration>,
+    
+    /// Promote the target even if it has not caught up when the timeout expires
+    pub force: bool,
+}
+
 /// Main replication manager
 pub struct ReplicationManager {
     /// Current role (master or replica)
@@ -116,6 +154,12 @@ pub struct ReplicationManager {
     
     /// Handle to stop background replication
     replication_stop_flag: Arc<Mutex<Option<Arc<AtomicBool>>>>,
+    
+    /// Progress of a running FAILOVER
+    failover_state: Arc<Mutex<FailoverState>>,
+    
+    /// Set by FAILOVER ABORT, checked while waiting for the target
+    failover_abort: Arc<AtomicBool>,
 }
 
 impl ReplicationManager {
@@ -137,6 +181,8 @@ impl ReplicationManager {
             paused: AtomicBool::new(false),
             master_conn_id: Arc::new(Mutex::new(None)),
             replication_stop_flag: Arc::new(Mutex::new(None)),
+            failover_state: Arc::new(Mutex::new(FailoverState::NoFailover)),
+            failover_abort: Arc::new(AtomicBool::new(false)),
         })
     }
     
@@ -196,7 +242,10 @@ impl ReplicationManager {
                 *self.state.lock().unwrap() = ReplicationState::None;
                 
                 // Start background replication
-                let config = ReplicationClientConfig::default();
+                let config = ReplicationClientConfig {
+                    listening_port: self.config.listening_port,
+                    ..ReplicationClientConfig::default()
+                };
                 let stop_flag = start_background_replication(
                     addr,
                     config,
@@ -218,6 +267,9 @@ impl ReplicationManager {
                     repl_offset2: Arc::new(AtomicU64::new(0)),
                 };
                 
+                // The new replication ID starts a new stream
+                self.backlog.reset(0);
+                
                 // Reset state
                 *self.state.lock().unwrap() = ReplicationState::None;
                 
@@ -258,16 +310,14 @@ impl ReplicationManager {
             return Ok(Vec::new());
         }
         
-        // Add to backlog
-        self.backlog.append_command(cmd_frame)?;
-        
-        // Get current replication offset
-        let offset = match &*self.role.read().unwrap() {
+        // Add to backlog; the offset counts bytes of the replication stream
+        let len = self.backlog.append_command(cmd_frame)?;
+        match &*self.role.read().unwrap() {
             ReplicationRole::Master { repl_offset, .. } => {
-                repl_offset.fetch_add(1, Ordering::SeqCst)
+                repl_offset.fetch_add(len, Ordering::SeqCst);
             }
             _ => return Ok(Vec::new()),
-        };
+        }
         
         // Get replica connection IDs
         let replicas = self.replicas.lock().unwrap();
@@ -276,6 +326,18 @@ impl ReplicationManager {
         Ok(replica_ids)
     }
     
+    /// Replication ID and offset to resume from with PSYNC (for replica)
+    ///
+    /// `None` until the first synchronization with the current master.
+    pub fn resume_point(&self) -> Option<(String, u64)> {
+        match &*self.role.read().unwrap() {
+            ReplicationRole::Replica { master_repl_id, repl_offset, .. } if !master_repl_id.is_empty() => {
+                Some((master_repl_id.clone(), *repl_offset))
+            }
+            _ => None,
+        }
+    }
+    
     /// Update master link status (for replica)
     pub fn update_master_link_status(&self, status: MasterLinkStatus) -> Result<()> {
         let mut role = self.role.write().unwrap();
@@ -324,15 +386,118 @@ impl ReplicationManager {
         self.paused.store(false, Ordering::SeqCst);
     }
     
+    /// Current failover progress
+    pub fn failover_state(&self) -> FailoverState {
+        *self.failover_state.lock().unwrap()
+    }
+    
+    /// Check if writes are held back by a running failover
+    pub fn writes_paused(&self) -> bool {
+        self.failover_state() != FailoverState::NoFailover
+    }
+    
+    /// Start a coordinated failover (for master)
+    ///
+    /// Writes are paused at once. A background thread waits for the target
+    /// replica to acknowledge the whole stream, promotes it with REPLICAOF NO ONE
+    /// and then follows it as a replica. The failover ends early, with this
+    /// server still the master, if it is aborted, the timeout expires without
+    /// FORCE, or the target can't be promoted.
+    pub fn start_failover(&self, request: FailoverRequest, storage: Arc<StorageEngine>) -> Result<()> {
+        if !self.is_master() {
+            return Err(failover_error("FAILOVER is not valid when server is a replica."));
+        }
+        
+        let replicas = self.get_replicas();
+        if replicas.is_empty() {
+            return Err(failover_error("FAILOVER requires connected replicas."));
+        }
+        let target = match request.target {
+            Some(addr) => replicas.into_iter()
+                .find(|replica| replica.listening_addr() == Some(addr))
+                .ok_or_else(|| failover_error("FAILOVER target HOST and PORT is not a replica."))?,
+            None => replicas.into_iter()
+                .filter(|replica| replica.listening_addr().is_some())
+                .max_by_key(|replica| replica.ack_offset.load(Ordering::SeqCst))
+                .ok_or_else(|| failover_error("FAILOVER requires connected replicas."))?,
+        };
+        
+        {
+            let mut state = self.failover_state.lock().unwrap();
+            if *state != FailoverState::NoFailover {
+                return Err(failover_error("FAILOVER already in progress."));
+            }
+            *state = FailoverState::WaitingForSync;
+        }
+        self.failover_abort.store(false, Ordering::SeqCst);
+        
+        let manager = self.clone();
+        thread::spawn(move || manager.run_failover(target, request, storage));
+        
+        Ok(())
+    }
+    
+    /// Abort a failover that is still waiting for its target
+    pub fn abort_failover(&self) -> Result<()> {
+        if self.failover_state() == FailoverState::NoFailover {
+            return Err(failover_error("No failover in progress."));
+        }
+        self.failover_abort.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+    
+    /// Drive a failover started by [`start_failover`](Self::start_failover)
+    fn run_failover(&self, target: Arc<ReplicaInfo>, request: FailoverRequest, storage: Arc<StorageEngine>) {
+        let deadline = request.timeout.map(|timeout| Instant::now() + timeout);
+        
+        // Writes are paused, so the master offset only moves for writes already under way
+        while target.ack_offset.load(Ordering::SeqCst) < self.get_repl_offset() {
+            if self.failover_abort.load(Ordering::SeqCst) {
+                println!("FAILOVER: aborted");
+                return self.end_failover();
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                if !request.force {
+                    println!("FAILOVER: replica {} did not catch up in time", target.addr);
+                    return self.end_failover();
+                }
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        
+        *self.failover_state.lock().unwrap() = FailoverState::FailoverInProgress;
+        let Some(addr) = target.listening_addr() else {
+            return self.end_failover();
+        };
+        if let Err(e) = promote_replica(addr, self.config.timeout) {
+            eprintln!("FAILOVER: could not promote {}: {}", addr, e);
+            return self.end_failover();
+        }
+        
+        println!("FAILOVER: {} promoted, following it as a replica", addr);
+        if let Err(e) = self.set_master(Some(addr), storage) {
+            eprintln!("FAILOVER: could not replicate from {}: {}", addr, e);
+        }
+        self.end_failover();
+    }
+    
+    /// Resume writes after a failover, however it ended
+    fn end_failover(&self) {
+        *self.failover_state.lock().unwrap() = FailoverState::NoFailover;
+        self.failover_abort.store(false, Ordering::SeqCst);
+    }
+    
     /// Get replication info for INFO command
     pub fn get_info(&self) -> HashMap<String, String> {
         let mut info = HashMap::new();
+        info.insert("master_failover_state".to_string(), self.failover_state().name().to_string());
         
         match &*self.role.read().unwrap() {
             ReplicationRole::Master { repl_id, repl_offset, .. } => {
                 info.insert("role".to_string(), "master".to_string());
-                info.insert("repl_id".to_string(), repl_id.clone());
-                info.insert("repl_offset".to_string(), 
+                info.insert("master_replid".to_string(), repl_id.clone());
+                info.insert("master_repl_offset".to_string(),
                     repl_offset.load(Ordering::SeqCst).to_string());
                 
                 let replicas = self.replicas.lock().unwrap();
@@ -341,8 +506,9 @@ impl ReplicationManager {
                 // Add replica info
                 for (idx, (_, replica)) in replicas.iter().enumerate() {
                     let key = format!("slave{}", idx);
-                    let value = format!("ip={},port=0,state=online,offset={},lag=0",
+                    let value = format!("ip={},port={},state=online,offset={},lag=0",
                         replica.addr.ip(),
+                        replica.listening_port.unwrap_or(0),
                         replica.ack_offset.load(Ordering::SeqCst));
                     info.insert(key, value);
                 }
@@ -383,10 +549,51 @@ impl Clone for ReplicationManager {
             paused: AtomicBool::new(self.paused.load(Ordering::SeqCst)),
             master_conn_id: Arc::clone(&self.master_conn_id),
             replication_stop_flag: Arc::clone(&self.replication_stop_flag),
+            failover_state: Arc::clone(&self.failover_state),
+            failover_abort: Arc::clone(&self.failover_abort),
         }
     }
 }
 
+/// Error for a FAILOVER that can't start
+fn failover_error(message: &str) -> FerrousError {
+    FerrousError::Command(crate::error::CommandError::Generic(message.into()))
+}
+
+/// Make the replica at `addr` a master with REPLICAOF NO ONE
+fn promote_replica(addr: SocketAddr, timeout_secs: u64) -> Result<()> {
+    let timeout = Duration::from_secs(timeout_secs.max(1));
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    
+    // A replica without a password rejects AUTH, which REPLICAOF doesn't need then
+    if let Some(password) = master_password() {
+        send_command(&mut stream, &["AUTH", &password])?;
+    }
+    let reply = send_command(&mut stream, &["REPLICAOF", "NO", "ONE"])?;
+    if reply != "+OK" {
+        return Err(FerrousError::Connection(format!("REPLICAOF NO ONE replied {}", reply)));
+    }
+    Ok(())
+}
+
+/// Send a command and read its one-line reply
+fn send_command(stream: &mut TcpStream, args: &[&str]) -> Result<String> {
+    let command = RespFrame::array(args.iter().map(|arg| RespFrame::bulk_string(arg.as_bytes())).collect());
+    let mut bytes = Vec::new();
+    serialize_resp_frame(&command, &mut bytes)?;
+    stream.write_all(&bytes)?;
+    
+    let mut reply = Vec::new();
+    let mut byte = [0u8; 1];
+    while !reply.ends_with(b"\r\n") {
+        stream.read_exact(&mut byte)?;
+        reply.push(byte[0]);
+    }
+    reply.truncate(reply.len() - 2);
+    Ok(String::from_utf8_lossy(&reply).into_owned())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -415,4 +622,102 @@ mod tests {
         manager.set_master(None, Arc::clone(&storage)).unwrap();
         assert!(manager.is_master());
     }
+    
+    #[test]
+    fn test_offsets_count_propagated_bytes() {
+        let manager = ReplicationManager::new(ReplicationConfig::default());
+        let cmd = RespFrame::array(vec![RespFrame::bulk_string(b"DEL"), RespFrame::bulk_string(b"k")]);
+        
+        // *2\r\n$3\r\nDEL\r\n$1\r\nk\r\n
+        manager.propagate_command(&cmd).unwrap();
+        manager.propagate_command(&cmd).unwrap();
+        assert_eq!(manager.get_repl_offset(), 40);
+        assert_eq!(manager.get_backlog_data(20).unwrap().len(), 20);
+        assert!(manager.get_backlog_data(40).unwrap().is_empty());
+    }
+    
+    /// Wait for a failover started in the background to finish
+    fn wait_for_failover(manager: &ReplicationManager) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while manager.failover_state() != FailoverState::NoFailover {
+            assert!(Instant::now() < deadline, "failover did not finish");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+    
+    #[test]
+    fn test_failover_needs_a_known_replica() {
+        let manager = ReplicationManager::new(ReplicationConfig::default());
+        let storage = StorageEngine::new();
+        let err = manager.start_failover(FailoverRequest::default(), Arc::clone(&storage)).unwrap_err();
+        assert_eq!(err.to_string(), "ERR FAILOVER requires connected replicas.");
+        
+        let addr = "127.0.0.1:40000".parse().unwrap();
+        manager.add_replica(ReplicaInfo::with_listening_port(1, addr, Some(7000))).unwrap();
+        let request = FailoverRequest { target: Some("127.0.0.1:7001".parse().unwrap()), ..FailoverRequest::default() };
+        let err = manager.start_failover(request, storage).unwrap_err();
+        assert_eq!(err.to_string(), "ERR FAILOVER target HOST and PORT is not a replica.");
+        assert!(!manager.writes_paused());
+    }
+    
+    #[test]
+    fn test_failover_gives_up_on_a_lagging_replica() {
+        let manager = ReplicationManager::new(ReplicationConfig::default());
+        let storage = StorageEngine::new();
+        manager.add_replica(ReplicaInfo::with_listening_port(1, "127.0.0.1:40000".parse().unwrap(), Some(7000))).unwrap();
+        manager.propagate_command(&RespFrame::array(vec![RespFrame::bulk_string(b"DEL"), RespFrame::bulk_string(b"k")])).unwrap();
+        
+        // Without FORCE, the timeout ends the failover with writes resumed
+        let request = FailoverRequest { timeout: Some(Duration::from_millis(50)), ..FailoverRequest::default() };
+        manager.start_failover(request.clone(), Arc::clone(&storage)).unwrap();
+        assert!(manager.writes_paused());
+        assert_eq!(manager.start_failover(request, Arc::clone(&storage)).unwrap_err().to_string(), "ERR FAILOVER already in progress.");
+        wait_for_failover(&manager);
+        assert!(manager.is_master());
+        
+        // ABORT ends a failover that would otherwise wait forever
+        manager.start_failover(FailoverRequest::default(), storage).unwrap();
+        assert_eq!(manager.failover_state(), FailoverState::WaitingForSync);
+        manager.abort_failover().unwrap();
+        wait_for_failover(&manager);
+        assert!(manager.is_master());
+        assert_eq!(manager.abort_failover().unwrap_err().to_string(), "ERR No failover in progress.");
+    }
+    
+    #[test]
+    fn test_failover_promotes_the_target() {
+        let target = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let target_addr = target.local_addr().unwrap();
+        let promoted = thread::spawn(move || {
+            let (stream, _) = target.accept().unwrap();
+            let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut commands = Vec::new();
+            
+            // AUTH, then REPLICAOF NO ONE, as arrays of three or two bulk strings
+            for _ in 0..2 {
+                let mut header = String::new();
+                std::io::BufRead::read_line(&mut reader, &mut header).unwrap();
+                let mut command = header.clone();
+                for _ in 0..header[1..].trim().parse::<usize>().unwrap() * 2 {
+                    std::io::BufRead::read_line(&mut reader, &mut command).unwrap();
+                }
+                writer.write_all(b"+OK\r\n").unwrap();
+                commands.push(command);
+            }
+            commands
+        });
+        
+        let manager = ReplicationManager::new(ReplicationConfig::default());
+        let storage = StorageEngine::new();
+        manager.add_replica(ReplicaInfo::with_listening_port(1, "127.0.0.1:40000".parse().unwrap(), Some(target_addr.port()))).unwrap();
+        manager.start_failover(FailoverRequest::default(), Arc::clone(&storage)).unwrap();
+        
+        assert_eq!(promoted.join().unwrap()[1], "*3\r\n$9\r\nREPLICAOF\r\n$2\r\nNO\r\n$3\r\nONE\r\n");
+        wait_for_failover(&manager);
+        assert!(manager.is_replica());
+        assert_eq!(manager.get_info()["master_port"], target_addr.port().to_string());
+        
+        manager.set_master(None, storage).unwrap();
+    }
 }
\ No newline at end of file
@@ -6,6 +6,7 @@
 //! - Incremental sync (PSYNC)
 //! - Command propagation
 //! - Replication backlog
+//! - Coordinated failover (FAILOVER)
 
 mod manager;
 pub mod sync; // Make the sync module public
@@ -13,9 +14,9 @@ pub mod commands;
 mod backlog;
 mod client;
 
-pub use manager::{ReplicationManager, ReplicationRole, MasterLinkStatus};
+pub use manager::{ReplicationManager, ReplicationRole, MasterLinkStatus, FailoverState, FailoverRequest};
  // Re-export SyncProtocol
-pub use commands::{handle_replicaof, handle_slaveof, handle_sync, handle_psync};
+pub use commands::{handle_replicaof, handle_slaveof, handle_sync, handle_psync, handle_psync_with_backlog, handle_failover};
 pub use backlog::ReplicationBacklog;
 
 use std::sync::{Arc, Mutex};
@@ -43,6 +44,9 @@ pub struct ReplicationConfig {
     
     /// Master port to replicate from (if this is a replica)
     pub master_port: Option<u16>,
+    
+    /// Port this server listens on, reported to its master
+    pub listening_port: u16,
 }
 
 impl Default for ReplicationConfig {
@@ -54,6 +58,7 @@ impl Default for ReplicationConfig {
             diskless_sync: false,
             master_host: None,
             master_port: None,
+            listening_port: 6379,
         }
     }
 }
@@ -67,6 +72,9 @@ pub struct ReplicaInfo {
     /// Address of the replica
     pub addr: SocketAddr,
     
+    /// Port the replica accepts clients on (from REPLCONF listening-port)
+    pub listening_port: Option<u16>,
+    
     /// Replication offset acknowledged by the replica
     pub ack_offset: AtomicU64,
     
@@ -79,15 +87,26 @@ pub struct ReplicaInfo {
 
 impl ReplicaInfo {
     pub fn new(conn_id: u64, addr: SocketAddr) -> Arc<Self> {
+        Self::with_listening_port(conn_id, addr, None)
+    }
+    
+    /// Create a replica that announced the port it accepts clients on
+    pub fn with_listening_port(conn_id: u64, addr: SocketAddr, listening_port: Option<u16>) -> Arc<Self> {
         Arc::new(ReplicaInfo {
             conn_id,
             addr,
+            listening_port,
             ack_offset: AtomicU64::new(0),
             last_interaction: Mutex::new(Instant::now()),
             capabilities: Vec::new(),
         })
     }
     
+    /// Address clients (and a failing-over master) reach the replica on
+    pub fn listening_addr(&self) -> Option<SocketAddr> {
+        self.listening_port.map(|port| SocketAddr::new(self.addr.ip(), port))
+    }
+    
     /// Update last interaction time
     pub fn touch(&self) {
         *self.last_interaction.lock().unwrap() = Instant::now();
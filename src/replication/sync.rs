@@ -20,6 +20,7 @@ pub enum PsyncResult {
     
     /// Partial resynchronization possible
     PartialResync {
+        repl_id: String,
         backlog_data: Vec<u8>,
     },
 }
@@ -44,6 +45,10 @@ impl SyncProtocol {
     }
     
     /// Handle PSYNC command from replica
+    ///
+    /// As in Redis, the offset a replica sends is one past the last byte it
+    /// processed. The replica continues from the backlog when it followed this
+    /// replication ID and the backlog still holds everything after that byte.
     pub fn handle_psync(
         manager: &Arc<ReplicationManager>,
         storage: &Arc<StorageEngine>,
@@ -66,10 +71,10 @@ impl SyncProtocol {
                 
                 // Check if partial resync is possible
                 if let (Some(replica_repl_id), Some(replica_offset)) = (repl_id, offset) {
-                    if replica_repl_id == master_repl_id || replica_repl_id == "?" {
+                    if replica_repl_id == master_repl_id {
                         // Check if we have the data in backlog
-                        if let Ok(backlog_data) = manager.get_backlog_data(replica_offset) {
-                            return Ok(PsyncResult::PartialResync { backlog_data });
+                        if let Some(Ok(backlog_data)) = replica_offset.checked_sub(1).map(|next| manager.get_backlog_data(next)) {
+                            return Ok(PsyncResult::PartialResync { repl_id: master_repl_id, backlog_data });
                         }
                     }
                 }
@@ -225,4 +230,35 @@ mod tests {
             _ => panic!("Wrong result type"),
         }
     }
+    
+    #[test]
+    fn test_psync_continues_from_backlog() {
+        let manager = ReplicationManager::new(super::super::ReplicationConfig::default());
+        let storage = Arc::new(StorageEngine::new());
+        let rdb_engine = Arc::new(RdbEngine::new(crate::storage::rdb::RdbConfig::default()));
+        let repl_id = match manager.role() {
+            ReplicationRole::Master { repl_id, .. } => repl_id,
+            _ => unreachable!(),
+        };
+        let cmd = RespFrame::array(vec![RespFrame::bulk_string(b"DEL"), RespFrame::bulk_string(b"k")]);
+        manager.propagate_command(&cmd).unwrap();
+        manager.propagate_command(&cmd).unwrap();
+        
+        let psync = |id: &str, offset: u64| SyncProtocol::handle_psync(&manager, &storage, &rdb_engine, Some(id.to_string()), Some(offset)).unwrap();
+        
+        // A replica that processed the first command gets the second
+        match psync(&repl_id, 21) {
+            PsyncResult::PartialResync { repl_id: id, backlog_data } => {
+                assert_eq!(id, repl_id);
+                assert_eq!(backlog_data, b"*2\r\n$3\r\nDEL\r\n$1\r\nk\r\n");
+            }
+            other => panic!("Expected partial resync, got {:?}", other),
+        }
+        assert!(matches!(psync(&repl_id, 41), PsyncResult::PartialResync { ref backlog_data, .. } if backlog_data.is_empty()));
+        
+        // Another history, or an offset past the stream, needs a full resync
+        assert!(matches!(psync("0000000000000000000000000000000000000000", 21), PsyncResult::FullResync { offset: 40, .. }));
+        assert!(matches!(psync(&repl_id, 42), PsyncResult::FullResync { .. }));
+        assert!(matches!(psync(&repl_id, 0), PsyncResult::FullResync { .. }));
+    }
 }
\ No newline at end of file
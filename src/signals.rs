@@ -0,0 +1,104 @@
+//! Process signal handling
+//!
+//! SIGTERM and SIGINT ask the server to shut down the way SHUTDOWN does, so
+//! the AOF is synced and the dataset saved before exiting; SIGHUP asks for
+//! the log file to be reopened after log rotation. Handlers only record the
+//! request, which the event loop picks up on its next iteration.
+//!
+//! A second SIGTERM or SIGINT while a shutdown is still in progress exits
+//! immediately, for an operator who doesn't want to wait for the save.
+
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+/// Signal that requested a shutdown, or 0
+static SHUTDOWN_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// SIGHUP was received since the log was last reopened
+static REOPEN_LOG: AtomicBool = AtomicBool::new(false);
+
+/// Install the SIGTERM, SIGINT and SIGHUP handlers
+#[cfg(unix)]
+pub fn install() -> std::io::Result<()> {
+    for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+        // SAFETY: the handler only touches atomics and calls _exit, both async-signal-safe
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Signals are not handled on this platform
+#[cfg(not(unix))]
+pub fn install() -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+extern "C" fn handle_signal(signal: libc::c_int) {
+    if signal == libc::SIGHUP {
+        REOPEN_LOG.store(true, Ordering::SeqCst);
+    } else if SHUTDOWN_SIGNAL.swap(signal, Ordering::SeqCst) != 0 {
+        // SAFETY: _exit is async-signal-safe
+        unsafe { libc::_exit(1) }
+    }
+}
+
+/// Name of a shutdown signal, for the log
+pub fn name(signal: i32) -> &'static str {
+    #[cfg(unix)]
+    {
+        match signal {
+            libc::SIGTERM => "SIGTERM",
+            libc::SIGINT => "SIGINT",
+            _ => "signal",
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = signal;
+        "signal"
+    }
+}
+
+/// Signal asking for a shutdown, if one arrived and wasn't cleared
+pub fn shutdown_requested() -> Option<i32> {
+    match SHUTDOWN_SIGNAL.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+/// Forget the shutdown request after a shutdown failed, to keep serving
+pub fn clear_shutdown_request() {
+    SHUTDOWN_SIGNAL.store(0, Ordering::SeqCst);
+}
+
+/// Check for, and consume, a log reopen request
+pub fn take_log_reopen() -> bool {
+    REOPEN_LOG.swap(false, Ordering::SeqCst)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_handler_records_requests() {
+        handle_signal(libc::SIGHUP);
+        assert!(take_log_reopen());
+        assert!(!take_log_reopen());
+        
+        handle_signal(libc::SIGTERM);
+        assert_eq!(shutdown_requested(), Some(libc::SIGTERM));
+        assert_eq!(name(libc::SIGTERM), "SIGTERM");
+        clear_shutdown_request();
+        assert_eq!(shutdown_requested(), None);
+    }
+}
@@ -3,15 +3,17 @@
 //! Provides append-only file logging for command persistence and replay.
 
 use std::fs::{File, OpenOptions};
-use std::io::{Write, BufWriter, BufReader, BufRead};
+use std::io::{Write, BufWriter};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 use std::thread;
 
 use crate::error::{FerrousError, Result};
 use crate::protocol::{RespFrame, serialize_resp_frame, RespParser};
-use crate::storage::StorageEngine;
+use crate::storage::{atomic_file, StorageEngine};
+use crate::storage::commands::executor::ServerCommandAdapter;
 
 /// AOF persistence engine
 pub struct AofEngine {
@@ -24,11 +26,23 @@ pub struct AofEngine {
     /// Configuration
     config: AofConfig,
     
+    /// Fsync policy in effect, changed by CONFIG SET appendfsync
+    fsync_policy: Arc<Mutex<FsyncPolicy>>,
+    
     /// Last fsync time for everysec mode
     last_fsync: Arc<Mutex<Instant>>,
     
     /// Is background rewrite in progress?
     rewrite_in_progress: Arc<Mutex<bool>>,
+    
+    /// Database the last appended command ran in, to know when to log SELECT
+    last_db: Arc<Mutex<Option<usize>>>,
+    
+    /// Whether the last append reached the file (aof_last_write_status)
+    last_write_ok: Arc<AtomicBool>,
+    
+    /// Whether the last rewrite succeeded (aof_last_bgrewrite_status)
+    last_rewrite_ok: Arc<AtomicBool>,
 }
 
 /// AOF configuration
@@ -86,9 +100,13 @@ impl AofEngine {
         Self {
             file_path,
             writer: Arc::new(Mutex::new(None)),
+            fsync_policy: Arc::new(Mutex::new(config.fsync_policy)),
             config,
             last_fsync: Arc::new(Mutex::new(Instant::now())),
             rewrite_in_progress: Arc::new(Mutex::new(false)),
+            last_db: Arc::new(Mutex::new(None)),
+            last_write_ok: Arc::new(AtomicBool::new(true)),
+            last_rewrite_ok: Arc::new(AtomicBool::new(true)),
         }
     }
     
@@ -115,25 +133,17 @@ impl AofEngine {
             return Ok(());
         }
         
-        let file = File::open(&self.file_path)?;
-        let reader = BufReader::new(file);
+        // Values may hold any bytes, so the file is parsed as raw RESP rather than lines
         let mut parser = RespParser::new();
+        parser.feed(&std::fs::read(&self.file_path)?);
         
-        // Read and replay all commands
-        for line in reader.lines() {
-            let line = line?;
-            parser.feed(line.as_bytes());
-            parser.feed(b"\n");
-            
-            while let Some(frame) = parser.parse()? {
-                // Execute command against storage
-                // This is simplified - in reality we'd need the full command processor
-                match frame {
-                    RespFrame::Array(Some(parts)) if !parts.is_empty() => {
-                        // Process command
-                        self.replay_command(storage, &parts)?;
-                    }
-                    _ => continue,
+        // Replay all commands, starting in database 0 like a new connection
+        let adapter = ServerCommandAdapter::new(storage.clone());
+        let mut db = 0;
+        while let Some(frame) = parser.parse()? {
+            if let RespFrame::Array(Some(parts)) = frame {
+                if !parts.is_empty() {
+                    self.replay_command(storage, &adapter, &parts, &mut db);
                 }
             }
         }
@@ -141,44 +151,107 @@ impl AofEngine {
         Ok(())
     }
     
-    /// Append a command to the AOF
-    pub fn append_command(&self, command: &[RespFrame]) -> Result<()> {
+    /// Append a command run in database `db` to the AOF
+    pub fn append_command(&self, db: usize, command: &[RespFrame]) -> Result<()> {
         if !self.config.enabled {
             return Ok(());
         }
         
         let mut writer_guard = self.writer.lock().unwrap();
         if let Some(writer) = writer_guard.as_mut() {
-            // Serialize command as RESP array
-            let frame = RespFrame::Array(Some(command.to_vec()));
-            serialize_resp_frame(&frame, writer)?;
-            
-            // Handle fsync based on policy
-            match self.config.fsync_policy {
-                FsyncPolicy::Always => {
-                    writer.flush()?;
+            let result = self.write_command(writer, db, command);
+            self.last_write_ok.store(result.is_ok(), Ordering::Relaxed);
+            result?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Serialize a command into the AOF and flush or fsync it per the policy
+    fn write_command(&self, writer: &mut BufWriter<File>, db: usize, command: &[RespFrame]) -> Result<()> {
+        // Log a SELECT first when the database changed since the last command
+        let mut last_db = self.last_db.lock().unwrap();
+        if *last_db != Some(db) {
+            let select = RespFrame::Array(Some(vec![
+                RespFrame::BulkString(Some(Arc::new(b"SELECT".to_vec()))),
+                RespFrame::BulkString(Some(Arc::new(db.to_string().into_bytes()))),
+            ]));
+            serialize_resp_frame(&select, writer)?;
+            *last_db = Some(db);
+        }
+        
+        // Serialize command as RESP array
+        let frame = RespFrame::Array(Some(command.to_vec()));
+        serialize_resp_frame(&frame, writer)?;
+        
+        // Handle fsync based on policy
+        match self.fsync_policy() {
+            FsyncPolicy::Always => {
+                writer.flush()?;
+                writer.get_ref().sync_all()?;
+            }
+            FsyncPolicy::EverySecond => {
+                writer.flush()?;
+                
+                // Check if we should fsync
+                let mut last_fsync = self.last_fsync.lock().unwrap();
+                if last_fsync.elapsed() >= Duration::from_secs(1) {
                     writer.get_ref().sync_all()?;
+                    *last_fsync = Instant::now();
                 }
-                FsyncPolicy::EverySecond => {
-                    writer.flush()?;
-                    
-                    // Check if we should fsync
-                    let mut last_fsync = self.last_fsync.lock().unwrap();
-                    if last_fsync.elapsed() >= Duration::from_secs(1) {
-                        writer.get_ref().sync_all()?;
-                        *last_fsync = Instant::now();
-                    }
-                }
-                FsyncPolicy::No => {
-                    // Just flush to OS buffers
-                    writer.flush()?;
-                }
+            }
+            FsyncPolicy::No => {
+                // Just flush to OS buffers
+                writer.flush()?;
             }
         }
         
         Ok(())
     }
     
+    /// Fsync policy in effect
+    pub fn fsync_policy(&self) -> FsyncPolicy {
+        *self.fsync_policy.lock().unwrap()
+    }
+    
+    /// Change the fsync policy for the following appends
+    pub fn set_fsync_policy(&self, policy: FsyncPolicy) {
+        *self.fsync_policy.lock().unwrap() = policy;
+    }
+    
+    /// Flush buffered appends and fsync the file, whatever the policy
+    ///
+    /// Used on shutdown so the tail of the log survives even with
+    /// `appendfsync everysec` or `no`.
+    pub fn fsync(&self) -> Result<()> {
+        let mut writer_guard = self.writer.lock().unwrap();
+        if let Some(writer) = writer_guard.as_mut() {
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        Ok(())
+    }
+    
+    /// Whether the last append reached the file
+    pub fn last_write_ok(&self) -> bool {
+        self.last_write_ok.load(Ordering::Relaxed)
+    }
+    
+    /// Whether a background rewrite is running (aof_rewrite_in_progress)
+    pub fn is_rewrite_in_progress(&self) -> bool {
+        *self.rewrite_in_progress.lock().unwrap()
+    }
+    
+    /// Current size of the AOF file in bytes (aof_current_size)
+    pub fn current_size(&self) -> u64 {
+        std::fs::metadata(&self.file_path).map_or(0, |metadata| metadata.len())
+    }
+    
+    /// Whether the last rewrite succeeded
+    pub fn last_rewrite_ok(&self) -> bool {
+        self.last_rewrite_ok.load(Ordering::Relaxed)
+    }
+    
     /// Perform background rewrite
     pub fn bgrewrite(&self) -> Result<()> {
         {
@@ -194,7 +267,9 @@ impl AofEngine {
         let engine = self.clone();
         
         thread::spawn(move || {
-            if let Err(e) = engine.do_rewrite() {
+            let result = engine.do_rewrite();
+            engine.last_rewrite_ok.store(result.is_ok(), Ordering::Relaxed);
+            if let Err(e) = result {
                 eprintln!("AOF rewrite failed: {}", e);
             }
             
@@ -206,46 +281,59 @@ impl AofEngine {
     }
     
     /// Perform the actual rewrite
+    ///
+    /// Appends are held off while the log is copied into a temporary file that
+    /// replaces it atomically; the writer then reopens the new file. The copy
+    /// is not compacted yet, but a failed rewrite never touches the live AOF.
     fn do_rewrite(&self) -> Result<()> {
-        // This is a simplified rewrite
-        // In reality, we'd need to:
-        // 1. Fork the process or carefully synchronize
-        // 2. Write all current data to a temp file
-        // 3. Buffer any new commands during rewrite
-        // 4. Atomically replace the old AOF
-        
         println!("AOF rewrite started");
         
-        // For now, just compact the existing AOF
-        let temp_path = self.file_path.with_extension("aof.rewrite");
+        let mut writer_guard = self.writer.lock().unwrap();
+        if let Some(writer) = writer_guard.as_mut() {
+            writer.flush()?;
+        }
         
-        // Write compacted data to temp file
-        // ... implementation details ...
+        let contents = if self.file_path.exists() { std::fs::read(&self.file_path)? } else { Vec::new() };
+        atomic_file::write_atomically(&self.file_path, |writer| Ok(writer.write_all(&contents)?))?;
         
-        // Rename temp file to replace original
-        std::fs::rename(&temp_path, &self.file_path)?;
+        // Keep appending to the file now at the path, not the replaced one
+        if writer_guard.is_some() {
+            let file = OpenOptions::new().append(true).open(&self.file_path)?;
+            *writer_guard = Some(BufWriter::new(file));
+        }
         
         println!("AOF rewrite completed");
         Ok(())
     }
     
-    /// Replay a command during AOF loading
-    fn replay_command(&self, storage: &Arc<StorageEngine>, parts: &[RespFrame]) -> Result<()> {
-        // Extract command name
-        let cmd_frame = &parts[0];
-        let command = match cmd_frame {
+    /// Replay a command during AOF loading, tracking the selected database
+    fn replay_command(&self, storage: &Arc<StorageEngine>, adapter: &ServerCommandAdapter, parts: &[RespFrame], db: &mut usize) {
+        let command = match &parts[0] {
             RespFrame::BulkString(Some(bytes)) => String::from_utf8_lossy(bytes).to_uppercase(),
-            _ => return Ok(()), // Skip invalid commands
+            _ => return, // Skip invalid commands
         };
         
-        // Only replay write commands
         match command.as_str() {
-            "SET" | "DEL" | "EXPIRE" | "LPUSH" | "RPUSH" | "SADD" | "ZADD" | "HSET" => {
-                // Simplified replay - in reality we'd call the actual command handlers
-                // This is just to demonstrate the concept
-                Ok(())
+            "SELECT" => {
+                if let Some(RespFrame::BulkString(Some(index))) = parts.get(1) {
+                    if let Ok(index) = String::from_utf8_lossy(index).parse() {
+                        *db = index;
+                    }
+                }
             }
-            _ => Ok(()), // Skip read-only commands
+            // Script effects arrive wrapped in a transaction; replay is already sequential
+            "MULTI" | "EXEC" => {}
+            // Libraries live with the script engines, not in the keyspace the executor serves
+            "FUNCTION" => match crate::storage::commands::lua::handle_function(storage, parts) {
+                Ok(RespFrame::Error(e)) => eprintln!("AOF replay of {} failed: {}", command, String::from_utf8_lossy(&e)),
+                Err(e) => eprintln!("AOF replay of {} failed: {}", command, e),
+                Ok(_) => {}
+            },
+            _ => match adapter.execute_with_context(parts, 0, *db) {
+                Ok(RespFrame::Error(e)) => eprintln!("AOF replay of {} failed: {}", command, String::from_utf8_lossy(&e)),
+                Err(e) => eprintln!("AOF replay of {} failed: {}", command, e),
+                Ok(_) => {}
+            },
         }
     }
 }
@@ -256,8 +344,12 @@ impl Clone for AofEngine {
             file_path: self.file_path.clone(),
             writer: Arc::clone(&self.writer),
             config: self.config.clone(),
+            fsync_policy: Arc::clone(&self.fsync_policy),
             last_fsync: Arc::clone(&self.last_fsync),
             rewrite_in_progress: Arc::clone(&self.rewrite_in_progress),
+            last_db: Arc::clone(&self.last_db),
+            last_write_ok: Arc::clone(&self.last_write_ok),
+            last_rewrite_ok: Arc::clone(&self.last_rewrite_ok),
         }
     }
 }
@@ -272,4 +364,64 @@ mod tests {
         assert!(!config.enabled);
         assert_eq!(config.fsync_policy, FsyncPolicy::EverySecond);
     }
+    
+    #[test]
+    fn test_replay_restores_sorted_sets_per_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AofConfig {
+            enabled: true,
+            dir: dir.path().to_string_lossy().into_owned(),
+            ..AofConfig::default()
+        };
+        let command = |words: &[&str]| -> Vec<RespFrame> {
+            words.iter().map(|word| RespFrame::BulkString(Some(Arc::new(word.as_bytes().to_vec())))).collect()
+        };
+        
+        let aof = AofEngine::new(config.clone());
+        aof.init().unwrap();
+        aof.append_command(1, &command(&["ZADD", "z", "1", "a", "2", "b\r\nc", "3", "d"])).unwrap();
+        aof.append_command(1, &command(&["ZREMRANGEBYLEX", "z", "[d", "+"])).unwrap();
+        aof.append_command(0, &command(&["SET", "k", "v"])).unwrap();
+        
+        let storage = StorageEngine::new_in_memory();
+        AofEngine::new(config).load(&storage).unwrap();
+        let members = storage.zrange(1, b"z", 0, -1, false).unwrap();
+        assert_eq!(members, vec![(b"a".to_vec(), 1.0), (b"b\r\nc".to_vec(), 2.0)]);
+        assert!(storage.zrange(0, b"z", 0, -1, false).unwrap().is_empty());
+        assert!(storage.exists(0, b"k").unwrap());
+    }
+    
+    #[test]
+    fn test_replay_restores_function_libraries() {
+        use crate::storage::commands::lua::{handle_fcall_with_db, handle_function};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let config = AofConfig {
+            enabled: true,
+            dir: dir.path().to_string_lossy().into_owned(),
+            ..AofConfig::default()
+        };
+        let command = |words: &[&str]| -> Vec<RespFrame> {
+            words.iter().map(|word| RespFrame::BulkString(Some(Arc::new(word.as_bytes().to_vec())))).collect()
+        };
+        
+        let aof = AofEngine::new(config.clone());
+        aof.init().unwrap();
+        let library = |name: &str, reply: i64| {
+            format!("#!lua name={}\nredis.register_function('{}fn', function() return {} end)", name, name, reply)
+        };
+        aof.append_command(0, &command(&["FUNCTION", "LOAD", &library("kept", 1)])).unwrap();
+        aof.append_command(0, &command(&["FUNCTION", "LOAD", &library("dropped", 2)])).unwrap();
+        aof.append_command(0, &command(&["FUNCTION", "DELETE", "dropped"])).unwrap();
+        aof.append_command(0, &command(&["FUNCTION", "LOAD", "REPLACE", &library("kept", 3)])).unwrap();
+        
+        let storage = StorageEngine::new_in_memory();
+        AofEngine::new(config).load(&storage).unwrap();
+        assert_eq!(handle_fcall_with_db(&storage, &command(&["FCALL", "keptfn", "0"]), 0).unwrap(), RespFrame::Integer(3));
+        assert!(handle_fcall_with_db(&storage, &command(&["FCALL", "droppedfn", "0"]), 0).unwrap().is_error());
+        match handle_function(&storage, &command(&["FUNCTION", "LIST"])).unwrap() {
+            RespFrame::Array(Some(libraries)) => assert_eq!(libraries.len(), 1),
+            other => panic!("Unexpected FUNCTION LIST reply: {:?}", other),
+        }
+    }
 }
\ No newline at end of file
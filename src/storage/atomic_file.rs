@@ -0,0 +1,116 @@
+//! Crash-safe replacement of persistence files
+//!
+//! RDB snapshots and AOF rewrites are written to a temporary file next to the
+//! target, fsynced, renamed over it, and the directory is fsynced so the rename
+//! itself survives a crash. A failed write removes the temporary file and
+//! leaves the previous file untouched, so a save started from a script (CONFIG
+//! SET, DEBUG) or cut short by a full disk can never leave a truncated dump
+//! behind.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::error::{FerrousError, Result};
+
+/// Distinguishes temporary files of writers running at the same time
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Temporary file a new version of `path` is written to before the rename
+pub fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let id = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    path.with_file_name(format!("temp-{}-{}-{}", std::process::id(), id, name))
+}
+
+/// Replace `path` with the contents written by `write`, atomically
+///
+/// Either the whole new file is in place and on disk when this returns `Ok`,
+/// or `path` is unchanged and no temporary file is left behind.
+pub fn write_atomically<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<()>,
+{
+    let temp = temp_path(path);
+    let result = write_and_rename(path, &temp, write);
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn write_and_rename<F>(path: &Path, temp: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<()>,
+{
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temp)
+        .map_err(|e| FerrousError::Io(format!("Failed to create {}: {}", temp.display(), e)))?;
+    
+    let mut writer = BufWriter::new(file);
+    write(&mut writer)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()
+        .map_err(|e| FerrousError::Io(format!("Failed to fsync {}: {}", temp.display(), e)))?;
+    drop(writer);
+    
+    fs::rename(temp, path)
+        .map_err(|e| FerrousError::Io(format!("Failed to rename {} to {}: {}", temp.display(), path.display(), e)))?;
+    sync_parent_dir(path)
+}
+
+/// Fsync the directory holding `path`, making a rename into it durable
+pub fn sync_parent_dir(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .map_err(|e| FerrousError::Io(format!("Failed to fsync directory {}: {}", dir.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+    
+    #[test]
+    fn test_write_replaces_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dump.rdb");
+        fs::write(&path, b"old").unwrap();
+        
+        write_atomically(&path, |writer| Ok(writer.write_all(b"new")?)).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(entries(dir.path()), ["dump.rdb"]);
+    }
+    
+    #[test]
+    fn test_failed_write_keeps_old_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dump.rdb");
+        fs::write(&path, b"old").unwrap();
+        
+        let result = write_atomically(&path, |writer| {
+            writer.write_all(b"partial")?;
+            Err(FerrousError::Io("disk full".into()))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+        assert_eq!(entries(dir.path()), ["dump.rdb"]);
+        
+        // A missing directory fails up front and creates nothing
+        assert!(write_atomically(&dir.path().join("missing/dump.rdb"), |_| Ok(())).is_err());
+    }
+}
@@ -0,0 +1,389 @@
+//! Bitmap command implementations
+//!
+//! Provides SETBIT, GETBIT, BITCOUNT, BITPOS and BITOP over the raw bytes of
+//! string values. Bit 0 is the most significant bit of the first byte, as in
+//! Redis, so bitmaps round-trip through GET/SET unchanged.
+
+use crate::error::{FerrousError, Result, StorageError};
+use crate::protocol::RespFrame;
+use crate::storage::StorageEngine;
+use std::sync::Arc;
+
+/// Highest bit offset SETBIT accepts, keeping values within 512MB
+pub const MAX_BIT_OFFSET: u64 = 4 * 1024 * 1024 * 1024 - 1;
+
+/// Whether BITCOUNT/BITPOS ranges count bytes or bits
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitUnit {
+    Byte,
+    Bit,
+}
+
+impl BitUnit {
+    /// Parse a `BYTE` or `BIT` argument
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        match String::from_utf8_lossy(bytes).to_uppercase().as_str() {
+            "BYTE" => Some(BitUnit::Byte),
+            "BIT" => Some(BitUnit::Bit),
+            _ => None,
+        }
+    }
+}
+
+/// BITOP operators
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+impl BitOperation {
+    /// Parse the operator name
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        match String::from_utf8_lossy(bytes).to_uppercase().as_str() {
+            "AND" => Some(BitOperation::And),
+            "OR" => Some(BitOperation::Or),
+            "XOR" => Some(BitOperation::Xor),
+            "NOT" => Some(BitOperation::Not),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a bit offset, rejecting anything past `MAX_BIT_OFFSET`
+pub fn parse_offset(bytes: &[u8]) -> Option<usize> {
+    String::from_utf8_lossy(bytes).parse::<u64>().ok()
+        .filter(|offset| *offset <= MAX_BIT_OFFSET)
+        .map(|offset| offset as usize)
+}
+
+/// Read one bit, treating bits past the end as 0
+pub fn get_bit(value: &[u8], offset: usize) -> bool {
+    value.get(offset / 8).is_some_and(|byte| byte & (0x80 >> (offset % 8)) != 0)
+}
+
+/// Resolve an inclusive range with negative indexes from the end, clamped to `len` units
+fn resolve_range(len: usize, start: i64, end: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let end = if end < 0 { (len + end).max(0) } else { end.min(len - 1) };
+    if len == 0 || start > end {
+        None
+    } else {
+        Some((start as usize, end as usize))
+    }
+}
+
+/// The inclusive bit range a BITCOUNT/BITPOS range selects
+fn bit_range(value: &[u8], start: i64, end: i64, unit: BitUnit) -> Option<(usize, usize)> {
+    match unit {
+        BitUnit::Byte => resolve_range(value.len(), start, end).map(|(first, last)| (first * 8, last * 8 + 7)),
+        BitUnit::Bit => resolve_range(value.len() * 8, start, end),
+    }
+}
+
+/// Count the set bits of a value, optionally within a range
+pub fn count_bits(value: &[u8], range: Option<(i64, i64, BitUnit)>) -> usize {
+    let (first, last) = match range {
+        Some((start, end, unit)) => match bit_range(value, start, end, unit) {
+            Some(bits) => bits,
+            None => return 0,
+        },
+        None => (0, (value.len() * 8).saturating_sub(1)),
+    };
+    if value.is_empty() {
+        return 0;
+    }
+    
+    // Whole bytes in the middle are counted at once, partial ones bit by bit
+    let (first_byte, last_byte) = (first / 8, last / 8);
+    if first_byte == last_byte {
+        return (first..=last).filter(|offset| get_bit(value, *offset)).count();
+    }
+    let head = (first..(first_byte + 1) * 8).filter(|offset| get_bit(value, *offset)).count();
+    let middle: usize = value[first_byte + 1..last_byte].iter().map(|byte| byte.count_ones() as usize).sum();
+    let tail = (last_byte * 8..=last).filter(|offset| get_bit(value, *offset)).count();
+    head + middle + tail
+}
+
+/// Find the first bit set to `bit` in an existing value, as BITPOS does
+///
+/// Looking for a clear bit without an explicit end treats the value as
+/// padded with zeros, so a value of all ones yields the bit after its end.
+pub fn bit_position(value: &[u8], bit: bool, start: i64, end: Option<i64>, unit: BitUnit) -> i64 {
+    let Some((first, last)) = bit_range(value, start, end.unwrap_or(-1), unit) else {
+        return -1;
+    };
+    
+    // Skip whole bytes that cannot hold the bit
+    let skip = if bit { 0x00 } else { 0xff };
+    let mut offset = first;
+    while offset <= last {
+        if offset % 8 == 0 && offset + 7 <= last && value[offset / 8] == skip {
+            offset += 8;
+            continue;
+        }
+        if get_bit(value, offset) == bit {
+            return offset as i64;
+        }
+        offset += 1;
+    }
+    
+    if !bit && end.is_none() {
+        (last + 1) as i64
+    } else {
+        -1
+    }
+}
+
+/// Combine source values with a BITOP operator, padding shorter ones with zeros
+pub fn combine(operation: BitOperation, sources: &[Vec<u8>]) -> Vec<u8> {
+    let len = sources.iter().map(Vec::len).max().unwrap_or(0);
+    (0..len)
+        .map(|i| {
+            let mut bytes = sources.iter().map(|source| source.get(i).copied().unwrap_or(0));
+            let first = bytes.next().unwrap_or(0);
+            match operation {
+                BitOperation::And => bytes.fold(first, |acc, byte| acc & byte),
+                BitOperation::Or => bytes.fold(first, |acc, byte| acc | byte),
+                BitOperation::Xor => bytes.fold(first, |acc, byte| acc ^ byte),
+                BitOperation::Not => !first,
+            }
+        })
+        .collect()
+}
+
+/// Reply with an error matching a storage failure
+fn storage_error(e: FerrousError) -> Result<RespFrame> {
+    match e {
+        FerrousError::Storage(StorageError::WrongType) => {
+            Ok(RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"))
+        }
+        e => Ok(RespFrame::error(format!("ERR {}", e))),
+    }
+}
+
+fn bytes_arg(part: &RespFrame) -> Option<&[u8]> {
+    match part {
+        RespFrame::BulkString(Some(bytes)) => Some(bytes.as_slice()),
+        _ => None,
+    }
+}
+
+fn int_arg(part: &RespFrame) -> Option<i64> {
+    bytes_arg(part).and_then(|bytes| String::from_utf8_lossy(bytes).parse::<i64>().ok())
+}
+
+/// Handle SETBIT command - Set or clear one bit
+pub fn handle_setbit(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() != 4 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'setbit' command"));
+    }
+    
+    let Some(key) = bytes_arg(&parts[1]) else {
+        return Ok(RespFrame::error("ERR invalid key format"));
+    };
+    let Some(offset) = bytes_arg(&parts[2]).and_then(parse_offset) else {
+        return Ok(RespFrame::error("ERR bit offset is not an integer or out of range"));
+    };
+    let bit = match bytes_arg(&parts[3]) {
+        Some(b"0") => false,
+        Some(b"1") => true,
+        _ => return Ok(RespFrame::error("ERR bit is not an integer or out of range")),
+    };
+    
+    match storage.setbit(db, key.to_vec(), offset, bit) {
+        Ok(previous) => Ok(RespFrame::Integer(previous as i64)),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Handle GETBIT command - Read one bit
+pub fn handle_getbit(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() != 3 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'getbit' command"));
+    }
+    
+    let Some(key) = bytes_arg(&parts[1]) else {
+        return Ok(RespFrame::error("ERR invalid key format"));
+    };
+    let Some(offset) = bytes_arg(&parts[2]).and_then(parse_offset) else {
+        return Ok(RespFrame::error("ERR bit offset is not an integer or out of range"));
+    };
+    
+    match storage.get_string(db, key) {
+        Ok(value) => Ok(RespFrame::Integer(value.is_some_and(|value| get_bit(&value, offset)) as i64)),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Handle BITCOUNT command - Count set bits, optionally within a byte or bit range
+pub fn handle_bitcount(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 2 || parts.len() > 5 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'bitcount' command"));
+    }
+    if parts.len() == 3 {
+        return Ok(RespFrame::error("ERR syntax error"));
+    }
+    
+    let Some(key) = bytes_arg(&parts[1]) else {
+        return Ok(RespFrame::error("ERR invalid key format"));
+    };
+    let range = if parts.len() >= 4 {
+        let (Some(start), Some(end)) = (int_arg(&parts[2]), int_arg(&parts[3])) else {
+            return Ok(RespFrame::error("ERR value is not an integer or out of range"));
+        };
+        let unit = match parts.get(4).map(|part| bytes_arg(part).and_then(BitUnit::parse)) {
+            None => BitUnit::Byte,
+            Some(Some(unit)) => unit,
+            Some(None) => return Ok(RespFrame::error("ERR syntax error")),
+        };
+        Some((start, end, unit))
+    } else {
+        None
+    };
+    
+    match storage.get_string(db, key) {
+        Ok(value) => Ok(RespFrame::Integer(value.map_or(0, |value| count_bits(&value, range)) as i64)),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Handle BITPOS command - Find the first set or clear bit
+pub fn handle_bitpos(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 3 || parts.len() > 6 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'bitpos' command"));
+    }
+    
+    let Some(key) = bytes_arg(&parts[1]) else {
+        return Ok(RespFrame::error("ERR invalid key format"));
+    };
+    let bit = match bytes_arg(&parts[2]) {
+        Some(b"0") => false,
+        Some(b"1") => true,
+        _ => return Ok(RespFrame::error("ERR The bit argument must be 1 or 0.")),
+    };
+    let mut numbers = Vec::new();
+    for part in &parts[3..parts.len().min(5)] {
+        match int_arg(part) {
+            Some(number) => numbers.push(number),
+            None => return Ok(RespFrame::error("ERR value is not an integer or out of range")),
+        }
+    }
+    let unit = match parts.get(5).map(|part| bytes_arg(part).and_then(BitUnit::parse)) {
+        None => BitUnit::Byte,
+        Some(Some(unit)) => unit,
+        Some(None) => return Ok(RespFrame::error("ERR syntax error")),
+    };
+    
+    match storage.get_string(db, key) {
+        // A missing key is an endless run of clear bits
+        Ok(None) => Ok(RespFrame::Integer(if bit { -1 } else { 0 })),
+        Ok(Some(value)) => {
+            let start = numbers.first().copied().unwrap_or(0);
+            Ok(RespFrame::Integer(bit_position(&value, bit, start, numbers.get(1).copied(), unit)))
+        }
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Handle BITOP command - Combine string values bitwise into a destination key
+pub fn handle_bitop(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 4 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'bitop' command"));
+    }
+    
+    let Some(operation) = bytes_arg(&parts[1]).and_then(BitOperation::parse) else {
+        return Ok(RespFrame::error("ERR syntax error"));
+    };
+    if operation == BitOperation::Not && parts.len() != 4 {
+        return Ok(RespFrame::error("ERR BITOP NOT must be called with a single source key."));
+    }
+    let Some(dest) = bytes_arg(&parts[2]) else {
+        return Ok(RespFrame::error("ERR invalid key format"));
+    };
+    
+    let mut sources = Vec::with_capacity(parts.len() - 3);
+    for part in &parts[3..] {
+        let Some(key) = bytes_arg(part) else {
+            return Ok(RespFrame::error("ERR invalid key format"));
+        };
+        match storage.get_string(db, key) {
+            Ok(value) => sources.push(value.unwrap_or_default()),
+            Err(e) => return storage_error(e),
+        }
+    }
+    
+    match store_result(storage, db, dest, combine(operation, &sources)) {
+        Ok(len) => Ok(RespFrame::Integer(len as i64)),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Store a BITOP result, deleting the destination when it is empty
+pub fn store_result(storage: &StorageEngine, db: usize, dest: &[u8], result: Vec<u8>) -> Result<usize> {
+    let len = result.len();
+    if result.is_empty() {
+        storage.delete(db, dest)?;
+    } else {
+        storage.set_string(db, dest.to_vec(), result)?;
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_count_bits_ranges() {
+        let value = b"foobar";
+        assert_eq!(count_bits(value, None), 26);
+        assert_eq!(count_bits(value, Some((0, 0, BitUnit::Byte))), 4);
+        assert_eq!(count_bits(value, Some((1, 1, BitUnit::Byte))), 6);
+        assert_eq!(count_bits(value, Some((-2, -1, BitUnit::Byte))), 7);
+        assert_eq!(count_bits(value, Some((5, 30, BitUnit::Bit))), 17);
+        assert_eq!(count_bits(value, Some((3, 1, BitUnit::Byte))), 0);
+        assert_eq!(count_bits(b"", Some((0, -1, BitUnit::Byte))), 0);
+    }
+    
+    #[test]
+    fn test_bit_position() {
+        assert_eq!(bit_position(&[0xff, 0xf0, 0x00], false, 0, None, BitUnit::Byte), 12);
+        assert_eq!(bit_position(&[0x00, 0xff, 0xf0], true, 0, None, BitUnit::Byte), 8);
+        assert_eq!(bit_position(&[0x00, 0xff, 0xf0], true, 2, Some(-1), BitUnit::Byte), 16);
+        assert_eq!(bit_position(&[0x00, 0xff, 0xf0], true, 7, Some(15), BitUnit::Bit), 8);
+        assert_eq!(bit_position(&[0xff, 0xff], false, 0, None, BitUnit::Byte), 16);
+        assert_eq!(bit_position(&[0xff, 0xff], false, 0, Some(-1), BitUnit::Byte), -1);
+        assert_eq!(bit_position(&[0x00], true, 0, None, BitUnit::Byte), -1);
+    }
+    
+    #[test]
+    fn test_combine() {
+        let sources = [b"foobar".to_vec(), b"abcdef".to_vec()];
+        assert_eq!(combine(BitOperation::And, &sources), b"`bc`ab");
+        assert_eq!(combine(BitOperation::Or, &sources), b"goofev");
+        assert_eq!(combine(BitOperation::Xor, &[vec![0x0f, 0xff], vec![0xff]]), [0xf0, 0xff]);
+        assert_eq!(combine(BitOperation::And, &[vec![0xff, 0xff], vec![0xff]]), [0xff, 0x00]);
+        assert_eq!(combine(BitOperation::Not, &[vec![0x0f]]), [0xf0]);
+    }
+    
+    #[test]
+    fn test_setbit_keeps_ttl_and_checks_type() {
+        let storage = StorageEngine::new_in_memory();
+        storage.set_string_ex(0, b"b".to_vec(), Vec::new(), std::time::Duration::from_secs(100)).unwrap();
+        assert!(!storage.setbit(0, b"b".to_vec(), 7, true).unwrap());
+        assert!(storage.setbit(0, b"b".to_vec(), 7, false).unwrap());
+        assert!(storage.setbit(0, b"new".to_vec(), 9, true).is_ok());
+        assert_eq!(storage.get_string(0, b"b").unwrap(), Some(vec![0x00]));
+        assert_eq!(storage.get_string(0, b"new").unwrap(), Some(vec![0x00, 0x40]));
+        assert!(storage.ttl(0, b"b").unwrap().is_some());
+        
+        storage.sadd(0, b"s".to_vec(), vec![b"m".to_vec()]).unwrap();
+        assert!(storage.setbit(0, b"s".to_vec(), 0, true).is_err());
+        assert_eq!(parse_offset(b"4294967295"), Some(MAX_BIT_OFFSET as usize));
+        assert_eq!(parse_offset(b"4294967296"), None);
+    }
+}
@@ -3,17 +3,32 @@
 //! This module provides Redis-compatible CLIENT commands for
 //! examining and managing client connections.
 
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 use crate::error::Result;
 use crate::protocol::RespFrame;
 use crate::network::Connection;
+use crate::network::pause::{self, PauseGate, PauseMode};
+use crate::network::tracking::{self, TrackingOptions, TrackingTable};
+use crate::pubsub::PubSubManager;
+
+/// Server state CLIENT subcommands act on, besides the connections
+pub struct ClientContext<'a> {
+    /// CLIENT PAUSE gate, where pausing is supported
+    pub pause: Option<&'a mut PauseGate>,
+    
+    /// Keys and prefixes followed by CLIENT TRACKING
+    pub tracking: &'a TrackingTable,
+    
+    /// Subscriptions, telling pub/sub clients apart
+    pub pubsub: &'a PubSubManager,
+}
 
 /// Handle the CLIENT command and its various subcommands
 pub fn handle_client(
     parts: &[RespFrame], 
     connections: &impl ConnectionProvider, 
     this_conn_id: u64,
-    paused_until: Option<&mut SystemTime>
+    ctx: ClientContext,
 ) -> Result<RespFrame> {
     if parts.len() < 2 {
         return Ok(RespFrame::error("ERR wrong number of arguments for 'client' command"));
@@ -28,12 +43,18 @@ pub fn handle_client(
     
     match subcommand.as_str() {
         "LIST" => handle_client_list(parts, connections),
-        "KILL" => handle_client_kill(parts, connections, this_conn_id),
+        "KILL" => handle_client_kill(parts, connections, this_conn_id, ctx.pubsub),
         "SETNAME" => handle_client_setname(parts, connections, this_conn_id),
         "GETNAME" => handle_client_getname(parts, connections, this_conn_id),
         "ID" => handle_client_id(parts, this_conn_id),
-        "PAUSE" => handle_client_pause(parts, paused_until),
-        "UNPAUSE" => handle_client_unpause(parts, paused_until),
+        "PAUSE" => handle_client_pause(parts, ctx.pause),
+        "UNPAUSE" => handle_client_unpause(parts, ctx.pause),
+        "NO-TOUCH" => handle_client_no_touch(parts, connections, this_conn_id),
+        "NO-EVICT" => handle_client_no_evict(parts, connections, this_conn_id),
+        "TRACKING" => handle_client_tracking(parts, connections, this_conn_id, ctx.tracking),
+        "CACHING" => handle_client_caching(parts, connections, this_conn_id),
+        "GETREDIR" => handle_client_getredir(parts, connections, this_conn_id),
+        "TRACKINGINFO" => handle_client_trackinginfo(parts, connections, this_conn_id),
         _ => Ok(RespFrame::error(format!("ERR Unknown subcommand or wrong number of arguments for '{}'", subcommand))),
     }
 }
@@ -67,16 +88,18 @@ fn handle_client_list(parts: &[RespFrame], connections: &impl ConnectionProvider
             let age_secs = conn.created_at.elapsed().as_secs();
             
             format!(
-                "id={} addr={} fd={} name={} age={} idle={} flags={} db={} cmd={}\n",
+                "id={} addr={} laddr={} fd={} name={} age={} idle={} flags={} db={} cmd={} user={}\n",
                 id,
                 conn.addr,
+                conn.local_addr,
                 id, // Using ID as a proxy for file descriptor
                 conn.name.as_deref().unwrap_or(""),
                 age_secs,
                 conn.idle_time().as_secs(),
                 get_client_flags(conn),
                 conn.db_index,
-                "unknown" // We're not tracking last command yet
+                "unknown", // We're not tracking last command yet
+                conn.user
             )
         });
         
@@ -89,78 +112,134 @@ fn handle_client_list(parts: &[RespFrame], connections: &impl ConnectionProvider
     Ok(RespFrame::from_string(result))
 }
 
+/// Filters of CLIENT KILL, all of which a client must match
+#[derive(Default)]
+struct KillFilter {
+    id: Option<u64>,
+    addr: Option<String>,
+    laddr: Option<String>,
+    kind: Option<ClientKind>,
+    max_age: Option<u64>,
+    skip_me: bool,
+}
+
+/// Client types of CLIENT KILL TYPE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClientKind {
+    Normal,
+    Master,
+    Replica,
+    PubSub,
+}
+
 /// Handle the CLIENT KILL command
-fn handle_client_kill(parts: &[RespFrame], connections: &impl ConnectionProvider, this_conn_id: u64) -> Result<RespFrame> {
+///
+/// Either the old `CLIENT KILL addr:port` form, replying OK, or filters
+/// (ID, ADDR, LADDR, TYPE, USER, MAXAGE, SKIPME) replying with the number of
+/// clients killed.
+fn handle_client_kill(
+    parts: &[RespFrame],
+    connections: &impl ConnectionProvider,
+    this_conn_id: u64,
+    pubsub: &PubSubManager,
+) -> Result<RespFrame> {
     if parts.len() < 3 {
         return Ok(RespFrame::error("ERR wrong number of arguments for 'client kill' command"));
     }
     
-    // Simplified CLIENT KILL implementation - just by ID or ADDRESS
-    // Redis has more complex filtering options
+    let mut args = Vec::with_capacity(parts.len() - 2);
+    for part in &parts[2..] {
+        match part {
+            RespFrame::BulkString(Some(bytes)) => args.push(String::from_utf8_lossy(bytes).to_string()),
+            _ => return Ok(RespFrame::error("ERR syntax error")),
+        }
+    }
     
-    let arg_type = match &parts[2] {
-        RespFrame::BulkString(Some(bytes)) => String::from_utf8_lossy(bytes).to_uppercase(),
-        _ => return Ok(RespFrame::error("ERR syntax error")),
-    };
+    // Old form: a single address, and killing ourselves is allowed
+    if args.len() == 1 {
+        let filter = KillFilter { addr: Some(args.remove(0)), ..Default::default() };
+        let killed = kill_matching(&filter, connections, this_conn_id, pubsub);
+        return Ok(if killed > 0 { RespFrame::ok() } else { RespFrame::error("ERR No such client") });
+    }
     
-    match arg_type.as_str() {
-        "ID" => {
-            if parts.len() != 4 {
-                return Ok(RespFrame::error("ERR syntax error"));
+    if args.len() % 2 != 0 {
+        return Ok(RespFrame::error("ERR syntax error"));
+    }
+    
+    let mut filter = KillFilter { skip_me: true, ..Default::default() };
+    for pair in args.chunks(2) {
+        let value = &pair[1];
+        match pair[0].to_uppercase().as_str() {
+            "ID" => match value.parse::<u64>() {
+                Ok(id) if id > 0 => filter.id = Some(id),
+                _ => return Ok(RespFrame::error("ERR client-id should be greater than 0")),
+            },
+            "ADDR" => filter.addr = Some(value.clone()),
+            "LADDR" => filter.laddr = Some(value.clone()),
+            "TYPE" => {
+                filter.kind = Some(match value.to_lowercase().as_str() {
+                    "normal" => ClientKind::Normal,
+                    "master" => ClientKind::Master,
+                    "replica" | "slave" => ClientKind::Replica,
+                    "pubsub" => ClientKind::PubSub,
+                    _ => return Ok(RespFrame::error(format!("ERR Unknown client type '{}'", value))),
+                });
             }
-            
-            let id_str = match &parts[3] {
-                RespFrame::BulkString(Some(bytes)) => String::from_utf8_lossy(bytes),
-                _ => return Ok(RespFrame::error("ERR syntax error")),
-            };
-            
-            let id = match id_str.parse::<u64>() {
-                Ok(id) => id,
+            // Every client is the default user until ACLs exist
+            "USER" if value == "default" => {}
+            "USER" => return Ok(RespFrame::error(format!("ERR No such user '{}'", value))),
+            "MAXAGE" => match value.parse::<u64>() {
+                Ok(age) => filter.max_age = Some(age),
                 Err(_) => return Ok(RespFrame::error("ERR value is not an integer or out of range")),
-            };
-            
-            // Don't allow killing self
-            if id == this_conn_id {
-                return Ok(RespFrame::error("ERR cannot kill self"));
-            }
-            
-            if connections.close_connection(id) {
-                Ok(RespFrame::Integer(1)) // 1 client killed
-            } else {
-                Ok(RespFrame::Integer(0)) // No clients killed
-            }
-        },
-        "ADDR" => {
-            if parts.len() != 4 {
-                return Ok(RespFrame::error("ERR syntax error"));
-            }
-            
-            let addr_str = match &parts[3] {
-                RespFrame::BulkString(Some(bytes)) => String::from_utf8_lossy(bytes),
+            },
+            "SKIPME" => match value.to_lowercase().as_str() {
+                "yes" => filter.skip_me = true,
+                "no" => filter.skip_me = false,
                 _ => return Ok(RespFrame::error("ERR syntax error")),
+            },
+            _ => return Ok(RespFrame::error("ERR syntax error")),
+        }
+    }
+    
+    let killed = kill_matching(&filter, connections, this_conn_id, pubsub);
+    Ok(RespFrame::Integer(killed as i64))
+}
+
+/// Close every client matching a CLIENT KILL filter, returning how many were
+fn kill_matching(
+    filter: &KillFilter,
+    connections: &impl ConnectionProvider,
+    this_conn_id: u64,
+    pubsub: &PubSubManager,
+) -> usize {
+    let mut killed = 0;
+    
+    for id in connections.all_connection_ids() {
+        if (filter.skip_me && id == this_conn_id) || filter.id.is_some_and(|wanted| wanted != id) {
+            continue;
+        }
+        
+        let matches = connections.with_connection(id, |conn| {
+            let kind = if conn.is_replica {
+                ClientKind::Replica
+            } else if pubsub.is_subscribed(id) {
+                ClientKind::PubSub
+            } else {
+                ClientKind::Normal
             };
             
-            let mut killed = 0;
-            
-            for id in connections.all_connection_ids() {
-                // Skip self
-                if id == this_conn_id {
-                    continue;
-                }
-                
-                let should_kill = connections.with_connection(id, |conn| {
-                    conn.addr.to_string() == addr_str
-                }).unwrap_or(false);
-                
-                if should_kill && connections.close_connection(id) {
-                    killed += 1;
-                }
-            }
-            
-            Ok(RespFrame::Integer(killed))
-        },
-        _ => Ok(RespFrame::error("ERR syntax error")),
+            filter.addr.as_ref().is_none_or(|addr| conn.addr.to_string() == *addr)
+                && filter.laddr.as_ref().is_none_or(|laddr| conn.local_addr.to_string() == *laddr)
+                && filter.kind.is_none_or(|wanted| wanted == kind)
+                && filter.max_age.is_none_or(|age| conn.created_at.elapsed().as_secs() >= age)
+        }).unwrap_or(false);
+        
+        if matches && connections.close_connection(id) {
+            killed += 1;
+        }
     }
+    
+    killed
 }
 
 /// Handle the CLIENT SETNAME command
@@ -213,9 +292,9 @@ fn handle_client_id(parts: &[RespFrame], conn_id: u64) -> Result<RespFrame> {
     Ok(RespFrame::Integer(conn_id as i64))
 }
 
-/// Handle the CLIENT PAUSE command
-fn handle_client_pause(parts: &[RespFrame], paused_until: Option<&mut SystemTime>) -> Result<RespFrame> {
-    if parts.len() != 3 {
+/// Handle the CLIENT PAUSE timeout [WRITE|ALL] command
+fn handle_client_pause(parts: &[RespFrame], gate: Option<&mut PauseGate>) -> Result<RespFrame> {
+    if parts.len() != 3 && parts.len() != 4 {
         return Ok(RespFrame::error("ERR wrong number of arguments for 'client pause' command"));
     }
     
@@ -235,33 +314,289 @@ fn handle_client_pause(parts: &[RespFrame], paused_until: Option<&mut SystemTime
         return Ok(RespFrame::error("ERR timeout is out of range"));
     }
     
-    // Set pause until timestamp
-    if let Some(paused) = paused_until {
-        let now = SystemTime::now();
-        *paused = now + Duration::from_millis(timeout_ms);
+    // Everything is paused unless only writes are
+    let mode = match parts.get(3) {
+        None => PauseMode::All,
+        Some(RespFrame::BulkString(Some(bytes))) => match pause::parse_mode(bytes) {
+            Some(mode) => mode,
+            None => return Ok(RespFrame::error("ERR syntax error")),
+        },
+        Some(_) => return Ok(RespFrame::error("ERR syntax error")),
+    };
+    
+    if let Some(gate) = gate {
+        gate.pause(Duration::from_millis(timeout_ms), mode);
         Ok(RespFrame::ok())
     } else {
-        // If no pause_until field is available
+        // If no pause gate is available
         Ok(RespFrame::error("ERR client pause not supported in this context"))
     }
 }
 
 /// Handle the CLIENT UNPAUSE command
-fn handle_client_unpause(parts: &[RespFrame], paused_until: Option<&mut SystemTime>) -> Result<RespFrame> {
+fn handle_client_unpause(parts: &[RespFrame], gate: Option<&mut PauseGate>) -> Result<RespFrame> {
     if parts.len() != 2 {
         return Ok(RespFrame::error("ERR wrong number of arguments for 'client unpause' command"));
     }
     
-    if let Some(paused) = paused_until {
-        // Set to UNIX_EPOCH to effectively disable the pause
-        *paused = UNIX_EPOCH;
+    if let Some(gate) = gate {
+        gate.unpause();
         Ok(RespFrame::ok())
     } else {
-        // If no pause_until field is available
+        // If no pause gate is available
         Ok(RespFrame::error("ERR client pause not supported in this context"))
     }
 }
 
+/// Handle the CLIENT NO-TOUCH command
+fn handle_client_no_touch(parts: &[RespFrame], connections: &impl ConnectionProvider, this_conn_id: u64) -> Result<RespFrame> {
+    if parts.len() != 3 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'client no-touch' command"));
+    }
+    
+    let no_touch = match &parts[2] {
+        RespFrame::BulkString(Some(bytes)) => match String::from_utf8_lossy(bytes).to_uppercase().as_str() {
+            "ON" => true,
+            "OFF" => false,
+            _ => return Ok(RespFrame::error("ERR syntax error")),
+        },
+        _ => return Ok(RespFrame::error("ERR syntax error")),
+    };
+    
+    match connections.with_connection(this_conn_id, |conn| conn.no_touch = no_touch) {
+        Some(()) => Ok(RespFrame::ok()),
+        None => Ok(RespFrame::error("ERR connection not found")),
+    }
+}
+
+/// Handle the CLIENT NO-EVICT command
+///
+/// Exempts the client from client eviction (maxmemory-clients).
+fn handle_client_no_evict(parts: &[RespFrame], connections: &impl ConnectionProvider, this_conn_id: u64) -> Result<RespFrame> {
+    if parts.len() != 3 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'client no-evict' command"));
+    }
+    
+    let no_evict = match &parts[2] {
+        RespFrame::BulkString(Some(bytes)) => match String::from_utf8_lossy(bytes).to_uppercase().as_str() {
+            "ON" => true,
+            "OFF" => false,
+            _ => return Ok(RespFrame::error("ERR syntax error")),
+        },
+        _ => return Ok(RespFrame::error("ERR syntax error")),
+    };
+    
+    match connections.with_connection(this_conn_id, |conn| conn.no_evict = no_evict) {
+        Some(()) => Ok(RespFrame::ok()),
+        None => Ok(RespFrame::error("ERR connection not found")),
+    }
+}
+
+/// Handle the CLIENT TRACKING command
+///
+/// CLIENT TRACKING ON|OFF [REDIRECT id] [PREFIX prefix ...] [BCAST] [OPTIN] [OPTOUT] [NOLOOP]
+fn handle_client_tracking(
+    parts: &[RespFrame],
+    connections: &impl ConnectionProvider,
+    this_conn_id: u64,
+    tracking: &TrackingTable,
+) -> Result<RespFrame> {
+    if parts.len() < 3 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'client tracking' command"));
+    }
+    
+    let enable = match &parts[2] {
+        RespFrame::BulkString(Some(bytes)) => match String::from_utf8_lossy(bytes).to_uppercase().as_str() {
+            "ON" => true,
+            "OFF" => false,
+            _ => return Ok(RespFrame::error("ERR syntax error")),
+        },
+        _ => return Ok(RespFrame::error("ERR syntax error")),
+    };
+    
+    let mut options = TrackingOptions::default();
+    let mut i = 3;
+    while i < parts.len() {
+        let option = match &parts[i] {
+            RespFrame::BulkString(Some(bytes)) => String::from_utf8_lossy(bytes).to_uppercase(),
+            _ => return Ok(RespFrame::error("ERR syntax error")),
+        };
+        match option.as_str() {
+            "REDIRECT" | "PREFIX" if i + 1 < parts.len() => {
+                let RespFrame::BulkString(Some(value)) = &parts[i + 1] else {
+                    return Ok(RespFrame::error("ERR syntax error"));
+                };
+                if option == "PREFIX" {
+                    options.prefixes.push(value.to_vec());
+                } else {
+                    let id = match std::str::from_utf8(value).ok().and_then(|s| s.parse::<u64>().ok()) {
+                        Some(id) => id,
+                        None => return Ok(RespFrame::error("ERR value is not an integer or out of range")),
+                    };
+                    if connections.with_connection(id, |_| ()).is_none() {
+                        return Ok(RespFrame::error("ERR The client ID you want redirect to does not exist"));
+                    }
+                    options.redirect = Some(id);
+                }
+                i += 2;
+                continue;
+            }
+            "BCAST" => options.bcast = true,
+            "OPTIN" => options.optin = true,
+            "OPTOUT" => options.optout = true,
+            "NOLOOP" => options.noloop = true,
+            _ => return Ok(RespFrame::error("ERR syntax error")),
+        }
+        i += 1;
+    }
+    
+    if !enable {
+        connections.with_connection(this_conn_id, |conn| conn.tracking = None);
+        tracking.disable(this_conn_id);
+        return Ok(RespFrame::ok());
+    }
+    
+    if !options.prefixes.is_empty() && !options.bcast {
+        return Ok(RespFrame::error("ERR PREFIX option requires BCAST mode to be enabled"));
+    }
+    if options.optin && options.optout {
+        return Ok(RespFrame::error("ERR You can't use both OPTIN and OPTOUT"));
+    }
+    if options.bcast && (options.optin || options.optout) {
+        return Ok(RespFrame::error("ERR OPTIN and OPTOUT are not compatible with BCAST"));
+    }
+    
+    let current = connections.with_connection(this_conn_id, |conn| conn.tracking.clone()).flatten();
+    if let Some(current) = current {
+        if current.bcast != options.bcast {
+            return Ok(RespFrame::error(
+                "ERR You can't switch BCAST mode on/off before disabling tracking for this client, and then re-enabling it with a different mode."
+            ));
+        }
+        // Turning tracking on again adds prefixes to the ones already followed
+        let mut prefixes = current.prefixes;
+        prefixes.retain(|prefix| !options.prefixes.contains(prefix));
+        prefixes.append(&mut options.prefixes);
+        options.prefixes = prefixes;
+    }
+    if let Some((a, b)) = tracking::overlapping_prefixes(&options.prefixes) {
+        return Ok(RespFrame::error(format!(
+            "ERR Prefix '{}' overlaps with an existing prefix '{}'. Prefixes for a single client must not overlap.",
+            String::from_utf8_lossy(a),
+            String::from_utf8_lossy(b)
+        )));
+    }
+    
+    tracking.enable(this_conn_id, &options);
+    match connections.with_connection(this_conn_id, |conn| conn.tracking = Some(options)) {
+        Some(()) => Ok(RespFrame::ok()),
+        None => Ok(RespFrame::error("ERR connection not found")),
+    }
+}
+
+/// Handle the CLIENT CACHING command
+fn handle_client_caching(parts: &[RespFrame], connections: &impl ConnectionProvider, this_conn_id: u64) -> Result<RespFrame> {
+    if parts.len() != 3 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'client caching' command"));
+    }
+    
+    let caching = match &parts[2] {
+        RespFrame::BulkString(Some(bytes)) => match String::from_utf8_lossy(bytes).to_uppercase().as_str() {
+            "YES" => true,
+            "NO" => false,
+            _ => return Ok(RespFrame::error("ERR syntax error")),
+        },
+        _ => return Ok(RespFrame::error("ERR syntax error")),
+    };
+    
+    let result = connections.with_connection(this_conn_id, |conn| {
+        match &mut conn.tracking {
+            Some(options) if options.optin || options.optout => {
+                if caching && !options.optin {
+                    Err("ERR CLIENT CACHING YES is only valid when tracking is enabled in OPTIN mode.")
+                } else if !caching && !options.optout {
+                    Err("ERR CLIENT CACHING NO is only valid when tracking is enabled in OPTOUT mode.")
+                } else {
+                    options.caching = Some(caching);
+                    Ok(())
+                }
+            }
+            _ => Err("ERR CLIENT CACHING can be called only when the client is in tracking mode with OPTIN or OPTOUT mode enabled"),
+        }
+    });
+    
+    match result {
+        Some(Ok(())) => Ok(RespFrame::ok()),
+        Some(Err(e)) => Ok(RespFrame::error(e)),
+        None => Ok(RespFrame::error("ERR connection not found")),
+    }
+}
+
+/// Handle the CLIENT GETREDIR command: -1 when not tracking, 0 without redirection
+fn handle_client_getredir(parts: &[RespFrame], connections: &impl ConnectionProvider, this_conn_id: u64) -> Result<RespFrame> {
+    if parts.len() != 2 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'client getredir' command"));
+    }
+    
+    let redirect = connections.with_connection(this_conn_id, |conn| {
+        conn.tracking.as_ref().map_or(-1, |options| options.redirect.unwrap_or(0) as i64)
+    });
+    
+    match redirect {
+        Some(redirect) => Ok(RespFrame::Integer(redirect)),
+        None => Ok(RespFrame::error("ERR connection not found")),
+    }
+}
+
+/// Handle the CLIENT TRACKINGINFO command
+fn handle_client_trackinginfo(parts: &[RespFrame], connections: &impl ConnectionProvider, this_conn_id: u64) -> Result<RespFrame> {
+    if parts.len() != 2 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'client trackinginfo' command"));
+    }
+    
+    let info = connections.with_connection(this_conn_id, |conn| (conn.tracking.clone(), conn.protocol));
+    let Some((options, protocol)) = info else {
+        return Ok(RespFrame::error("ERR connection not found"));
+    };
+    
+    let (flags, redirect, prefixes) = match options {
+        None => (vec!["off"], -1, Vec::new()),
+        Some(options) => {
+            let mut flags = vec!["on"];
+            if options.bcast {
+                flags.push("bcast");
+            }
+            if options.optin {
+                flags.push("optin");
+                if options.caching == Some(true) {
+                    flags.push("caching-yes");
+                }
+            }
+            if options.optout {
+                flags.push("optout");
+                if options.caching == Some(false) {
+                    flags.push("caching-no");
+                }
+            }
+            if options.noloop {
+                flags.push("noloop");
+            }
+            (flags, options.redirect.map_or(0, |id| id as i64), options.prefixes)
+        }
+    };
+    
+    let fields = vec![
+        (RespFrame::bulk_string("flags"), RespFrame::array(flags.into_iter().map(RespFrame::bulk_string).collect())),
+        (RespFrame::bulk_string("redirect"), RespFrame::Integer(redirect)),
+        (RespFrame::bulk_string("prefixes"), RespFrame::array(prefixes.into_iter().map(RespFrame::from_bytes).collect())),
+    ];
+    if protocol >= 3 {
+        Ok(RespFrame::Map(fields))
+    } else {
+        Ok(RespFrame::array(fields.into_iter().flat_map(|(k, v)| [k, v]).collect()))
+    }
+}
+
 /// Helper to format client flags
 fn get_client_flags(conn: &Connection) -> String {
     let mut flags = Vec::new();
@@ -274,6 +609,22 @@ fn get_client_flags(conn: &Connection) -> String {
         flags.push("x"); // In MULTI/EXEC transaction
     }
     
+    if conn.no_touch {
+        flags.push("T"); // CLIENT NO-TOUCH
+    }
+    
+    if conn.no_evict {
+        flags.push("e"); // CLIENT NO-EVICT
+    }
+    
+    if conn.is_replica {
+        flags.push("S"); // Replica
+    }
+    
+    if conn.tracking.is_some() {
+        flags.push("t"); // CLIENT TRACKING
+    }
+    
     // Add more flags as needed
     
     // Default to 'N' (normal) if no special flags
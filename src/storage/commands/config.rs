@@ -3,14 +3,72 @@
 //! Provides Redis-compatible CONFIG command implementation for better compatibility
 //! with Redis benchmarking tools.
 
+use crate::config::{parse_size, parse_yes_no, Config, ConfigParseError};
 use crate::error::Result;
 use crate::protocol::RespFrame;
+use crate::pubsub::pattern_matches;
+use crate::storage::lua_engine::LuaEngine;
+
+/// Scripting parameters backed by the live Lua engine
+pub const SCRIPTING_PARAMS: [&str; 6] = [
+    "lua-time-limit",
+    "lua-max-instructions",
+    "lua-max-call-depth",
+    "lua-memory-limit",
+    "lua-deterministic",
+    "lua-enable-debug-library",
+];
+
+/// Current value of a scripting parameter, or `None` if `param` is not one
+pub fn scripting_config_get(engine: &LuaEngine, param: &str) -> Option<String> {
+    let yes_no = |enabled: bool| if enabled { "yes" } else { "no" }.to_string();
+    match param.to_lowercase().as_str() {
+        "lua-time-limit" => Some(engine.time_limit_ms().to_string()),
+        "lua-max-instructions" => Some(engine.max_instructions().to_string()),
+        "lua-max-call-depth" => Some(engine.max_call_depth().to_string()),
+        "lua-memory-limit" => Some(engine.memory_limit().to_string()),
+        "lua-deterministic" => Some(yes_no(engine.deterministic())),
+        "lua-enable-debug-library" => Some(yes_no(engine.debug_library_enabled())),
+        _ => None,
+    }
+}
+
+/// Apply a scripting parameter to the live Lua engine
+///
+/// Returns `None` if `param` is not a scripting parameter, otherwise the
+/// CONFIG SET reply. New values take effect for the next script.
+pub fn scripting_config_set(engine: &LuaEngine, param: &str, value: &str) -> Option<RespFrame> {
+    let param = param.to_lowercase();
+    let applied = match param.as_str() {
+        "lua-time-limit" => value.parse().map(|ms| engine.set_time_limit_ms(ms)).is_ok(),
+        "lua-max-instructions" => value.parse().map(|n| engine.set_max_instructions(n)).is_ok(),
+        "lua-max-call-depth" => value.parse().map(|depth| engine.set_max_call_depth(depth)).is_ok(),
+        "lua-memory-limit" => parse_size(&param, value, 0)
+            .map(|bytes| engine.set_memory_limit(bytes as usize)).is_ok(),
+        "lua-deterministic" => parse_yes_no(&param, value, 0)
+            .map(|enabled| engine.set_deterministic(enabled)).is_ok(),
+        "lua-enable-debug-library" => parse_yes_no(&param, value, 0)
+            .map(|enabled| engine.set_debug_library_enabled(enabled)).is_ok(),
+        _ => return None,
+    };
+    
+    Some(if applied {
+        RespFrame::ok()
+    } else {
+        RespFrame::error(format!(
+            "ERR CONFIG SET failed (possibly related to argument '{}') - invalid value '{}'",
+            param, value
+        ))
+    })
+}
 
 /// Handle CONFIG command
 /// 
-/// redis-benchmark uses this command to fetch server configuration.
-/// If we don't handle it properly, benchmarks will error out with "Could not fetch server CONFIG".
-pub fn handle_config(parts: &[RespFrame]) -> Result<RespFrame> {
+/// GET is answered from the live configuration, which redis-benchmark also
+/// fetches at startup ("Could not fetch server CONFIG" otherwise). SET,
+/// REWRITE and RESETSTAT change server state and are handled by the server;
+/// they only get here with the wrong number of arguments.
+pub fn handle_config(parts: &[RespFrame], config: &Config) -> Result<RespFrame> {
     if parts.len() < 2 {
         return Ok(RespFrame::error("ERR wrong number of arguments for 'config' command"));
     }
@@ -26,109 +84,89 @@ pub fn handle_config(parts: &[RespFrame]) -> Result<RespFrame> {
     match subcommand.as_str() {
         "GET" => {
             if parts.len() < 3 {
-                return Ok(RespFrame::error("ERR wrong number of arguments for 'config get' command"));
+                return Ok(RespFrame::error("ERR wrong number of arguments for 'config|get' command"));
             }
             
-            // Extract parameter
-            let param = match &parts[2] {
-                RespFrame::BulkString(Some(bytes)) => {
-                    String::from_utf8_lossy(bytes).to_string()
-                },
-                _ => return Ok(RespFrame::error("ERR invalid parameter format")),
-            };
+            // Extract parameter patterns
+            let mut patterns = Vec::new();
+            for part in &parts[2..] {
+                match part {
+                    RespFrame::BulkString(Some(bytes)) => patterns.push(bytes.to_ascii_lowercase()),
+                    _ => return Ok(RespFrame::error("ERR invalid parameter format")),
+                }
+            }
             
-            handle_config_get(&param)
+            Ok(handle_config_get(config, &patterns))
         },
-        "SET" => Ok(RespFrame::error("ERR CONFIG SET not supported")),
-        "RESETSTAT" => Ok(RespFrame::error("ERR CONFIG RESETSTAT not supported")),
-        "REWRITE" => Ok(RespFrame::error("ERR CONFIG REWRITE not supported")),
+        "SET" => Ok(RespFrame::error("ERR wrong number of arguments for 'config|set' command")),
+        "REWRITE" => Ok(RespFrame::error("ERR wrong number of arguments for 'config|rewrite' command")),
+        "RESETSTAT" => Ok(RespFrame::error("ERR wrong number of arguments for 'config|resetstat' command")),
         _ => Ok(RespFrame::error("ERR CONFIG command not supported")),
     }
 }
 
-/// Handle CONFIG GET parameter
-fn handle_config_get(param: &str) -> Result<RespFrame> {
-    // If parameter is "*" or "save", return all configs
-    if param == "*" {
-        return generate_all_configs();
+/// Name/value pairs of every parameter matching one of the glob patterns
+fn handle_config_get(config: &Config, patterns: &[Vec<u8>]) -> RespFrame {
+    let mut values = Vec::new();
+    for (param, value) in config.get_all() {
+        if patterns.iter().any(|pattern| pattern_matches(pattern, param.as_bytes())) {
+            values.push(RespFrame::from_string(param));
+            values.push(RespFrame::from_string(value));
+        }
     }
-    
-    // Handle common parameters that redis-benchmark needs
-    match param.to_lowercase().as_str() {
-        "save" => {
-            let values = vec![
-                RespFrame::from_string("save"),
-                RespFrame::from_string("900 1 300 10 60 10000"),
-            ];
-            Ok(RespFrame::Array(Some(values)))
-        },
-        "appendonly" => {
-            let values = vec![
-                RespFrame::from_string("appendonly"),
-                RespFrame::from_string("no"),
-            ];
-            Ok(RespFrame::Array(Some(values)))
-        },
-        "maxmemory" => {
-            let values = vec![
-                RespFrame::from_string("maxmemory"),
-                RespFrame::from_string("0"),
-            ];
-            Ok(RespFrame::Array(Some(values)))
-        },
-        "maxmemory-policy" => {
-            let values = vec![
-                RespFrame::from_string("maxmemory-policy"),
-                RespFrame::from_string("noeviction"),
-            ];
-            Ok(RespFrame::Array(Some(values)))
-        },
-        "timeout" => {
-            let values = vec![
-                RespFrame::from_string("timeout"),
-                RespFrame::from_string("0"),
-            ];
-            Ok(RespFrame::Array(Some(values)))
-        },
-        "databases" => {
-            let values = vec![
-                RespFrame::from_string("databases"),
-                RespFrame::from_string("16"),
-            ];
-            Ok(RespFrame::Array(Some(values)))
-        },
-        _ => {
-            // For unknown parameters, return empty array
-            Ok(RespFrame::Array(Some(vec![])))
-        },
+    RespFrame::Array(Some(values))
+}
+
+/// Reply to a CONFIG SET that could not be applied
+pub fn config_set_error(param: &str, error: &ConfigParseError) -> RespFrame {
+    match error {
+        ConfigParseError::UnknownParam(..) => RespFrame::error(format!(
+            "ERR Unknown option or number of arguments for CONFIG SET - '{}'", param
+        )),
+        ConfigParseError::Value(_, _, value) => RespFrame::error(format!(
+            "ERR CONFIG SET failed (possibly related to argument '{}') - invalid value '{}'",
+            param, value
+        )),
+        other => RespFrame::error(format!(
+            "ERR CONFIG SET failed (possibly related to argument '{}') - {}", param, other
+        )),
     }
 }
 
-/// Generate a response with all configs
-fn generate_all_configs() -> Result<RespFrame> {
-    let mut configs = Vec::new();
-    
-    // Standard Redis configuration parameters
-    configs.push(RespFrame::from_string("save"));
-    configs.push(RespFrame::from_string("900 1 300 10 60 10000"));
-    
-    configs.push(RespFrame::from_string("appendonly"));
-    configs.push(RespFrame::from_string("no"));
+#[cfg(test)]
+mod tests {
+    use super::*;
     
-    configs.push(RespFrame::from_string("maxmemory"));
-    configs.push(RespFrame::from_string("0"));
-    
-    configs.push(RespFrame::from_string("maxmemory-policy"));
-    configs.push(RespFrame::from_string("noeviction"));
-    
-    configs.push(RespFrame::from_string("timeout"));
-    configs.push(RespFrame::from_string("0"));
-    
-    configs.push(RespFrame::from_string("tcp-keepalive"));
-    configs.push(RespFrame::from_string("300"));
+    fn command(args: &[&str]) -> Vec<RespFrame> {
+        args.iter().map(|arg| RespFrame::from_string(*arg)).collect()
+    }
     
-    configs.push(RespFrame::from_string("databases"));
-    configs.push(RespFrame::from_string("16"));
+    #[test]
+    fn test_config_get_patterns() {
+        let mut config = Config::default();
+        config.set("maxmemory", "10mb").unwrap();
+        
+        let reply = handle_config(&command(&["CONFIG", "GET", "MAXMEMORY"]), &config).unwrap();
+        assert_eq!(reply, RespFrame::Array(Some(command(&["maxmemory", "10485760"]))));
+        
+        let reply = handle_config(&command(&["CONFIG", "GET", "slowlog-*", "nosuch"]), &config).unwrap();
+        assert_eq!(reply, RespFrame::Array(Some(command(&[
+            "slowlog-enabled", "no",
+            "slowlog-log-slower-than", "10000",
+            "slowlog-max-len", "128",
+        ]))));
+    }
     
-    Ok(RespFrame::Array(Some(configs)))
-}
\ No newline at end of file
+    #[test]
+    fn test_config_set_errors() {
+        let mut config = Config::default();
+        let error = config.set("port", "7000").unwrap_err();
+        assert_eq!(config_set_error("port", &error), RespFrame::error(
+            "ERR Unknown option or number of arguments for CONFIG SET - 'port'"
+        ));
+        
+        let error = config.set("save", "900").unwrap_err();
+        assert!(config_set_error("save", &error).is_error());
+        assert_eq!(config.get("save"), Config::default().get("save"));
+    }
+}
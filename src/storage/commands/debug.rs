@@ -1,7 +1,20 @@
 //! Debug utilities for development and troubleshooting
 //! 
 //! This module provides utilities for debugging and logging during development.
-//! It can be compiled out in production builds.
+//! It can be compiled out in production builds. It also implements the DEBUG
+//! command's introspection subcommands.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::protocol::RespFrame;
+use crate::storage::commands::lua::script_error_reply;
+use crate::storage::lua_engine::{get_lua_engine, LuaCommandContext};
+use crate::storage::{lua_stats, StorageEngine};
+
+/// Longest DEBUG SCRIPTHEALTH may take before the scripting subsystem is reported unhealthy
+pub const SCRIPT_HEALTH_DEADLINE: Duration = Duration::from_millis(100);
 
 // Feature flag for enabling/disabling debug output
 // #[cfg(feature = "debug")]
@@ -32,4 +45,54 @@ pub fn log_command_timing(command: &str, duration_micros: u64, threshold_micros:
         println!("TIMING: Command '{}' took {}μs (threshold: {}μs)", 
                  command, duration_micros, threshold_micros);
     }
-}
\ No newline at end of file
+}
+/// Handle DEBUG command and its subcommands
+///
+/// Only the introspection subcommands are supported; the ones that crash,
+/// reload or reconfigure the server are not.
+pub fn handle_debug(parts: &[RespFrame], storage: &Arc<StorageEngine>) -> Result<RespFrame> {
+    if parts.len() < 2 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'debug' command"));
+    }
+    
+    let subcommand = match &parts[1] {
+        RespFrame::BulkString(Some(bytes)) => String::from_utf8_lossy(bytes).to_uppercase(),
+        _ => return Ok(RespFrame::error("ERR invalid subcommand format")),
+    };
+    let argument = parts.get(2).and_then(|part| match part {
+        RespFrame::BulkString(Some(bytes)) => Some(String::from_utf8_lossy(bytes).to_uppercase()),
+        _ => None,
+    });
+    
+    match (subcommand.as_str(), argument.as_deref(), parts.len()) {
+        ("LUA", Some("VMSTATS"), 3) => Ok(lua_stats::last().to_resp()),
+        ("SCRIPTHEALTH", None, 2) => Ok(handle_script_health(storage)),
+        ("HELP", None, 2) => handle_debug_help(),
+        _ => Ok(RespFrame::error(format!("ERR Unknown subcommand or wrong number of arguments for 'debug {}'", subcommand))),
+    }
+}
+
+/// Handle DEBUG SCRIPTHEALTH: run `return 1` in a fresh script VM and reply its latency in microseconds
+///
+/// A liveness probe for the scripting subsystem alone. It fails with an error
+/// reply when no VM can be set up or the probe misses its deadline, and, like
+/// every other command, gets a BUSY reply while a script is running too long.
+fn handle_script_health(storage: &Arc<StorageEngine>) -> RespFrame {
+    let probe = get_lua_engine(storage.clone()).and_then(|engine| {
+        let ctx = LuaCommandContext { db_index: 0, storage: storage.clone() };
+        engine.probe(&ctx, SCRIPT_HEALTH_DEADLINE)
+    });
+    match probe {
+        Ok(latency) => RespFrame::Integer(latency.as_micros() as i64),
+        Err(e) => script_error_reply(e),
+    }
+}
+
+/// Handle DEBUG HELP command
+fn handle_debug_help() -> Result<RespFrame> {
+    let help_text = r#"DEBUG LUA VMSTATS - Return counters of the most recently finished script VM (instructions, redis_calls, peak_memory, peak_stack_depth)
+DEBUG SCRIPTHEALTH - Run `return 1` in a fresh script VM and return its latency in microseconds
+DEBUG HELP - Show this help"#;
+    
+    Ok(RespFrame::from_string(help_text))
+}
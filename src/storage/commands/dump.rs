@@ -0,0 +1,218 @@
+//! DUMP and RESTORE command implementation
+//!
+//! Values travel in the serialized payload format Redis uses, so keys can be
+//! copied between Ferrous and Redis servers by migration tools.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::error::{FerrousError, Result};
+use crate::protocol::RespFrame;
+use crate::storage::{rdb, GetResult, StorageEngine};
+
+/// Handle DUMP command - Serialize the value stored at a key
+pub fn handle_dump(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() != 2 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'dump' command"));
+    }
+    
+    let key = match &parts[1] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_ref(),
+        _ => return Ok(RespFrame::error("ERR invalid key format")),
+    };
+    
+    match storage.get(db, key)? {
+        GetResult::Found(value) => Ok(RespFrame::bulk_string(rdb::dump_payload(&value))),
+        _ => Ok(RespFrame::null_bulk()),
+    }
+}
+
+/// Parse a non-negative integer option argument
+fn parse_option(arg: &[u8], max: i64, invalid: &str) -> std::result::Result<i64, RespFrame> {
+    match String::from_utf8_lossy(arg).parse::<i64>() {
+        Ok(n) if (0..=max).contains(&n) => Ok(n),
+        Ok(_) => Err(RespFrame::error(invalid)),
+        Err(_) => Err(RespFrame::error("ERR value is not an integer or out of range")),
+    }
+}
+
+/// Handle RESTORE command - Create a key from a DUMP payload
+///
+/// RESTORE key ttl payload [REPLACE] [ABSTTL] [IDLETIME seconds] [FREQ frequency]
+pub fn handle_restore(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 4 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'restore' command"));
+    }
+    
+    let mut args = Vec::with_capacity(parts.len());
+    for part in parts {
+        match part {
+            RespFrame::BulkString(Some(bytes)) => args.push(bytes.as_slice()),
+            _ => return Ok(RespFrame::error("ERR syntax error")),
+        }
+    }
+    let key = args[1];
+    
+    let ttl = match String::from_utf8_lossy(args[2]).parse::<i64>() {
+        Ok(ttl) if ttl < 0 => return Ok(RespFrame::error("ERR Invalid TTL value, must be >= 0")),
+        Ok(ttl) => ttl as u64,
+        Err(_) => return Ok(RespFrame::error("ERR value is not an integer or out of range")),
+    };
+    
+    // IDLETIME and FREQ exclude each other, as Redis keeps one or the other
+    let mut replace = false;
+    let mut absolute_ttl = false;
+    let mut idle = None;
+    let mut frequency = None;
+    let mut i = 4;
+    while i < args.len() {
+        match String::from_utf8_lossy(args[i]).to_uppercase().as_str() {
+            "REPLACE" => replace = true,
+            "ABSTTL" => absolute_ttl = true,
+            "IDLETIME" if i + 1 < args.len() && frequency.is_none() => {
+                match parse_option(args[i + 1], i64::MAX, "ERR Invalid IDLETIME value, must be >= 0") {
+                    Ok(secs) => idle = Some(Duration::from_secs(secs as u64)),
+                    Err(error) => return Ok(error),
+                }
+                i += 1;
+            }
+            "FREQ" if i + 1 < args.len() && idle.is_none() => {
+                match parse_option(args[i + 1], 255, "ERR Invalid FREQ value, must be >= 0 and <= 255") {
+                    Ok(counter) => frequency = Some(counter as u8),
+                    Err(error) => return Ok(error),
+                }
+                i += 1;
+            }
+            _ => return Ok(RespFrame::error("ERR syntax error")),
+        }
+        i += 1;
+    }
+    
+    let Some((version, value)) = rdb::verify_payload(args[3]) else {
+        return Ok(RespFrame::error("ERR DUMP payload version or checksum are wrong"));
+    };
+    
+    if !replace && storage.exists(db, key)? {
+        return Ok(RespFrame::error("BUSYKEY Target key name already exists."));
+    }
+    
+    // An absolute TTL that has already passed only removes the key being replaced
+    let expires_in = match ttl {
+        0 => None,
+        ttl if absolute_ttl => {
+            let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+            if ttl <= now_ms {
+                if replace {
+                    storage.delete(db, key)?;
+                }
+                return Ok(RespFrame::ok());
+            }
+            Some(Duration::from_millis(ttl - now_ms))
+        }
+        ttl => Some(Duration::from_millis(ttl)),
+    };
+    
+    // The replaced value comes back if the payload turns out not to parse
+    let previous = match storage.get(db, key)? {
+        GetResult::Found(value) if replace => Some((value, storage.ttl(db, key)?)),
+        _ => None,
+    };
+    if previous.is_some() {
+        storage.delete(db, key)?;
+    }
+    
+    if let Err(e) = rdb::restore_payload(storage, db, key.to_vec(), version, value) {
+        if let Some((value, ttl)) = previous {
+            storage.set_value(db, key.to_vec(), value, ttl)?;
+        }
+        return match e {
+            FerrousError::Io(_) => Ok(RespFrame::error("ERR Bad data format")),
+            e => Err(e),
+        };
+    }
+    
+    if let Some(expires_in) = expires_in {
+        storage.expire(db, key, expires_in)?;
+    }
+    storage.set_access_stats(db, key, idle, frequency)?;
+    
+    Ok(RespFrame::ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn command(args: &[&[u8]]) -> Vec<RespFrame> {
+        args.iter().map(|arg| RespFrame::bulk_string(arg.to_vec())).collect()
+    }
+    
+    fn payload(reply: RespFrame) -> Vec<u8> {
+        match reply {
+            RespFrame::BulkString(Some(bytes)) => bytes.to_vec(),
+            other => panic!("expected a payload, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_dump_restore_round_trip() {
+        let storage = StorageEngine::new();
+        storage.rpush(0, b"list".to_vec(), vec![b"a".to_vec(), b"b".to_vec()]).unwrap();
+        storage.zadd(0, b"zset".to_vec(), b"m".to_vec(), 1.5).unwrap();
+        
+        for key in [&b"list"[..], b"zset"] {
+            let dump = payload(handle_dump(&storage, 0, &command(&[b"DUMP", key])).unwrap());
+            let copy = [key, b"-copy"].concat();
+            let reply = handle_restore(&storage, 0, &command(&[b"RESTORE", &copy, b"0", &dump])).unwrap();
+            assert_eq!(reply, RespFrame::ok());
+        }
+        
+        assert_eq!(storage.lrange(0, b"list-copy", 0, -1).unwrap(), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(storage.zscore(0, b"zset-copy", b"m").unwrap(), Some(1.5));
+        assert_eq!(handle_dump(&storage, 0, &command(&[b"DUMP", b"missing"])).unwrap(), RespFrame::null_bulk());
+    }
+    
+    #[test]
+    fn test_restore_redis_payload() {
+        // DUMP of the integer 10 from the Redis documentation
+        let dump = b"\x00\xc0\x0a\x09\x00\xbe\x6d\x06\x89\x5a\x28\x00\x0a";
+        let storage = StorageEngine::new();
+        
+        let reply = handle_restore(&storage, 0, &command(&[b"RESTORE", b"k", b"5000", dump])).unwrap();
+        assert_eq!(reply, RespFrame::ok());
+        assert_eq!(storage.get_string(0, b"k").unwrap(), Some(b"10".to_vec()));
+        let ttl = storage.pttl(0, b"k").unwrap();
+        assert!(ttl > 4000 && ttl <= 5000);
+    }
+    
+    #[test]
+    fn test_restore_options_and_errors() {
+        let storage = StorageEngine::new();
+        storage.set_string(0, b"k".to_vec(), b"old".to_vec()).unwrap();
+        let dump = payload(handle_dump(&storage, 0, &command(&[b"DUMP", b"k"])).unwrap());
+        storage.set_string(0, b"k".to_vec(), b"new".to_vec()).unwrap();
+        
+        let restore = |args: &[&[u8]]| handle_restore(&storage, 0, &command(args)).unwrap();
+        
+        assert_eq!(restore(&[b"RESTORE", b"k", b"0", &dump]), RespFrame::error("BUSYKEY Target key name already exists."));
+        assert_eq!(restore(&[b"RESTORE", b"k", b"-1", &dump]), RespFrame::error("ERR Invalid TTL value, must be >= 0"));
+        assert_eq!(restore(&[b"RESTORE", b"k", b"0", &dump, b"FREQ", b"256"]),
+                   RespFrame::error("ERR Invalid FREQ value, must be >= 0 and <= 255"));
+        assert_eq!(restore(&[b"RESTORE", b"k", b"0", &dump, b"IDLETIME", b"1", b"FREQ", b"1"]),
+                   RespFrame::error("ERR syntax error"));
+        
+        // A corrupted payload fails its checksum and leaves the key alone
+        let mut corrupted = dump.clone();
+        corrupted[2] ^= 1;
+        assert_eq!(restore(&[b"RESTORE", b"k", b"0", &corrupted, b"REPLACE"]),
+                   RespFrame::error("ERR DUMP payload version or checksum are wrong"));
+        assert_eq!(storage.get_string(0, b"k").unwrap(), Some(b"new".to_vec()));
+        
+        assert_eq!(restore(&[b"RESTORE", b"k", b"0", &dump, b"REPLACE", b"IDLETIME", b"1000"]), RespFrame::ok());
+        assert_eq!(storage.get_string(0, b"k").unwrap(), Some(b"old".to_vec()));
+        assert!(storage.idle_time(0, b"k").unwrap().unwrap() >= Duration::from_secs(1000));
+        
+        // An absolute TTL in the past replaces the key with nothing
+        assert_eq!(restore(&[b"RESTORE", b"k", b"1000", &dump, b"REPLACE", b"ABSTTL"]), RespFrame::ok());
+        assert!(!storage.exists(0, b"k").unwrap());
+    }
+}
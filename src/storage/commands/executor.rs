@@ -4,10 +4,19 @@
 //! eliminating the fragmentation between server, storage, and Lua command handling.
 
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use crate::error::{Result, FerrousError, CommandError};
 use crate::protocol::RespFrame;
 use crate::storage::StorageEngine;
+use crate::storage::value::{ExpireCondition, parse_float};
+use crate::storage::commands::sort::{self, SortOptions};
+use crate::storage::commands::sorted_sets::{self, LexBound};
+use crate::storage::commands::bitmaps::{self, BitOperation, BitUnit};
+use crate::storage::commands::geo::{self, GeoAddOptions, GeoItem, GeoQuery};
+use crate::storage::commands::strings;
+use crate::storage::commands::lists::{self, LposOptions, MultiPop};
+use crate::storage::commands::sets::{self, InterCard};
+use crate::storage::value::ListEnd;
 
 /// Unified command executor that guarantees atomicity and consistency
 #[derive(Clone)]
@@ -49,6 +58,8 @@ pub enum Command {
     ConsumerGroup(ConsumerGroupCommand),
     Persistence(PersistenceCommand),
     Bit(BitCommand),
+    HyperLogLog(HyperLogLogCommand),
+    Geo(GeoCommand),
     Config(ConfigCommand),
 }
 
@@ -83,6 +94,10 @@ pub enum StringCommand {
         key: Vec<u8>,
         decrement: i64,
     },
+    IncrByFloat {
+        key: Vec<u8>,
+        increment: f64,
+    },
     SetNx {
         key: Vec<u8>,
         value: Vec<u8>,
@@ -167,6 +182,20 @@ pub enum ListCommand {
         count: isize,
         element: Vec<u8>,
     },
+    LPos {
+        key: Vec<u8>,
+        element: Vec<u8>,
+        options: LposOptions,
+    },
+    LMove {
+        source: Vec<u8>,
+        destination: Vec<u8>,
+        from: ListEnd,
+        to: ListEnd,
+    },
+    LMPop {
+        pop: MultiPop,
+    },
 }
 
 /// Set commands for Redis Lua compatibility
@@ -190,12 +219,19 @@ pub enum SetCommand {
         key: Vec<u8>,
         member: Vec<u8>,
     },
+    SMIsMember {
+        key: Vec<u8>,
+        members: Vec<Vec<u8>>,
+    },
     SUnion {
         keys: Vec<Vec<u8>>,
     },
     SInter {
         keys: Vec<Vec<u8>>,
     },
+    SInterCard {
+        card: InterCard,
+    },
     SDiff {
         keys: Vec<Vec<u8>>,
     },
@@ -253,6 +289,29 @@ pub enum HashCommand {
         field: Vec<u8>,
         increment: i64,
     },
+    HIncrByFloat {
+        key: Vec<u8>,
+        field: Vec<u8>,
+        increment: f64,
+    },
+    /// HEXPIRE/HPEXPIRE/HEXPIREAT/HPEXPIREAT normalized to milliseconds
+    HExpire {
+        key: Vec<u8>,
+        millis: i64,
+        absolute: bool,
+        condition: Option<ExpireCondition>,
+        fields: Vec<Vec<u8>>,
+    },
+    HPersist {
+        key: Vec<u8>,
+        fields: Vec<Vec<u8>>,
+    },
+    /// HTTL/HPTTL
+    HTtl {
+        key: Vec<u8>,
+        fields: Vec<Vec<u8>>,
+        in_millis: bool,
+    },
 }
 
 /// Sorted Set commands for Redis Lua compatibility
@@ -333,10 +392,27 @@ pub enum SortedSetCommand {
         min_score: f64,
         max_score: f64,
     },
+    ZRangeByLex {
+        key: Vec<u8>,
+        min: LexBound,
+        max: LexBound,
+        limit: Option<(i64, i64)>,
+    },
+    ZRevRangeByLex {
+        key: Vec<u8>,
+        max: LexBound,
+        min: LexBound,
+        limit: Option<(i64, i64)>,
+    },
+    ZLexCount {
+        key: Vec<u8>,
+        min: LexBound,
+        max: LexBound,
+    },
     ZRemRangeByLex {
         key: Vec<u8>,
-        min_lex: String,
-        max_lex: String,
+        min: LexBound,
+        max: LexBound,
     },
 }
 
@@ -346,13 +422,19 @@ pub enum KeyCommand {
     Exists {
         keys: Vec<Vec<u8>>,
     },
+    Touch {
+        keys: Vec<Vec<u8>>,
+    },
+    /// EXPIRE/PEXPIRE/EXPIREAT/PEXPIREAT resolved to a deadline
     Expire {
         key: Vec<u8>,
-        seconds: u64,
+        expires_at: Instant,
+        conditions: Vec<ExpireCondition>,
     },
-    PExpire {
+    /// EXPIRETIME/PEXPIRETIME
+    ExpireTime {
         key: Vec<u8>,
-        milliseconds: u64,
+        in_millis: bool,
     },
     Ttl {
         key: Vec<u8>,
@@ -375,6 +457,10 @@ pub enum KeyCommand {
         new_key: Vec<u8>,
     },
     RandomKey,
+    Sort {
+        key: Vec<u8>,
+        options: SortOptions,
+    },
 }
 
 /// Stream operations for comprehensive Redis Lua support
@@ -523,8 +609,64 @@ pub enum BitCommand {
     },
     BitCount {
         key: Vec<u8>,
-        start: Option<isize>,
-        end: Option<isize>,
+        range: Option<(i64, i64, BitUnit)>,
+    },
+    BitPos {
+        key: Vec<u8>,
+        bit: bool,
+        start: i64,
+        end: Option<i64>,
+        unit: BitUnit,
+    },
+    BitOp {
+        operation: BitOperation,
+        dest: Vec<u8>,
+        sources: Vec<Vec<u8>>,
+    },
+}
+
+/// HyperLogLog operations
+#[derive(Debug, Clone)]
+pub enum HyperLogLogCommand {
+    PfAdd {
+        key: Vec<u8>,
+        elements: Vec<Vec<u8>>,
+    },
+    PfCount {
+        keys: Vec<Vec<u8>>,
+    },
+    PfMerge {
+        dest: Vec<u8>,
+        sources: Vec<Vec<u8>>,
+    },
+}
+
+/// Geospatial operations
+#[derive(Debug, Clone)]
+pub enum GeoCommand {
+    GeoAdd {
+        key: Vec<u8>,
+        options: GeoAddOptions,
+        items: Vec<GeoItem>,
+    },
+    GeoPos {
+        key: Vec<u8>,
+        members: Vec<Vec<u8>>,
+    },
+    GeoDist {
+        key: Vec<u8>,
+        first: Vec<u8>,
+        second: Vec<u8>,
+        unit: f64,
+    },
+    GeoSearch {
+        key: Vec<u8>,
+        query: GeoQuery,
+    },
+    GeoSearchStore {
+        dest: Vec<u8>,
+        key: Vec<u8>,
+        query: GeoQuery,
     },
 }
 
@@ -565,6 +707,53 @@ pub struct SetOptions {
     pub keepttl: bool,
 }
 
+/// Typed command reply for programmatic (non-RESP) callers
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    /// Status reply such as `OK`
+    Status(String),
+    /// Error reply, including its error code prefix (e.g. `ERR ...`)
+    Error(String),
+    Integer(i64),
+    Bulk(Arc<Vec<u8>>),
+    /// Missing value (null bulk string or null array)
+    Nil,
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    /// Check whether this reply is an error
+    pub fn is_error(&self) -> bool {
+        matches!(self, Reply::Error(_))
+    }
+}
+
+impl From<RespFrame> for Reply {
+    fn from(frame: RespFrame) -> Self {
+        match frame {
+            RespFrame::SimpleString(bytes) => Reply::Status(String::from_utf8_lossy(&bytes).into_owned()),
+            RespFrame::Error(bytes) => Reply::Error(String::from_utf8_lossy(&bytes).into_owned()),
+            RespFrame::Integer(n) => Reply::Integer(n),
+            RespFrame::BulkString(Some(bytes)) => Reply::Bulk(bytes),
+            RespFrame::Array(Some(frames)) => Reply::Array(frames.into_iter().map(Reply::from).collect()),
+            _ => Reply::Nil,
+        }
+    }
+}
+
+impl From<Reply> for RespFrame {
+    fn from(reply: Reply) -> Self {
+        match reply {
+            Reply::Status(s) => RespFrame::SimpleString(Arc::new(s.into_bytes())),
+            Reply::Error(e) => RespFrame::Error(Arc::new(e.into_bytes())),
+            Reply::Integer(n) => RespFrame::Integer(n),
+            Reply::Bulk(bytes) => RespFrame::BulkString(Some(bytes)),
+            Reply::Nil => RespFrame::BulkString(None),
+            Reply::Array(items) => RespFrame::Array(Some(items.into_iter().map(RespFrame::from).collect())),
+        }
+    }
+}
+
 impl UnifiedCommandExecutor {
     /// Create new executor with storage reference
     pub fn new(storage: Arc<StorageEngine>) -> Self {
@@ -602,6 +791,8 @@ impl UnifiedCommandExecutor {
             Command::ConsumerGroup(cg_cmd) => self.execute_consumer_group(db, cg_cmd),
             Command::Persistence(persist_cmd) => self.execute_persistence(persist_cmd),
             Command::Bit(bit_cmd) => self.execute_bit(db, bit_cmd),
+            Command::HyperLogLog(hll_cmd) => self.execute_hyperloglog(db, hll_cmd),
+            Command::Geo(geo_cmd) => self.execute_geo(db, geo_cmd),
             Command::Config(config_cmd) => self.execute_config(config_cmd),
         }
     }
@@ -705,6 +896,11 @@ impl UnifiedCommandExecutor {
                 Ok(RespFrame::Integer(result))
             }
             
+            StringCommand::IncrByFloat { key, increment } => {
+                let result = self.storage.incr_by_float(db, key, increment)?;
+                Ok(RespFrame::from_bytes(result))
+            }
+            
             StringCommand::SetNx { key, value } => {
                 let result = self.storage.set_string_nx(db, key, value)?;
                 Ok(RespFrame::Integer(if result { 1 } else { 0 }))
@@ -823,6 +1019,22 @@ impl UnifiedCommandExecutor {
                 let removed = self.storage.lrem(db, key, count, element)?;
                 Ok(RespFrame::Integer(removed as i64))
             }
+            
+            ListCommand::LPos { key, element, options } => {
+                let positions = lists::lpos(&self.storage, db, &key, &element, &options)?;
+                Ok(lists::lpos_reply(positions, &options))
+            }
+            
+            ListCommand::LMove { source, destination, from, to } => {
+                match self.storage.lmove(db, &source, destination, from, to)? {
+                    Some(value) => Ok(RespFrame::from_bytes(value)),
+                    None => Ok(RespFrame::null_bulk()),
+                }
+            }
+            
+            ListCommand::LMPop { pop } => {
+                Ok(lists::lmpop_reply(lists::lmpop(&self.storage, db, &pop)?))
+            }
         }
     }
     
@@ -857,6 +1069,10 @@ impl UnifiedCommandExecutor {
                 Ok(RespFrame::Integer(if is_member { 1 } else { 0 }))
             }
             
+            SetCommand::SMIsMember { key, members } => {
+                Ok(sets::smismember_reply(self.storage.smismember(db, &key, &members)?))
+            }
+            
             SetCommand::SUnion { keys } => {
                 let result = self.storage.sunion(db, &keys)?;
                 let frames: Vec<RespFrame> = result.into_iter()
@@ -873,6 +1089,11 @@ impl UnifiedCommandExecutor {
                 Ok(RespFrame::Array(Some(frames)))
             }
             
+            SetCommand::SInterCard { card } => {
+                let count = self.storage.sintercard(db, &card.keys, card.limit)?;
+                Ok(RespFrame::Integer(count as i64))
+            }
+            
             SetCommand::SDiff { keys } => {
                 let result = self.storage.sdiff(db, &keys)?;
                 let frames: Vec<RespFrame> = result.into_iter()
@@ -882,10 +1103,10 @@ impl UnifiedCommandExecutor {
             }
             
             SetCommand::SRandMember { key, count } => {
-                let count = count.unwrap_or(1);
-                let members = self.storage.srandmember(db, &key, count)?;
-                if count == 1 && !members.is_empty() {
-                    Ok(RespFrame::from_bytes(members[0].clone()))
+                let members = self.storage.srandmember(db, &key, count.unwrap_or(1))?;
+                if count.is_none() {
+                    // Without a count the reply is a single member or nil
+                    Ok(members.into_iter().next().map_or_else(RespFrame::null_bulk, RespFrame::from_bytes))
                 } else {
                     let frames: Vec<RespFrame> = members.into_iter()
                         .map(|m| RespFrame::from_bytes(m))
@@ -982,6 +1203,41 @@ impl UnifiedCommandExecutor {
                 let new_value = self.storage.hincrby(db, key, field, increment)?;
                 Ok(RespFrame::Integer(new_value))
             }
+            
+            HashCommand::HIncrByFloat { key, field, increment } => {
+                let new_value = self.storage.hincrbyfloat(db, key, field, increment)?;
+                Ok(RespFrame::from_bytes(new_value))
+            }
+            
+            HashCommand::HExpire { key, millis, absolute, condition, fields } => {
+                let remaining_ms = if absolute {
+                    millis - SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+                } else {
+                    millis
+                };
+                let expires_at = if remaining_ms <= 0 {
+                    Instant::now()
+                } else {
+                    Instant::now().checked_add(Duration::from_millis(remaining_ms as u64))
+                        .ok_or_else(|| FerrousError::Command(CommandError::Generic("invalid expire time".into())))?
+                };
+                let results = self.storage.hexpire(db, &key, &fields, expires_at, condition)?;
+                Ok(RespFrame::Array(Some(results.into_iter().map(RespFrame::Integer).collect())))
+            }
+            
+            HashCommand::HPersist { key, fields } => {
+                let results = self.storage.hpersist(db, &key, &fields)?;
+                Ok(RespFrame::Array(Some(results.into_iter().map(RespFrame::Integer).collect())))
+            }
+            
+            HashCommand::HTtl { key, fields, in_millis } => {
+                let results = self.storage.hpttl(db, &key, &fields)?;
+                let frames = results.into_iter()
+                    .map(|ms| if in_millis || ms < 0 { ms } else { (ms + 500) / 1000 })
+                    .map(RespFrame::Integer)
+                    .collect();
+                Ok(RespFrame::Array(Some(frames)))
+            }
         }
     }
     
@@ -1176,14 +1432,36 @@ impl UnifiedCommandExecutor {
                 Ok(RespFrame::Integer(removed))
             }
             
-            SortedSetCommand::ZRemRangeByLex { key: _, min_lex: _, max_lex: _ } => {
-                // Basic range-by-lex implementation 
-                // Note: This is a simplified implementation, full lex range would need more sophisticated storage engine support
-                Ok(RespFrame::Integer(0))
+            SortedSetCommand::ZRangeByLex { key, min, max, limit } => {
+                self.lex_range_reply(db, &key, &min, &max, limit, false)
+            }
+            
+            SortedSetCommand::ZRevRangeByLex { key, max, min, limit } => {
+                self.lex_range_reply(db, &key, &min, &max, limit, true)
+            }
+            
+            SortedSetCommand::ZLexCount { key, min, max } => {
+                let members = sorted_sets::range_by_lex(&self.storage, db, &key, &min, &max, false)?;
+                Ok(RespFrame::Integer(members.len() as i64))
+            }
+            
+            SortedSetCommand::ZRemRangeByLex { key, min, max } => {
+                let members = sorted_sets::range_by_lex(&self.storage, db, &key, &min, &max, false)?;
+                Ok(RespFrame::Integer(sorted_sets::remove_members(&self.storage, db, &key, members)?))
             }
         }
     }
     
+    /// Reply to ZRANGEBYLEX and ZREVRANGEBYLEX
+    fn lex_range_reply(&self, db: usize, key: &[u8], min: &LexBound, max: &LexBound, limit: Option<(i64, i64)>, reverse: bool) -> Result<RespFrame> {
+        let mut members = sorted_sets::range_by_lex(&self.storage, db, key, min, max, reverse)?;
+        if let Some((offset, count)) = limit {
+            members = sorted_sets::apply_limit(members, offset, count);
+        }
+        
+        Ok(RespFrame::Array(Some(members.into_iter().map(|(member, _)| RespFrame::from_bytes(member)).collect())))
+    }
+    
     /// Execute key management commands
     fn execute_key(&self, db: usize, cmd: KeyCommand) -> Result<RespFrame> {
         match cmd {
@@ -1197,16 +1475,24 @@ impl UnifiedCommandExecutor {
                 Ok(RespFrame::Integer(count))
             }
             
-            KeyCommand::Expire { key, seconds } => {
-                let result = self.storage.expire(db, &key, Duration::from_secs(seconds))?;
-                Ok(RespFrame::Integer(if result { 1 } else { 0 }))
+            KeyCommand::Touch { keys } => {
+                Ok(RespFrame::Integer(self.storage.touch_keys(db, &keys)? as i64))
             }
             
-            KeyCommand::PExpire { key, milliseconds } => {
-                let result = self.storage.pexpire(db, &key, milliseconds)?;
+            KeyCommand::Sort { key, options } => {
+                Ok(sort::sort(&self.storage, db, &key, &options)?.into())
+            }
+            
+            KeyCommand::Expire { key, expires_at, conditions } => {
+                let result = self.storage.expire_at(db, &key, expires_at, &conditions)?;
                 Ok(RespFrame::Integer(if result { 1 } else { 0 }))
             }
             
+            KeyCommand::ExpireTime { key, in_millis } => {
+                let millis = strings::expire_time_millis(&self.storage, db, &key)?;
+                Ok(RespFrame::Integer(if in_millis || millis < 0 { millis } else { millis / 1000 }))
+            }
+            
             KeyCommand::Ttl { key } => {
                 let ttl = self.storage.ttl(db, &key)?;
                 match ttl {
@@ -1236,7 +1522,7 @@ impl UnifiedCommandExecutor {
             
             KeyCommand::Type { key } => {
                 let type_name = self.storage.key_type(db, &key)?;
-                Ok(RespFrame::from_string(type_name))
+                Ok(RespFrame::simple_string(type_name))
             }
             
             KeyCommand::Rename { old_key, new_key } => {
@@ -1255,15 +1541,7 @@ impl UnifiedCommandExecutor {
             }
             
             KeyCommand::RandomKey => {
-                let keys = self.storage.get_all_keys(db)?;
-                if keys.is_empty() {
-                    Ok(RespFrame::null_bulk())
-                } else {
-                    use rand::seq::SliceRandom;
-                    let mut rng = rand::thread_rng();
-                    let random_key = keys.choose(&mut rng).unwrap();
-                    Ok(RespFrame::from_bytes(random_key.clone()))
-                }
+                Ok(self.storage.random_key(db)?.map_or_else(RespFrame::null_bulk, RespFrame::from_bytes))
             }
         }
     }
@@ -1274,7 +1552,7 @@ impl UnifiedCommandExecutor {
             ServerCommand::Ping { message } => {
                 match message {
                     Some(msg) => Ok(RespFrame::from_bytes(msg)),
-                    None => Ok(RespFrame::from_string("PONG")),
+                    None => Ok(crate::protocol::shared::pong()),
                 }
             }
             
@@ -1296,7 +1574,7 @@ impl UnifiedCommandExecutor {
             }
             
             ServerCommand::Info { section: _section } => {
-                Ok(RespFrame::from_string("# Server\nredis_version:7.0.0\n"))
+                Ok(RespFrame::from_string(format!("# Server\nredis_version:{}\n", super::monitor::REDIS_VERSION)))
             }
         }
     }
@@ -1527,8 +1805,7 @@ impl UnifiedCommandExecutor {
             }
             
             DatabaseCommand::DbSize => {
-                let keys = self.storage.get_all_keys(db)?;
-                Ok(RespFrame::Integer(keys.len() as i64))
+                Ok(RespFrame::Integer(self.storage.dbsize(db)? as i64))
             }
             
             DatabaseCommand::Keys { pattern } => {
@@ -1681,66 +1958,85 @@ impl UnifiedCommandExecutor {
     fn execute_bit(&self, db: usize, cmd: BitCommand) -> Result<RespFrame> {
         match cmd {
             BitCommand::GetBit { key, offset } => {
-                match self.storage.get_string(db, &key)? {
-                    Some(value) => {
-                        let byte_offset = offset / 8;
-                        let bit_offset = offset % 8;
-                        
-                        if byte_offset < value.len() {
-                            let byte_val = value[byte_offset];
-                            let bit = (byte_val >> (7 - bit_offset)) & 1;
-                            Ok(RespFrame::Integer(bit as i64))
-                        } else {
-                            Ok(RespFrame::Integer(0))
-                        }
-                    }
-                    None => Ok(RespFrame::Integer(0)),
-                }
+                let bit = self.storage.get_string(db, &key)?
+                    .is_some_and(|value| bitmaps::get_bit(&value, offset));
+                Ok(RespFrame::Integer(bit as i64))
             }
             
             BitCommand::SetBit { key, offset, value } => {
-                let byte_offset = offset / 8;
-                let bit_offset = offset % 8;
-                
-                let mut data = match self.storage.get_string(db, &key)? {
-                    Some(existing) => existing,
-                    None => Vec::new(),
-                };
-                
-                if byte_offset >= data.len() {
-                    data.resize(byte_offset + 1, 0);
-                }
-                
-                let old_bit = (data[byte_offset] >> (7 - bit_offset)) & 1;
-                
-                if value == 1 {
-                    data[byte_offset] |= 1 << (7 - bit_offset);
-                } else {
-                    data[byte_offset] &= !(1 << (7 - bit_offset));
-                }
-                
-                self.storage.set_string(db, key, data)?;
+                let old_bit = self.storage.setbit(db, key, offset, value == 1)?;
                 Ok(RespFrame::Integer(old_bit as i64))
             }
             
-            BitCommand::BitCount { key, start, end } => {
+            BitCommand::BitCount { key, range } => {
+                let count = self.storage.get_string(db, &key)?
+                    .map_or(0, |value| bitmaps::count_bits(&value, range));
+                Ok(RespFrame::Integer(count as i64))
+            }
+            
+            BitCommand::BitPos { key, bit, start, end, unit } => {
                 match self.storage.get_string(db, &key)? {
-                    Some(value) => {
-                        let (start_byte, end_byte) = if let (Some(s), Some(e)) = (start, end) {
-                            let len = value.len() as isize;
-                            let start_pos = if s < 0 { (len + s).max(0) } else { s.min(len - 1) } as usize;
-                            let end_pos = if e < 0 { (len + e).max(0) } else { e.min(len - 1) } as usize;
-                            (start_pos, end_pos)
-                        } else {
-                            (0, value.len().saturating_sub(1))
-                        };
-                        
-                        let slice = &value[start_byte..=end_byte.min(value.len().saturating_sub(1))];
-                        let bit_count = slice.iter().map(|&byte| byte.count_ones() as i64).sum::<i64>();
-                        Ok(RespFrame::Integer(bit_count))
-                    }
-                    None => Ok(RespFrame::Integer(0)),
+                    Some(value) => Ok(RespFrame::Integer(bitmaps::bit_position(&value, bit, start, end, unit))),
+                    None => Ok(RespFrame::Integer(if bit { -1 } else { 0 })),
+                }
+            }
+            
+            BitCommand::BitOp { operation, dest, sources } => {
+                let mut values = Vec::with_capacity(sources.len());
+                for source in &sources {
+                    values.push(self.storage.get_string(db, source)?.unwrap_or_default());
                 }
+                let len = bitmaps::store_result(&self.storage, db, &dest, bitmaps::combine(operation, &values))?;
+                Ok(RespFrame::Integer(len as i64))
+            }
+        }
+    }
+    
+    /// Execute HyperLogLog commands
+    fn execute_hyperloglog(&self, db: usize, cmd: HyperLogLogCommand) -> Result<RespFrame> {
+        match cmd {
+            HyperLogLogCommand::PfAdd { key, elements } => {
+                let changed = self.storage.pfadd(db, key, &elements)?;
+                Ok(RespFrame::Integer(changed as i64))
+            }
+            
+            HyperLogLogCommand::PfCount { keys } => {
+                let count = self.storage.pfcount(db, &keys)?;
+                Ok(RespFrame::Integer(count as i64))
+            }
+            
+            HyperLogLogCommand::PfMerge { dest, sources } => {
+                self.storage.pfmerge(db, dest, &sources)?;
+                Ok(RespFrame::ok())
+            }
+        }
+    }
+    
+    /// Execute geospatial commands
+    fn execute_geo(&self, db: usize, cmd: GeoCommand) -> Result<RespFrame> {
+        match cmd {
+            GeoCommand::GeoAdd { key, options, items } => {
+                let changed = geo::geoadd(&self.storage, db, &key, options, items)?;
+                Ok(RespFrame::Integer(changed))
+            }
+            
+            GeoCommand::GeoPos { key, members } => {
+                let members: Vec<&[u8]> = members.iter().map(|member| member.as_slice()).collect();
+                geo::positions_reply(&self.storage, db, &key, &members)
+            }
+            
+            GeoCommand::GeoDist { key, first, second, unit } => {
+                geo::distance_reply(&self.storage, db, &key, &first, &second, unit)
+            }
+            
+            GeoCommand::GeoSearch { key, query } => {
+                let matches = geo::search(&self.storage, db, &key, &query)?;
+                Ok(geo::search_reply(matches, &query))
+            }
+            
+            GeoCommand::GeoSearchStore { dest, key, query } => {
+                let stored = geo::search_store(&self.storage, db, &dest, &key, &query)?;
+                Ok(RespFrame::Integer(stored))
             }
         }
     }
@@ -1776,6 +2072,19 @@ impl UnifiedCommandExecutor {
 pub struct CommandParser;
 
 impl CommandParser {
+    /// Parse raw command arguments (command name first) into a typed command
+    pub fn parse_args<I, T>(args: I) -> Result<ParsedCommand>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<Vec<u8>>,
+    {
+        let frames: Vec<RespFrame> = args
+            .into_iter()
+            .map(|arg| RespFrame::BulkString(Some(Arc::new(arg.into()))))
+            .collect();
+        Self::parse(&frames)
+    }
+    
     /// Parse RESP frames into typed command
     pub fn parse(frames: &[RespFrame]) -> Result<ParsedCommand> {
         if frames.is_empty() {
@@ -1794,6 +2103,7 @@ impl CommandParser {
             "INCRBY" => Command::String(Self::parse_incrby(frames)?),
             "DECR" => Command::String(Self::parse_decr(frames)?),
             "DECRBY" => Command::String(Self::parse_decrby(frames)?),
+            "INCRBYFLOAT" => Command::String(Self::parse_incrbyfloat(frames)?),
             "SETNX" => Command::String(Self::parse_setnx(frames)?),
             "SETEX" => Command::String(Self::parse_setex(frames)?),
             "PSETEX" => Command::String(Self::parse_psetex(frames)?),
@@ -1815,6 +2125,10 @@ impl CommandParser {
             "LRANGE" => Command::List(Self::parse_lrange(frames)?),
             "LTRIM" => Command::List(Self::parse_ltrim(frames)?),
             "LREM" => Command::List(Self::parse_lrem(frames)?),
+            "LPOS" => Command::List(Self::parse_lpos(frames)?),
+            "LMOVE" => Command::List(Self::parse_lmove(frames)?),
+            "RPOPLPUSH" => Command::List(Self::parse_rpoplpush(frames)?),
+            "LMPOP" => Command::List(Self::parse_lmpop(frames)?),
             
             // Set commands
             "SADD" => Command::Set(Self::parse_sadd(frames)?),
@@ -1822,8 +2136,10 @@ impl CommandParser {
             "SMEMBERS" => Command::Set(Self::parse_smembers(frames)?),
             "SCARD" => Command::Set(Self::parse_scard(frames)?),
             "SISMEMBER" => Command::Set(Self::parse_sismember(frames)?),
+            "SMISMEMBER" => Command::Set(Self::parse_smismember(frames)?),
             "SUNION" => Command::Set(Self::parse_sunion(frames)?),
             "SINTER" => Command::Set(Self::parse_sinter(frames)?),
+            "SINTERCARD" => Command::Set(Self::parse_sintercard(frames)?),
             "SDIFF" => Command::Set(Self::parse_sdiff(frames)?),
             "SRANDMEMBER" => Command::Set(Self::parse_srandmember(frames)?),
             "SPOP" => Command::Set(Self::parse_spop(frames)?),
@@ -1840,6 +2156,14 @@ impl CommandParser {
             "HKEYS" => Command::Hash(Self::parse_hkeys(frames)?),
             "HVALS" => Command::Hash(Self::parse_hvals(frames)?),
             "HINCRBY" => Command::Hash(Self::parse_hincrby(frames)?),
+            "HINCRBYFLOAT" => Command::Hash(Self::parse_hincrbyfloat(frames)?),
+            "HEXPIRE" => Command::Hash(Self::parse_hexpire(frames, "HEXPIRE", 1000, false)?),
+            "HPEXPIRE" => Command::Hash(Self::parse_hexpire(frames, "HPEXPIRE", 1, false)?),
+            "HEXPIREAT" => Command::Hash(Self::parse_hexpire(frames, "HEXPIREAT", 1000, true)?),
+            "HPEXPIREAT" => Command::Hash(Self::parse_hexpire(frames, "HPEXPIREAT", 1, true)?),
+            "HPERSIST" => Command::Hash(Self::parse_hpersist(frames)?),
+            "HTTL" => Command::Hash(Self::parse_httl(frames, "HTTL", false)?),
+            "HPTTL" => Command::Hash(Self::parse_httl(frames, "HPTTL", true)?),
             
             // Sorted set commands
             "ZADD" => Command::SortedSet(Self::parse_zadd(frames)?),
@@ -1858,12 +2182,22 @@ impl CommandParser {
             "ZPOPMAX" => Command::SortedSet(Self::parse_zpopmax(frames)?),
             "ZREMRANGEBYRANK" => Command::SortedSet(Self::parse_zremrangebyrank(frames)?),
             "ZREMRANGEBYSCORE" => Command::SortedSet(Self::parse_zremrangebyscore(frames)?),
+            "ZRANGEBYLEX" => Command::SortedSet(Self::parse_zrangebylex(frames, false)?),
+            "ZREVRANGEBYLEX" => Command::SortedSet(Self::parse_zrangebylex(frames, true)?),
+            "ZLEXCOUNT" => Command::SortedSet(Self::parse_zlexcount(frames)?),
             "ZREMRANGEBYLEX" => Command::SortedSet(Self::parse_zremrangebylex(frames)?),
             
             // Key commands
             "EXISTS" => Command::Key(Self::parse_exists(frames)?),
-            "EXPIRE" => Command::Key(Self::parse_expire(frames)?),
-            "PEXPIRE" => Command::Key(Self::parse_pexpire(frames)?),
+            "TOUCH" => Command::Key(Self::parse_touch(frames)?),
+            "SORT" => Command::Key(Self::parse_sort(frames, false)?),
+            "SORT_RO" => Command::Key(Self::parse_sort(frames, true)?),
+            "EXPIRE" => Command::Key(Self::parse_expire(frames, "EXPIRE", 1000, false)?),
+            "PEXPIRE" => Command::Key(Self::parse_expire(frames, "PEXPIRE", 1, false)?),
+            "EXPIREAT" => Command::Key(Self::parse_expire(frames, "EXPIREAT", 1000, true)?),
+            "PEXPIREAT" => Command::Key(Self::parse_expire(frames, "PEXPIREAT", 1, true)?),
+            "EXPIRETIME" => Command::Key(Self::parse_expire_time_query(frames, "EXPIRETIME", false)?),
+            "PEXPIRETIME" => Command::Key(Self::parse_expire_time_query(frames, "PEXPIRETIME", true)?),
             "TTL" => Command::Key(Self::parse_ttl(frames)?),
             "PTTL" => Command::Key(Self::parse_pttl(frames)?),
             "PERSIST" => Command::Key(Self::parse_persist(frames)?),
@@ -1917,6 +2251,20 @@ impl CommandParser {
             "GETBIT" => Command::Bit(Self::parse_getbit(frames)?),
             "SETBIT" => Command::Bit(Self::parse_setbit(frames)?),
             "BITCOUNT" => Command::Bit(Self::parse_bitcount(frames)?),
+            "BITPOS" => Command::Bit(Self::parse_bitpos(frames)?),
+            "BITOP" => Command::Bit(Self::parse_bitop(frames)?),
+            
+            // HyperLogLog commands
+            "PFADD" => Command::HyperLogLog(Self::parse_pfadd(frames)?),
+            "PFCOUNT" => Command::HyperLogLog(Self::parse_pfcount(frames)?),
+            "PFMERGE" => Command::HyperLogLog(Self::parse_pfmerge(frames)?),
+            
+            // Geospatial commands
+            "GEOADD" => Command::Geo(Self::parse_geoadd(frames)?),
+            "GEOPOS" => Command::Geo(Self::parse_geopos(frames)?),
+            "GEODIST" => Command::Geo(Self::parse_geodist(frames)?),
+            "GEOSEARCH" => Command::Geo(Self::parse_geosearch(frames, false)?),
+            "GEOSEARCHSTORE" => Command::Geo(Self::parse_geosearch(frames, true)?),
             
             // Config operations
             "CONFIG" => Command::Config(Self::parse_config(frames)?),
@@ -1947,6 +2295,21 @@ impl CommandParser {
         }
     }
     
+    /// Parse a relative expire time for SET EX/PX, SETEX and PSETEX
+    ///
+    /// Non-integers are rejected with the generic integer error, and zero,
+    /// negative or overflowing times with the command's invalid expire time error.
+    pub fn parse_expire_time(raw: &[u8], cmd: &str, millis: bool) -> Result<Duration> {
+        let value = std::str::from_utf8(raw).ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .ok_or(FerrousError::Command(CommandError::NotInteger))?;
+        let ms = if millis { Some(value) } else { value.checked_mul(1000) };
+        match ms {
+            Some(ms) if ms > 0 => Ok(Duration::from_millis(ms as u64)),
+            _ => Err(FerrousError::Command(CommandError::InvalidExpireTime(cmd.to_string()))),
+        }
+    }
+    
     fn parse_set(frames: &[RespFrame]) -> Result<StringCommand> {
         if frames.len() < 3 {
             return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("SET".into())));
@@ -1976,18 +2339,14 @@ impl CommandParser {
                     if i + 1 >= frames.len() {
                         return Err(FerrousError::Command(CommandError::SyntaxError("Missing EX value".to_string())));
                     }
-                    let seconds = Self::extract_string(&frames[i + 1])?.parse::<u64>()
-                        .map_err(|_| FerrousError::Command(CommandError::InvalidIntegerValue))?;
-                    options.expiration = Some(Duration::from_secs(seconds));
+                    options.expiration = Some(Self::parse_expire_time(&Self::extract_bytes(&frames[i + 1])?, "set", false)?);
                     i += 2;
                 }
                 "PX" => {
                     if i + 1 >= frames.len() {
                         return Err(FerrousError::Command(CommandError::SyntaxError("Missing PX value".to_string())));
                     }
-                    let millis = Self::extract_string(&frames[i + 1])?.parse::<u64>()
-                        .map_err(|_| FerrousError::Command(CommandError::InvalidIntegerValue))?;
-                    options.expiration = Some(Duration::from_millis(millis));
+                    options.expiration = Some(Self::parse_expire_time(&Self::extract_bytes(&frames[i + 1])?, "set", true)?);
                     i += 2;
                 }
                 "KEEPTTL" => {
@@ -2089,6 +2448,18 @@ impl CommandParser {
         })
     }
 
+    fn parse_incrbyfloat(frames: &[RespFrame]) -> Result<StringCommand> {
+        if frames.len() != 3 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("INCRBYFLOAT".into())));
+        }
+        let increment = parse_float(&Self::extract_bytes(&frames[2])?)
+            .ok_or(FerrousError::Command(CommandError::NotFloat))?;
+        Ok(StringCommand::IncrByFloat {
+            key: Self::extract_bytes(&frames[1])?,
+            increment,
+        })
+    }
+
     fn parse_setnx(frames: &[RespFrame]) -> Result<StringCommand> {
         if frames.len() != 3 {
             return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("SETNX".into())));
@@ -2103,12 +2474,11 @@ impl CommandParser {
         if frames.len() != 4 {
             return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("SETEX".into())));
         }
-        let seconds = Self::extract_string(&frames[2])?.parse::<u64>()
-            .map_err(|_| FerrousError::Command(CommandError::InvalidIntegerValue))?;
+        let ttl = Self::parse_expire_time(&Self::extract_bytes(&frames[2])?, "setex", false)?;
         Ok(StringCommand::SetEx {
             key: Self::extract_bytes(&frames[1])?,
             value: Self::extract_bytes(&frames[3])?,
-            seconds,
+            seconds: ttl.as_secs(),
         })
     }
 
@@ -2116,12 +2486,11 @@ impl CommandParser {
         if frames.len() != 4 {
             return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("PSETEX".into())));
         }
-        let milliseconds = Self::extract_string(&frames[2])?.parse::<u64>()
-            .map_err(|_| FerrousError::Command(CommandError::InvalidIntegerValue))?;
+        let ttl = Self::parse_expire_time(&Self::extract_bytes(&frames[2])?, "psetex", true)?;
         Ok(StringCommand::PSetEx {
             key: Self::extract_bytes(&frames[1])?,
             value: Self::extract_bytes(&frames[3])?,
-            milliseconds,
+            milliseconds: ttl.as_millis() as u64,
         })
     }
 
@@ -2301,6 +2670,56 @@ impl CommandParser {
         })
     }
 
+    fn parse_lpos(frames: &[RespFrame]) -> Result<ListCommand> {
+        if frames.len() < 3 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("LPOS".into())));
+        }
+        let args = Self::geo_args(&frames[3..])?;
+        let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_slice()).collect();
+        Ok(ListCommand::LPos {
+            key: Self::extract_bytes(&frames[1])?,
+            element: Self::extract_bytes(&frames[2])?,
+            options: lists::parse_lpos(&args).map_err(Self::geo_error)?,
+        })
+    }
+    
+    fn parse_lmove(frames: &[RespFrame]) -> Result<ListCommand> {
+        if frames.len() != 5 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("LMOVE".into())));
+        }
+        let (from, to) = lists::parse_lmove_ends(&Self::extract_bytes(&frames[3])?, &Self::extract_bytes(&frames[4])?)
+            .map_err(Self::geo_error)?;
+        Ok(ListCommand::LMove {
+            source: Self::extract_bytes(&frames[1])?,
+            destination: Self::extract_bytes(&frames[2])?,
+            from,
+            to,
+        })
+    }
+    
+    fn parse_rpoplpush(frames: &[RespFrame]) -> Result<ListCommand> {
+        if frames.len() != 3 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("RPOPLPUSH".into())));
+        }
+        Ok(ListCommand::LMove {
+            source: Self::extract_bytes(&frames[1])?,
+            destination: Self::extract_bytes(&frames[2])?,
+            from: ListEnd::Right,
+            to: ListEnd::Left,
+        })
+    }
+    
+    fn parse_lmpop(frames: &[RespFrame]) -> Result<ListCommand> {
+        if frames.len() < 4 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("LMPOP".into())));
+        }
+        let args = Self::geo_args(&frames[1..])?;
+        let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_slice()).collect();
+        Ok(ListCommand::LMPop {
+            pop: lists::parse_lmpop(&args).map_err(Self::geo_error)?,
+        })
+    }
+    
     fn parse_sadd(frames: &[RespFrame]) -> Result<SetCommand> {
         if frames.len() < 3 {
             return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("SADD".into())));
@@ -2353,6 +2772,20 @@ impl CommandParser {
         })
     }
 
+    fn parse_smismember(frames: &[RespFrame]) -> Result<SetCommand> {
+        if frames.len() < 3 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("SMISMEMBER".into())));
+        }
+        let mut members = Vec::new();
+        for frame in &frames[2..] {
+            members.push(Self::extract_bytes(frame)?);
+        }
+        Ok(SetCommand::SMIsMember {
+            key: Self::extract_bytes(&frames[1])?,
+            members,
+        })
+    }
+    
     fn parse_sunion(frames: &[RespFrame]) -> Result<SetCommand> {
         if frames.len() < 2 {
             return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("SUNION".into())));
@@ -2375,6 +2808,17 @@ impl CommandParser {
         Ok(SetCommand::SInter { keys })
     }
 
+    fn parse_sintercard(frames: &[RespFrame]) -> Result<SetCommand> {
+        if frames.len() < 3 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("SINTERCARD".into())));
+        }
+        let args = Self::geo_args(&frames[1..])?;
+        let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_slice()).collect();
+        Ok(SetCommand::SInterCard {
+            card: sets::parse_sintercard(&args).map_err(Self::geo_error)?,
+        })
+    }
+    
     fn parse_sdiff(frames: &[RespFrame]) -> Result<SetCommand> {
         if frames.len() < 2 {
             return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("SDIFF".into())));
@@ -2391,8 +2835,9 @@ impl CommandParser {
             return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("SRANDMEMBER".into())));
         }
         let count = if frames.len() == 3 {
-            Some(Self::extract_string(&frames[2])?.parse::<i64>()
-                .map_err(|_| FerrousError::Command(CommandError::InvalidIntegerValue))?)
+            Some(Self::extract_string(&frames[2])?.parse::<i64>().ok()
+                .filter(|&count| count != i64::MIN)
+                .ok_or(FerrousError::Command(CommandError::InvalidIntegerValue))?)
         } else {
             None
         };
@@ -2543,6 +2988,80 @@ impl CommandParser {
         })
     }
 
+    fn parse_hincrbyfloat(frames: &[RespFrame]) -> Result<HashCommand> {
+        if frames.len() != 4 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("HINCRBYFLOAT".into())));
+        }
+        let increment = parse_float(&Self::extract_bytes(&frames[3])?)
+            .ok_or(FerrousError::Command(CommandError::NotFloat))?;
+        Ok(HashCommand::HIncrByFloat {
+            key: Self::extract_bytes(&frames[1])?,
+            field: Self::extract_bytes(&frames[2])?,
+            increment,
+        })
+    }
+
+    /// Parse the `FIELDS numfields field [field ...]` clause of the hash field TTL commands
+    fn parse_fields_clause(frames: &[RespFrame], start: usize) -> Result<Vec<Vec<u8>>> {
+        if frames.len() <= start + 1 || !Self::extract_string(&frames[start])?.eq_ignore_ascii_case("FIELDS") {
+            return Err(FerrousError::Command(CommandError::Generic(
+                "Mandatory argument FIELDS is missing or not at the right position".into())));
+        }
+        let numfields = Self::extract_string(&frames[start + 1])?.parse::<i64>()
+            .map_err(|_| FerrousError::Command(CommandError::InvalidIntegerValue))?;
+        if numfields <= 0 {
+            return Err(FerrousError::Command(CommandError::Generic(
+                "Parameter `numFields` should be greater than 0".into())));
+        }
+        if frames.len() - start - 2 != numfields as usize {
+            return Err(FerrousError::Command(CommandError::Generic(
+                "The `numfields` parameter must match the number of arguments".into())));
+        }
+        frames[start + 2..].iter().map(Self::extract_bytes).collect()
+    }
+
+    fn parse_hexpire(frames: &[RespFrame], name: &str, unit_ms: i64, absolute: bool) -> Result<HashCommand> {
+        if frames.len() < 6 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments(name.into())));
+        }
+        let time = Self::extract_string(&frames[2])?.parse::<i64>()
+            .map_err(|_| FerrousError::Command(CommandError::InvalidIntegerValue))?;
+        let millis = time.checked_mul(unit_ms)
+            .filter(|ms| *ms >= 0)
+            .ok_or_else(|| FerrousError::Command(CommandError::Generic(
+                format!("invalid expire time in '{}' command", name.to_lowercase()))))?;
+        let condition = ExpireCondition::parse(&Self::extract_bytes(&frames[3])?);
+        let fields_start = if condition.is_some() { 4 } else { 3 };
+        Ok(HashCommand::HExpire {
+            key: Self::extract_bytes(&frames[1])?,
+            millis,
+            absolute,
+            condition,
+            fields: Self::parse_fields_clause(frames, fields_start)?,
+        })
+    }
+
+    fn parse_hpersist(frames: &[RespFrame]) -> Result<HashCommand> {
+        if frames.len() < 5 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("HPERSIST".into())));
+        }
+        Ok(HashCommand::HPersist {
+            key: Self::extract_bytes(&frames[1])?,
+            fields: Self::parse_fields_clause(frames, 2)?,
+        })
+    }
+
+    fn parse_httl(frames: &[RespFrame], name: &str, in_millis: bool) -> Result<HashCommand> {
+        if frames.len() < 5 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments(name.into())));
+        }
+        Ok(HashCommand::HTtl {
+            key: Self::extract_bytes(&frames[1])?,
+            fields: Self::parse_fields_clause(frames, 2)?,
+            in_millis,
+        })
+    }
+
     fn parse_zadd(frames: &[RespFrame]) -> Result<SortedSetCommand> {
         if frames.len() < 4 || frames.len() % 2 != 0 {
             return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("ZADD".into())));
@@ -2704,24 +3223,54 @@ impl CommandParser {
         Ok(KeyCommand::Exists { keys })
     }
     
-    fn parse_expire(frames: &[RespFrame]) -> Result<KeyCommand> {
-        if frames.len() != 3 {
-            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("EXPIRE".into())));
+    fn parse_sort(frames: &[RespFrame], read_only: bool) -> Result<KeyCommand> {
+        if frames.len() < 2 {
+            let name = if read_only { "SORT_RO" } else { "SORT" };
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments(name.into())));
+        }
+        let args = frames[2..].iter()
+            .map(Self::extract_bytes)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(KeyCommand::Sort {
+            key: Self::extract_bytes(&frames[1])?,
+            options: sort::parse_options(&args, read_only)?,
+        })
+    }
+    
+    fn parse_touch(frames: &[RespFrame]) -> Result<KeyCommand> {
+        if frames.len() < 2 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("TOUCH".into())));
+        }
+        let keys = frames[1..].iter()
+            .map(Self::extract_bytes)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(KeyCommand::Touch { keys })
+    }
+    
+    fn parse_expire(frames: &[RespFrame], name: &str, unit_ms: i64, absolute: bool) -> Result<KeyCommand> {
+        if frames.len() < 3 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments(name.into())));
         }
         let key = Self::extract_bytes(&frames[1])?;
-        let seconds = Self::extract_string(&frames[2])?.parse::<u64>()
+        let time = Self::extract_string(&frames[2])?.parse::<i64>()
             .map_err(|_| FerrousError::Command(CommandError::InvalidIntegerValue))?;
-        Ok(KeyCommand::Expire { key, seconds })
+        let flags = frames[3..].iter().map(Self::extract_bytes).collect::<Result<Vec<_>>>()?;
+        let conditions = ExpireCondition::parse_flags(&flags)
+            .map_err(|msg| FerrousError::Command(CommandError::Generic(msg)))?;
+        let expires_at = strings::expire_deadline(time, unit_ms, absolute)
+            .ok_or_else(|| FerrousError::Command(CommandError::Generic(
+                format!("invalid expire time in '{}' command", name.to_lowercase()))))?;
+        Ok(KeyCommand::Expire { key, expires_at, conditions })
     }
 
-    fn parse_pexpire(frames: &[RespFrame]) -> Result<KeyCommand> {
-        if frames.len() != 3 {
-            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("PEXPIRE".into())));
+    fn parse_expire_time_query(frames: &[RespFrame], name: &str, in_millis: bool) -> Result<KeyCommand> {
+        if frames.len() != 2 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments(name.into())));
         }
-        let key = Self::extract_bytes(&frames[1])?;
-        let milliseconds = Self::extract_string(&frames[2])?.parse::<u64>()
-            .map_err(|_| FerrousError::Command(CommandError::InvalidIntegerValue))?;
-        Ok(KeyCommand::PExpire { key, milliseconds })
+        Ok(KeyCommand::ExpireTime {
+            key: Self::extract_bytes(&frames[1])?,
+            in_millis,
+        })
     }
 
     fn parse_ttl(frames: &[RespFrame]) -> Result<KeyCommand> {
@@ -2860,16 +3409,57 @@ impl CommandParser {
         })
     }
     
+    fn parse_lex_bound(frame: &RespFrame) -> Result<LexBound> {
+        LexBound::parse(&Self::extract_bytes(frame)?)
+            .ok_or_else(|| FerrousError::Command(CommandError::Generic("min or max not valid string range item".into())))
+    }
+    
+    fn parse_zrangebylex(frames: &[RespFrame], reverse: bool) -> Result<SortedSetCommand> {
+        let name = if reverse { "ZREVRANGEBYLEX" } else { "ZRANGEBYLEX" };
+        if frames.len() != 4 && frames.len() != 7 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments(name.into())));
+        }
+        let key = Self::extract_bytes(&frames[1])?;
+        let first = Self::parse_lex_bound(&frames[2])?;
+        let second = Self::parse_lex_bound(&frames[3])?;
+        let limit = if frames.len() == 7 {
+            if Self::extract_string(&frames[4])?.to_uppercase() != "LIMIT" {
+                return Err(FerrousError::Command(CommandError::SyntaxError("LIMIT expected".into())));
+            }
+            let offset = Self::extract_string(&frames[5])?.parse::<i64>()
+                .map_err(|_| FerrousError::Command(CommandError::NotInteger))?;
+            let count = Self::extract_string(&frames[6])?.parse::<i64>()
+                .map_err(|_| FerrousError::Command(CommandError::NotInteger))?;
+            Some((offset, count))
+        } else {
+            None
+        };
+        Ok(if reverse {
+            SortedSetCommand::ZRevRangeByLex { key, max: first, min: second, limit }
+        } else {
+            SortedSetCommand::ZRangeByLex { key, min: first, max: second, limit }
+        })
+    }
+    
+    fn parse_zlexcount(frames: &[RespFrame]) -> Result<SortedSetCommand> {
+        if frames.len() != 4 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("ZLEXCOUNT".into())));
+        }
+        Ok(SortedSetCommand::ZLexCount {
+            key: Self::extract_bytes(&frames[1])?,
+            min: Self::parse_lex_bound(&frames[2])?,
+            max: Self::parse_lex_bound(&frames[3])?,
+        })
+    }
+    
     fn parse_zremrangebylex(frames: &[RespFrame]) -> Result<SortedSetCommand> {
         if frames.len() != 4 {
             return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("ZREMRANGEBYLEX".into())));
         }
-        let min_lex = Self::extract_string(&frames[2])?;
-        let max_lex = Self::extract_string(&frames[3])?;
         Ok(SortedSetCommand::ZRemRangeByLex {
             key: Self::extract_bytes(&frames[1])?,
-            min_lex,
-            max_lex,
+            min: Self::parse_lex_bound(&frames[2])?,
+            max: Self::parse_lex_bound(&frames[3])?,
         })
     }
 
@@ -3380,8 +3970,7 @@ impl CommandParser {
             return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("GETBIT".into())));
         }
         let key = Self::extract_bytes(&frames[1])?;
-        let offset = Self::extract_string(&frames[2])?.parse::<usize>()
-            .map_err(|_| FerrousError::Command(CommandError::InvalidIntegerValue))?;
+        let offset = Self::parse_bit_offset(&frames[2])?;
         Ok(BitCommand::GetBit { key, offset })
     }
     
@@ -3390,8 +3979,7 @@ impl CommandParser {
             return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("SETBIT".into())));
         }
         let key = Self::extract_bytes(&frames[1])?;
-        let offset = Self::extract_string(&frames[2])?.parse::<usize>()
-            .map_err(|_| FerrousError::Command(CommandError::InvalidIntegerValue))?;
+        let offset = Self::parse_bit_offset(&frames[2])?;
         let value = match Self::extract_string(&frames[3])?.as_str() {
             "0" => 0,
             "1" => 1,
@@ -3400,23 +3988,178 @@ impl CommandParser {
         Ok(BitCommand::SetBit { key, offset, value })
     }
     
+    fn parse_bit_offset(frame: &RespFrame) -> Result<usize> {
+        bitmaps::parse_offset(&Self::extract_bytes(frame)?)
+            .ok_or_else(|| FerrousError::Command(CommandError::Generic("bit offset is not an integer or out of range".into())))
+    }
+    
+    fn parse_bit_unit(frame: Option<&RespFrame>) -> Result<BitUnit> {
+        match frame {
+            None => Ok(BitUnit::Byte),
+            Some(frame) => BitUnit::parse(&Self::extract_bytes(frame)?)
+                .ok_or_else(|| FerrousError::Command(CommandError::Generic("syntax error".into()))),
+        }
+    }
+    
     fn parse_bitcount(frames: &[RespFrame]) -> Result<BitCommand> {
-        if frames.len() < 2 || frames.len() > 4 {
+        if frames.len() < 2 || frames.len() > 5 {
             return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("BITCOUNT".into())));
         }
+        if frames.len() == 3 {
+            return Err(FerrousError::Command(CommandError::Generic("syntax error".into())));
+        }
         let key = Self::extract_bytes(&frames[1])?;
         
-        let (start, end) = if frames.len() == 4 {
-            let start = Self::extract_string(&frames[2])?.parse::<isize>()
+        let range = if frames.len() >= 4 {
+            let start = Self::extract_string(&frames[2])?.parse::<i64>()
                 .map_err(|_| FerrousError::Command(CommandError::InvalidIntegerValue))?;
-            let end = Self::extract_string(&frames[3])?.parse::<isize>()
+            let end = Self::extract_string(&frames[3])?.parse::<i64>()
                 .map_err(|_| FerrousError::Command(CommandError::InvalidIntegerValue))?;
-            (Some(start), Some(end))
+            Some((start, end, Self::parse_bit_unit(frames.get(4))?))
         } else {
-            (None, None)
+            None
         };
         
-        Ok(BitCommand::BitCount { key, start, end })
+        Ok(BitCommand::BitCount { key, range })
+    }
+    
+    fn parse_bitpos(frames: &[RespFrame]) -> Result<BitCommand> {
+        if frames.len() < 3 || frames.len() > 6 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("BITPOS".into())));
+        }
+        let key = Self::extract_bytes(&frames[1])?;
+        let bit = match Self::extract_string(&frames[2])?.as_str() {
+            "0" => false,
+            "1" => true,
+            _ => return Err(FerrousError::Command(CommandError::Generic("The bit argument must be 1 or 0.".into()))),
+        };
+        let mut numbers = Vec::new();
+        for frame in &frames[3..frames.len().min(5)] {
+            numbers.push(Self::extract_string(frame)?.parse::<i64>()
+                .map_err(|_| FerrousError::Command(CommandError::InvalidIntegerValue))?);
+        }
+        Ok(BitCommand::BitPos {
+            key,
+            bit,
+            start: numbers.first().copied().unwrap_or(0),
+            end: numbers.get(1).copied(),
+            unit: Self::parse_bit_unit(frames.get(5))?,
+        })
+    }
+    
+    fn parse_bitop(frames: &[RespFrame]) -> Result<BitCommand> {
+        if frames.len() < 4 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("BITOP".into())));
+        }
+        let operation = BitOperation::parse(&Self::extract_bytes(&frames[1])?)
+            .ok_or_else(|| FerrousError::Command(CommandError::Generic("syntax error".into())))?;
+        if operation == BitOperation::Not && frames.len() != 4 {
+            return Err(FerrousError::Command(CommandError::Generic("BITOP NOT must be called with a single source key.".into())));
+        }
+        let mut sources = Vec::with_capacity(frames.len() - 3);
+        for frame in &frames[3..] {
+            sources.push(Self::extract_bytes(frame)?);
+        }
+        Ok(BitCommand::BitOp {
+            operation,
+            dest: Self::extract_bytes(&frames[2])?,
+            sources,
+        })
+    }
+    
+    // HyperLogLog parsers
+    fn parse_pfadd(frames: &[RespFrame]) -> Result<HyperLogLogCommand> {
+        if frames.len() < 2 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("PFADD".into())));
+        }
+        let mut elements = Vec::with_capacity(frames.len() - 2);
+        for frame in &frames[2..] {
+            elements.push(Self::extract_bytes(frame)?);
+        }
+        Ok(HyperLogLogCommand::PfAdd { key: Self::extract_bytes(&frames[1])?, elements })
+    }
+    
+    fn parse_pfcount(frames: &[RespFrame]) -> Result<HyperLogLogCommand> {
+        if frames.len() < 2 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("PFCOUNT".into())));
+        }
+        let mut keys = Vec::with_capacity(frames.len() - 1);
+        for frame in &frames[1..] {
+            keys.push(Self::extract_bytes(frame)?);
+        }
+        Ok(HyperLogLogCommand::PfCount { keys })
+    }
+    
+    fn parse_pfmerge(frames: &[RespFrame]) -> Result<HyperLogLogCommand> {
+        if frames.len() < 2 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("PFMERGE".into())));
+        }
+        let mut sources = Vec::with_capacity(frames.len() - 2);
+        for frame in &frames[2..] {
+            sources.push(Self::extract_bytes(frame)?);
+        }
+        Ok(HyperLogLogCommand::PfMerge { dest: Self::extract_bytes(&frames[1])?, sources })
+    }
+    
+    // Geospatial parsers
+    fn geo_args(frames: &[RespFrame]) -> Result<Vec<Vec<u8>>> {
+        frames.iter().map(Self::extract_bytes).collect()
+    }
+    
+    fn geo_error(message: String) -> FerrousError {
+        FerrousError::Command(CommandError::Generic(message))
+    }
+    
+    fn parse_geoadd(frames: &[RespFrame]) -> Result<GeoCommand> {
+        if frames.len() < 5 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("GEOADD".into())));
+        }
+        let args = Self::geo_args(&frames[2..])?;
+        let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_slice()).collect();
+        let (options, items) = geo::parse_geoadd(&args).map_err(Self::geo_error)?;
+        Ok(GeoCommand::GeoAdd { key: Self::extract_bytes(&frames[1])?, options, items })
+    }
+    
+    fn parse_geopos(frames: &[RespFrame]) -> Result<GeoCommand> {
+        if frames.len() < 2 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("GEOPOS".into())));
+        }
+        Ok(GeoCommand::GeoPos { key: Self::extract_bytes(&frames[1])?, members: Self::geo_args(&frames[2..])? })
+    }
+    
+    fn parse_geodist(frames: &[RespFrame]) -> Result<GeoCommand> {
+        if frames.len() < 4 {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments("GEODIST".into())));
+        }
+        if frames.len() > 5 {
+            return Err(Self::geo_error("syntax error".to_string()));
+        }
+        let unit = match frames.get(4) {
+            Some(frame) => geo::parse_unit(&Self::extract_bytes(frame)?).map_err(Self::geo_error)?,
+            None => 1.0,
+        };
+        Ok(GeoCommand::GeoDist {
+            key: Self::extract_bytes(&frames[1])?,
+            first: Self::extract_bytes(&frames[2])?,
+            second: Self::extract_bytes(&frames[3])?,
+            unit,
+        })
+    }
+    
+    fn parse_geosearch(frames: &[RespFrame], store: bool) -> Result<GeoCommand> {
+        let (name, min_args) = if store { ("GEOSEARCHSTORE", 8) } else { ("GEOSEARCH", 7) };
+        if frames.len() < min_args {
+            return Err(FerrousError::Command(CommandError::WrongNumberOfArguments(name.into())));
+        }
+        let first_option = if store { 3 } else { 2 };
+        let args = Self::geo_args(&frames[first_option..])?;
+        let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_slice()).collect();
+        let query = GeoQuery::parse(&args, store).map_err(Self::geo_error)?;
+        if store {
+            Ok(GeoCommand::GeoSearchStore { dest: Self::extract_bytes(&frames[1])?, key: Self::extract_bytes(&frames[2])?, query })
+        } else {
+            Ok(GeoCommand::GeoSearch { key: Self::extract_bytes(&frames[1])?, query })
+        }
     }
     
     // Config command parser
@@ -3499,6 +4242,12 @@ impl LuaCommandAdapter {
         }
     }
     
+    /// Execute raw command arguments from a script as a typed command
+    pub fn execute(&self, args: Vec<Vec<u8>>, db_index: usize) -> Result<Reply> {
+        let parsed = CommandParser::parse_args(args)?;
+        self.executor.storage.execute(db_index, parsed.command)
+    }
+    
     /// Execute command from Lua context with proper atomicity
     pub fn execute_lua_command(
         &self,
@@ -3570,6 +4319,23 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_typed_execute() {
+        let storage = StorageEngine::new_in_memory();
+        
+        let set = CommandParser::parse_args(["SET", "k", "v"]).unwrap().command;
+        assert_eq!(storage.execute(0, set).unwrap(), Reply::Status("OK".to_string()));
+        
+        let get = CommandParser::parse_args(["GET", "k"]).unwrap().command;
+        assert_eq!(storage.execute(0, get).unwrap(), Reply::Bulk(Arc::new(b"v".to_vec())));
+        
+        let missing = CommandParser::parse_args(["GET", "missing"]).unwrap().command;
+        assert_eq!(storage.execute(0, missing).unwrap(), Reply::Nil);
+        
+        let incr = CommandParser::parse_args(["INCR", "k"]).unwrap().command;
+        assert!(storage.execute(0, incr).is_err());
+    }
+    
     #[test]
     fn test_lua_server_command_parity() {
         let storage = StorageEngine::new_in_memory();
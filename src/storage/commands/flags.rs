@@ -0,0 +1,106 @@
+//! Command classification flags
+//!
+//! One place that knows which commands modify or only read the dataset, shared
+//! by the server (AOF, replication and replica write filtering), the script
+//! bridge, its dirty tracking and read-only script enforcement.
+
+/// Check whether a command may modify the dataset
+///
+/// Blocking list commands are excluded: once served they are logged and
+/// propagated as their non-blocking form.
+pub fn is_write_command(name: &str) -> bool {
+    matches!(name,
+        "SET" | "SETNX" | "SETEX" | "PSETEX" | "GETSET" | "GETDEL" | "MSET" | "MSETNX" |
+        "APPEND" | "SETRANGE" | "INCR" | "DECR" | "INCRBY" | "DECRBY" | "INCRBYFLOAT" |
+        "DEL" | "UNLINK" | "RENAME" | "RENAMENX" | "COPY" | "RESTORE" | "RESTORE-ASKING" |
+        "EXPIRE" | "PEXPIRE" | "EXPIREAT" | "PEXPIREAT" | "PERSIST" |
+        "FLUSHDB" | "FLUSHALL" |
+        "LPUSH" | "RPUSH" | "LPUSHX" | "RPUSHX" | "LPOP" | "RPOP" | "LSET" | "LREM" | "LTRIM" |
+        "LINSERT" | "LMOVE" | "RPOPLPUSH" | "LMPOP" |
+        "SADD" | "SREM" | "SPOP" | "SMOVE" | "SINTERSTORE" | "SUNIONSTORE" | "SDIFFSTORE" |
+        "HSET" | "HMSET" | "HSETNX" | "HDEL" | "HINCRBY" | "HINCRBYFLOAT" |
+        "HEXPIRE" | "HPEXPIRE" | "HEXPIREAT" | "HPEXPIREAT" | "HPERSIST" |
+        "ZADD" | "ZREM" | "ZINCRBY" | "ZPOPMIN" | "ZPOPMAX" | "ZREMRANGEBYSCORE" | "ZREMRANGEBYRANK" |
+        "ZREMRANGEBYLEX" |
+        "XADD" | "XTRIM" | "XDEL" | "XGROUP" | "XACK" | "XCLAIM" |
+        "PFADD" | "PFMERGE" | "SETBIT" | "BITOP" | "SORT" | "GEOADD" | "GEOSEARCHSTORE" |
+        "EVAL" | "EVALSHA" | "FCALL"
+    )
+}
+
+/// Check whether a command is refused while memory is over maxmemory
+///
+/// These are the writes that can grow the dataset, Redis' `denyoom` commands.
+/// Deletions, pops, trims and TTL changes still run so clients can free
+/// memory, and scripts are left to fail on the writes they make.
+pub fn is_denyoom_command(name: &str) -> bool {
+    is_write_command(name) && !matches!(name,
+        "GETDEL" | "DEL" | "UNLINK" | "RENAME" | "RENAMENX" |
+        "EXPIRE" | "PEXPIRE" | "EXPIREAT" | "PEXPIREAT" | "PERSIST" | "FLUSHDB" | "FLUSHALL" |
+        "LPOP" | "RPOP" | "LREM" | "LTRIM" | "LMPOP" | "SREM" | "SPOP" | "SMOVE" |
+        "HDEL" | "HEXPIRE" | "HPEXPIRE" | "HEXPIREAT" | "HPEXPIREAT" | "HPERSIST" |
+        "ZREM" | "ZPOPMIN" | "ZPOPMAX" | "ZREMRANGEBYSCORE" | "ZREMRANGEBYRANK" | "ZREMRANGEBYLEX" |
+        "XTRIM" | "XDEL" | "XGROUP" | "XACK" | "XCLAIM" |
+        "EVAL" | "EVALSHA" | "FCALL"
+    )
+}
+
+/// Check whether a command only reads the dataset
+///
+/// This is the read-only classification behind the `_RO` command variants: a
+/// read-only script may issue exactly these commands (and any other non-write
+/// command such as PING), and they are the ones a replica serves to clients.
+pub fn is_read_only_command(name: &str) -> bool {
+    matches!(name,
+        "GET" | "MGET" | "GETRANGE" | "STRLEN" | "EXISTS" | "TYPE" | "TTL" | "PTTL" | "KEYS" | "SCAN" |
+        "EXPIRETIME" | "PEXPIRETIME" |
+        "RANDOMKEY" | "DBSIZE" | "TOUCH" | "OBJECT" | "MEMORY" | "DUMP" |
+        "LLEN" | "LRANGE" | "LINDEX" | "LPOS" |
+        "SCARD" | "SISMEMBER" | "SMISMEMBER" | "SMEMBERS" | "SRANDMEMBER" | "SINTER" | "SUNION" | "SDIFF" |
+        "SINTERCARD" | "SSCAN" |
+        "HGET" | "HMGET" | "HGETALL" | "HKEYS" | "HVALS" | "HLEN" | "HEXISTS" | "HSTRLEN" | "HSCAN" |
+        "HTTL" | "HPTTL" |
+        "ZCARD" | "ZCOUNT" | "ZSCORE" | "ZMSCORE" | "ZRANK" | "ZREVRANK" | "ZRANGE" | "ZREVRANGE" |
+        "ZRANGEBYSCORE" | "ZREVRANGEBYSCORE" | "ZRANGEBYLEX" | "ZREVRANGEBYLEX" | "ZLEXCOUNT" | "ZSCAN" |
+        "XLEN" | "XRANGE" | "XREVRANGE" | "XREAD" | "XINFO" | "XPENDING" |
+        "GETBIT" | "BITCOUNT" | "BITPOS" | "PFCOUNT" | "GEOPOS" | "GEODIST" | "GEOSEARCH" |
+        "SORT_RO" | "BITFIELD_RO" | "EVAL_RO" | "EVALSHA_RO" | "FCALL_RO"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_is_write_command() {
+        assert!(is_write_command("SET"));
+        assert!(is_write_command("HMSET"));
+        assert!(!is_write_command("GET"));
+        assert!(!is_write_command("BLPOP"));
+        assert!(is_write_command("LMPOP"));
+        assert!(!is_write_command("BLMOVE"));
+    }
+    
+    #[test]
+    fn test_denyoom_classification() {
+        assert!(is_denyoom_command("SET"));
+        assert!(is_denyoom_command("LMOVE"));
+        assert!(!is_denyoom_command("DEL"));
+        assert!(!is_denyoom_command("LPOP"));
+        assert!(!is_denyoom_command("EVAL"));
+        assert!(!is_denyoom_command("GET"));
+    }
+    
+    #[test]
+    fn test_read_only_classification() {
+        assert!(is_read_only_command("GET"));
+        assert!(is_read_only_command("EVAL_RO"));
+        assert!(!is_read_only_command("SET"));
+        
+        // Read-only variants never write
+        for name in ["SORT_RO", "BITFIELD_RO", "EVALSHA_RO", "FCALL_RO"] {
+            assert!(is_read_only_command(name) && !is_write_command(name));
+        }
+    }
+}
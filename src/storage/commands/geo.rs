@@ -0,0 +1,554 @@
+//! Geospatial command implementations
+//!
+//! Provides GEOADD, GEOPOS, GEODIST, GEOSEARCH and GEOSEARCHSTORE over sorted
+//! sets scored by 52-bit geohashes (see `storage::geo`). Parsing and searching
+//! are shared with the unified executor; parse errors are messages without the
+//! `ERR ` prefix.
+
+use crate::error::{CommandError, FerrousError, Result, StorageError};
+use crate::protocol::RespFrame;
+use crate::storage::geo::{self, Shape};
+use crate::storage::StorageEngine;
+use std::sync::Arc;
+
+/// Error for a distance unit other than m, km, ft or mi
+const UNSUPPORTED_UNIT: &str = "unsupported unit provided. please use M, KM, FT, MI";
+
+/// GEOADD options
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GeoAddOptions {
+    /// NX: only add new members
+    pub nx: bool,
+    
+    /// XX: only update existing members
+    pub xx: bool,
+    
+    /// CH: count updated members as well as added ones
+    pub ch: bool,
+}
+
+/// A location to add: longitude, latitude and member
+pub type GeoItem = (f64, f64, Vec<u8>);
+
+/// Where a GEOSEARCH starts
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoOrigin {
+    /// FROMMEMBER: the location of a member of the searched set
+    Member(Vec<u8>),
+    
+    /// FROMLONLAT: a longitude and latitude
+    LonLat(f64, f64),
+}
+
+/// Order of GEOSEARCH results
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoOrder {
+    /// As found
+    Unsorted,
+    Ascending,
+    Descending,
+}
+
+/// A parsed GEOSEARCH or GEOSEARCHSTORE query
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoQuery {
+    pub origin: GeoOrigin,
+    pub shape: Shape,
+    
+    /// Meters per unit of the BYRADIUS/BYBOX unit, used for reported distances
+    pub unit: f64,
+    pub order: GeoOrder,
+    
+    /// COUNT limit and whether ANY may stop at the first matches found
+    pub count: Option<(usize, bool)>,
+    pub with_coord: bool,
+    pub with_dist: bool,
+    pub with_hash: bool,
+    
+    /// GEOSEARCHSTORE STOREDIST: store distances instead of geohashes
+    pub store_dist: bool,
+}
+
+/// A member found by a search
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoMatch {
+    pub member: Vec<u8>,
+    
+    /// Distance from the origin in meters
+    pub distance: f64,
+    pub hash: u64,
+    pub longitude: f64,
+    pub latitude: f64,
+}
+
+/// Parse a float argument
+fn parse_float(bytes: &[u8]) -> std::result::Result<f64, String> {
+    std::str::from_utf8(bytes).ok()
+        .and_then(|text| text.parse::<f64>().ok())
+        .filter(|value| !value.is_nan())
+        .ok_or_else(|| "value is not a valid float".to_string())
+}
+
+/// Parse a longitude/latitude pair, checking that it can be encoded
+pub fn parse_coordinates(longitude: &[u8], latitude: &[u8]) -> std::result::Result<(f64, f64), String> {
+    let (longitude, latitude) = (parse_float(longitude)?, parse_float(latitude)?);
+    if !geo::is_valid(longitude, latitude) {
+        return Err(format!("invalid longitude,latitude pair {:.6},{:.6}", longitude, latitude));
+    }
+    Ok((longitude, latitude))
+}
+
+/// Parse a distance unit to meters per unit
+pub fn parse_unit(unit: &[u8]) -> std::result::Result<f64, String> {
+    geo::unit_factor(unit).ok_or_else(|| UNSUPPORTED_UNIT.to_string())
+}
+
+/// Parse the GEOADD arguments after the key
+pub fn parse_geoadd(args: &[&[u8]]) -> std::result::Result<(GeoAddOptions, Vec<GeoItem>), String> {
+    let mut options = GeoAddOptions::default();
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        match arg.to_ascii_uppercase().as_slice() {
+            b"NX" => options.nx = true,
+            b"XX" => options.xx = true,
+            b"CH" => options.ch = true,
+            _ => break,
+        }
+        index += 1;
+    }
+    
+    let triples = &args[index..];
+    if triples.is_empty() || !triples.len().is_multiple_of(3) || (options.nx && options.xx) {
+        return Err("syntax error".to_string());
+    }
+    let mut items = Vec::with_capacity(triples.len() / 3);
+    for triple in triples.chunks(3) {
+        let (longitude, latitude) = parse_coordinates(triple[0], triple[1])?;
+        items.push((longitude, latitude, triple[2].to_vec()));
+    }
+    Ok((options, items))
+}
+
+impl GeoQuery {
+    /// Parse the GEOSEARCH options after the key, or the GEOSEARCHSTORE ones when `store`
+    pub fn parse(args: &[&[u8]], store: bool) -> std::result::Result<Self, String> {
+        let command = if store { "GEOSEARCHSTORE" } else { "GEOSEARCH" };
+        let mut origin = None;
+        let mut shape = None;
+        let mut unit = 1.0;
+        let mut query = GeoQuery {
+            origin: GeoOrigin::LonLat(0.0, 0.0),
+            shape: Shape::Radius(0.0),
+            unit,
+            order: GeoOrder::Unsorted,
+            count: None,
+            with_coord: false,
+            with_dist: false,
+            with_hash: false,
+            store_dist: false,
+        };
+        let mut any = false;
+        
+        let mut index = 0;
+        while index < args.len() {
+            let remaining = args.len() - index - 1;
+            match args[index].to_ascii_uppercase().as_slice() {
+                b"WITHCOORD" => query.with_coord = true,
+                b"WITHDIST" => query.with_dist = true,
+                b"WITHHASH" => query.with_hash = true,
+                b"ANY" => any = true,
+                b"ASC" => query.order = GeoOrder::Ascending,
+                b"DESC" => query.order = GeoOrder::Descending,
+                b"STOREDIST" if store => query.store_dist = true,
+                b"COUNT" if remaining >= 1 => {
+                    let count = std::str::from_utf8(args[index + 1]).ok().and_then(|text| text.parse::<i64>().ok())
+                        .ok_or_else(|| "value is not an integer or out of range".to_string())?;
+                    if count <= 0 {
+                        return Err("COUNT must be > 0".to_string());
+                    }
+                    query.count = Some((count as usize, false));
+                    index += 1;
+                }
+                b"FROMMEMBER" if remaining >= 1 && origin.is_none() => {
+                    origin = Some(GeoOrigin::Member(args[index + 1].to_vec()));
+                    index += 1;
+                }
+                b"FROMLONLAT" if remaining >= 2 && origin.is_none() => {
+                    let (longitude, latitude) = parse_coordinates(args[index + 1], args[index + 2])?;
+                    origin = Some(GeoOrigin::LonLat(longitude, latitude));
+                    index += 2;
+                }
+                b"BYRADIUS" if remaining >= 2 && shape.is_none() => {
+                    let radius = parse_float(args[index + 1])?;
+                    if radius < 0.0 {
+                        return Err("radius cannot be negative".to_string());
+                    }
+                    unit = parse_unit(args[index + 2])?;
+                    shape = Some(Shape::Radius(radius * unit));
+                    index += 2;
+                }
+                b"BYBOX" if remaining >= 3 && shape.is_none() => {
+                    let (width, height) = (parse_float(args[index + 1])?, parse_float(args[index + 2])?);
+                    if width < 0.0 || height < 0.0 {
+                        return Err("height or width cannot be negative".to_string());
+                    }
+                    unit = parse_unit(args[index + 3])?;
+                    shape = Some(Shape::Box { width: width * unit, height: height * unit });
+                    index += 3;
+                }
+                _ => return Err("syntax error".to_string()),
+            }
+            index += 1;
+        }
+        
+        if store && (query.with_coord || query.with_dist || query.with_hash) {
+            return Err(format!("{} is not compatible with WITHDIST, WITHHASH and WITHCOORD options", command));
+        }
+        query.origin = match origin {
+            Some(origin) => origin,
+            None => return Err(format!("exactly one of FROMMEMBER or FROMLONLAT can be specified for {}", command)),
+        };
+        query.shape = match shape {
+            Some(shape) => shape,
+            None => return Err(format!("exactly one of BYRADIUS and BYBOX can be specified for {}", command)),
+        };
+        query.unit = unit;
+        if any {
+            match &mut query.count {
+                Some((_, count_any)) => *count_any = true,
+                None => return Err("the ANY argument requires COUNT argument".to_string()),
+            }
+        }
+        // A COUNT without ANY needs the closest matches
+        if matches!(query.count, Some((_, false))) && query.order == GeoOrder::Unsorted {
+            query.order = GeoOrder::Ascending;
+        }
+        Ok(query)
+    }
+}
+
+/// Add locations to a geo set, returning how many were added (or changed, with CH)
+pub fn geoadd(storage: &StorageEngine, db: usize, key: &[u8], options: GeoAddOptions, items: Vec<GeoItem>) -> Result<i64> {
+    let mut changed = 0;
+    for (longitude, latitude, member) in items {
+        let Some(hash) = geo::encode(longitude, latitude) else {
+            continue;
+        };
+        let score = hash as f64;
+        let existing = storage.zscore(db, key, &member)?;
+        if (options.nx && existing.is_some()) || (options.xx && existing.is_none()) {
+            continue;
+        }
+        storage.zadd(db, key.to_vec(), member, score)?;
+        match existing {
+            None => changed += 1,
+            Some(old) if options.ch && old != score => changed += 1,
+            Some(_) => {}
+        }
+    }
+    Ok(changed)
+}
+
+/// Decoded location of a member, if it is in the set
+pub fn position(storage: &StorageEngine, db: usize, key: &[u8], member: &[u8]) -> Result<Option<(f64, f64)>> {
+    Ok(storage.zscore(db, key, member)?.map(|score| geo::decode(score as u64)))
+}
+
+/// Format a coordinate the way Redis prints them
+pub fn format_coordinate(value: f64) -> String {
+    let text = format!("{:.17}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Format a distance in `unit` with four decimals
+fn format_distance(meters: f64, unit: f64) -> Vec<u8> {
+    format!("{:.4}", meters / unit).into_bytes()
+}
+
+/// Reply to GEOPOS
+pub fn positions_reply(storage: &StorageEngine, db: usize, key: &[u8], members: &[&[u8]]) -> Result<RespFrame> {
+    let mut replies = Vec::with_capacity(members.len());
+    for member in members {
+        replies.push(match position(storage, db, key, member)? {
+            Some((longitude, latitude)) => RespFrame::Array(Some(vec![
+                RespFrame::from_string(format_coordinate(longitude)),
+                RespFrame::from_string(format_coordinate(latitude)),
+            ])),
+            None => RespFrame::Array(None),
+        });
+    }
+    Ok(RespFrame::Array(Some(replies)))
+}
+
+/// Reply to GEODIST: the distance in `unit`, or nil when a member is missing
+pub fn distance_reply(storage: &StorageEngine, db: usize, key: &[u8], first: &[u8], second: &[u8], unit: f64) -> Result<RespFrame> {
+    let (Some(first), Some(second)) = (position(storage, db, key, first)?, position(storage, db, key, second)?) else {
+        return Ok(RespFrame::BulkString(None));
+    };
+    Ok(RespFrame::from_bytes(format_distance(geo::distance(first.0, first.1, second.0, second.1), unit)))
+}
+
+/// Members of a geo set inside the query's shape, ordered and limited as requested
+pub fn search(storage: &StorageEngine, db: usize, key: &[u8], query: &GeoQuery) -> Result<Vec<GeoMatch>> {
+    let members = storage.zrange(db, key, 0, -1, false)?;
+    if members.is_empty() {
+        return Ok(Vec::new());
+    }
+    let center = match &query.origin {
+        GeoOrigin::LonLat(longitude, latitude) => (*longitude, *latitude),
+        GeoOrigin::Member(member) => position(storage, db, key, member)?.ok_or_else(|| {
+            FerrousError::Command(CommandError::Generic("could not decode requested zset member".to_string()))
+        })?,
+    };
+    
+    let mut matches = Vec::new();
+    for (member, score) in members {
+        let hash = score as u64;
+        let (longitude, latitude) = geo::decode(hash);
+        if let Some(distance) = query.shape.distance_if_inside(center, (longitude, latitude)) {
+            matches.push(GeoMatch { member, distance, hash, longitude, latitude });
+            if query.count.is_some_and(|(count, any)| any && matches.len() == count) {
+                break;
+            }
+        }
+    }
+    
+    match query.order {
+        GeoOrder::Unsorted => {}
+        GeoOrder::Ascending => matches.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+        GeoOrder::Descending => matches.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+    }
+    if let Some((count, _)) = query.count {
+        matches.truncate(count);
+    }
+    Ok(matches)
+}
+
+/// Reply to GEOSEARCH: members, or `[member, dist?, hash?, [lon, lat]?]` entries with WITH options
+pub fn search_reply(matches: Vec<GeoMatch>, query: &GeoQuery) -> RespFrame {
+    let plain = !(query.with_coord || query.with_dist || query.with_hash);
+    let items = matches.into_iter().map(|found| {
+        if plain {
+            return RespFrame::from_bytes(found.member);
+        }
+        let mut entry = vec![RespFrame::from_bytes(found.member)];
+        if query.with_dist {
+            entry.push(RespFrame::from_bytes(format_distance(found.distance, query.unit)));
+        }
+        if query.with_hash {
+            entry.push(RespFrame::Integer(found.hash as i64));
+        }
+        if query.with_coord {
+            entry.push(RespFrame::Array(Some(vec![
+                RespFrame::from_string(format_coordinate(found.longitude)),
+                RespFrame::from_string(format_coordinate(found.latitude)),
+            ])));
+        }
+        RespFrame::Array(Some(entry))
+    }).collect();
+    RespFrame::Array(Some(items))
+}
+
+/// Run GEOSEARCHSTORE: store the matches in `dest`, returning how many were stored
+pub fn search_store(storage: &StorageEngine, db: usize, dest: &[u8], key: &[u8], query: &GeoQuery) -> Result<i64> {
+    let matches = search(storage, db, key, query)?;
+    let members = matches.into_iter()
+        .map(|found| {
+            let score = if query.store_dist { found.distance / query.unit } else { found.hash as f64 };
+            (found.member, score)
+        })
+        .collect();
+    Ok(storage.zstore(db, dest.to_vec(), members)? as i64)
+}
+
+/// Reply with an error matching a storage or search failure
+fn storage_error(e: FerrousError) -> Result<RespFrame> {
+    match e {
+        FerrousError::Storage(StorageError::WrongType) => {
+            Ok(RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"))
+        }
+        e @ FerrousError::Command(_) => Ok(RespFrame::error(e.to_string())),
+        e => Ok(RespFrame::error(format!("ERR {}", e))),
+    }
+}
+
+/// Collect key and argument bytes
+fn byte_args(parts: &[RespFrame]) -> Option<Vec<&[u8]>> {
+    parts.iter()
+        .map(|part| match part {
+            RespFrame::BulkString(Some(bytes)) => Some(bytes.as_slice()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Reply to a parse failure
+fn parse_error(message: String) -> Result<RespFrame> {
+    Ok(RespFrame::error(format!("ERR {}", message)))
+}
+
+/// Handle GEOADD command - Add locations to a geo set
+pub fn handle_geoadd(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 5 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'geoadd' command"));
+    }
+    
+    let Some(args) = byte_args(&parts[1..]) else {
+        return Ok(RespFrame::error("ERR invalid argument format"));
+    };
+    let (options, items) = match parse_geoadd(&args[1..]) {
+        Ok(parsed) => parsed,
+        Err(message) => return parse_error(message),
+    };
+    match geoadd(storage, db, args[0], options, items) {
+        Ok(changed) => Ok(RespFrame::Integer(changed)),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Handle GEOPOS command - Locations of members
+pub fn handle_geopos(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 2 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'geopos' command"));
+    }
+    
+    let Some(args) = byte_args(&parts[1..]) else {
+        return Ok(RespFrame::error("ERR invalid argument format"));
+    };
+    positions_reply(storage, db, args[0], &args[1..]).or_else(storage_error)
+}
+
+/// Handle GEODIST command - Distance between two members
+pub fn handle_geodist(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 4 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'geodist' command"));
+    }
+    if parts.len() > 5 {
+        return Ok(RespFrame::error("ERR syntax error"));
+    }
+    
+    let Some(args) = byte_args(&parts[1..]) else {
+        return Ok(RespFrame::error("ERR invalid argument format"));
+    };
+    let unit = match args.get(3).map(|unit| parse_unit(unit)).unwrap_or(Ok(1.0)) {
+        Ok(unit) => unit,
+        Err(message) => return parse_error(message),
+    };
+    distance_reply(storage, db, args[0], args[1], args[2], unit).or_else(storage_error)
+}
+
+/// Handle GEOSEARCH command - Members inside a radius or box
+pub fn handle_geosearch(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 7 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'geosearch' command"));
+    }
+    
+    let Some(args) = byte_args(&parts[1..]) else {
+        return Ok(RespFrame::error("ERR invalid argument format"));
+    };
+    let query = match GeoQuery::parse(&args[1..], false) {
+        Ok(query) => query,
+        Err(message) => return parse_error(message),
+    };
+    match search(storage, db, args[0], &query) {
+        Ok(matches) => Ok(search_reply(matches, &query)),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Handle GEOSEARCHSTORE command - Store the members GEOSEARCH would return
+pub fn handle_geosearchstore(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 8 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'geosearchstore' command"));
+    }
+    
+    let Some(args) = byte_args(&parts[1..]) else {
+        return Ok(RespFrame::error("ERR invalid argument format"));
+    };
+    let query = match GeoQuery::parse(&args[2..], true) {
+        Ok(query) => query,
+        Err(message) => return parse_error(message),
+    };
+    match search_store(storage, db, args[0], args[1], &query) {
+        Ok(stored) => Ok(RespFrame::Integer(stored)),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn command(words: &[&str]) -> Vec<RespFrame> {
+        words.iter().map(|word| RespFrame::BulkString(Some(Arc::new(word.as_bytes().to_vec())))).collect()
+    }
+    
+    fn bulk(text: &str) -> RespFrame {
+        RespFrame::from_string(text)
+    }
+    
+    fn array(items: Vec<RespFrame>) -> RespFrame {
+        RespFrame::Array(Some(items))
+    }
+    
+    #[test]
+    fn test_geo_commands() {
+        let storage = StorageEngine::new_in_memory();
+        let run = |handler: fn(&Arc<StorageEngine>, usize, &[RespFrame]) -> Result<RespFrame>, words: &[&str]| {
+            handler(&storage, 0, &command(words)).unwrap()
+        };
+        
+        assert_eq!(run(handle_geoadd, &["GEOADD", "Sicily", "13.361389", "38.115556", "Palermo", "15.087269", "37.502669", "Catania"]), RespFrame::Integer(2));
+        assert_eq!(run(handle_geoadd, &["GEOADD", "Sicily", "NX", "CH", "13.5", "38.1", "Palermo"]), RespFrame::Integer(0));
+        assert_eq!(run(handle_geoadd, &["GEOADD", "Sicily", "XX", "13.5", "38.1", "Nowhere"]), RespFrame::Integer(0));
+        assert_eq!(run(handle_geoadd, &["GEOADD", "Sicily", "200", "100", "Bad"]), RespFrame::error("ERR invalid longitude,latitude pair 200.000000,100.000000"));
+        assert_eq!(run(handle_geoadd, &["GEOADD", "Sicily", "NX", "XX", "1", "2", "m"]), RespFrame::error("ERR syntax error"));
+        
+        assert_eq!(run(handle_geodist, &["GEODIST", "Sicily", "Palermo", "Catania"]), bulk("166274.1516"));
+        assert_eq!(run(handle_geodist, &["GEODIST", "Sicily", "Palermo", "Catania", "km"]), bulk("166.2742"));
+        assert_eq!(run(handle_geodist, &["GEODIST", "Sicily", "Palermo", "Nowhere"]), RespFrame::BulkString(None));
+        assert_eq!(run(handle_geodist, &["GEODIST", "Sicily", "Palermo", "Catania", "yd"]), RespFrame::error(format!("ERR {}", UNSUPPORTED_UNIT)));
+        
+        assert_eq!(run(handle_geopos, &["GEOPOS", "Sicily", "Palermo", "Nowhere"]), array(vec![
+            array(vec![bulk("13.36138933897018433"), bulk("38.11555639549629859")]),
+            RespFrame::Array(None),
+        ]));
+        
+        assert_eq!(run(handle_geosearch, &["GEOSEARCH", "Sicily", "FROMLONLAT", "15", "37", "BYRADIUS", "200", "km", "ASC"]),
+            array(vec![bulk("Catania"), bulk("Palermo")]));
+        assert_eq!(run(handle_geosearch, &["GEOSEARCH", "Sicily", "FROMLONLAT", "15", "37", "BYBOX", "400", "400", "km", "ASC", "WITHCOORD", "WITHDIST", "WITHHASH"]),
+            array(vec![
+                array(vec![bulk("Catania"), bulk("56.4413"), RespFrame::Integer(3479447370796909),
+                    array(vec![bulk("15.08726745843887329"), bulk("37.50266842333162032")])]),
+                array(vec![bulk("Palermo"), bulk("190.4424"), RespFrame::Integer(3479099956230698),
+                    array(vec![bulk("13.36138933897018433"), bulk("38.11555639549629859")])]),
+            ]));
+        assert_eq!(run(handle_geosearch, &["GEOSEARCH", "Sicily", "FROMMEMBER", "Palermo", "BYRADIUS", "100", "km", "WITHDIST"]),
+            array(vec![array(vec![bulk("Palermo"), bulk("0.0000")])]));
+        assert_eq!(run(handle_geosearch, &["GEOSEARCH", "Sicily", "FROMLONLAT", "15", "37", "BYRADIUS", "200", "km", "DESC", "COUNT", "1"]),
+            array(vec![bulk("Palermo")]));
+        assert_eq!(run(handle_geosearch, &["GEOSEARCH", "Sicily", "FROMMEMBER", "Nowhere", "BYRADIUS", "1", "km"]),
+            RespFrame::error("ERR could not decode requested zset member"));
+        assert_eq!(run(handle_geosearch, &["GEOSEARCH", "Sicily", "BYRADIUS", "1", "km", "ASC", "WITHDIST"]),
+            RespFrame::error("ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH"));
+        assert_eq!(run(handle_geosearch, &["GEOSEARCH", "Sicily", "FROMLONLAT", "15", "37", "BYRADIUS", "1", "km", "ANY"]),
+            RespFrame::error("ERR the ANY argument requires COUNT argument"));
+        assert_eq!(run(handle_geosearch, &["GEOSEARCH", "missing", "FROMLONLAT", "15", "37", "BYRADIUS", "1", "km"]), array(vec![]));
+        
+        assert_eq!(run(handle_geosearchstore, &["GEOSEARCHSTORE", "near", "Sicily", "FROMLONLAT", "15", "37", "BYRADIUS", "200", "km", "STOREDIST"]),
+            RespFrame::Integer(2));
+        assert_eq!(format!("{:.4}", storage.zscore(0, b"near", b"Catania").unwrap().unwrap()), "56.4413");
+        assert_eq!(run(handle_geosearchstore, &["GEOSEARCHSTORE", "near", "Sicily", "FROMLONLAT", "15", "37", "BYRADIUS", "100", "km"]),
+            RespFrame::Integer(1));
+        assert_eq!(storage.zscore(0, b"near", b"Catania").unwrap(), Some(3479447370796909.0));
+        assert_eq!(storage.zscore(0, b"near", b"Palermo").unwrap(), None);
+        assert_eq!(run(handle_geosearchstore, &["GEOSEARCHSTORE", "near", "Sicily", "FROMLONLAT", "0", "0", "BYRADIUS", "1", "m"]),
+            RespFrame::Integer(0));
+        assert!(!storage.exists(0, b"near").unwrap());
+        assert_eq!(run(handle_geosearchstore, &["GEOSEARCHSTORE", "near", "Sicily", "FROMLONLAT", "15", "37", "BYRADIUS", "1", "m", "WITHDIST"]),
+            RespFrame::error("ERR GEOSEARCHSTORE is not compatible with WITHDIST, WITHHASH and WITHCOORD options"));
+        
+        storage.set_string(0, b"plain".to_vec(), b"value".to_vec()).unwrap();
+        assert_eq!(run(handle_geopos, &["GEOPOS", "plain", "x"]), RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"));
+    }
+}
@@ -5,7 +5,9 @@
 use crate::error::{FerrousError, Result, StorageError};
 use crate::protocol::RespFrame;
 use crate::storage::StorageEngine;
+use crate::storage::value::{ExpireCondition, parse_float};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Handle HSET command - Set hash field(s)
 pub fn handle_hset(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
@@ -374,4 +376,277 @@ pub fn handle_hincrby(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFram
             Ok(RespFrame::error(format!("ERR {}", e)))
         }
     }
-}
\ No newline at end of file
+}
+
+/// Handle HINCRBYFLOAT command - Increment a field by a float
+pub fn handle_hincrbyfloat(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() != 4 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'hincrbyfloat' command"));
+    }
+    
+    let key = match &parts[1] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_ref().clone(),
+        _ => return Ok(RespFrame::error("ERR invalid key format")),
+    };
+    
+    let field = match &parts[2] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_ref().clone(),
+        _ => return Ok(RespFrame::error("ERR invalid field format")),
+    };
+    
+    let increment = match &parts[3] {
+        RespFrame::BulkString(Some(bytes)) => match parse_float(bytes) {
+            Some(increment) => increment,
+            None => return Ok(RespFrame::error("ERR value is not a valid float")),
+        },
+        _ => return Ok(RespFrame::error("ERR invalid increment format")),
+    };
+    
+    match storage.hincrbyfloat(db, key, field, increment) {
+        Ok(new_value) => Ok(RespFrame::from_bytes(new_value)),
+        Err(FerrousError::Storage(StorageError::WrongType)) => {
+            Ok(RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"))
+        },
+        Err(e) => Ok(RespFrame::error(e.to_string())),
+    }
+}
+
+/// Units of the time argument for the HEXPIRE family
+#[derive(Debug, Clone, Copy)]
+enum FieldExpireUnit {
+    /// Relative seconds (HEXPIRE)
+    Seconds,
+    /// Relative milliseconds (HPEXPIRE)
+    Milliseconds,
+    /// Absolute unix time in seconds (HEXPIREAT)
+    UnixSeconds,
+    /// Absolute unix time in milliseconds (HPEXPIREAT)
+    UnixMilliseconds,
+}
+
+impl FieldExpireUnit {
+    /// Unix time in milliseconds a time argument stands for, `None` on overflow
+    fn deadline_millis(self, time: i64, now_ms: i64) -> Option<i64> {
+        match self {
+            FieldExpireUnit::Seconds => time.checked_mul(1000)?.checked_add(now_ms),
+            FieldExpireUnit::Milliseconds => time.checked_add(now_ms),
+            FieldExpireUnit::UnixSeconds => time.checked_mul(1000),
+            FieldExpireUnit::UnixMilliseconds => Some(time),
+        }
+    }
+}
+
+/// Parse the `FIELDS numfields field [field ...]` clause starting at `start`
+fn parse_fields_clause(parts: &[RespFrame], start: usize) -> std::result::Result<Vec<&[u8]>, RespFrame> {
+    match parts.get(start) {
+        Some(RespFrame::BulkString(Some(bytes))) if bytes.eq_ignore_ascii_case(b"FIELDS") => {}
+        _ => return Err(RespFrame::error("ERR Mandatory argument FIELDS is missing or not at the right position")),
+    }
+    
+    let numfields = match parts.get(start + 1) {
+        Some(RespFrame::BulkString(Some(bytes))) => {
+            match String::from_utf8_lossy(bytes).parse::<i64>() {
+                Ok(n) => n,
+                Err(_) => return Err(RespFrame::error("ERR value is not an integer or out of range")),
+            }
+        }
+        _ => return Err(RespFrame::error("ERR value is not an integer or out of range")),
+    };
+    
+    if numfields <= 0 {
+        return Err(RespFrame::error("ERR Parameter `numFields` should be greater than 0"));
+    }
+    
+    let fields = &parts[start + 2..];
+    if fields.len() != numfields as usize {
+        return Err(RespFrame::error("ERR The `numfields` parameter must match the number of arguments"));
+    }
+    
+    fields.iter().map(|frame| match frame {
+        RespFrame::BulkString(Some(bytes)) => Ok(bytes.as_slice()),
+        _ => Err(RespFrame::error("ERR invalid field format")),
+    }).collect()
+}
+
+/// Convert per-field integer results into a RESP array
+fn field_results_frame(results: Vec<i64>) -> RespFrame {
+    RespFrame::Array(Some(results.into_iter().map(RespFrame::Integer).collect()))
+}
+
+/// Shared implementation of HEXPIRE/HPEXPIRE/HEXPIREAT/HPEXPIREAT
+fn handle_field_expire(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame], name: &str, unit: FieldExpireUnit) -> Result<RespFrame> {
+    if parts.len() < 6 {
+        return Ok(RespFrame::error(format!("ERR wrong number of arguments for '{}' command", name)));
+    }
+    
+    // Extract key
+    let key = match &parts[1] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_ref(),
+        _ => return Ok(RespFrame::error("ERR invalid key format")),
+    };
+    
+    // Extract time argument
+    let time = match &parts[2] {
+        RespFrame::BulkString(Some(bytes)) => {
+            match String::from_utf8_lossy(bytes).parse::<i64>() {
+                Ok(n) => n,
+                Err(_) => return Ok(RespFrame::error("ERR value is not an integer or out of range")),
+            }
+        }
+        _ => return Ok(RespFrame::error("ERR value is not an integer or out of range")),
+    };
+    
+    if time < 0 {
+        return Ok(RespFrame::error(format!("ERR invalid expire time in '{}' command", name)));
+    }
+    
+    // Optional NX | XX | GT | LT condition
+    let (condition, fields_start) = match &parts[3] {
+        RespFrame::BulkString(Some(bytes)) => match ExpireCondition::parse(bytes) {
+            Some(condition) => (Some(condition), 4),
+            None => (None, 3),
+        },
+        _ => (None, 3),
+    };
+    
+    let fields = match parse_fields_clause(parts, fields_start) {
+        Ok(fields) => fields,
+        Err(error) => return Ok(error),
+    };
+    
+    // Resolve the deadline as a monotonic instant
+    let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    let relative_ms = unit.deadline_millis(time, now_ms).map(|ms| ms - now_ms);
+    
+    let expires_at = match relative_ms {
+        Some(ms) if ms <= 0 => Instant::now(),
+        Some(ms) => match Instant::now().checked_add(Duration::from_millis(ms as u64)) {
+            Some(instant) => instant,
+            None => return Ok(RespFrame::error(format!("ERR invalid expire time in '{}' command", name))),
+        },
+        None => return Ok(RespFrame::error(format!("ERR invalid expire time in '{}' command", name))),
+    };
+    
+    match storage.hexpire(db, key, &fields, expires_at, condition) {
+        Ok(results) => Ok(field_results_frame(results)),
+        Err(FerrousError::Storage(StorageError::WrongType)) => {
+            Ok(RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"))
+        },
+        Err(e) => {
+            Ok(RespFrame::error(format!("ERR {}", e)))
+        }
+    }
+}
+
+/// Handle HEXPIRE command - Set hash field TTLs in seconds
+pub fn handle_hexpire(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    handle_field_expire(storage, db, parts, "hexpire", FieldExpireUnit::Seconds)
+}
+
+/// Handle HPEXPIRE command - Set hash field TTLs in milliseconds
+pub fn handle_hpexpire(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    handle_field_expire(storage, db, parts, "hpexpire", FieldExpireUnit::Milliseconds)
+}
+
+/// Handle HEXPIREAT command - Set hash field expiration as a unix timestamp in seconds
+pub fn handle_hexpireat(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    handle_field_expire(storage, db, parts, "hexpireat", FieldExpireUnit::UnixSeconds)
+}
+
+/// Handle HPEXPIREAT command - Set hash field expiration as a unix timestamp in milliseconds
+pub fn handle_hpexpireat(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    handle_field_expire(storage, db, parts, "hpexpireat", FieldExpireUnit::UnixMilliseconds)
+}
+
+/// Command replicas apply for a successful HEXPIRE/HPEXPIRE/HEXPIREAT:
+/// HPEXPIREAT with the absolute deadline, so replicas expire the fields when
+/// the master does regardless of delay. `None` for other commands.
+pub fn propagated_field_expire<T: AsRef<[u8]>>(args: &[T]) -> Option<Vec<Vec<u8>>> {
+    let unit = match args.first()?.as_ref().to_ascii_uppercase().as_slice() {
+        b"HEXPIRE" => FieldExpireUnit::Seconds,
+        b"HPEXPIRE" => FieldExpireUnit::Milliseconds,
+        b"HEXPIREAT" => FieldExpireUnit::UnixSeconds,
+        _ => return None,
+    };
+    let time = std::str::from_utf8(args.get(2)?.as_ref()).ok()?.parse::<i64>().ok()?;
+    let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    let deadline_ms = unit.deadline_millis(time, now_ms)?;
+    
+    let mut command = vec![b"HPEXPIREAT".to_vec(), args[1].as_ref().to_vec(), deadline_ms.to_string().into_bytes()];
+    command.extend(args[3..].iter().map(|arg| arg.as_ref().to_vec()));
+    Some(command)
+}
+
+/// Handle HPERSIST command - Remove hash field TTLs
+pub fn handle_hpersist(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 5 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'hpersist' command"));
+    }
+    
+    // Extract key
+    let key = match &parts[1] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_ref(),
+        _ => return Ok(RespFrame::error("ERR invalid key format")),
+    };
+    
+    let fields = match parse_fields_clause(parts, 2) {
+        Ok(fields) => fields,
+        Err(error) => return Ok(error),
+    };
+    
+    match storage.hpersist(db, key, &fields) {
+        Ok(results) => Ok(field_results_frame(results)),
+        Err(FerrousError::Storage(StorageError::WrongType)) => {
+            Ok(RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"))
+        },
+        Err(e) => {
+            Ok(RespFrame::error(format!("ERR {}", e)))
+        }
+    }
+}
+
+/// Shared implementation of HTTL/HPTTL
+fn handle_field_ttl(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame], name: &str, in_millis: bool) -> Result<RespFrame> {
+    if parts.len() < 5 {
+        return Ok(RespFrame::error(format!("ERR wrong number of arguments for '{}' command", name)));
+    }
+    
+    // Extract key
+    let key = match &parts[1] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_ref(),
+        _ => return Ok(RespFrame::error("ERR invalid key format")),
+    };
+    
+    let fields = match parse_fields_clause(parts, 2) {
+        Ok(fields) => fields,
+        Err(error) => return Ok(error),
+    };
+    
+    match storage.hpttl(db, key, &fields) {
+        Ok(results) => {
+            let results = if in_millis {
+                results
+            } else {
+                // Round remaining milliseconds to the nearest second like Redis
+                results.into_iter().map(|ms| if ms < 0 { ms } else { (ms + 500) / 1000 }).collect()
+            };
+            Ok(field_results_frame(results))
+        }
+        Err(FerrousError::Storage(StorageError::WrongType)) => {
+            Ok(RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"))
+        },
+        Err(e) => {
+            Ok(RespFrame::error(format!("ERR {}", e)))
+        }
+    }
+}
+
+/// Handle HTTL command - Get remaining hash field TTLs in seconds
+pub fn handle_httl(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    handle_field_ttl(storage, db, parts, "httl", false)
+}
+
+/// Handle HPTTL command - Get remaining hash field TTLs in milliseconds
+pub fn handle_hpttl(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    handle_field_ttl(storage, db, parts, "hpttl", true)
+}
@@ -0,0 +1,116 @@
+//! HyperLogLog command implementations
+//!
+//! Provides PFADD, PFCOUNT and PFMERGE over HyperLogLogs stored as strings
+//! (see `storage::hyperloglog`).
+
+use crate::error::{FerrousError, Result, StorageError};
+use crate::protocol::RespFrame;
+use crate::storage::StorageEngine;
+use std::sync::Arc;
+
+/// Reply with an error matching a storage failure
+fn storage_error(e: FerrousError) -> Result<RespFrame> {
+    match e {
+        FerrousError::Storage(StorageError::WrongType) => {
+            Ok(RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"))
+        }
+        // Already carry their WRONGTYPE/INVALIDOBJ prefix
+        e @ FerrousError::Storage(StorageError::NotHyperLogLog | StorageError::CorruptedHyperLogLog) => {
+            Ok(RespFrame::error(e.to_string()))
+        }
+        e => Ok(RespFrame::error(format!("ERR {}", e))),
+    }
+}
+
+/// Collect key or element arguments
+fn byte_args(parts: &[RespFrame]) -> Option<Vec<&[u8]>> {
+    parts.iter()
+        .map(|part| match part {
+            RespFrame::BulkString(Some(bytes)) => Some(bytes.as_slice()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Handle PFADD command - Add elements to a HyperLogLog
+pub fn handle_pfadd(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 2 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'pfadd' command"));
+    }
+    
+    let Some(args) = byte_args(&parts[1..]) else {
+        return Ok(RespFrame::error("ERR invalid argument format"));
+    };
+    match storage.pfadd(db, args[0].to_vec(), &args[1..]) {
+        Ok(changed) => Ok(RespFrame::Integer(changed as i64)),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Handle PFCOUNT command - Estimate the cardinality of one HyperLogLog or the union of several
+pub fn handle_pfcount(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 2 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'pfcount' command"));
+    }
+    
+    let Some(keys) = byte_args(&parts[1..]) else {
+        return Ok(RespFrame::error("ERR invalid key format"));
+    };
+    match storage.pfcount(db, &keys) {
+        Ok(count) => Ok(RespFrame::Integer(count as i64)),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Handle PFMERGE command - Merge HyperLogLogs into a destination key
+pub fn handle_pfmerge(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 2 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'pfmerge' command"));
+    }
+    
+    let Some(keys) = byte_args(&parts[1..]) else {
+        return Ok(RespFrame::error("ERR invalid key format"));
+    };
+    match storage.pfmerge(db, keys[0].to_vec(), &keys[1..]) {
+        Ok(()) => Ok(RespFrame::ok()),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn command(words: &[&str]) -> Vec<RespFrame> {
+        words.iter().map(|word| RespFrame::BulkString(Some(Arc::new(word.as_bytes().to_vec())))).collect()
+    }
+    
+    #[test]
+    fn test_pf_commands() {
+        let storage = StorageEngine::new_in_memory();
+        let run = |handler: fn(&Arc<StorageEngine>, usize, &[RespFrame]) -> Result<RespFrame>, words: &[&str]| {
+            handler(&storage, 0, &command(words)).unwrap()
+        };
+        
+        assert_eq!(run(handle_pfadd, &["PFADD", "h1", "a", "b", "c", "d", "e", "f", "g"]), RespFrame::Integer(1));
+        assert_eq!(run(handle_pfadd, &["PFADD", "h1", "a"]), RespFrame::Integer(0));
+        assert_eq!(run(handle_pfcount, &["PFCOUNT", "h1"]), RespFrame::Integer(7));
+        assert_eq!(run(handle_pfadd, &["PFADD", "h2", "g", "h", "i"]), RespFrame::Integer(1));
+        assert_eq!(run(handle_pfadd, &["PFADD", "empty"]), RespFrame::Integer(1));
+        assert_eq!(run(handle_pfcount, &["PFCOUNT", "h1", "h2", "missing"]), RespFrame::Integer(9));
+        
+        assert_eq!(run(handle_pfmerge, &["PFMERGE", "all", "h1", "h2", "empty"]), RespFrame::ok());
+        assert_eq!(run(handle_pfcount, &["PFCOUNT", "all"]), RespFrame::Integer(9));
+        assert_eq!(storage.get_string(0, b"all").unwrap().unwrap().len(), crate::storage::hyperloglog::DENSE_SIZE);
+        
+        // The cached estimate is used until the next change
+        assert_eq!(run(handle_pfcount, &["PFCOUNT", "h1"]), RespFrame::Integer(7));
+        assert_eq!(run(handle_pfadd, &["PFADD", "h1", "z"]), RespFrame::Integer(1));
+        assert_eq!(run(handle_pfcount, &["PFCOUNT", "h1"]), RespFrame::Integer(8));
+        
+        storage.set_string(0, b"plain".to_vec(), b"value".to_vec()).unwrap();
+        assert_eq!(run(handle_pfadd, &["PFADD", "plain", "x"]), RespFrame::error("WRONGTYPE Key is not a valid HyperLogLog string value."));
+        storage.sadd(0, b"set".to_vec(), vec![b"m".to_vec()]).unwrap();
+        assert_eq!(run(handle_pfcount, &["PFCOUNT", "h1", "set"]), RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"));
+    }
+}
@@ -1,11 +1,180 @@
 //! List command implementations
 //! 
 //! Provides Redis-compatible list operations including push, pop, range, and more.
+//! Parsing for LPOS, LMOVE and LMPOP is shared with the unified executor and
+//! with the server's blocking forms; parse errors are messages without the
+//! `ERR ` prefix.
 
 use crate::error::{FerrousError, Result, StorageError};
 use crate::protocol::RespFrame;
+use crate::storage::value::ListEnd;
 use crate::storage::StorageEngine;
 use std::sync::Arc;
+use std::time::Duration;
+
+/// Error for an LPOS RANK of zero
+const ZERO_RANK: &str = "RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list";
+
+/// LPOS options
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LposOptions {
+    /// Match to start from; negative counts matches from the tail
+    pub rank: i64,
+    /// Matches to return, 0 for all; without COUNT the reply is a single position
+    pub count: Option<usize>,
+    /// Elements to compare, 0 for the whole list
+    pub maxlen: usize,
+}
+
+impl Default for LposOptions {
+    fn default() -> Self {
+        LposOptions { rank: 1, count: None, maxlen: 0 }
+    }
+}
+
+/// Key an LMPOP popped from, with the popped elements
+pub type Popped = (Vec<u8>, Vec<Vec<u8>>);
+
+/// Keys, end and count of an LMPOP or BLMPOP
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiPop {
+    pub keys: Vec<Vec<u8>>,
+    pub end: ListEnd,
+    pub count: usize,
+}
+
+fn parse_integer(arg: &[u8]) -> std::result::Result<i64, String> {
+    std::str::from_utf8(arg).ok()
+        .and_then(|arg| arg.parse().ok())
+        .ok_or_else(|| "value is not an integer or out of range".to_string())
+}
+
+/// Parse the LPOS options after the key and element
+pub fn parse_lpos(args: &[&[u8]]) -> std::result::Result<LposOptions, String> {
+    let mut options = LposOptions::default();
+    for pair in args.chunks(2) {
+        let [name, value] = pair else {
+            return Err("syntax error".to_string());
+        };
+        match name.to_ascii_uppercase().as_slice() {
+            b"RANK" => match parse_integer(value)? {
+                0 => return Err(ZERO_RANK.to_string()),
+                rank => options.rank = rank,
+            },
+            b"COUNT" => {
+                let count = usize::try_from(parse_integer(value)?).map_err(|_| "COUNT can't be negative".to_string())?;
+                options.count = Some(count);
+            }
+            b"MAXLEN" => {
+                options.maxlen = usize::try_from(parse_integer(value)?).map_err(|_| "MAXLEN can't be negative".to_string())?;
+            }
+            _ => return Err("syntax error".to_string()),
+        }
+    }
+    Ok(options)
+}
+
+/// Parse the LMOVE wherefrom and whereto arguments
+pub fn parse_lmove_ends(from: &[u8], to: &[u8]) -> std::result::Result<(ListEnd, ListEnd), String> {
+    match (ListEnd::parse(from), ListEnd::parse(to)) {
+        (Some(from), Some(to)) => Ok((from, to)),
+        _ => Err("syntax error".to_string()),
+    }
+}
+
+/// Parse the LMPOP arguments from numkeys on
+pub fn parse_lmpop(args: &[&[u8]]) -> std::result::Result<MultiPop, String> {
+    let numkeys = args.first()
+        .and_then(|numkeys| parse_integer(numkeys).ok())
+        .filter(|&numkeys| numkeys > 0)
+        .ok_or_else(|| "numkeys should be greater than 0".to_string())? as usize;
+    if numkeys > args.len().saturating_sub(2) {
+        return Err("syntax error".to_string());
+    }
+    
+    let keys = args[1..=numkeys].iter().map(|key| key.to_vec()).collect();
+    let end = ListEnd::parse(args[numkeys + 1]).ok_or_else(|| "syntax error".to_string())?;
+    let count = match &args[numkeys + 2..] {
+        [] => 1,
+        [name, count] if name.eq_ignore_ascii_case(b"COUNT") => parse_integer(count).ok()
+            .filter(|&count| count > 0)
+            .ok_or_else(|| "count should be greater than 0".to_string())? as usize,
+        _ => return Err("syntax error".to_string()),
+    };
+    Ok(MultiPop { keys, end, count })
+}
+
+/// Parse a blocking timeout in seconds; zero blocks forever
+pub fn parse_timeout(arg: &[u8]) -> std::result::Result<Option<Duration>, String> {
+    let timeout = std::str::from_utf8(arg).ok()
+        .and_then(|arg| arg.parse::<f64>().ok())
+        .filter(|timeout| timeout.is_finite())
+        .ok_or_else(|| "timeout is not a float or out of range".to_string())?;
+    if timeout < 0.0 {
+        return Err("timeout is negative".to_string());
+    }
+    Ok((timeout > 0.0).then(|| Duration::from_secs_f64(timeout)))
+}
+
+/// Positions of `element` in the list at `key`
+pub fn lpos(storage: &StorageEngine, db: usize, key: &[u8], element: &[u8], options: &LposOptions) -> Result<Vec<usize>> {
+    storage.lpos(db, key, element, options.rank, options.count.unwrap_or(1), options.maxlen)
+}
+
+/// LPOS reply: a position or nil without COUNT, an array of positions with it
+pub fn lpos_reply(positions: Vec<usize>, options: &LposOptions) -> RespFrame {
+    match options.count {
+        Some(_) => RespFrame::Array(Some(positions.into_iter().map(|index| RespFrame::Integer(index as i64)).collect())),
+        None => positions.first().map_or_else(RespFrame::null_bulk, |&index| RespFrame::Integer(index as i64)),
+    }
+}
+
+/// Pop from the first non-empty list among the keys
+pub fn lmpop(storage: &StorageEngine, db: usize, pop: &MultiPop) -> Result<Option<Popped>> {
+    for key in &pop.keys {
+        let elements = storage.list_pop_count(db, key, pop.end, pop.count)?;
+        if !elements.is_empty() {
+            return Ok(Some((key.clone(), elements)));
+        }
+    }
+    Ok(None)
+}
+
+/// LMPOP reply: the key and its popped elements, or a null array
+pub fn lmpop_reply(popped: Option<Popped>) -> RespFrame {
+    match popped {
+        Some((key, elements)) => RespFrame::Array(Some(vec![
+            RespFrame::from_bytes(key),
+            RespFrame::Array(Some(elements.into_iter().map(RespFrame::from_bytes).collect())),
+        ])),
+        None => RespFrame::null_array(),
+    }
+}
+
+fn storage_error(e: FerrousError) -> Result<RespFrame> {
+    match e {
+        FerrousError::Storage(StorageError::WrongType) => {
+            Ok(RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"))
+        }
+        e @ FerrousError::Command(_) => Ok(RespFrame::error(e.to_string())),
+        e => Ok(RespFrame::error(format!("ERR {}", e))),
+    }
+}
+
+/// Collect key and argument bytes
+fn byte_args(parts: &[RespFrame]) -> Option<Vec<&[u8]>> {
+    parts.iter()
+        .map(|part| match part {
+            RespFrame::BulkString(Some(bytes)) => Some(bytes.as_slice()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Reply to a parse failure
+fn parse_error(message: String) -> Result<RespFrame> {
+    Ok(RespFrame::error(format!("ERR {}", message)))
+}
 
 /// Handle LPUSH command - Insert elements at the head of the list
 pub fn handle_lpush(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
@@ -360,4 +529,75 @@ pub fn handle_lrem(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame])
             Ok(RespFrame::error(format!("ERR {}", e)))
         }
     }
-}
\ No newline at end of file
+}
+/// Handle LPOS command - Positions of an element in a list
+pub fn handle_lpos(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 3 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'lpos' command"));
+    }
+    
+    let Some(args) = byte_args(&parts[1..]) else {
+        return Ok(RespFrame::error("ERR invalid argument format"));
+    };
+    let options = match parse_lpos(&args[2..]) {
+        Ok(options) => options,
+        Err(message) => return parse_error(message),
+    };
+    match lpos(storage, db, args[0], args[1], &options) {
+        Ok(positions) => Ok(lpos_reply(positions, &options)),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Handle LMOVE command - Move an element between the ends of two lists
+pub fn handle_lmove(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() != 5 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'lmove' command"));
+    }
+    
+    let Some(args) = byte_args(&parts[1..]) else {
+        return Ok(RespFrame::error("ERR invalid argument format"));
+    };
+    let (from, to) = match parse_lmove_ends(args[2], args[3]) {
+        Ok(ends) => ends,
+        Err(message) => return parse_error(message),
+    };
+    match storage.lmove(db, args[0], args[1].to_vec(), from, to) {
+        Ok(moved) => Ok(moved.map_or_else(RespFrame::null_bulk, RespFrame::from_bytes)),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Handle RPOPLPUSH command - LMOVE from the tail to the head
+pub fn handle_rpoplpush(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() != 3 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'rpoplpush' command"));
+    }
+    
+    let Some(args) = byte_args(&parts[1..]) else {
+        return Ok(RespFrame::error("ERR invalid argument format"));
+    };
+    match storage.lmove(db, args[0], args[1].to_vec(), ListEnd::Right, ListEnd::Left) {
+        Ok(moved) => Ok(moved.map_or_else(RespFrame::null_bulk, RespFrame::from_bytes)),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Handle LMPOP command - Pop elements from the first non-empty list
+pub fn handle_lmpop(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 4 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'lmpop' command"));
+    }
+    
+    let Some(args) = byte_args(&parts[1..]) else {
+        return Ok(RespFrame::error("ERR invalid argument format"));
+    };
+    let pop = match parse_lmpop(&args) {
+        Ok(pop) => pop,
+        Err(message) => return parse_error(message),
+    };
+    match lmpop(storage, db, &pop) {
+        Ok(popped) => Ok(lmpop_reply(popped)),
+        Err(e) => storage_error(e),
+    }
+}
@@ -1,18 +1,27 @@
 //! MEMORY command implementations
-//! 
+//!
 //! Provides Redis-compatible commands for memory usage reporting and management.
 
-use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 use crate::error::Result;
+use crate::network::server::ServerStats;
 use crate::protocol::RespFrame;
-use crate::storage::{StorageEngine, Value};
-use crate::storage::memory::MemoryManager;
+use crate::storage::StorageEngine;
+use crate::storage::memory::EvictionPolicy;
+use super::monitor::get_process_rss;
+
+/// Collection elements MEMORY USAGE sizes when SAMPLES is not given
+const DEFAULT_SAMPLES: usize = 5;
+
+/// Below this much memory MEMORY DOCTOR has nothing meaningful to say
+const DOCTOR_MIN_MEMORY: usize = 5 * 1024 * 1024;
 
 /// Handle MEMORY command and its subcommands
 pub fn handle_memory(
-    parts: &[RespFrame], 
+    parts: &[RespFrame],
     storage: &Arc<StorageEngine>,
+    stats: &Arc<ServerStats>,
     db: usize,
 ) -> Result<RespFrame> {
     if parts.len() < 2 {
@@ -28,238 +37,242 @@ pub fn handle_memory(
     };
     
     match subcommand.as_str() {
-        "USAGE" => handle_memory_usage(parts, storage, db),
-        "STATS" => handle_memory_stats(storage),
-        "DOCTOR" => handle_memory_doctor(storage),
+        "USAGE" if parts.len() >= 3 => handle_memory_usage(parts, storage, db),
+        "STATS" if parts.len() == 2 => handle_memory_stats(storage, stats),
+        "DOCTOR" if parts.len() == 2 => handle_memory_doctor(storage, stats),
         "HELP" => handle_memory_help(),
         _ => Ok(RespFrame::error(format!("ERR Unknown subcommand or wrong number of arguments for 'memory {}'", subcommand)))
     }
 }
 
-/// Handle MEMORY USAGE command to report memory consumption of a key
-/// 
-/// This command estimates the memory usage of a key and its value
+/// Handle MEMORY USAGE key [SAMPLES count]
+///
+/// Estimates the bytes held by a key and its value. Collections are sized
+/// from their first `count` elements (5 by default, 0 for all of them).
 pub fn handle_memory_usage(parts: &[RespFrame], storage: &Arc<StorageEngine>, db: usize) -> Result<RespFrame> {
-    if parts.len() < 3 {
-        return Ok(RespFrame::error("ERR wrong number of arguments for 'memory usage' command"));
-    }
-    
     // Extract key
     let key = match &parts[2] {
         RespFrame::BulkString(Some(bytes)) => bytes.as_ref(),
         _ => return Ok(RespFrame::error("ERR invalid key format")),
     };
     
-    // Check if key exists - return nil for non-existent keys (correct Redis behavior)
-    if !storage.exists(db, key)? {
-        return Ok(RespFrame::null_bulk());
-    }
-    
-    // Get the memory usage
-    let memory_usage = calculate_key_memory_usage(storage, db, key)?;
+    let samples = match &parts[3..] {
+        [] => DEFAULT_SAMPLES,
+        [RespFrame::BulkString(Some(option)), RespFrame::BulkString(Some(count))]
+            if option.eq_ignore_ascii_case(b"SAMPLES") =>
+        {
+            match std::str::from_utf8(count).ok().and_then(|count| count.parse::<usize>().ok()) {
+                Some(count) => count,
+                None => return Ok(RespFrame::error("ERR value is not an integer or out of range")),
+            }
+        }
+        _ => return Ok(RespFrame::error("ERR syntax error")),
+    };
     
-    Ok(RespFrame::Integer(memory_usage as i64))
+    // Nil for keys that don't exist, as in Redis
+    match storage.key_memory_usage(db, key, samples)? {
+        Some(usage) => Ok(RespFrame::Integer(usage as i64)),
+        None => Ok(RespFrame::null_bulk()),
+    }
 }
 
-/// Helper function to calculate memory usage of a key
-fn calculate_key_memory_usage(storage: &Arc<StorageEngine>, db: usize, key: &[u8]) -> Result<usize> {
-    // First, see what type of value we're dealing with
-    let key_type = storage.key_type(db, key)?;
-
-    // Calculate base memory (key + metadata overhead)
-    let mut total_size = MemoryManager::calculate_size(key);
+/// Handle MEMORY STATS command to report server memory usage statistics
+///
+/// `dataset.bytes` is the memory accounted to keys and values (INFO's
+/// used_memory); the key tables come on top of it as `overhead.total`.
+pub fn handle_memory_stats(storage: &Arc<StorageEngine>, stats: &Arc<ServerStats>) -> Result<RespFrame> {
+    let dataset = storage.memory_usage();
+    let peak = stats.peak_memory.fetch_max(dataset, Ordering::Relaxed).max(dataset);
     
-    // Add value-specific memory usage
-    match key_type.as_str() {
-        "string" => {
-            if let Ok(Some(value)) = storage.get_string(db, key) {
-                total_size += MemoryManager::calculate_size(&value);
-                total_size += std::mem::size_of::<Value>(); // Value enum overhead
-            }
-        },
-        "list" => {
-            // Approximate calculation based on elements
-            if let Ok(list_len) = storage.llen(db, key) {
-                if list_len > 0 {
-                    // Sample a few items to get average size
-                    let mut sample_size = 0;
-                    let mut sample_count = 0;
-                    
-                    // Try to get a few elements from different positions
-                    let positions = [0, list_len/2, list_len.saturating_sub(1)];
-                    for pos in positions.iter().take_while(|&&p| p < list_len) {
-                        if let Ok(Some(item)) = storage.lindex(db, key, *pos as isize) {
-                            sample_size += MemoryManager::calculate_size(&item);
-                            sample_count += 1;
-                        }
-                    }
-                    
-                    let avg_item_size = if sample_count > 0 {
-                        sample_size / sample_count
-                    } else {
-                        0
-                    };
-                    
-                    total_size += avg_item_size * list_len; // Element size
-                    total_size += std::mem::size_of::<std::collections::VecDeque<Vec<u8>>>(); // List struct overhead
-                }
-            }
-        },
-        "set" => {
-            // Approximate calculation based on members
-            if let Ok(set_size) = storage.scard(db, key) {
-                if set_size > 0 {
-                    if let Ok(members) = storage.smembers(db, key) {
-                        let mut member_size = 0;
-                        for member in members.iter().take(std::cmp::min(set_size, 10)) {
-                            member_size += MemoryManager::calculate_size(member);
-                        }
-                        
-                        let avg_member_size = if members.len() > 0 {
-                            member_size / members.len()
-                        } else {
-                            0
-                        };
-                        
-                        total_size += avg_member_size * set_size; // Member size
-                        total_size += std::mem::size_of::<std::collections::HashSet<Vec<u8>>>(); // Set struct overhead
-                    }
-                }
-            }
-        },
-        "hash" => {
-            // Get all fields and values and calculate size
-            if let Ok(hash_len) = storage.hlen(db, key) {
-                if hash_len > 0 {
-                    if let Ok(pairs) = storage.hgetall(db, key) {
-                        for (field, value) in pairs {
-                            total_size += MemoryManager::calculate_size(&field);
-                            total_size += MemoryManager::calculate_size(&value);
-                        }
-                        
-                        total_size += std::mem::size_of::<HashMap<Vec<u8>, Vec<u8>>>(); // Hash struct overhead
-                    }
-                }
-            }
-        },
-        "zset" => {
-            // Estimate based on a sample of sorted set entries
-            // We're making a simplified calculation here - for more accuracy we'd need access to internal SkipList details
-            // But this gives a reasonable approximation
-            if let Ok(range) = storage.zrange(db, key, 0, -1, false) {
-                for (member, _) in range {
-                    total_size += MemoryManager::calculate_size(&member);
-                    total_size += std::mem::size_of::<f64>(); // Score size
-                }
-                
-                // Add overhead for SkipList structure
-                total_size += std::mem::size_of::<crate::storage::skiplist::SkipList<Vec<u8>, f64>>(); // SkipList overhead
-            }
-        },
-        _ => {
-            // Unknown type or none
-            // Only count key overhead already added above
+    let mut keys_total = 0;
+    let mut overhead_total = 0;
+    let mut databases = Vec::new();
+    for db in 0..storage.database_count() {
+        let (keys, _) = storage.keyspace_counts(db)?;
+        let (main, expires) = storage.table_overhead(db)?;
+        keys_total += keys;
+        overhead_total += main + expires;
+        if keys > 0 {
+            databases.push((db, main, expires));
         }
     }
     
-    // Include standard metadata overhead
-    total_size += std::mem::size_of::<super::super::value::StoredValue>(); // StoredValue overhead
+    let total = dataset + overhead_total;
+    let rss = get_process_rss();
+    let percent = |part: usize, whole: usize| if whole == 0 { 0.0 } else { part as f64 * 100.0 / whole as f64 };
+    let ratio = if total == 0 { 1.0 } else { rss as f64 / total as f64 };
+    
+    let mut reply = vec![
+        RespFrame::from_string("peak.allocated"),
+        RespFrame::Integer(peak as i64),
+        RespFrame::from_string("total.allocated"),
+        RespFrame::Integer(total as i64),
+        RespFrame::from_string("overhead.total"),
+        RespFrame::Integer(overhead_total as i64),
+    ];
+    for (db, main, expires) in databases {
+        reply.push(RespFrame::from_string(format!("db.{}", db)));
+        reply.push(RespFrame::array(vec![
+            RespFrame::from_string("overhead.hashtable.main"),
+            RespFrame::Integer(main as i64),
+            RespFrame::from_string("overhead.hashtable.expires"),
+            RespFrame::Integer(expires as i64),
+        ]));
+    }
+    reply.extend([
+        RespFrame::from_string("keys.count"),
+        RespFrame::Integer(keys_total as i64),
+        RespFrame::from_string("keys.bytes-per-key"),
+        RespFrame::Integer(total.checked_div(keys_total).unwrap_or(0) as i64),
+        RespFrame::from_string("dataset.bytes"),
+        RespFrame::Integer(dataset as i64),
+        RespFrame::from_string("dataset.percentage"),
+        RespFrame::from_string(format!("{:.2}", percent(dataset, total))),
+        RespFrame::from_string("peak.percentage"),
+        RespFrame::from_string(format!("{:.2}", percent(total, peak + overhead_total))),
+        RespFrame::from_string("allocator.resident"),
+        RespFrame::Integer(rss as i64),
+        RespFrame::from_string("fragmentation"),
+        RespFrame::from_string(format!("{:.2}", ratio)),
+        RespFrame::from_string("fragmentation.bytes"),
+        RespFrame::Integer(rss as i64 - total as i64),
+    ]);
     
-    Ok(total_size)
+    Ok(RespFrame::Array(Some(reply)))
 }
 
-/// Handle MEMORY STATS command to report server memory usage statistics
-pub fn handle_memory_stats(storage: &Arc<StorageEngine>) -> Result<RespFrame> {
-    let mut stats_data = Vec::new();
-    
-    // Get total memory usage
-    stats_data.push(("total.allocated".to_string(), format!("{}", storage.memory_usage())));
+/// Figures MEMORY DOCTOR bases its advice on
+struct MemoryFacts {
+    /// Memory accounted to keys and values
+    used: usize,
     
-    // Memory fragmentation ratio (simplified as we don't track RSS)
-    stats_data.push(("mem_fragmentation_ratio".to_string(), "1.0".to_string()));
+    /// Highest `used` seen
+    peak: usize,
     
-    // Memory allocator stats (simplified)
-    stats_data.push(("allocator".to_string(), "rust-memory".to_string()));
+    /// Resident set size of the process
+    rss: usize,
     
-    // Per-database memory usage (if we had more detailed tracking)
-    let db_count = storage.database_count();
-    for db in 0..db_count {
-        if let Ok(keys) = storage.get_all_keys(db) {
-            let key_count = keys.len();  // Store the length before moving the keys
-            let mut db_size = 0;
-            for key in &keys {  // Use a reference to avoid moving keys
-                if let Ok(usage) = calculate_key_memory_usage(storage, db, key) {
-                    db_size += usage;
-                }
-            }
-            stats_data.push((format!("db{}.overhead", db), format!("{}", db_size)));
-            stats_data.push((format!("db{}.keys", db), format!("{}", key_count)));
-        }
-    }
+    /// maxmemory, 0 if unlimited
+    max_memory: usize,
     
-    // Convert stats to RESP format
-    let mut resp_array = Vec::new();
-    for (key, value) in stats_data {
-        resp_array.push(RespFrame::from_string(key));
-        resp_array.push(RespFrame::from_string(value));
-    }
-    
-    Ok(RespFrame::Array(Some(resp_array)))
+    /// The eviction policy frees memory at the limit
+    evicts: bool,
 }
 
 /// Handle MEMORY DOCTOR command to report memory health assessment
-pub fn handle_memory_doctor(storage: &Arc<StorageEngine>) -> Result<RespFrame> {
-    // Perform basic memory health checks
-    let mut report = String::new();
+pub fn handle_memory_doctor(storage: &Arc<StorageEngine>, stats: &Arc<ServerStats>) -> Result<RespFrame> {
+    let used = storage.memory_usage();
+    let facts = MemoryFacts {
+        used,
+        peak: stats.peak_memory.fetch_max(used, Ordering::Relaxed).max(used),
+        rss: get_process_rss(),
+        max_memory: storage.max_memory(),
+        evicts: !matches!(storage.eviction_policy(), EvictionPolicy::NoEviction),
+    };
+    Ok(RespFrame::from_string(doctor_report(&facts)))
+}
+
+/// Advice for the issues found in `facts`, worded like Redis' report
+fn doctor_report(facts: &MemoryFacts) -> String {
+    if facts.used < DOCTOR_MIN_MEMORY {
+        return "Hi Sam, this instance is empty or is using very little memory, my issues detector can't be used in these conditions. Please, leave for your mission on Earth and fill it with some data. The new Sam and I will be back to our programming as soon as I finished rebooting.".to_string();
+    }
     
-    // Check total memory usage
-    let total_memory = storage.memory_usage();
-    report.push_str(&format!("Memory usage: {} bytes\n", total_memory));
+    let mut issues = Vec::new();
+    if facts.peak as f64 > facts.used as f64 * 1.5 {
+        issues.push(format!(
+            " * Peak memory: In the past this instance used more than 150% the memory that is currently using ({} bytes at peak, {} now). The allocator is normally not able to release memory after a peak, so you can expect to see a big fragmentation ratio, however this is actually harmless and is only due to the memory peak. If the peak was unexpected, check which commands wrote that much data.",
+            facts.peak, facts.used
+        ));
+    }
     
-    // Check for large keys
-    let mut largest_keys = Vec::new();
-    for db in 0..storage.database_count() {
-        if let Ok(keys) = storage.get_all_keys(db) {
-            for key in keys {
-                if let Ok(usage) = calculate_key_memory_usage(storage, db, &key) {
-                    largest_keys.push((db, key.clone(), usage));
-                }
-                
-                // Only track most extreme cases to avoid excessive memory use in the analysis
-                largest_keys.sort_by(|a, b| b.2.cmp(&a.2));
-                if largest_keys.len() > 10 {
-                    largest_keys.truncate(10);
-                }
-            }
-        }
+    let fragmentation = facts.rss as f64 / facts.used as f64;
+    if fragmentation > 1.4 {
+        issues.push(format!(
+            " * High fragmentation: This instance has a memory fragmentation of {:.2} (RSS of {} bytes for {} bytes of data). This is often due to the freeing of many small keys after larger ones were created, or to a workload that keeps resizing values.",
+            fragmentation, facts.rss, facts.used
+        ));
     }
     
-    // Report on largest keys if any are found
-    if !largest_keys.is_empty() {
-        report.push_str("\nLargest keys found:\n");
-        for (db, key, size) in largest_keys {
-            let key_str = String::from_utf8_lossy(&key);
-            let key_type = storage.key_type(db, &key).unwrap_or_else(|_| "unknown".to_string());
-            report.push_str(&format!("db={} key='{}' type={} size={} bytes\n", 
-                            db, key_str, key_type, size));
-        }
+    if facts.max_memory > 0 && facts.used as f64 > facts.max_memory as f64 * 0.9 {
+        let consequence = if facts.evicts {
+            "keys will keep being evicted to make room for new writes"
+        } else {
+            "with the noeviction policy, writes will be refused with OOM errors once the limit is reached"
+        };
+        issues.push(format!(
+            " * Close to maxmemory: {} of the {} bytes allowed by maxmemory are in use, so {}. Raise maxmemory or reduce the dataset.",
+            facts.used, facts.max_memory, consequence
+        ));
     }
     
-    // Add general advice
-    report.push_str("\nMemory efficiency tips:\n");
-    report.push_str("1. Use EXPIRE for temporary keys\n");
-    report.push_str("2. Use MEMORY USAGE to identify large keys\n");
-    report.push_str("3. Consider using smaller key names to save memory\n");
+    if issues.is_empty() {
+        return "Hi Sam, I can't find any memory issue in your instance. I can only account for what occurs on this base.".to_string();
+    }
     
-    Ok(RespFrame::from_string(report))
+    format!(
+        "Sam, I detected a few issues in this instance memory implants:\n\n{}\n\nI'm here to keep you safe, Sam. I want to help you.\n",
+        issues.join("\n\n")
+    )
 }
 
 /// Handle MEMORY HELP command
 pub fn handle_memory_help() -> Result<RespFrame> {
-    let help_text = r#"MEMORY USAGE <key> - Report the memory usage in bytes of a key and its value
+    let help_text = r#"MEMORY USAGE <key> [SAMPLES <count>] - Report the memory usage in bytes of a key and its value, sampling <count> elements of collections (default 5, 0 for all)
 MEMORY STATS - Report memory usage statistics
 MEMORY DOCTOR - Report about memory problems and provide recommendations
 MEMORY HELP - Show this help"#;
-    
+
     Ok(RespFrame::from_string(help_text))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn memory(storage: &Arc<StorageEngine>, args: &[&str]) -> RespFrame {
+        let parts: Vec<RespFrame> = std::iter::once("MEMORY").chain(args.iter().copied())
+            .map(RespFrame::from_string)
+            .collect();
+        handle_memory(&parts, storage, &Arc::new(ServerStats::new()), 0).unwrap()
+    }
+    
+    #[test]
+    fn test_memory_usage_samples() {
+        let storage = StorageEngine::new();
+        // Large enough for a quicklist, which is sampled rather than sized exactly
+        let mut members: Vec<Vec<u8>> = vec![b"x".repeat(10_000)];
+        members.extend((0..99).map(|i| format!("m{}", i).into_bytes()));
+        for member in members {
+            storage.rpush(0, b"list".to_vec(), vec![member]).unwrap();
+        }
+        
+        // The first, large element skews a 5-element sample
+        let RespFrame::Integer(sampled) = memory(&storage, &["USAGE", "list"]) else { panic!() };
+        let RespFrame::Integer(exact) = memory(&storage, &["USAGE", "list", "SAMPLES", "0"]) else { panic!() };
+        assert!(sampled > exact * 5, "{} vs {}", sampled, exact);
+        
+        assert!(memory(&storage, &["USAGE", "missing"]).is_null());
+        assert!(memory(&storage, &["USAGE", "list", "SAMPLES", "-1"]).is_error());
+        assert!(memory(&storage, &["USAGE", "list", "COUNT", "1"]).is_error());
+    }
+    
+    #[test]
+    fn test_doctor_report() {
+        let mut facts = MemoryFacts { used: 1024, peak: 1024, rss: 0, max_memory: 0, evicts: false };
+        assert!(doctor_report(&facts).contains("empty"));
+        
+        facts.used = 100 * 1024 * 1024;
+        facts.peak = facts.used;
+        facts.rss = facts.used;
+        assert!(doctor_report(&facts).contains("can't find any memory issue"));
+        
+        facts.peak = facts.used * 2;
+        facts.rss = facts.used * 2;
+        facts.max_memory = facts.used + 1;
+        let report = doctor_report(&facts);
+        assert!(report.contains("Peak memory"));
+        assert!(report.contains("High fragmentation"));
+        assert!(report.contains("OOM errors"));
+    }
+}
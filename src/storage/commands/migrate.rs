@@ -0,0 +1,336 @@
+//! MIGRATE command implementation
+//!
+//! Keys move to the target server as DUMP payloads restored there, the same
+//! serialized form Redis uses, so a Ferrous instance can hand keys to either a
+//! Ferrous or a Redis target. All commands for one call go out as a single
+//! pipeline, and a source key is only removed once the target acknowledged it.
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::error::Result;
+use crate::protocol::{serialize_resp_frame, RespFrame, RespParser};
+use crate::storage::{rdb, GetResult, StorageEngine};
+
+/// Timeout applied when the command asks for none
+const DEFAULT_TIMEOUT_MS: u64 = 1000;
+
+/// Parsed arguments of a MIGRATE call
+struct MigrateOptions<'a> {
+    host: String,
+    port: u16,
+    destination_db: u64,
+    timeout: Duration,
+    copy: bool,
+    replace: bool,
+    auth: Option<Vec<&'a [u8]>>,
+    keys: Vec<&'a [u8]>,
+}
+
+fn parse_options<'a>(args: &[&'a [u8]]) -> std::result::Result<MigrateOptions<'a>, RespFrame> {
+    let integer = |arg: &[u8]| {
+        String::from_utf8_lossy(arg).parse::<i64>()
+            .map_err(|_| RespFrame::error("ERR value is not an integer or out of range"))
+    };
+    
+    let port = match integer(args[2])? {
+        port if (0..=u16::MAX as i64).contains(&port) => port as u16,
+        _ => return Err(RespFrame::error("ERR Invalid port")),
+    };
+    let destination_db = match integer(args[4])? {
+        db if db >= 0 => db as u64,
+        _ => return Err(RespFrame::error("ERR value is not an integer or out of range")),
+    };
+    let timeout = match integer(args[5])? {
+        ms if ms <= 0 => DEFAULT_TIMEOUT_MS,
+        ms => ms as u64,
+    };
+    
+    let mut options = MigrateOptions {
+        host: String::from_utf8_lossy(args[1]).into_owned(),
+        port,
+        destination_db,
+        timeout: Duration::from_millis(timeout),
+        copy: false,
+        replace: false,
+        auth: None,
+        keys: vec![args[3]],
+    };
+    
+    let mut i = 6;
+    while i < args.len() {
+        match String::from_utf8_lossy(args[i]).to_uppercase().as_str() {
+            "COPY" => options.copy = true,
+            "REPLACE" => options.replace = true,
+            "AUTH" if i + 1 < args.len() => {
+                options.auth = Some(vec![args[i + 1]]);
+                i += 1;
+            }
+            "AUTH2" if i + 2 < args.len() => {
+                options.auth = Some(vec![args[i + 1], args[i + 2]]);
+                i += 2;
+            }
+            "KEYS" => {
+                if !args[3].is_empty() {
+                    return Err(RespFrame::error(
+                        "ERR When using MIGRATE KEYS option, the key argument must be set to the empty string"));
+                }
+                options.keys = args[i + 1..].to_vec();
+                break;
+            }
+            _ => return Err(RespFrame::error("ERR syntax error")),
+        }
+        i += 1;
+    }
+    
+    Ok(options)
+}
+
+/// Serialize one command into the outgoing pipeline
+fn push_command(pipeline: &mut Vec<u8>, args: &[&[u8]]) -> Result<()> {
+    let frame = RespFrame::Array(Some(args.iter().map(RespFrame::bulk_string).collect()));
+    serialize_resp_frame(&frame, pipeline)
+}
+
+/// Read the next reply of the pipeline, `None` once the target failed or timed out
+fn read_reply(stream: &mut TcpStream, parser: &mut RespParser) -> Option<RespFrame> {
+    let mut buf = [0u8; 4096];
+    loop {
+        match parser.parse() {
+            Ok(Some(frame)) => return Some(frame),
+            Ok(None) => {}
+            Err(_) => return None,
+        }
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => parser.feed(&buf[..n]),
+        }
+    }
+}
+
+fn target_error(reply: &RespFrame) -> RespFrame {
+    let message = match reply {
+        RespFrame::Error(message) => String::from_utf8_lossy(message).into_owned(),
+        other => format!("{:?}", other),
+    };
+    RespFrame::error(format!("ERR Target instance replied with error: {}", message))
+}
+
+/// Handle MIGRATE command - Move keys to another server
+///
+/// MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE]
+///     [AUTH password | AUTH2 username password] [KEYS key [key ...]]
+///
+/// Returns the reply together with the keys removed from `db`, which the
+/// caller logs and replicates as a DEL: the migration itself must not be
+/// replayed by replicas or the AOF. In cluster mode (`asking`) keys are sent
+/// with RESTORE-ASKING, which a target importing their slot accepts.
+pub fn handle_migrate(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame], asking: bool) -> Result<(RespFrame, Vec<Vec<u8>>)> {
+    if parts.len() < 6 {
+        return Ok((RespFrame::error("ERR wrong number of arguments for 'migrate' command"), Vec::new()));
+    }
+    
+    let mut args = Vec::with_capacity(parts.len());
+    for part in parts {
+        match part {
+            RespFrame::BulkString(Some(bytes)) => args.push(bytes.as_slice()),
+            _ => return Ok((RespFrame::error("ERR syntax error"), Vec::new())),
+        }
+    }
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(error) => return Ok((error, Vec::new())),
+    };
+    
+    // Keys that are gone by now are skipped, as Redis does
+    let mut payloads = Vec::with_capacity(options.keys.len());
+    for &key in &options.keys {
+        if let GetResult::Found(value) = storage.get(db, key)? {
+            let ttl = storage.pttl(db, key)?.max(0);
+            payloads.push((key, ttl.to_string(), rdb::dump_payload(&value)));
+        }
+    }
+    if payloads.is_empty() {
+        return Ok((RespFrame::simple_string("NOKEY"), Vec::new()));
+    }
+    
+    let connected = (options.host.as_str(), options.port).to_socket_addrs().ok()
+        .and_then(|mut addrs| addrs.next())
+        .and_then(|addr| TcpStream::connect_timeout(&addr, options.timeout).ok());
+    let Some(mut stream) = connected else {
+        return Ok((RespFrame::error("IOERR error or timeout connecting to the client"), Vec::new()));
+    };
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(options.timeout))?;
+    stream.set_write_timeout(Some(options.timeout))?;
+    
+    let mut pipeline = Vec::new();
+    if let Some(auth) = &options.auth {
+        let mut command: Vec<&[u8]> = vec![b"AUTH"];
+        command.extend(auth);
+        push_command(&mut pipeline, &command)?;
+    }
+    let destination_db = options.destination_db.to_string();
+    push_command(&mut pipeline, &[b"SELECT", destination_db.as_bytes()])?;
+    for (key, ttl, payload) in &payloads {
+        let restore: &[u8] = if asking { b"RESTORE-ASKING" } else { b"RESTORE" };
+        let mut command: Vec<&[u8]> = vec![restore, key, ttl.as_bytes(), payload];
+        if options.replace {
+            command.push(b"REPLACE");
+        }
+        push_command(&mut pipeline, &command)?;
+    }
+    if stream.write_all(&pipeline).is_err() {
+        return Ok((RespFrame::error("IOERR error or timeout writing to target instance"), Vec::new()));
+    }
+    
+    // AUTH and SELECT must both succeed before any key is considered moved
+    let mut parser = RespParser::new();
+    let setup_replies = if options.auth.is_some() { 2 } else { 1 };
+    for _ in 0..setup_replies {
+        match read_reply(&mut stream, &mut parser) {
+            Some(reply) if reply.is_error() => return Ok((target_error(&reply), Vec::new())),
+            Some(_) => {}
+            None => return Ok((RespFrame::error("IOERR error or timeout reading to target instance"), Vec::new())),
+        }
+    }
+    
+    // Each acknowledged key leaves the source even when a later one fails
+    let mut moved = Vec::new();
+    let mut error = None;
+    for (key, _, _) in &payloads {
+        match read_reply(&mut stream, &mut parser) {
+            Some(reply) if reply.is_error() => {
+                error.get_or_insert_with(|| target_error(&reply));
+            }
+            Some(_) => {
+                if !options.copy && storage.delete(db, key)? {
+                    moved.push(key.to_vec());
+                }
+            }
+            None => {
+                error = Some(RespFrame::error("IOERR error or timeout reading to target instance"));
+                break;
+            }
+        }
+    }
+    
+    Ok((error.unwrap_or_else(RespFrame::ok), moved))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use crate::storage::commands::dump::handle_restore;
+    
+    fn command(args: &[&[u8]]) -> Vec<RespFrame> {
+        args.iter().map(|arg| RespFrame::bulk_string(arg.to_vec())).collect()
+    }
+    
+    /// Serve AUTH, SELECT and RESTORE against `storage` for one connection
+    fn spawn_target(storage: Arc<StorageEngine>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut parser = RespParser::new();
+            let mut db = 0;
+            while let Some(RespFrame::Array(Some(parts))) = read_reply(&mut stream, &mut parser) {
+                let name = parts[0].as_bulk_string_lossy().unwrap().to_uppercase();
+                let reply = match name.as_str() {
+                    "AUTH" if parts[1].as_bulk_string_lossy().as_deref() == Some("secret") => RespFrame::ok(),
+                    "AUTH" => RespFrame::error("WRONGPASS invalid username-password pair"),
+                    "SELECT" => {
+                        db = parts[1].as_bulk_string_lossy().unwrap().parse().unwrap();
+                        RespFrame::ok()
+                    }
+                    _ => handle_restore(&storage, db, &parts).unwrap(),
+                };
+                let mut buffer = Vec::new();
+                serialize_resp_frame(&reply, &mut buffer).unwrap();
+                stream.write_all(&buffer).unwrap();
+            }
+        });
+        port
+    }
+    
+    #[test]
+    fn test_migrate_moves_keys() {
+        let source = StorageEngine::new();
+        let target = StorageEngine::new();
+        source.set_string(0, b"a".to_vec(), b"1".to_vec()).unwrap();
+        source.rpush(0, b"b".to_vec(), vec![b"x".to_vec()]).unwrap();
+        source.expire(0, b"b", Duration::from_secs(100)).unwrap();
+        
+        let port = spawn_target(target.clone()).to_string();
+        let (reply, moved) = handle_migrate(&source, 0, &command(&[
+            b"MIGRATE", b"127.0.0.1", port.as_bytes(), b"", b"2", b"1000", b"KEYS", b"a", b"b", b"missing",
+        ]), false).unwrap();
+        
+        assert_eq!(reply, RespFrame::ok());
+        assert_eq!(moved, vec![b"a".to_vec(), b"b".to_vec()]);
+        assert!(!source.exists(0, b"a").unwrap() && !source.exists(0, b"b").unwrap());
+        assert_eq!(target.get_string(2, b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(target.lrange(2, b"b", 0, -1).unwrap(), vec![b"x".to_vec()]);
+        assert!(target.pttl(2, b"b").unwrap() > 90_000);
+    }
+    
+    #[test]
+    fn test_migrate_copy_and_busy_keys() {
+        let source = StorageEngine::new();
+        let target = StorageEngine::new();
+        source.set_string(0, b"a".to_vec(), b"new".to_vec()).unwrap();
+        source.set_string(0, b"b".to_vec(), b"new".to_vec()).unwrap();
+        target.set_string(0, b"a".to_vec(), b"old".to_vec()).unwrap();
+        
+        // The key already on the target fails and stays; the other one is copied
+        let port = spawn_target(target.clone()).to_string();
+        let (reply, moved) = handle_migrate(&source, 0, &command(&[
+            b"MIGRATE", b"127.0.0.1", port.as_bytes(), b"", b"0", b"1000", b"COPY", b"AUTH", b"secret", b"KEYS", b"a", b"b",
+        ]), false).unwrap();
+        
+        assert_eq!(reply, RespFrame::error("ERR Target instance replied with error: BUSYKEY Target key name already exists."));
+        assert!(moved.is_empty());
+        assert!(source.exists(0, b"a").unwrap() && source.exists(0, b"b").unwrap());
+        assert_eq!(target.get_string(0, b"a").unwrap(), Some(b"old".to_vec()));
+        assert_eq!(target.get_string(0, b"b").unwrap(), Some(b"new".to_vec()));
+        
+        // REPLACE overwrites it
+        let port = spawn_target(target.clone()).to_string();
+        let (reply, moved) = handle_migrate(&source, 0, &command(&[
+            b"MIGRATE", b"127.0.0.1", port.as_bytes(), b"a", b"0", b"1000", b"REPLACE", b"AUTH", b"secret",
+        ]), false).unwrap();
+        assert_eq!(reply, RespFrame::ok());
+        assert_eq!(moved, vec![b"a".to_vec()]);
+        assert_eq!(target.get_string(0, b"a").unwrap(), Some(b"new".to_vec()));
+    }
+    
+    #[test]
+    fn test_migrate_errors() {
+        let source = StorageEngine::new();
+        source.set_string(0, b"a".to_vec(), b"1".to_vec()).unwrap();
+        let migrate = |args: &[&[u8]]| handle_migrate(&source, 0, &command(args), false).unwrap().0;
+        
+        assert_eq!(migrate(&[b"MIGRATE", b"127.0.0.1", b"1", b"missing", b"0", b"100"]), RespFrame::simple_string("NOKEY"));
+        assert_eq!(migrate(&[b"MIGRATE", b"127.0.0.1", b"1", b"a", b"0", b"100", b"KEYS", b"a"]),
+                   RespFrame::error("ERR When using MIGRATE KEYS option, the key argument must be set to the empty string"));
+        assert_eq!(migrate(&[b"MIGRATE", b"127.0.0.1", b"1", b"a", b"0", b"100", b"NOPE"]), RespFrame::error("ERR syntax error"));
+        assert_eq!(migrate(&[b"MIGRATE", b"127.0.0.1", b"1", b"a", b"x", b"100"]),
+                   RespFrame::error("ERR value is not an integer or out of range"));
+        
+        // A rejected AUTH leaves every key in place
+        let port = spawn_target(StorageEngine::new()).to_string();
+        assert_eq!(migrate(&[b"MIGRATE", b"127.0.0.1", port.as_bytes(), b"a", b"0", b"1000", b"AUTH", b"wrong"]),
+                   RespFrame::error("ERR Target instance replied with error: WRONGPASS invalid username-password pair"));
+        assert!(source.exists(0, b"a").unwrap());
+        
+        // Nothing listens on a port just released
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port().to_string();
+        assert_eq!(migrate(&[b"MIGRATE", b"127.0.0.1", port.as_bytes(), b"a", b"0", b"100"]),
+                   RespFrame::error("IOERR error or timeout connecting to the client"));
+        assert!(source.exists(0, b"a").unwrap());
+    }
+}
@@ -4,22 +4,31 @@
 
 pub mod lists;
 pub mod sets;
+pub mod sorted_sets;
 pub mod hashes;
 pub mod strings;
+pub mod bitmaps;
+pub mod hyperloglog;
+pub mod geo;
 pub mod transactions;
 pub mod aof;
 pub mod monitor;
 pub mod config;
 pub mod scan;
+pub mod sort;
 pub mod slowlog;
 pub mod debug;
 pub mod monitor_cmd;
 pub mod client;
 pub mod memory;
+pub mod object;
+pub mod dump;
+pub mod migrate;
 pub mod lua;          // MLua-based Lua 5.1 scripting
 pub mod streams;
 pub mod consumer_groups;
 pub mod executor;
+pub mod flags;
 
 // Re-export all handlers for easy access
        // Export new MLua-based Lua commands
@@ -2,159 +2,280 @@
 //! 
 //! Provides Redis-compatible monitoring commands including INFO, MONITOR, and SLOWLOG.
 
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use std::sync::atomic::Ordering;
-use std::time::SystemTime;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::process;
 use std::fmt::Write;
+use rand::Rng;
+use crate::config::Config;
 use crate::error::Result;
 use crate::protocol::RespFrame;
-use crate::storage::StorageEngine;
+use crate::pubsub::PubSubManager;
+use crate::storage::{lua_quota, lua_vm, RdbEngine, StorageEngine, StorageMonitor};
+use crate::storage::aof::AofEngine;
 use crate::network::server::ServerStats;
 use crate::replication::ReplicationManager;
+use crate::cluster::ClusterState;
 
-/// Handle INFO command
+/// Redis version Ferrous is compatible with, reported by INFO and to scripts
+pub const REDIS_VERSION: &str = "7.0.0";
+
+/// `REDIS_VERSION` as `0x00MMmmpp`, the form of `redis.REDIS_VERSION_NUM`
+pub const REDIS_VERSION_NUM: i64 = 0x00_07_00_00;
+
+/// Sections INFO prints without arguments, or with `default`
+const DEFAULT_SECTIONS: [&str; 9] = [
+    "server", "clients", "memory", "persistence", "stats", "replication", "cpu", "cluster", "keyspace",
+];
+
+/// Sections printed only when asked for, or with `all` and `everything`
+const EXTRA_SECTIONS: [&str; 2] = ["commandstats", "latencystats"];
+
+/// Percentiles reported by INFO latencystats
+const LATENCY_PERCENTILES: [f64; 3] = [50.0, 99.0, 99.9];
+
+/// Client counts reported in the Clients section
+#[derive(Debug, Default, Clone)]
+pub struct ClientCounts {
+    /// Open client connections
+    pub connected: usize,
+    
+    /// Clients waiting in a blocking command
+    pub blocked: usize,
+    
+    /// Clients with CLIENT TRACKING on
+    pub tracking: usize,
+    
+    /// Clients subscribed to channels or patterns
+    pub pubsub: usize,
+    
+    /// Clients watching keys for a transaction
+    pub watching: usize,
+    
+    /// The maxclients limit
+    pub max_clients: usize,
+    
+    /// ACL users with script quotas, with their quotas and usage
+    pub script_users: Vec<(String, lua_quota::Quota, lua_quota::Usage)>,
+}
+
+/// Subsystems reported by INFO
+pub struct Subsystems<'a> {
+    /// Replication manager
+    pub replication: &'a Arc<ReplicationManager>,
+    
+    /// Cluster slot map
+    pub cluster: &'a ClusterState,
+    
+    /// RDB engine, if snapshots are configured
+    pub rdb: Option<&'a RdbEngine>,
+    
+    /// AOF engine, if appendonly is enabled
+    pub aof: Option<&'a AofEngine>,
+    
+    /// Auto-save monitor, counting changes since the last save
+    pub storage_monitor: Option<&'a StorageMonitor>,
+    
+    /// Pub/sub channels and patterns
+    pub pubsub: &'a PubSubManager,
+    
+    /// Live configuration, as changed by CONFIG SET
+    pub config: &'a Config,
+}
+
+/// Handle INFO [section ...]
 pub fn handle_info(
     storage: &Arc<StorageEngine>, 
     stats: &Arc<ServerStats>,
     start_time: SystemTime,
-    connected_clients: usize,
-    max_clients: usize,
-    replication: &Arc<ReplicationManager>,
+    clients: ClientCounts,
+    subsystems: Subsystems<'_>,
     parts: &[RespFrame]
 ) -> Result<RespFrame> {
-    // Parse section filter if provided
-    let section = if parts.len() > 1 {
-        match &parts[1] {
-            RespFrame::BulkString(Some(bytes)) => {
-                Some(String::from_utf8_lossy(bytes).to_lowercase())
-            }
-            _ => None,
-        }
-    } else {
-        None
+    let sections = match requested_sections(parts) {
+        Some(sections) => sections,
+        None => return Ok(RespFrame::error("ERR syntax error")),
     };
+    let wanted = |section: &str| sections.iter().any(|s| s == section);
     
     let mut info_output = String::new();
-    let show_all = section.is_none();
     
-    // Server section
-    if show_all || section.as_deref() == Some("server") {
-        append_server_info(&mut info_output, start_time);
+    if wanted("server") {
+        append_server_info(&mut info_output, start_time, &subsystems);
     }
-    
-    // Clients section
-    if show_all || section.as_deref() == Some("clients") {
-        append_clients_info(&mut info_output, connected_clients, max_clients, stats);
+    if wanted("clients") {
+        append_clients_info(&mut info_output, &clients);
     }
-    
-    // Memory section
-    if show_all || section.as_deref() == Some("memory") {
-        append_memory_info(&mut info_output, storage, stats);
+    if wanted("memory") {
+        append_memory_info(&mut info_output, storage, stats, subsystems.config);
     }
-    
-    // Stats section
-    if show_all || section.as_deref() == Some("stats") {
-        append_stats_info(&mut info_output, stats, start_time);
+    if wanted("persistence") {
+        append_persistence_info(&mut info_output, &subsystems);
     }
-    
-    // Replication section
-    if show_all || section.as_deref() == Some("replication") {
-        append_replication_info(&mut info_output, replication);
+    if wanted("stats") {
+        append_stats_info(&mut info_output, storage, stats, &subsystems);
     }
-    
-    // CPU section
-    if show_all || section.as_deref() == Some("cpu") {
+    if wanted("replication") {
+        append_replication_info(&mut info_output, subsystems.replication);
+    }
+    if wanted("cpu") {
         append_cpu_info(&mut info_output);
     }
-    
-    // Keyspace section
-    if show_all || section.as_deref() == Some("keyspace") {
+    if wanted("commandstats") {
+        append_commandstats_info(&mut info_output, stats);
+    }
+    if wanted("latencystats") {
+        append_latencystats_info(&mut info_output, stats);
+    }
+    if wanted("cluster") {
+        append_cluster_info(&mut info_output, subsystems.cluster);
+    }
+    if wanted("keyspace") {
         append_keyspace_info(&mut info_output, storage);
     }
     
     Ok(RespFrame::from_string(info_output))
 }
 
-fn append_server_info(output: &mut String, start_time: SystemTime) {
+/// Sections named by the INFO arguments, expanding `default`, `all` and
+/// `everything`; `None` if an argument is not a string
+fn requested_sections(parts: &[RespFrame]) -> Option<Vec<String>> {
+    if parts.len() < 2 {
+        return Some(DEFAULT_SECTIONS.iter().map(|s| s.to_string()).collect());
+    }
+    
+    let mut sections = Vec::new();
+    for part in &parts[1..] {
+        let RespFrame::BulkString(Some(bytes)) = part else {
+            return None;
+        };
+        match String::from_utf8_lossy(bytes).to_lowercase().as_str() {
+            "default" => sections.extend(DEFAULT_SECTIONS.iter().map(|s| s.to_string())),
+            "all" | "everything" => sections.extend(
+                DEFAULT_SECTIONS.iter().chain(EXTRA_SECTIONS.iter()).map(|s| s.to_string())
+            ),
+            section => sections.push(section.to_string()),
+        }
+    }
+    Some(sections)
+}
+
+fn append_server_info(output: &mut String, start_time: SystemTime, subsystems: &Subsystems<'_>) {
     writeln!(output, "# Server").unwrap();
-    writeln!(output, "redis_version:7.0.0-ferrous").unwrap();
+    writeln!(output, "redis_version:{}", REDIS_VERSION).unwrap();
     writeln!(output, "ferrous_version:{}", env!("CARGO_PKG_VERSION")).unwrap();
-    writeln!(output, "redis_mode:standalone").unwrap();
+    writeln!(output, "redis_mode:{}", if subsystems.cluster.is_enabled() { "cluster" } else { "standalone" }).unwrap();
+    writeln!(output, "os:{} {}", std::env::consts::OS, std::env::consts::ARCH).unwrap();
+    writeln!(output, "arch_bits:{}", usize::BITS).unwrap();
     writeln!(output, "process_id:{}", process::id()).unwrap();
+    writeln!(output, "run_id:{}", run_id()).unwrap();
+    writeln!(output, "tcp_port:{}", subsystems.config.network.port).unwrap();
+    
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    writeln!(output, "server_time_usec:{}", now.as_micros()).unwrap();
     
     // Calculate uptime
     let uptime = start_time.elapsed().unwrap_or_default().as_secs();
     writeln!(output, "uptime_in_seconds:{}", uptime).unwrap();
     writeln!(output, "uptime_in_days:{}", uptime / 86400).unwrap();
     writeln!(output, "rust_version:{}", rustc_version()).unwrap();
-    writeln!(output, "").unwrap();
+    
+    let executable = std::env::current_exe().map(|path| path.display().to_string()).unwrap_or_default();
+    writeln!(output, "executable:{}", executable).unwrap();
+    let config_file = subsystems.config.file.as_ref().map(|path| path.display().to_string()).unwrap_or_default();
+    writeln!(output, "config_file:{}", config_file).unwrap();
+    writeln!(output).unwrap();
 }
 
-fn append_clients_info(output: &mut String, connected_clients: usize, max_clients: usize, stats: &Arc<ServerStats>) {
+fn append_clients_info(output: &mut String, clients: &ClientCounts) {
     writeln!(output, "# Clients").unwrap();
-    writeln!(output, "connected_clients:{}", connected_clients).unwrap();
-    writeln!(output, "blocked_clients:{}", stats.blocked_clients.load(Ordering::Relaxed)).unwrap();
-    writeln!(output, "maxclients:{}", max_clients).unwrap();
-    writeln!(output, "").unwrap();
+    writeln!(output, "connected_clients:{}", clients.connected).unwrap();
+    writeln!(output, "maxclients:{}", clients.max_clients).unwrap();
+    writeln!(output, "blocked_clients:{}", clients.blocked).unwrap();
+    writeln!(output, "tracking_clients:{}", clients.tracking).unwrap();
+    writeln!(output, "pubsub_clients:{}", clients.pubsub).unwrap();
+    writeln!(output, "watching_clients:{}", clients.watching).unwrap();
+    for (user, quota, usage) in &clients.script_users {
+        writeln!(output, "script_user_{}:running={},scripts={},rejected={},cpu_ms={},max_concurrent={},cpu_ms_per_minute={},max_memory={}",
+                 user, usage.running, usage.scripts, usage.rejected, usage.cpu_ms,
+                 quota.max_concurrent, quota.cpu_ms_per_minute, quota.max_memory).unwrap();
+    }
+    writeln!(output).unwrap();
 }
 
-fn append_memory_info(output: &mut String, storage: &Arc<StorageEngine>, stats: &Arc<ServerStats>) {
+fn append_memory_info(output: &mut String, storage: &Arc<StorageEngine>, stats: &Arc<ServerStats>, config: &Config) {
     writeln!(output, "# Memory").unwrap();
     
     // Get memory stats from storage engine
     let used_memory = storage.memory_usage();
-    let peak_memory = stats.peak_memory.load(Ordering::Relaxed);
-    
-    // Update peak memory if current is higher
-    if used_memory > peak_memory {
-        stats.peak_memory.store(used_memory, Ordering::Relaxed);
-    }
+    let peak_memory = stats.peak_memory.fetch_max(used_memory, Ordering::Relaxed).max(used_memory);
+    let rss = get_process_rss();
     
     // Memory usage metrics
     writeln!(output, "used_memory:{}", used_memory).unwrap();
     writeln!(output, "used_memory_human:{}", format_bytes(used_memory)).unwrap();
-    writeln!(output, "used_memory_rss:{}", get_process_rss()).unwrap();
-    writeln!(output, "used_memory_rss_human:{}", format_bytes(get_process_rss())).unwrap();
+    writeln!(output, "used_memory_rss:{}", rss).unwrap();
+    writeln!(output, "used_memory_rss_human:{}", format_bytes(rss)).unwrap();
     writeln!(output, "used_memory_peak:{}", peak_memory).unwrap();
     writeln!(output, "used_memory_peak_human:{}", format_bytes(peak_memory)).unwrap();
     
+    let total_system_memory = get_total_system_memory();
+    writeln!(output, "total_system_memory:{}", total_system_memory).unwrap();
+    writeln!(output, "total_system_memory_human:{}", format_bytes(total_system_memory)).unwrap();
+    
+    // Calculate memory usage percentage
+    let used_percent = if total_system_memory > 0 {
+        (used_memory as f64 / total_system_memory as f64) * 100.0
+    } else {
+        0.0
+    };
+    writeln!(output, "used_memory_percentage:{:.2}", used_percent).unwrap();
+    
+    // Limit and policy, as changed by CONFIG SET
+    let max_memory = storage.max_memory();
+    writeln!(output, "maxmemory:{}", max_memory).unwrap();
+    writeln!(output, "maxmemory_human:{}", format_bytes(max_memory)).unwrap();
+    writeln!(output, "maxmemory_policy:{}", config.get("maxmemory-policy").unwrap_or_default()).unwrap();
+    
     // Calculate memory fragmentation ratio
-    let rss = get_process_rss();
-    let fragmentation_ratio = if used_memory == 0 {
+    let fragmentation_ratio = if used_memory == 0 || rss == 0 {
         1.0
     } else {
         rss as f64 / used_memory as f64
     };
     writeln!(output, "mem_fragmentation_ratio:{:.2}", fragmentation_ratio).unwrap();
+    writeln!(output, "mem_allocator:libc").unwrap();
+    writeln!(output).unwrap();
+}
+
+fn append_persistence_info(output: &mut String, subsystems: &Subsystems<'_>) {
+    let status = |ok: bool| if ok { "ok" } else { "err" };
+    writeln!(output, "# Persistence").unwrap();
+    writeln!(output, "loading:0").unwrap();
     
-    // Memory allocator information
-    writeln!(output, "mem_allocator:jemalloc").unwrap();
-    
-    // Memory allocation stats
-    let (allocated, active, resident) = get_memory_stats();
-    writeln!(output, "allocator_allocated:{}", allocated).unwrap();
-    writeln!(output, "allocator_active:{}", active).unwrap();
-    writeln!(output, "allocator_resident:{}", resident).unwrap();
-    
-    // Add other memory statistics
-    if let Ok(total_system_memory) = get_total_system_memory() {
-        writeln!(output, "total_system_memory:{}", total_system_memory).unwrap();
-        writeln!(output, "total_system_memory_human:{}", format_bytes(total_system_memory)).unwrap();
-        
-        // Calculate memory usage percentage
-        let used_percent = if total_system_memory > 0 {
-            (used_memory as f64 / total_system_memory as f64) * 100.0
-        } else {
-            0.0
-        };
-        writeln!(output, "used_memory_percentage:{:.2}", used_percent).unwrap();
+    let changes = subsystems.storage_monitor.map_or(0, |monitor| monitor.changes());
+    writeln!(output, "rdb_changes_since_last_save:{}", changes).unwrap();
+    if let Some(rdb) = subsystems.rdb {
+        let last_save = rdb.last_save_time()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |time| time.as_secs());
+        writeln!(output, "rdb_bgsave_in_progress:{}", rdb.is_bgsave_in_progress() as u8).unwrap();
+        writeln!(output, "rdb_last_save_time:{}", last_save).unwrap();
+        writeln!(output, "rdb_last_bgsave_status:{}", status(rdb.last_save_ok())).unwrap();
     }
     
-    writeln!(output, "").unwrap();
+    writeln!(output, "aof_enabled:{}", subsystems.aof.is_some() as u8).unwrap();
+    if let Some(aof) = subsystems.aof {
+        writeln!(output, "aof_rewrite_in_progress:{}", aof.is_rewrite_in_progress() as u8).unwrap();
+        writeln!(output, "aof_last_bgrewrite_status:{}", status(aof.last_rewrite_ok())).unwrap();
+        writeln!(output, "aof_last_write_status:{}", status(aof.last_write_ok())).unwrap();
+        writeln!(output, "aof_current_size:{}", aof.current_size()).unwrap();
+    }
+    writeln!(output).unwrap();
 }
 
-fn append_stats_info(output: &mut String, stats: &Arc<ServerStats>, start_time: SystemTime) {
+fn append_stats_info(output: &mut String, storage: &Arc<StorageEngine>, stats: &Arc<ServerStats>, subsystems: &Subsystems<'_>) {
     writeln!(output, "# Stats").unwrap();
     
     writeln!(
@@ -168,14 +289,19 @@ fn append_stats_info(output: &mut String, stats: &Arc<ServerStats>, start_time:
         stats.total_commands_processed.load(Ordering::Relaxed)
     ).unwrap();
     
-    // Calculate operations per second
-    let uptime_secs = start_time.elapsed().unwrap_or_default().as_secs();
-    let ops_per_sec = if uptime_secs > 0 {
-        stats.total_commands_processed.load(Ordering::Relaxed) / uptime_secs
-    } else {
-        0
-    };
-    writeln!(output, "instantaneous_ops_per_sec:{}", ops_per_sec).unwrap();
+    // Rates over the last couple of seconds, sampled by the event loop
+    let (ops_per_sec, input_per_sec, output_per_sec) = stats.instantaneous_rates();
+    writeln!(output, "instantaneous_ops_per_sec:{}", ops_per_sec.round() as u64).unwrap();
+    
+    let (net_input, net_output) = crate::network::connection::net_io_bytes();
+    writeln!(output, "total_net_input_bytes:{}", net_input).unwrap();
+    writeln!(output, "total_net_output_bytes:{}", net_output).unwrap();
+    writeln!(output, "instantaneous_input_kbps:{:.2}", input_per_sec / 1024.0).unwrap();
+    writeln!(output, "instantaneous_output_kbps:{:.2}", output_per_sec / 1024.0).unwrap();
+    
+    writeln!(output, "rejected_connections:{}", stats.rejected_connections.load(Ordering::Relaxed)).unwrap();
+    writeln!(output, "expired_keys:{}", storage.expired_keys()).unwrap();
+    writeln!(output, "evicted_keys:{}", storage.evicted_keys()).unwrap();
     
     writeln!(
         output,
@@ -199,6 +325,10 @@ fn append_stats_info(output: &mut String, stats: &Arc<ServerStats>, start_time:
     };
     writeln!(output, "keyspace_hit_rate:{:.2}", hit_rate).unwrap();
     
+    writeln!(output, "pubsub_channels:{}", subsystems.pubsub.channel_count()).unwrap();
+    writeln!(output, "pubsub_patterns:{}", subsystems.pubsub.pattern_count()).unwrap();
+    writeln!(output, "total_error_replies:{}", stats.total_error_replies.load(Ordering::Relaxed)).unwrap();
+    
     // Authentication statistics
     writeln!(
         output,
@@ -211,29 +341,68 @@ fn append_stats_info(output: &mut String, stats: &Arc<ServerStats>, start_time:
         stats.auth_failures.load(Ordering::Relaxed)
     ).unwrap();
     
-    writeln!(output, "").unwrap();
+    // Script effects, to spot scripts flooding the replication stream
+    writeln!(output, "total_script_effects:{}", stats.script_effects.load(Ordering::Relaxed)).unwrap();
+    writeln!(output, "script_effects_peak:{}", stats.script_effects_peak.load(Ordering::Relaxed)).unwrap();
+    
+    // Script VMs that failed to load the standard library
+    writeln!(output, "lua_vm_init_failures:{}", lua_vm::init_failures()).unwrap();
+    
+    writeln!(output).unwrap();
 }
 
 fn append_replication_info(output: &mut String, replication: &Arc<ReplicationManager>) {
     writeln!(output, "# Replication").unwrap();
-    let repl_info = replication.get_info();
+    
+    // Role first, like Redis, then the rest in a stable order
+    let mut repl_info: Vec<_> = replication.get_info().into_iter().collect();
+    repl_info.sort_by(|(a, _), (b, _)| (a != "role", a).cmp(&(b != "role", b)));
     for (key, value) in repl_info {
         writeln!(output, "{}:{}", key, value).unwrap();
     }
-    writeln!(output, "").unwrap();
+    writeln!(output).unwrap();
+}
+
+fn append_cluster_info(output: &mut String, cluster: &ClusterState) {
+    writeln!(output, "# Cluster").unwrap();
+    writeln!(output, "cluster_enabled:{}", cluster.is_enabled() as u8).unwrap();
+    writeln!(output).unwrap();
 }
 
 fn append_cpu_info(output: &mut String) {
     writeln!(output, "# CPU").unwrap();
     
-    // Note: Detailed CPU usage would require platform-specific code
-    // For simplicity, we just report placeholder values
-    writeln!(output, "used_cpu_sys:0.00").unwrap();
-    writeln!(output, "used_cpu_user:0.00").unwrap();
-    writeln!(output, "used_cpu_sys_children:0.00").unwrap();
-    writeln!(output, "used_cpu_user_children:0.00").unwrap();
+    let (sys, user) = cpu_times(false);
+    let (sys_children, user_children) = cpu_times(true);
+    writeln!(output, "used_cpu_sys:{:.6}", sys).unwrap();
+    writeln!(output, "used_cpu_user:{:.6}", user).unwrap();
+    writeln!(output, "used_cpu_sys_children:{:.6}", sys_children).unwrap();
+    writeln!(output, "used_cpu_user_children:{:.6}", user_children).unwrap();
     
-    writeln!(output, "").unwrap();
+    writeln!(output).unwrap();
+}
+
+fn append_commandstats_info(output: &mut String, stats: &Arc<ServerStats>) {
+    writeln!(output, "# Commandstats").unwrap();
+    for (name, stat) in stats.commands.snapshot() {
+        writeln!(
+            output,
+            "cmdstat_{}:calls={},usec={},usec_per_call={:.2},rejected_calls=0,failed_calls={}",
+            name, stat.calls, stat.usec, stat.usec as f64 / stat.calls as f64, stat.failed_calls
+        ).unwrap();
+    }
+    writeln!(output).unwrap();
+}
+
+fn append_latencystats_info(output: &mut String, stats: &Arc<ServerStats>) {
+    writeln!(output, "# Latencystats").unwrap();
+    for (name, stat) in stats.commands.snapshot() {
+        let percentiles: Vec<String> = LATENCY_PERCENTILES.iter()
+            .map(|&p| format!("p{}={:.3}", p, stat.latency.percentile(p) as f64))
+            .collect();
+        writeln!(output, "latency_percentiles_usec_{}:{}", name, percentiles.join(",")).unwrap();
+    }
+    writeln!(output).unwrap();
 }
 
 fn append_keyspace_info(output: &mut String, storage: &Arc<StorageEngine>) {
@@ -241,28 +410,23 @@ fn append_keyspace_info(output: &mut String, storage: &Arc<StorageEngine>) {
     
     // Get statistics for each database
     for db in 0..storage.database_count() {
-        if let Ok(keys) = storage.get_all_keys(db) {
-            if !keys.is_empty() {
-                let total_keys = keys.len();
-                let mut expiring_keys = 0;
-                
-                // Count keys with expiration
-                for key in &keys {
-                    if let Ok(Some(_)) = storage.ttl(db, key) {
-                        expiring_keys += 1;
-                    }
-                }
-                
-                writeln!(
-                    output,
-                    "db{}:keys={},expires={}",
-                    db, total_keys, expiring_keys
-                ).unwrap();
+        if let Ok((keys, expires)) = storage.keyspace_counts(db) {
+            if keys > 0 {
+                writeln!(output, "db{}:keys={},expires={}", db, keys, expires).unwrap();
             }
         }
     }
 }
 
+/// Random identifier of this server process (run_id)
+fn run_id() -> &'static str {
+    static RUN_ID: OnceLock<String> = OnceLock::new();
+    RUN_ID.get_or_init(|| {
+        let mut rng = rand::thread_rng();
+        (0..40).map(|_| char::from_digit(rng.gen_range(0..16), 16).unwrap()).collect()
+    })
+}
+
 /// Format bytes in human-readable form
 fn format_bytes(bytes: usize) -> String {
     const UNITS: &[&str] = &["B", "K", "M", "G", "T"];
@@ -290,12 +454,16 @@ fn rustc_version() -> &'static str {
 }
 
 /// Get the resident set size (RSS) of the current process
-fn get_process_rss() -> usize {
+pub(crate) fn get_process_rss() -> usize {
     #[cfg(target_os = "linux")]
     {
-        // On Linux, we could read /proc/self/statm but for simplicity just return a placeholder
-        // A more accurate implementation would parse from /proc
-        0
+        // Second field of /proc/self/statm, in pages
+        let resident_pages = std::fs::read_to_string("/proc/self/statm").ok()
+            .and_then(|statm| statm.split_whitespace().nth(1)?.parse::<usize>().ok())
+            .unwrap_or(0);
+        // SAFETY: sysconf has no preconditions
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(0) as usize;
+        resident_pages * page_size
     }
     
     #[cfg(not(target_os = "linux"))]
@@ -305,25 +473,81 @@ fn get_process_rss() -> usize {
     }
 }
 
-/// Get memory stats from the allocator
-fn get_memory_stats() -> (usize, usize, usize) {
-    // In a real implementation, we'd get these from the memory allocator
-    // Returns (allocated, active, resident)
-    (0, 0, 0)
-}
-
 /// Get total system memory
-fn get_total_system_memory() -> Result<usize> {
+fn get_total_system_memory() -> usize {
     #[cfg(target_os = "linux")]
     {
-        // On Linux, we could read from /proc/meminfo
-        // But for simplicity just return a placeholder
-        Ok(0)
+        // "MemTotal:  16318412 kB" in /proc/meminfo
+        std::fs::read_to_string("/proc/meminfo").ok()
+            .and_then(|meminfo| {
+                let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+                line.split_whitespace().nth(1)?.parse::<usize>().ok()
+            })
+            .map_or(0, |kb| kb * 1024)
     }
     
     #[cfg(not(target_os = "linux"))]
     {
         // Default fallback for other platforms
-        Ok(0)
+        0
     }
-}
\ No newline at end of file
+}
+
+/// System and user CPU seconds used by this process, or by its waited-for
+/// children (background saves)
+fn cpu_times(children: bool) -> (f64, f64) {
+    #[cfg(unix)]
+    {
+        let who = if children { libc::RUSAGE_CHILDREN } else { libc::RUSAGE_SELF };
+        // SAFETY: getrusage only writes to the zeroed struct it is given
+        let usage = unsafe {
+            let mut usage: libc::rusage = std::mem::zeroed();
+            if libc::getrusage(who, &mut usage) != 0 {
+                return (0.0, 0.0);
+            }
+            usage
+        };
+        let seconds = |time: libc::timeval| time.tv_sec as f64 + time.tv_usec as f64 / 1_000_000.0;
+        (seconds(usage.ru_stime), seconds(usage.ru_utime))
+    }
+    
+    #[cfg(not(unix))]
+    {
+        let _ = children;
+        (0.0, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_requested_sections() {
+        let info = |args: &[&str]| {
+            let parts: Vec<RespFrame> = std::iter::once("INFO").chain(args.iter().copied())
+                .map(RespFrame::from_string)
+                .collect();
+            requested_sections(&parts).unwrap()
+        };
+        
+        assert_eq!(info(&[]).len(), DEFAULT_SECTIONS.len());
+        assert!(!info(&[]).iter().any(|s| s == "commandstats"));
+        assert_eq!(info(&["Memory", "CPU"]), vec!["memory", "cpu"]);
+        assert!(info(&["everything"]).iter().any(|s| s == "latencystats"));
+        assert_eq!(info(&["default", "commandstats"]).len(), DEFAULT_SECTIONS.len() + 1);
+    }
+    
+    #[test]
+    fn test_process_metrics() {
+        #[cfg(target_os = "linux")]
+        {
+            assert!(get_process_rss() > 0);
+            assert!(get_total_system_memory() > get_process_rss());
+        }
+        let (sys, user) = cpu_times(false);
+        assert!(sys >= 0.0 && user >= 0.0);
+        assert_eq!(run_id().len(), 40);
+        assert_eq!(run_id(), run_id());
+    }
+}
@@ -0,0 +1,114 @@
+//! OBJECT command implementation
+//! 
+//! Provides Redis-compatible introspection of the internal objects backing keys.
+
+use std::sync::Arc;
+use crate::error::Result;
+use crate::protocol::RespFrame;
+use crate::storage::StorageEngine;
+
+/// Handle OBJECT command and its subcommands
+pub fn handle_object(
+    parts: &[RespFrame],
+    storage: &Arc<StorageEngine>,
+    db: usize,
+) -> Result<RespFrame> {
+    if parts.len() < 2 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'object' command"));
+    }
+    
+    // Extract subcommand
+    let subcommand = match &parts[1] {
+        RespFrame::BulkString(Some(bytes)) => {
+            String::from_utf8_lossy(bytes).to_uppercase()
+        },
+        _ => return Ok(RespFrame::error("ERR invalid subcommand format")),
+    };
+    
+    match subcommand.as_str() {
+        "REFCOUNT" if parts.len() == 3 => handle_object_refcount(parts, storage, db),
+        "ENCODING" if parts.len() == 3 => handle_object_encoding(parts, storage, db),
+        "FREQ" if parts.len() == 3 => handle_object_freq(parts, storage, db),
+        "IDLETIME" if parts.len() == 3 => handle_object_idletime(parts, storage, db),
+        "HELP" => handle_object_help(),
+        _ => Ok(RespFrame::error(format!("ERR Unknown subcommand or wrong number of arguments for 'object {}'", subcommand)))
+    }
+}
+
+/// Handle OBJECT REFCOUNT command
+/// 
+/// Shared small integers report Redis' shared-object refcount (INT_MAX), everything else 1
+pub fn handle_object_refcount(parts: &[RespFrame], storage: &Arc<StorageEngine>, db: usize) -> Result<RespFrame> {
+    // Extract key
+    let key = match &parts[2] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_ref(),
+        _ => return Ok(RespFrame::error("ERR invalid key format")),
+    };
+    
+    match storage.object_refcount(db, key)? {
+        Some(refcount) => Ok(RespFrame::Integer(refcount)),
+        None => Ok(RespFrame::null_bulk()),
+    }
+}
+
+/// Handle OBJECT ENCODING command
+pub fn handle_object_encoding(parts: &[RespFrame], storage: &Arc<StorageEngine>, db: usize) -> Result<RespFrame> {
+    let key = match &parts[2] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_ref(),
+        _ => return Ok(RespFrame::error("ERR invalid key format")),
+    };
+    
+    match storage.object_encoding(db, key)? {
+        Some(encoding) => Ok(RespFrame::from_string(encoding)),
+        None => Ok(RespFrame::null_bulk()),
+    }
+}
+
+/// Handle OBJECT FREQ command
+/// 
+/// The access frequency is only reported under an LFU maxmemory-policy, as in Redis
+pub fn handle_object_freq(parts: &[RespFrame], storage: &Arc<StorageEngine>, db: usize) -> Result<RespFrame> {
+    let key = match &parts[2] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_ref(),
+        _ => return Ok(RespFrame::error("ERR invalid key format")),
+    };
+    
+    if !storage.eviction_policy().is_lfu() {
+        return Ok(RespFrame::error("ERR An LFU maxmemory policy is not selected, access frequency not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust."));
+    }
+    
+    match storage.access_frequency(db, key)? {
+        Some(frequency) => Ok(RespFrame::Integer(frequency as i64)),
+        None => Ok(RespFrame::null_bulk()),
+    }
+}
+
+/// Handle OBJECT IDLETIME command
+/// 
+/// Seconds since the key was last accessed; refused under an LFU maxmemory-policy, as in Redis
+pub fn handle_object_idletime(parts: &[RespFrame], storage: &Arc<StorageEngine>, db: usize) -> Result<RespFrame> {
+    let key = match &parts[2] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_ref(),
+        _ => return Ok(RespFrame::error("ERR invalid key format")),
+    };
+    
+    if storage.eviction_policy().is_lfu() {
+        return Ok(RespFrame::error("ERR An LFU maxmemory policy is selected, idle time not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust."));
+    }
+    
+    match storage.idle_time(db, key)? {
+        Some(idle) => Ok(RespFrame::Integer(idle.as_secs() as i64)),
+        None => Ok(RespFrame::null_bulk()),
+    }
+}
+
+/// Handle OBJECT HELP command
+pub fn handle_object_help() -> Result<RespFrame> {
+    let help_text = r#"OBJECT ENCODING <key> - Return the kind of internal representation used to store the value associated with the specified key
+OBJECT FREQ <key> - Return the access frequency index of the key (requires an LFU maxmemory-policy)
+OBJECT IDLETIME <key> - Return the idle time of the key, in seconds (not available under an LFU maxmemory-policy)
+OBJECT REFCOUNT <key> - Return the number of references of the value associated with the specified key
+OBJECT HELP - Show this help"#;
+    
+    Ok(RespFrame::from_string(help_text))
+}
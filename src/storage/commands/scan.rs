@@ -58,6 +58,7 @@ pub fn handle_scan(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame])
                         if i + 1 < parts.len() {
                             if let RespFrame::BulkString(Some(c)) = &parts[i + 1] {
                                 match String::from_utf8_lossy(c).parse::<usize>() {
+                                    Ok(0) => return Ok(RespFrame::error("ERR syntax error")),
                                     Ok(n) => {
                                         count = n;
                                         i += 2;
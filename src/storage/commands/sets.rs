@@ -2,12 +2,51 @@
 //! 
 //! Provides Redis-compatible set operations including add, remove, membership testing,
 //! and set operations like union, intersection, and difference.
+//! Parsing for SINTERCARD is shared with the unified executor; parse errors
+//! are messages without the `ERR ` prefix.
 
 use crate::error::{FerrousError, Result, StorageError};
 use crate::protocol::RespFrame;
 use crate::storage::StorageEngine;
 use std::sync::Arc;
 
+/// Keys and LIMIT of a SINTERCARD
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterCard {
+    /// Sets to intersect
+    pub keys: Vec<Vec<u8>>,
+    /// Count to stop at, 0 for no limit
+    pub limit: usize,
+}
+
+/// Parse the SINTERCARD arguments after the command name
+pub fn parse_sintercard(args: &[&[u8]]) -> std::result::Result<InterCard, String> {
+    let integer = |arg: &[u8]| std::str::from_utf8(arg).ok().and_then(|arg| arg.parse::<i64>().ok());
+    let numkeys = args.first()
+        .and_then(|numkeys| integer(numkeys))
+        .filter(|&numkeys| numkeys > 0)
+        .ok_or_else(|| "numkeys should be greater than 0".to_string())? as usize;
+    if numkeys > args.len() - 1 {
+        return Err("Number of keys can't be greater than number of args".to_string());
+    }
+    
+    let keys = args[1..=numkeys].iter().map(|key| key.to_vec()).collect();
+    let limit = match &args[numkeys + 1..] {
+        [] => 0,
+        [name, limit] if name.eq_ignore_ascii_case(b"LIMIT") => {
+            let limit = integer(limit).ok_or_else(|| "LIMIT can't be negative".to_string())?;
+            usize::try_from(limit).map_err(|_| "LIMIT can't be negative".to_string())?
+        }
+        _ => return Err("syntax error".to_string()),
+    };
+    Ok(InterCard { keys, limit })
+}
+
+/// SMISMEMBER reply: 1 or 0 per member
+pub fn smismember_reply(membership: Vec<bool>) -> RespFrame {
+    RespFrame::Array(Some(membership.into_iter().map(|member| RespFrame::Integer(member as i64)).collect()))
+}
+
 /// Handle SADD command - Add members to a set
 pub fn handle_sadd(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
     if parts.len() < 3 {
@@ -133,6 +172,32 @@ pub fn handle_sismember(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFr
     }
 }
 
+/// Handle SMISMEMBER command - Check membership of several members at once
+pub fn handle_smismember(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 3 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'smismember' command"));
+    }
+    
+    // Extract key and members
+    let mut args = Vec::new();
+    for part in &parts[1..] {
+        match part {
+            RespFrame::BulkString(Some(bytes)) => args.push(bytes.as_slice()),
+            _ => return Ok(RespFrame::error("ERR invalid argument format")),
+        }
+    }
+    
+    match storage.smismember(db, args[0], &args[1..]) {
+        Ok(membership) => Ok(smismember_reply(membership)),
+        Err(FerrousError::Storage(StorageError::WrongType)) => {
+            Ok(RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"))
+        },
+        Err(e) => {
+            Ok(RespFrame::error(format!("ERR {}", e)))
+        }
+    }
+}
+
 /// Handle SCARD command - Get the number of members in a set
 pub fn handle_scard(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
     if parts.len() != 2 {
@@ -221,6 +286,36 @@ pub fn handle_sinter(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame
     }
 }
 
+/// Handle SINTERCARD command - Count the intersection of multiple sets
+pub fn handle_sintercard(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() < 3 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'sintercard' command"));
+    }
+    
+    // Extract numkeys, keys and options
+    let mut args = Vec::new();
+    for part in &parts[1..] {
+        match part {
+            RespFrame::BulkString(Some(bytes)) => args.push(bytes.as_slice()),
+            _ => return Ok(RespFrame::error("ERR invalid argument format")),
+        }
+    }
+    let card = match parse_sintercard(&args) {
+        Ok(card) => card,
+        Err(message) => return Ok(RespFrame::error(format!("ERR {}", message))),
+    };
+    
+    match storage.sintercard(db, &card.keys, card.limit) {
+        Ok(count) => Ok(RespFrame::Integer(count as i64)),
+        Err(FerrousError::Storage(StorageError::WrongType)) => {
+            Ok(RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"))
+        },
+        Err(e) => {
+            Ok(RespFrame::error(format!("ERR {}", e)))
+        }
+    }
+}
+
 /// Handle SDIFF command - Get difference of multiple sets
 pub fn handle_sdiff(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
     if parts.len() < 2 {
@@ -270,6 +365,7 @@ pub fn handle_srandmember(storage: &Arc<StorageEngine>, db: usize, parts: &[Resp
         match &parts[2] {
             RespFrame::BulkString(Some(bytes)) => {
                 match String::from_utf8_lossy(bytes).parse::<i64>() {
+                    Ok(i64::MIN) => return Ok(RespFrame::error("ERR value is out of range")),
                     Ok(n) => Some(n),
                     Err(_) => return Ok(RespFrame::error("ERR value is not an integer or out of range")),
                 }
@@ -364,4 +460,26 @@ pub fn handle_spop(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame])
             Ok(RespFrame::error(format!("ERR {}", e)))
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn parse(line: &str) -> std::result::Result<InterCard, String> {
+        let args: Vec<&[u8]> = line.split_whitespace().map(str::as_bytes).collect();
+        parse_sintercard(&args)
+    }
+    
+    #[test]
+    fn test_parse_sintercard() {
+        assert_eq!(parse("2 a b"), Ok(InterCard { keys: vec![b"a".to_vec(), b"b".to_vec()], limit: 0 }));
+        assert_eq!(parse("1 a limit 5"), Ok(InterCard { keys: vec![b"a".to_vec()], limit: 5 }));
+        
+        assert_eq!(parse("0 a").unwrap_err(), "numkeys should be greater than 0");
+        assert_eq!(parse("x a").unwrap_err(), "numkeys should be greater than 0");
+        assert_eq!(parse("3 a b").unwrap_err(), "Number of keys can't be greater than number of args");
+        assert_eq!(parse("1 a LIMIT -1").unwrap_err(), "LIMIT can't be negative");
+        assert_eq!(parse("1 a LIMIT").unwrap_err(), "syntax error");
+        assert_eq!(parse("1 a b").unwrap_err(), "syntax error");
+    }
+}
@@ -36,6 +36,9 @@ pub struct SlowlogEntry {
     
     /// Client name if set
     pub client_name: Option<String>,
+    
+    /// Write commands a script propagated, for EVAL, EVALSHA and FCALL
+    pub effects: Option<u64>,
 }
 
 /// The slowlog system
@@ -71,18 +74,18 @@ impl Slowlog {
     }
     
     /// Add an entry to the slowlog if it exceeds the threshold
-    pub fn add_if_slow(&self, duration: Duration, command_parts: &[RespFrame], client_addr: &str, client_name: Option<&str>) {
+    pub fn add_if_slow(&self, duration: Duration, command_parts: &[RespFrame], client_addr: &str, client_name: Option<&str>, effects: Option<u64>) {
         let duration_micros = duration.as_micros() as u64;
         let threshold = self.threshold_micros.load(Ordering::Relaxed);
         
         // Check if command is slow enough to log
         if threshold >= 0 && duration_micros >= threshold as u64 {
-            self.add_entry(duration_micros, command_parts, client_addr, client_name);
+            self.add_entry(duration_micros, command_parts, client_addr, client_name, effects);
         }
     }
     
     /// Add an entry to the slowlog
-    fn add_entry(&self, duration_micros: u64, command_parts: &[RespFrame], client_addr: &str, client_name: Option<&str>) {
+    fn add_entry(&self, duration_micros: u64, command_parts: &[RespFrame], client_addr: &str, client_name: Option<&str>, effects: Option<u64>) {
         // Convert command parts to bytes
         let mut command = Vec::new();
         for part in command_parts {
@@ -120,6 +123,7 @@ impl Slowlog {
             command,
             client_addr: client_addr.to_string(),
             client_name: client_name.map(|s| s.to_string()),
+            effects,
         };
         
         let mut entries = self.entries.lock().unwrap();
@@ -210,6 +214,7 @@ pub fn handle_slowlog_get(slowlog: &Slowlog, parts: &[RespFrame]) -> Result<Resp
         // 4. Command array
         // 5. Client info (IP:port)
         // 6. Client name (if set)
+        // 7. Effects propagated (scripts only)
         
         let mut entry_parts = vec![
             RespFrame::Integer(entry.id as i64),
@@ -230,6 +235,11 @@ pub fn handle_slowlog_get(slowlog: &Slowlog, parts: &[RespFrame]) -> Result<Resp
         let client_name = entry.client_name.unwrap_or_default();
         entry_parts.push(RespFrame::BulkString(Some(Arc::new(client_name.into_bytes()))));
         
+        // Effects, so scripts flooding the replicas stand out
+        if let Some(effects) = entry.effects {
+            entry_parts.push(RespFrame::Integer(effects as i64));
+        }
+        
         RespFrame::Array(Some(entry_parts))
     }).collect();
     
@@ -265,4 +275,33 @@ pub fn handle_slowlog(slowlog: &Slowlog, parts: &[RespFrame]) -> Result<RespFram
         "RESET" => handle_slowlog_reset(slowlog),
         _ => Ok(RespFrame::error("ERR Unknown subcommand or wrong number of arguments for SLOWLOG")),
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn command(words: &[&str]) -> Vec<RespFrame> {
+        words.iter().map(|word| RespFrame::BulkString(Some(Arc::new(word.as_bytes().to_vec())))).collect()
+    }
+    
+    #[test]
+    fn test_script_entries_report_effects() {
+        let slowlog = Slowlog::new();
+        slowlog.set_threshold_micros(0);
+        slowlog.add_if_slow(Duration::from_millis(1), &command(&["GET", "k"]), "127.0.0.1:1", None, None);
+        slowlog.add_if_slow(Duration::from_millis(2), &command(&["EVAL", "...", "0"]), "127.0.0.1:1", None, Some(5000));
+        
+        let entries = match handle_slowlog_get(&slowlog, &command(&["SLOWLOG", "GET"])).unwrap() {
+            RespFrame::Array(Some(entries)) => entries,
+            other => panic!("unexpected reply {:?}", other),
+        };
+        let lens: Vec<_> = entries.iter()
+            .map(|entry| match entry {
+                RespFrame::Array(Some(parts)) => parts.len(),
+                _ => 0,
+            })
+            .collect();
+        assert_eq!(lens, [7, 6]);
+        assert!(matches!(&entries[0], RespFrame::Array(Some(parts)) if parts[6] == RespFrame::Integer(5000)));
+    }
+}
@@ -0,0 +1,327 @@
+//! SORT and SORT_RO command implementation
+//!
+//! Sorts the elements of a list, set or sorted set, optionally weighting them by
+//! external keys (`BY weight_*`, `BY obj_*->field`) and projecting the result
+//! through GET patterns (`GET #`, `GET data_*`, `GET obj_*->field`). With STORE
+//! the result replaces the destination key as a list.
+
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use crate::error::{CommandError, FerrousError, Result, StorageError};
+use crate::protocol::RespFrame;
+use crate::storage::{DatabaseIndex, StorageEngine};
+
+/// Parsed SORT options
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortOptions {
+    /// BY pattern used to look up weights
+    pub by: Option<Vec<u8>>,
+    
+    /// LIMIT offset and count
+    pub limit: Option<(i64, i64)>,
+    
+    /// GET patterns, in order
+    pub get: Vec<Vec<u8>>,
+    
+    /// Sort in descending order
+    pub desc: bool,
+    
+    /// Compare lexicographically instead of as numbers
+    pub alpha: bool,
+    
+    /// Destination key for STORE
+    pub store: Option<Vec<u8>>,
+}
+
+impl SortOptions {
+    /// Whether elements are left in their stored order (BY without a `*`)
+    fn dont_sort(&self) -> bool {
+        self.by.as_ref().is_some_and(|by| !by.contains(&b'*'))
+    }
+}
+
+/// Parse the arguments after the key; SORT_RO rejects STORE
+pub fn parse_options<T: AsRef<[u8]>>(args: &[T], read_only: bool) -> Result<SortOptions> {
+    let syntax_error = || FerrousError::Command(CommandError::Generic("syntax error".to_string()));
+    let mut options = SortOptions::default();
+    
+    let mut i = 0;
+    while i < args.len() {
+        let option = String::from_utf8_lossy(args[i].as_ref()).to_uppercase();
+        let remaining = args.len() - i - 1;
+        match option.as_str() {
+            "ASC" => options.desc = false,
+            "DESC" => options.desc = true,
+            "ALPHA" => options.alpha = true,
+            "LIMIT" if remaining >= 2 => {
+                let parse = |arg: &T| std::str::from_utf8(arg.as_ref()).ok()
+                    .and_then(|s| s.parse::<i64>().ok())
+                    .ok_or(FerrousError::Command(CommandError::NotInteger));
+                options.limit = Some((parse(&args[i + 1])?, parse(&args[i + 2])?));
+                i += 2;
+            }
+            "STORE" if remaining >= 1 && !read_only => {
+                options.store = Some(args[i + 1].as_ref().to_vec());
+                i += 1;
+            }
+            "BY" if remaining >= 1 => {
+                options.by = Some(args[i + 1].as_ref().to_vec());
+                i += 1;
+            }
+            "GET" if remaining >= 1 => {
+                options.get.push(args[i + 1].as_ref().to_vec());
+                i += 1;
+            }
+            _ => return Err(syntax_error()),
+        }
+        i += 1;
+    }
+    
+    Ok(options)
+}
+
+/// Result of running SORT
+#[derive(Debug, PartialEq)]
+pub enum SortResult {
+    /// Sorted (and projected) values; None for missing GET lookups
+    Values(Vec<Option<Vec<u8>>>),
+    
+    /// Number of elements written to the STORE destination
+    Stored(usize),
+}
+
+/// Look up the value a BY or GET pattern refers to for one element
+///
+/// The first `*` is replaced by the element; a trailing `->field` reads a hash
+/// field instead of a string. Missing keys and keys of the wrong type yield None.
+fn lookup(storage: &StorageEngine, db: DatabaseIndex, pattern: &[u8], element: &[u8]) -> Result<Option<Vec<u8>>> {
+    if pattern == b"#" {
+        return Ok(Some(element.to_vec()));
+    }
+    
+    let Some(star) = pattern.iter().position(|&b| b == b'*') else {
+        return Ok(None);
+    };
+    
+    // A `->` after the `*` with a non-empty field name selects a hash field
+    let arrow = pattern.windows(2).rposition(|w| w == b"->")
+        .filter(|&pos| pos > star && pos + 2 < pattern.len());
+    let (key_pattern, field) = match arrow {
+        Some(pos) => (&pattern[..pos], Some(&pattern[pos + 2..])),
+        None => (pattern, None),
+    };
+    
+    let mut key = Vec::with_capacity(key_pattern.len() + element.len());
+    key.extend_from_slice(&key_pattern[..star]);
+    key.extend_from_slice(element);
+    key.extend_from_slice(&key_pattern[star + 1..]);
+    
+    let value = match field {
+        Some(field) => storage.hget(db, &key, field),
+        None => storage.get_string(db, &key),
+    };
+    match value {
+        Err(FerrousError::Storage(StorageError::WrongType)) => Ok(None),
+        other => other,
+    }
+}
+
+/// Elements of the source key, or WRONGTYPE for unsortable types
+fn source_elements(storage: &StorageEngine, db: DatabaseIndex, key: &[u8]) -> Result<(Vec<Vec<u8>>, bool)> {
+    match storage.key_type(db, key)?.as_str() {
+        "none" => Ok((Vec::new(), false)),
+        "list" => Ok((storage.lrange(db, key, 0, -1)?, false)),
+        "set" => Ok((storage.smembers(db, key)?, true)),
+        "zset" => Ok((storage.zrange(db, key, 0, -1, false)?.into_iter().map(|(member, _)| member).collect(), false)),
+        _ => Err(FerrousError::Command(CommandError::WrongType)),
+    }
+}
+
+/// Run SORT against a key
+pub fn sort(storage: &StorageEngine, db: DatabaseIndex, key: &[u8], options: &SortOptions) -> Result<SortResult> {
+    let (elements, is_set) = source_elements(storage, db, key)?;
+    
+    // Sets have no stored order, so an unsorted set result would differ between
+    // replicas and AOF replays: order it lexicographically like Redis does.
+    let mut alpha = options.alpha;
+    let mut dont_sort = options.dont_sort();
+    if dont_sort && is_set {
+        dont_sort = false;
+        alpha = true;
+    }
+    
+    let mut items: Vec<(Vec<u8>, Option<Vec<u8>>, f64)> = Vec::with_capacity(elements.len());
+    for element in elements {
+        let weight = match (&options.by, dont_sort) {
+            (Some(by), false) => lookup(storage, db, by, &element)?,
+            _ => None,
+        };
+        
+        let score = if dont_sort || alpha {
+            0.0
+        } else {
+            match weight.as_deref().or(options.by.is_none().then_some(element.as_slice())) {
+                // Missing weights sort as zero
+                None => 0.0,
+                Some(raw) => std::str::from_utf8(raw).ok()
+                    .and_then(|s| s.trim().parse::<f64>().ok())
+                    .filter(|score| !score.is_nan())
+                    .ok_or_else(|| FerrousError::Command(CommandError::Generic(
+                        "One or more scores can't be converted into double".to_string())))?,
+            }
+        };
+        items.push((element, weight, score));
+    }
+    
+    if !dont_sort {
+        items.sort_by(|a, b| {
+            let ordering = if alpha {
+                match &options.by {
+                    // Missing weights sort before present ones
+                    Some(_) => a.1.cmp(&b.1),
+                    None => a.0.cmp(&b.0),
+                }
+            } else {
+                a.2.partial_cmp(&b.2).unwrap_or(Ordering::Equal)
+            };
+            // Ties are broken by the elements themselves so the result is deterministic
+            let ordering = ordering.then_with(|| a.0.cmp(&b.0));
+            if options.desc { ordering.reverse() } else { ordering }
+        });
+    }
+    
+    let (start, count) = match options.limit {
+        Some((offset, count)) => {
+            let start = offset.clamp(0, items.len() as i64) as usize;
+            let count = if count < 0 { items.len() } else { count as usize };
+            (start, count)
+        }
+        None => (0, items.len()),
+    };
+    
+    let mut values = Vec::new();
+    for (element, _, _) in items.into_iter().skip(start).take(count) {
+        if options.get.is_empty() {
+            values.push(Some(element));
+        } else {
+            for pattern in &options.get {
+                values.push(lookup(storage, db, pattern, &element)?);
+            }
+        }
+    }
+    
+    match &options.store {
+        Some(destination) => {
+            let stored = values.len();
+            storage.delete(db, destination)?;
+            if stored > 0 {
+                // Missing lookups are stored as empty strings
+                let elements = values.into_iter().map(|value| value.unwrap_or_default()).collect();
+                storage.rpush(db, destination.clone(), elements)?;
+            }
+            Ok(SortResult::Stored(stored))
+        }
+        None => Ok(SortResult::Values(values)),
+    }
+}
+
+impl From<SortResult> for RespFrame {
+    fn from(result: SortResult) -> Self {
+        match result {
+            SortResult::Stored(count) => RespFrame::Integer(count as i64),
+            SortResult::Values(values) => RespFrame::Array(Some(values.into_iter()
+                .map(|value| RespFrame::BulkString(value.map(Arc::new)))
+                .collect())),
+        }
+    }
+}
+
+/// Handle SORT and SORT_RO from the server
+pub fn handle_sort(storage: &Arc<StorageEngine>, db: DatabaseIndex, parts: &[RespFrame], read_only: bool) -> Result<RespFrame> {
+    let name = if read_only { "sort_ro" } else { "sort" };
+    if parts.len() < 2 {
+        return Ok(RespFrame::error(format!("ERR wrong number of arguments for '{}' command", name)));
+    }
+    
+    let mut args = Vec::with_capacity(parts.len() - 1);
+    for part in &parts[1..] {
+        match part {
+            RespFrame::BulkString(Some(bytes)) => args.push(bytes.as_slice()),
+            _ => return Ok(RespFrame::error("ERR syntax error")),
+        }
+    }
+    
+    let result = parse_options(&args[1..], read_only).and_then(|options| sort(storage, db, args[0], &options));
+    match result {
+        Ok(result) => Ok(result.into()),
+        Err(e) => Ok(RespFrame::error(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn values(result: SortResult) -> Vec<Option<Vec<u8>>> {
+        match result {
+            SortResult::Values(values) => values,
+            other => panic!("Expected values, got {:?}", other),
+        }
+    }
+    
+    fn bytes(items: &[&str]) -> Vec<Option<Vec<u8>>> {
+        items.iter().map(|s| Some(s.as_bytes().to_vec())).collect()
+    }
+    
+    #[test]
+    fn test_numeric_alpha_and_limit() {
+        let storage = StorageEngine::new_in_memory();
+        storage.rpush(0, b"l".to_vec(), vec![b"3".to_vec(), b"10".to_vec(), b"1".to_vec()]).unwrap();
+        
+        let numeric = parse_options::<&[u8]>(&[], false).unwrap();
+        assert_eq!(values(sort(&storage, 0, b"l", &numeric).unwrap()), bytes(&["1", "3", "10"]));
+        
+        let alpha = parse_options(&[b"ALPHA".as_slice(), b"DESC", b"LIMIT", b"0", b"2"], false).unwrap();
+        assert_eq!(values(sort(&storage, 0, b"l", &alpha).unwrap()), bytes(&["3", "10"]));
+        
+        storage.rpush(0, b"words".to_vec(), vec![b"b".to_vec(), b"a".to_vec()]).unwrap();
+        assert!(sort(&storage, 0, b"words", &numeric).is_err());
+    }
+    
+    #[test]
+    fn test_by_and_get_patterns() {
+        let storage = StorageEngine::new_in_memory();
+        storage.sadd(0, b"ids".to_vec(), vec![b"1".to_vec(), b"2".to_vec(), b"3".to_vec()]).unwrap();
+        storage.set_string(0, b"w_1".to_vec(), b"30".to_vec()).unwrap();
+        storage.set_string(0, b"w_2".to_vec(), b"10".to_vec()).unwrap();
+        storage.set_string(0, b"w_3".to_vec(), b"20".to_vec()).unwrap();
+        for (id, name) in [("1", "one"), ("2", "two"), ("3", "three")] {
+            storage.hset(0, format!("obj_{}", id).into_bytes(), vec![(b"name".to_vec(), name.as_bytes().to_vec())]).unwrap();
+        }
+        
+        let options = parse_options(&[b"BY".as_slice(), b"w_*", b"GET", b"#", b"GET", b"obj_*->name"], false).unwrap();
+        assert_eq!(values(sort(&storage, 0, b"ids", &options).unwrap()),
+            bytes(&["2", "two", "3", "three", "1", "one"]));
+        
+        // BY without a pattern skips sorting; sets still come back in a stable order
+        let options = parse_options(&[b"BY".as_slice(), b"nosort", b"GET", b"obj_*->missing"], false).unwrap();
+        assert_eq!(values(sort(&storage, 0, b"ids", &options).unwrap()), vec![None, None, None]);
+    }
+    
+    #[test]
+    fn test_store_and_read_only() {
+        let storage = StorageEngine::new_in_memory();
+        storage.rpush(0, b"l".to_vec(), vec![b"2".to_vec(), b"1".to_vec()]).unwrap();
+        
+        let options = parse_options(&[b"STORE".as_slice(), b"dst"], false).unwrap();
+        assert_eq!(sort(&storage, 0, b"l", &options).unwrap(), SortResult::Stored(2));
+        assert_eq!(storage.lrange(0, b"dst", 0, -1).unwrap(), vec![b"1".to_vec(), b"2".to_vec()]);
+        
+        assert!(parse_options(&[b"STORE".as_slice(), b"dst"], true).is_err());
+        
+        storage.set_string(0, b"s".to_vec(), b"v".to_vec()).unwrap();
+        assert!(matches!(sort(&storage, 0, b"s", &SortOptions::default()),
+            Err(FerrousError::Command(CommandError::WrongType))));
+    }
+}
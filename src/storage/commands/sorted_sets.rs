@@ -0,0 +1,308 @@
+//! Sorted set range command implementations
+//!
+//! Provides the lexicographical range commands (ZRANGEBYLEX, ZREVRANGEBYLEX,
+//! ZLEXCOUNT) and the range removals (ZREMRANGEBYRANK, ZREMRANGEBYSCORE,
+//! ZREMRANGEBYLEX). The basic sorted set commands live in the server.
+
+use crate::error::{FerrousError, Result, StorageError};
+use crate::protocol::RespFrame;
+use crate::storage::StorageEngine;
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// Error for a lex bound that is not `-`, `+`, `[member` or `(member`
+pub const INVALID_LEX_RANGE: &str = "ERR min or max not valid string range item";
+
+/// One end of a lexicographical range
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    /// `-`: below every member
+    Min,
+    
+    /// `+`: above every member
+    Max,
+    
+    /// `[member`
+    Inclusive(Vec<u8>),
+    
+    /// `(member`
+    Exclusive(Vec<u8>),
+}
+
+impl LexBound {
+    /// Parse a lex bound argument
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            b"-" => Some(LexBound::Min),
+            b"+" => Some(LexBound::Max),
+            [b'[', member @ ..] => Some(LexBound::Inclusive(member.to_vec())),
+            [b'(', member @ ..] => Some(LexBound::Exclusive(member.to_vec())),
+            _ => None,
+        }
+    }
+    
+    fn as_bound(&self) -> Bound<&[u8]> {
+        match self {
+            LexBound::Min | LexBound::Max => Bound::Unbounded,
+            LexBound::Inclusive(member) => Bound::Included(member),
+            LexBound::Exclusive(member) => Bound::Excluded(member),
+        }
+    }
+}
+
+/// Members of a sorted set between two lex bounds, in order or reversed
+pub fn range_by_lex(storage: &StorageEngine, db: usize, key: &[u8], min: &LexBound, max: &LexBound, reverse: bool)
+    -> Result<Vec<(Vec<u8>, f64)>> {
+    // `+` as the lower end or `-` as the upper end selects nothing
+    if *min == LexBound::Max || *max == LexBound::Min {
+        return Ok(Vec::new());
+    }
+    storage.zrangebylex(db, key, min.as_bound(), max.as_bound(), reverse)
+}
+
+/// Apply a `LIMIT offset count` clause: a negative offset selects nothing, a negative count everything after it
+pub fn apply_limit<T>(items: Vec<T>, offset: i64, count: i64) -> Vec<T> {
+    if offset < 0 {
+        return Vec::new();
+    }
+    let items = items.into_iter().skip(offset as usize);
+    if count < 0 {
+        items.collect()
+    } else {
+        items.take(count as usize).collect()
+    }
+}
+
+/// Remove members from a sorted set, returning how many were removed
+pub fn remove_members(storage: &StorageEngine, db: usize, key: &[u8], members: Vec<(Vec<u8>, f64)>) -> Result<i64> {
+    let mut removed = 0;
+    for (member, _score) in members {
+        if storage.zrem(db, key, &member)? {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Reply with an error matching a storage failure
+fn storage_error(e: FerrousError) -> Result<RespFrame> {
+    match e {
+        FerrousError::Storage(StorageError::WrongType) => {
+            Ok(RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"))
+        }
+        e => Ok(RespFrame::error(format!("ERR {}", e))),
+    }
+}
+
+/// Parse the key and the two lex bounds shared by the lex commands
+fn parse_lex_args(parts: &[RespFrame], reverse: bool) -> std::result::Result<(&[u8], LexBound, LexBound), RespFrame> {
+    let key = match &parts[1] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_slice(),
+        _ => return Err(RespFrame::error("ERR invalid key format")),
+    };
+    let bound = |part: &RespFrame| match part {
+        RespFrame::BulkString(Some(bytes)) => LexBound::parse(bytes).ok_or_else(|| RespFrame::error(INVALID_LEX_RANGE)),
+        _ => Err(RespFrame::error(INVALID_LEX_RANGE)),
+    };
+    // The reverse command takes max before min
+    let (first, second) = (bound(&parts[2])?, bound(&parts[3])?);
+    if reverse {
+        Ok((key, second, first))
+    } else {
+        Ok((key, first, second))
+    }
+}
+
+/// Parse an optional `LIMIT offset count` clause starting at `parts[index]`
+fn parse_limit(parts: &[RespFrame], index: usize) -> std::result::Result<Option<(i64, i64)>, RespFrame> {
+    if parts.len() == index {
+        return Ok(None);
+    }
+    let word = |i: usize| match parts.get(i) {
+        Some(RespFrame::BulkString(Some(bytes))) => Some(String::from_utf8_lossy(bytes).to_string()),
+        _ => None,
+    };
+    if parts.len() != index + 3 || !word(index).is_some_and(|w| w.eq_ignore_ascii_case("LIMIT")) {
+        return Err(RespFrame::error("ERR syntax error"));
+    }
+    let number = |i: usize| word(i).and_then(|w| w.parse::<i64>().ok())
+        .ok_or_else(|| RespFrame::error("ERR value is not an integer or out of range"));
+    Ok(Some((number(index + 1)?, number(index + 2)?)))
+}
+
+/// Parse a score argument the way ZRANGEBYSCORE does
+fn parse_score(part: &RespFrame) -> Option<f64> {
+    match part {
+        RespFrame::BulkString(Some(bytes)) => String::from_utf8_lossy(bytes).parse::<f64>().ok(),
+        _ => None,
+    }
+}
+
+/// Handle ZRANGEBYLEX and ZREVRANGEBYLEX commands
+fn handle_range_by_lex(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame], reverse: bool) -> Result<RespFrame> {
+    if parts.len() != 4 && parts.len() != 7 {
+        let name = if reverse { "zrevrangebylex" } else { "zrangebylex" };
+        return Ok(RespFrame::error(format!("ERR wrong number of arguments for '{}' command", name)));
+    }
+    
+    let (key, min, max) = match parse_lex_args(parts, reverse) {
+        Ok(args) => args,
+        Err(reply) => return Ok(reply),
+    };
+    let limit = match parse_limit(parts, 4) {
+        Ok(limit) => limit,
+        Err(reply) => return Ok(reply),
+    };
+    
+    let mut members = match range_by_lex(storage, db, key, &min, &max, reverse) {
+        Ok(members) => members,
+        Err(e) => return storage_error(e),
+    };
+    if let Some((offset, count)) = limit {
+        members = apply_limit(members, offset, count);
+    }
+    
+    Ok(RespFrame::Array(Some(members.into_iter().map(|(member, _)| RespFrame::from_bytes(member)).collect())))
+}
+
+/// Handle ZRANGEBYLEX command - Members between two lex bounds
+pub fn handle_zrangebylex(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    handle_range_by_lex(storage, db, parts, false)
+}
+
+/// Handle ZREVRANGEBYLEX command - Members between two lex bounds, highest first
+pub fn handle_zrevrangebylex(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    handle_range_by_lex(storage, db, parts, true)
+}
+
+/// Handle ZLEXCOUNT command - Count members between two lex bounds
+pub fn handle_zlexcount(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() != 4 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'zlexcount' command"));
+    }
+    
+    let (key, min, max) = match parse_lex_args(parts, false) {
+        Ok(args) => args,
+        Err(reply) => return Ok(reply),
+    };
+    match range_by_lex(storage, db, key, &min, &max, false) {
+        Ok(members) => Ok(RespFrame::Integer(members.len() as i64)),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Handle ZREMRANGEBYRANK command - Remove members within a rank range
+pub fn handle_zremrangebyrank(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() != 4 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'zremrangebyrank' command"));
+    }
+    
+    let key = match &parts[1] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_slice(),
+        _ => return Ok(RespFrame::error("ERR invalid key format")),
+    };
+    let rank = |part: &RespFrame| match part {
+        RespFrame::BulkString(Some(bytes)) => String::from_utf8_lossy(bytes).parse::<isize>().ok(),
+        _ => None,
+    };
+    let (Some(start), Some(stop)) = (rank(&parts[2]), rank(&parts[3])) else {
+        return Ok(RespFrame::error("ERR value is not an integer or out of range"));
+    };
+    
+    match storage.zrange(db, key, start, stop, false).and_then(|members| remove_members(storage, db, key, members)) {
+        Ok(removed) => Ok(RespFrame::Integer(removed)),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Handle ZREMRANGEBYSCORE command - Remove members within a score range
+pub fn handle_zremrangebyscore(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() != 4 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'zremrangebyscore' command"));
+    }
+    
+    let key = match &parts[1] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_slice(),
+        _ => return Ok(RespFrame::error("ERR invalid key format")),
+    };
+    let (Some(min_score), Some(max_score)) = (parse_score(&parts[2]), parse_score(&parts[3])) else {
+        return Ok(RespFrame::error("ERR min or max is not a float"));
+    };
+    
+    match storage.zrangebyscore(db, key, min_score, max_score, false).and_then(|members| remove_members(storage, db, key, members)) {
+        Ok(removed) => Ok(RespFrame::Integer(removed)),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Handle ZREMRANGEBYLEX command - Remove members between two lex bounds
+pub fn handle_zremrangebylex(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() != 4 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'zremrangebylex' command"));
+    }
+    
+    let (key, min, max) = match parse_lex_args(parts, false) {
+        Ok(args) => args,
+        Err(reply) => return Ok(reply),
+    };
+    match range_by_lex(storage, db, key, &min, &max, false).and_then(|members| remove_members(storage, db, key, members)) {
+        Ok(removed) => Ok(RespFrame::Integer(removed)),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn bulk(s: &str) -> RespFrame {
+        RespFrame::BulkString(Some(Arc::new(s.as_bytes().to_vec())))
+    }
+    
+    fn command(words: &[&str]) -> Vec<RespFrame> {
+        words.iter().map(|word| bulk(word)).collect()
+    }
+    
+    #[test]
+    fn test_lex_ranges() {
+        let storage = StorageEngine::new_in_memory();
+        for member in ["a", "b", "c", "d", "e"] {
+            storage.zadd(0, b"z".to_vec(), member.as_bytes().to_vec(), 0.0).unwrap();
+        }
+        let members = |words: &[&str]| handle_zrangebylex(&storage, 0, &command(words)).unwrap();
+        let array = |words: &[&str]| RespFrame::Array(Some(words.iter().map(|word| bulk(word)).collect()));
+        
+        assert_eq!(members(&["ZRANGEBYLEX", "z", "-", "[c"]), array(&["a", "b", "c"]));
+        assert_eq!(members(&["ZRANGEBYLEX", "z", "(b", "+", "LIMIT", "1", "2"]), array(&["d", "e"]));
+        assert_eq!(members(&["ZRANGEBYLEX", "z", "+", "-"]), array(&[]));
+        assert_eq!(members(&["ZRANGEBYLEX", "z", "b", "+"]), RespFrame::error(INVALID_LEX_RANGE));
+        assert_eq!(handle_zrevrangebylex(&storage, 0, &command(&["ZREVRANGEBYLEX", "z", "[d", "(b"])).unwrap(), array(&["d", "c"]));
+        assert_eq!(handle_zlexcount(&storage, 0, &command(&["ZLEXCOUNT", "z", "[b", "[d"])).unwrap(), RespFrame::Integer(3));
+        
+        assert_eq!(handle_zremrangebylex(&storage, 0, &command(&["ZREMRANGEBYLEX", "z", "[b", "(d"])).unwrap(), RespFrame::Integer(2));
+        assert_eq!(members(&["ZRANGEBYLEX", "z", "-", "+"]), array(&["a", "d", "e"]));
+    }
+    
+    #[test]
+    fn test_range_removals() {
+        let storage = StorageEngine::new_in_memory();
+        for (score, member) in [(1.0, "a"), (2.0, "b"), (3.0, "c"), (4.0, "d")] {
+            storage.zadd(0, b"z".to_vec(), member.as_bytes().to_vec(), score).unwrap();
+        }
+        
+        assert_eq!(handle_zremrangebyscore(&storage, 0, &command(&["ZREMRANGEBYSCORE", "z", "-inf", "1.5"])).unwrap(), RespFrame::Integer(1));
+        assert_eq!(handle_zremrangebyrank(&storage, 0, &command(&["ZREMRANGEBYRANK", "z", "-1", "-1"])).unwrap(), RespFrame::Integer(1));
+        assert_eq!(storage.zcard(0, b"z").unwrap(), 2);
+        
+        storage.set_string(0, b"s".to_vec(), b"v".to_vec()).unwrap();
+        let reply = handle_zremrangebyrank(&storage, 0, &command(&["ZREMRANGEBYRANK", "s", "0", "-1"])).unwrap();
+        assert!(matches!(reply, RespFrame::Error(message) if message.starts_with(b"WRONGTYPE")));
+    }
+    
+    #[test]
+    fn test_limit() {
+        assert_eq!(apply_limit(vec![1, 2, 3, 4], 1, 2), [2, 3]);
+        assert_eq!(apply_limit(vec![1, 2, 3, 4], 2, -1), [3, 4]);
+        assert!(apply_limit(vec![1, 2, 3, 4], -1, 2).is_empty());
+    }
+}
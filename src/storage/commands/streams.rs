@@ -8,6 +8,86 @@ use crate::storage::StorageEngine;
 use crate::storage::stream::StreamId;
 use std::sync::Arc;
 use std::collections::HashMap;
+use std::time::Duration;
+
+/// An XREAD or XREADGROUP with BLOCK, as the server parks it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockingRead {
+    /// BLOCK timeout; None blocks until an entry arrives
+    pub timeout: Option<Duration>,
+    /// Streams whose new entries wake the reader
+    pub keys: Vec<Vec<u8>>,
+    /// The same read without BLOCK, re-run on each wake-up
+    pub command: Vec<Vec<u8>>,
+}
+
+/// Split the BLOCK option off an XREAD or XREADGROUP
+///
+/// Returns None without a valid BLOCK, leaving errors to the command handler.
+/// XREAD's `$` is replaced by the stream's current last ID, so the re-run read
+/// returns the entries added while the client was blocked.
+pub fn parse_blocking_read(storage: &StorageEngine, db: usize, args: &[&[u8]]) -> Option<BlockingRead> {
+    let group = args.first()?.eq_ignore_ascii_case(b"XREADGROUP");
+    
+    // XREADGROUP's GROUP group consumer come before the options
+    let options_start = if group { 4 } else { 1 };
+    let mut command: Vec<Vec<u8>> = args.get(..options_start)?.iter().map(|arg| arg.to_vec()).collect();
+    let mut timeout = None;
+    let mut index = options_start;
+    loop {
+        match args.get(index)?.to_ascii_uppercase().as_slice() {
+            b"BLOCK" => {
+                let millis: u64 = std::str::from_utf8(args.get(index + 1)?).ok()?.parse().ok()?;
+                timeout = Some((millis > 0).then(|| Duration::from_millis(millis)));
+                index += 2;
+            }
+            b"COUNT" => {
+                command.extend(args.get(index..index + 2)?.iter().map(|arg| arg.to_vec()));
+                index += 2;
+            }
+            b"NOACK" if group => {
+                command.push(args[index].to_vec());
+                index += 1;
+            }
+            b"STREAMS" => {
+                command.push(args[index].to_vec());
+                index += 1;
+                break;
+            }
+            _ => return None,
+        }
+    }
+    
+    let timeout = timeout?;
+    let streams = &args[index..];
+    if streams.is_empty() || !streams.len().is_multiple_of(2) {
+        return None;
+    }
+    let (keys, ids) = streams.split_at(streams.len() / 2);
+    command.extend(keys.iter().map(|key| key.to_vec()));
+    for (key, id) in keys.iter().zip(ids) {
+        if group && *id != b">" {
+            // Reading a consumer's pending history never blocks
+            return None;
+        } else if !group && *id == b"$" {
+            let last_id = storage.xlast_id(db, key).ok()?.unwrap_or_else(|| StreamId::new(0, 0));
+            command.push(last_id.to_string().into_bytes());
+        } else {
+            command.push(id.to_vec());
+        }
+    }
+    
+    Some(BlockingRead { timeout, keys: keys.iter().map(|key| key.to_vec()).collect(), command })
+}
+
+/// Check whether an XREAD or XREADGROUP reply carries no entries
+pub fn is_empty_read(reply: &RespFrame) -> bool {
+    match reply {
+        RespFrame::Array(None) => true,
+        RespFrame::Array(Some(streams)) => streams.is_empty(),
+        _ => false,
+    }
+}
 
 /// Handle XADD command - Add entries to a stream
 pub fn handle_xadd(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
@@ -527,4 +607,42 @@ pub fn handle_xdel(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame])
     
     let deleted = storage.xdel(db, key, ids)?;
     Ok(RespFrame::Integer(deleted as i64))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn args(command: &str) -> Vec<&[u8]> {
+        command.split(' ').map(str::as_bytes).collect()
+    }
+    
+    fn command(read: &BlockingRead) -> String {
+        read.command.iter().map(|arg| String::from_utf8_lossy(arg)).collect::<Vec<_>>().join(" ")
+    }
+    
+    #[test]
+    fn test_parse_blocking_read() {
+        let storage = StorageEngine::new();
+        let mut fields = HashMap::new();
+        fields.insert(b"f".to_vec(), b"v".to_vec());
+        storage.xadd_with_id(0, b"events".to_vec(), StreamId::new(5, 1), fields).unwrap();
+        
+        // BLOCK is dropped and `$` pinned to the last ID, or 0-0 for a missing stream
+        let read = parse_blocking_read(&storage, 0, &args("XREAD COUNT 2 BLOCK 1500 STREAMS events other $ $")).unwrap();
+        assert_eq!(read.timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(read.keys, vec![b"events".to_vec(), b"other".to_vec()]);
+        assert_eq!(command(&read), "XREAD COUNT 2 STREAMS events other 5-1 0-0");
+        
+        // BLOCK 0 waits forever; a group only blocks for new entries
+        let read = parse_blocking_read(&storage, 0, &args("XREADGROUP GROUP block consumer BLOCK 0 NOACK STREAMS events >")).unwrap();
+        assert_eq!(read.timeout, None);
+        assert_eq!(command(&read), "XREADGROUP GROUP block consumer NOACK STREAMS events >");
+        assert!(parse_blocking_read(&storage, 0, &args("XREADGROUP GROUP g c BLOCK 10 STREAMS events 0")).is_none());
+        
+        // Without BLOCK, or with a malformed command, the handler takes over
+        assert!(parse_blocking_read(&storage, 0, &args("XREAD STREAMS events $")).is_none());
+        assert!(parse_blocking_read(&storage, 0, &args("XREAD BLOCK x STREAMS events $")).is_none());
+        assert!(parse_blocking_read(&storage, 0, &args("XREAD BLOCK 10 STREAMS events")).is_none());
+    }
+}
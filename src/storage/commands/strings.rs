@@ -5,7 +5,9 @@
 use crate::error::{FerrousError, Result, StorageError};
 use crate::protocol::RespFrame;
 use crate::storage::StorageEngine;
+use crate::storage::value::{ExpireCondition, parse_float};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Handle MGET command - Get multiple keys
 pub fn handle_mget(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
@@ -110,6 +112,34 @@ pub fn handle_append(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame
     }
 }
 
+/// Handle INCRBYFLOAT command - Increment a string by a float
+pub fn handle_incrbyfloat(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    if parts.len() != 3 {
+        return Ok(RespFrame::error("ERR wrong number of arguments for 'incrbyfloat' command"));
+    }
+    
+    let key = match &parts[1] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_ref().clone(),
+        _ => return Ok(RespFrame::error("ERR invalid key format")),
+    };
+    
+    let increment = match &parts[2] {
+        RespFrame::BulkString(Some(bytes)) => match parse_float(bytes) {
+            Some(increment) => increment,
+            None => return Ok(RespFrame::error("ERR value is not a valid float")),
+        },
+        _ => return Ok(RespFrame::error("ERR invalid increment format")),
+    };
+    
+    match storage.incr_by_float(db, key, increment) {
+        Ok(new_value) => Ok(RespFrame::from_bytes(new_value)),
+        Err(FerrousError::Storage(StorageError::WrongType)) => {
+            Ok(RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"))
+        },
+        Err(e) => Ok(RespFrame::error(e.to_string())),
+    }
+}
+
 /// Handle STRLEN command - Get string length
 pub fn handle_strlen(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
     if parts.len() != 2 {
@@ -268,10 +298,26 @@ pub fn handle_keys(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame])
     Ok(RespFrame::Array(Some(frames)))
 }
 
-/// Handle PEXPIRE command - Set expiration in milliseconds
-pub fn handle_pexpire(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
-    if parts.len() != 3 {
-        return Ok(RespFrame::error("ERR wrong number of arguments for 'pexpire' command"));
+/// Resolve the time argument of the EXPIRE family as a monotonic deadline.
+/// `time` is in units of `unit_ms` milliseconds, relative or a unix timestamp;
+/// `None` when it overflows. Deadlines in the past come back as now.
+pub fn expire_deadline(time: i64, unit_ms: i64, absolute: bool) -> Option<Instant> {
+    let millis = time.checked_mul(unit_ms)?;
+    let remaining_ms = if absolute {
+        millis.checked_sub(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64)?
+    } else {
+        millis
+    };
+    if remaining_ms <= 0 {
+        return Some(Instant::now());
+    }
+    Instant::now().checked_add(Duration::from_millis(remaining_ms as u64))
+}
+
+/// Shared implementation of EXPIRE/PEXPIRE/EXPIREAT/PEXPIREAT
+fn handle_key_expire(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame], name: &str, unit_ms: i64, absolute: bool) -> Result<RespFrame> {
+    if parts.len() < 3 {
+        return Ok(RespFrame::error(format!("ERR wrong number of arguments for '{}' command", name)));
     }
     
     let key = match &parts[1] {
@@ -279,20 +325,110 @@ pub fn handle_pexpire(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFram
         _ => return Ok(RespFrame::error("ERR invalid key format")),
     };
     
-    let milliseconds = match &parts[2] {
+    let time = match &parts[2] {
         RespFrame::BulkString(Some(bytes)) => {
-            match String::from_utf8_lossy(bytes).parse::<u64>() {
+            match String::from_utf8_lossy(bytes).parse::<i64>() {
                 Ok(n) => n,
                 Err(_) => return Ok(RespFrame::error("ERR value is not an integer or out of range")),
             }
         }
-        _ => return Ok(RespFrame::error("ERR invalid milliseconds format")),
+        _ => return Ok(RespFrame::error("ERR value is not an integer or out of range")),
     };
     
-    let result = storage.pexpire(db, key, milliseconds)?;
+    // Optional NX | XX | GT | LT flags
+    let mut flags = Vec::new();
+    for part in &parts[3..] {
+        match part {
+            RespFrame::BulkString(Some(bytes)) => flags.push(bytes.as_slice()),
+            _ => return Ok(RespFrame::error("ERR syntax error")),
+        }
+    }
+    let conditions = match ExpireCondition::parse_flags(&flags) {
+        Ok(conditions) => conditions,
+        Err(msg) => return Ok(RespFrame::error(format!("ERR {}", msg))),
+    };
+    
+    let expires_at = match expire_deadline(time, unit_ms, absolute) {
+        Some(expires_at) => expires_at,
+        None => return Ok(RespFrame::error(format!("ERR invalid expire time in '{}' command", name))),
+    };
+    
+    let result = storage.expire_at(db, key, expires_at, &conditions)?;
     Ok(RespFrame::Integer(if result { 1 } else { 0 }))
 }
 
+/// Handle EXPIRE command - Set expiration in seconds
+pub fn handle_expire(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    handle_key_expire(storage, db, parts, "expire", 1000, false)
+}
+
+/// Handle PEXPIRE command - Set expiration in milliseconds
+pub fn handle_pexpire(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    handle_key_expire(storage, db, parts, "pexpire", 1, false)
+}
+
+/// Handle EXPIREAT command - Set expiration as a unix timestamp in seconds
+pub fn handle_expireat(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    handle_key_expire(storage, db, parts, "expireat", 1000, true)
+}
+
+/// Handle PEXPIREAT command - Set expiration as a unix timestamp in milliseconds
+pub fn handle_pexpireat(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    handle_key_expire(storage, db, parts, "pexpireat", 1, true)
+}
+
+/// Absolute expiration of a key as a unix timestamp in milliseconds,
+/// -1 when the key has no expiration and -2 when it does not exist
+pub fn expire_time_millis(storage: &StorageEngine, db: usize, key: &[u8]) -> Result<i64> {
+    match storage.ttl(db, key)? {
+        Some(ttl) => Ok((SystemTime::now() + ttl).duration_since(UNIX_EPOCH).unwrap().as_millis() as i64),
+        None if storage.exists(db, key)? => Ok(-1),
+        None => Ok(-2),
+    }
+}
+
+/// Shared implementation of EXPIRETIME/PEXPIRETIME
+fn handle_key_expire_time(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame], name: &str, in_millis: bool) -> Result<RespFrame> {
+    if parts.len() != 2 {
+        return Ok(RespFrame::error(format!("ERR wrong number of arguments for '{}' command", name)));
+    }
+    
+    let key = match &parts[1] {
+        RespFrame::BulkString(Some(bytes)) => bytes.as_ref(),
+        _ => return Ok(RespFrame::error("ERR invalid key format")),
+    };
+    
+    let millis = expire_time_millis(storage, db, key)?;
+    Ok(RespFrame::Integer(if in_millis || millis < 0 { millis } else { millis / 1000 }))
+}
+
+/// Handle EXPIRETIME command - Get the expiration as a unix timestamp in seconds
+pub fn handle_expiretime(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    handle_key_expire_time(storage, db, parts, "expiretime", false)
+}
+
+/// Handle PEXPIRETIME command - Get the expiration as a unix timestamp in milliseconds
+pub fn handle_pexpiretime(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
+    handle_key_expire_time(storage, db, parts, "pexpiretime", true)
+}
+
+/// Command replicas apply for a successful EXPIRE/PEXPIRE/EXPIREAT/PEXPIREAT:
+/// PEXPIREAT with the absolute deadline, so replicas expire the key when the
+/// master does regardless of delay, or DEL when the key is already gone.
+/// `None` for other commands and keys left without an expiration.
+pub fn propagated_expire<T: AsRef<[u8]>>(storage: &StorageEngine, db: usize, args: &[T]) -> Option<Vec<Vec<u8>>> {
+    let name = args.first()?.as_ref().to_ascii_uppercase();
+    if !matches!(name.as_slice(), b"EXPIRE" | b"PEXPIRE" | b"EXPIREAT" | b"PEXPIREAT") {
+        return None;
+    }
+    let key = args.get(1)?.as_ref().to_vec();
+    match expire_time_millis(storage, db, &key).ok()? {
+        -2 => Some(vec![b"DEL".to_vec(), key]),
+        -1 => None,
+        millis => Some(vec![b"PEXPIREAT".to_vec(), key, millis.to_string().into_bytes()]),
+    }
+}
+
 /// Handle PTTL command - Get TTL in milliseconds
 pub fn handle_pttl(storage: &Arc<StorageEngine>, db: usize, parts: &[RespFrame]) -> Result<RespFrame> {
     if parts.len() != 2 {
@@ -155,5 +155,5 @@ pub fn should_queue_command(command: &str) -> bool {
 /// Queue a command for later execution
 pub fn queue_command(conn: &mut Connection, parts: Vec<RespFrame>) -> Result<RespFrame> {
     conn.transaction_state.queued_commands.push_back(parts);
-    Ok(RespFrame::SimpleString(Arc::new(b"QUEUED".to_vec())))
+    Ok(crate::protocol::shared::queued())
 }
\ No newline at end of file
@@ -2,22 +2,36 @@
 //! 
 //! Provides Redis-compatible storage with sharded simple structure and no access time tracking overhead.
 
-use std::collections::{VecDeque, HashSet, HashMap};
+use std::collections::{hash_map, HashSet, HashMap};
+use std::ops::Bound;
+use std::sync::atomic::AtomicU64;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 use std::thread;
+use rand::Rng;
 use rand::seq::SliceRandom;
 
 use crate::error::{FerrousError, Result, StorageError, CommandError};
-use super::value::{Value, StoredValue};
-use super::memory::MemoryManager;
-use super::skiplist::SkipList;
+use super::value::{Value, StoredValue, HashValue, ListValue, SetValue, ZSetValue, EncodingLimits, ExpireCondition, ListEnd, string_memory_usage, parse_float, format_float};
+use super::memory::{EvictionPolicy, MemoryManager};
+use super::shared;
+use super::hyperloglog::{self, HyperLogLog};
 use super::stream::{Stream, StreamId, StreamEntry};
 use super::{DatabaseIndex, Key};
+use super::commands::executor::{Command, ParsedCommand, Reply, UnifiedCommandExecutor};
 
 /// Number of shards per database for optimal concurrency
 const SHARDS_PER_DATABASE: usize = 16;
 
+/// Best eviction candidates kept between sampling rounds (Redis' EVPOOL_SIZE)
+const EVICTION_POOL_SIZE: usize = 16;
+
+/// Hashes with field TTLs checked per round of active field expiry
+const HASH_FIELD_EXPIRE_SAMPLES: usize = 20;
+
+/// Time a pass of active field expiry may spend repeating rounds on busy shards
+const HASH_FIELD_EXPIRE_BUDGET: Duration = Duration::from_millis(25);
+
 /// Sharded storage engine with simple HashMap structures - NO access time tracking
 pub struct StorageEngine {
     /// Multiple databases, each with multiple shards
@@ -29,6 +43,12 @@ pub struct StorageEngine {
     
     /// Background expiration thread handle
     expiration_handle: Option<thread::JoinHandle<()>>,
+    
+    /// Keys removed by the expiration thread, for INFO expired_keys
+    expired_keys: std::sync::atomic::AtomicU64,
+    
+    /// Keys removed to stay under maxmemory, for INFO evicted_keys
+    evicted_keys: std::sync::atomic::AtomicU64,
 }
 
 /// A single database with sharded storage
@@ -97,17 +117,121 @@ impl ShardWatchTracker {
     }
 }
 
+/// A shard's keys, keeping old values for the snapshots being saved
+///
+/// While a [`Snapshot`] is alive, the first change to a key, through
+/// `get_mut`, `insert`, `remove` or `clear`, keeps what the key held when the
+/// snapshot was taken (`None` if it did not exist), and the snapshot reads
+/// that instead of the live value. Only keys written during a save are copied,
+/// the way pages are after a fork.
+#[derive(Debug, Default)]
+struct ShardData {
+    map: HashMap<Key, StoredValue>,
+    
+    /// Old values kept for each live snapshot, by snapshot id
+    preserved: Vec<(u64, HashMap<Key, Option<StoredValue>>)>,
+}
+
+impl ShardData {
+    fn get(&self, key: &[u8]) -> Option<&StoredValue> {
+        self.map.get(key)
+    }
+    
+    fn get_mut(&mut self, key: &[u8]) -> Option<&mut StoredValue> {
+        self.preserve(key);
+        self.map.get_mut(key)
+    }
+    
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.map.contains_key(key)
+    }
+    
+    fn insert(&mut self, key: Key, stored_value: StoredValue) -> Option<StoredValue> {
+        self.preserve(&key);
+        self.map.insert(key, stored_value)
+    }
+    
+    fn remove(&mut self, key: &[u8]) -> Option<StoredValue> {
+        self.preserve(key);
+        self.map.remove(key)
+    }
+    
+    fn clear(&mut self) {
+        let Some(((_, last), others)) = self.preserved.split_last_mut() else {
+            self.map.clear();
+            return;
+        };
+        // The values move into the newest snapshot, older ones need copies
+        for (key, stored_value) in self.map.drain() {
+            for (_, old) in others.iter_mut() {
+                old.entry(key.clone()).or_insert_with(|| Some(stored_value.clone()));
+            }
+            last.entry(key).or_insert(Some(stored_value));
+        }
+    }
+    
+    fn iter(&self) -> hash_map::Iter<'_, Key, StoredValue> {
+        self.map.iter()
+    }
+    
+    fn keys(&self) -> hash_map::Keys<'_, Key, StoredValue> {
+        self.map.keys()
+    }
+    
+    fn values(&self) -> hash_map::Values<'_, Key, StoredValue> {
+        self.map.values()
+    }
+    
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+    
+    fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+    
+    /// Keep the value a key has now for every live snapshot that has none yet
+    fn preserve(&mut self, key: &[u8]) {
+        for (_, old) in &mut self.preserved {
+            if !old.contains_key(key) {
+                old.insert(key.to_vec(), self.map.get(key).cloned());
+            }
+        }
+    }
+    
+    /// Start keeping old values for snapshot `id`
+    fn preserve_for(&mut self, id: u64) {
+        self.preserved.push((id, HashMap::new()));
+    }
+    
+    /// Old values kept for snapshot `id`
+    fn preserved(&self, id: u64) -> Option<&HashMap<Key, Option<StoredValue>>> {
+        self.preserved.iter().find(|(snapshot, _)| *snapshot == id).map(|(_, old)| old)
+    }
+    
+    /// Stop keeping old values for snapshot `id`
+    fn release(&mut self, id: u64) {
+        self.preserved.retain(|(snapshot, _)| *snapshot != id);
+    }
+}
+
 /// A single database shard with conditional WATCH tracking
 #[derive(Debug)]
 pub struct DatabaseShard {
-    /// Key-value storage using simple HashMap for maximum performance
-    data: HashMap<Key, StoredValue>,
+    /// Keys and values, with old values kept for snapshots being saved
+    data: ShardData,
     
     /// Keys with expiration timestamps for efficient cleanup
     expiring_keys: HashMap<Key, Instant>,
     
+    /// Hash keys holding at least one field with a TTL (zero overhead when no field TTLs exist)
+    volatile_hash_keys: HashSet<Key>,
+    
     /// Conditional WATCH tracking (zero overhead when no WATCH active)
     watch_tracker: ShardWatchTracker,
+    
+    /// Engine-wide accounting every key stored or removed here is charged to
+    memory: Arc<MemoryManager>,
 }
 
 /// Result of a GET operation
@@ -126,6 +250,102 @@ pub enum GetResult {
     Expired,
 }
 
+/// Outcome of [`StorageEngine::evict`]
+#[derive(Debug, Default)]
+pub struct Eviction {
+    /// Keys evicted, by database, for the caller to propagate as deletions
+    pub keys: Vec<(DatabaseIndex, Key)>,
+    
+    /// Memory is back under maxmemory, or was never over it
+    pub within_limit: bool,
+}
+
+/// A key sampled for eviction, ranked by how good a victim it is
+struct EvictionCandidate {
+    db: DatabaseIndex,
+    key: Key,
+    
+    /// Higher is evicted first: idle time, inverse frequency, or closeness to expiry
+    score: u64,
+}
+
+/// Source of [`Snapshot`] ids
+static NEXT_SNAPSHOT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Every database at a point in time, taken for RDB saves
+///
+/// Taking one copies nothing: each shard starts keeping the old value of a key
+/// the first time it changes (see [`ShardData`]), and the keys are copied out
+/// one shard at a time when a database is read, on the thread writing the
+/// save. Old values are dropped with the snapshot.
+#[derive(Debug)]
+pub struct Snapshot {
+    /// Identifies the old values kept for this snapshot in each shard
+    id: u64,
+    
+    /// When the snapshot was taken; keys expired by then are left out
+    taken_at: Instant,
+    
+    /// Shards of each database
+    databases: Vec<Vec<Arc<RwLock<DatabaseShard>>>>,
+}
+
+/// A key as it was when its [`Snapshot`] was taken
+#[derive(Debug)]
+pub struct SnapshotEntry {
+    /// Key name
+    pub key: Key,
+    
+    /// Value, including any hash field TTLs
+    pub value: Value,
+    
+    /// When the key expires, if it has a TTL
+    pub expires_at: Option<Instant>,
+}
+
+impl Snapshot {
+    /// Number of databases captured
+    pub fn database_count(&self) -> usize {
+        self.databases.len()
+    }
+    
+    /// Copy the keys of a database as they were when the snapshot was taken
+    ///
+    /// Each shard is read-locked only while its own keys are copied.
+    pub fn database(&self, db: DatabaseIndex) -> Vec<SnapshotEntry> {
+        let mut entries = Vec::new();
+        for shard in self.databases.get(db).into_iter().flatten() {
+            let shard_guard = shard.read().unwrap();
+            let Some(old) = shard_guard.data.preserved(self.id) else { continue };
+            let unchanged = shard_guard.data.iter().filter(|(key, _)| !old.contains_key(*key));
+            let changed = old.iter().filter_map(|(key, stored_value)| stored_value.as_ref().map(|stored_value| (key, stored_value)));
+            entries.extend(unchanged.chain(changed)
+                .filter(|(_, stored_value)| stored_value.metadata.expires_at.is_none_or(|expires_at| expires_at > self.taken_at))
+                .map(|(key, stored_value)| SnapshotEntry {
+                    key: key.clone(),
+                    value: stored_value.value.clone(),
+                    expires_at: stored_value.metadata.expires_at,
+                }));
+        }
+        entries
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        for shard in self.databases.iter().flatten() {
+            shard.write().unwrap().data.release(self.id);
+        }
+    }
+}
+
+impl SnapshotEntry {
+    /// Time left to live, zero once the key expired after the snapshot was taken
+    pub fn ttl(&self) -> Option<Duration> {
+        self.expires_at.map(|expires_at| expires_at.saturating_duration_since(Instant::now()))
+    }
+}
+
 impl StorageEngine {
     /// Create a new storage engine with default settings
     pub fn new() -> Arc<Self> {
@@ -139,15 +359,18 @@ impl StorageEngine {
     
     /// Create storage engine with configuration
     pub fn with_config(num_databases: usize, memory_manager: MemoryManager) -> Arc<Self> {
+        let memory_manager = Arc::new(memory_manager);
         let mut databases = Vec::with_capacity(num_databases);
         for _ in 0..num_databases {
-            databases.push(Database::new());
+            databases.push(Database::new(&memory_manager));
         }
         
         let engine = Arc::new(StorageEngine {
             databases,
-            memory_manager: Arc::new(memory_manager),
+            memory_manager,
             expiration_handle: None,
+            expired_keys: std::sync::atomic::AtomicU64::new(0),
+            evicted_keys: std::sync::atomic::AtomicU64::new(0),
         });
         
         // Start expiration cleanup thread
@@ -162,17 +385,7 @@ impl StorageEngine {
     /// Calculate shard index for a key using deterministic hash function
     /// This ensures the same key always maps to the same shard for consistent modification tracking
     fn get_shard_index(&self, key: &[u8]) -> usize {
-        // Use deterministic FNV-1a hash instead of random DefaultHasher
-        const FNV_OFFSET: u64 = 0xcbf29ce484222325;
-        const FNV_PRIME: u64 = 0x100000001b3;
-        
-        let mut hash = FNV_OFFSET;
-        for &byte in key {
-            hash ^= byte as u64;
-            hash = hash.wrapping_mul(FNV_PRIME);
-        }
-        
-        (hash % SHARDS_PER_DATABASE as u64) as usize
+        (key_hash(key) % SHARDS_PER_DATABASE as u64) as usize
     }
     
     /// Get shard for a key in a specific database
@@ -182,6 +395,16 @@ impl StorageEngine {
         Ok(&database.shards[shard_idx])
     }
     
+    /// Execute a typed command against a database
+    ///
+    /// Programmatic entry point for embedders and the script bridge: callers pass a
+    /// typed Command and get a typed Reply instead of building and re-parsing RESP.
+    pub fn execute(self: &Arc<Self>, db: DatabaseIndex, command: Command) -> Result<Reply> {
+        let executor = UnifiedCommandExecutor::new(Arc::clone(self));
+        let reply = executor.execute(ParsedCommand { command, db_override: Some(db) })?;
+        Ok(Reply::from(reply))
+    }
+    
     /// Set a string value
     pub fn set_string(&self, db: DatabaseIndex, key: Key, value: Vec<u8>) -> Result<()> {
         self.set_value(db, key, Value::string(value), None)
@@ -194,32 +417,16 @@ impl StorageEngine {
     
     /// Set a string value only if the key doesn't exist (atomic operation)
     pub fn set_string_nx(&self, db: DatabaseIndex, key: Key, value: Vec<u8>) -> Result<bool> {
-        let shard = self.get_shard(db, &key)?;
-        let mut shard_guard = shard.write().unwrap();
-        
-        // Check and set atomically while holding the write lock
-        if let Some(stored_value) = shard_guard.data.get(&key) {
-            // Key exists, check if expired
-            if !stored_value.is_expired() {
-                return Ok(false); // Key exists and not expired
-            }
-        }
-        
-        // Key doesn't exist or is expired, set it
-        let memory_size = self.calculate_value_size(&key, &Value::String(value.clone()));
-        if !self.memory_manager.add_memory(memory_size) {
-            return Err(StorageError::OutOfMemory.into());
-        }
-        
-        let stored_value = StoredValue::new(Value::String(value));
-        shard_guard.data.insert(key.clone(), stored_value);
-        shard_guard.mark_modified(&key);
-        
-        Ok(true)
+        self.set_value_nx(db, key, Value::string(value), None)
     }
     
     /// Set a string value with expiration only if the key doesn't exist (atomic operation)
     pub fn set_string_nx_ex(&self, db: DatabaseIndex, key: Key, value: Vec<u8>, expires_in: Duration) -> Result<bool> {
+        self.set_value_nx(db, key, Value::string(value), Some(expires_in))
+    }
+    
+    /// Set any value only if the key doesn't exist (atomic operation)
+    pub fn set_value_nx(&self, db: DatabaseIndex, key: Key, value: Value, expires_in: Option<Duration>) -> Result<bool> {
         let shard = self.get_shard(db, &key)?;
         let mut shard_guard = shard.write().unwrap();
         
@@ -231,17 +438,13 @@ impl StorageEngine {
             }
         }
         
-        // Key doesn't exist or is expired, set it with expiration
-        let memory_size = self.calculate_value_size(&key, &Value::String(value.clone()));
-        if !self.memory_manager.add_memory(memory_size) {
-            return Err(StorageError::OutOfMemory.into());
-        }
-        
-        let stored_value = StoredValue::with_expiration(Value::String(value), expires_in);
-        let expires_at = Instant::now() + expires_in;
-        shard_guard.expiring_keys.insert(key.clone(), expires_at);
-        shard_guard.data.insert(key.clone(), stored_value);
+        // Key doesn't exist or is expired, set it
+        let stored_value = match expires_in {
+            Some(expires_in) => StoredValue::with_expiration(value, expires_in),
+            None => StoredValue::new(value),
+        };
         shard_guard.mark_modified(&key);
+        shard_guard.insert_entry(key, stored_value);
         
         Ok(true)
     }
@@ -251,14 +454,6 @@ impl StorageEngine {
         let shard = self.get_shard(db, &key)?;
         let mut shard_guard = shard.write().unwrap();
         
-        // Calculate memory usage
-        let memory_size = self.calculate_value_size(&key, &value);
-        
-        // Check memory limits
-        if !self.memory_manager.add_memory(memory_size) {
-            return Err(StorageError::OutOfMemory.into());
-        }
-        
         // Create stored value
         let stored_value = if let Some(expires_in) = expires_in {
             StoredValue::with_expiration(value, expires_in)
@@ -266,17 +461,12 @@ impl StorageEngine {
             StoredValue::new(value)
         };
         
-        // Track expiration if needed
-        if let Some(expires_at) = stored_value.metadata.expires_at {
-            shard_guard.expiring_keys.insert(key.clone(), expires_at);
-        }
-        
         // CRITICAL FIX: Mark as modified BEFORE data change to fix WATCH race condition
         // This ensures any concurrent EXEC operations see the modification
         shard_guard.mark_modified(&key);
         
-        // Store the value - direct HashMap access for maximum performance
-        shard_guard.data.insert(key.clone(), stored_value);
+        // Store the value, tracking its expiration and memory
+        shard_guard.insert_entry(key, stored_value);
         
         Ok(())
     }
@@ -285,16 +475,17 @@ impl StorageEngine {
     pub fn get(&self, db: DatabaseIndex, key: &[u8]) -> Result<GetResult> {
         let shard = self.get_shard(db, key)?;
         let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(key);
         
-        match shard_guard.data.get_mut(key) {
+        match shard_guard.data.get(key) {
             Some(stored_value) => {
                 // Check if expired
                 if stored_value.is_expired() {
                     shard_guard.mark_modified(key);
                     
                     // Remove expired key
-                    shard_guard.data.remove(key);
-                    shard_guard.expiring_keys.remove(key);
+                    shard_guard.remove_entry(key);
+                    crate::storage::lua_triggers::notify(db, "expired", key.to_vec());
                     Ok(GetResult::Expired)
                 } else {
                     // Return value without touch() - matches Valkey's noeviction config
@@ -307,6 +498,11 @@ impl StorageEngine {
     
     /// Get string value
     pub fn get_string(&self, db: DatabaseIndex, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.get_string_shared(db, key)?.map(Arc::unwrap_or_clone))
+    }
+    
+    /// Get a string value without copying it out of the keyspace
+    pub fn get_string_shared(&self, db: DatabaseIndex, key: &[u8]) -> Result<Option<Arc<Vec<u8>>>> {
         match self.get(db, key)? {
             GetResult::Found(Value::String(bytes)) => Ok(Some(bytes)),
             GetResult::Found(_) => Err(StorageError::WrongType.into()),
@@ -315,9 +511,24 @@ impl StorageEngine {
         }
     }
     
+    /// Get the reference count of a key's value (OBJECT REFCOUNT)
+    pub fn object_refcount(&self, db: DatabaseIndex, key: &[u8]) -> Result<Option<i64>> {
+        let shard = self.get_shard(db, key)?;
+        let shard_guard = shard.read().unwrap();
+        
+        match shard_guard.data.get(key) {
+            Some(stored_value) if !stored_value.is_expired() => match &stored_value.value {
+                Value::String(bytes) if shared::is_shared(bytes) => Ok(Some(shared::SHARED_REFCOUNT)),
+                _ => Ok(Some(1)),
+            },
+            _ => Ok(None),
+        }
+    }
+    
     /// Check if key exists - optimized read path, no access time tracking
     pub fn exists(&self, db: DatabaseIndex, key: &[u8]) -> Result<bool> {
         let shard = self.get_shard(db, key)?;
+        Self::expire_hash_fields_before_read(shard, key);
         let shard_guard = shard.read().unwrap(); // Use read lock for existence check
         
         if let Some(stored_value) = shard_guard.data.get(key) {
@@ -327,19 +538,120 @@ impl StorageEngine {
         }
     }
     
+    /// Expire a hash's elapsed fields ahead of a read-locked lookup of its key
+    ///
+    /// The write lock is only taken when the key is a hash with field TTLs,
+    /// so a hash whose last field expired reads as gone.
+    fn expire_hash_fields_before_read(shard: &RwLock<DatabaseShard>, key: &[u8]) {
+        let volatile = {
+            let shard_guard = shard.read().unwrap();
+            !shard_guard.volatile_hash_keys.is_empty() && shard_guard.volatile_hash_keys.contains(key)
+        };
+        if volatile {
+            shard.write().unwrap().expire_hash_fields(key);
+        }
+    }
+    
+    /// Mark keys as accessed on the LRU clock, returning how many exist
+    ///
+    /// Callers normally go through `lru::record_access`, which honors CLIENT NO-TOUCH;
+    /// TOUCH calls this directly since it always updates the clock.
+    pub fn touch_keys<T: AsRef<[u8]>>(&self, db: DatabaseIndex, keys: &[T]) -> Result<usize> {
+        let lfu = self.memory_manager.lfu_params();
+        let mut count = 0;
+        for key in keys {
+            let key = key.as_ref();
+            let shard = self.get_shard(db, key)?;
+            let shard_guard = shard.read().unwrap();
+            if let Some(stored_value) = shard_guard.data.get(key) {
+                if !stored_value.is_expired() {
+                    stored_value.touch(lfu);
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+    
+    /// Time since a key was last accessed
+    pub fn idle_time(&self, db: DatabaseIndex, key: &[u8]) -> Result<Option<Duration>> {
+        let shard = self.get_shard(db, key)?;
+        let shard_guard = shard.read().unwrap();
+        match shard_guard.data.get(key) {
+            Some(stored_value) if !stored_value.is_expired() => Ok(Some(stored_value.metadata.last_accessed.idle_time())),
+            _ => Ok(None),
+        }
+    }
+    
+    /// Access frequency of a key on the LFU counter, decayed for its idle time
+    pub fn access_frequency(&self, db: DatabaseIndex, key: &[u8]) -> Result<Option<u8>> {
+        let shard = self.get_shard(db, key)?;
+        let shard_guard = shard.read().unwrap();
+        match shard_guard.data.get(key) {
+            Some(stored_value) if !stored_value.is_expired() => {
+                let metadata = &stored_value.metadata;
+                Ok(Some(metadata.access_frequency.get(metadata.last_accessed.idle_time(), self.memory_manager.lfu_params())))
+            }
+            _ => Ok(None),
+        }
+    }
+    
+    /// Set a key's idle time and LFU counter, as kept in RDB files and DUMP payloads
+    pub fn set_access_stats(&self, db: DatabaseIndex, key: &[u8], idle: Option<Duration>, frequency: Option<u8>) -> Result<bool> {
+        let shard = self.get_shard(db, key)?;
+        let shard_guard = shard.read().unwrap();
+        match shard_guard.data.get(key) {
+            Some(stored_value) if !stored_value.is_expired() => {
+                let metadata = &stored_value.metadata;
+                if let Some(frequency) = frequency {
+                    metadata.access_frequency.set(frequency);
+                }
+                if let Some(idle) = idle {
+                    metadata.last_accessed.set_idle_time(idle);
+                }
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+    
+    /// Encoding Redis would report for a key's value (OBJECT ENCODING)
+    pub fn object_encoding(&self, db: DatabaseIndex, key: &[u8]) -> Result<Option<&'static str>> {
+        let shard = self.get_shard(db, key)?;
+        let shard_guard = shard.read().unwrap();
+        match shard_guard.data.get(key) {
+            Some(stored_value) if !stored_value.is_expired() => Ok(Some(stored_value.value.encoding())),
+            _ => Ok(None),
+        }
+    }
+    
+    /// Current eviction policy
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.memory_manager.policy()
+    }
+    
+    /// Change the eviction policy (CONFIG SET maxmemory-policy)
+    pub fn set_eviction_policy(&self, policy: EvictionPolicy) {
+        self.memory_manager.set_policy(policy);
+    }
+    
+    /// Memory limit in bytes (0 = no limit)
+    pub fn max_memory(&self) -> usize {
+        self.memory_manager.max_memory()
+    }
+    
+    /// Change the memory limit (CONFIG SET maxmemory)
+    pub fn set_max_memory(&self, bytes: usize) {
+        self.memory_manager.set_max_memory(bytes);
+    }
+    
     /// Delete a key
     pub fn delete(&self, db: DatabaseIndex, key: &[u8]) -> Result<bool> {
         let shard = self.get_shard(db, key)?;
         let mut shard_guard = shard.write().unwrap();
         
-        if let Some(stored_value) = shard_guard.data.remove(key) {
+        if shard_guard.remove_entry(key).is_some() {
             shard_guard.mark_modified(key);
-            shard_guard.expiring_keys.remove(key);
-            
-            // Update memory usage
-            let memory_size = self.calculate_value_size(key, &stored_value.value);
-            self.memory_manager.remove_memory(memory_size);
-            
             Ok(true)
         } else {
             Ok(false)
@@ -353,13 +665,40 @@ impl StorageEngine {
         
         if let Some(stored_value) = shard_guard.data.get_mut(key) {
             stored_value.metadata.set_expiration(expires_in);
-            shard_guard.expiring_keys.insert(key.to_vec(), Instant::now() + expires_in);
+            let expires_at = stored_value.metadata.expires_at;
+            shard_guard.set_expiry(key, expires_at);
             Ok(true)
         } else {
             Ok(false)
         }
     }
     
+    /// Set an absolute expiration on a key when every condition allows it.
+    /// A deadline that already passed deletes the key, as Redis does.
+    pub fn expire_at(&self, db: DatabaseIndex, key: &[u8], expires_at: Instant, conditions: &[ExpireCondition]) -> Result<bool> {
+        let shard = self.get_shard(db, key)?;
+        let mut shard_guard = shard.write().unwrap();
+        
+        let current = match shard_guard.data.get(key) {
+            Some(stored_value) if !stored_value.is_expired() => stored_value.metadata.expires_at,
+            _ => return Ok(false),
+        };
+        if !conditions.iter().all(|condition| condition.allows(current, expires_at)) {
+            return Ok(false);
+        }
+        
+        if expires_at <= Instant::now() {
+            drop(shard_guard);
+            return self.delete(db, key);
+        }
+        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+            stored_value.metadata.expires_at = Some(expires_at);
+        }
+        shard_guard.set_expiry(key, Some(expires_at));
+        shard_guard.mark_modified(key);
+        Ok(true)
+    }
+    
     /// Get time to live for a key - optimized read path, no access time tracking
     pub fn ttl(&self, db: DatabaseIndex, key: &[u8]) -> Result<Option<Duration>> {
         let shard = self.get_shard(db, key)?;
@@ -398,7 +737,9 @@ impl StorageEngine {
                     // Check for overflow before performing the operation (Redis compliance)
                     match current.checked_add(increment) {
                         Some(new_val) => {
+                            let before = stored_value.value.memory_usage(0);
                             stored_value.value = Value::integer(new_val);
+                            self.memory_manager.resize_memory(before, stored_value.value.memory_usage(0));
                             drop(stored_value); // Release mutable borrow
                             shard_guard.mark_modified(&key);
                             new_val
@@ -417,20 +758,46 @@ impl StorageEngine {
             // Create new key with increment value
             let new_val = increment;
             let stored_value = StoredValue::new(Value::integer(new_val));
-            let memory_size = self.calculate_value_size(&key, &stored_value.value);
-            
-            if !self.memory_manager.add_memory(memory_size) {
-                return Err(StorageError::OutOfMemory.into());
-            }
-            
-            shard_guard.data.insert(key.clone(), stored_value);
             shard_guard.mark_modified(&key);
+            shard_guard.insert_entry(key, stored_value);
             new_val
         };
         
         Ok(new_value)
     }
     
+    /// Increment a string value by a float (INCRBYFLOAT), keeping its TTL
+    ///
+    /// Returns the new value as stored, in the format of [`format_float`].
+    pub fn incr_by_float(&self, db: DatabaseIndex, key: Key, increment: f64) -> Result<Vec<u8>> {
+        let shard = self.get_shard(db, &key)?;
+        let mut shard_guard = shard.write().unwrap();
+        
+        let current = match shard_guard.data.get(&key) {
+            Some(stored_value) => match &stored_value.value {
+                Value::String(bytes) => parse_float(bytes).ok_or(FerrousError::Command(CommandError::NotFloat))?,
+                _ => return Err(StorageError::WrongType.into()),
+            },
+            None => 0.0,
+        };
+        let new_value = current + increment;
+        if !new_value.is_finite() {
+            return Err(FerrousError::Command(CommandError::NanOrInfinity));
+        }
+        let formatted = format_float(new_value).into_bytes();
+        
+        if let Some(stored_value) = shard_guard.data.get_mut(&key) {
+            let before = stored_value.value.memory_usage(0);
+            stored_value.value = Value::string(formatted.clone());
+            self.memory_manager.resize_memory(before, stored_value.value.memory_usage(0));
+        } else {
+            shard_guard.insert_entry(key.clone(), StoredValue::new(Value::string(formatted.clone())));
+        }
+        shard_guard.mark_modified(&key);
+        
+        Ok(formatted)
+    }
+    
     /// Get all keys from a database (for RDB persistence)
     pub fn get_all_keys(&self, db: DatabaseIndex) -> Result<Vec<Key>> {
         let database = self.databases.get(db).ok_or(StorageError::InvalidDatabase)?;
@@ -447,6 +814,261 @@ impl StorageEngine {
         Ok(all_keys)
     }
     
+    /// Take a snapshot of every database at a single point in time
+    ///
+    /// Locks all shards at once, in the address order cross-shard writes lock
+    /// them in, so no write lands halfway through, but only to start keeping
+    /// old values: nothing is copied here.
+    pub fn snapshot(&self) -> Snapshot {
+        let id = NEXT_SNAPSHOT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let mut shards: Vec<&Arc<RwLock<DatabaseShard>>> = self.databases.iter()
+            .flat_map(|database| database.shards.iter())
+            .collect();
+        shards.sort_by_key(|shard| Arc::as_ptr(shard) as usize);
+        let mut guards: Vec<_> = shards.into_iter().map(|shard| shard.write().unwrap()).collect();
+        for shard_guard in &mut guards {
+            shard_guard.data.preserve_for(id);
+        }
+        let taken_at = Instant::now();
+        drop(guards);
+        
+        Snapshot {
+            id,
+            taken_at,
+            databases: self.databases.iter().map(|database| database.shards.clone()).collect(),
+        }
+    }
+    
+    /// Number of keys in a database that have not expired
+    pub fn dbsize(&self, db: DatabaseIndex) -> Result<usize> {
+        let database = self.databases.get(db).ok_or(StorageError::InvalidDatabase)?;
+        Ok(database.shards.iter()
+            .map(|shard| shard.read().unwrap().data.values().filter(|stored_value| !stored_value.is_expired()).count())
+            .sum())
+    }
+    
+    /// Keys and keys with a TTL in a database, for INFO keyspace
+    ///
+    /// Counts come from the shard maps without looking at each key, so keys
+    /// that expired but were not removed yet are included, as in Redis.
+    pub fn keyspace_counts(&self, db: DatabaseIndex) -> Result<(usize, usize)> {
+        let database = self.databases.get(db).ok_or(StorageError::InvalidDatabase)?;
+        Ok(database.shards.iter()
+            .map(|shard| {
+                let shard_guard = shard.read().unwrap();
+                (shard_guard.data.len(), shard_guard.expiring_keys.len())
+            })
+            .fold((0, 0), |(keys, expires), (k, e)| (keys + k, expires + e)))
+    }
+    
+    /// Keys removed by active expiration since startup
+    pub fn expired_keys(&self) -> u64 {
+        self.expired_keys.load(std::sync::atomic::Ordering::Relaxed)
+    }
+    
+    /// Keys evicted by the maxmemory policy since startup
+    pub fn evicted_keys(&self) -> u64 {
+        self.evicted_keys.load(std::sync::atomic::Ordering::Relaxed)
+    }
+    
+    /// Keys sampled per eviction round (maxmemory-samples)
+    pub fn eviction_samples(&self) -> usize {
+        self.memory_manager.samples()
+    }
+    
+    /// Change the keys sampled per eviction round (CONFIG SET maxmemory-samples)
+    pub fn set_eviction_samples(&self, samples: usize) {
+        self.memory_manager.set_samples(samples);
+    }
+    
+    /// Change how fast LFU counters saturate (CONFIG SET lfu-log-factor)
+    pub fn set_lfu_log_factor(&self, log_factor: u32) {
+        self.memory_manager.set_lfu_log_factor(log_factor);
+    }
+    
+    /// Change the idle minutes per LFU counter decrement (CONFIG SET lfu-decay-time)
+    pub fn set_lfu_decay_time(&self, minutes: u64) {
+        self.memory_manager.set_lfu_decay_time(minutes);
+    }
+    
+    /// Change the sizes past which collections leave their compact encoding
+    /// (CONFIG SET hash-max-listpack-entries and friends)
+    pub fn set_encoding_limits(&self, limits: EncodingLimits) {
+        self.memory_manager.set_encoding_limits(limits);
+    }
+    
+    /// Evict keys until memory is back under maxmemory, as the policy allows
+    ///
+    /// Victims are picked the way Redis approximates LRU, LFU and TTL order:
+    /// every round samples maxmemory-samples keys of each database into a
+    /// small pool of the best candidates seen so far, and evicts the best one.
+    /// Random policies rank samples randomly. Stops early when the policy
+    /// leaves nothing to evict, in which case memory stays over the limit.
+    pub fn evict(&self) -> Eviction {
+        let mut eviction = Eviction::default();
+        let policy = self.memory_manager.policy();
+        let mut pool = Vec::with_capacity(EVICTION_POOL_SIZE + 1);
+        
+        while self.memory_manager.bytes_to_free() > 0 {
+            if matches!(policy, EvictionPolicy::NoEviction) {
+                return eviction;
+            }
+            
+            let samples = self.memory_manager.samples();
+            for db in 0..self.databases.len() {
+                self.sample_eviction_candidates(db, policy, samples, &mut pool);
+            }
+            let Some(candidate) = pool.pop() else {
+                return eviction;
+            };
+            
+            if self.evict_key(candidate.db, &candidate.key, policy) {
+                eviction.keys.push((candidate.db, candidate.key));
+            }
+        }
+        
+        eviction.within_limit = true;
+        eviction
+    }
+    
+    /// Add up to `samples` keys of a database to the eviction pool
+    ///
+    /// Samples are runs of consecutive keys from a random point of each shard,
+    /// walking shards from a random one until enough were taken. Volatile
+    /// policies sample the expires tables instead, as only keys with a TTL
+    /// may go.
+    fn sample_eviction_candidates(&self, db: DatabaseIndex, policy: EvictionPolicy, samples: usize, pool: &mut Vec<EvictionCandidate>) {
+        let mut rng = rand::thread_rng();
+        let shards = &self.databases[db].shards;
+        let first = rng.gen_range(0..shards.len());
+        let now = Instant::now();
+        let lfu = self.memory_manager.lfu_params();
+        let mut remaining = samples;
+        
+        for offset in 0..shards.len() {
+            if remaining == 0 {
+                return;
+            }
+            let shard_guard = shards[(first + offset) % shards.len()].read().unwrap();
+            let len = if policy.is_volatile() { shard_guard.expiring_keys.len() } else { shard_guard.data.len() };
+            if len == 0 {
+                continue;
+            }
+            
+            let start = rng.gen_range(0..len);
+            let count = remaining.min(len);
+            remaining -= count;
+            let keys: Vec<&Key> = if policy.is_volatile() {
+                shard_guard.expiring_keys.keys().chain(shard_guard.expiring_keys.keys()).skip(start).take(count).collect()
+            } else {
+                shard_guard.data.keys().chain(shard_guard.data.keys()).skip(start).take(count).collect()
+            };
+            
+            for key in keys {
+                let Some(stored_value) = shard_guard.data.get(key) else { continue };
+                let metadata = &stored_value.metadata;
+                let idle = metadata.last_accessed.idle_time();
+                let score = match policy {
+                    EvictionPolicy::AllKeysLru | EvictionPolicy::VolatileLru => idle.as_millis() as u64,
+                    EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu => (u8::MAX - metadata.access_frequency.get(idle, lfu)) as u64,
+                    EvictionPolicy::VolatileTtl => {
+                        let ttl = metadata.expires_at.map_or(Duration::MAX, |expires_at| expires_at.saturating_duration_since(now));
+                        u64::MAX - ttl.as_millis().min(u64::MAX as u128) as u64
+                    }
+                    EvictionPolicy::AllKeysRandom | EvictionPolicy::VolatileRandom => rng.gen(),
+                    EvictionPolicy::NoEviction => return,
+                };
+                
+                if pool.iter().any(|candidate| candidate.db == db && candidate.key == *key) {
+                    continue;
+                }
+                let position = pool.partition_point(|candidate| candidate.score <= score);
+                pool.insert(position, EvictionCandidate { db, key: key.clone(), score });
+                if pool.len() > EVICTION_POOL_SIZE {
+                    // Drop the worst candidate
+                    pool.remove(0);
+                }
+            }
+        }
+    }
+    
+    /// Evict one key, unless it went away or lost its TTL since it was sampled
+    fn evict_key(&self, db: DatabaseIndex, key: &[u8], policy: EvictionPolicy) -> bool {
+        let Ok(shard) = self.get_shard(db, key) else { return false };
+        let mut shard_guard = shard.write().unwrap();
+        if policy.is_volatile() && !shard_guard.expiring_keys.contains_key(key) {
+            return false;
+        }
+        if shard_guard.remove_entry(key).is_none() {
+            return false;
+        }
+        
+        shard_guard.mark_modified(key);
+        self.evicted_keys.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        crate::storage::lua_triggers::notify(db, "evicted", key.to_vec());
+        true
+    }
+    
+    /// Estimated bytes used by a key, its value and their bookkeeping (MEMORY USAGE)
+    ///
+    /// Collections are sized from `samples` of their elements, as
+    /// [`Value::memory_usage`] does. `None` if the key doesn't exist.
+    pub fn key_memory_usage(&self, db: DatabaseIndex, key: &[u8], samples: usize) -> Result<Option<usize>> {
+        let shard = self.get_shard(db, key)?;
+        let shard_guard = shard.read().unwrap();
+        let stored_value = match shard_guard.data.get(key) {
+            Some(stored_value) if !stored_value.is_expired() => stored_value,
+            _ => return Ok(None),
+        };
+        
+        let mut usage = MemoryManager::calculate_size(key)
+            + std::mem::size_of::<StoredValue>()
+            + stored_value.value.memory_usage(samples);
+        if stored_value.metadata.expires_at.is_some() {
+            usage += expiry_size(key);
+        }
+        Ok(Some(usage))
+    }
+    
+    /// Bytes allocated by a database's main and expires tables themselves,
+    /// beyond what their keys and values hold (MEMORY STATS)
+    pub fn table_overhead(&self, db: DatabaseIndex) -> Result<(usize, usize)> {
+        // One control byte per slot next to each entry
+        let main_slot = std::mem::size_of::<(Key, StoredValue)>() + 1;
+        let expires_slot = std::mem::size_of::<(Key, Instant)>() + 1;
+        
+        let database = self.databases.get(db).ok_or(StorageError::InvalidDatabase)?;
+        Ok(database.shards.iter()
+            .map(|shard| {
+                let shard_guard = shard.read().unwrap();
+                (shard_guard.data.capacity() * main_slot, shard_guard.expiring_keys.capacity() * expires_slot)
+            })
+            .fold((0, 0), |(main, expires), (m, e)| (main + m, expires + e)))
+    }
+    
+    /// A key picked uniformly among the keys of a database that have not expired
+    pub fn random_key(&self, db: DatabaseIndex) -> Result<Option<Key>> {
+        let database = self.databases.get(db).ok_or(StorageError::InvalidDatabase)?;
+        let mut rng = rand::thread_rng();
+        let mut seen = 0usize;
+        let mut chosen = None;
+        
+        // Reservoir sampling across the shards, without collecting every key
+        for shard in &database.shards {
+            let shard_guard = shard.read().unwrap();
+            for (key, stored_value) in shard_guard.data.iter() {
+                if stored_value.is_expired() {
+                    continue;
+                }
+                seen += 1;
+                if rng.gen_range(0..seen) == 0 {
+                    chosen = Some(key.clone());
+                }
+            }
+        }
+        Ok(chosen)
+    }
+    
     /// Get database count
     pub fn database_count(&self) -> usize {
         self.databases.len()
@@ -469,11 +1091,15 @@ impl StorageEngine {
             
             // Calculate memory to free from this shard
             for (key, stored_value) in shard_guard.data.iter() {
-                total_memory_to_free += self.calculate_value_size(key, &stored_value.value);
+                total_memory_to_free += entry_size(key, &stored_value.value);
+            }
+            for key in shard_guard.expiring_keys.keys() {
+                total_memory_to_free += expiry_size(key);
             }
             
             shard_guard.data.clear();
             shard_guard.expiring_keys.clear();
+            shard_guard.volatile_hash_keys.clear();
         }
         
         self.memory_manager.remove_memory(total_memory_to_free);
@@ -491,7 +1117,9 @@ impl StorageEngine {
             Some(stored_value) => {
                 match &mut stored_value.value {
                     Value::Stream(stream) => {
+                        let before = stream.memory_usage();
                         let id = stream.add_auto(fields);
+                        self.memory_manager.resize_memory(before, stream.memory_usage());
                         shard_guard.mark_modified(&key);
                         id
                     }
@@ -503,16 +1131,9 @@ impl StorageEngine {
                 let new_stream = Stream::new();
                 let id = new_stream.add_auto(fields);
                 
-                let memory_size = self.calculate_value_size(&key, &Value::empty_stream()) +
-                                 new_stream.memory_usage();
-                
-                if !self.memory_manager.add_memory(memory_size) {
-                    return Err(StorageError::OutOfMemory.into());
-                }
-                
                 let stored_value = StoredValue::new(Value::Stream(new_stream));
-                shard_guard.data.insert(key.clone(), stored_value);
                 shard_guard.mark_modified(&key);
+                shard_guard.insert_entry(key, stored_value);
                 
                 id
             }
@@ -530,8 +1151,10 @@ impl StorageEngine {
             Some(stored_value) => {
                 match &mut stored_value.value {
                     Value::Stream(stream) => {
+                        let before = stream.memory_usage();
                         stream.add_with_id(id.clone(), fields)
                             .map_err(|e| FerrousError::Command(CommandError::Generic(e.to_string())))?;
+                        self.memory_manager.resize_memory(before, stream.memory_usage());
                         shard_guard.mark_modified(&key);
                         id
                     }
@@ -544,16 +1167,9 @@ impl StorageEngine {
                 stream.add_with_id(id.clone(), fields)
                     .map_err(|e| FerrousError::Command(CommandError::Generic(e.to_string())))?;
                 
-                let memory_size = self.calculate_value_size(&key, &Value::empty_stream()) +
-                                 stream.memory_usage();
-                
-                if !self.memory_manager.add_memory(memory_size) {
-                    return Err(StorageError::OutOfMemory.into());
-                }
-                
                 let stored_value = StoredValue::new(Value::Stream(stream));
-                shard_guard.data.insert(key.clone(), stored_value);
                 shard_guard.mark_modified(&key);
+                shard_guard.insert_entry(key, stored_value);
                 
                 id
             }
@@ -613,6 +1229,20 @@ impl StorageEngine {
         }
     }
     
+    /// ID of the last entry added to a stream, None when the key is missing
+    pub fn xlast_id(&self, db: DatabaseIndex, key: &[u8]) -> Result<Option<StreamId>> {
+        let shard = self.get_shard(db, key)?;
+        let shard_guard = shard.read().unwrap();
+        
+        match shard_guard.data.get(key) {
+            Some(stored_value) if !stored_value.is_expired() => match &stored_value.value {
+                Value::Stream(stream) => Ok(Some(stream.last_id())),
+                _ => Err(StorageError::WrongType.into()),
+            },
+            _ => Ok(None),
+        }
+    }
+    
     /// Read entries from multiple streams after specific IDs
     pub fn xread(&self, db: DatabaseIndex, keys_and_ids: Vec<(&[u8], StreamId)>, count: Option<usize>, block: Option<Duration>) 
         -> Result<Vec<(Vec<u8>, Vec<StreamEntry>)>> {
@@ -651,7 +1281,9 @@ impl StorageEngine {
         if let Some(stored_value) = shard_guard.data.get_mut(key) {
             let trimmed = match &mut stored_value.value {
                 Value::Stream(stream) => {
+                    let before = stream.memory_usage();
                     let trimmed = stream.trim_by_count(max_len);
+                    self.memory_manager.resize_memory(before, stream.memory_usage());
                     if trimmed > 0 {
                         shard_guard.mark_modified(key);
                     }
@@ -674,7 +1306,9 @@ impl StorageEngine {
         if let Some(stored_value) = shard_guard.data.get_mut(key) {
             let deleted = match &mut stored_value.value {
                 Value::Stream(stream) => {
+                    let before = stream.memory_usage();
                     let deleted = stream.delete(&ids);
+                    self.memory_manager.resize_memory(before, stream.memory_usage());
                     if deleted > 0 {
                         shard_guard.mark_modified(key);
                     }
@@ -688,7 +1322,7 @@ impl StorageEngine {
             Ok(0)
         }
     }
-
+    
     /// Create a consumer group for a stream (basic implementation)
     pub fn stream_create_consumer_group(&self, db: DatabaseIndex, key: &[u8], group_name: String, start_id: StreamId) -> Result<()> {
         let shard = self.get_shard(db, key)?;
@@ -706,48 +1340,19 @@ impl StorageEngine {
             Ok(())
         }
     }
-
-    /// Calculate memory size for a key-value pair
-    fn calculate_value_size(&self, key: &[u8], value: &Value) -> usize {
-        let key_size = MemoryManager::calculate_size(key);
-        let value_size = match value {
-            Value::String(bytes) => MemoryManager::calculate_size(bytes),
-            Value::List(list) => {
-                list.iter().map(|item| MemoryManager::calculate_size(item)).sum::<usize>()
-                    + std::mem::size_of::<VecDeque<Vec<u8>>>()
-            }
-            Value::Set(set) => {
-                set.iter().map(|item| MemoryManager::calculate_size(item)).sum::<usize>()
-                    + std::mem::size_of::<std::collections::HashSet<Vec<u8>>>()
-            }
-            Value::Hash(hash) => {
-                hash.iter().map(|(k, v)| MemoryManager::calculate_size(k) + MemoryManager::calculate_size(v)).sum::<usize>()
-                    + std::mem::size_of::<HashMap<Vec<u8>, Vec<u8>>>()
-            }
-            Value::SortedSet(skiplist) => {
-                skiplist.memory_usage()
-            }
-            Value::Stream(stream) => {
-                stream.memory_usage()
-            }
-        };
-        key_size + value_size
-    }
-
-    /// Calculate the memory size of a sorted set member
-    fn calculate_member_size(&self, member: &[u8]) -> usize {
-        MemoryManager::calculate_size(member) + std::mem::size_of::<f64>() + 32
-    }
-
+    
     /// Add a member with score to a sorted set - NO access time tracking
     pub fn zadd(&self, db: DatabaseIndex, key: Key, member: Vec<u8>, score: f64) -> Result<bool> {
         let shard = self.get_shard(db, &key)?;
         let mut shard_guard = shard.write().unwrap();
         
+        let limits = self.memory_manager.encoding_limits();
         let is_new = if let Some(stored_value) = shard_guard.data.get_mut(&key) {
             match &mut stored_value.value {
-                Value::SortedSet(skiplist) => {
-                    let old_score = skiplist.insert(member.clone(), score);
+                Value::SortedSet(zset) => {
+                    let before = zset.memory_usage();
+                    let old_score = zset.insert(member, score, limits);
+                    self.memory_manager.resize_memory(before, zset.memory_usage());
                     // NO touch() call - no access time tracking overhead
                     shard_guard.mark_modified(&key);
                     old_score.is_none()
@@ -756,19 +1361,12 @@ impl StorageEngine {
             }
         } else {
             // Create a new sorted set
-            let skiplist = SkipList::new();
-            skiplist.insert(member.clone(), score);
-            
-            let memory_size = self.calculate_value_size(&key, &Value::empty_sorted_set()) +
-                             self.calculate_member_size(&member);
-            
-            if !self.memory_manager.add_memory(memory_size) {
-                return Err(StorageError::OutOfMemory.into());
-            }
+            let mut zset = ZSetValue::new();
+            zset.insert(member, score, limits);
             
-            let stored_value = StoredValue::new(Value::SortedSet(Arc::new(skiplist)));
-            shard_guard.data.insert(key.clone(), stored_value);
+            let stored_value = StoredValue::new(Value::SortedSet(zset));
             shard_guard.mark_modified(&key);
+            shard_guard.insert_entry(key, stored_value);
             true
         };
         
@@ -781,20 +1379,20 @@ impl StorageEngine {
         
         if let Some(stored_value) = shard_guard.data.get_mut(key) {
             let (removed, is_empty) = match &mut stored_value.value {
-                Value::SortedSet(skiplist) => {
-                    let removed = skiplist.remove(member).is_some();
-                    (removed, skiplist.is_empty())
+                Value::SortedSet(zset) => {
+                    let before = zset.memory_usage();
+                    let removed = zset.remove(member).is_some();
+                    self.memory_manager.resize_memory(before, zset.memory_usage());
+                    (removed, zset.is_empty())
                 }
                 _ => return Err(StorageError::WrongType.into()),
             };
             
             if removed {
                 shard_guard.mark_modified(key);
-                let member_size = self.calculate_member_size(member);
-                self.memory_manager.remove_memory(member_size);
                 
                 if is_empty {
-                    shard_guard.data.remove(key);
+                    shard_guard.remove_entry(key);
                 } 
                 // NO else branch with touch() - no access time tracking overhead
             }
@@ -807,11 +1405,11 @@ impl StorageEngine {
     
     pub fn zscore(&self, db: DatabaseIndex, key: &[u8], member: &[u8]) -> Result<Option<f64>> {
         let shard = self.get_shard(db, key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let score = match &stored_value.value {
-                Value::SortedSet(skiplist) => skiplist.get_score(member),
+                Value::SortedSet(zset) => zset.get_score(member),
                 _ => return Err(StorageError::WrongType.into()),
             };
             
@@ -824,16 +1422,16 @@ impl StorageEngine {
     
     pub fn zrank(&self, db: DatabaseIndex, key: &[u8], member: &[u8], reverse: bool) -> Result<Option<usize>> {
         let shard = self.get_shard(db, key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let result = match &stored_value.value {
-                Value::SortedSet(skiplist) => {
-                    let rank = skiplist.get_rank(member);
+                Value::SortedSet(zset) => {
+                    let rank = zset.get_rank(member);
                     
                     if let Some(rank) = rank {
                         if reverse {
-                            Some(skiplist.len() - 1 - rank)
+                            Some(zset.len() - 1 - rank)
                         } else {
                             Some(rank)
                         }
@@ -854,12 +1452,12 @@ impl StorageEngine {
     pub fn zrange(&self, db: DatabaseIndex, key: &[u8], start: isize, stop: isize, reverse: bool) 
         -> Result<Vec<(Vec<u8>, f64)>> {
         let shard = self.get_shard(db, key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let result = match &stored_value.value {
-                Value::SortedSet(skiplist) => {
-                    let len = skiplist.len();
+                Value::SortedSet(zset) => {
+                    let len = zset.len();
                     if len == 0 {
                         Vec::new()
                     } else {
@@ -879,8 +1477,7 @@ impl StorageEngine {
                             let real_start = len.saturating_sub(1).saturating_sub(stop_idx.min(len.saturating_sub(1)));
                             let real_stop = len.saturating_sub(1).saturating_sub(start_idx.min(len.saturating_sub(1)));
                             
-                            let range = skiplist.range_by_rank(real_start, real_stop);
-                            let mut items = range.items;
+                            let mut items = zset.range_by_rank(real_start, real_stop);
                             items.reverse();
                             items
                         } else {
@@ -890,8 +1487,7 @@ impl StorageEngine {
                                 let start_idx = start_idx.min(len - 1);
                                 let stop_idx = stop_idx.min(len - 1);
                                 
-                                let range = skiplist.range_by_rank(start_idx, stop_idx);
-                                range.items
+                                zset.range_by_rank(start_idx, stop_idx)
                             }
                         }
                     }
@@ -909,13 +1505,12 @@ impl StorageEngine {
     pub fn zrangebyscore(&self, db: DatabaseIndex, key: &[u8], min_score: f64, max_score: f64, reverse: bool) 
         -> Result<Vec<(Vec<u8>, f64)>> {
         let shard = self.get_shard(db, key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let result = match &stored_value.value {
-                Value::SortedSet(skiplist) => {
-                    let range = skiplist.range_by_score(min_score, max_score);
-                    let mut items = range.items;
+                Value::SortedSet(zset) => {
+                    let mut items = zset.range_by_score(min_score, max_score);
                     
                     if reverse {
                         items.reverse();
@@ -933,6 +1528,25 @@ impl StorageEngine {
         }
     }
     
+    /// Members between two lex bounds (ZRANGEBYLEX), assuming they share one score
+    pub fn zrangebylex(&self, db: DatabaseIndex, key: &[u8], min: Bound<&[u8]>, max: Bound<&[u8]>, reverse: bool)
+        -> Result<Vec<(Vec<u8>, f64)>> {
+        let shard = self.get_shard(db, key)?;
+        let shard_guard = shard.read().unwrap();
+        
+        match shard_guard.data.get(key).map(|stored_value| &stored_value.value) {
+            Some(Value::SortedSet(zset)) => {
+                let mut items = zset.range_by_key(min, max);
+                if reverse {
+                    items.reverse();
+                }
+                Ok(items)
+            }
+            Some(_) => Err(StorageError::WrongType.into()),
+            None => Ok(Vec::new()),
+        }
+    }
+    
     pub fn zcount(&self, db: DatabaseIndex, key: &[u8], min_score: f64, max_score: f64) -> Result<usize> {
         let members = self.zrangebyscore(db, key, min_score, max_score, false)?;
         Ok(members.len())
@@ -942,15 +1556,18 @@ impl StorageEngine {
         let shard = self.get_shard(db, &key)?;
         let mut shard_guard = shard.write().unwrap();
         
+        let limits = self.memory_manager.encoding_limits();
         let new_score = if let Some(stored_value) = shard_guard.data.get_mut(&key) {
             match &mut stored_value.value {
-                Value::SortedSet(skiplist) => {
-                    let new_score = match skiplist.get_score(&member) {
+                Value::SortedSet(zset) => {
+                    let new_score = match zset.get_score(&member) {
                         Some(curr_score) => curr_score + increment,
                         None => increment,
                     };
                     
-                    skiplist.insert(member, new_score);
+                    let before = zset.memory_usage();
+                    zset.insert(member, new_score, limits);
+                    self.memory_manager.resize_memory(before, zset.memory_usage());
                     shard_guard.mark_modified(&key);
                     // NO touch() call - no access time tracking overhead
                     new_score
@@ -958,34 +1575,27 @@ impl StorageEngine {
                 _ => return Err(StorageError::WrongType.into()),
             }
         } else {
-            let skiplist = SkipList::new();
-            skiplist.insert(member.clone(), increment);
-            
-            let memory_size = self.calculate_value_size(&key, &Value::empty_sorted_set()) +
-                             self.calculate_member_size(&member);
-            
-            if !self.memory_manager.add_memory(memory_size) {
-                return Err(StorageError::OutOfMemory.into());
-            }
+            let mut zset = ZSetValue::new();
+            zset.insert(member, increment, limits);
             
-            let stored_value = StoredValue::new(Value::SortedSet(Arc::new(skiplist)));
-            shard_guard.data.insert(key.clone(), stored_value);
+            let stored_value = StoredValue::new(Value::SortedSet(zset));
             shard_guard.mark_modified(&key);
+            shard_guard.insert_entry(key, stored_value);
             
             increment
         };
         
         Ok(new_score)
     }
-
+    
     /// Get the cardinality (number of elements) of a sorted set
     pub fn zcard(&self, db: DatabaseIndex, key: &[u8]) -> Result<usize> {
         let shard = self.get_shard(db, key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let cardinality = match &stored_value.value {
-                Value::SortedSet(skiplist) => skiplist.len(),
+                Value::SortedSet(zset) => zset.len(),
                 _ => return Err(StorageError::WrongType.into()),
             };
             Ok(cardinality)
@@ -993,18 +1603,38 @@ impl StorageEngine {
             Ok(0)
         }
     }
-
+    
+    /// Replace a key with a sorted set of `members`, deleting it when there are none
+    pub fn zstore(&self, db: DatabaseIndex, key: Key, members: Vec<(Vec<u8>, f64)>) -> Result<usize> {
+        self.delete(db, &key)?;
+        if members.is_empty() {
+            return Ok(0);
+        }
+        
+        let limits = self.memory_manager.encoding_limits();
+        let mut zset = ZSetValue::new();
+        for (member, score) in members {
+            zset.insert(member, score, limits);
+        }
+        let len = zset.len();
+        self.set_value(db, key, Value::SortedSet(zset), None)?;
+        Ok(len)
+    }
+    
     /// Push elements to the head of a list - NO access time tracking
     pub fn lpush(&self, db: DatabaseIndex, key: Key, elements: Vec<Vec<u8>>) -> Result<usize> {
         let shard = self.get_shard(db, &key)?;
         let mut shard_guard = shard.write().unwrap();
         
+        let limits = self.memory_manager.encoding_limits();
         let list_len = if let Some(stored_value) = shard_guard.data.get_mut(&key) {
             match &mut stored_value.value {
                 Value::List(list) => {
+                    let before = list.memory_usage(0);
                     for element in elements {
-                        list.push_front(element);
+                        list.push(ListEnd::Left, element, limits);
                     }
+                    self.memory_manager.resize_memory(before, list.memory_usage(0));
                     let len = list.len();
                     drop(stored_value);
                     shard_guard.mark_modified(&key);
@@ -1014,15 +1644,15 @@ impl StorageEngine {
             }
         } else {
             // Create new list
-            let mut list = VecDeque::new();
+            let mut list = ListValue::new();
             for element in elements {
-                list.push_front(element);
+                list.push(ListEnd::Left, element, limits);
             }
             let len = list.len();
             
             let stored_value = StoredValue::new(Value::List(list));
-            shard_guard.data.insert(key.clone(), stored_value);
             shard_guard.mark_modified(&key);
+            shard_guard.insert_entry(key, stored_value);
             len
         };
         
@@ -1033,12 +1663,15 @@ impl StorageEngine {
         let shard = self.get_shard(db, &key)?;
         let mut shard_guard = shard.write().unwrap();
         
+        let limits = self.memory_manager.encoding_limits();
         let list_len = if let Some(stored_value) = shard_guard.data.get_mut(&key) {
             match &mut stored_value.value {
                 Value::List(list) => {
+                    let before = list.memory_usage(0);
                     for element in elements {
-                        list.push_back(element);
+                        list.push(ListEnd::Right, element, limits);
                     }
+                    self.memory_manager.resize_memory(before, list.memory_usage(0));
                     let len = list.len();
                     drop(stored_value);
                     shard_guard.mark_modified(&key);
@@ -1048,15 +1681,15 @@ impl StorageEngine {
             }
         } else {
             // Create new list
-            let mut list = VecDeque::new();
+            let mut list = ListValue::new();
             for element in elements {
-                list.push_back(element);
+                list.push(ListEnd::Right, element, limits);
             }
             let len = list.len();
             
             let stored_value = StoredValue::new(Value::List(list));
-            shard_guard.data.insert(key.clone(), stored_value);
             shard_guard.mark_modified(&key);
+            shard_guard.insert_entry(key, stored_value);
             len
         };
         
@@ -1070,7 +1703,9 @@ impl StorageEngine {
         if let Some(stored_value) = shard_guard.data.get_mut(key) {
             match &mut stored_value.value {
                 Value::List(list) => {
-                    let element = list.pop_front();
+                    let before = list.memory_usage(0);
+                    let element = list.pop(ListEnd::Left);
+                    self.memory_manager.resize_memory(before, list.memory_usage(0));
                     let is_empty = list.is_empty();
                     drop(stored_value);
                     
@@ -1079,7 +1714,7 @@ impl StorageEngine {
                     }
                     
                     if is_empty {
-                        shard_guard.data.remove(key);
+                        shard_guard.remove_entry(key);
                     }
                     
                     Ok(element)
@@ -1098,7 +1733,9 @@ impl StorageEngine {
         if let Some(stored_value) = shard_guard.data.get_mut(key) {
             match &mut stored_value.value {
                 Value::List(list) => {
-                    let element = list.pop_back();
+                    let before = list.memory_usage(0);
+                    let element = list.pop(ListEnd::Right);
+                    self.memory_manager.resize_memory(before, list.memory_usage(0));
                     let is_empty = list.is_empty();
                     drop(stored_value);
                     
@@ -1107,7 +1744,7 @@ impl StorageEngine {
                     }
                     
                     if is_empty {
-                        shard_guard.data.remove(key);
+                        shard_guard.remove_entry(key);
                     }
                     
                     Ok(element)
@@ -1121,9 +1758,9 @@ impl StorageEngine {
     
     pub fn llen(&self, db: DatabaseIndex, key: &[u8]) -> Result<usize> {
         let shard = self.get_shard(db, key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let len = match &stored_value.value {
                 Value::List(list) => list.len(),
                 _ => return Err(StorageError::WrongType.into()),
@@ -1137,9 +1774,9 @@ impl StorageEngine {
     
     pub fn lrange(&self, db: DatabaseIndex, key: &[u8], start: isize, stop: isize) -> Result<Vec<Vec<u8>>> {
         let shard = self.get_shard(db, key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let result = match &stored_value.value {
                 Value::List(list) => {
                     let len = list.len() as isize;
@@ -1147,16 +1784,11 @@ impl StorageEngine {
                     let start = if start < 0 { (len + start).max(0) } else { start } as usize;
                     let stop = if stop < 0 { (len + stop).max(0) } else { stop } as usize;
                     
-                    let mut result = Vec::new();
-                    for (i, item) in list.iter().enumerate() {
-                        if i >= start && i <= stop {
-                            result.push(item.clone());
-                        }
-                        if i > stop {
-                            break;
-                        }
-                    }
-                    result
+                    list.iter()
+                        .skip(start)
+                        .take((stop + 1).saturating_sub(start))
+                        .map(<[u8]>::to_vec)
+                        .collect()
                 }
                 _ => return Err(StorageError::WrongType.into()),
             };
@@ -1169,32 +1801,113 @@ impl StorageEngine {
     
     pub fn lindex(&self, db: DatabaseIndex, key: &[u8], index: isize) -> Result<Option<Vec<u8>>> {
         let shard = self.get_shard(db, key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let result = match &stored_value.value {
                 Value::List(list) => {
                     let len = list.len() as isize;
                     let idx = if index < 0 { len + index } else { index };
                     
                     if idx >= 0 && idx < len {
-                        list.get(idx as usize).cloned()
+                        list.get(idx as usize).map(<[u8]>::to_vec)
                     } else {
                         None
                     }
                 }
                 _ => return Err(StorageError::WrongType.into()),
-            };
-            // NO touch() call - no access time tracking overhead
-            Ok(result)
-        } else {
-            Ok(None)
+            };
+            // NO touch() call - no access time tracking overhead
+            Ok(result)
+        } else {
+            Ok(None)
+        }
+    }
+    
+    /// Positions of `element` in a list (LPOS)
+    ///
+    /// A negative `rank` scans from the tail and skips the first `|rank| - 1`
+    /// matches; `count` 0 returns every match and `maxlen` 0 compares every element.
+    pub fn lpos(&self, db: DatabaseIndex, key: &[u8], element: &[u8], rank: i64, count: usize, maxlen: usize) -> Result<Vec<usize>> {
+        let shard = self.get_shard(db, key)?;
+        let shard_guard = shard.read().unwrap();
+        
+        let list = match shard_guard.data.get(key) {
+            Some(stored_value) if !stored_value.is_expired() => match &stored_value.value {
+                Value::List(list) => list,
+                _ => return Err(StorageError::WrongType.into()),
+            },
+            _ => return Ok(Vec::new()),
+        };
+        
+        let elements: Vec<&[u8]> = list.iter().collect();
+        let scanned = if maxlen == 0 { elements.len() } else { maxlen.min(elements.len()) };
+        let limit = if count == 0 { usize::MAX } else { count };
+        Ok((0..scanned)
+            .map(|offset| if rank < 0 { elements.len() - 1 - offset } else { offset })
+            .filter(|&index| elements[index] == element)
+            .skip(rank.unsigned_abs() as usize - 1)
+            .take(limit)
+            .collect())
+    }
+    
+    /// Pop up to `count` elements from one end of a list (LMPOP)
+    pub fn list_pop_count(&self, db: DatabaseIndex, key: &[u8], end: ListEnd, count: usize) -> Result<Vec<Vec<u8>>> {
+        let shard = self.get_shard(db, key)?;
+        let mut shard_guard = shard.write().unwrap();
+        
+        let (elements, is_empty) = match shard_guard.data.get_mut(key) {
+            Some(stored_value) if !stored_value.is_expired() => match &mut stored_value.value {
+                Value::List(list) => {
+                    let before = list.memory_usage(0);
+                    let elements: Vec<_> = (0..count).map_while(|_| list.pop(end)).collect();
+                    self.memory_manager.resize_memory(before, list.memory_usage(0));
+                    (elements, list.is_empty())
+                }
+                _ => return Err(StorageError::WrongType.into()),
+            },
+            _ => return Ok(Vec::new()),
+        };
+        
+        if !elements.is_empty() {
+            shard_guard.mark_modified(key);
+        }
+        if is_empty {
+            shard_guard.remove_entry(key);
+        }
+        Ok(elements)
+    }
+    
+    /// Atomically pop an element from one end of `source` and push it to one end
+    /// of `destination` (LMOVE, RPOPLPUSH)
+    ///
+    /// Nothing moves when the source is missing, and a destination holding
+    /// another type fails with WRONGTYPE before the source is touched.
+    pub fn lmove(&self, db: DatabaseIndex, source: &[u8], destination: Key, from: ListEnd, to: ListEnd) -> Result<Option<Vec<u8>>> {
+        let source_shard = self.get_shard(db, source)?;
+        let destination_shard = self.get_shard(db, &destination)?;
+        
+        if Arc::ptr_eq(source_shard, destination_shard) {
+            let mut shard_guard = source_shard.write().unwrap();
+            return DatabaseShard::move_list_element(&mut shard_guard, None, source, destination, from, to);
         }
+        
+        // Cross-shard move - acquire both locks in consistent order
+        let source_first = (source_shard.as_ref() as *const _) < (destination_shard.as_ref() as *const _);
+        let (mut source_guard, mut destination_guard) = if source_first {
+            let source_guard = source_shard.write().unwrap();
+            (source_guard, destination_shard.write().unwrap())
+        } else {
+            let destination_guard = destination_shard.write().unwrap();
+            (source_shard.write().unwrap(), destination_guard)
+        };
+        DatabaseShard::move_list_element(&mut source_guard, Some(&mut destination_guard), source, destination, from, to)
     }
     
     pub fn lset(&self, db: DatabaseIndex, key: Key, index: isize, value: Vec<u8>) -> Result<()> {
         let shard = self.get_shard(db, &key)?;
         let mut shard_guard = shard.write().unwrap();
+        let limits = self.memory_manager.encoding_limits();
         
         if let Some(stored_value) = shard_guard.data.get_mut(&key) {
             match &mut stored_value.value {
@@ -1203,7 +1916,9 @@ impl StorageEngine {
                     let idx = if index < 0 { len + index } else { index };
                     
                     if idx >= 0 && idx < len {
-                        list[idx as usize] = value;
+                        let before = list.memory_usage(0);
+                        list.set(idx as usize, value, limits);
+                        self.memory_manager.resize_memory(before, list.memory_usage(0));
                         shard_guard.mark_modified(&key);
                         // NO touch() call - no access time tracking overhead
                         Ok(())
@@ -1230,21 +1945,16 @@ impl StorageEngine {
                     let start = if start < 0 { (len + start).max(0) } else { start } as usize;
                     let stop = if stop < 0 { (len + stop).max(0) } else { stop } as usize;
                     
-                    let mut new_list = VecDeque::new();
-                    for (i, item) in list.iter().enumerate() {
-                        if i >= start && i <= stop {
-                            new_list.push_back(item.clone());
-                        }
-                    }
-                    
-                    *list = new_list;
+                    let before = list.memory_usage(0);
+                    list.retain(|i, _| i >= start && i <= stop);
+                    self.memory_manager.resize_memory(before, list.memory_usage(0));
                     let is_empty = list.is_empty();
                     drop(stored_value); // Release mutable borrow
                     
                     shard_guard.mark_modified(&key); // Now safe to call
                     
                     if is_empty {
-                        shard_guard.data.remove(&key);
+                        shard_guard.remove_entry(&key);
                     }
                     
                     Ok(())
@@ -1263,46 +1973,29 @@ impl StorageEngine {
         if let Some(stored_value) = shard_guard.data.get_mut(&key) {
             match &mut stored_value.value {
                 Value::List(list) => {
-                    let mut removed = 0;
+                    // Removing from the tail skips all but the last |count| matches
+                    let limit = if count == 0 { usize::MAX } else { count.unsigned_abs() };
+                    let skipped = if count < 0 {
+                        list.iter().filter(|item| *item == element.as_slice()).count().saturating_sub(limit)
+                    } else {
+                        0
+                    };
                     
-                    if count == 0 {
-                        list.retain(|item| {
-                            if item == &element {
-                                removed += 1;
-                                false
-                            } else {
-                                true
-                            }
-                        });
-                    } else if count > 0 {
-                        let mut new_list = VecDeque::new();
-                        let mut to_remove = count as usize;
-                        
-                        for item in list.drain(..) {
-                            if item == element && to_remove > 0 {
-                                to_remove -= 1;
-                                removed += 1;
-                            } else {
-                                new_list.push_back(item);
-                            }
+                    let before = list.memory_usage(0);
+                    let mut matches = 0;
+                    let mut removed = 0;
+                    list.retain(|_, item| {
+                        if item != element.as_slice() {
+                            return true;
                         }
-                        
-                        *list = new_list;
-                    } else {
-                        let mut new_list = VecDeque::new();
-                        let mut to_remove = (-count) as usize;
-                        
-                        for item in list.drain(..).rev() {
-                            if item == element && to_remove > 0 {
-                                to_remove -= 1;
-                                removed += 1;
-                            } else {
-                                new_list.push_front(item);
-                            }
+                        matches += 1;
+                        if matches <= skipped || removed == limit {
+                            return true;
                         }
-                        
-                        *list = new_list;
-                    }
+                        removed += 1;
+                        false
+                    });
+                    self.memory_manager.resize_memory(before, list.memory_usage(0));
                     
                     let is_empty = list.is_empty();
                     drop(stored_value); // Release mutable borrow
@@ -1312,7 +2005,7 @@ impl StorageEngine {
                     }
                     
                     if is_empty {
-                        shard_guard.data.remove(&key);
+                        shard_guard.remove_entry(&key);
                     }
                     
                     Ok(removed)
@@ -1323,21 +2016,24 @@ impl StorageEngine {
             Ok(0)
         }
     }
-
+    
     /// Set operations - NO access time tracking
     pub fn sadd(&self, db: DatabaseIndex, key: Key, members: Vec<Vec<u8>>) -> Result<usize> {
         let shard = self.get_shard(db, &key)?;
         let mut shard_guard = shard.write().unwrap();
         
+        let limits = self.memory_manager.encoding_limits();
         let added = if let Some(stored_value) = shard_guard.data.get_mut(&key) {
             match &mut stored_value.value {
                 Value::Set(set) => {
+                    let before = set.memory_usage(0);
                     let mut added = 0;
                     for member in members {
-                        if set.insert(member) {
+                        if set.insert(member, limits) {
                             added += 1;
                         }
                     }
+                    self.memory_manager.resize_memory(before, set.memory_usage(0));
                     drop(stored_value); // Release mutable borrow
                     if added > 0 {
                         shard_guard.mark_modified(&key); // Now safe to call
@@ -1348,17 +2044,17 @@ impl StorageEngine {
             }
         } else {
             // Create new set
-            let mut set = HashSet::new();
+            let mut set = SetValue::new();
             let mut added = 0;
             for member in members {
-                if set.insert(member) {
+                if set.insert(member, limits) {
                     added += 1;
                 }
             }
             
             let stored_value = StoredValue::new(Value::Set(set));
-            shard_guard.data.insert(key.clone(), stored_value);
             shard_guard.mark_modified(&key);
+            shard_guard.insert_entry(key, stored_value);
             added
         };
         
@@ -1372,19 +2068,16 @@ impl StorageEngine {
         if let Some(stored_value) = shard_guard.data.get_mut(key) {
             match &mut stored_value.value {
                 Value::Set(set) => {
-                    let mut removed = 0;
-                    for member in members {
-                        if set.remove(member.as_ref()) {
-                            removed += 1;
-                        }
-                    }
+                    let before = set.memory_usage(0);
+                    let removed = members.iter().filter(|member| set.remove(member.as_ref())).count();
+                    self.memory_manager.resize_memory(before, set.memory_usage(0));
                     
                     let is_empty = set.is_empty();
                     drop(stored_value); // Release mutable borrow
                     
                     if is_empty {
                         shard_guard.mark_modified(key); // Mark before removal
-                        shard_guard.data.remove(key);
+                        shard_guard.remove_entry(key);
                     } else if removed > 0 {
                         shard_guard.mark_modified(key); // Now safe to call
                     }
@@ -1400,11 +2093,11 @@ impl StorageEngine {
     
     pub fn smembers(&self, db: DatabaseIndex, key: &[u8]) -> Result<Vec<Vec<u8>>> {
         let shard = self.get_shard(db, key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let members = match &stored_value.value {
-                Value::Set(set) => set.iter().cloned().collect(),
+                Value::Set(set) => set.members().collect(),
                 _ => return Err(StorageError::WrongType.into()),
             };
             // NO touch() call - no access time tracking overhead
@@ -1416,9 +2109,9 @@ impl StorageEngine {
     
     pub fn sismember(&self, db: DatabaseIndex, key: &[u8], member: &[u8]) -> Result<bool> {
         let shard = self.get_shard(db, key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let is_member = match &stored_value.value {
                 Value::Set(set) => set.contains(member),
                 _ => return Err(StorageError::WrongType.into()),
@@ -1432,9 +2125,9 @@ impl StorageEngine {
     
     pub fn scard(&self, db: DatabaseIndex, key: &[u8]) -> Result<usize> {
         let shard = self.get_shard(db, key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let len = match &stored_value.value {
                 Value::Set(set) => set.len(),
                 _ => return Err(StorageError::WrongType.into()),
@@ -1456,14 +2149,12 @@ impl StorageEngine {
         for key_ref in keys {
             let key = key_ref.as_ref();
             let shard = self.get_shard(db, key)?;
-            let mut shard_guard = shard.write().unwrap();
+            let shard_guard = shard.write().unwrap();
             
-            if let Some(stored_value) = shard_guard.data.get_mut(key) {
+            if let Some(stored_value) = shard_guard.data.get(key) {
                 match &stored_value.value {
                     Value::Set(set) => {
-                        for member in set {
-                            result.insert(member.clone());
-                        }
+                        result.extend(set.members());
                         // NO touch() call - no access time tracking overhead
                     }
                     _ => return Err(StorageError::WrongType.into()),
@@ -1482,12 +2173,12 @@ impl StorageEngine {
         // Get first set as base
         let first_key = keys[0].as_ref();
         let shard = self.get_shard(db, first_key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        let result: HashSet<Vec<u8>> = if let Some(stored_value) = shard_guard.data.get_mut(first_key) {
+        let result: HashSet<Vec<u8>> = if let Some(stored_value) = shard_guard.data.get(first_key) {
             // NO touch() call - no access time tracking overhead
             match &stored_value.value {
-                Value::Set(set) => set.iter().cloned().collect(),
+                Value::Set(set) => set.members().collect(),
                 _ => return Err(StorageError::WrongType.into()),
             }
         } else {
@@ -1500,9 +2191,9 @@ impl StorageEngine {
         for k in 1..keys.len() {
             let key = keys[k].as_ref();
             let shard = self.get_shard(db, key)?;
-            let mut shard_guard = shard.write().unwrap();
+            let shard_guard = shard.write().unwrap();
             
-            if let Some(stored_value) = shard_guard.data.get_mut(key) {
+            if let Some(stored_value) = shard_guard.data.get(key) {
                 // NO touch() call - no access time tracking overhead
                 match &stored_value.value {
                     Value::Set(set) => {
@@ -1518,6 +2209,24 @@ impl StorageEngine {
         Ok(result.into_iter().collect())
     }
     
+    /// Size of the intersection of the sets at `keys`, capped at `limit` unless it is 0
+    pub fn sintercard<T: AsRef<[u8]>>(&self, db: DatabaseIndex, keys: &[T], limit: usize) -> Result<usize> {
+        let count = self.sinter(db, keys)?.len();
+        Ok(if limit == 0 { count } else { count.min(limit) })
+    }
+    
+    /// Membership of each of `members` in the set at `key`
+    pub fn smismember<T: AsRef<[u8]>>(&self, db: DatabaseIndex, key: &[u8], members: &[T]) -> Result<Vec<bool>> {
+        let shard = self.get_shard(db, key)?;
+        let shard_guard = shard.read().unwrap();
+        
+        match shard_guard.data.get(key).map(|stored_value| &stored_value.value) {
+            Some(Value::Set(set)) => Ok(members.iter().map(|member| set.contains(member.as_ref())).collect()),
+            Some(_) => Err(StorageError::WrongType.into()),
+            None => Ok(vec![false; members.len()]),
+        }
+    }
+    
     pub fn sdiff<'a, T: AsRef<[u8]>>(&self, db: DatabaseIndex, keys: &[T]) -> Result<Vec<Vec<u8>>> {
         if keys.is_empty() {
             return Ok(Vec::new());
@@ -1526,12 +2235,12 @@ impl StorageEngine {
         // Get first set as base
         let first_key = keys[0].as_ref();
         let shard = self.get_shard(db, first_key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        let result: HashSet<Vec<u8>> = if let Some(stored_value) = shard_guard.data.get_mut(first_key) {
+        let result: HashSet<Vec<u8>> = if let Some(stored_value) = shard_guard.data.get(first_key) {
             // NO touch() call - no access time tracking overhead
             match &stored_value.value {
-                Value::Set(set) => set.iter().cloned().collect(),
+                Value::Set(set) => set.members().collect(),
                 _ => return Err(StorageError::WrongType.into()),
             }
         } else {
@@ -1544,14 +2253,14 @@ impl StorageEngine {
         for k in 1..keys.len() {
             let key = keys[k].as_ref();
             let shard = self.get_shard(db, key)?;
-            let mut shard_guard = shard.write().unwrap();
+            let shard_guard = shard.write().unwrap();
             
-            if let Some(stored_value) = shard_guard.data.get_mut(key) {
+            if let Some(stored_value) = shard_guard.data.get(key) {
                 // NO touch() call - no access time tracking overhead
                 match &stored_value.value {
                     Value::Set(set) => {
-                        for member in set {
-                            result.remove(member);
+                        for member in set.members() {
+                            result.remove(&member);
                         }
                     }
                     _ => return Err(StorageError::WrongType.into()),
@@ -1564,12 +2273,12 @@ impl StorageEngine {
     
     pub fn srandmember(&self, db: DatabaseIndex, key: &[u8], count: i64) -> Result<Vec<Vec<u8>>> {
         let shard = self.get_shard(db, key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let result = match &stored_value.value {
                 Value::Set(set) => {
-                    let members: Vec<Vec<u8>> = set.iter().cloned().collect();
+                    let members: Vec<Vec<u8>> = set.members().collect();
                     if members.is_empty() {
                         Vec::new()
                     } else {
@@ -1582,14 +2291,10 @@ impl StorageEngine {
                             result.truncate(n);
                             result
                         } else {
-                            let n = (-count) as usize;
-                            let mut result = Vec::with_capacity(n);
-                            for _ in 0..n {
-                                if let Some(member) = members.choose(&mut rng) {
-                                    result.push(member.clone());
-                                }
-                            }
-                            result
+                            // Members may repeat; the reply is sized by the count, not the set
+                            (0..count.unsigned_abs())
+                                .filter_map(|_| members.choose(&mut rng).cloned())
+                                .collect()
                         }
                     }
                 }
@@ -1609,7 +2314,7 @@ impl StorageEngine {
         if let Some(stored_value) = shard_guard.data.get_mut(&key) {
             match &mut stored_value.value {
                 Value::Set(set) => {
-                    let mut members: Vec<Vec<u8>> = set.iter().cloned().collect();
+                    let mut members: Vec<Vec<u8>> = set.members().collect();
                     if members.is_empty() {
                         return Ok(Vec::new());
                     }
@@ -1620,9 +2325,11 @@ impl StorageEngine {
                     let n = std::cmp::min(count, members.len());
                     let result: Vec<Vec<u8>> = members.drain(..n).collect();
                     
+                    let before = set.memory_usage(0);
                     for member in &result {
                         set.remove(member);
                     }
+                    self.memory_manager.resize_memory(before, set.memory_usage(0));
                     
                     let is_empty = set.is_empty();
                     drop(stored_value); // Release mutable borrow
@@ -1632,7 +2339,7 @@ impl StorageEngine {
                     }
                     
                     if is_empty {
-                        shard_guard.data.remove(&key);
+                        shard_guard.remove_entry(&key);
                     }
                     
                     Ok(result)
@@ -1643,21 +2350,25 @@ impl StorageEngine {
             Ok(Vec::new())
         }
     }
-
+    
     /// Hash operations - NO access time tracking
     pub fn hset(&self, db: DatabaseIndex, key: Key, field_values: Vec<(Vec<u8>, Vec<u8>)>) -> Result<usize> {
         let shard = self.get_shard(db, &key)?;
         let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(&key);
         
+        let limits = self.memory_manager.encoding_limits();
         let fields_added = if let Some(stored_value) = shard_guard.data.get_mut(&key) {
             match &mut stored_value.value {
                 Value::Hash(hash) => {
+                    let before = hash.memory_usage();
                     let mut added = 0;
                     for (field, value) in field_values {
-                        if hash.insert(field, value).is_none() {
+                        if hash.insert(field, value, limits).is_none() {
                             added += 1;
                         }
                     }
+                    self.memory_manager.resize_memory(before, hash.memory_usage());
                     shard_guard.mark_modified(&key);
                     // NO touch() call - no access time tracking overhead
                     added
@@ -1666,15 +2377,15 @@ impl StorageEngine {
             }
         } else {
             // Create new hash
-            let mut hash = HashMap::new();
+            let mut hash = HashValue::new();
             let len = field_values.len();
             for (field, value) in field_values {
-                hash.insert(field, value);
+                hash.insert(field, value, limits);
             }
             
             let stored_value = StoredValue::new(Value::Hash(hash));
-            shard_guard.data.insert(key.clone(), stored_value);
             shard_guard.mark_modified(&key);
+            shard_guard.insert_entry(key, stored_value);
             len
         };
         
@@ -1684,10 +2395,11 @@ impl StorageEngine {
     pub fn hget(&self, db: DatabaseIndex, key: &[u8], field: &[u8]) -> Result<Option<Vec<u8>>> {
         let shard = self.get_shard(db, key)?;
         let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(key);
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let value = match &stored_value.value {
-                Value::Hash(hash) => hash.get(field).cloned(),
+                Value::Hash(hash) => hash.get(field).map(<[u8]>::to_vec),
                 _ => return Err(StorageError::WrongType.into()),
             };
             // NO touch() call - no access time tracking overhead
@@ -1700,11 +2412,12 @@ impl StorageEngine {
     pub fn hmget<'a, T: AsRef<[u8]>>(&self, db: DatabaseIndex, key: &[u8], fields: &[T]) -> Result<Vec<Option<Vec<u8>>>> {
         let shard = self.get_shard(db, key)?;
         let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(key);
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             // NO touch() call - no access time tracking overhead
             match &stored_value.value {
-                Value::Hash(hash) => Ok(fields.iter().map(|field| hash.get(field.as_ref()).cloned()).collect()),
+                Value::Hash(hash) => Ok(fields.iter().map(|field| hash.get(field.as_ref()).map(<[u8]>::to_vec)).collect()),
                 _ => Err(StorageError::WrongType.into()),
             }
         } else {
@@ -1715,10 +2428,11 @@ impl StorageEngine {
     pub fn hgetall(&self, db: DatabaseIndex, key: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
         let shard = self.get_shard(db, key)?;
         let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(key);
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let pairs = match &stored_value.value {
-                Value::Hash(hash) => hash.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+                Value::Hash(hash) => hash.iter().map(|(k, v)| (k.to_vec(), v.to_vec())).collect(),
                 _ => return Err(StorageError::WrongType.into()),
             };
             // NO touch() call - no access time tracking overhead
@@ -1731,19 +2445,22 @@ impl StorageEngine {
     pub fn hdel<'a, T: AsRef<[u8]>>(&self, db: DatabaseIndex, key: Key, fields: &[T]) -> Result<usize> {
         let shard = self.get_shard(db, &key)?;
         let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(&key);
         
         if let Some(stored_value) = shard_guard.data.get_mut(&key) {
             match &mut stored_value.value {
                 Value::Hash(hash) => {
+                    let before = hash.memory_usage();
                     let mut deleted = 0;
                     for field in fields {
                         if hash.remove(field.as_ref()).is_some() {
                             deleted += 1;
                         }
                     }
+                    self.memory_manager.resize_memory(before, hash.memory_usage());
                     
                     if hash.is_empty() {
-                        shard_guard.data.remove(&key);
+                        shard_guard.remove_entry(&key);
                     } 
                     // NO else branch with touch() - no access time tracking overhead
                     shard_guard.mark_modified(&key);
@@ -1760,8 +2477,9 @@ impl StorageEngine {
     pub fn hlen(&self, db: DatabaseIndex, key: &[u8]) -> Result<usize> {
         let shard = self.get_shard(db, key)?;
         let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(key);
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let len = match &stored_value.value {
                 Value::Hash(hash) => hash.len(),
                 _ => return Err(StorageError::WrongType.into()),
@@ -1776,8 +2494,9 @@ impl StorageEngine {
     pub fn hexists(&self, db: DatabaseIndex, key: &[u8], field: &[u8]) -> Result<bool> {
         let shard = self.get_shard(db, key)?;
         let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(key);
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let exists = match &stored_value.value {
                 Value::Hash(hash) => hash.contains_key(field),
                 _ => return Err(StorageError::WrongType.into()),
@@ -1792,10 +2511,11 @@ impl StorageEngine {
     pub fn hkeys(&self, db: DatabaseIndex, key: &[u8]) -> Result<Vec<Vec<u8>>> {
         let shard = self.get_shard(db, key)?;
         let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(key);
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let keys = match &stored_value.value {
-                Value::Hash(hash) => hash.keys().cloned().collect(),
+                Value::Hash(hash) => hash.keys().map(<[u8]>::to_vec).collect(),
                 _ => return Err(StorageError::WrongType.into()),
             };
             // NO touch() call - no access time tracking overhead
@@ -1808,10 +2528,11 @@ impl StorageEngine {
     pub fn hvals(&self, db: DatabaseIndex, key: &[u8]) -> Result<Vec<Vec<u8>>> {
         let shard = self.get_shard(db, key)?;
         let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(key);
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let values = match &stored_value.value {
-                Value::Hash(hash) => hash.values().cloned().collect(),
+                Value::Hash(hash) => hash.values().map(<[u8]>::to_vec).collect(),
                 _ => return Err(StorageError::WrongType.into()),
             };
             // NO touch() call - no access time tracking overhead
@@ -1824,7 +2545,9 @@ impl StorageEngine {
     pub fn hincrby(&self, db: DatabaseIndex, key: Key, field: Vec<u8>, increment: i64) -> Result<i64> {
         let shard = self.get_shard(db, &key)?;
         let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(&key);
         
+        let limits = self.memory_manager.encoding_limits();
         let new_value = if let Some(stored_value) = shard_guard.data.get_mut(&key) {
             match &mut stored_value.value {
                 Value::Hash(hash) => {
@@ -1839,7 +2562,9 @@ impl StorageEngine {
                         None => increment,
                     };
                     
-                    hash.insert(field, new_val.to_string().into_bytes());
+                    let before = hash.memory_usage();
+                    hash.insert(field, new_val.to_string().into_bytes(), limits);
+                    self.memory_manager.resize_memory(before, hash.memory_usage());
                     shard_guard.mark_modified(&key);
                     // NO touch() call - no access time tracking overhead
                     new_val
@@ -1848,18 +2573,191 @@ impl StorageEngine {
             }
         } else {
             // Create new hash with single field
-            let mut hash = HashMap::new();
-            hash.insert(field, increment.to_string().into_bytes());
+            let mut hash = HashValue::new();
+            hash.insert(field, increment.to_string().into_bytes(), limits);
             
             let stored_value = StoredValue::new(Value::Hash(hash));
-            shard_guard.data.insert(key.clone(), stored_value);
             shard_guard.mark_modified(&key);
+            shard_guard.insert_entry(key, stored_value);
             increment
         };
         
         Ok(new_value)
     }
-
+    
+    /// Increment a hash field by a float (HINCRBYFLOAT)
+    ///
+    /// Returns the new value as stored, in the format of [`format_float`].
+    pub fn hincrbyfloat(&self, db: DatabaseIndex, key: Key, field: Vec<u8>, increment: f64) -> Result<Vec<u8>> {
+        let shard = self.get_shard(db, &key)?;
+        let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(&key);
+        
+        let limits = self.memory_manager.encoding_limits();
+        let current = match shard_guard.data.get(&key) {
+            Some(stored_value) => match &stored_value.value {
+                Value::Hash(hash) => match hash.get(&field) {
+                    Some(bytes) => parse_float(bytes).ok_or_else(|| {
+                        FerrousError::Command(CommandError::Generic("hash value is not a float".to_string()))
+                    })?,
+                    None => 0.0,
+                },
+                _ => return Err(StorageError::WrongType.into()),
+            },
+            None => 0.0,
+        };
+        let new_value = current + increment;
+        if !new_value.is_finite() {
+            return Err(FerrousError::Command(CommandError::NanOrInfinity));
+        }
+        let formatted = format_float(new_value).into_bytes();
+        
+        match shard_guard.data.get_mut(&key).map(|stored_value| &mut stored_value.value) {
+            Some(Value::Hash(hash)) => {
+                let before = hash.memory_usage();
+                hash.insert(field, formatted.clone(), limits);
+                self.memory_manager.resize_memory(before, hash.memory_usage());
+            }
+            _ => {
+                let mut hash = HashValue::new();
+                hash.insert(field, formatted.clone(), limits);
+                shard_guard.insert_entry(key.clone(), StoredValue::new(Value::Hash(hash)));
+            }
+        }
+        shard_guard.mark_modified(&key);
+        
+        Ok(formatted)
+    }
+    
+    /// Set a TTL on hash fields (HEXPIRE/HPEXPIRE/HEXPIREAT/HPEXPIREAT)
+    ///
+    /// Per-field results: -2 no such field, 0 condition not met, 1 TTL set,
+    /// 2 field deleted because the deadline has already passed
+    pub fn hexpire<T: AsRef<[u8]>>(&self, db: DatabaseIndex, key: &[u8], fields: &[T], expires_at: Instant, condition: Option<ExpireCondition>) -> Result<Vec<i64>> {
+        let shard = self.get_shard(db, key)?;
+        let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(key);
+        
+        let now = Instant::now();
+        let (results, changed, now_empty, volatile) = match shard_guard.data.get_mut(key) {
+            Some(stored_value) => match &mut stored_value.value {
+                Value::Hash(hash) => {
+                    let before = hash.memory_usage();
+                    let mut results = Vec::with_capacity(fields.len());
+                    let mut changed = false;
+                    for field in fields {
+                        let field = field.as_ref();
+                        if !hash.contains_key(field) {
+                            results.push(-2);
+                            continue;
+                        }
+                        
+                        if let Some(condition) = condition {
+                            if !condition.allows(hash.field_expiration(field), expires_at) {
+                                results.push(0);
+                                continue;
+                            }
+                        }
+                        
+                        changed = true;
+                        if expires_at <= now {
+                            hash.remove(field);
+                            results.push(2);
+                        } else {
+                            hash.set_field_expiration(field, expires_at);
+                            results.push(1);
+                        }
+                    }
+                    self.memory_manager.resize_memory(before, hash.memory_usage());
+                    (results, changed, hash.is_empty(), hash.has_field_ttls())
+                }
+                _ => return Err(StorageError::WrongType.into()),
+            },
+            None => return Ok(vec![-2; fields.len()]),
+        };
+        
+        if volatile {
+            shard_guard.volatile_hash_keys.insert(key.to_vec());
+        }
+        if changed {
+            shard_guard.mark_modified(key);
+        }
+        if now_empty {
+            shard_guard.remove_entry(key);
+        }
+        
+        Ok(results)
+    }
+    
+    /// Remove the TTL from hash fields (HPERSIST)
+    ///
+    /// Per-field results: -2 no such field, -1 field has no TTL, 1 TTL removed
+    pub fn hpersist<T: AsRef<[u8]>>(&self, db: DatabaseIndex, key: &[u8], fields: &[T]) -> Result<Vec<i64>> {
+        let shard = self.get_shard(db, key)?;
+        let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(key);
+        
+        let (results, changed) = match shard_guard.data.get_mut(key) {
+            Some(stored_value) => match &mut stored_value.value {
+                Value::Hash(hash) => {
+                    let before = hash.memory_usage();
+                    let mut changed = false;
+                    let results: Vec<i64> = fields.iter().map(|field| {
+                        let field = field.as_ref();
+                        if !hash.contains_key(field) {
+                            -2
+                        } else if hash.clear_field_expiration(field) {
+                            changed = true;
+                            1
+                        } else {
+                            -1
+                        }
+                    }).collect();
+                    self.memory_manager.resize_memory(before, hash.memory_usage());
+                    (results, changed)
+                }
+                _ => return Err(StorageError::WrongType.into()),
+            },
+            None => return Ok(vec![-2; fields.len()]),
+        };
+        
+        if changed {
+            shard_guard.mark_modified(key);
+        }
+        
+        Ok(results)
+    }
+    
+    /// Get the remaining TTL of hash fields in milliseconds (HTTL/HPTTL)
+    ///
+    /// Per-field results: -2 no such field, -1 field has no TTL, otherwise remaining milliseconds
+    pub fn hpttl<T: AsRef<[u8]>>(&self, db: DatabaseIndex, key: &[u8], fields: &[T]) -> Result<Vec<i64>> {
+        let shard = self.get_shard(db, key)?;
+        let mut shard_guard = shard.write().unwrap();
+        shard_guard.expire_hash_fields(key);
+        
+        match shard_guard.data.get(key) {
+            Some(stored_value) => match &stored_value.value {
+                Value::Hash(hash) => {
+                    let now = Instant::now();
+                    Ok(fields.iter().map(|field| {
+                        let field = field.as_ref();
+                        if !hash.contains_key(field) {
+                            -2
+                        } else {
+                            match hash.field_expiration(field) {
+                                Some(expires_at) => expires_at.saturating_duration_since(now).as_millis() as i64,
+                                None => -1,
+                            }
+                        }
+                    }).collect())
+                }
+                _ => Err(StorageError::WrongType.into()),
+            },
+            None => Ok(vec![-2; fields.len()]),
+        }
+    }
+    
     /// String operations - NO access time tracking
     pub fn append(&self, db: DatabaseIndex, key: Key, value: Vec<u8>) -> Result<usize> {
         let shard = self.get_shard(db, &key)?;
@@ -1868,7 +2766,10 @@ impl StorageEngine {
         let new_len = if let Some(stored_value) = shard_guard.data.get_mut(&key) {
             match &mut stored_value.value {
                 Value::String(bytes) => {
-                    bytes.extend_from_slice(&value);
+                    let before = string_memory_usage(bytes);
+                    // Copy-on-write: never mutate a shared integer buffer in place
+                    Arc::make_mut(bytes).extend_from_slice(&value);
+                    self.memory_manager.resize_memory(before, string_memory_usage(bytes));
                     let len = bytes.len();
                     // NO touch() call - no access time tracking overhead
                     shard_guard.mark_modified(&key);
@@ -1879,9 +2780,9 @@ impl StorageEngine {
         } else {
             // Create new string
             let len = value.len();
-            let stored_value = StoredValue::new(Value::String(value));
-            shard_guard.data.insert(key.clone(), stored_value);
+            let stored_value = StoredValue::new(Value::string(value));
             shard_guard.mark_modified(&key);
+            shard_guard.insert_entry(key, stored_value);
             len
         };
         
@@ -1904,9 +2805,9 @@ impl StorageEngine {
     
     pub fn getrange(&self, db: DatabaseIndex, key: &[u8], start: isize, end: isize) -> Result<Vec<u8>> {
         let shard = self.get_shard(db, key)?;
-        let mut shard_guard = shard.write().unwrap();
+        let shard_guard = shard.write().unwrap();
         
-        if let Some(stored_value) = shard_guard.data.get_mut(key) {
+        if let Some(stored_value) = shard_guard.data.get(key) {
             let substring = match &stored_value.value {
                 Value::String(bytes) => {
                     let len = bytes.len() as isize;
@@ -1939,56 +2840,208 @@ impl StorageEngine {
         }
     }
     
-    pub fn setrange(&self, db: DatabaseIndex, key: Key, offset: usize, value: Vec<u8>) -> Result<usize> {
-        let shard = self.get_shard(db, &key)?;
-        let mut shard_guard = shard.write().unwrap();
+    pub fn setrange(&self, db: DatabaseIndex, key: Key, offset: usize, value: Vec<u8>) -> Result<usize> {
+        let shard = self.get_shard(db, &key)?;
+        let mut shard_guard = shard.write().unwrap();
+        
+        let new_len = if let Some(stored_value) = shard_guard.data.get_mut(&key) {
+            match &mut stored_value.value {
+                Value::String(bytes) => {
+                    let before = string_memory_usage(bytes);
+                    let buffer = Arc::make_mut(bytes);
+                    let required_len = offset + value.len();
+                    if required_len > buffer.len() {
+                        buffer.resize(required_len, 0);
+                    }
+                    
+                    buffer[offset..offset + value.len()].copy_from_slice(&value);
+                    self.memory_manager.resize_memory(before, string_memory_usage(bytes));
+                    let len = bytes.len();
+                    
+                    // NO touch() call - no access time tracking overhead
+                    shard_guard.mark_modified(&key);
+                    len
+                }
+                _ => return Err(StorageError::WrongType.into()),
+            }
+        } else {
+            // Create new string with padding
+            let mut new_string = vec![0; offset + value.len()];
+            new_string[offset..].copy_from_slice(&value);
+            let len = new_string.len();
+            
+            let stored_value = StoredValue::new(Value::string(new_string));
+            shard_guard.mark_modified(&key);
+            shard_guard.insert_entry(key, stored_value);
+            len
+        };
+        
+        Ok(new_len)
+    }
+    
+    /// Set or clear one bit of a string value in place, returning the previous bit
+    pub fn setbit(&self, db: DatabaseIndex, key: Key, offset: usize, bit: bool) -> Result<bool> {
+        let shard = self.get_shard(db, &key)?;
+        let mut shard_guard = shard.write().unwrap();
+        
+        let (byte_index, mask) = (offset / 8, 0x80u8 >> (offset % 8));
+        let previous = if let Some(stored_value) = shard_guard.data.get_mut(&key) {
+            match &mut stored_value.value {
+                Value::String(bytes) => {
+                    let before = string_memory_usage(bytes);
+                    let buffer = Arc::make_mut(bytes);
+                    if byte_index >= buffer.len() {
+                        buffer.resize(byte_index + 1, 0);
+                    }
+                    
+                    let previous = buffer[byte_index] & mask != 0;
+                    if bit {
+                        buffer[byte_index] |= mask;
+                    } else {
+                        buffer[byte_index] &= !mask;
+                    }
+                    self.memory_manager.resize_memory(before, string_memory_usage(bytes));
+                    previous
+                }
+                _ => return Err(StorageError::WrongType.into()),
+            }
+        } else {
+            // Create a zero-padded string holding the bit
+            let mut new_string = vec![0; byte_index + 1];
+            if bit {
+                new_string[byte_index] |= mask;
+            }
+            
+            let stored_value = StoredValue::new(Value::string(new_string));
+            shard_guard.insert_entry(key.clone(), stored_value);
+            false
+        };
+        
+        shard_guard.mark_modified(&key);
+        Ok(previous)
+    }
+    
+    /// Add elements to a HyperLogLog, returning whether its estimate may have changed
+    pub fn pfadd<T: AsRef<[u8]>>(&self, db: DatabaseIndex, key: Key, elements: &[T]) -> Result<bool> {
+        let shard = self.get_shard(db, &key)?;
+        let mut shard_guard = shard.write().unwrap();
+        
+        let changed = if let Some(stored_value) = shard_guard.data.get_mut(&key) {
+            match &mut stored_value.value {
+                Value::String(bytes) => {
+                    let mut hll = HyperLogLog::decode(bytes)?;
+                    let mut changed = false;
+                    for element in elements {
+                        changed |= hll.add(element.as_ref());
+                    }
+                    if changed {
+                        let before = string_memory_usage(bytes);
+                        *bytes = Arc::new(hll.encode());
+                        self.memory_manager.resize_memory(before, string_memory_usage(bytes));
+                        shard_guard.mark_modified(&key);
+                    }
+                    changed
+                }
+                _ => return Err(StorageError::WrongType.into()),
+            }
+        } else {
+            // Creating the key counts as a change even without elements
+            let mut hll = HyperLogLog::new();
+            for element in elements {
+                hll.add(element.as_ref());
+            }
+            
+            let stored_value = StoredValue::new(Value::string(hll.encode()));
+            shard_guard.mark_modified(&key);
+            shard_guard.insert_entry(key, stored_value);
+            true
+        };
+        
+        Ok(changed)
+    }
+    
+    /// Estimate the cardinality of the union of HyperLogLogs; missing keys count as empty
+    ///
+    /// A single key's estimate is cached in its header until the next change.
+    pub fn pfcount<T: AsRef<[u8]>>(&self, db: DatabaseIndex, keys: &[T]) -> Result<u64> {
+        if let [key] = keys {
+            let key = key.as_ref();
+            let shard = self.get_shard(db, key)?;
+            let mut shard_guard = shard.write().unwrap();
+            
+            return match shard_guard.data.get_mut(key) {
+                Some(stored_value) => match &mut stored_value.value {
+                    Value::String(bytes) => {
+                        let hll = HyperLogLog::decode(bytes)?;
+                        if let Some(count) = hyperloglog::cached_count(bytes) {
+                            return Ok(count);
+                        }
+                        let count = hll.count();
+                        hyperloglog::set_cached_count(Arc::make_mut(bytes).as_mut_slice(), count);
+                        Ok(count)
+                    }
+                    _ => Err(StorageError::WrongType.into()),
+                },
+                None => Ok(0),
+            };
+        }
+        
+        let mut union = HyperLogLog::new();
+        for key in keys {
+            if let Some(hll) = self.get_hyperloglog(db, key.as_ref())? {
+                union.merge(&hll);
+            }
+        }
+        Ok(union.count())
+    }
+    
+    /// Merge HyperLogLogs into `dest`, including its own registers, as a dense HyperLogLog
+    pub fn pfmerge<T: AsRef<[u8]>>(&self, db: DatabaseIndex, dest: Key, sources: &[T]) -> Result<()> {
+        // Read everything before locking the destination, which may share a shard
+        let mut merged = self.get_hyperloglog(db, &dest)?.unwrap_or_default();
+        for source in sources {
+            if let Some(hll) = self.get_hyperloglog(db, source.as_ref())? {
+                merged.merge(&hll);
+            }
+        }
+        merged.promote();
+        let encoded = merged.encode();
         
-        let new_len = if let Some(stored_value) = shard_guard.data.get_mut(&key) {
+        let shard = self.get_shard(db, &dest)?;
+        let mut shard_guard = shard.write().unwrap();
+        if let Some(stored_value) = shard_guard.data.get_mut(&dest) {
+            // Replace the value in place so the destination keeps its TTL
             match &mut stored_value.value {
                 Value::String(bytes) => {
-                    let required_len = offset + value.len();
-                    if required_len > bytes.len() {
-                        bytes.resize(required_len, 0);
-                    }
-                    
-                    bytes[offset..offset + value.len()].copy_from_slice(&value);
-                    let len = bytes.len();
-                    
-                    // NO touch() call - no access time tracking overhead
-                    shard_guard.mark_modified(&key);
-                    len
+                    let before = string_memory_usage(bytes);
+                    *bytes = Arc::new(encoded);
+                    self.memory_manager.resize_memory(before, string_memory_usage(bytes));
                 }
                 _ => return Err(StorageError::WrongType.into()),
             }
         } else {
-            // Create new string with padding
-            let mut new_string = vec![0; offset + value.len()];
-            new_string[offset..].copy_from_slice(&value);
-            let len = new_string.len();
-            
-            let stored_value = StoredValue::new(Value::String(new_string));
-            shard_guard.data.insert(key.clone(), stored_value);
-            shard_guard.mark_modified(&key);
-            len
-        };
-        
-        Ok(new_len)
+            shard_guard.insert_entry(dest.clone(), StoredValue::new(Value::string(encoded)));
+        }
+        shard_guard.mark_modified(&dest);
+        Ok(())
+    }
+    
+    /// Decode the HyperLogLog stored at a key, if any
+    fn get_hyperloglog(&self, db: DatabaseIndex, key: &[u8]) -> Result<Option<HyperLogLog>> {
+        match self.get(db, key)? {
+            GetResult::Found(Value::String(bytes)) => HyperLogLog::decode(&bytes).map(Some),
+            GetResult::Found(_) | GetResult::WrongType => Err(StorageError::WrongType.into()),
+            GetResult::NotFound | GetResult::Expired => Ok(None),
+        }
     }
     
     pub fn key_type(&self, db: DatabaseIndex, key: &[u8]) -> Result<String> {
         let shard = self.get_shard(db, key)?;
+        Self::expire_hash_fields_before_read(shard, key);
         let shard_guard = shard.read().unwrap(); // Use read lock for type check
         
         if let Some(stored_value) = shard_guard.data.get(key) {
-            let type_name = match &stored_value.value {
-                Value::String(_) => "string",
-                Value::List(_) => "list",
-                Value::Set(_) => "set",
-                Value::Hash(_) => "hash",
-                Value::SortedSet(_) => "zset",
-                Value::Stream(_) => "stream",
-            };
-            Ok(type_name.to_string())
+            Ok(stored_value.value.value_type().name().to_string())
         } else {
             Ok("none".to_string())
         }
@@ -2002,8 +3055,9 @@ impl StorageEngine {
         if Arc::ptr_eq(old_shard, new_shard) {
             // Same shard - simple case
             let mut shard_guard = old_shard.write().unwrap();
-            if let Some(stored_value) = shard_guard.data.remove(old_key) {
-                shard_guard.data.insert(new_key.clone(), stored_value);
+            if let Some(stored_value) = shard_guard.remove_entry(old_key) {
+                shard_guard.insert_entry(new_key.clone(), stored_value);
+                shard_guard.track_volatile_hash(&new_key);
                 shard_guard.mark_modified(&new_key);
                 Ok(())
             } else {
@@ -2027,8 +3081,9 @@ impl StorageEngine {
             };
             
             // Move the value between shards
-            if let Some(stored_value) = old_guard.data.remove(old_key) {
-                new_guard.data.insert(new_key.clone(), stored_value);
+            if let Some(stored_value) = old_guard.remove_entry(old_key) {
+                new_guard.insert_entry(new_key.clone(), stored_value);
+                new_guard.track_volatile_hash(&new_key);
                 new_guard.mark_modified(&new_key);
                 Ok(())
             } else {
@@ -2084,7 +3139,7 @@ impl StorageEngine {
         if let Some(stored_value) = shard_guard.data.get_mut(key) {
             if stored_value.metadata.expires_at.is_some() {
                 stored_value.metadata.clear_expiration();
-                shard_guard.expiring_keys.remove(key);
+                shard_guard.set_expiry(key, None);
                 Ok(true)
             } else {
                 Ok(false)
@@ -2093,7 +3148,7 @@ impl StorageEngine {
             Ok(false)
         }
     }
-
+    
     /// Get the current modification counter for a key (for WATCH baseline)
     pub fn get_modification_counter(&self, db: DatabaseIndex, key: &[u8]) -> Result<u64> {
         let shard = self.get_shard(db, key)?;
@@ -2128,13 +3183,13 @@ impl StorageEngine {
         
         Ok(false)
     }
-
+    
     /// Check if a key was modified (for WATCH command) - kept for backward compatibility
     pub fn was_modified(&self, db: DatabaseIndex, key: &[u8]) -> Result<bool> {
         let _database = self.databases.get(db).ok_or(StorageError::InvalidDatabase)?;
         Ok(false)
     }
-
+    
     /// Register a WATCH on a specific key and return baseline counter
     pub fn register_watch(&self, db: DatabaseIndex, key: &[u8]) -> Result<u64> {
         let shard = self.get_shard(db, key)?;
@@ -2146,6 +3201,13 @@ impl StorageEngine {
         Ok(baseline_counter)
     }
     
+    /// Mark a key as modified for WATCH without changing it
+    pub fn mark_key_modified(&self, db: DatabaseIndex, key: &[u8]) -> Result<()> {
+        let shard = self.get_shard(db, key)?;
+        shard.read().unwrap().mark_modified(key);
+        Ok(())
+    }
+    
     /// Unregister WATCH for keys (when UNWATCH or EXEC called)
     pub fn unregister_watch(&self, db: DatabaseIndex, key: &[u8]) -> Result<()> {
         let shard = self.get_shard(db, key)?;
@@ -2155,92 +3217,78 @@ impl StorageEngine {
         
         Ok(())
     }
-
+    
     /// Get the current database ID
     pub fn get_current_db(&self) -> usize {
         0
     }
-
+    
     /// Scan operations - optimized for sharded access, NO access time tracking
+    ///
+    /// Keys are visited in order of their hash and the cursor is the hash to
+    /// resume from, so the order depends neither on how the shard tables grow or
+    /// shrink nor on which other keys exist. A key present for the whole scan is
+    /// returned at least once, and every call moves the cursor forward, so the
+    /// scan always terminates. Keys sharing a hash are returned by the same call.
     pub fn scan(&self, db: DatabaseIndex, cursor: u64, pattern: Option<&[u8]>, type_filter: Option<&str>, count: usize) -> Result<(u64, Vec<Vec<u8>>)> {
         let database = self.databases.get(db).ok_or(StorageError::InvalidDatabase)?;
         
         let scan_count = if count == 0 { 10 } else { count };
         let max_scan_count = std::cmp::min(scan_count, 1000);
         
-        // Collect keys from all shards - NO touch() calls
-        let mut all_keys = Vec::new();
+        // Collect the keys at or past the cursor from all shards - NO touch() calls
+        let mut ahead = Vec::new();
         for shard in &database.shards {
             let shard_guard = shard.read().unwrap();
             for (key, stored_value) in shard_guard.data.iter() {
-                if stored_value.is_expired() {
+                let hash = key_hash(key);
+                if hash < cursor || stored_value.is_expired() {
                     continue;
                 }
                 
                 if let Some(type_name) = type_filter {
-                    let value_type = match &stored_value.value {
-                        Value::String(_) => "string",
-                        Value::List(_) => "list",
-                        Value::Set(_) => "set",
-                        Value::Hash(_) => "hash",
-                        Value::SortedSet(_) => "zset",
-                        Value::Stream(_) => "stream",
-                    };
-                    
-                    if value_type != type_name {
+                    if !stored_value.value.value_type().name().eq_ignore_ascii_case(type_name) {
                         continue;
                     }
                 }
                 
-                all_keys.push(key.clone());
+                ahead.push((hash, key.clone()));
             }
         }
         
-        all_keys.sort();
-        
-        let start_pos = if cursor == 0 { 0 } else { cursor as usize };
-        if start_pos >= all_keys.len() && !all_keys.is_empty() {
-            return Ok((0, Vec::new()));
-        }
+        ahead.sort_unstable();
         
         let mut matching_keys = Vec::new();
         let mut keys_examined = 0;
-        let mut current_pos = start_pos;
+        let mut current_pos = 0;
         
         let pattern_str = pattern.map(|p| String::from_utf8_lossy(p));
         
-        while keys_examined < max_scan_count * 10 && matching_keys.len() < max_scan_count {
-            if current_pos >= all_keys.len() {
-                break;
-            }
-            
-            let key = &all_keys[current_pos];
-            let mut include_key = true;
-            
-            if let Some(ref pat) = pattern_str {
-                let key_str = String::from_utf8_lossy(key);
-                if !pattern_matches(pat, &key_str) {
-                    include_key = false;
+        while current_pos < ahead.len() && keys_examined < max_scan_count * 10 && matching_keys.len() < max_scan_count {
+            // Take every key with this hash, so the cursor never splits them
+            let hash = ahead[current_pos].0;
+            while current_pos < ahead.len() && ahead[current_pos].0 == hash {
+                let key = &ahead[current_pos].1;
+                let include_key = match pattern_str {
+                    Some(ref pat) => pattern_matches(pat, &String::from_utf8_lossy(key)),
+                    None => true,
+                };
+                
+                if include_key {
+                    matching_keys.push(key.clone());
                 }
+                
+                current_pos += 1;
+                keys_examined += 1;
             }
-            
-            if include_key {
-                matching_keys.push(key.clone());
-            }
-            
-            current_pos += 1;
-            keys_examined += 1;
         }
         
-        let next_cursor = if current_pos >= all_keys.len() {
-            0
-        } else {
-            current_pos as u64
-        };
+        // The next hash is above the ones returned, so it is never the 0 that ends the scan
+        let next_cursor = ahead.get(current_pos).map_or(0, |(hash, _)| *hash);
         
         Ok((next_cursor, matching_keys))
     }
-
+    
     pub fn hscan(&self, db: DatabaseIndex, key: &[u8], cursor: u64, pattern: Option<&[u8]>, count: usize, no_values: bool) -> Result<(u64, Vec<Vec<u8>>)> {
         match self.get(db, key)? {
             GetResult::Found(Value::Hash(hash)) => {
@@ -2250,15 +3298,15 @@ impl StorageEngine {
                 if hash.len() <= max_scan_count && cursor == 0 && pattern.is_none() {
                     let mut result = Vec::new();
                     for (field, value) in hash.iter() {
-                        result.push(field.clone());
+                        result.push(field.to_vec());
                         if !no_values {
-                            result.push(value.clone());
+                            result.push(value.to_vec());
                         }
                     }
                     return Ok((0, result));
                 }
                 
-                let mut fields: Vec<Vec<u8>> = hash.keys().cloned().collect();
+                let mut fields: Vec<Vec<u8>> = hash.keys().map(<[u8]>::to_vec).collect();
                 fields.sort();
                 
                 let start_pos = if cursor == 0 { 0 } else { cursor as usize };
@@ -2290,7 +3338,7 @@ impl StorageEngine {
                         result.push(field.clone());
                         if !no_values {
                             let value = hash.get(field).unwrap();
-                            result.push(value.clone());
+                            result.push(value.to_vec());
                         }
                     }
                     
@@ -2322,10 +3370,10 @@ impl StorageEngine {
                 let max_scan_count = std::cmp::min(scan_count, 1000);
                 
                 if set.len() <= max_scan_count && cursor == 0 && pattern.is_none() {
-                    return Ok((0, set.iter().cloned().collect()));
+                    return Ok((0, set.members().collect()));
                 }
                 
-                let mut members: Vec<Vec<u8>> = set.iter().cloned().collect();
+                let mut members: Vec<Vec<u8>> = set.members().collect();
                 members.sort();
                 
                 let start_pos = if cursor == 0 { 0 } else { cursor as usize };
@@ -2384,12 +3432,7 @@ impl StorageEngine {
                 let scan_count = if count == 0 { 10 } else { count };
                 let max_scan_count = std::cmp::min(scan_count, 1000);
                 
-                let mut items: Vec<(Vec<u8>, f64)> = Vec::new();
-                let range = zset.range_by_rank(0, zset.len() - 1);
-                for (member, score) in range.items {
-                    items.push((member, score));
-                }
-                
+                let mut items = zset.items();
                 items.sort_by(|a, b| a.0.cmp(&b.0));
                 
                 if items.len() <= max_scan_count && cursor == 0 && pattern.is_none() {
@@ -2445,13 +3488,43 @@ impl StorageEngine {
             }
         }
     }
-
+    
+    /// Actively expire hash fields of a shard, sampling its volatile hashes
+    ///
+    /// As in Redis's active expire cycle, each round checks a run of hashes
+    /// from a random point under the write lock, and another round follows
+    /// only while more than a quarter of them lost fields and `deadline` has
+    /// not passed. Every shard gets one round per pass however late it is.
+    fn active_expire_hash_fields(shard: &RwLock<DatabaseShard>, deadline: Instant) {
+        let mut rng = rand::thread_rng();
+        loop {
+            let sample: Vec<Key> = {
+                let shard_guard = shard.read().unwrap();
+                let volatile = &shard_guard.volatile_hash_keys;
+                if volatile.is_empty() {
+                    return;
+                }
+                let start = rng.gen_range(0..volatile.len());
+                volatile.iter().chain(volatile.iter()).skip(start).take(HASH_FIELD_EXPIRE_SAMPLES.min(volatile.len())).cloned().collect()
+            };
+            
+            let expired = {
+                let mut shard_guard = shard.write().unwrap();
+                sample.iter().filter(|key| shard_guard.expire_hash_fields(key) > 0).count()
+            };
+            if expired * 4 <= sample.len() || Instant::now() >= deadline {
+                return;
+            }
+        }
+    }
+    
     /// Background thread for cleaning up expired keys in sharded structure
     fn expiration_cleanup_loop(engine: Arc<StorageEngine>) {
         loop {
             thread::sleep(Duration::from_secs(1)); // Check every second
             
-            for database in &engine.databases {
+            let hash_fields_deadline = Instant::now() + HASH_FIELD_EXPIRE_BUDGET;
+            for (db, database) in engine.databases.iter().enumerate() {
                 let now = Instant::now();
                 
                 // Check each shard for expired keys
@@ -2468,33 +3541,35 @@ impl StorageEngine {
                         }
                     }
                     
+                    Self::active_expire_hash_fields(shard, hash_fields_deadline);
+                    
                     // Remove expired keys with write lock
                     if !expired_keys.is_empty() {
                         let mut shard_guard = shard.write().unwrap();
                         for key in expired_keys {
-                            if let Some(stored_value) = shard_guard.data.remove(&key) {
-                                shard_guard.expiring_keys.remove(&key);
-                                
+                            if shard_guard.remove_entry(&key).is_some() {
                                 shard_guard.mark_modified(&key);
+                                engine.expired_keys.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
                                 
-                                // Update memory usage
-                                let memory_size = engine.calculate_value_size(&key, &stored_value.value);
-                                engine.memory_manager.remove_memory(memory_size);
+                                crate::storage::lua_triggers::notify(db, "expired", key);
                             }
                         }
                     }
                 }
             }
+            
+            // Run keyspace triggers for the keys expired in this pass
+            crate::storage::lua_triggers::dispatch();
         }
     }
 }
 
 impl Database {
-    /// Create a new sharded database
-    pub fn new() -> Self {
+    /// Create a new sharded database accounting its keys to `memory`
+    pub fn new(memory: &Arc<MemoryManager>) -> Self {
         let mut shards = Vec::with_capacity(SHARDS_PER_DATABASE);
         for _ in 0..SHARDS_PER_DATABASE {
-            shards.push(Arc::new(RwLock::new(DatabaseShard::new())));
+            shards.push(Arc::new(RwLock::new(DatabaseShard::new(Arc::clone(memory)))));
         }
         
         Database { shards }
@@ -2502,12 +3577,14 @@ impl Database {
 }
 
 impl DatabaseShard {
-    /// Create a new database shard
-    pub fn new() -> Self {
+    /// Create a new database shard accounting its keys to `memory`
+    pub fn new(memory: Arc<MemoryManager>) -> Self {
         DatabaseShard {
-            data: HashMap::new(),
+            data: ShardData::default(),
             expiring_keys: HashMap::new(),
+            volatile_hash_keys: HashSet::new(),
             watch_tracker: ShardWatchTracker::new(),
+            memory,
         }
     }
     
@@ -2516,6 +3593,163 @@ impl DatabaseShard {
         self.watch_tracker.mark_key_modified(key);
     }
     
+    /// Store a value under a key, replacing any previous one
+    ///
+    /// Accounts for the memory of both values and tracks the new value's TTL
+    /// in the expires table. Returns the value replaced.
+    fn insert_entry(&mut self, key: Key, stored_value: StoredValue) -> Option<StoredValue> {
+        self.set_expiry(&key, stored_value.metadata.expires_at);
+        self.memory.add_memory(entry_size(&key, &stored_value.value));
+        match self.data.get_mut(&key) {
+            Some(existing) => {
+                let previous = std::mem::replace(existing, stored_value);
+                self.memory.remove_memory(entry_size(&key, &previous.value));
+                Some(previous)
+            }
+            None => {
+                self.data.insert(key, stored_value);
+                None
+            }
+        }
+    }
+    
+    /// Remove a key with its TTL, releasing its memory
+    fn remove_entry(&mut self, key: &[u8]) -> Option<StoredValue> {
+        let stored_value = self.data.remove(key)?;
+        self.memory.remove_memory(entry_size(key, &stored_value.value));
+        self.set_expiry(key, None);
+        if !self.volatile_hash_keys.is_empty() {
+            self.volatile_hash_keys.remove(key);
+        }
+        Some(stored_value)
+    }
+    
+    /// Track a key's deadline in the expires table, or stop tracking it
+    ///
+    /// Only the table is updated; callers keep the value's metadata in step.
+    fn set_expiry(&mut self, key: &[u8], expires_at: Option<Instant>) {
+        match expires_at {
+            Some(expires_at) => match self.expiring_keys.get_mut(key) {
+                Some(deadline) => *deadline = expires_at,
+                None => {
+                    self.expiring_keys.insert(key.to_vec(), expires_at);
+                    self.memory.add_memory(expiry_size(key));
+                }
+            },
+            None => {
+                if self.expiring_keys.remove(key).is_some() {
+                    self.memory.remove_memory(expiry_size(key));
+                }
+            }
+        }
+    }
+    
+    /// Lazily expire hash fields whose TTL has elapsed, deleting the key if the hash empties
+    ///
+    /// Returns how many fields were removed.
+    fn expire_hash_fields(&mut self, key: &[u8]) -> usize {
+        if self.volatile_hash_keys.is_empty() || !self.volatile_hash_keys.contains(key) {
+            return 0;
+        }
+        
+        let (removed, still_volatile, now_empty) = match self.data.get_mut(key) {
+            Some(StoredValue { value: Value::Hash(hash), .. }) => {
+                let before = hash.memory_usage();
+                let removed = hash.purge_expired_fields();
+                self.memory.resize_memory(before, hash.memory_usage());
+                (removed, hash.has_field_ttls(), hash.is_empty())
+            }
+            _ => (0, false, false),
+        };
+        
+        if !still_volatile {
+            self.volatile_hash_keys.remove(key);
+        }
+        
+        if removed > 0 {
+            self.mark_modified(key);
+            if now_empty {
+                self.remove_entry(key);
+            }
+        }
+        removed
+    }
+    
+    /// Move a list element between keys of this shard, or into `destination_shard`
+    fn move_list_element(
+        &mut self,
+        destination_shard: Option<&mut DatabaseShard>,
+        source: &[u8],
+        destination: Key,
+        from: ListEnd,
+        to: ListEnd,
+    ) -> Result<Option<Vec<u8>>> {
+        match self.data.get(source) {
+            Some(stored_value) if !stored_value.is_expired() => {
+                if !matches!(stored_value.value, Value::List(_)) {
+                    return Err(StorageError::WrongType.into());
+                }
+            }
+            _ => return Ok(None),
+        }
+        
+        // Check the destination type before anything is popped
+        let target: &DatabaseShard = match &destination_shard {
+            Some(shard) => shard,
+            None => self,
+        };
+        if let Some(stored_value) = target.data.get(&destination) {
+            if !stored_value.is_expired() && !matches!(stored_value.value, Value::List(_)) {
+                return Err(StorageError::WrongType.into());
+            }
+        }
+        
+        let (element, is_empty) = match self.data.get_mut(source).map(|stored_value| &mut stored_value.value) {
+            Some(Value::List(list)) => {
+                let before = list.memory_usage(0);
+                let element = list.pop(from);
+                self.memory.resize_memory(before, list.memory_usage(0));
+                (element, list.is_empty())
+            }
+            _ => return Ok(None),
+        };
+        let Some(element) = element else { return Ok(None) };
+        self.mark_modified(source);
+        
+        // A single-element list rotated onto itself keeps its key and TTL
+        if is_empty && source != destination.as_slice() {
+            self.remove_entry(source);
+        }
+        
+        let target = match destination_shard {
+            Some(shard) => shard,
+            None => self,
+        };
+        if target.data.get(&destination).is_some_and(|stored_value| stored_value.is_expired()) {
+            target.remove_entry(&destination);
+        }
+        if !target.data.contains_key(&destination) {
+            target.insert_entry(destination.clone(), StoredValue::new(Value::empty_list()));
+        }
+        if let Some(StoredValue { value: Value::List(list), .. }) = target.data.get_mut(&destination) {
+            let before = list.memory_usage(0);
+            list.push(to, element.clone(), target.memory.encoding_limits());
+            target.memory.resize_memory(before, list.memory_usage(0));
+        }
+        target.mark_modified(&destination);
+        
+        Ok(Some(element))
+    }
+    
+    /// Start tracking a key for field expiration if it holds a hash with field TTLs
+    fn track_volatile_hash(&mut self, key: &[u8]) {
+        if let Some(StoredValue { value: Value::Hash(hash), .. }) = self.data.get(key) {
+            if hash.has_field_ttls() {
+                self.volatile_hash_keys.insert(key.to_vec());
+            }
+        }
+    }
+    
     /// Get current modification counter for shard
     fn get_modification_counter(&self) -> u64 {
         // Use simplified shard-level counter for basic compatibility
@@ -2525,19 +3759,21 @@ impl DatabaseShard {
 
 impl Default for Database {
     fn default() -> Self {
-        Self::new()
+        Self::new(&Arc::new(MemoryManager::unlimited()))
     }
 }
 
 impl Default for DatabaseShard {
     fn default() -> Self {
-        Self::new()
+        Self::new(Arc::new(MemoryManager::unlimited()))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::lru;
+    use crate::storage::memory::LFU_INIT_VAL;
     
     #[test]
     fn test_basic_operations() {
@@ -2575,6 +3811,34 @@ mod tests {
         assert_eq!(result, 7);
     }
     
+    #[test]
+    fn test_incr_by_float() {
+        let engine = StorageEngine::new_in_memory();
+        
+        // Results have no trailing zeros or binary noise, and keep the TTL
+        engine.set_string_ex(0, b"f".to_vec(), b"10.50".to_vec(), Duration::from_secs(60)).unwrap();
+        assert_eq!(engine.incr_by_float(0, b"f".to_vec(), 0.1).unwrap(), b"10.6");
+        assert!(engine.ttl(0, b"f").unwrap().is_some());
+        engine.set_string(0, b"f".to_vec(), b"5.0e3".to_vec()).unwrap();
+        assert_eq!(engine.incr_by_float(0, b"f".to_vec(), 200.0).unwrap(), b"5200");
+        assert_eq!(engine.incr_by_float(0, b"f".to_vec(), -5200.0).unwrap(), b"0");
+        assert_eq!(engine.incr_by_float(0, b"new".to_vec(), 1.1).unwrap(), b"1.1");
+        engine.set_string(0, b"f".to_vec(), b"0.1".to_vec()).unwrap();
+        assert_eq!(engine.incr_by_float(0, b"f".to_vec(), 0.2).unwrap(), b"0.3");
+        assert_eq!(engine.incr_by_float(0, b"f".to_vec(), 1e300).unwrap(), b"1e+300");
+        
+        engine.set_string(0, b"f".to_vec(), b"abc".to_vec()).unwrap();
+        assert_eq!(engine.incr_by_float(0, b"f".to_vec(), 1.0).unwrap_err().to_string(), "ERR value is not a valid float");
+        engine.set_string(0, b"f".to_vec(), b"1e308".to_vec()).unwrap();
+        assert_eq!(engine.incr_by_float(0, b"f".to_vec(), 1e308).unwrap_err().to_string(), "ERR increment would produce NaN or Infinity");
+        
+        assert_eq!(engine.hincrbyfloat(0, b"h".to_vec(), b"a".to_vec(), 2.5).unwrap(), b"2.5");
+        assert_eq!(engine.hincrbyfloat(0, b"h".to_vec(), b"a".to_vec(), 1e-7).unwrap(), b"2.5000001");
+        engine.hset(0, b"h".to_vec(), vec![(b"s".to_vec(), b"x".to_vec())]).unwrap();
+        assert_eq!(engine.hincrbyfloat(0, b"h".to_vec(), b"s".to_vec(), 1.0).unwrap_err().to_string(), "ERR hash value is not a float");
+        assert!(matches!(engine.hincrbyfloat(0, b"f".to_vec(), b"a".to_vec(), 1.0), Err(FerrousError::Storage(StorageError::WrongType))));
+    }
+    
     #[test]
     fn test_expiration() {
         let engine = StorageEngine::new();
@@ -2620,6 +3884,501 @@ mod tests {
         // All should succeed without any access time tracking overhead
         assert!(true);
     }
+    
+    #[test]
+    fn test_touch_keys_respects_no_touch() {
+        let engine = StorageEngine::new();
+        engine.set_string(0, b"k".to_vec(), b"v".to_vec()).unwrap();
+        thread::sleep(Duration::from_millis(30));
+        
+        // Accesses from a NO-TOUCH client leave the clock alone
+        {
+            let _guard = lru::NoTouchGuard::new(true);
+            lru::record_access(&engine, 0, &["GET", "k"]);
+        }
+        assert!(engine.idle_time(0, b"k").unwrap().unwrap() >= Duration::from_millis(30));
+        
+        lru::record_access(&engine, 0, &["GET", "k"]);
+        assert!(engine.idle_time(0, b"k").unwrap().unwrap() < Duration::from_millis(30));
+        
+        assert_eq!(engine.touch_keys(0, &["k", "missing"]).unwrap(), 1);
+        assert_eq!(engine.idle_time(0, b"missing").unwrap(), None);
+    }
+    
+    #[test]
+    fn test_shared_integer_refcount() {
+        let engine = StorageEngine::new();
+        
+        engine.set_string(0, b"small".to_vec(), b"100".to_vec()).unwrap();
+        engine.set_string(0, b"large".to_vec(), b"100000".to_vec()).unwrap();
+        engine.incr_by(0, b"counter".to_vec(), 5).unwrap();
+        
+        assert_eq!(engine.object_refcount(0, b"small").unwrap(), Some(shared::SHARED_REFCOUNT));
+        assert_eq!(engine.object_refcount(0, b"counter").unwrap(), Some(shared::SHARED_REFCOUNT));
+        assert_eq!(engine.object_refcount(0, b"large").unwrap(), Some(1));
+        assert_eq!(engine.object_refcount(0, b"missing").unwrap(), None);
+        
+        // Mutating a shared value must not touch other keys sharing it
+        engine.set_string(0, b"other".to_vec(), b"100".to_vec()).unwrap();
+        engine.append(0, b"small".to_vec(), b"0".to_vec()).unwrap();
+        assert_eq!(engine.get_string(0, b"small").unwrap(), Some(b"1000".to_vec()));
+        assert_eq!(engine.get_string(0, b"other").unwrap(), Some(b"100".to_vec()));
+        assert_eq!(engine.object_refcount(0, b"small").unwrap(), Some(1));
+    }
+    
+    #[test]
+    fn test_object_encoding_and_frequency() {
+        let engine = StorageEngine::with_config(16, MemoryManager::with_policy(EvictionPolicy::AllKeysLfu));
+        let encoding = |key: &[u8]| engine.object_encoding(0, key).unwrap();
+        
+        engine.set_string(0, b"int".to_vec(), b"12345".to_vec()).unwrap();
+        engine.set_string(0, b"embstr".to_vec(), b"hello".to_vec()).unwrap();
+        engine.set_string(0, b"raw".to_vec(), vec![b'x'; 45]).unwrap();
+        assert_eq!((encoding(b"int"), encoding(b"embstr"), encoding(b"raw")), (Some("int"), Some("embstr"), Some("raw")));
+        
+        // Collections report the compact encoding until they outgrow it
+        engine.sadd(0, b"set".to_vec(), vec![b"1".to_vec(), b"2".to_vec()]).unwrap();
+        assert_eq!(encoding(b"set"), Some("intset"));
+        engine.sadd(0, b"set".to_vec(), vec![b"a".to_vec()]).unwrap();
+        assert_eq!(encoding(b"set"), Some("listpack"));
+        engine.rpush(0, b"list".to_vec(), vec![b"a".to_vec()]).unwrap();
+        assert_eq!(encoding(b"list"), Some("listpack"));
+        engine.rpush(0, b"list".to_vec(), vec![vec![b'x'; 65]]).unwrap();
+        assert_eq!(encoding(b"list"), Some("listpack"));
+        engine.rpush(0, b"list".to_vec(), vec![vec![b'x'; 8 * 1024]]).unwrap();
+        assert_eq!(encoding(b"list"), Some("quicklist"));
+        engine.hset(0, b"hash".to_vec(), vec![(b"f".to_vec(), b"v".to_vec())]).unwrap();
+        assert_eq!(encoding(b"hash"), Some("listpack"));
+        for i in 0..=128 {
+            engine.zadd(0, b"zset".to_vec(), i.to_string().into_bytes(), i as f64).unwrap();
+        }
+        assert_eq!(encoding(b"zset"), Some("skiplist"));
+        assert_eq!(encoding(b"missing"), None);
+        
+        // Accesses raise the LFU counter from its initial value
+        assert!(engine.eviction_policy().is_lfu());
+        assert_eq!(engine.access_frequency(0, b"int").unwrap(), Some(LFU_INIT_VAL));
+        lru::record_access(&engine, 0, &["GET", "int"]);
+        assert_eq!(engine.access_frequency(0, b"int").unwrap(), Some(LFU_INIT_VAL + 1));
+        assert_eq!(engine.access_frequency(0, b"missing").unwrap(), None);
+    }
+    
+    #[test]
+    fn test_list_positions_moves_and_pops() {
+        let engine = StorageEngine::new();
+        let items = |items: &[&str]| items.iter().map(|item| item.as_bytes().to_vec()).collect::<Vec<_>>();
+        engine.rpush(0, b"list".to_vec(), items(&["a", "b", "c", "a", "b", "a"])).unwrap();
+        
+        // A negative rank scans from the tail, MAXLEN bounds the elements compared
+        assert_eq!(engine.lpos(0, b"list", b"a", 1, 0, 0).unwrap(), vec![0, 3, 5]);
+        assert_eq!(engine.lpos(0, b"list", b"a", 2, 1, 0).unwrap(), vec![3]);
+        assert_eq!(engine.lpos(0, b"list", b"a", -1, 2, 0).unwrap(), vec![5, 3]);
+        assert_eq!(engine.lpos(0, b"list", b"a", 1, 0, 3).unwrap(), vec![0]);
+        assert!(engine.lpos(0, b"missing", b"a", 1, 0, 0).unwrap().is_empty());
+        
+        // Moving onto the same list rotates it
+        assert_eq!(engine.lmove(0, b"list", b"list".to_vec(), ListEnd::Right, ListEnd::Left).unwrap(), Some(b"a".to_vec()));
+        assert_eq!(engine.lrange(0, b"list", 0, 0).unwrap(), items(&["a"]));
+        assert_eq!(engine.lmove(0, b"list", b"other".to_vec(), ListEnd::Left, ListEnd::Right).unwrap(), Some(b"a".to_vec()));
+        assert_eq!(engine.lrange(0, b"other", 0, -1).unwrap(), items(&["a"]));
+        assert_eq!(engine.lmove(0, b"missing", b"other".to_vec(), ListEnd::Left, ListEnd::Left).unwrap(), None);
+        
+        // A destination of another type leaves the source untouched
+        engine.set_string(0, b"string".to_vec(), b"x".to_vec()).unwrap();
+        assert!(engine.lmove(0, b"list", b"string".to_vec(), ListEnd::Left, ListEnd::Left).is_err());
+        assert_eq!(engine.llen(0, b"list").unwrap(), 5);
+        
+        // Popping past the end returns what is left and deletes the key
+        assert_eq!(engine.list_pop_count(0, b"list", ListEnd::Right, 2).unwrap(), items(&["b", "a"]));
+        assert_eq!(engine.list_pop_count(0, b"list", ListEnd::Left, 10).unwrap(), items(&["a", "b", "c"]));
+        assert!(!engine.exists(0, b"list").unwrap());
+    }
+    
+    #[test]
+    fn test_set_membership_and_intersection_size() {
+        let engine = StorageEngine::new();
+        let items = |items: &[&str]| items.iter().map(|item| item.as_bytes().to_vec()).collect::<Vec<_>>();
+        engine.sadd(0, b"a".to_vec(), items(&["1", "2", "3", "4"])).unwrap();
+        engine.sadd(0, b"b".to_vec(), items(&["2", "3", "4", "5"])).unwrap();
+        
+        assert_eq!(engine.smismember(0, b"a", &["1", "5", "4"]).unwrap(), vec![true, false, true]);
+        assert_eq!(engine.smismember(0, b"missing", &["1", "2"]).unwrap(), vec![false, false]);
+        assert_eq!(engine.sintercard(0, &["a", "b"], 0).unwrap(), 3);
+        assert_eq!(engine.sintercard(0, &["a", "b"], 2).unwrap(), 2);
+        assert_eq!(engine.sintercard(0, &["a", "missing"], 0).unwrap(), 0);
+        
+        // A negative count repeats members to fill the reply
+        assert_eq!(engine.srandmember(0, b"a", -10).unwrap().len(), 10);
+        assert_eq!(engine.srandmember(0, b"a", 10).unwrap().len(), 4);
+        
+        engine.set_string(0, b"string".to_vec(), b"x".to_vec()).unwrap();
+        assert!(engine.smismember(0, b"string", &["x"]).is_err());
+        assert!(engine.sintercard(0, &["string", "a"], 0).is_err());
+    }
+    
+    #[test]
+    fn test_hash_field_expiration() {
+        let engine = StorageEngine::new();
+        let fields = vec![b"a".to_vec(), b"b".to_vec(), b"missing".to_vec()];
+        engine.hset(0, b"h".to_vec(), vec![(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), b"2".to_vec())]).unwrap();
+        
+        let deadline = Instant::now() + Duration::from_millis(20);
+        assert_eq!(engine.hexpire(0, b"h", &fields[..1], deadline, None).unwrap(), vec![1]);
+        
+        // NX fails for a field that already has a TTL, GT never applies to a field without one
+        assert_eq!(engine.hexpire(0, b"h", &fields[..1], deadline, Some(ExpireCondition::Nx)).unwrap(), vec![0]);
+        assert_eq!(engine.hexpire(0, b"h", &fields[1..2], deadline, Some(ExpireCondition::Gt)).unwrap(), vec![0]);
+        
+        let ttls = engine.hpttl(0, b"h", &fields).unwrap();
+        assert!(ttls[0] > 0 && ttls[0] <= 20);
+        assert_eq!(&ttls[1..], &[-1, -2]);
+        
+        // Field expires lazily, the rest of the hash survives
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(engine.hget(0, b"h", b"a").unwrap(), None);
+        assert_eq!(engine.hlen(0, b"h").unwrap(), 1);
+        
+        // HPERSIST clears the TTL
+        engine.hexpire(0, b"h", &fields[1..2], Instant::now() + Duration::from_secs(60), None).unwrap();
+        assert_eq!(engine.hpersist(0, b"h", &fields).unwrap(), vec![-2, 1, -2]);
+        
+        // A deadline in the past deletes the field, and the empty hash with it
+        assert_eq!(engine.hexpire(0, b"h", &fields[1..2], Instant::now(), None).unwrap(), vec![2]);
+        assert!(!engine.exists(0, b"h").unwrap());
+        
+        // EXISTS and TYPE see a hash whose last field expired as gone
+        engine.hset(0, b"h".to_vec(), vec![(b"a".to_vec(), b"1".to_vec())]).unwrap();
+        engine.hexpire(0, b"h", &fields[..1], Instant::now() + Duration::from_millis(10), None).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(engine.key_type(0, b"h").unwrap(), "none");
+        assert!(!engine.exists(0, b"h").unwrap());
+    }
+    
+    #[test]
+    fn test_active_hash_field_expiry() {
+        let engine = StorageEngine::new_in_memory();
+        let deadline = Instant::now() + Duration::from_millis(10);
+        for i in 0..200 {
+            let key = format!("h:{}", i).into_bytes();
+            engine.hset(0, key.clone(), vec![(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), b"2".to_vec())]).unwrap();
+            engine.hexpire(0, &key, &[b"a"], deadline, None).unwrap();
+        }
+        engine.hexpire(0, b"h:0", &[b"b"], Instant::now() + Duration::from_secs(60), None).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        
+        // Rounds repeat while most samples lose fields, so every shard is cleared
+        for shard in &engine.databases[0].shards {
+            StorageEngine::active_expire_hash_fields(shard, Instant::now() + Duration::from_secs(1));
+        }
+        let volatile: usize = engine.databases[0].shards.iter().map(|shard| shard.read().unwrap().volatile_hash_keys.len()).sum();
+        assert_eq!(volatile, 1);
+        assert_eq!(engine.hlen(0, b"h:1").unwrap(), 1);
+    }
+    
+    #[test]
+    fn test_scan_survives_keyspace_changes() {
+        let engine = StorageEngine::new();
+        for i in 0..200 {
+            engine.set_string(0, format!("key:{}", i).into_bytes(), b"v".to_vec()).unwrap();
+        }
+        
+        // Keys added and removed between calls never hide the keys that stay
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let mut calls = 0;
+        loop {
+            let (next, keys) = engine.scan(0, cursor, None, None, 10).unwrap();
+            seen.extend(keys);
+            engine.set_string(0, format!("new:{}", calls).into_bytes(), b"v".to_vec()).unwrap();
+            engine.delete(0, format!("key:{}", 199 - calls).as_bytes()).unwrap();
+            calls += 1;
+            assert!(next == 0 || next > cursor, "cursor went back");
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        for i in 0..(200 - calls) {
+            assert!(seen.contains(format!("key:{}", i).as_bytes()), "key:{} was skipped", i);
+        }
+        
+        // TYPE, MATCH and COUNT combine
+        engine.rpush(0, b"key:list".to_vec(), vec![b"a".to_vec()]).unwrap();
+        engine.rpush(0, b"other:list".to_vec(), vec![b"a".to_vec()]).unwrap();
+        let mut lists = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (next, keys) = engine.scan(0, cursor, Some(b"key:*"), Some("LIST"), 3).unwrap();
+            lists.extend(keys);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        assert_eq!(lists, vec![b"key:list".to_vec()]);
+    }
+    
+    #[test]
+    fn test_dbsize_and_random_key_skip_expired_keys() {
+        let engine = StorageEngine::new();
+        assert_eq!(engine.random_key(0).unwrap(), None);
+        
+        engine.set_string(0, b"live".to_vec(), b"v".to_vec()).unwrap();
+        engine.set_string_ex(0, b"gone".to_vec(), b"v".to_vec(), Duration::from_millis(1)).unwrap();
+        thread::sleep(Duration::from_millis(5));
+        
+        assert_eq!(engine.dbsize(0).unwrap(), 1);
+        for _ in 0..10 {
+            assert_eq!(engine.random_key(0).unwrap(), Some(b"live".to_vec()));
+        }
+    }
+    
+    #[test]
+    fn test_snapshot_is_a_point_in_time_copy() {
+        let engine = StorageEngine::new();
+        engine.set_string(0, b"plain".to_vec(), b"v".to_vec()).unwrap();
+        engine.set_string_ex(0, b"volatile".to_vec(), b"v".to_vec(), Duration::from_secs(60)).unwrap();
+        engine.set_string_ex(0, b"gone".to_vec(), b"v".to_vec(), Duration::from_millis(1)).unwrap();
+        engine.set_string(3, b"other".to_vec(), b"v".to_vec()).unwrap();
+        thread::sleep(Duration::from_millis(5));
+        
+        let snapshot = engine.snapshot();
+        engine.set_string(0, b"plain".to_vec(), b"changed".to_vec()).unwrap();
+        
+        let entries = snapshot.database(0);
+        let mut keys: Vec<&[u8]> = entries.iter().map(|entry| entry.key.as_slice()).collect();
+        keys.sort();
+        assert_eq!(keys, vec![b"plain".as_slice(), b"volatile".as_slice()]);
+        assert_eq!(snapshot.database(3).len(), 1);
+        assert!(snapshot.database(99).is_empty());
+        for entry in snapshot.database(0) {
+            match entry.key.as_slice() {
+                b"plain" => {
+                    assert!(matches!(&entry.value, Value::String(bytes) if bytes.as_slice() == b"v"));
+                    assert_eq!(entry.ttl(), None);
+                }
+                _ => assert!(entry.ttl().unwrap() > Duration::from_secs(59)),
+            }
+        }
+        
+        // An element moving between lists of two shards is in exactly one of them
+        let source = b"list:0".to_vec();
+        let destination = (1..).map(|i| format!("list:{}", i).into_bytes())
+            .find(|key| engine.get_shard_index(key) != engine.get_shard_index(&source))
+            .unwrap();
+        engine.lpush(0, source.clone(), vec![b"element".to_vec()]).unwrap();
+        let mover = {
+            let engine = Arc::clone(&engine);
+            let (source, destination) = (source.clone(), destination.clone());
+            thread::spawn(move || for i in 0..2000 {
+                let (from, to) = if i % 2 == 0 { (&source, &destination) } else { (&destination, &source) };
+                engine.lmove(0, from, to.clone(), ListEnd::Left, ListEnd::Left).unwrap();
+            })
+        };
+        while !mover.is_finished() {
+            let lists = engine.snapshot();
+            let elements: usize = lists.database(0).iter()
+                .filter_map(|entry| match &entry.value {
+                    Value::List(list) if entry.key == source || entry.key == destination => Some(list.len()),
+                    _ => None,
+                })
+                .sum();
+            assert_eq!(elements, 1);
+        }
+        mover.join().unwrap();
+    }
+    
+    #[test]
+    fn test_snapshot_keeps_old_values_until_dropped() {
+        let engine = StorageEngine::new();
+        engine.set_string(0, b"deleted".to_vec(), b"v".to_vec()).unwrap();
+        engine.rpush(0, b"list".to_vec(), vec![b"a".to_vec()]).unwrap();
+        engine.set_string(0, b"flushed".to_vec(), b"v".to_vec()).unwrap();
+        for i in 0..200 {
+            engine.zadd(0, b"zset".to_vec(), format!("m{}", i).into_bytes(), i as f64).unwrap();
+        }
+        
+        let first = engine.snapshot();
+        engine.delete(0, b"deleted").unwrap();
+        // A skiplist changes in place, so the live set must stop sharing it
+        engine.zadd(0, b"zset".to_vec(), b"late".to_vec(), 0.5).unwrap();
+        engine.rpush(0, b"list".to_vec(), vec![b"b".to_vec()]).unwrap();
+        engine.set_string(0, b"created".to_vec(), b"v".to_vec()).unwrap();
+        let second = engine.snapshot();
+        engine.flush_db(0).unwrap();
+        
+        let keys = |snapshot: &Snapshot| {
+            let mut entries = snapshot.database(0);
+            entries.sort_by(|a, b| a.key.cmp(&b.key));
+            entries.into_iter()
+                .map(|entry| match entry.value {
+                    Value::List(list) => (entry.key, list.len()),
+                    Value::SortedSet(zset) => (entry.key, zset.len()),
+                    _ => (entry.key, 1),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(&first), vec![
+            (b"deleted".to_vec(), 1), (b"flushed".to_vec(), 1), (b"list".to_vec(), 1), (b"zset".to_vec(), 200),
+        ]);
+        assert_eq!(keys(&second), vec![
+            (b"created".to_vec(), 1), (b"flushed".to_vec(), 1), (b"list".to_vec(), 2), (b"zset".to_vec(), 201),
+        ]);
+        
+        // Old values go with the snapshots, and writes stop keeping them
+        drop((first, second));
+        engine.set_string(0, b"after".to_vec(), b"v".to_vec()).unwrap();
+        for shard in &engine.databases[0].shards {
+            assert!(shard.read().unwrap().data.preserved.is_empty());
+        }
+    }
+    
+    #[test]
+    fn test_conditional_key_expiration() {
+        let engine = StorageEngine::new();
+        engine.set_string(0, b"k".to_vec(), b"v".to_vec()).unwrap();
+        let soon = Instant::now() + Duration::from_secs(10);
+        let later = Instant::now() + Duration::from_secs(20);
+        
+        // XX and GT need an existing expiration, NX and LT accept a key without one
+        assert!(!engine.expire_at(0, b"k", soon, &[ExpireCondition::Xx]).unwrap());
+        assert!(!engine.expire_at(0, b"k", soon, &[ExpireCondition::Gt]).unwrap());
+        assert!(engine.expire_at(0, b"k", later, &[ExpireCondition::Nx]).unwrap());
+        assert!(!engine.expire_at(0, b"k", soon, &[ExpireCondition::Nx]).unwrap());
+        assert!(!engine.expire_at(0, b"k", later + Duration::from_secs(1), &[ExpireCondition::Lt]).unwrap());
+        assert!(engine.expire_at(0, b"k", soon, &[ExpireCondition::Xx, ExpireCondition::Lt]).unwrap());
+        let ttl = engine.pttl(0, b"k").unwrap();
+        assert!(ttl > 9_000 && ttl <= 10_000);
+        
+        // A deadline in the past deletes the key, missing keys are left alone
+        assert!(engine.expire_at(0, b"k", Instant::now(), &[]).unwrap());
+        assert!(!engine.exists(0, b"k").unwrap());
+        assert!(!engine.expire_at(0, b"k", later, &[]).unwrap());
+    }
+    
+    /// Sum of what every key of db 0 is accounted for
+    fn accounted(engine: &StorageEngine) -> usize {
+        engine.keys(0, b"*").unwrap().iter()
+            .map(|key| engine.key_memory_usage(0, key, 0).unwrap().unwrap())
+            .sum()
+    }
+    
+    #[test]
+    fn test_memory_accounting() {
+        let engine = StorageEngine::new();
+        engine.set_string(0, b"s".to_vec(), b"value".to_vec()).unwrap();
+        engine.set_string_ex(0, b"ttl".to_vec(), b"x".repeat(100), Duration::from_secs(60)).unwrap();
+        engine.rpush(0, b"list".to_vec(), vec![b"a".to_vec(), b"bb".to_vec(), b"ccc".to_vec()]).unwrap();
+        engine.lpop(0, b"list").unwrap();
+        engine.sadd(0, b"set".to_vec(), vec![b"m1".to_vec(), b"m2".to_vec()]).unwrap();
+        engine.srem(0, b"set", &[b"m1"]).unwrap();
+        engine.hset(0, b"hash".to_vec(), vec![(b"f".to_vec(), b"v".to_vec()), (b"g".to_vec(), b"w".to_vec())]).unwrap();
+        engine.hdel(0, b"hash".to_vec(), &[b"g"]).unwrap();
+        engine.zadd(0, b"zset".to_vec(), b"member".to_vec(), 1.0).unwrap();
+        engine.append(0, b"s".to_vec(), b"-more".to_vec()).unwrap();
+        engine.incr(0, b"counter".to_vec()).unwrap();
+        assert_eq!(accounted(&engine), engine.memory_usage());
+        
+        // Overwrites and renames carry no stale charge or TTL along
+        engine.set_string(0, b"ttl".to_vec(), b"y".to_vec()).unwrap();
+        engine.rename(0, b"list", b"list2".to_vec()).unwrap();
+        engine.expire(0, b"hash", Duration::from_secs(60)).unwrap();
+        engine.rename(0, b"hash", b"s".to_vec()).unwrap();
+        assert_eq!(accounted(&engine), engine.memory_usage());
+        
+        engine.delete(0, b"zset").unwrap();
+        assert_eq!(accounted(&engine), engine.memory_usage());
+        engine.flush_db(0).unwrap();
+        assert_eq!(engine.memory_usage(), 0);
+    }
+    
+    #[test]
+    fn test_allkeys_eviction() {
+        let engine = StorageEngine::with_config(16, MemoryManager::new(0, EvictionPolicy::AllKeysLru));
+        for i in 0..100 {
+            engine.set_string(0, format!("key:{}", i).into_bytes(), b"x".repeat(100)).unwrap();
+        }
+        let used = engine.memory_usage();
+        engine.set_max_memory(used / 2);
+        
+        let eviction = engine.evict();
+        assert!(eviction.within_limit);
+        assert!(!eviction.keys.is_empty());
+        assert!(engine.memory_usage() <= used / 2);
+        assert_eq!(engine.evicted_keys(), eviction.keys.len() as u64);
+        assert_eq!(engine.dbsize(0).unwrap(), 100 - eviction.keys.len());
+        
+        // Under noeviction nothing is freed
+        engine.set_max_memory(used / 4);
+        engine.set_eviction_policy(EvictionPolicy::NoEviction);
+        let eviction = engine.evict();
+        assert!(!eviction.within_limit);
+        assert!(eviction.keys.is_empty());
+    }
+    
+    #[test]
+    fn test_volatile_ttl_eviction() {
+        let engine = StorageEngine::with_config(16, MemoryManager::new(0, EvictionPolicy::VolatileTtl));
+        engine.set_string(0, b"persistent".to_vec(), b"x".repeat(1000)).unwrap();
+        engine.set_string_ex(0, b"soon".to_vec(), b"x".to_vec(), Duration::from_secs(10)).unwrap();
+        engine.set_string_ex(0, b"later".to_vec(), b"x".to_vec(), Duration::from_secs(1000)).unwrap();
+        engine.set_max_memory(engine.memory_usage() - 1);
+        
+        let eviction = engine.evict();
+        assert!(eviction.within_limit);
+        assert_eq!(eviction.keys, vec![(0, b"soon".to_vec())]);
+        
+        // Once only keys without a TTL are left, the limit can't be met
+        engine.set_max_memory(1);
+        let eviction = engine.evict();
+        assert!(!eviction.within_limit);
+        assert!(engine.exists(0, b"persistent").unwrap());
+        assert!(!engine.exists(0, b"later").unwrap());
+    }
+    
+    #[test]
+    fn test_lfu_eviction_keeps_hot_keys() {
+        let engine = StorageEngine::with_config(16, MemoryManager::new(0, EvictionPolicy::AllKeysLfu));
+        engine.set_lfu_log_factor(0);
+        for i in 0..8 {
+            engine.set_string(0, format!("key:{}", i).into_bytes(), b"x".repeat(100)).unwrap();
+        }
+        for _ in 0..20 {
+            lru::record_access(&engine, 0, &["MGET", "key:0", "key:1"]);
+        }
+        assert_eq!(engine.access_frequency(0, b"key:0").unwrap(), Some(LFU_INIT_VAL + 20));
+        
+        // With every key sampled, the cold ones go first
+        engine.set_eviction_samples(8);
+        engine.set_max_memory(engine.memory_usage() / 3);
+        assert!(engine.evict().within_limit);
+        assert!(engine.exists(0, b"key:0").unwrap() && engine.exists(0, b"key:1").unwrap());
+        assert!(engine.dbsize(0).unwrap() < 8);
+    }
+}
+
+/// Bytes a key and its value are accounted for, not counting a TTL
+fn entry_size(key: &[u8], value: &Value) -> usize {
+    MemoryManager::calculate_size(key) + std::mem::size_of::<StoredValue>() + value.memory_usage(0)
+}
+
+/// Bytes the expires table holds for a key with a TTL: its own copy of the
+/// key and the deadline
+fn expiry_size(key: &[u8]) -> usize {
+    MemoryManager::calculate_size(key) + std::mem::size_of::<Instant>()
+}
+
+/// Deterministic FNV-1a hash of a key, used for sharding and the SCAN order
+fn key_hash(key: &[u8]) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+    
+    let mut hash = FNV_OFFSET;
+    for &byte in key {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
 }
 
 /// Simple glob pattern matching (unchanged)
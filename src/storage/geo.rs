@@ -0,0 +1,171 @@
+//! Geohash encoding for the GEO commands
+//!
+//! Locations are stored in sorted sets the way Redis stores them: the score is
+//! a 52-bit geohash, 26 bits of latitude and 26 bits of longitude interleaved
+//! with latitude in the even bits. Latitude is limited to the range Web
+//! Mercator can represent, so scores stay exact in an `f64` and nearby points
+//! get nearby scores. Distances use the haversine formula on Redis' earth
+//! radius, so replies match Redis to the last printed digit.
+
+/// Longitude range
+pub const LONGITUDE_MIN: f64 = -180.0;
+pub const LONGITUDE_MAX: f64 = 180.0;
+
+/// Latitude range representable in Web Mercator
+pub const LATITUDE_MIN: f64 = -85.05112878;
+pub const LATITUDE_MAX: f64 = 85.05112878;
+
+/// Bits per coordinate
+const STEP: u32 = 26;
+
+/// Earth radius used for distances, in meters
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+
+/// Check whether a longitude/latitude pair can be encoded
+pub fn is_valid(longitude: f64, latitude: f64) -> bool {
+    (LONGITUDE_MIN..=LONGITUDE_MAX).contains(&longitude) && (LATITUDE_MIN..=LATITUDE_MAX).contains(&latitude)
+}
+
+/// Encode a location as a 52-bit geohash, or `None` when it is out of range
+pub fn encode(longitude: f64, latitude: f64) -> Option<u64> {
+    if !is_valid(longitude, latitude) {
+        return None;
+    }
+    let scale = (1u64 << STEP) as f64;
+    let lat_offset = ((latitude - LATITUDE_MIN) / (LATITUDE_MAX - LATITUDE_MIN) * scale) as u32;
+    let lon_offset = ((longitude - LONGITUDE_MIN) / (LONGITUDE_MAX - LONGITUDE_MIN) * scale) as u32;
+    Some(interleave(lat_offset) | (interleave(lon_offset) << 1))
+}
+
+/// Decode a geohash to the center of its cell, as `(longitude, latitude)`
+pub fn decode(hash: u64) -> (f64, f64) {
+    let lat_cell = deinterleave(hash) as f64;
+    let lon_cell = deinterleave(hash >> 1) as f64;
+    let scale = (1u64 << STEP) as f64;
+    
+    let lat_min = LATITUDE_MIN + lat_cell / scale * (LATITUDE_MAX - LATITUDE_MIN);
+    let lat_max = LATITUDE_MIN + (lat_cell + 1.0) / scale * (LATITUDE_MAX - LATITUDE_MIN);
+    let lon_min = LONGITUDE_MIN + lon_cell / scale * (LONGITUDE_MAX - LONGITUDE_MIN);
+    let lon_max = LONGITUDE_MIN + (lon_cell + 1.0) / scale * (LONGITUDE_MAX - LONGITUDE_MIN);
+    
+    let longitude = ((lon_min + lon_max) / 2.0).clamp(LONGITUDE_MIN, LONGITUDE_MAX);
+    let latitude = ((lat_min + lat_max) / 2.0).clamp(LATITUDE_MIN, LATITUDE_MAX);
+    (longitude, latitude)
+}
+
+/// Spread the low 32 bits of `value` over the even bits of the result
+fn interleave(value: u32) -> u64 {
+    let mut spread = 0u64;
+    for bit in 0..32 {
+        spread |= ((value as u64 >> bit) & 1) << (2 * bit);
+    }
+    spread
+}
+
+/// Collect the even bits of `hash`
+fn deinterleave(hash: u64) -> u32 {
+    let mut value = 0u32;
+    for bit in 0..32 {
+        value |= (((hash >> (2 * bit)) & 1) as u32) << bit;
+    }
+    value
+}
+
+/// Distance in meters along a meridian between two latitudes
+fn latitude_distance(lat1: f64, lat2: f64) -> f64 {
+    EARTH_RADIUS_IN_METERS * (lat2.to_radians() - lat1.to_radians()).abs()
+}
+
+/// Great-circle distance in meters between two locations
+pub fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let v = ((lon2.to_radians() - lon1.to_radians()) / 2.0).sin();
+    // Same longitude: only the latitudes differ
+    if v == 0.0 {
+        return latitude_distance(lat1, lat2);
+    }
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let a = u * u + lat1.cos() * lat2.cos() * v * v;
+    2.0 * EARTH_RADIUS_IN_METERS * a.sqrt().asin()
+}
+
+/// Meters per distance unit (`m`, `km`, `ft`, `mi`, any case)
+pub fn unit_factor(unit: &[u8]) -> Option<f64> {
+    match unit.to_ascii_lowercase().as_slice() {
+        b"m" => Some(1.0),
+        b"km" => Some(1000.0),
+        b"ft" => Some(0.3048),
+        b"mi" => Some(1609.34),
+        _ => None,
+    }
+}
+
+/// Area searched by GEOSEARCH, in meters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    /// BYRADIUS: points at most this far from the center
+    Radius(f64),
+    
+    /// BYBOX: an axis-aligned box centered on the center
+    Box { width: f64, height: f64 },
+}
+
+impl Shape {
+    /// Distance from `center` to `point` when the point lies in the shape
+    pub fn distance_if_inside(&self, center: (f64, f64), point: (f64, f64)) -> Option<f64> {
+        let (center_lon, center_lat) = center;
+        let (lon, lat) = point;
+        match *self {
+            Shape::Radius(radius) => {
+                let distance = distance(center_lon, center_lat, lon, lat);
+                (distance <= radius).then_some(distance)
+            }
+            Shape::Box { width, height } => {
+                if latitude_distance(lat, center_lat) > height / 2.0 {
+                    return None;
+                }
+                // East-west extent measured along the point's own parallel
+                if distance(lon, lat, center_lon, lat) > width / 2.0 {
+                    return None;
+                }
+                Some(distance(center_lon, center_lat, lon, lat))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_encode_matches_redis() {
+        assert_eq!(encode(13.361389, 38.115556), Some(3479099956230698));
+        assert_eq!(encode(15.087269, 37.502669), Some(3479447370796909));
+        assert_eq!(encode(200.0, 0.0), None);
+        assert_eq!(encode(0.0, 86.0), None);
+        
+        let (lon, lat) = decode(3479099956230698);
+        assert_eq!(format!("{:.17}", lon), "13.36138933897018433");
+        assert_eq!(format!("{:.17}", lat), "38.11555639549629859");
+        assert_eq!(encode(lon, lat), Some(3479099956230698));
+    }
+    
+    #[test]
+    fn test_distances_and_shapes() {
+        let palermo = decode(3479099956230698);
+        let catania = decode(3479447370796909);
+        assert_eq!(format!("{:.4}", distance(palermo.0, palermo.1, catania.0, catania.1)), "166274.1516");
+        assert_eq!(distance(10.0, 10.0, 10.0, 11.0), latitude_distance(10.0, 11.0));
+        
+        let center = (15.0, 37.0);
+        let within = Shape::Radius(200_000.0).distance_if_inside(center, catania).unwrap();
+        assert_eq!(format!("{:.4}", within / 1000.0), "56.4413");
+        assert!(Shape::Radius(100_000.0).distance_if_inside(center, palermo).is_none());
+        assert!(Shape::Box { width: 400_000.0, height: 400_000.0 }.distance_if_inside(center, palermo).is_some());
+        assert!(Shape::Box { width: 200_000.0, height: 400_000.0 }.distance_if_inside(center, palermo).is_none());
+        
+        assert_eq!(unit_factor(b"KM"), Some(1000.0));
+        assert_eq!(unit_factor(b"yd"), None);
+    }
+}
@@ -0,0 +1,409 @@
+//! HyperLogLog cardinality estimator
+//!
+//! HyperLogLogs are stored as string values in Redis's own layout, so they
+//! round-trip through RDB snapshots and GET/SET like any other string and stay
+//! byte-compatible with Redis:
+//!
+//! - a 16 byte header: `HYLL`, the encoding, 3 unused bytes and a little-endian
+//!   cached cardinality whose top bit marks the cache as stale;
+//! - 16384 six-bit registers, either dense (12288 packed bytes) or sparse
+//!   (run-length opcodes for mostly empty registers).
+//!
+//! New HyperLogLogs start sparse and are promoted to dense once a register
+//! exceeds what the sparse encoding holds or it grows past
+//! `SPARSE_MAX_BYTES`. Dense ones stay dense. Elements are hashed with
+//! MurmurHash64A and counted with the same estimator as Redis, so PFCOUNT
+//! answers match Redis for the same elements.
+//!
+//! Updates decode the registers, apply every element and re-encode once per
+//! command.
+
+use crate::error::{Result, StorageError};
+
+/// Header magic
+const MAGIC: &[u8; 4] = b"HYLL";
+
+/// Header size in bytes
+pub const HEADER_SIZE: usize = 16;
+
+/// Register index bits
+const P: u32 = 14;
+
+/// Number of registers
+pub const REGISTERS: usize = 1 << P;
+
+/// Bits of the hash left after the register index
+const Q: u32 = 64 - P;
+
+/// Highest register value
+const REGISTER_MAX: u8 = 63;
+
+/// Size of a dense HyperLogLog, header included
+pub const DENSE_SIZE: usize = HEADER_SIZE + (REGISTERS * 6).div_ceil(8);
+
+/// Sparse HyperLogLogs are promoted to dense beyond this size (`hll-sparse-max-bytes`)
+pub const SPARSE_MAX_BYTES: usize = 3000;
+
+const ENCODING_DENSE: u8 = 0;
+const ENCODING_SPARSE: u8 = 1;
+
+/// Highest register value and run length a sparse VAL opcode holds
+const SPARSE_VAL_MAX_VALUE: u8 = 32;
+const SPARSE_VAL_MAX_LEN: usize = 4;
+const SPARSE_ZERO_MAX_LEN: usize = 64;
+const SPARSE_XZERO_MAX_LEN: usize = 16384;
+
+/// Stale flag in the last cardinality byte
+const CACHE_STALE: u8 = 0x80;
+
+/// Decoded registers of one HyperLogLog
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+    dense: bool,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HyperLogLog {
+    /// An empty HyperLogLog, sparse encoded
+    pub fn new() -> Self {
+        HyperLogLog { registers: vec![0; REGISTERS], dense: false }
+    }
+    
+    /// Decode a stored value
+    ///
+    /// Values that are not HyperLogLogs at all are `NotHyperLogLog`; sparse
+    /// opcodes that do not cover exactly every register are `CorruptedHyperLogLog`.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+            return Err(StorageError::NotHyperLogLog.into());
+        }
+        match bytes[4] {
+            ENCODING_DENSE if bytes.len() == DENSE_SIZE => {
+                let data = &bytes[HEADER_SIZE..];
+                let registers = (0..REGISTERS).map(|index| dense_get(data, index)).collect();
+                Ok(HyperLogLog { registers, dense: true })
+            }
+            ENCODING_SPARSE => Self::decode_sparse(&bytes[HEADER_SIZE..]),
+            _ => Err(StorageError::NotHyperLogLog.into()),
+        }
+    }
+    
+    fn decode_sparse(data: &[u8]) -> Result<Self> {
+        let mut registers = Vec::with_capacity(REGISTERS);
+        let mut i = 0;
+        while i < data.len() {
+            let op = data[i];
+            let (value, len) = if op & 0xc0 == 0x00 {
+                i += 1;
+                (0, (op & 0x3f) as usize + 1)
+            } else if op & 0xc0 == 0x40 {
+                let Some(&low) = data.get(i + 1) else {
+                    return Err(StorageError::CorruptedHyperLogLog.into());
+                };
+                i += 2;
+                (0, ((((op & 0x3f) as usize) << 8) | low as usize) + 1)
+            } else {
+                i += 1;
+                (((op >> 2) & 0x1f) + 1, (op & 0x03) as usize + 1)
+            };
+            if registers.len() + len > REGISTERS {
+                return Err(StorageError::CorruptedHyperLogLog.into());
+            }
+            registers.resize(registers.len() + len, value);
+        }
+        if registers.len() != REGISTERS {
+            return Err(StorageError::CorruptedHyperLogLog.into());
+        }
+        Ok(HyperLogLog { registers, dense: false })
+    }
+    
+    /// Whether the value uses the dense encoding
+    pub fn is_dense(&self) -> bool {
+        self.dense
+    }
+    
+    /// Add an element, returning whether a register changed
+    pub fn add(&mut self, element: &[u8]) -> bool {
+        let (index, count) = pattern(element);
+        if self.registers[index] < count {
+            self.registers[index] = count;
+            true
+        } else {
+            false
+        }
+    }
+    
+    /// Take the register-wise maximum with another HyperLogLog
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+    }
+    
+    /// Switch to the dense encoding
+    pub fn promote(&mut self) {
+        self.dense = true;
+    }
+    
+    /// Estimate the cardinality
+    pub fn count(&self) -> u64 {
+        let mut histogram = [0u32; Q as usize + 2];
+        for register in &self.registers {
+            histogram[*register as usize] += 1;
+        }
+        
+        // Ertl's improved estimator, as in Redis's hllCount
+        let m = REGISTERS as f64;
+        let mut z = m * tau((m - histogram[Q as usize + 1] as f64) / m);
+        for j in (1..=Q as usize).rev() {
+            z += histogram[j] as f64;
+            z *= 0.5;
+        }
+        z += m * sigma(histogram[0] as f64 / m);
+        (0.721_347_520_444_481_7 * m * m / z).round() as u64
+    }
+    
+    /// Encode with a stale cardinality cache, promoting sparse values that outgrew the encoding
+    pub fn encode(&mut self) -> Vec<u8> {
+        if !self.dense {
+            match self.encode_sparse() {
+                Some(bytes) => return bytes,
+                None => self.dense = true,
+            }
+        }
+        
+        let mut bytes = header(ENCODING_DENSE);
+        bytes.resize(DENSE_SIZE, 0);
+        let data = &mut bytes[HEADER_SIZE..];
+        for (index, register) in self.registers.iter().enumerate() {
+            dense_set(data, index, *register);
+        }
+        bytes
+    }
+    
+    fn encode_sparse(&self) -> Option<Vec<u8>> {
+        let mut bytes = header(ENCODING_SPARSE);
+        let mut index = 0;
+        while index < REGISTERS {
+            let value = self.registers[index];
+            if value > SPARSE_VAL_MAX_VALUE {
+                return None;
+            }
+            let run = self.registers[index..].iter().take_while(|register| **register == value).count();
+            index += run;
+            
+            let mut left = run;
+            while left > 0 {
+                if value == 0 && left > SPARSE_ZERO_MAX_LEN {
+                    let len = left.min(SPARSE_XZERO_MAX_LEN);
+                    bytes.push(0x40 | ((len - 1) >> 8) as u8);
+                    bytes.push(((len - 1) & 0xff) as u8);
+                    left -= len;
+                } else if value == 0 {
+                    bytes.push((left - 1) as u8);
+                    left = 0;
+                } else {
+                    let len = left.min(SPARSE_VAL_MAX_LEN);
+                    bytes.push(0x80 | ((value - 1) << 2) | (len - 1) as u8);
+                    left -= len;
+                }
+            }
+            if bytes.len() > SPARSE_MAX_BYTES {
+                return None;
+            }
+        }
+        Some(bytes)
+    }
+}
+
+/// The cached cardinality of a stored HyperLogLog, if it is still valid
+pub fn cached_count(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() < HEADER_SIZE || bytes[15] & CACHE_STALE != 0 {
+        return None;
+    }
+    Some(u64::from_le_bytes(bytes[8..16].try_into().unwrap()))
+}
+
+/// Store a freshly computed cardinality in a HyperLogLog's header
+pub fn set_cached_count(bytes: &mut [u8], count: u64) {
+    bytes[8..16].copy_from_slice(&count.to_le_bytes());
+}
+
+/// A header with the given encoding and a stale cache
+fn header(encoding: u8) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + 2);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&[encoding, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, CACHE_STALE]);
+    bytes
+}
+
+fn dense_get(data: &[u8], index: usize) -> u8 {
+    let (byte, shift) = (index * 6 / 8, (index * 6) & 7);
+    let low = data[byte] >> shift;
+    let high = data.get(byte + 1).map_or(0, |next| next.checked_shl(8 - shift as u32).unwrap_or(0));
+    (low | high) & REGISTER_MAX
+}
+
+fn dense_set(data: &mut [u8], index: usize, value: u8) {
+    let (byte, shift) = (index * 6 / 8, (index * 6) & 7);
+    data[byte] &= !(REGISTER_MAX << shift);
+    data[byte] |= value << shift;
+    if shift > 2 {
+        let spill = 8 - shift;
+        data[byte + 1] &= !(REGISTER_MAX >> spill);
+        data[byte + 1] |= value >> spill;
+    }
+}
+
+/// Register index and run length of leading zeros (plus one) for an element
+fn pattern(element: &[u8]) -> (usize, u8) {
+    let hash = murmurhash64a(element, 0xadc8_3b19);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+    let bits = (hash >> P) | (1 << Q);
+    (index, bits.trailing_zeros() as u8 + 1)
+}
+
+/// MurmurHash64A, as used by Redis for HyperLogLog elements
+fn murmurhash64a(data: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (data.len() as u64).wrapping_mul(M);
+    
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let (mut y, mut z) = (1.0, x);
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if previous == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let (mut y, mut z) = (1.0, 1.0 - x);
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if previous == z {
+            return z / 3.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn with_elements(range: std::ops::Range<u32>) -> HyperLogLog {
+        let mut hll = HyperLogLog::new();
+        for i in range {
+            hll.add(format!("element:{}", i).as_bytes());
+        }
+        hll
+    }
+    
+    #[test]
+    fn test_small_counts_are_exact() {
+        let mut hll = HyperLogLog::new();
+        for element in ["a", "b", "c", "d", "e", "f", "g"] {
+            assert!(hll.add(element.as_bytes()));
+        }
+        assert!(!hll.add(b"a"));
+        assert_eq!(hll.count(), 7);
+        assert_eq!(HyperLogLog::new().count(), 0);
+    }
+    
+    #[test]
+    fn test_estimate_error_is_small() {
+        for n in [1_000u32, 100_000] {
+            let estimate = with_elements(0..n).count() as f64;
+            assert!((estimate - n as f64).abs() / (n as f64) < 0.02, "{} estimated as {}", n, estimate);
+        }
+    }
+    
+    #[test]
+    fn test_encodings_round_trip() {
+        let mut sparse = with_elements(0..100);
+        let bytes = sparse.encode();
+        assert_eq!(bytes[4], ENCODING_SPARSE);
+        assert!(bytes.len() < SPARSE_MAX_BYTES);
+        assert_eq!(HyperLogLog::decode(&bytes).unwrap(), sparse);
+        
+        // Too many distinct registers for the sparse size limit
+        let mut dense = with_elements(0..5_000);
+        let bytes = dense.encode();
+        assert_eq!(bytes.len(), DENSE_SIZE);
+        assert!(dense.is_dense());
+        assert_eq!(HyperLogLog::decode(&bytes).unwrap(), dense);
+        
+        let mut empty = HyperLogLog::new();
+        assert_eq!(&empty.encode()[HEADER_SIZE..], &[0x7f, 0xff]);
+    }
+    
+    #[test]
+    fn test_merge_counts_the_union() {
+        let mut union = with_elements(0..3_000);
+        union.merge(&with_elements(2_000..6_000));
+        let estimate = union.count() as f64;
+        assert!((estimate - 6_000.0).abs() / 6_000.0 < 0.02, "estimated {}", estimate);
+    }
+    
+    #[test]
+    fn test_invalid_values_are_rejected() {
+        assert!(matches!(HyperLogLog::decode(b"not an hll"), Err(crate::error::FerrousError::Storage(StorageError::NotHyperLogLog))));
+        
+        // Sparse opcodes covering fewer registers than there are
+        let mut short = header(ENCODING_SPARSE);
+        short.push(0x00);
+        assert!(matches!(HyperLogLog::decode(&short), Err(crate::error::FerrousError::Storage(StorageError::CorruptedHyperLogLog))));
+    }
+    
+    #[test]
+    fn test_cardinality_cache() {
+        let mut bytes = with_elements(0..10).encode();
+        assert_eq!(cached_count(&bytes), None);
+        set_cached_count(&mut bytes, 10);
+        assert_eq!(cached_count(&bytes), Some(10));
+    }
+}
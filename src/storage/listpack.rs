@@ -0,0 +1,368 @@
+//! Listpack: the compact encoding of small collections
+//!
+//! A listpack keeps all the elements of a small list, set, hash or sorted set
+//! in one contiguous buffer instead of a heap allocation (and a table slot)
+//! per element, as Redis does. Each entry is its length as a varint, its
+//! bytes, and a back-length that lets the buffer be walked from the tail, so
+//! both ends can be pushed to, popped from and iterated without an index.
+//!
+//! Lookups are linear scans, which is why collections move to their general
+//! encoding once they outgrow the thresholds in
+//! [`EncodingLimits`](super::value::EncodingLimits).
+
+use std::ops::Range;
+
+/// Contiguous sequence of byte-string entries
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Listpack {
+    /// Encoded entries, head first
+    data: Vec<u8>,
+    
+    /// Number of entries
+    len: usize,
+}
+
+/// Bytes of the varint encoding of `n`
+fn varint_size(n: usize) -> usize {
+    let bits = usize::BITS - n.leading_zeros();
+    (bits as usize).div_ceil(7).max(1)
+}
+
+/// Append `n` as a varint: 7 bits per byte, least significant first, the high
+/// bit set on every byte but the last
+fn write_varint(n: usize, out: &mut Vec<u8>) {
+    let mut n = n;
+    while n >= 0x80 {
+        out.push((n & 0x7f) as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// Varint starting at `at`, with its size
+fn read_varint(data: &[u8], at: usize) -> (usize, usize) {
+    let mut value = 0;
+    let mut size = 0;
+    loop {
+        let byte = data[at + size];
+        value |= ((byte & 0x7f) as usize) << (7 * size);
+        size += 1;
+        if byte & 0x80 == 0 {
+            return (value, size);
+        }
+    }
+}
+
+/// Append `n` as a back-length: the same groups as a varint in reverse, so
+/// that it is read from its last byte towards the head
+fn write_back_len(n: usize, out: &mut Vec<u8>) {
+    let start = out.len();
+    write_varint(n, out);
+    out[start..].reverse();
+}
+
+/// Back-length ending right before `end`, with its size
+fn read_back_len(data: &[u8], end: usize) -> (usize, usize) {
+    let mut value = 0;
+    let mut size = 0;
+    loop {
+        let byte = data[end - 1 - size];
+        value |= ((byte & 0x7f) as usize) << (7 * size);
+        size += 1;
+        if byte & 0x80 == 0 {
+            return (value, size);
+        }
+    }
+}
+
+/// Append the encoding of an entry
+fn write_entry(element: &[u8], out: &mut Vec<u8>) {
+    write_varint(element.len(), out);
+    out.extend_from_slice(element);
+    write_back_len(varint_size(element.len()) + element.len(), out);
+}
+
+/// Encoded size of an entry
+fn entry_size(element: &[u8]) -> usize {
+    let body = varint_size(element.len()) + element.len();
+    body + varint_size(body)
+}
+
+impl Listpack {
+    /// Create an empty listpack
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    
+    /// Check if there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    
+    /// Bytes of the encoded entries
+    pub fn bytes(&self) -> usize {
+        self.data.len()
+    }
+    
+    /// Bytes held by the listpack, its header included
+    pub fn memory_usage(&self) -> usize {
+        self.data.len() + std::mem::size_of::<Self>()
+    }
+    
+    /// Iterate over the entries, from either end
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { data: &self.data, front: 0, back: self.data.len(), remaining: self.len }
+    }
+    
+    /// Iterate over consecutive pairs of entries, as hashes and sorted sets
+    /// store them
+    pub fn pairs(&self) -> Pairs<'_> {
+        Pairs(self.iter())
+    }
+    
+    /// Entry at `index`
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        let span = self.span(index)?;
+        Some(self.element(span.start).0)
+    }
+    
+    /// Add an entry at the tail
+    pub fn push_back(&mut self, element: &[u8]) {
+        write_entry(element, &mut self.data);
+        self.len += 1;
+    }
+    
+    /// Add an entry at the head
+    pub fn push_front(&mut self, element: &[u8]) {
+        self.insert(0, element);
+    }
+    
+    /// Insert an entry before the one at `index`, or at the tail if `index` is
+    /// the length
+    pub fn insert(&mut self, index: usize, element: &[u8]) {
+        assert!(index <= self.len, "listpack index {} out of range", index);
+        let at = self.span(index).map_or(self.data.len(), |span| span.start);
+        let mut entry = Vec::with_capacity(entry_size(element));
+        write_entry(element, &mut entry);
+        self.data.splice(at..at, entry);
+        self.len += 1;
+    }
+    
+    /// Remove the entry at the head
+    pub fn pop_front(&mut self) -> Option<Vec<u8>> {
+        self.remove(0)
+    }
+    
+    /// Remove the entry at the tail
+    pub fn pop_back(&mut self) -> Option<Vec<u8>> {
+        self.remove(self.len.checked_sub(1)?)
+    }
+    
+    /// Remove the entry at `index`
+    pub fn remove(&mut self, index: usize) -> Option<Vec<u8>> {
+        let span = self.span(index)?;
+        let element = self.element(span.start).0.to_vec();
+        self.data.drain(span);
+        self.len -= 1;
+        Some(element)
+    }
+    
+    /// Replace the entry at `index`. Returns false if there is none.
+    pub fn replace(&mut self, index: usize, element: &[u8]) -> bool {
+        let Some(span) = self.span(index) else { return false };
+        let mut entry = Vec::with_capacity(entry_size(element));
+        write_entry(element, &mut entry);
+        self.data.splice(span, entry);
+        true
+    }
+    
+    /// Index of the first entry equal to `element`
+    pub fn find(&self, element: &[u8]) -> Option<usize> {
+        self.iter().position(|entry| entry == element)
+    }
+    
+    /// Keep only the entries `keep` accepts, given their index
+    pub fn retain(&mut self, mut keep: impl FnMut(usize, &[u8]) -> bool) {
+        let mut kept = Listpack::new();
+        for (index, element) in self.iter().enumerate() {
+            if keep(index, element) {
+                kept.push_back(element);
+            }
+        }
+        *self = kept;
+    }
+    
+    /// Bytes of the entry at `index`, walking from the nearer end
+    fn span(&self, index: usize) -> Option<Range<usize>> {
+        if index >= self.len {
+            return None;
+        }
+        
+        if index < self.len / 2 {
+            let mut start = 0;
+            for _ in 0..index {
+                start = self.element(start).1;
+            }
+            Some(start..self.element(start).1)
+        } else {
+            let mut end = self.data.len();
+            for _ in index + 1..self.len {
+                end = self.element_before(end);
+            }
+            Some(self.element_before(end)..end)
+        }
+    }
+    
+    /// Entry starting at `start`, with the offset of the next one
+    fn element(&self, start: usize) -> (&[u8], usize) {
+        let (len, header) = read_varint(&self.data, start);
+        let body = start + header;
+        (&self.data[body..body + len], body + len + varint_size(header + len))
+    }
+    
+    /// Start of the entry ending at `end`
+    fn element_before(&self, end: usize) -> usize {
+        let (body, size) = read_back_len(&self.data, end);
+        end - size - body
+    }
+}
+
+/// Iterator over the entries of a [`Listpack`]
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    data: &'a [u8],
+    front: usize,
+    back: usize,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a [u8];
+    
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let (len, header) = read_varint(self.data, self.front);
+        let body = self.front + header;
+        self.front = body + len + varint_size(header + len);
+        self.remaining -= 1;
+        Some(&self.data[body..body + len])
+    }
+    
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let (body, size) = read_back_len(self.data, self.back);
+        let start = self.back - size - body;
+        let (len, header) = read_varint(self.data, start);
+        self.back = start;
+        self.remaining -= 1;
+        Some(&self.data[start + header..start + header + len])
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+/// Iterator over the pairs of entries of a [`Listpack`]
+#[derive(Debug, Clone)]
+pub struct Pairs<'a>(Iter<'a>);
+
+impl<'a> Iterator for Pairs<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        Some((self.0.next()?, self.0.next()?))
+    }
+    
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let pairs = self.0.remaining / 2;
+        (pairs, Some(pairs))
+    }
+}
+
+impl<'a> IntoIterator for &'a Listpack {
+    type Item = &'a [u8];
+    type IntoIter = Iter<'a>;
+    
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn elements(listpack: &Listpack) -> Vec<Vec<u8>> {
+        listpack.iter().map(<[u8]>::to_vec).collect()
+    }
+    
+    #[test]
+    fn test_push_pop_both_ends() {
+        let mut listpack = Listpack::new();
+        listpack.push_back(b"b");
+        listpack.push_back(b"c");
+        listpack.push_front(b"a");
+        assert_eq!(elements(&listpack), vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(listpack.iter().rev().next(), Some(&b"c"[..]));
+        
+        assert_eq!(listpack.pop_back(), Some(b"c".to_vec()));
+        assert_eq!(listpack.pop_front(), Some(b"a".to_vec()));
+        assert_eq!(listpack.pop_front(), Some(b"b".to_vec()));
+        assert_eq!(listpack.pop_back(), None);
+        assert_eq!(listpack.bytes(), 0);
+    }
+    
+    #[test]
+    fn test_entries_of_every_size() {
+        // Lengths around the varint boundaries of the header and the back-length
+        let sizes = [0, 1, 125, 126, 127, 128, 16381, 16382, 16383, 16384, 70000];
+        let mut listpack = Listpack::new();
+        for (i, &size) in sizes.iter().enumerate() {
+            listpack.push_back(&vec![i as u8; size]);
+        }
+        
+        let forward: Vec<usize> = listpack.iter().map(<[u8]>::len).collect();
+        assert_eq!(forward, sizes);
+        let backward: Vec<usize> = listpack.iter().rev().map(<[u8]>::len).collect();
+        assert!(backward.iter().eq(sizes.iter().rev()));
+        assert_eq!(listpack.bytes(), sizes.iter().map(|&size| entry_size(&vec![0; size])).sum::<usize>());
+        
+        for (i, &size) in sizes.iter().enumerate() {
+            assert_eq!(listpack.get(i), Some(&vec![i as u8; size][..]));
+        }
+        assert_eq!(listpack.get(sizes.len()), None);
+    }
+    
+    #[test]
+    fn test_edit_in_the_middle() {
+        let mut listpack = Listpack::new();
+        for element in [&b"a"[..], b"b", b"c", b"d"] {
+            listpack.push_back(element);
+        }
+        
+        listpack.insert(2, b"x");
+        assert!(listpack.replace(0, &[b'y'; 200]));
+        assert!(!listpack.replace(5, b"z"));
+        assert_eq!(listpack.remove(3), Some(b"c".to_vec()));
+        assert_eq!(listpack.find(b"x"), Some(2));
+        assert_eq!(elements(&listpack), vec![vec![b'y'; 200], b"b".to_vec(), b"x".to_vec(), b"d".to_vec()]);
+        
+        listpack.retain(|index, element| index == 0 || element == b"d");
+        assert_eq!(listpack.len(), 2);
+        assert_eq!(listpack.pairs().collect::<Vec<_>>(), vec![(&[b'y'; 200][..], &b"d"[..])]);
+    }
+}
@@ -0,0 +1,218 @@
+//! LRU access clock
+//!
+//! Every stored value carries a coarse access timestamp used for idle time and
+//! eviction decisions. All clock updates go through [`record_access`], which is
+//! called by both the server dispatch and the script bridge, so a key read via
+//! `redis.call` is touched exactly like a key read by a client. Clients that set
+//! `CLIENT NO-TOUCH ON` run their commands (and any scripts they invoke) inside
+//! a [`NoTouchGuard`], which suppresses the updates.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::protocol::RespFrame;
+use crate::storage::{DatabaseIndex, StorageEngine};
+
+static CLOCK_EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// Reading of the LRU clock at startup, leaving room for restored idle times
+/// (RESTORE IDLETIME, RDB files) that predate the process
+const CLOCK_START_MS: u64 = 1 << 40;
+
+thread_local! {
+    /// Set while executing on behalf of a NO-TOUCH client
+    static NO_TOUCH: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Milliseconds elapsed on the process-wide LRU clock
+pub fn now_ms() -> u64 {
+    CLOCK_START_MS + CLOCK_EPOCH.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
+
+/// Access timestamp that can be updated through a shared reference
+pub struct AccessClock(AtomicU64);
+
+impl AccessClock {
+    /// Create a clock stamped with the current time
+    pub fn new() -> Self {
+        AccessClock(AtomicU64::new(now_ms()))
+    }
+    
+    /// Record an access now
+    pub fn touch(&self) {
+        self.0.store(now_ms(), Ordering::Relaxed);
+    }
+    
+    /// LRU clock value of the last access
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+    
+    /// Time since the last access
+    pub fn idle_time(&self) -> Duration {
+        Duration::from_millis(now_ms().saturating_sub(self.get()))
+    }
+    
+    /// Backdate the last access so the key has been idle for `idle`
+    pub fn set_idle_time(&self, idle: Duration) {
+        self.0.store(now_ms().saturating_sub(idle.as_millis() as u64), Ordering::Relaxed);
+    }
+}
+
+impl Default for AccessClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for AccessClock {
+    fn clone(&self) -> Self {
+        AccessClock(AtomicU64::new(self.get()))
+    }
+}
+
+impl std::fmt::Debug for AccessClock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AccessClock({})", self.get())
+    }
+}
+
+impl PartialEq for AccessClock {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl PartialOrd for AccessClock {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.get().partial_cmp(&other.get())
+    }
+}
+
+/// Suppresses access clock updates on this thread until dropped
+pub struct NoTouchGuard {
+    previous: bool,
+}
+
+impl NoTouchGuard {
+    /// Enter a scope in which accesses are (or are not) recorded
+    pub fn new(no_touch: bool) -> Self {
+        let previous = NO_TOUCH.with(|flag| flag.replace(no_touch));
+        NoTouchGuard { previous }
+    }
+}
+
+impl Drop for NoTouchGuard {
+    fn drop(&mut self) {
+        NO_TOUCH.with(|flag| flag.set(self.previous));
+    }
+}
+
+/// Check whether accesses are currently recorded on this thread
+pub fn touch_enabled() -> bool {
+    !NO_TOUCH.with(|flag| flag.get())
+}
+
+/// Keys read or written by a command, by argument position
+///
+/// Commands whose lookups must not count as an access (TYPE, TTL, OBJECT, ...)
+/// and commands without key arguments return no keys. Scripts are not listed:
+/// the keys they touch are recorded by their own redis.call invocations.
+pub fn command_keys<T: AsRef<[u8]>>(args: &[T]) -> Vec<&[u8]> {
+    if args.len() < 2 {
+        return Vec::new();
+    }
+    
+    let name = String::from_utf8_lossy(args[0].as_ref()).to_uppercase();
+    let rest = &args[1..];
+    let keys: Vec<&T> = match name.as_str() {
+        "DEL" | "UNLINK" | "EXISTS" | "MGET" | "TOUCH" | "WATCH" |
+        "SINTER" | "SUNION" | "SDIFF" | "SINTERSTORE" | "SUNIONSTORE" | "SDIFFSTORE" |
+        "PFCOUNT" | "PFMERGE" => rest.iter().collect(),
+        "MSET" | "MSETNX" => rest.iter().step_by(2).collect(),
+        "RENAME" | "RENAMENX" | "SMOVE" | "LMOVE" | "RPOPLPUSH" | "BLMOVE" | "BRPOPLPUSH" | "COPY" |
+        "GEOSEARCHSTORE" => rest.iter().take(2).collect(),
+        "BLPOP" | "BRPOP" => rest[..rest.len() - 1].iter().collect(),
+        "LMPOP" | "BLMPOP" | "SINTERCARD" => {
+            // BLMPOP takes the timeout before numkeys
+            let rest = if name == "BLMPOP" { &rest[1..] } else { rest };
+            let numkeys = rest.first()
+                .and_then(|numkeys| std::str::from_utf8(numkeys.as_ref()).ok()?.parse::<usize>().ok())
+                .unwrap_or(0);
+            rest.iter().skip(1).take(numkeys).collect()
+        }
+        "PING" | "ECHO" | "INFO" | "CONFIG" | "CLIENT" | "SELECT" | "AUTH" | "QUIT" |
+        "DBSIZE" | "FLUSHDB" | "FLUSHALL" | "KEYS" | "SCAN" | "RANDOMKEY" |
+        "SAVE" | "BGSAVE" | "BGREWRITEAOF" | "LASTSAVE" | "TIME" | "COMMAND" | "SLOWLOG" | "MONITOR" |
+        "SUBSCRIBE" | "UNSUBSCRIBE" | "PSUBSCRIBE" | "PUNSUBSCRIBE" | "PUBLISH" | "PUBSUB" |
+        "MULTI" | "EXEC" | "DISCARD" | "UNWATCH" |
+        "EVAL" | "EVALSHA" | "EVAL_RO" | "EVALSHA_RO" | "SCRIPT" | "FUNCTION" | "FCALL" | "FCALL_RO" |
+        "SHUTDOWN" | "REPLICAOF" | "SLAVEOF" | "FAILOVER" | "SYNC" | "PSYNC" | "REPLCONF" |
+        "OBJECT" | "TYPE" | "TTL" | "PTTL" | "EXPIRETIME" | "PEXPIRETIME" | "MEMORY" | "DEBUG" |
+        "XREAD" | "XREADGROUP" => Vec::new(),
+        _ => vec![&rest[0]],
+    };
+    
+    keys.into_iter().map(|k| k.as_ref()).collect()
+}
+
+/// Record an access to the keys of a command (the single clock update path)
+pub fn record_access<T: AsRef<[u8]>>(storage: &StorageEngine, db: DatabaseIndex, args: &[T]) {
+    if !touch_enabled() {
+        return;
+    }
+    
+    let keys = command_keys(args);
+    if !keys.is_empty() {
+        let _ = storage.touch_keys(db, &keys);
+    }
+}
+
+/// Record an access for a command given as RESP frames
+pub fn record_frame_access(storage: &StorageEngine, db: DatabaseIndex, parts: &[RespFrame]) {
+    if !touch_enabled() {
+        return;
+    }
+    
+    let args: Vec<&[u8]> = parts.iter()
+        .filter_map(|part| match part {
+            RespFrame::BulkString(Some(bytes)) => Some(bytes.as_slice()),
+            _ => None,
+        })
+        .collect();
+    record_access(storage, db, &args);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_command_keys() {
+        assert_eq!(command_keys(&["GET", "a"]), vec![b"a".as_slice()]);
+        assert_eq!(command_keys(&["MSET", "a", "1", "b", "2"]), vec![b"a".as_slice(), b"b".as_slice()]);
+        assert_eq!(command_keys(&["BLPOP", "a", "b", "0"]), vec![b"a".as_slice(), b"b".as_slice()]);
+        assert_eq!(command_keys(&["BLMPOP", "0", "2", "a", "b", "LEFT"]), vec![b"a".as_slice(), b"b".as_slice()]);
+        assert_eq!(command_keys(&["LMPOP", "1", "a", "RIGHT", "COUNT", "2"]), vec![b"a".as_slice()]);
+        assert_eq!(command_keys(&["SINTERCARD", "2", "a", "b", "LIMIT", "1"]), vec![b"a".as_slice(), b"b".as_slice()]);
+        assert!(command_keys(&["TTL", "a"]).is_empty());
+        assert!(command_keys(&["PING"]).is_empty());
+    }
+    
+    #[test]
+    fn test_no_touch_guard_nests() {
+        assert!(touch_enabled());
+        {
+            let _outer = NoTouchGuard::new(true);
+            assert!(!touch_enabled());
+            {
+                let _inner = NoTouchGuard::new(false);
+                assert!(touch_enabled());
+            }
+            assert!(!touch_enabled());
+        }
+        assert!(touch_enabled());
+    }
+}
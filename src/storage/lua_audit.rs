@@ -0,0 +1,295 @@
+//! Script write audit log
+//!
+//! When `lua-audit-log` is set, every write command a script executes through
+//! `redis.call`/`redis.pcall` is appended to the log with the script's SHA1 (or
+//! `function:<name>` for FCALL), the database and the command's outcome, and a
+//! closing line records how the script itself ended. Writes are logged as they
+//! happen, so a script that was killed or failed halfway still leaves a record
+//! of what it changed.
+//!
+//! Lines are handed to a background writer thread and never block the script.
+//! When the file grows past `lua-audit-log-max-size` it is renamed to
+//! `<path>.1` (replacing the previous one) and a fresh file is started.
+//!
+//! ```text
+//! 1760522400.123456 e0e1f9fabfc9d4800c877a703b823ac0578ff8db db=0 OK "SET" "k" "v"
+//! 1760522400.123462 e0e1f9fabfc9d4800c877a703b823ac0578ff8db db=0 err="WRONGTYPE Operation against a key holding the wrong kind of value" "LPUSH" "k" "x"
+//! 1760522400.123470 e0e1f9fabfc9d4800c877a703b823ac0578ff8db script error: ERR boom
+//! ```
+
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::Result;
+use crate::monitor::escape_string;
+use crate::protocol::RespFrame;
+use crate::storage::commands::executor::Reply;
+use crate::storage::commands::flags;
+use crate::storage::commands::lua::script_error_message;
+use crate::storage::DatabaseIndex;
+
+/// Default size at which the audit log is rotated (64MB)
+pub const DEFAULT_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Messages handled by the writer thread
+enum AuditMessage {
+    Line(String),
+    Flush(Sender<()>),
+}
+
+/// Channel to the writer thread, or None while auditing is disabled
+static AUDIT: Mutex<Option<Sender<AuditMessage>>> = Mutex::new(None);
+
+thread_local! {
+    /// Identifier of the audited script running on this thread
+    static SCRIPT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Start (or with `None`, stop) auditing script writes to `path`
+///
+/// A previously configured log is flushed and closed. `max_size` of 0 disables
+/// rotation.
+pub fn configure(path: Option<PathBuf>, max_size: u64) -> io::Result<()> {
+    let sender = match path {
+        Some(path) => {
+            let writer = AuditWriter::open(path, max_size)?;
+            let (sender, receiver) = mpsc::channel();
+            thread::Builder::new()
+                .name("lua-audit".to_string())
+                .spawn(move || writer.run(receiver))?;
+            Some(sender)
+        }
+        None => None,
+    };
+    
+    let previous = std::mem::replace(&mut *AUDIT.lock().unwrap(), sender);
+    if let Some(previous) = previous {
+        flush_sender(&previous);
+    }
+    Ok(())
+}
+
+/// Check whether script writes are being audited
+pub fn is_enabled() -> bool {
+    AUDIT.lock().unwrap().is_some()
+}
+
+/// Wait until every line queued so far has been written
+pub fn flush() {
+    let sender = AUDIT.lock().unwrap().clone();
+    if let Some(sender) = sender {
+        flush_sender(&sender);
+    }
+}
+
+fn flush_sender(sender: &Sender<AuditMessage>) {
+    let (ack, done) = mpsc::channel();
+    if sender.send(AuditMessage::Flush(ack)).is_ok() {
+        let _ = done.recv();
+    }
+}
+
+/// Queue a line for the writer thread
+fn send_line(line: String) {
+    if let Some(sender) = AUDIT.lock().unwrap().as_ref() {
+        let _ = sender.send(AuditMessage::Line(line));
+    }
+}
+
+/// Audit record of one script invocation on this thread
+///
+/// Dropping the scope without [`AuditScope::finish`] records the script as
+/// aborted.
+pub struct AuditScope {
+    previous: Option<String>,
+    finished: bool,
+}
+
+impl AuditScope {
+    /// Attribute writes on this thread to the script named by `script` until the scope ends
+    ///
+    /// Returns None (without calling `script`) when auditing is disabled.
+    pub fn begin(script: impl FnOnce() -> String) -> Option<Self> {
+        if !is_enabled() {
+            return None;
+        }
+        let previous = SCRIPT.with(|s| s.borrow_mut().replace(script()));
+        Some(AuditScope { previous, finished: false })
+    }
+    
+    /// Record how the script ended and close the scope
+    pub fn finish(mut self, result: &Result<RespFrame>) {
+        let outcome = match result {
+            Ok(_) => "ok".to_string(),
+            Err(e) => format!("error: {}", single_line(&script_error_message(e))),
+        };
+        self.close(&outcome);
+    }
+    
+    fn close(&mut self, outcome: &str) {
+        self.finished = true;
+        let script = SCRIPT.with(|s| std::mem::replace(&mut *s.borrow_mut(), self.previous.take()));
+        if let Some(script) = script {
+            send_line(format!("{} {} script {}\n", timestamp(), script, outcome));
+        }
+    }
+}
+
+impl Drop for AuditScope {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.close("aborted");
+        }
+    }
+}
+
+/// Whether an audited script is running on this thread
+pub(crate) fn is_auditing() -> bool {
+    SCRIPT.with(|s| s.borrow().is_some())
+}
+
+/// Check whether a command run on this thread would be audited
+///
+/// Checked before the command runs (which consumes its arguments), so the
+/// arguments are only copied for audited writes.
+pub fn wants<T: AsRef<[u8]>>(args: &[T]) -> bool {
+    let Some(name) = args.first() else { return false };
+    is_auditing()
+        && flags::is_write_command(&String::from_utf8_lossy(name.as_ref()).to_uppercase())
+}
+
+/// Record a write command executed by the current script
+pub fn record_write(db: DatabaseIndex, args: &[Vec<u8>], result: &Result<Reply>) {
+    let Some(script) = SCRIPT.with(|s| s.borrow().clone()) else { return };
+    send_line(format_write(&script, db, args, result));
+}
+
+fn format_write(script: &str, db: DatabaseIndex, args: &[Vec<u8>], result: &Result<Reply>) -> String {
+    let outcome = match result {
+        Ok(_) => "OK".to_string(),
+        Err(e) => format!("err=\"{}\"", escape_string(script_error_message(e).as_bytes())),
+    };
+    let mut line = format!("{} {} db={} {}", timestamp(), script, db, outcome);
+    for arg in args {
+        line.push_str(&format!(" \"{}\"", escape_string(arg)));
+    }
+    line.push('\n');
+    line
+}
+
+/// Log lines cannot carry newlines
+fn single_line(message: &str) -> String {
+    message.replace(['\r', '\n'], " ")
+}
+
+/// Current time as `seconds.micros`, like MONITOR
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("{}.{:06}", now.as_secs(), now.subsec_micros())
+}
+
+/// Appends lines to the audit file, rotating it by size
+struct AuditWriter {
+    path: PathBuf,
+    max_size: u64,
+    file: BufWriter<File>,
+    size: u64,
+}
+
+impl AuditWriter {
+    fn open(path: PathBuf, max_size: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(AuditWriter { path, max_size, file: BufWriter::new(file), size })
+    }
+    
+    /// Write lines until every sender is gone, flushing whenever the queue drains
+    fn run(mut self, receiver: Receiver<AuditMessage>) {
+        while let Ok(message) = receiver.recv() {
+            self.handle(message);
+            while let Ok(message) = receiver.try_recv() {
+                self.handle(message);
+            }
+            if let Err(e) = self.file.flush() {
+                eprintln!("Failed to write script audit log {}: {}", self.path.display(), e);
+            }
+        }
+    }
+    
+    fn handle(&mut self, message: AuditMessage) {
+        match message {
+            AuditMessage::Line(line) => {
+                if let Err(e) = self.write_line(&line) {
+                    eprintln!("Failed to write script audit log {}: {}", self.path.display(), e);
+                }
+            }
+            AuditMessage::Flush(ack) => {
+                let _ = self.file.flush();
+                let _ = ack.send(());
+            }
+        }
+    }
+    
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.max_size > 0 && self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+    
+    /// Move the current file to `<path>.1` and start a new one
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        fs::rename(&self.path, rotated_path(&self.path))?;
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.file = BufWriter::new(file);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Path the audit log is rotated to
+pub fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::FerrousError;
+    
+    #[test]
+    fn test_write_line_format() {
+        let args = vec![b"SET".to_vec(), b"k".to_vec(), b"a b\n".to_vec()];
+        let line = format_write("abc", 3, &args, &Ok(Reply::Status("OK".to_string())));
+        assert!(line.ends_with(" abc db=3 OK \"SET\" \"k\" \"a b\\n\"\n"));
+        
+        let err = Err(FerrousError::Internal("bad\nthing".to_string()));
+        let line = format_write("abc", 0, &args[..1], &err);
+        assert!(line.contains(" abc db=0 err=\""));
+        assert_eq!(line.matches('\n').count(), 1);
+    }
+    
+    #[test]
+    fn test_rotation_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let mut writer = AuditWriter::open(path.clone(), 10).unwrap();
+        writer.write_line("first line\n").unwrap();
+        writer.write_line("second\n").unwrap();
+        writer.file.flush().unwrap();
+        
+        assert_eq!(fs::read_to_string(rotated_path(&path)).unwrap(), "first line\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+    }
+}
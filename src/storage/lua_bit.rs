@@ -0,0 +1,109 @@
+//! LuaBitOp `bit` library for scripts
+//!
+//! Redis bundles LuaBitOp, and rate limiters and hashing scripts rely on it.
+//! Like LuaBitOp, every operation works on 32-bit integers: arguments are
+//! normalised with `tobit` (rounded, then wrapped modulo 2^32) and results are
+//! signed 32-bit numbers. Shift counts use their low 5 bits.
+
+use mlua::{Lua, Result as LuaResult, Table, Value as LuaValue, Variadic};
+
+/// 2^52 + 2^51: adding it leaves a number's low 32 bits in the mantissa, rounded
+const TOBIT_BIAS: f64 = 6755399441055744.0;
+
+/// Create the `bit` table
+pub fn create_bit_table(lua: &Lua) -> LuaResult<Table> {
+    let bit = lua.create_table()?;
+    
+    bit.set("tobit", lua.create_function(|lua, x: LuaValue| Ok(to_bit(lua, x, 1, "tobit")? as f64))?)?;
+    bit.set("bnot", lua.create_function(|lua, x: LuaValue| Ok(!to_bit(lua, x, 1, "bnot")? as f64))?)?;
+    bit.set("bswap", lua.create_function(|lua, x: LuaValue| Ok(to_bit(lua, x, 1, "bswap")?.swap_bytes() as f64))?)?;
+    
+    for (name, op) in [
+        ("band", (|a, b| a & b) as fn(i32, i32) -> i32),
+        ("bor", |a, b| a | b),
+        ("bxor", |a, b| a ^ b),
+    ] {
+        bit.set(name, lua.create_function(move |lua, args: Variadic<LuaValue>| {
+            let mut args = args.into_iter();
+            let mut result = to_bit(lua, args.next().unwrap_or(LuaValue::Nil), 1, name)?;
+            for (i, arg) in args.enumerate() {
+                result = op(result, to_bit(lua, arg, i + 2, name)?);
+            }
+            Ok(result as f64)
+        })?)?;
+    }
+    
+    for (name, op) in [
+        ("lshift", (|x: i32, n| ((x as u32) << n) as i32) as fn(i32, u32) -> i32),
+        ("rshift", |x, n| ((x as u32) >> n) as i32),
+        ("arshift", |x, n| x >> n),
+        ("rol", |x, n| x.rotate_left(n)),
+        ("ror", |x, n| x.rotate_right(n)),
+    ] {
+        bit.set(name, lua.create_function(move |lua, (x, n): (LuaValue, LuaValue)| {
+            let x = to_bit(lua, x, 1, name)?;
+            let n = to_bit(lua, n, 2, name)? as u32 & 31;
+            Ok(op(x, n) as f64)
+        })?)?;
+    }
+    
+    bit.set("tohex", lua.create_function(|lua, (x, n): (LuaValue, Option<LuaValue>)| {
+        let x = to_bit(lua, x, 1, "tohex")? as u32;
+        let n = match n {
+            Some(LuaValue::Nil) | None => 8,
+            Some(n) => to_bit(lua, n, 2, "tohex")?,
+        };
+        Ok(to_hex(x, n))
+    })?)?;
+    
+    Ok(bit)
+}
+
+/// Normalise an argument to a 32-bit integer like LuaBitOp's `barg`
+fn to_bit(lua: &Lua, value: LuaValue, arg: usize, name: &str) -> LuaResult<i32> {
+    let type_name = value.type_name();
+    match lua.coerce_number(value)? {
+        Some(n) => Ok((n + TOBIT_BIAS).to_bits() as u32 as i32),
+        None => Err(mlua::Error::RuntimeError(format!(
+            "bad argument #{} to '{}' (number expected, got {})", arg, name, type_name))),
+    }
+}
+
+/// Format the low `n` hex digits of `x` (at most 8), uppercase when `n` is negative
+fn to_hex(x: u32, n: i32) -> String {
+    let digits = n.unsigned_abs().min(8) as usize;
+    if digits == 0 {
+        return String::new();
+    }
+    let masked = if digits == 8 { x } else { x & ((1u32 << (digits * 4)) - 1) };
+    if n < 0 {
+        format!("{:01$X}", masked, digits)
+    } else {
+        format!("{:01$x}", masked, digits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_to_hex() {
+        assert_eq!(to_hex(0xdeadbeef, 8), "deadbeef");
+        assert_eq!(to_hex(0xdeadbeef, -4), "BEEF");
+        assert_eq!(to_hex(1, 2), "01");
+        assert_eq!(to_hex(0xff, 12), "000000ff");
+        assert_eq!(to_hex(0xff, 0), "");
+    }
+    
+    #[test]
+    fn test_tobit_wraps_and_rounds() {
+        let lua = Lua::new();
+        let tobit = |n: f64| to_bit(&lua, LuaValue::Number(n), 1, "tobit").unwrap();
+        assert_eq!(tobit(4294967295.0), -1);
+        assert_eq!(tobit(4294967296.0 + 7.0), 7);
+        assert_eq!(tobit(-1.0), -1);
+        assert_eq!(tobit(2.5), 2);
+        assert_eq!(tobit(3.5), 4);
+    }
+}
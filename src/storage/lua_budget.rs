@@ -0,0 +1,270 @@
+//! Execution budgets for scripts
+//!
+//! A script that never finishes would hold its server thread forever, so each
+//! run gets a budget: Lua instructions (`lua-max-instructions`) and call-stack
+//! depth (`lua-max-call-depth`), where 0 disables a limit. Both default to 0
+//! as in Redis, which leaves runaway scripts to SCRIPT KILL once they are busy.
+//! The Lua heap is capped separately by `lua-memory-limit`. A user with a
+//! script time quota also gets the time left in it as a run time allowance
+//! (see [`lua_quota`](crate::storage::lua_quota)), and unlike `lua-time-limit`
+//! that stops the script.
+//!
+//! Wall-clock time is handled the Redis way: a script running longer than
+//! `lua-time-limit` is not stopped but becomes busy, and SCRIPT KILL may then
+//! end it (see [`lua_busy`]).
+//!
+//! Budgets are checked by the `lua_stats` count hook, so a limit or a kill is
+//! noticed within [`INSTRUCTION_SAMPLE`](crate::storage::lua_stats::INSTRUCTION_SAMPLE)
+//! instructions. Time spent inside redis.call counts, but a command is never
+//! interrupted. Once a limit is exceeded the script fails at every later check
+//! and `pcall`/`xpcall` re-raise the error, so a script cannot catch its way
+//! past the budget.
+
+use std::cell::Cell;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use mlua::{Function, Lua, Result as LuaResult, Table};
+
+use crate::error::{FerrousError, Result, ScriptError};
+use crate::protocol::RespFrame;
+use crate::storage::{lua_busy, lua_dirty};
+
+/// Error raised inside a script stopped by SCRIPT KILL
+const KILLED_MESSAGE: &str = "Script killed by user with SCRIPT KILL";
+
+/// `pcall` and `xpcall` wrappers that re-raise once the budget is exceeded
+const PROTECTED_CALLS: &str = r#"
+local pcall, xpcall, error, exceeded = pcall, xpcall, error, ...
+local function rethrow(...)
+    local message = exceeded()
+    if message then
+        error(message, 0)
+    end
+    return ...
+end
+return function(...) return rethrow(pcall(...)) end,
+    function(f, handler) return rethrow(xpcall(f, handler)) end
+"#;
+
+/// Limits for one script run (0 = unlimited)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Budget {
+    /// Milliseconds after which the script is busy and may be killed
+    pub time_limit_ms: u64,
+    
+    /// Lua instructions the script may execute
+    pub max_instructions: u64,
+    
+    /// Deepest Lua call stack the script may reach, in frames
+    pub max_call_depth: usize,
+    
+    /// Milliseconds the script may run before its user's time quota is spent
+    pub cpu_allowance_ms: u64,
+}
+
+/// The limit a script exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// `lua-max-instructions`
+    Instructions(u64),
+    
+    /// `lua-max-call-depth`
+    CallDepth(usize),
+    
+    /// What was left of the user's script time quota
+    CpuAllowance(u64),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Instructions(n) => write!(f, "instruction limit of {}", n),
+            Limit::CallDepth(n) => write!(f, "call depth limit of {}", n),
+            Limit::CpuAllowance(ms) => write!(f, "per-user script time allowance of {} ms", ms),
+        }
+    }
+}
+
+impl Budget {
+    /// The first limit exceeded after `instructions` at call depth `depth`, `elapsed` into the run
+    fn exceeded_by(&self, instructions: u64, depth: usize, elapsed: Duration) -> Option<Limit> {
+        if self.max_instructions > 0 && instructions > self.max_instructions {
+            Some(Limit::Instructions(self.max_instructions))
+        } else if self.max_call_depth > 0 && depth > self.max_call_depth {
+            Some(Limit::CallDepth(self.max_call_depth))
+        } else if self.cpu_allowance_ms > 0 && elapsed > Duration::from_millis(self.cpu_allowance_ms) {
+            Some(Limit::CpuAllowance(self.cpu_allowance_ms))
+        } else {
+            None
+        }
+    }
+}
+
+/// Budget of the script running on a thread
+#[derive(Clone, Copy)]
+struct Running {
+    budget: Budget,
+    started: Instant,
+    exceeded: Option<Limit>,
+    killed: bool,
+    
+    /// When other clients were last serviced while this script was busy
+    serviced: Option<Instant>,
+}
+
+impl Running {
+    /// Whether the script has run past its time limit
+    fn is_busy(&self) -> bool {
+        self.budget.time_limit_ms > 0 && self.started.elapsed() > Duration::from_millis(self.budget.time_limit_ms)
+    }
+    
+    /// Whether other clients are due to be serviced again
+    fn service_due(&self) -> bool {
+        self.is_busy() && self.serviced.is_none_or(|last| last.elapsed() >= lua_busy::SERVICE_INTERVAL)
+    }
+}
+
+thread_local! {
+    /// Budget of the script running on this thread
+    static CURRENT: Cell<Option<Running>> = const { Cell::new(None) };
+}
+
+/// Budget enforcement for one script run
+pub struct BudgetScope {
+    previous: Option<Running>,
+}
+
+impl BudgetScope {
+    /// Start enforcing `budget` for the script about to run on this thread
+    pub fn begin(budget: Budget) -> Self {
+        let running = Running { budget, started: Instant::now(), exceeded: None, killed: false, serviced: None };
+        BudgetScope { previous: CURRENT.with(|current| current.replace(Some(running))) }
+    }
+    
+    /// End the run, reporting a kill or the exceeded limit in place of the script's own result
+    pub fn finish(self, result: Result<RespFrame>) -> Result<RespFrame> {
+        if killed() {
+            return Err(FerrousError::Script(ScriptError::Killed));
+        }
+        match exceeded() {
+            Some(limit) => Err(FerrousError::Script(ScriptError::BudgetExceeded(limit.to_string()))),
+            None => result,
+        }
+    }
+}
+
+impl Drop for BudgetScope {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.previous));
+    }
+}
+
+/// Whether a budget is being enforced on this thread
+pub(crate) fn is_running() -> bool {
+    CURRENT.with(|current| current.get().is_some())
+}
+
+/// The limit exceeded by the script running on this thread, if any
+pub fn exceeded() -> Option<Limit> {
+    CURRENT.with(|current| current.get().and_then(|running| running.exceeded))
+}
+
+/// Whether the script running on this thread was stopped by SCRIPT KILL
+pub fn killed() -> bool {
+    CURRENT.with(|current| current.get().is_some_and(|running| running.killed))
+}
+
+/// Whether a script running on this thread has run past its time limit
+pub fn is_busy() -> bool {
+    CURRENT.with(|current| current.get().is_some_and(|running| running.is_busy()))
+}
+
+/// Stop the busy script running on this thread, for SCRIPT KILL
+///
+/// Fails with NOTBUSY when no script has run past its time limit, and with
+/// UNKILLABLE once the script has written to the dataset.
+pub fn kill() -> std::result::Result<(), ScriptError> {
+    CURRENT.with(|current| {
+        let mut running = current.get().filter(Running::is_busy).ok_or(ScriptError::NotBusy)?;
+        if lua_dirty::dirty_count() > 0 {
+            return Err(ScriptError::Unkillable);
+        }
+        running.killed = true;
+        current.set(Some(running));
+        Ok(())
+    })
+}
+
+/// Fail if the script running on this thread is over its budget or was killed
+///
+/// Called from the count hook with the instructions run and the current call
+/// depth. A busy script also services other clients here.
+pub(crate) fn check(instructions: u64, depth: usize) -> LuaResult<()> {
+    let service = CURRENT.with(|current| {
+        let Some(mut running) = current.get() else { return false };
+        if running.exceeded.is_none() {
+            running.exceeded = running.budget.exceeded_by(instructions, depth, running.started.elapsed());
+        }
+        let due = running.exceeded.is_none() && !running.killed && running.service_due();
+        if due {
+            running.serviced = Some(Instant::now());
+        }
+        current.set(Some(running));
+        due
+    });
+    if service {
+        lua_busy::service();
+    }
+    
+    match stop_message() {
+        Some(message) => Err(mlua::Error::RuntimeError(message)),
+        None => Ok(()),
+    }
+}
+
+/// Error raised inside a script that has to stop, if it does
+fn stop_message() -> Option<String> {
+    if killed() {
+        return Some(KILLED_MESSAGE.to_string());
+    }
+    exceeded().map(|limit| format!("Script exceeded its {}", limit))
+}
+
+/// Replace `pcall` and `xpcall` in `globals` with versions that re-raise budget and kill errors
+pub fn register(lua: &Lua, globals: &Table) -> LuaResult<()> {
+    let exceeded = lua.create_function(|_, ()| Ok(stop_message()))?;
+    let (pcall, xpcall): (Function, Function) = lua.load(PROTECTED_CALLS).set_name("=budget").call(exceeded)?;
+    globals.set("pcall", pcall)?;
+    globals.set("xpcall", xpcall)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_first_exceeded_limit() {
+        let budget = Budget { time_limit_ms: 100, max_instructions: 1000, max_call_depth: 10, cpu_allowance_ms: 50 };
+        let second = Duration::from_secs(1);
+        assert_eq!(budget.exceeded_by(500, 5, Duration::ZERO), None);
+        assert_eq!(budget.exceeded_by(2000, 11, second), Some(Limit::Instructions(1000)));
+        assert_eq!(budget.exceeded_by(0, 11, second), Some(Limit::CallDepth(10)));
+        assert_eq!(budget.exceeded_by(0, 0, second), Some(Limit::CpuAllowance(50)));
+        assert_eq!(Budget::default().exceeded_by(u64::MAX, usize::MAX, second), None);
+    }
+    
+    #[test]
+    fn test_kill_needs_busy_script() {
+        assert!(matches!(kill(), Err(ScriptError::NotBusy)));
+        
+        let scope = BudgetScope::begin(Budget { time_limit_ms: 1, ..Budget::default() });
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(is_busy());
+        assert!(kill().is_ok());
+        assert!(check(0, 0).is_err());
+        let result = scope.finish(Ok(RespFrame::ok()));
+        assert!(matches!(result, Err(FerrousError::Script(ScriptError::Killed))));
+        assert!(!killed());
+    }
+}
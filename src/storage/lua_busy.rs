@@ -0,0 +1,68 @@
+//! Busy scripts and SCRIPT KILL
+//!
+//! As in Redis, a script running longer than `lua-time-limit` is busy. It is
+//! not interrupted, but the server starts serving its other clients again,
+//! only to refuse their commands with a BUSY error so the script stays atomic.
+//! The exceptions are SCRIPT KILL (or FUNCTION KILL), which stops the script
+//! at its next budget check unless it already wrote to the dataset, and
+//! SHUTDOWN NOSAVE.
+//!
+//! Other clients are serviced from the `lua_stats` count hook on the thread
+//! running the script, at most once per [`SERVICE_INTERVAL`]. A kill therefore
+//! lands on the script's own thread, where [`lua_budget::kill`] records it.
+//!
+//! [`lua_budget::kill`]: crate::storage::lua_budget::kill
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::storage::lua_checkpoint;
+
+/// Minimum time between two services of other clients while a script is busy
+pub const SERVICE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Answers other clients while a script is busy
+///
+/// The server registers one at startup.
+pub trait BusyService: Send + Sync {
+    /// Answer the waiting commands of clients other than `current`, returning how many were answered
+    fn service(&self, current: Option<u64>) -> usize;
+}
+
+/// Registered servicer
+static SERVICE: RwLock<Option<Arc<dyn BusyService>>> = RwLock::new(None);
+
+/// Register the servicer run while a script is busy, replacing any previous one
+pub fn set_service(service: Arc<dyn BusyService>) {
+    *SERVICE.write().unwrap() = Some(service);
+}
+
+/// Check whether a command may run while a script is busy, instead of getting BUSY
+pub fn is_allowed(name: &str, subcommand: Option<&str>) -> bool {
+    match (name, subcommand) {
+        ("SCRIPT" | "FUNCTION", Some(sub)) => sub.eq_ignore_ascii_case("KILL"),
+        ("SHUTDOWN", Some(sub)) => sub.eq_ignore_ascii_case("NOSAVE"),
+        _ => false,
+    }
+}
+
+/// Answer other clients on behalf of the busy script running on this thread
+pub(crate) fn service() -> usize {
+    let Some(service) = SERVICE.read().unwrap().clone() else { return 0 };
+    service.service(lua_checkpoint::current_client())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_commands_allowed_while_busy() {
+        assert!(is_allowed("SCRIPT", Some("kill")));
+        assert!(is_allowed("FUNCTION", Some("KILL")));
+        assert!(is_allowed("SHUTDOWN", Some("nosave")));
+        assert!(!is_allowed("SHUTDOWN", None));
+        assert!(!is_allowed("SCRIPT", Some("FLUSH")));
+        assert!(!is_allowed("GET", Some("key")));
+    }
+}
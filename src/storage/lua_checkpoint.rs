@@ -0,0 +1,165 @@
+//! Script checkpoints: cooperative yielding for long-running scripts
+//!
+//! A script normally runs to completion before any other client is served.
+//! With `lua-checkpoints yes`, a long script can call `redis.checkpoint()` at
+//! points where it is safe for other clients to observe its partial writes;
+//! the server then serves the read-only commands already waiting on other
+//! connections before the script resumes. This trades strict script atomicity
+//! for availability and is off by default.
+//!
+//! Only plain read-only data commands are served (the `is_read_only_command`
+//! set, minus the `_RO` script commands). On each connection, service stops at
+//! the first other command, which waits with everything pipelined after it
+//! until the script finishes, so replies stay in order. Writes never run at a
+//! checkpoint, so replication and the AOF are unaffected.
+//!
+//! A checkpoint yields at most once per `lua-checkpoint-interval` milliseconds
+//! on a thread, so calling it inside a tight loop is cheap. Time spent serving
+//! other clients counts towards the script's `lua-time-limit`.
+//! `redis.checkpoint()` returns the number of commands served, which is 0 when
+//! checkpoints are disabled, the interval has not elapsed, or nothing waited.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use mlua::{Lua, Result as LuaResult, Table};
+
+use crate::storage::commands::flags;
+
+/// Default minimum milliseconds between two checkpoints that yield
+pub const DEFAULT_INTERVAL_MS: u64 = 100;
+
+/// Serves other clients while a script is paused at a checkpoint
+///
+/// The server registers one when checkpoints are enabled.
+pub trait CheckpointService: Send + Sync {
+    /// Serve waiting read-only commands of clients other than `current`, returning how many ran
+    fn service(&self, current: Option<u64>) -> usize;
+}
+
+/// Whether `redis.checkpoint()` may yield
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Minimum milliseconds between two yielding checkpoints on a thread
+static INTERVAL_MS: AtomicU64 = AtomicU64::new(DEFAULT_INTERVAL_MS);
+
+/// Commands served at checkpoints since startup
+static SERVED: AtomicU64 = AtomicU64::new(0);
+
+/// Registered servicer
+static SERVICE: RwLock<Option<Arc<dyn CheckpointService>>> = RwLock::new(None);
+
+thread_local! {
+    /// Client whose command is running on this thread
+    static CLIENT: Cell<Option<u64>> = const { Cell::new(None) };
+    
+    /// When a checkpoint last yielded on this thread
+    static LAST_YIELD: Cell<Option<Instant>> = const { Cell::new(None) };
+    
+    /// Set while a checkpoint is serving other clients
+    static SERVICING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Apply the `lua-checkpoints` and `lua-checkpoint-interval` settings
+pub fn configure(enabled: bool, interval_ms: u64) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    INTERVAL_MS.store(interval_ms, Ordering::Relaxed);
+}
+
+/// Check whether checkpoints may yield
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Register the servicer run at checkpoints, replacing any previous one
+pub fn set_service(service: Arc<dyn CheckpointService>) {
+    *SERVICE.write().unwrap() = Some(service);
+}
+
+/// Number of commands served at checkpoints since startup
+pub fn served_commands() -> u64 {
+    SERVED.load(Ordering::Relaxed)
+}
+
+/// Check whether a command may be served to another client at a checkpoint
+pub fn is_serviceable(name: &str) -> bool {
+    flags::is_read_only_command(name) && !matches!(name, "EVAL_RO" | "EVALSHA_RO" | "FCALL_RO")
+}
+
+/// Client whose command is running on this thread, if any
+pub fn current_client() -> Option<u64> {
+    CLIENT.with(|client| client.get())
+}
+
+/// Marks the client whose commands run on this thread, so checkpoints skip it
+pub struct ClientScope {
+    previous: Option<u64>,
+}
+
+impl ClientScope {
+    /// Run this thread's commands on behalf of `client` until the scope ends
+    pub fn begin(client: u64) -> Self {
+        ClientScope { previous: CLIENT.with(|current| current.replace(Some(client))) }
+    }
+}
+
+impl Drop for ClientScope {
+    fn drop(&mut self) {
+        CLIENT.with(|current| current.set(self.previous));
+    }
+}
+
+/// Serve other clients if checkpoints are enabled and the interval has elapsed
+///
+/// Returns the number of commands served.
+pub fn checkpoint() -> usize {
+    if !is_enabled() || SERVICING.with(|servicing| servicing.get()) {
+        return 0;
+    }
+    let Some(service) = SERVICE.read().unwrap().clone() else { return 0 };
+    
+    let now = Instant::now();
+    let interval = Duration::from_millis(INTERVAL_MS.load(Ordering::Relaxed));
+    if LAST_YIELD.with(|last| last.get()).is_some_and(|last| now.duration_since(last) < interval) {
+        return 0;
+    }
+    LAST_YIELD.with(|last| last.set(Some(now)));
+    
+    SERVICING.with(|servicing| servicing.set(true));
+    let served = service.service(current_client());
+    SERVICING.with(|servicing| servicing.set(false));
+    SERVED.fetch_add(served as u64, Ordering::Relaxed);
+    served
+}
+
+/// Install redis.checkpoint into a redis table
+pub fn register(lua: &Lua, redis_table: &Table) -> LuaResult<()> {
+    let checkpoint = lua.create_function(|_, ()| Ok(checkpoint() as i64))?;
+    redis_table.set("checkpoint", checkpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_serviceable_commands() {
+        assert!(is_serviceable("GET"));
+        assert!(is_serviceable("ZRANGE"));
+        assert!(!is_serviceable("SET"));
+        assert!(!is_serviceable("FCALL_RO"));
+        assert!(!is_serviceable("MULTI"));
+    }
+    
+    #[test]
+    fn test_client_scope_restores_previous() {
+        let _outer = ClientScope::begin(1);
+        {
+            let _inner = ClientScope::begin(2);
+            assert_eq!(CLIENT.with(|client| client.get()), Some(2));
+        }
+        assert_eq!(CLIENT.with(|client| client.get()), Some(1));
+    }
+}
@@ -0,0 +1,500 @@
+//! `cjson` library for scripts
+//!
+//! A pure Rust `cjson.encode` / `cjson.decode` following the conventions of
+//! the lua-cjson build bundled with Redis, so scripts written against Redis
+//! behave the same here:
+//!
+//! - a table whose keys are all positive integers encodes as an array (holes
+//!   become `null`), anything else as an object; the empty table is `{}`
+//! - arrays whose largest index is over twice their element count (and over
+//!   10) are rejected as excessively sparse
+//! - numbers are written with Lua's `%.14g` precision; NaN and Inf are rejected
+//! - `/` is escaped as `\/`, and JSON `null` decodes to the `cjson.null` sentinel
+//! - nesting is limited to [`MAX_DEPTH`] levels in both directions
+
+use mlua::{Lua, Result as LuaResult, String as LuaString, Table, Value as LuaValue};
+
+use crate::storage::lua_readonly;
+
+/// Deepest nesting of arrays and objects accepted by encode and decode
+pub const MAX_DEPTH: usize = 1000;
+
+/// Largest index an array may have beyond twice its element count
+const SPARSE_SAFE: i64 = 10;
+
+/// Create the `cjson` table
+pub fn create_cjson_table(lua: &Lua) -> LuaResult<Table> {
+    let cjson = lua.create_table()?;
+    cjson.set("_NAME", "cjson")?;
+    cjson.set("_VERSION", "2.1.0")?;
+    cjson.set("null", LuaValue::NULL)?;
+    
+    cjson.set("encode", lua.create_function(|lua, value: LuaValue| {
+        let mut out = Vec::new();
+        encode_value(value, &mut out)?;
+        lua.create_string(&out)
+    })?)?;
+    
+    cjson.set("decode", lua.create_function(|lua, text: LuaString| {
+        let bytes = text.as_bytes();
+        let mut decoder = Decoder { lua, input: &bytes, pos: 0 };
+        let value = decoder.value()?;
+        decoder.skip_whitespace();
+        if decoder.pos < bytes.len() {
+            return Err(decoder.unexpected("the end"));
+        }
+        Ok(value)
+    })?)?;
+    
+    Ok(cjson)
+}
+
+fn runtime_error(message: String) -> mlua::Error {
+    mlua::Error::RuntimeError(message)
+}
+
+/// An array or object being encoded: its remaining entries (keys only for objects)
+struct OpenTable {
+    entries: std::vec::IntoIter<(Option<LuaValue>, LuaValue)>,
+    close: u8,
+    first: bool,
+}
+
+/// Encode a value, keeping open tables on a heap stack rather than recursing
+fn encode_value(value: LuaValue, out: &mut Vec<u8>) -> LuaResult<()> {
+    let mut open: Vec<OpenTable> = Vec::new();
+    let mut next = Some(value);
+    loop {
+        match next.take().map(lua_readonly::unwrap) {
+            Some(LuaValue::Table(table)) => {
+                if open.len() >= MAX_DEPTH {
+                    return Err(runtime_error(format!("Cannot serialise, excessive nesting ({})", open.len() + 1)));
+                }
+                open.push(open_table(&table, out)?);
+            }
+            Some(value) => encode_scalar(&value, out)?,
+            None => {}
+        }
+        
+        let Some(table) = open.last_mut() else { return Ok(()) };
+        match table.entries.next() {
+            Some((key, value)) => {
+                if !table.first {
+                    out.push(b',');
+                }
+                table.first = false;
+                if let Some(key) = key {
+                    encode_key(&key, out)?;
+                    out.push(b':');
+                }
+                next = Some(value);
+            }
+            None => {
+                out.push(table.close);
+                open.pop();
+            }
+        }
+    }
+}
+
+fn encode_scalar(value: &LuaValue, out: &mut Vec<u8>) -> LuaResult<()> {
+    match value {
+        LuaValue::Nil => out.extend_from_slice(b"null"),
+        value if value.is_null() => out.extend_from_slice(b"null"),
+        LuaValue::Boolean(b) => out.extend_from_slice(if *b { b"true" } else { b"false" }),
+        LuaValue::Integer(i) => encode_number(*i as f64, out)?,
+        LuaValue::Number(n) => encode_number(*n, out)?,
+        LuaValue::String(s) => encode_string(&s.as_bytes(), out),
+        other => return Err(runtime_error(format!("Cannot serialise {}: type not supported", other.type_name()))),
+    }
+    Ok(())
+}
+
+fn encode_number(n: f64, out: &mut Vec<u8>) -> LuaResult<()> {
+    if !n.is_finite() {
+        return Err(runtime_error("Cannot serialise number: must not be NaN or Inf".to_string()));
+    }
+    out.extend_from_slice(format_number(n).as_bytes());
+    Ok(())
+}
+
+fn encode_string(s: &[u8], out: &mut Vec<u8>) {
+    out.push(b'"');
+    for &b in s {
+        match b {
+            b'"' => out.extend_from_slice(b"\\\""),
+            b'\\' => out.extend_from_slice(b"\\\\"),
+            b'/' => out.extend_from_slice(b"\\/"),
+            b'\x08' => out.extend_from_slice(b"\\b"),
+            b'\x0c' => out.extend_from_slice(b"\\f"),
+            b'\n' => out.extend_from_slice(b"\\n"),
+            b'\r' => out.extend_from_slice(b"\\r"),
+            b'\t' => out.extend_from_slice(b"\\t"),
+            0..=0x1f | 0x7f => out.extend_from_slice(format!("\\u{:04x}", b).as_bytes()),
+            _ => out.push(b),
+        }
+    }
+    out.push(b'"');
+}
+
+/// Length of the table as an array, or `None` if it has a non-index key
+fn array_length(table: &Table) -> LuaResult<Option<i64>> {
+    let mut max = 0;
+    let mut items = 0;
+    for pair in table.pairs::<LuaValue, LuaValue>() {
+        let (key, _) = pair?;
+        let index = match key {
+            LuaValue::Integer(i) if i >= 1 => i,
+            LuaValue::Number(n) if n >= 1.0 && n.fract() == 0.0 => n as i64,
+            _ => return Ok(None),
+        };
+        max = max.max(index);
+        items += 1;
+    }
+    
+    if max > items * 2 && max > SPARSE_SAFE {
+        return Err(runtime_error("Cannot serialise table: excessively sparse array".to_string()));
+    }
+    Ok(Some(max))
+}
+
+/// Write the opening bracket of a table and collect the entries to encode
+fn open_table(table: &Table, out: &mut Vec<u8>) -> LuaResult<OpenTable> {
+    let mut entries = Vec::new();
+    let close = match array_length(table)? {
+        Some(len) if len > 0 => {
+            for i in 1..=len {
+                entries.push((None, table.raw_get::<LuaValue>(i)?));
+            }
+            out.push(b'[');
+            b']'
+        }
+        _ => {
+            for pair in table.pairs::<LuaValue, LuaValue>() {
+                let (key, value) = pair?;
+                entries.push((Some(key), value));
+            }
+            out.push(b'{');
+            b'}'
+        }
+    };
+    Ok(OpenTable { entries: entries.into_iter(), close, first: true })
+}
+
+/// Object keys: strings as they are, numbers as strings
+fn encode_key(key: &LuaValue, out: &mut Vec<u8>) -> LuaResult<()> {
+    match key {
+        LuaValue::String(s) => encode_string(&s.as_bytes(), out),
+        LuaValue::Integer(_) | LuaValue::Number(_) => {
+            out.push(b'"');
+            encode_scalar(key, out)?;
+            out.push(b'"');
+        }
+        _ => return Err(runtime_error("Cannot serialise table: table key must be a number or string".to_string())),
+    }
+    Ok(())
+}
+
+/// Format a number like C's `%.14g`, Lua 5.1's number format
+fn format_number(n: f64) -> String {
+    if n == 0.0 {
+        return if n.is_sign_negative() { "-0".to_string() } else { "0".to_string() };
+    }
+    
+    // Round to 14 significant digits first: the exponent may change
+    let scientific = format!("{:.13e}", n);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    
+    if !(-4..14).contains(&exponent) {
+        let mantissa = trim_fraction(mantissa);
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+    } else {
+        let fixed = format!("{:.*}", (13 - exponent) as usize, n);
+        trim_fraction(&fixed).to_string()
+    }
+}
+
+/// Drop trailing zeros of a fraction, and the point if nothing is left
+fn trim_fraction(number: &str) -> &str {
+    if number.contains('.') {
+        number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        number
+    }
+}
+
+/// An array or object being decoded: the table and its next index or pending key
+enum Container {
+    Array(Table, i64),
+    Object(Table, Vec<u8>),
+}
+
+struct Decoder<'a> {
+    lua: &'a Lua,
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Decoder<'_> {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.input.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+    
+    /// Name of the token at the current position, as lua-cjson reports it
+    fn token_name(&self) -> &'static str {
+        match self.input.get(self.pos) {
+            None => "T_END",
+            Some(b'{') => "T_OBJ_BEGIN",
+            Some(b'}') => "T_OBJ_END",
+            Some(b'[') => "T_ARR_BEGIN",
+            Some(b']') => "T_ARR_END",
+            Some(b'"') => "T_STRING",
+            Some(b':') => "T_COLON",
+            Some(b',') => "T_COMMA",
+            Some(b'-' | b'0'..=b'9') => "T_NUMBER",
+            Some(b't' | b'f') if self.input[self.pos..].starts_with(b"true") || self.input[self.pos..].starts_with(b"false") => "T_BOOLEAN",
+            Some(b'n') if self.input[self.pos..].starts_with(b"null") => "T_NULL",
+            Some(_) => "invalid token",
+        }
+    }
+    
+    fn unexpected(&self, expected: &str) -> mlua::Error {
+        self.error_at(format!("Expected {} but found {}", expected, self.token_name()), self.pos)
+    }
+    
+    fn error_at(&self, message: String, pos: usize) -> mlua::Error {
+        runtime_error(format!("{} at character {}", message, pos + 1))
+    }
+    
+    /// Decode one value, keeping open arrays and objects on a heap stack rather than recursing
+    fn value(&mut self) -> LuaResult<LuaValue> {
+        let mut open: Vec<Container> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let mut value = match self.input.get(self.pos) {
+                Some(b'{' | b'[') => {
+                    let is_object = self.input[self.pos] == b'{';
+                    if open.len() >= MAX_DEPTH {
+                        return Err(self.error_at(format!("Found too many nested data structures ({})", open.len() + 1), self.pos));
+                    }
+                    self.pos += 1;
+                    let table = self.lua.create_table()?;
+                    self.skip_whitespace();
+                    let close = if is_object { b'}' } else { b']' };
+                    if self.input.get(self.pos) == Some(&close) {
+                        self.pos += 1;
+                        LuaValue::Table(table)
+                    } else {
+                        let container = if is_object {
+                            Container::Object(table, self.object_key()?)
+                        } else {
+                            Container::Array(table, 1)
+                        };
+                        open.push(container);
+                        continue;
+                    }
+                }
+                Some(b'"') => LuaValue::String(self.lua.create_string(self.string()?)?),
+                Some(b'-' | b'0'..=b'9') => self.number()?,
+                _ if self.input[self.pos..].starts_with(b"true") => {
+                    self.pos += 4;
+                    LuaValue::Boolean(true)
+                }
+                _ if self.input[self.pos..].starts_with(b"false") => {
+                    self.pos += 5;
+                    LuaValue::Boolean(false)
+                }
+                _ if self.input[self.pos..].starts_with(b"null") => {
+                    self.pos += 4;
+                    LuaValue::NULL
+                }
+                _ => return Err(self.unexpected("value")),
+            };
+            
+            // Store the value in its container, closing every container that ends here
+            loop {
+                let Some(container) = open.last_mut() else { return Ok(value) };
+                self.skip_whitespace();
+                let next = self.input.get(self.pos).copied();
+                let closed = match container {
+                    Container::Array(table, index) => {
+                        table.raw_set(*index, value)?;
+                        *index += 1;
+                        match next {
+                            Some(b',') => false,
+                            Some(b']') => true,
+                            _ => return Err(self.unexpected("comma or array end")),
+                        }
+                    }
+                    Container::Object(table, key) => {
+                        table.raw_set(self.lua.create_string(&*key)?, value)?;
+                        match next {
+                            Some(b',') => false,
+                            Some(b'}') => true,
+                            _ => return Err(self.unexpected("comma or object end")),
+                        }
+                    }
+                };
+                self.pos += 1;
+                if !closed {
+                    if let Container::Object(_, key) = container {
+                        *key = self.object_key()?;
+                    }
+                    break;
+                }
+                value = match open.pop() {
+                    Some(Container::Array(table, _) | Container::Object(table, _)) => LuaValue::Table(table),
+                    None => unreachable!(),
+                };
+            }
+        }
+    }
+    
+    /// Read an object key and the colon after it
+    fn object_key(&mut self) -> LuaResult<Vec<u8>> {
+        self.skip_whitespace();
+        if self.input.get(self.pos) != Some(&b'"') {
+            return Err(self.unexpected("object key string"));
+        }
+        let key = self.string()?;
+        self.skip_whitespace();
+        if self.input.get(self.pos) != Some(&b':') {
+            return Err(self.unexpected("colon"));
+        }
+        self.pos += 1;
+        Ok(key)
+    }
+    
+    fn number(&mut self) -> LuaResult<LuaValue> {
+        let start = self.pos;
+        let digits = |input: &[u8], mut pos: usize| {
+            while matches!(input.get(pos), Some(b'0'..=b'9')) {
+                pos += 1;
+            }
+            pos
+        };
+        
+        let mut end = start;
+        if self.input.get(end) == Some(&b'-') {
+            end += 1;
+        }
+        let int_end = digits(self.input, end);
+        let mut valid = int_end > end;
+        end = int_end;
+        if self.input.get(end) == Some(&b'.') {
+            let frac_end = digits(self.input, end + 1);
+            valid &= frac_end > end + 1;
+            end = frac_end;
+        }
+        if matches!(self.input.get(end), Some(b'e' | b'E')) {
+            let mut exp = end + 1;
+            if matches!(self.input.get(exp), Some(b'+' | b'-')) {
+                exp += 1;
+            }
+            let exp_end = digits(self.input, exp);
+            valid &= exp_end > exp;
+            end = exp_end;
+        }
+        
+        let number = std::str::from_utf8(&self.input[start..end]).ok()
+            .filter(|_| valid)
+            .and_then(|text| text.parse::<f64>().ok());
+        match number {
+            Some(n) => {
+                self.pos = end;
+                Ok(LuaValue::Number(n))
+            }
+            None => Err(self.error_at("Expected value but found invalid number".to_string(), start)),
+        }
+    }
+    
+    fn string(&mut self) -> LuaResult<Vec<u8>> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let Some(&b) = self.input.get(self.pos) else {
+                return Err(self.error_at("Expected value but found unexpected end of string".to_string(), start));
+            };
+            self.pos += 1;
+            match b {
+                b'"' => return Ok(out),
+                b'\\' => {
+                    let escape = self.input.get(self.pos).copied();
+                    self.pos += 1;
+                    match escape {
+                        Some(b'"') => out.push(b'"'),
+                        Some(b'\\') => out.push(b'\\'),
+                        Some(b'/') => out.push(b'/'),
+                        Some(b'b') => out.push(b'\x08'),
+                        Some(b'f') => out.push(b'\x0c'),
+                        Some(b'n') => out.push(b'\n'),
+                        Some(b'r') => out.push(b'\r'),
+                        Some(b't') => out.push(b'\t'),
+                        Some(b'u') => {
+                            let code = self.unicode_escape()
+                                .ok_or_else(|| self.error_at("Expected value but found invalid unicode escape code".to_string(), start))?;
+                            let mut buf = [0u8; 4];
+                            out.extend_from_slice(code.encode_utf8(&mut buf).as_bytes());
+                        }
+                        _ => return Err(self.error_at("Expected value but found invalid escape code".to_string(), start)),
+                    }
+                }
+                _ => out.push(b),
+            }
+        }
+    }
+    
+    /// Decode the code point of a `\uXXXX` escape (the `\u` already consumed), joining surrogate pairs
+    fn unicode_escape(&mut self) -> Option<char> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high);
+        }
+        if self.input.get(self.pos..self.pos + 2) != Some(b"\\u") {
+            return None;
+        }
+        self.pos += 2;
+        let low = self.hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return None;
+        }
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+    }
+    
+    fn hex4(&mut self) -> Option<u32> {
+        let digits = self.input.get(self.pos..self.pos + 4)?;
+        let code = u32::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+        self.pos += 4;
+        Some(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_format_number_matches_lua() {
+        assert_eq!(format_number(1.0), "1");
+        assert_eq!(format_number(-2.5), "-2.5");
+        assert_eq!(format_number(0.1), "0.1");
+        assert_eq!(format_number(1.0 / 3.0), "0.33333333333333");
+        assert_eq!(format_number(1e14), "1e+14");
+        assert_eq!(format_number(12345678901234.0), "12345678901234");
+        assert_eq!(format_number(0.0001), "0.0001");
+        assert_eq!(format_number(0.00001), "1e-05");
+        assert_eq!(format_number(99999999999999.9), "1e+14");
+    }
+    
+    #[test]
+    fn test_string_escapes() {
+        let mut out = Vec::new();
+        encode_string(b"a\"b\\c/d\n\x01\x7f\xc3\xa9", &mut out);
+        assert_eq!(out, b"\"a\\\"b\\\\c\\/d\\n\\u0001\\u007f\xc3\xa9\"".to_vec());
+    }
+}
@@ -0,0 +1,121 @@
+//! Script dirty set
+//!
+//! Writes issued through `redis.call` apply immediately, so a script always
+//! reads its own prior writes. The keys they touch are also collected in a
+//! per-script dirty set that is marked modified in one step when the script
+//! finishes (successfully or not): a WATCH baseline taken at any point before
+//! the script completed is invalidated for every key the script wrote, exactly
+//! as Redis invalidates it when the whole script runs as one command.
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use crate::storage::commands::flags;
+use crate::storage::{lru, DatabaseIndex, StorageEngine};
+
+/// Keys written by a script, with their database
+type DirtyKeys = Vec<(DatabaseIndex, Vec<u8>)>;
+
+thread_local! {
+    /// Keys written by the script running on this thread
+    static DIRTY: RefCell<Option<DirtyKeys>> = const { RefCell::new(None) };
+}
+
+/// Dirty set of one script invocation, flushed when dropped
+pub struct DirtyScope {
+    storage: Arc<StorageEngine>,
+    previous: Option<DirtyKeys>,
+}
+
+impl DirtyScope {
+    /// Start collecting the keys written by a script on this thread
+    pub fn begin(storage: &Arc<StorageEngine>) -> Self {
+        let previous = DIRTY.with(|dirty| dirty.replace(Some(Vec::new())));
+        DirtyScope { storage: Arc::clone(storage), previous }
+    }
+}
+
+impl Drop for DirtyScope {
+    fn drop(&mut self) {
+        let keys = DIRTY.with(|dirty| dirty.replace(self.previous.take())).unwrap_or_default();
+        for (db, key) in keys {
+            let _ = self.storage.mark_key_modified(db, &key);
+        }
+    }
+}
+
+/// Keys a command would dirty, if it is a write issued inside a dirty scope
+///
+/// Taken before the command runs (which consumes its arguments) and passed to
+/// [`mark_written`] once it succeeded, so failed writes do not abort watchers.
+pub fn written_keys<T: AsRef<[u8]>>(args: &[T]) -> Vec<Vec<u8>> {
+    let active = DIRTY.with(|dirty| dirty.borrow().is_some());
+    let Some(name) = args.first() else { return Vec::new() };
+    if !active || !flags::is_write_command(&String::from_utf8_lossy(name.as_ref()).to_uppercase()) {
+        return Vec::new();
+    }
+    
+    lru::command_keys(args).into_iter().map(|key| key.to_vec()).collect()
+}
+
+/// Add the keys of a successful write to the current script's dirty set
+pub fn mark_written(db: DatabaseIndex, keys: Vec<Vec<u8>>) {
+    if keys.is_empty() {
+        return;
+    }
+    
+    DIRTY.with(|dirty| {
+        if let Some(set) = dirty.borrow_mut().as_mut() {
+            for key in keys {
+                if !set.iter().any(|(d, k)| *d == db && *k == key) {
+                    set.push((db, key));
+                }
+            }
+        }
+    });
+}
+
+/// Record a command a script executed successfully
+pub fn note_command<T: AsRef<[u8]>>(db: DatabaseIndex, args: &[T]) {
+    mark_written(db, written_keys(args));
+}
+
+/// Whether writes on this thread are being collected into a dirty set
+pub(crate) fn is_tracking() -> bool {
+    DIRTY.with(|dirty| dirty.borrow().is_some())
+}
+
+/// Number of keys in the current script's dirty set
+pub fn dirty_count() -> usize {
+    DIRTY.with(|dirty| dirty.borrow().as_ref().map_or(0, |set| set.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_dirty_scope_collects_writes_only() {
+        let storage = StorageEngine::new_in_memory();
+        {
+            let _scope = DirtyScope::begin(&storage);
+            note_command(0, &["SET", "a", "1"]);
+            note_command(0, &["GET", "b"]);
+            note_command(0, &["MSET", "a", "2", "c", "3"]);
+            assert_eq!(dirty_count(), 2);
+        }
+        assert_eq!(dirty_count(), 0);
+    }
+    
+    #[test]
+    fn test_flush_invalidates_watch() {
+        let storage = StorageEngine::new_in_memory();
+        let baseline = storage.register_watch(0, b"w").unwrap();
+        {
+            let _scope = DirtyScope::begin(&storage);
+            note_command(0, &["SET", "w", "1"]);
+        }
+        assert!(storage.was_modified_since(0, b"w", baseline).unwrap());
+        storage.unregister_watch(0, b"w").unwrap();
+    }
+}
@@ -0,0 +1,135 @@
+//! Script effects replication
+//!
+//! Replicas and the AOF receive what a script did, not the script itself: the
+//! write commands it ran through `redis.call`/`redis.pcall`, in order, wrapped
+//! in MULTI/EXEC when there is more than one, as Redis 7 does. Replicas then
+//! converge even when a script reads the clock, pops random members or depends
+//! on data the replica does not share, and EVALSHA needs no script cache on
+//! the other side.
+//!
+//! Only writes that succeeded are kept. A script that fails halfway still
+//! propagates the writes it made before failing, since they were applied.
+//!
+//! A script that writes in a loop can generate far more effects than a replica
+//! reads in one go, so the server sends them `PROPAGATION_CHUNK` commands at a
+//! time and relieves the replicas' output buffers in between.
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::protocol::RespFrame;
+use crate::storage::commands::executor::Reply;
+use crate::storage::commands::flags;
+
+/// Effect commands sent to replicas before their output buffers are checked
+pub const PROPAGATION_CHUNK: usize = 1024;
+
+thread_local! {
+    /// Writes made by the scripts running on this thread, while collected
+    static EFFECTS: RefCell<Option<Vec<Vec<Vec<u8>>>>> = const { RefCell::new(None) };
+}
+
+/// Collection of the writes made by one script command
+pub struct EffectsScope {
+    previous: Option<Vec<Vec<Vec<u8>>>>,
+}
+
+impl EffectsScope {
+    /// Start collecting the writes scripts make on this thread
+    pub fn begin() -> Self {
+        EffectsScope { previous: EFFECTS.with(|effects| effects.replace(Some(Vec::new()))) }
+    }
+    
+    /// Number of writes collected so far
+    pub fn count(&self) -> usize {
+        EFFECTS.with(|effects| effects.borrow().as_ref().map_or(0, Vec::len))
+    }
+    
+    /// Stop collecting and return the commands to propagate
+    pub fn finish(self) -> Vec<RespFrame> {
+        let writes = EFFECTS.with(|effects| effects.borrow_mut().take()).unwrap_or_default();
+        propagated_commands(writes)
+    }
+}
+
+impl Drop for EffectsScope {
+    fn drop(&mut self) {
+        EFFECTS.with(|effects| *effects.borrow_mut() = self.previous.take());
+    }
+}
+
+/// Whether the writes scripts make on this thread are being collected
+pub(crate) fn is_collecting() -> bool {
+    EFFECTS.with(|effects| effects.borrow().is_some())
+}
+
+/// Check whether a command a script is about to run should be collected
+pub fn wants<T: AsRef<[u8]>>(args: &[T]) -> bool {
+    let Some(name) = args.first() else { return false };
+    is_collecting()
+        && flags::is_write_command(&String::from_utf8_lossy(name.as_ref()).to_uppercase())
+}
+
+/// Keep a write the current script ran, if it succeeded
+pub fn record_write(args: Vec<Vec<u8>>, result: &Result<Reply>) {
+    if !matches!(result, Ok(reply) if !reply.is_error()) {
+        return;
+    }
+    EFFECTS.with(|effects| {
+        if let Some(writes) = effects.borrow_mut().as_mut() {
+            writes.push(args);
+        }
+    });
+}
+
+/// Turn collected writes into commands, wrapping several in MULTI/EXEC
+fn propagated_commands(writes: Vec<Vec<Vec<u8>>>) -> Vec<RespFrame> {
+    let command = |args: Vec<Vec<u8>>| {
+        RespFrame::Array(Some(args.into_iter().map(|arg| RespFrame::BulkString(Some(Arc::new(arg)))).collect()))
+    };
+    if writes.len() < 2 {
+        return writes.into_iter().map(command).collect();
+    }
+    
+    let mut commands = Vec::with_capacity(writes.len() + 2);
+    commands.push(command(vec![b"MULTI".to_vec()]));
+    commands.extend(writes.into_iter().map(command));
+    commands.push(command(vec![b"EXEC".to_vec()]));
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn args(words: &[&str]) -> Vec<Vec<u8>> {
+        words.iter().map(|word| word.as_bytes().to_vec()).collect()
+    }
+    
+    #[test]
+    fn test_effects_keep_successful_writes() {
+        let scope = EffectsScope::begin();
+        assert!(wants(&["SET", "k", "v"]));
+        assert!(!wants(&["GET", "k"]));
+        record_write(args(&["SET", "k", "v"]), &Ok(Reply::Status("OK".to_string())));
+        record_write(args(&["INCR", "k"]), &Ok(Reply::Error("ERR value is not an integer".to_string())));
+        assert_eq!(scope.count(), 1);
+        let commands = scope.finish();
+        assert_eq!(commands, propagated_commands(vec![args(&["SET", "k", "v"])]));
+        assert!(!wants(&["SET", "k", "v"]));
+    }
+    
+    #[test]
+    fn test_several_writes_are_wrapped_in_multi() {
+        let commands = propagated_commands(vec![args(&["SET", "a", "1"]), args(&["DEL", "b"])]);
+        let names: Vec<_> = commands.iter()
+            .map(|command| match command {
+                RespFrame::Array(Some(parts)) => parts[0].clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(names, ["MULTI", "SET", "DEL", "EXEC"].map(|name| RespFrame::BulkString(Some(Arc::new(name.as_bytes().to_vec())))));
+        assert!(propagated_commands(Vec::new()).is_empty());
+    }
+}
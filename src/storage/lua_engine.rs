@@ -4,15 +4,21 @@
 //! commands through the unified command executor, ensuring atomic operations
 //! and complete Redis compatibility.
 
-use std::sync::{Arc, OnceLock};
-use std::time::Instant;
-use mlua::{Lua, Result as LuaResult, MultiValue, Value as LuaValue};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use mlua::{ChunkMode, Lua, LuaOptions, Result as LuaResult, MultiValue, StdLib, Table, Value as LuaValue};
 use sha1::{Sha1, Digest};
 
-use crate::error::{Result, FerrousError};
-use crate::protocol::resp::RespFrame;
-use crate::storage::StorageEngine;
-use crate::storage::commands::executor::LuaCommandAdapter;
+use crate::error::{Result, FerrousError, ScriptError};
+use crate::protocol::resp::{Bytes, RespFrame};
+use crate::storage::{lru, lua_audit, lua_bit, lua_budget, lua_checkpoint, lua_cjson, lua_dirty, lua_effects, lua_invariants, lua_iter, lua_log, lua_quota, lua_readonly, lua_record, lua_require, lua_sandbox, lua_stats, lua_triggers, lua_utf8, lua_vm, StorageEngine};
+use crate::storage::commands::executor::{LuaCommandAdapter, Reply};
+use crate::storage::commands::{flags, hashes, monitor, strings};
+use crate::storage::commands::lua::{script_error_message, with_error_class};
 
 /// Command execution context passed from server to Lua engine
 pub struct LuaCommandContext {
@@ -20,189 +26,857 @@ pub struct LuaCommandContext {
     pub storage: Arc<StorageEngine>,
 }
 
+thread_local! {
+    /// Error raised by write commands from the script running on this thread, if writes are denied
+    static DENY_WRITES: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Denies write commands to scripts run on this thread until dropped
+///
+/// Entered by the read-only variants (EVAL_RO, EVALSHA_RO, FCALL_RO), by FCALL of
+/// `no-writes` functions, and for every script run on a read-only replica.
+pub struct ReadOnlyScript {
+    previous: Option<&'static str>,
+}
+
+impl ReadOnlyScript {
+    /// Error for writes from EVAL_RO and friends
+    pub const RO_VARIANT: &'static str = "ERR Write commands are not allowed from read-only scripts.";
+    
+    /// Error for writes from scripts run on a replica
+    pub const REPLICA: &'static str = "READONLY You can't write against a read only replica.";
+    
+    /// Error for writes while a FAILOVER waits for its target replica
+    pub const FAILOVER: &'static str = "ERR FAILOVER in progress, please retry later";
+    
+    /// Deny writes, raising `error` for any write command the script issues
+    pub fn enter(error: &'static str) -> Self {
+        let previous = DENY_WRITES.with(|deny| deny.replace(Some(error)));
+        ReadOnlyScript { previous }
+    }
+    
+    /// Error raised for writes on this thread, if they are denied
+    pub(crate) fn current() -> Option<&'static str> {
+        DENY_WRITES.with(|deny| deny.get())
+    }
+    
+    /// Error to raise for a command issued by the running script, if it is a denied write
+    fn denied(cmd_name: &str) -> Option<&'static str> {
+        DENY_WRITES.with(|deny| deny.get()).filter(|_| flags::is_write_command(cmd_name))
+    }
+}
+
+impl Drop for ReadOnlyScript {
+    fn drop(&mut self) {
+        DENY_WRITES.with(|deny| deny.set(self.previous));
+    }
+}
+
+/// Caps on the size of a single redis.call reply converted into Lua values
+///
+/// Protects the VM from scripts like `redis.call('KEYS', '*')` or HGETALL on a giant
+/// hash ballooning Lua heap memory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplyLimits {
+    /// Maximum number of values (array elements at any depth) per reply
+    pub max_elements: usize,
+    
+    /// Maximum total bytes of string data per reply
+    pub max_bytes: usize,
+    
+    /// Truncate oversized replies (flagged with `truncated = true`) instead of raising an error
+    pub truncate: bool,
+}
+
+impl Default for ReplyLimits {
+    fn default() -> Self {
+        ReplyLimits {
+            max_elements: 1_000_000,
+            max_bytes: 512 * 1024 * 1024,
+            truncate: false,
+        }
+    }
+}
+
+/// Remaining reply budget while converting one redis.call reply
+struct ReplyBudget {
+    limits: ReplyLimits,
+    elements: usize,
+    bytes: usize,
+    truncated: bool,
+}
+
+impl ReplyBudget {
+    fn new(limits: ReplyLimits) -> Self {
+        ReplyBudget { limits, elements: 0, bytes: 0, truncated: false }
+    }
+    
+    /// Account for one value of `len` bytes; returns false once the budget is exceeded
+    fn charge(&mut self, len: usize) -> bool {
+        self.elements += 1;
+        self.bytes += len;
+        self.elements <= self.limits.max_elements && self.bytes <= self.limits.max_bytes
+    }
+    
+    /// Check whether a whole reply fits within the limits without converting it
+    fn admits(&self, reply: &Reply) -> bool {
+        fn measure(reply: &Reply, elements: &mut usize, bytes: &mut usize) {
+            *elements += 1;
+            match reply {
+                Reply::Status(s) => *bytes += s.len(),
+                Reply::Bulk(b) => *bytes += b.len(),
+                Reply::Array(items) => {
+                    for item in items {
+                        measure(item, elements, bytes);
+                    }
+                }
+                _ => {}
+            }
+        }
+        
+        let (mut elements, mut bytes) = (0, 0);
+        measure(reply, &mut elements, &mut bytes);
+        elements <= self.limits.max_elements && bytes <= self.limits.max_bytes
+    }
+    
+    fn error_message(&self) -> String {
+        format!(
+            "reply exceeds the script reply size limit ({} elements, {} bytes)",
+            self.limits.max_elements, self.limits.max_bytes
+        )
+    }
+}
+
+/// Ordered replacement for `pairs` used in deterministic mode
+///
+/// Native `pairs` order depends on the table's hash layout, which varies with
+/// insertion history and, for table/function keys, with memory addresses. A
+/// master and replica running the same script could then issue writes in
+/// different orders. This version snapshots the keys and visits numbers first
+/// (ascending), then strings (bytewise), then booleans (false first). Keys of
+/// other types follow in native order, so they are only as stable as `next`.
+const DETERMINISTIC_PAIRS: &str = r#"
+local next, rawget, type, sort = next, rawget, type, table.sort
+local rank = {number = 1, string = 2, boolean = 3}
+local function before(a, b)
+    local ra, rb = rank[type(a)] or 4, rank[type(b)] or 4
+    if ra ~= rb then return ra < rb end
+    if ra == 3 then return b and not a end
+    if ra == 4 then return false end
+    return a < b
+end
+return function(t)
+    local keys, n = {}, 0
+    for k in next, t do
+        n = n + 1
+        keys[n] = k
+    end
+    sort(keys, before)
+    local i = 0
+    return function()
+        i = i + 1
+        local k = keys[i]
+        if k ~= nil then return k, rawget(t, k) end
+    end, t, nil
+end
+"#;
+
+/// Integer reply Redis gives for a Lua number returned by a script
+///
+/// Redis casts the double to `long long`, truncating toward zero, so `3.99`
+/// replies 3 and `-3.99` replies -3. Scripts that need the fractional part
+/// must return `tostring(n)` instead. Values outside the i64 range, infinities
+/// and NaN have no defined cast in C; on x86-64 it yields i64::MIN, which is
+/// what Redis replies there and what is replied here on every platform.
+pub(crate) fn number_to_integer(n: f64) -> i64 {
+    // 2^63 is exactly representable; i64::MAX as f64 would round up to it
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if (-LIMIT..LIMIT).contains(&n) {
+        n.trunc() as i64
+    } else {
+        i64::MIN
+    }
+}
+
+/// Chunk name scripts are loaded under, so errors read `user_script:<line>: ...`
+const SCRIPT_CHUNK_NAME: &str = "=user_script";
+
+/// Script run by the scripting health probe (DEBUG SCRIPTHEALTH)
+const PROBE_SCRIPT: &str = "return 1";
+
+/// Default for `lua-max-nesting-depth`
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 1000;
+
+/// Fixed PRNG seed applied to every script in deterministic mode
+const DETERMINISTIC_SEED: &str = "math.randomseed(0)";
+
+/// Build the deterministic `pairs` function for a Lua state
+pub(crate) fn deterministic_pairs(lua: &Lua) -> LuaResult<mlua::Function> {
+    lua.load(DETERMINISTIC_PAIRS).set_name("deterministic_pairs").eval()
+}
+
+/// Conversion specifiers os.date accepts (the C89 strftime set)
+const OS_DATE_CONVERSIONS: &[u8] = b"aAbBcdHIjmMpSUwWxXyYZ%";
+
+/// Build the restricted `os` table for scripts from the stdlib `os`
+///
+/// Only `clock`, `time` and `date` are exposed. `time` and `date` read the wall
+/// clock, so in deterministic mode they fail and scripts use `redis.call('TIME')`.
+pub(crate) fn restricted_os(lua: &Lua, stdlib_os: &Table, deterministic: bool) -> LuaResult<Table> {
+    let os = lua.create_table()?;
+    os.set("clock", stdlib_os.get::<mlua::Function>("clock")?)?;
+    if deterministic {
+        for name in ["time", "date"] {
+            let disabled = lua.create_function(move |_, _: MultiValue| -> LuaResult<()> {
+                Err(mlua::Error::RuntimeError(format!(
+                    "os.{} is not available in deterministic mode, use redis.call('TIME')", name)))
+            })?;
+            os.set(name, disabled)?;
+        }
+        return Ok(os);
+    }
+    
+    os.set("time", stdlib_os.get::<mlua::Function>("time")?)?;
+    let date = stdlib_os.get::<mlua::Function>("date")?;
+    let checked_date = lua.create_function(move |_, args: MultiValue| {
+        if let Some(LuaValue::String(format)) = args.front() {
+            let format = format.as_bytes();
+            let mut specifiers = format.split(|&b| b == b'%').skip(1);
+            while let Some(rest) = specifiers.next() {
+                match rest.first() {
+                    // "%%" splits into an empty piece; the next piece is literal text
+                    None => { specifiers.next(); }
+                    Some(c) if OS_DATE_CONVERSIONS.contains(c) => {}
+                    Some(c) => return Err(mlua::Error::RuntimeError(format!(
+                        "bad argument #1 to 'date' (invalid conversion specifier '%{}')", *c as char))),
+                }
+            }
+        }
+        date.call::<MultiValue>(args)
+    })?;
+    os.set("date", checked_date)?;
+    Ok(os)
+}
+
+/// First line of an error message, without what follows a line break
+fn first_line(msg: &str) -> &str {
+    msg.split('\n').next().unwrap_or(msg).trim_end()
+}
+
+/// A script loaded by SCRIPT LOAD: its source and compiled bytecode
+#[derive(Clone)]
+struct PrecompiledScript {
+    source: Arc<str>,
+    bytecode: Arc<Vec<u8>>,
+}
+
 /// Single-threaded Lua execution engine with unified command processing
 pub struct LuaEngine {
     // Removed local script_cache - using global cache at server level
+    
+    /// Reply size guard applied to every redis.call/pcall
+    reply_limits: RwLock<ReplyLimits>,
+    
+    /// Whether scripts get the opt-in `utf8` library
+    utf8_enabled: AtomicBool,
+    
+    /// Whether scripts run with deterministic `pairs` order and PRNG seed
+    deterministic: AtomicBool,
+    
+    /// Directory receiving tapes of failed scripts (recording is off when unset)
+    record_dir: RwLock<Option<PathBuf>>,
+    
+    /// Milliseconds a script may run before it is terminated (0 = unlimited)
+    time_limit_ms: AtomicU64,
+    
+    /// Lua instructions a script may execute (0 = unlimited)
+    max_instructions: AtomicU64,
+    
+    /// Deepest Lua call stack a script may reach (0 = Lua's own limit)
+    max_call_depth: AtomicUsize,
+    
+    /// Lua heap limit applied to each script context (0 = unlimited)
+    memory_limit: AtomicUsize,
+    
+    /// Whether scripts keep the `debug` library
+    debug_library: AtomicBool,
+    
+    /// Deepest table nesting converted from a script result before failing
+    max_nesting_depth: AtomicUsize,
+    
+    /// Scripts compiled by SCRIPT LOAD, keyed by SHA1
+    precompiled: RwLock<HashMap<String, PrecompiledScript>>,
 }
 
 impl LuaEngine {
     pub fn new(_storage: Arc<StorageEngine>) -> Result<Self> {
         Ok(LuaEngine {
-            // No local cache needed
+            reply_limits: RwLock::new(ReplyLimits::default()),
+            utf8_enabled: AtomicBool::new(false),
+            deterministic: AtomicBool::new(false),
+            record_dir: RwLock::new(None),
+            time_limit_ms: AtomicU64::new(5000),
+            max_instructions: AtomicU64::new(0),
+            max_call_depth: AtomicUsize::new(0),
+            memory_limit: AtomicUsize::new(0),
+            debug_library: AtomicBool::new(false),
+            max_nesting_depth: AtomicUsize::new(DEFAULT_MAX_NESTING_DEPTH),
+            precompiled: RwLock::new(HashMap::new()),
         })
     }
     
+    /// Get the current reply size limits
+    pub fn reply_limits(&self) -> ReplyLimits {
+        *self.reply_limits.read().unwrap()
+    }
+    
+    /// Replace the reply size limits used by subsequent scripts
+    pub fn set_reply_limits(&self, limits: ReplyLimits) {
+        *self.reply_limits.write().unwrap() = limits;
+    }
+    
+    /// Check whether the `utf8` library is exposed to scripts
+    pub fn utf8_enabled(&self) -> bool {
+        self.utf8_enabled.load(Ordering::Relaxed)
+    }
+    
+    /// Enable or disable the `utf8` library for subsequent scripts
+    pub fn set_utf8_enabled(&self, enabled: bool) {
+        self.utf8_enabled.store(enabled, Ordering::Relaxed);
+    }
+    
+    /// Check whether deterministic mode is enabled
+    pub fn deterministic(&self) -> bool {
+        self.deterministic.load(Ordering::Relaxed)
+    }
+    
+    /// Enable or disable deterministic mode for subsequent scripts
+    pub fn set_deterministic(&self, enabled: bool) {
+        self.deterministic.store(enabled, Ordering::Relaxed);
+    }
+    
+    /// Directory where tapes of failed scripts are written, if recording is enabled
+    pub fn record_dir(&self) -> Option<PathBuf> {
+        self.record_dir.read().unwrap().clone()
+    }
+    
+    /// Enable (or with `None`, disable) recording of failed scripts
+    pub fn set_record_dir(&self, dir: Option<PathBuf>) {
+        *self.record_dir.write().unwrap() = dir;
+    }
+    
+    /// Milliseconds after which a running script is busy (0 = never)
+    pub fn time_limit_ms(&self) -> u64 {
+        self.time_limit_ms.load(Ordering::Relaxed)
+    }
+    
+    /// Set the time limit for subsequent scripts
+    pub fn set_time_limit_ms(&self, millis: u64) {
+        self.time_limit_ms.store(millis, Ordering::Relaxed);
+    }
+    
+    /// Lua instructions a script may execute (0 = unlimited)
+    pub fn max_instructions(&self) -> u64 {
+        self.max_instructions.load(Ordering::Relaxed)
+    }
+    
+    /// Set the instruction limit for subsequent scripts
+    pub fn set_max_instructions(&self, instructions: u64) {
+        self.max_instructions.store(instructions, Ordering::Relaxed);
+    }
+    
+    /// Deepest Lua call stack a script may reach (0 = Lua's own limit)
+    pub fn max_call_depth(&self) -> usize {
+        self.max_call_depth.load(Ordering::Relaxed)
+    }
+    
+    /// Set the call depth limit for subsequent scripts
+    pub fn set_max_call_depth(&self, depth: usize) {
+        self.max_call_depth.store(depth, Ordering::Relaxed);
+    }
+    
+    /// Execution budget for the next script run
+    pub fn budget(&self) -> lua_budget::Budget {
+        lua_budget::Budget {
+            time_limit_ms: self.time_limit_ms(),
+            max_instructions: self.max_instructions(),
+            max_call_depth: self.max_call_depth(),
+            cpu_allowance_ms: 0,
+        }
+    }
+    
+    /// Lua heap limit applied to each script context (0 = unlimited)
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit.load(Ordering::Relaxed)
+    }
+    
+    /// Set the Lua heap limit for subsequent scripts
+    pub fn set_memory_limit(&self, bytes: usize) {
+        self.memory_limit.store(bytes, Ordering::Relaxed);
+    }
+    
+    /// Check whether scripts keep the `debug` library
+    pub fn debug_library_enabled(&self) -> bool {
+        self.debug_library.load(Ordering::Relaxed)
+    }
+    
+    /// Enable or disable the `debug` library for subsequent scripts
+    pub fn set_debug_library_enabled(&self, enabled: bool) {
+        self.debug_library.store(enabled, Ordering::Relaxed);
+    }
+    
+    /// Deepest table nesting converted from a script result before failing
+    pub fn max_nesting_depth(&self) -> usize {
+        self.max_nesting_depth.load(Ordering::Relaxed)
+    }
+    
+    /// Set the nesting limit for subsequent script results
+    pub fn set_max_nesting_depth(&self, depth: usize) {
+        self.max_nesting_depth.store(depth, Ordering::Relaxed);
+    }
+    
     /// Execute a Lua script using unified command processing
-    pub fn eval(&self, script: &str, keys: Vec<Vec<u8>>, args: Vec<Vec<u8>>, ctx: &LuaCommandContext) -> Result<RespFrame> {
+    pub fn eval(&self, script: &str, keys: Vec<Bytes>, args: Vec<Bytes>, ctx: &LuaCommandContext) -> Result<RespFrame> {
+        let _invariants = lua_invariants::InvariantScope::begin();
+        let checkout = lua_quota::Checkout::begin()?;
+        let _defer = lua_triggers::DeferScope::begin();
+        let record_dir = self.record_dir();
+        let inputs = record_dir.as_ref().map(|_| (keys.clone(), args.clone()));
+        
         let lua = self.create_lua_context(ctx)?;
-        self.setup_keys_and_args(&lua, keys, args)?;
+        self.setup_keys_and_args(&lua, &keys, &args)?;
         
-        let start_time = Instant::now();
-        let result = lua.load(script).eval::<LuaValue>();
+        let recording = inputs.as_ref().map(|_| lua_record::Recording::start());
+        let audit = lua_audit::AuditScope::begin(|| self.calculate_script_sha1(script));
+        let dirty = lua_dirty::DirtyScope::begin(&ctx.storage);
+        let log_scope = lua_log::ScriptLogScope::begin();
+        let budget = lua_budget::BudgetScope::begin(lua_budget::Budget { cpu_allowance_ms: checkout.cpu_allowance_ms(), ..self.budget() });
+        let stats = lua_stats::VmStatsScope::begin(&lua).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        let result = budget.finish(self.run_script(&lua, script));
+        stats.finish(&lua);
+        drop(log_scope);
+        drop(dirty);
+        if let Some(audit) = audit {
+            audit.finish(&result);
+        }
         
-        match result {
-            Ok(value) => Ok(self.lua_value_to_resp(value)),
-            Err(e) => {
-                match e {
-                    mlua::Error::RuntimeError(ref msg) => {
-                        if let Some(pos) = msg.find("REDIS_CALL_ABORT:") {
-                            let mut error_content = &msg[pos + "REDIS_CALL_ABORT:".len()..];
-                            error_content = error_content.trim_start_matches(|c: char| !c.is_alphabetic());
-                            let end_pos = error_content.find('\n').unwrap_or(error_content.len());
-                            let clean_error = error_content[..end_pos].trim().to_string();
-                            let final_error = if clean_error.starts_with("ERR ") { 
-                                clean_error 
-                            } else { 
-                                format!("ERR {}", clean_error) 
-                            };
-                            Err(FerrousError::LuaError(final_error))
-                        } else {
-                            Err(FerrousError::LuaError(format!("ERR Error running script: {}", msg)))
-                        }
-                    }
-                    mlua::Error::SyntaxError { message, .. } => {
-                        // Clean syntax error messages to remove file path information
-                        let clean_msg = if message.contains(".rs:") && message.contains(": ") {
-                            // Extract message after the file:line:col part
-                            if let Some(last_colon_space) = message.rfind(": ") {
-                                &message[last_colon_space + 2..]
-                            } else {
-                                &message
-                            }
-                        } else {
-                            &message
-                        };
-                        Err(FerrousError::LuaError(format!("ERR Error compiling script: {}", clean_msg)))
-                    }
-                    _ => {
-                        Err(FerrousError::LuaError(format!("ERR Script execution failed: {}", e)))
-                    }
+        if let (Some(dir), Some((keys, args)), Some(recording)) = (record_dir, inputs, recording) {
+            let calls = recording.finish();
+            if let Err(e) = &result {
+                let tape = lua_record::ScriptTape {
+                    script: script.to_string(),
+                    db: ctx.db_index,
+                    keys: keys.iter().map(|key| key.to_vec()).collect(),
+                    args: args.iter().map(|arg| arg.to_vec()).collect(),
+                    limits: self.reply_limits(),
+                    utf8: self.utf8_enabled(),
+                    deterministic: self.deterministic(),
+                    calls,
+                    outcome: Err(e.to_string()),
+                };
+                match tape.save_in(&dir) {
+                    Ok(path) => println!("Recorded failing script to {}", path.display()),
+                    Err(e) => eprintln!("Failed to record script tape: {}", e),
                 }
             }
         }
+        
+        result
     }
     
-    pub fn script_load(&self, script: &str) -> Result<String> {
-        // Create a basic Lua context for syntax validation only
-        let lua = Lua::new();
-        let globals = lua.globals();
-        
-        // Remove dangerous functions for sandboxing
-        let dangerous_functions = ["os", "io", "debug", "package", "require", "dofile", "loadfile", "load"];
-        for func in &dangerous_functions {
-            globals.set(*func, mlua::Nil).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+    /// Run a script in a prepared context and convert its result
+    ///
+    /// Scripts compiled ahead by SCRIPT LOAD run from their cached bytecode.
+    pub(crate) fn run_script(&self, lua: &Lua, script: &str) -> Result<RespFrame> {
+        let bytecode = self.precompiled_for(script);
+        let chunk = match &bytecode {
+            Some(bytecode) => lua.load(bytecode.as_slice()).set_mode(ChunkMode::Binary),
+            None => lua.load(script).set_mode(ChunkMode::Text),
+        };
+        match chunk.set_name(SCRIPT_CHUNK_NAME).eval::<LuaValue>() {
+            Ok(value) => self.lua_value_to_resp(value),
+            Err(e) => Err(Self::map_lua_error(e)),
         }
+    }
+    
+    /// Check that scripts can run: create a VM the way EVAL does and run `return 1` in it
+    ///
+    /// Returns how long that took. Fails when the VM cannot be set up, the
+    /// script does not return 1, or the whole check takes longer than `deadline`.
+    pub fn probe(&self, ctx: &LuaCommandContext, deadline: Duration) -> Result<Duration> {
+        let started = Instant::now();
+        let lua = self.create_lua_context(ctx)?;
+        let reply = self.run_script(&lua, PROBE_SCRIPT)?;
+        let elapsed = started.elapsed();
         
-        // Create minimal Redis API table for validation
-        let redis_table = lua.create_table().map_err(|e| FerrousError::LuaError(e.to_string()))?;
-        
-        // Add dummy redis.call and redis.pcall for syntax validation
-        let dummy_call = lua.create_function(|_, _: mlua::MultiValue| -> mlua::Result<mlua::Value> {
-            Ok(mlua::Value::Nil)
-        }).map_err(|e| FerrousError::LuaError(e.to_string()))?;
-        
-        redis_table.set("call", dummy_call.clone()).map_err(|e| FerrousError::LuaError(e.to_string()))?;
-        redis_table.set("pcall", dummy_call).map_err(|e| FerrousError::LuaError(e.to_string()))?;
-        globals.set("redis", redis_table).map_err(|e| FerrousError::LuaError(e.to_string()))?;
-        
-        // Set up empty KEYS and ARGV arrays for syntax validation
-        let keys_table = lua.create_table().map_err(|e| FerrousError::LuaError(e.to_string()))?;
-        globals.set("KEYS", keys_table).map_err(|e| FerrousError::LuaError(e.to_string()))?;
-        
-        let argv_table = lua.create_table().map_err(|e| FerrousError::LuaError(e.to_string()))?;
-        globals.set("ARGV", argv_table).map_err(|e| FerrousError::LuaError(e.to_string()))?;
-        
-        // Proper syntax validation: Load the chunk to validate compilation
-        // This will fail if there are syntax errors, but won't execute the script
-        let chunk = lua.load(script);
-        
-        // Attempt to create the function to validate syntax
-        // This catches compilation errors without executing
-        chunk.into_function().map_err(|e| {
-            // Completely strip internal file path information from mlua errors
-            let error_msg = e.to_string();
-            
-            // mlua error format: "src/storage/lua_engine.rs:41:1: '=' expected near 'lua'"
-            // We want to extract just: "'=' expected near 'lua'"
-            
-            // Find the last occurrence of ": " and take everything after it
-            let clean_error = if let Some(last_colon_space) = error_msg.rfind(": ") {
-                &error_msg[last_colon_space + 2..]
-            } else {
-                // If no pattern found, check if it's already a clean error
-                if error_msg.contains("expected") || error_msg.contains("unexpected") || error_msg.contains("syntax") {
-                    &error_msg
+        if reply != RespFrame::Integer(1) {
+            return Err(FerrousError::LuaError(format!("ERR health probe returned {:?} instead of 1", reply)));
+        }
+        if elapsed > deadline {
+            return Err(FerrousError::LuaError(format!(
+                "ERR health probe took {} us, over its {} ms deadline", elapsed.as_micros(), deadline.as_millis())));
+        }
+        Ok(elapsed)
+    }
+    
+    /// Cached bytecode for a script, if SCRIPT LOAD compiled it
+    fn precompiled_for(&self, script: &str) -> Option<Arc<Vec<u8>>> {
+        let precompiled = self.precompiled.read().unwrap();
+        if precompiled.is_empty() {
+            return None;
+        }
+        precompiled.get(&self.calculate_script_sha1(script)).map(|loaded| loaded.bytecode.clone())
+    }
+    
+    /// Source of a script loaded by SCRIPT LOAD, looked up by SHA1 (case-insensitive like Redis)
+    pub fn cached_script(&self, sha1: &str) -> Option<Arc<str>> {
+        let precompiled = self.precompiled.read().unwrap();
+        precompiled.get(&sha1.to_ascii_lowercase()).map(|loaded| loaded.source.clone())
+    }
+    
+    /// Sources of every script cached by SCRIPT LOAD, sorted by SHA1
+    pub fn cached_scripts(&self) -> Vec<Arc<str>> {
+        let precompiled = self.precompiled.read().unwrap();
+        let mut scripts: Vec<(&String, &PrecompiledScript)> = precompiled.iter().collect();
+        scripts.sort_by(|a, b| a.0.cmp(b.0));
+        scripts.into_iter().map(|(_, loaded)| loaded.source.clone()).collect()
+    }
+    
+    /// Check whether SCRIPT LOAD cached a script with this SHA1 (SCRIPT EXISTS)
+    pub fn script_exists(&self, sha1: &str) -> bool {
+        self.precompiled.read().unwrap().contains_key(&sha1.to_ascii_lowercase())
+    }
+    
+    /// Compile a script to bytecode the way EVAL loads it, without running it
+    ///
+    /// Like EVAL, the script is first tried as an expression and then as a
+    /// block. Syntax errors keep their `user_script:<line>:` location.
+    pub fn compile(&self, script: &str) -> Result<Vec<u8>> {
+        let lua = Lua::new_with(StdLib::NONE, LuaOptions::new()).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        let expression = format!("return {}", script);
+        let function = match lua.load(&expression).set_name(SCRIPT_CHUNK_NAME).into_function() {
+            Ok(function) => function,
+            Err(_) => lua.load(script).set_name(SCRIPT_CHUNK_NAME).set_mode(ChunkMode::Text).into_function().map_err(|e| {
+                let message = match e {
+                    mlua::Error::SyntaxError { message, .. } => message,
+                    other => other.to_string(),
+                };
+                FerrousError::Script(ScriptError::CompilationError(message))
+            })?,
+        };
+        Ok(function.dump(false))
+    }
+    
+    /// Drop all bytecode cached by SCRIPT LOAD (SCRIPT FLUSH)
+    pub fn flush_precompiled(&self) {
+        self.precompiled.write().unwrap().clear();
+    }
+    
+    /// Map an mlua error to the Redis-style error reported to the client
+    pub(crate) fn map_lua_error(e: mlua::Error) -> FerrousError {
+        match e {
+            mlua::Error::RuntimeError(ref msg) => {
+                if let Some(pos) = msg.find("REDIS_CALL_ABORT:") {
+                    let mut error_content = &msg[pos + "REDIS_CALL_ABORT:".len()..];
+                    error_content = error_content.trim_start_matches(|c: char| !c.is_alphabetic());
+                    let end_pos = error_content.find('\n').unwrap_or(error_content.len());
+                    let clean_error = error_content[..end_pos].trim().to_string();
+                    FerrousError::LuaError(with_error_class(clean_error))
                 } else {
-                    "syntax error"
+                    // Drop the stack traceback mlua appends on the following lines
+                    FerrousError::LuaError(format!("ERR Error running script: {}", first_line(msg)))
                 }
-            };
-            
-            FerrousError::LuaError(format!("ERR Error compiling script: {}", clean_error))
-        })?;
-        
-        // Generate SHA1 for the script
+            }
+            mlua::Error::SyntaxError { message, .. } => {
+                // Clean syntax error messages to remove file path information
+                let clean_msg = if message.contains(".rs:") && message.contains(": ") {
+                    // Extract message after the file:line:col part
+                    if let Some(last_colon_space) = message.rfind(": ") {
+                        &message[last_colon_space + 2..]
+                    } else {
+                        &message
+                    }
+                } else {
+                    &message
+                };
+                FerrousError::LuaError(format!("ERR Error compiling script: {}", first_line(clean_msg)))
+            }
+            mlua::Error::CallbackError { cause, .. } => Self::map_lua_error((*cause).clone()),
+            mlua::Error::MemoryError(_) => FerrousError::Script(ScriptError::OutOfMemory),
+            _ => {
+                FerrousError::LuaError(format!("ERR Script execution failed: {}", first_line(&e.to_string())))
+            }
+        }
+    }
+    
+    /// Compile a script and cache its bytecode for EVALSHA, returning its SHA1
+    pub fn script_load(&self, script: &str) -> Result<String> {
+        let bytecode = self.compile(script)?;
         let sha1 = self.calculate_script_sha1(script);
-        
+        let loaded = PrecompiledScript { source: Arc::from(script), bytecode: Arc::new(bytecode) };
+        self.precompiled.write().unwrap().insert(sha1.clone(), loaded);
         Ok(sha1)
     }
     
     /// Create Lua context with unified redis.call implementation
-    fn create_lua_context(&self, ctx: &LuaCommandContext) -> Result<Lua> {
-        let lua = Lua::new();
+    pub(crate) fn create_lua_context(&self, ctx: &LuaCommandContext) -> Result<Lua> {
+        let lua = lua_vm::create_vm(lua_quota::memory_limit(self.memory_limit()), self.debug_library_enabled())?;
         
-        // Remove dangerous functions for sandboxing
+        // Remove dangerous functions for sandboxing; os comes back restricted
         let globals = lua.globals();
+        let stdlib_os: Option<Table> = globals.get("os").map_err(|e| FerrousError::LuaError(e.to_string()))?;
         let dangerous_functions = ["os", "io", "debug", "package", "require", "dofile", "loadfile", "load"];
         for func in &dangerous_functions {
+            if *func == "debug" && self.debug_library_enabled() {
+                continue;
+            }
             globals.set(*func, mlua::Nil).map_err(|e| FerrousError::LuaError(e.to_string()))?;
         }
+        if let Some(stdlib_os) = stdlib_os {
+            let os = restricted_os(&lua, &stdlib_os, self.deterministic()).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+            globals.set("os", os).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        }
         
         // Create Redis API using unified command processing
         let redis_table = lua.create_table().map_err(|e| FerrousError::LuaError(e.to_string()))?;
         
         let storage_ref = ctx.storage.clone();
         let db_index = ctx.db_index;
+        let limits = self.reply_limits();
         
         // redis.call: Errors terminate the script immediately
         let redis_call = lua.create_function(move |lua_ctx, cmd: MultiValue| -> LuaResult<LuaValue> {
-            Self::execute_unified_redis_command(&storage_ref, lua_ctx, cmd, db_index, false)
+            Self::execute_unified_redis_command(&storage_ref, lua_ctx, cmd, db_index, false, limits)
         }).map_err(|e| FerrousError::LuaError(e.to_string()))?;
         
         let storage_ref_pcall = ctx.storage.clone();
         // redis.pcall: Errors return nil, script continues
         let redis_pcall = lua.create_function(move |lua_ctx, cmd: MultiValue| -> LuaResult<LuaValue> {
-            Self::execute_unified_redis_command(&storage_ref_pcall, lua_ctx, cmd, db_index, true)
+            Self::execute_unified_redis_command(&storage_ref_pcall, lua_ctx, cmd, db_index, true, limits)
         }).map_err(|e| FerrousError::LuaError(e.to_string()))?;
         
         redis_table.set("call", redis_call).map_err(|e| FerrousError::LuaError(e.to_string()))?;
         redis_table.set("pcall", redis_pcall).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        
+        // redis.mcall: a batch of commands in one bridge crossing, with redis.call error semantics
+        let storage_ref_mcall = ctx.storage.clone();
+        let redis_mcall = lua.create_function(move |lua_ctx, commands: Table| {
+            Self::run_batch(lua_ctx, commands, |cmd| {
+                Self::execute_unified_redis_command(&storage_ref_mcall, lua_ctx, cmd, db_index, false, limits)
+            })
+        }).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        redis_table.set("mcall", redis_mcall).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        
+        // redis.call_array: redis.call that guarantees a 1..n sequence
+        let storage_ref_array = ctx.storage.clone();
+        let redis_call_array = lua.create_function(move |lua_ctx, cmd: MultiValue| {
+            Self::call_array(lua_ctx, cmd, |cmd| {
+                Self::execute_unified_redis_command(&storage_ref_array, lua_ctx, cmd, db_index, false, limits)
+            })
+        }).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        redis_table.set("call_array", redis_call_array).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        
+        // redis.call_iter: chunked iteration over large collections
+        let call_iter = lua_iter::create_call_iter(&lua, ctx.storage.clone(), move || db_index)
+            .map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        redis_table.set("call_iter", call_iter).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        let cursor = lua_iter::create_cursor(&lua, ctx.storage.clone(), move || db_index)
+            .map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        redis_table.set("cursor", cursor).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        lua_log::register(&lua, &redis_table).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        lua_checkpoint::register(&lua, &redis_table).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        Self::register_reply_helpers(&lua, &redis_table).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        Self::register_version(&redis_table).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        let redis_table = lua_readonly::read_only(&lua, redis_table).map_err(|e| FerrousError::LuaError(e.to_string()))?;
         globals.set("redis", redis_table).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        lua_readonly::register(&lua, &globals).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        lua_sandbox::register(&lua, &globals).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        let require = lua_require::create_require(&lua, globals.clone()).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        globals.set("require", require).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        lua_budget::register(&lua, &globals).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        let cjson = lua_cjson::create_cjson_table(&lua).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        globals.set("cjson", cjson).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        let bit = lua_bit::create_bit_table(&lua).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        globals.set("bit", bit).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        
+        if self.utf8_enabled() {
+            let utf8 = lua_utf8::create_utf8_table(&lua).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+            globals.set("utf8", utf8).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        }
+        let ferrous = Self::ferrous_table(&lua, self.utf8_enabled(), self.debug_library_enabled(), self.deterministic())
+            .map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        globals.set("ferrous", ferrous).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        
+        if self.deterministic() {
+            globals.set("pairs", deterministic_pairs(&lua).map_err(|e| FerrousError::LuaError(e.to_string()))?)
+                .map_err(|e| FerrousError::LuaError(e.to_string()))?;
+            lua.load(DETERMINISTIC_SEED).exec().map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        }
         
         Ok(lua)
     }
     
+    /// Add redis.REDIS_VERSION and redis.REDIS_VERSION_NUM to a `redis` table
+    pub(crate) fn register_version(redis_table: &Table) -> LuaResult<()> {
+        redis_table.set("REDIS_VERSION", monitor::REDIS_VERSION)?;
+        redis_table.set("REDIS_VERSION_NUM", monitor::REDIS_VERSION_NUM)
+    }
+    
+    /// Build the `ferrous` table: the server version and the optional features scripts can detect
+    pub(crate) fn ferrous_table(lua: &Lua, utf8: bool, debug: bool, deterministic: bool) -> LuaResult<Table> {
+        let features = lua.create_table()?;
+        for (name, present) in [
+            ("utf8", utf8),
+            ("debug", debug),
+            ("deterministic", deterministic),
+            ("require", true),
+            ("checkpoint", lua_checkpoint::is_enabled()),
+            ("cjson", true),
+            ("bit", true),
+            // Libraries bundled with Redis that Ferrous does not provide
+            ("cmsgpack", false),
+            ("struct", false),
+        ] {
+            features.set(name, present)?;
+        }
+        
+        let ferrous = lua.create_table()?;
+        ferrous.set("version", env!("CARGO_PKG_VERSION"))?;
+        ferrous.set("features", features)?;
+        Ok(ferrous)
+    }
+    
+    /// Add redis.error_reply, redis.status_reply and redis.sha1hex to a `redis` table
+    pub(crate) fn register_reply_helpers(lua: &Lua, redis_table: &Table) -> LuaResult<()> {
+        for (name, field) in [("error_reply", "err"), ("status_reply", "ok")] {
+            let helper = lua.create_function(move |lua_ctx, message: mlua::String| {
+                let reply = lua_ctx.create_table_with_capacity(0, 1)?;
+                reply.raw_set(field, message)?;
+                Ok(reply)
+            })?;
+            redis_table.set(name, helper)?;
+        }
+        
+        let sha1hex = lua.create_function(|_, data: mlua::String| {
+            Ok(hex::encode(Sha1::digest(&data.as_bytes()[..])))
+        })?;
+        redis_table.set("sha1hex", sha1hex)
+    }
+    
     /// Execute Redis command using unified command processor
-    fn execute_unified_redis_command(
+    pub(crate) fn execute_unified_redis_command(
         storage: &Arc<StorageEngine>,
         lua_ctx: &Lua,
         cmd: MultiValue,
         db_index: usize,
         is_pcall: bool,
+        limits: ReplyLimits,
     ) -> LuaResult<LuaValue> {
-        // Parse command arguments
-        let mut args = Vec::new();
+        Self::run_redis_command(lua_ctx, cmd, is_pcall, limits, |args| {
+            // Same access clock path as client commands (honors the caller's NO-TOUCH)
+            lru::record_access(storage, db_index, &args);
+            lua_stats::note_redis_call();
+            
+            let recorded_args = lua_record::is_recording().then(|| args.clone());
+            let audited_args = lua_audit::wants(&args).then(|| args.clone());
+            let effect_args = lua_effects::wants(&args).then(|| args.clone());
+            
+            // Route through unified command processor
+            let written = lua_dirty::written_keys(&args);
+            let events = lua_triggers::command_events(&args);
+            let result = LuaCommandAdapter::new(storage.clone()).execute(args, db_index);
+            if result.is_ok() {
+                lua_dirty::mark_written(db_index, written);
+            }
+            if let (Some((event, keys)), Ok(reply)) = (events, &result) {
+                if !reply.is_error() {
+                    lua_triggers::notify_keys(db_index, &event, keys);
+                }
+            }
+            if let Some(args) = recorded_args {
+                lua_record::record_call(args, &result);
+            }
+            if let Some(args) = effect_args {
+                let args = strings::propagated_expire(storage, db_index, &args)
+                    .or_else(|| hashes::propagated_field_expire(&args))
+                    .unwrap_or(args);
+                lua_effects::record_write(args, &result);
+            }
+            if let Some(args) = audited_args {
+                lua_audit::record_write(db_index, &args, &result);
+            }
+            result
+        })
+    }
+    
+    /// Run each command of a redis.mcall batch through `call`, collecting the replies
+    ///
+    /// Nil replies are stored as `false` so the result stays a proper sequence.
+    pub(crate) fn run_batch<F>(lua_ctx: &Lua, commands: Table, mut call: F) -> LuaResult<Table>
+    where
+        F: FnMut(MultiValue) -> LuaResult<LuaValue>,
+    {
+        let replies = lua_ctx.create_table_with_capacity(commands.raw_len(), 0)?;
+        for (idx, command) in commands.sequence_values::<LuaValue>().enumerate() {
+            let command = match command? {
+                LuaValue::Table(command) => command,
+                _ => return Err(mlua::Error::RuntimeError(
+                    format!("REDIS_CALL_ABORT:ERR redis.mcall entry #{} is not a command table", idx + 1))),
+            };
+            let args = command.sequence_values::<LuaValue>().collect::<LuaResult<MultiValue>>()?;
+            let reply = match call(args)? {
+                LuaValue::Nil => LuaValue::Boolean(false),
+                reply => reply,
+            };
+            replies.raw_set(idx + 1, reply)?;
+        }
+        Ok(replies)
+    }
+    
+    /// Run a redis.call_array command through `call`, requiring an array reply
+    ///
+    /// Arrays come back as they are (nils already stored as `false`), a nil
+    /// reply as an empty sequence; status, bulk and integer replies raise an error.
+    pub(crate) fn call_array<F>(lua_ctx: &Lua, cmd: MultiValue, call: F) -> LuaResult<Table>
+    where
+        F: FnOnce(MultiValue) -> LuaResult<LuaValue>,
+    {
+        let name = match cmd.front() {
+            Some(LuaValue::String(name)) => name.to_string_lossy().to_uppercase(),
+            _ => String::new(),
+        };
+        let kind = match call(cmd)? {
+            LuaValue::Table(table) if table.raw_get::<LuaValue>("ok")?.is_nil() => return Ok(table),
+            LuaValue::Nil => return lua_ctx.create_table(),
+            LuaValue::Table(_) => "a status",
+            LuaValue::String(_) => "a bulk string",
+            _ => "an integer",
+        };
+        Err(mlua::Error::RuntimeError(format!(
+            "REDIS_CALL_ABORT:ERR redis.call_array expected an array reply but {} returned {} reply", name, kind)))
+    }
+    
+    /// Validate a redis.call invocation, run it through `execute` and convert the reply
+    ///
+    /// Shared by the live bridge and the tape replayer, so a replayed script sees
+    /// exactly the argument checks and reply conversion the original run did.
+    pub(crate) fn run_redis_command<F>(
+        lua_ctx: &Lua,
+        cmd: MultiValue,
+        is_pcall: bool,
+        limits: ReplyLimits,
+        execute: F,
+    ) -> LuaResult<LuaValue>
+    where
+        F: FnOnce(Vec<Vec<u8>>) -> Result<Reply>,
+    {
+        // Collect command arguments as raw bytes (scripts may pass binary data)
+        let mut args: Vec<Vec<u8>> = Vec::new();
         for value in cmd {
             match value {
-                LuaValue::String(s) => {
-                    match s.to_str() {
-                        Ok(string_val) => args.push(string_val.to_string()),
-                        Err(_) => {
-                            return Self::handle_command_error_with_context(lua_ctx, "Invalid UTF-8 in command argument".to_string(), is_pcall);
-                        }
-                    }
-                }
-                LuaValue::Integer(i) => args.push(i.to_string()),
-                LuaValue::Number(n) => args.push(n.to_string()),
+                LuaValue::String(s) => args.push(s.as_bytes().to_vec()),
+                LuaValue::Integer(i) => args.push(i.to_string().into_bytes()),
+                LuaValue::Number(n) => args.push(n.to_string().into_bytes()),
                 _ => {
                     return Self::handle_command_error_with_context(lua_ctx, "Invalid argument type".to_string(), is_pcall);
                 }
@@ -213,11 +887,15 @@ impl LuaEngine {
             return Self::handle_command_error_with_context(lua_ctx, "No command specified".to_string(), is_pcall);
         }
         
-        let cmd_name = args[0].to_uppercase();
+        let cmd_name = String::from_utf8_lossy(&args[0]).to_uppercase();
+        
+        if let Some(error) = ReadOnlyScript::denied(&cmd_name) {
+            return Self::handle_command_error_with_context(lua_ctx, error.to_string(), is_pcall);
+        }
         
         // Block commands that shouldn't be available in Lua scripts
         match cmd_name.as_str() {
-            "EVAL" | "EVALSHA" | "SCRIPT" => {
+            "EVAL" | "EVALSHA" | "EVAL_RO" | "EVALSHA_RO" | "SCRIPT" | "FCALL" | "FCALL_RO" | "FUNCTION" => {
                 return Self::handle_command_error_with_context(
                     lua_ctx, 
                     "Redis scripting commands are not allowed inside Lua scripts".to_string(), 
@@ -238,7 +916,7 @@ impl LuaEngine {
                     is_pcall
                 );
             }
-            "BLPOP" | "BRPOP" | "BZPOPMIN" | "BZPOPMAX" => {
+            "BLPOP" | "BRPOP" | "BLMOVE" | "BRPOPLPUSH" | "BLMPOP" | "BZPOPMIN" | "BZPOPMAX" => {
                 return Self::handle_command_error_with_context(
                     lua_ctx,
                     format!("'{}' blocking command is not allowed inside Lua scripts", cmd_name),
@@ -267,96 +945,140 @@ impl LuaEngine {
                 );
             }
             _ => {
-                // Route through unified command processor
-                let lua_adapter = LuaCommandAdapter::new(storage.clone());
-                match lua_adapter.execute_lua_command(args, db_index) {
-                    Ok(resp_frame) => Self::resp_frame_to_lua_value(lua_ctx, resp_frame, is_pcall),
-                    Err(e) => Self::handle_command_error_with_context(lua_ctx, e.to_string(), is_pcall),
+                match execute(args) {
+                    Ok(reply) => {
+                        let mut budget = ReplyBudget::new(limits);
+                        // Reject oversized replies up front so no partial table is ever built
+                        if !limits.truncate && !budget.admits(&reply) {
+                            return Self::handle_command_error_with_context(lua_ctx, budget.error_message(), is_pcall);
+                        }
+                        Self::reply_to_lua_value(lua_ctx, reply, is_pcall, &mut budget)
+                    }
+                    Err(e) => Self::handle_command_error_with_context(lua_ctx, script_error_message(&e), is_pcall),
                 }
             }
         }
     }
     
-    /// Convert RESP frame to Lua value
-    fn resp_frame_to_lua_value(lua_ctx: &Lua, frame: RespFrame, is_pcall: bool) -> LuaResult<LuaValue> {
-        match frame {
-            RespFrame::SimpleString(bytes) => {
-                let string_val = String::from_utf8_lossy(&bytes).into_owned();
-                match lua_ctx.create_string(&string_val) {
-                    Ok(lua_string) => Ok(LuaValue::String(lua_string)),
-                    Err(e) => Self::handle_command_error_with_context(lua_ctx, e.to_string(), is_pcall),
-                }
+    /// Convert a command reply to a Lua value, charging it against the size budget
+    fn reply_to_lua_value(lua_ctx: &Lua, reply: Reply, is_pcall: bool, budget: &mut ReplyBudget) -> LuaResult<LuaValue> {
+        if !budget.charge(Self::reply_len(&reply)) {
+            return Self::handle_command_error_with_context(lua_ctx, budget.error_message(), is_pcall);
+        }
+        
+        match reply {
+            Reply::Status(status) => {
+                // Status replies become {ok = status}, as in Redis
+                let table = lua_ctx.create_table_with_capacity(0, 1)?;
+                table.raw_set("ok", status)?;
+                Ok(LuaValue::Table(table))
             }
-            RespFrame::BulkString(Some(bytes)) => {
-                let string_val = String::from_utf8_lossy(&bytes).into_owned();
-                match lua_ctx.create_string(&string_val) {
+            Reply::Bulk(bytes) => {
+                match lua_ctx.create_string(bytes.as_slice()) {
                     Ok(lua_string) => Ok(LuaValue::String(lua_string)),
                     Err(e) => Self::handle_command_error_with_context(lua_ctx, e.to_string(), is_pcall),
                 }
             }
-            RespFrame::BulkString(None) => Ok(LuaValue::Nil),
-            RespFrame::Integer(i) => Ok(LuaValue::Integer(i)),
-            RespFrame::Error(bytes) => {
-                let error_msg = String::from_utf8_lossy(&bytes).into_owned();
-                Self::handle_command_error_with_context(lua_ctx, error_msg, is_pcall)
-            }
-            RespFrame::Array(Some(frames)) => {
-                // Convert Redis array to Lua table
-                match lua_ctx.create_table() {
+            Reply::Nil => Ok(LuaValue::Nil),
+            Reply::Integer(i) => Ok(LuaValue::Integer(i)),
+            Reply::Error(error_msg) => Self::handle_command_error_with_context(lua_ctx, error_msg, is_pcall),
+            Reply::Array(items) => {
+                // Convert Redis array to Lua table, sized up front so filling it never rehashes
+                match lua_ctx.create_table_with_capacity(items.len(), 0) {
                     Ok(table) => {
-                        for (idx, frame) in frames.iter().enumerate() {
-                            let lua_val = Self::resp_frame_to_lua_value(lua_ctx, frame.clone(), is_pcall)?;
-                            table.set(idx + 1, lua_val).map_err(|e| mlua::Error::RuntimeError(e.to_string()))?;
+                        for (idx, item) in items.into_iter().enumerate() {
+                            // In truncate mode, stop at the first element that doesn't fit the budget
+                            if budget.limits.truncate && !Self::fits_budget(&item, budget) {
+                                budget.truncated = true;
+                                break;
+                            }
+                            // Nil elements become false, as in Redis, so the table has no holes
+                            let lua_val = match Self::reply_to_lua_value(lua_ctx, item, is_pcall, budget)? {
+                                LuaValue::Nil => LuaValue::Boolean(false),
+                                lua_val => lua_val,
+                            };
+                            table.raw_set(idx + 1, lua_val).map_err(|e| mlua::Error::RuntimeError(e.to_string()))?;
+                        }
+                        if budget.truncated {
+                            table.set("truncated", true).map_err(|e| mlua::Error::RuntimeError(e.to_string()))?;
                         }
                         Ok(LuaValue::Table(table))
                     }
                     Err(e) => Self::handle_command_error_with_context(lua_ctx, e.to_string(), is_pcall),
                 }
             }
-            RespFrame::Array(None) => Ok(LuaValue::Nil),
-            _ => Ok(LuaValue::Nil),
         }
     }
     
+    /// Bytes of string data carried by a scalar reply
+    fn reply_len(reply: &Reply) -> usize {
+        match reply {
+            Reply::Status(s) => s.len(),
+            Reply::Bulk(bytes) => bytes.len(),
+            _ => 0,
+        }
+    }
+    
+    /// Check whether a scalar reply still fits the remaining budget (arrays are checked per element)
+    fn fits_budget(reply: &Reply, budget: &ReplyBudget) -> bool {
+        budget.elements < budget.limits.max_elements && budget.bytes + Self::reply_len(reply) <= budget.limits.max_bytes
+    }
+    
     /// Handle command errors with proper Redis semantics
-    fn handle_command_error_with_context(_lua_ctx: &Lua, error_msg: String, is_pcall: bool) -> LuaResult<LuaValue> {
-        let formatted_error = if error_msg.starts_with("ERR ") {
-            error_msg
-        } else {
-            format!("ERR {}", error_msg)
-        };
+    fn handle_command_error_with_context(lua_ctx: &Lua, error_msg: String, is_pcall: bool) -> LuaResult<LuaValue> {
+        let formatted_error = with_error_class(error_msg);
         
         if is_pcall {
-            // redis.pcall: Return nil, script continues
-            Ok(LuaValue::Nil)
+            // redis.pcall: Return {err = message}, script continues
+            let table = lua_ctx.create_table_with_capacity(0, 1)?;
+            table.raw_set("err", formatted_error)?;
+            Ok(LuaValue::Table(table))
         } else {
             // redis.call: Abort script execution immediately
             Err(mlua::Error::RuntimeError(format!("REDIS_CALL_ABORT:{}", formatted_error)))
         }
     }
     
-    fn setup_keys_and_args(&self, lua: &Lua, keys: Vec<Vec<u8>>, args: Vec<Vec<u8>>) -> Result<()> {
+    /// Install KEYS and ARGV, creating the Lua strings straight from the caller's buffers
+    pub(crate) fn setup_keys_and_args<T: AsRef<Vec<u8>>>(&self, lua: &Lua, keys: &[T], args: &[T]) -> Result<()> {
         let globals = lua.globals();
         
-        let keys_table = lua.create_table().map_err(|e| FerrousError::LuaError(e.to_string()))?;
-        for (i, key) in keys.iter().enumerate() {
-            let key_str = String::from_utf8_lossy(key).into_owned();
-            keys_table.set(i + 1, key_str).map_err(|e| FerrousError::LuaError(e.to_string()))?;
-        }
+        let keys_table = Self::byte_strings_table(lua, keys).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        let keys_table = lua_readonly::read_only_array(lua, keys_table).map_err(|e| FerrousError::LuaError(e.to_string()))?;
         globals.set("KEYS", keys_table).map_err(|e| FerrousError::LuaError(e.to_string()))?;
         
-        let argv_table = lua.create_table().map_err(|e| FerrousError::LuaError(e.to_string()))?;
-        for (i, arg) in args.iter().enumerate() {
-            let arg_str = String::from_utf8_lossy(arg).into_owned();
-            argv_table.set(i + 1, arg_str).map_err(|e| FerrousError::LuaError(e.to_string()))?;
-        }
+        let argv_table = Self::byte_strings_table(lua, args).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        let argv_table = lua_readonly::read_only_array(lua, argv_table).map_err(|e| FerrousError::LuaError(e.to_string()))?;
         globals.set("ARGV", argv_table).map_err(|e| FerrousError::LuaError(e.to_string()))?;
         
         Ok(())
     }
     
-    fn lua_value_to_resp(&self, value: LuaValue) -> RespFrame {
-        match value {
+    /// Build a 1-indexed table of binary-safe Lua strings (for KEYS and ARGV)
+    pub(crate) fn byte_strings_table<T: AsRef<Vec<u8>>>(lua: &Lua, items: &[T]) -> LuaResult<Table> {
+        let table = lua.create_table_with_capacity(items.len(), 0)?;
+        for (i, item) in items.iter().enumerate() {
+            table.raw_set(i + 1, lua.create_string(item.as_ref())?)?;
+        }
+        Ok(table)
+    }
+    
+    /// Convert a script result to a RESP reply
+    ///
+    /// The whole reply is built before anything is sent, so a value that cannot
+    /// be converted (a function, userdata or coroutine at any depth, or an
+    /// `__index` that raises) fails the script cleanly instead of leaving a
+    /// partial reply. Nested tables are converted recursively, so nesting is
+    /// capped at `lua-max-nesting-depth` levels. Deeper (or self-referencing)
+    /// results fail with a stack overflow error instead of overflowing the
+    /// native stack.
+    pub(crate) fn lua_value_to_resp(&self, value: LuaValue) -> Result<RespFrame> {
+        let limit = self.max_nesting_depth();
+        Self::value_to_resp(value, limit, limit)
+    }
+    
+    fn value_to_resp(value: LuaValue, remaining: usize, limit: usize) -> Result<RespFrame> {
+        let frame = match lua_readonly::unwrap(value) {
             LuaValue::Nil => RespFrame::BulkString(None),
             LuaValue::Boolean(b) => {
                 if b {
@@ -366,31 +1088,30 @@ impl LuaEngine {
                 }
             }
             LuaValue::Integer(i) => RespFrame::Integer(i),
-            LuaValue::Number(n) => {
-                if n.is_nan() {
-                    RespFrame::BulkString(None)
-                } else if n.is_infinite() {
-                    let inf_str = if n.is_sign_positive() { "inf" } else { "-inf" };
-                    RespFrame::BulkString(Some(Arc::new(inf_str.as_bytes().to_vec())))
-                } else if n.fract() == 0.0 && n >= i64::MIN as f64 && n <= i64::MAX as f64 {
-                    RespFrame::Integer(n as i64)
-                } else {
-                    let formatted = format!("{:.17}", n);
-                    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
-                    RespFrame::BulkString(Some(Arc::new(trimmed.as_bytes().to_vec())))
-                }
-            }
+            LuaValue::Number(n) => RespFrame::Integer(number_to_integer(n)),
             LuaValue::String(s) => {
                 RespFrame::BulkString(Some(Arc::new(s.as_bytes().to_vec())))
             }
             LuaValue::Table(table) => {
+                if remaining == 0 {
+                    return Err(FerrousError::Script(ScriptError::StackOverflow(limit)));
+                }
+                
+                // {err = ...} and {ok = ...} tables are error and status replies
+                if let Ok(LuaValue::String(message)) = table.raw_get::<LuaValue>("err") {
+                    return Ok(RespFrame::error(with_error_class(message.to_string_lossy())));
+                }
+                if let Ok(LuaValue::String(status)) = table.raw_get::<LuaValue>("ok") {
+                    return Ok(RespFrame::simple_string(status.as_bytes().to_vec()));
+                }
+                
                 // Convert Lua table to Redis array
-                let mut items = Vec::new();
+                let mut items = Vec::with_capacity(table.raw_len());
                 for i in 1.. {
                     match table.get::<LuaValue>(i) {
                         Ok(LuaValue::Nil) => break,
-                        Ok(value) => items.push(self.lua_value_to_resp(value)),
-                        Err(_) => break,
+                        Ok(value) => items.push(Self::value_to_resp(value, remaining - 1, limit)?),
+                        Err(e) => return Err(Self::map_lua_error(e)),
                     }
                 }
                 
@@ -400,8 +1121,9 @@ impl LuaEngine {
                     RespFrame::Array(Some(items))
                 }
             }
-            _ => RespFrame::BulkString(None),
-        }
+            other => return Err(FerrousError::Script(ScriptError::UnconvertibleReply(other.type_name()))),
+        };
+        Ok(frame)
     }
     
     fn calculate_script_sha1(&self, script: &str) -> String {
@@ -426,4 +1148,9 @@ pub fn get_lua_engine(storage: Arc<StorageEngine>) -> Result<Arc<LuaEngine>> {
         Ok(engine) => Ok(engine.clone()),
         Err(e) => Err(FerrousError::LuaError(e.clone())),
     }
+}
+
+/// The global Lua engine, if it has been created
+pub fn lua_engine() -> Option<Arc<LuaEngine>> {
+    LUA_ENGINE.get().and_then(|result| result.as_ref().ok().cloned())
 }
\ No newline at end of file
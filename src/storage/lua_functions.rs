@@ -0,0 +1,635 @@
+//! Redis Functions (FUNCTION LOAD / FCALL) runtime
+//!
+//! Function libraries live in one persistent Lua state that is separate from the
+//! fresh per-call state used by EVAL. Each library runs in its own environment
+//! table (inheriting the sandboxed globals), so library-level state persists
+//! between FCALLs without leaking into other libraries or into EVAL scripts.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use mlua::{ChunkMode, Function, Lua, MultiValue, Table, Value as LuaValue};
+
+use crate::error::{FerrousError, Result};
+use crate::protocol::resp::{Bytes, RespFrame};
+use crate::storage::{lua_audit, lua_bit, lua_budget, lua_checkpoint, lua_cjson, lua_dirty, lua_invariants, lua_iter, lua_log, lua_quota, lua_readonly, lua_require, lua_sandbox, lua_stats, lua_triggers, lua_utf8, StorageEngine};
+use crate::storage::lua_engine::{deterministic_pairs, get_lua_engine, restricted_os, LuaEngine, ReadOnlyScript};
+
+/// Registry keys of the restricted `os` tables shared by all libraries
+const OS_TABLE: &str = "ferrous.os";
+const DETERMINISTIC_OS_TABLE: &str = "ferrous.os.deterministic";
+
+/// Metadata for a loaded function library
+#[derive(Debug, Clone)]
+pub struct FunctionLibrary {
+    /// Library name from the `#!lua name=<name>` shebang
+    pub name: String,
+    
+    /// Full library source, including the shebang
+    pub code: String,
+    
+    /// Functions registered by the library, in registration order
+    pub functions: Vec<FunctionInfo>,
+    
+    /// Lua heap bytes attributed to the library when it was loaded
+    pub memory: usize,
+}
+
+/// Metadata for a registered function
+#[derive(Debug, Clone)]
+pub struct FunctionInfo {
+    /// Function name
+    pub name: String,
+    
+    /// Optional description passed to redis.register_function
+    pub description: Option<String>,
+    
+    /// Flags passed to redis.register_function (e.g. `no-writes`)
+    pub flags: Vec<String>,
+}
+
+/// A registered function and its callback in the persistent Lua state
+struct RegisteredFunction {
+    library: String,
+    callback: Function,
+    no_writes: bool,
+}
+
+/// A function registered during FUNCTION LOAD but not yet committed
+struct PendingFunction {
+    info: FunctionInfo,
+    callback: Function,
+}
+
+/// Mutable state guarded by the registry lock
+struct FunctionState {
+    /// Persistent Lua state shared by all libraries
+    lua: Lua,
+    
+    /// Database selected by the client currently running FCALL
+    db_index: Arc<AtomicUsize>,
+    
+    /// Set while a library body is executing (redis.call is not allowed then)
+    loading: Arc<AtomicBool>,
+    
+    /// Functions registered by the library currently being loaded
+    pending: Arc<Mutex<Vec<PendingFunction>>>,
+    
+    /// Triggers registered by the library currently being loaded
+    pending_triggers: Arc<Mutex<Vec<lua_triggers::Trigger>>>,
+    
+    /// Loaded libraries by name
+    libraries: HashMap<String, FunctionLibrary>,
+    
+    /// Registered functions by name
+    functions: HashMap<String, RegisteredFunction>,
+}
+
+/// Registry of function libraries backed by a persistent Lua state
+pub struct FunctionRegistry {
+    storage: Arc<StorageEngine>,
+    state: Mutex<FunctionState>,
+}
+
+impl FunctionRegistry {
+    /// Create a registry with an empty, sandboxed persistent Lua state
+    pub fn new(storage: Arc<StorageEngine>) -> Result<Self> {
+        let lua = Lua::new();
+        
+        // Remove dangerous functions for sandboxing; libraries get a restricted os
+        let globals = lua.globals();
+        let stdlib_os: Table = globals.get("os").map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        for (name, deterministic) in [(OS_TABLE, false), (DETERMINISTIC_OS_TABLE, true)] {
+            let os = restricted_os(&lua, &stdlib_os, deterministic).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+            lua.set_named_registry_value(name, os).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        }
+        let dangerous_functions = ["os", "io", "debug", "package", "require", "dofile", "loadfile", "load"];
+        for func in &dangerous_functions {
+            globals.set(*func, mlua::Nil).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        }
+        lua_budget::register(&lua, &globals).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        lua_readonly::register(&lua, &globals).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        lua_sandbox::register(&lua, &globals).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        lua_sandbox::isolate_shared_state(&lua).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+        
+        Ok(FunctionRegistry {
+            storage,
+            state: Mutex::new(FunctionState {
+                lua,
+                db_index: Arc::new(AtomicUsize::new(0)),
+                loading: Arc::new(AtomicBool::new(false)),
+                pending: Arc::new(Mutex::new(Vec::new())),
+                pending_triggers: Arc::new(Mutex::new(Vec::new())),
+                libraries: HashMap::new(),
+                functions: HashMap::new(),
+            }),
+        })
+    }
+    
+    /// Load a library (FUNCTION LOAD), returning its name
+    pub fn load(&self, code: &str, replace: bool) -> Result<String> {
+        let (name, body) = parse_library_header(code)?;
+        let mut state = self.state.lock().unwrap();
+        
+        if !replace && state.libraries.contains_key(&name) {
+            return Err(FerrousError::LuaError(format!("ERR Library '{}' already exists", name)));
+        }
+        
+        let lua_err = |e: mlua::Error| FerrousError::LuaError(format!("ERR {}", e));
+        
+        // Each library gets its own environment inheriting the sandboxed globals
+        let env = state.lua.create_table().map_err(lua_err)?;
+        let env_meta = state.lua.create_table().map_err(lua_err)?;
+        env_meta.set("__index", state.lua.globals()).map_err(lua_err)?;
+        env.set_metatable(Some(env_meta)).map_err(lua_err)?;
+        let redis_table = lua_readonly::read_only(&state.lua, self.create_redis_table(&state)?).map_err(lua_err)?;
+        env.set("redis", redis_table).map_err(lua_err)?;
+        env.set("require", lua_require::create_require(&state.lua, env.clone()).map_err(lua_err)?).map_err(lua_err)?;
+        env.set("cjson", lua_cjson::create_cjson_table(&state.lua).map_err(lua_err)?).map_err(lua_err)?;
+        env.set("bit", lua_bit::create_bit_table(&state.lua).map_err(lua_err)?).map_err(lua_err)?;
+        let engine = get_lua_engine(self.storage.clone())?;
+        if engine.utf8_enabled() {
+            env.set("utf8", lua_utf8::create_utf8_table(&state.lua).map_err(lua_err)?).map_err(lua_err)?;
+        }
+        let ferrous = LuaEngine::ferrous_table(&state.lua, engine.utf8_enabled(), false, engine.deterministic()).map_err(lua_err)?;
+        env.set("ferrous", ferrous).map_err(lua_err)?;
+        let os_table = if engine.deterministic() { DETERMINISTIC_OS_TABLE } else { OS_TABLE };
+        env.set("os", state.lua.named_registry_value::<Table>(os_table).map_err(lua_err)?).map_err(lua_err)?;
+        if engine.deterministic() {
+            env.set("pairs", deterministic_pairs(&state.lua).map_err(lua_err)?).map_err(lua_err)?;
+        }
+        
+        state.lua.gc_collect().map_err(lua_err)?;
+        let memory_before = state.lua.used_memory();
+        
+        state.pending.lock().unwrap().clear();
+        state.pending_triggers.lock().unwrap().clear();
+        state.loading.store(true, Ordering::SeqCst);
+        let result = state.lua.load(body.as_str()).set_name(name.as_str()).set_mode(ChunkMode::Text).set_environment(env).exec();
+        state.loading.store(false, Ordering::SeqCst);
+        let pending: Vec<PendingFunction> = state.pending.lock().unwrap().drain(..).collect();
+        let mut triggers: Vec<lua_triggers::Trigger> = state.pending_triggers.lock().unwrap().drain(..).collect();
+        
+        if let Err(e) = result {
+            return Err(match LuaEngine::map_lua_error(e) {
+                FerrousError::LuaError(msg) => FerrousError::LuaError(
+                    msg.replacen("ERR Error running script", "ERR Error registering functions", 1)),
+                other => other,
+            });
+        }
+        
+        if pending.is_empty() {
+            return Err(FerrousError::LuaError("ERR No functions registered".to_string()));
+        }
+        
+        // Function names are global across libraries
+        for function in &pending {
+            if let Some(existing) = state.functions.get(&function.info.name) {
+                if existing.library != name {
+                    return Err(FerrousError::LuaError(format!("ERR Function {} already exists", function.info.name)));
+                }
+            }
+        }
+        
+        // Triggers can only run functions of their own library
+        for trigger in &mut triggers {
+            if !pending.iter().any(|f| f.info.name == trigger.function) {
+                return Err(FerrousError::LuaError(format!("ERR Trigger function {} is not registered by this library", trigger.function)));
+            }
+            trigger.library = name.clone();
+        }
+        
+        state.lua.gc_collect().map_err(lua_err)?;
+        let memory = state.lua.used_memory().saturating_sub(memory_before);
+        
+        // Commit: drop the previous version of the library, then register the new one
+        state.functions.retain(|_, registered| registered.library != name);
+        let mut functions = Vec::with_capacity(pending.len());
+        for function in pending {
+            state.functions.insert(function.info.name.clone(), RegisteredFunction {
+                library: name.clone(),
+                callback: function.callback,
+                no_writes: function.info.flags.iter().any(|flag| flag == "no-writes"),
+            });
+            functions.push(function.info);
+        }
+        state.libraries.insert(name.clone(), FunctionLibrary {
+            name: name.clone(),
+            code: code.to_string(),
+            functions,
+            memory,
+        });
+        lua_triggers::set_library_triggers(&name, triggers);
+        
+        Ok(name)
+    }
+    
+    /// Call a registered function (FCALL)
+    pub fn call(&self, function: &str, keys: Vec<Bytes>, args: Vec<Bytes>, db_index: usize) -> Result<RespFrame> {
+        self.call_with_mode(function, keys, args, db_index, false)
+    }
+    
+    /// Call a registered function through FCALL_RO
+    ///
+    /// Only functions declared with the `no-writes` flag may be called this way.
+    pub fn call_ro(&self, function: &str, keys: Vec<Bytes>, args: Vec<Bytes>, db_index: usize) -> Result<RespFrame> {
+        self.call_with_mode(function, keys, args, db_index, true)
+    }
+    
+    fn call_with_mode(&self, function: &str, keys: Vec<Bytes>, args: Vec<Bytes>, db_index: usize, read_only: bool) -> Result<RespFrame> {
+        let _invariants = lua_invariants::InvariantScope::begin();
+        // Declared before the lock so triggers raised by the call run after it is released
+        let _defer = lua_triggers::DeferScope::begin();
+        let state = self.state.lock().unwrap();
+        let (callback, no_writes) = match state.functions.get(function) {
+            Some(registered) => (registered.callback.clone(), registered.no_writes),
+            None => return Err(FerrousError::LuaError("ERR Function not found".to_string())),
+        };
+        if read_only && !no_writes {
+            return Err(FerrousError::LuaError("ERR Can not execute a script with write flag using *_ro command.".to_string()));
+        }
+        
+        let lua_err = |e: mlua::Error| FerrousError::LuaError(format!("ERR {}", e));
+        let keys_table = LuaEngine::byte_strings_table(&state.lua, &keys).map_err(lua_err)?;
+        let args_table = LuaEngine::byte_strings_table(&state.lua, &args).map_err(lua_err)?;
+        let keys_table = lua_readonly::read_only_array(&state.lua, keys_table).map_err(lua_err)?;
+        let args_table = lua_readonly::read_only_array(&state.lua, args_table).map_err(lua_err)?;
+        
+        state.db_index.store(db_index, Ordering::SeqCst);
+        let audit = lua_audit::AuditScope::begin(|| format!("function:{}", function));
+        let dirty = lua_dirty::DirtyScope::begin(&self.storage);
+        let read_only_guard = no_writes.then(|| ReadOnlyScript::enter(ReadOnlyScript::RO_VARIANT));
+        let log_scope = lua_log::ScriptLogScope::begin();
+        let engine = get_lua_engine(self.storage.clone())?;
+        let checkout = lua_quota::Checkout::begin()?;
+        let budget = lua_budget::BudgetScope::begin(lua_budget::Budget { cpu_allowance_ms: checkout.cpu_allowance_ms(), ..engine.budget() });
+        let stats = lua_stats::VmStatsScope::begin(&state.lua).map_err(lua_err)?;
+        // A user's heap quota is what the call may add to the shared state
+        let memory_quota = lua_quota::max_memory();
+        let previous_limit = match memory_quota {
+            0 => None,
+            quota => Some(state.lua.set_memory_limit(state.lua.used_memory() + quota).map_err(lua_err)?),
+        };
+        let result = callback.call::<LuaValue>((keys_table, args_table));
+        if let Some(limit) = previous_limit {
+            state.lua.set_memory_limit(limit).map_err(lua_err)?;
+        }
+        stats.finish(&state.lua);
+        drop(log_scope);
+        drop(read_only_guard);
+        drop(dirty);
+        
+        let result = budget.finish(match result {
+            Ok(value) => engine.lua_value_to_resp(value),
+            Err(e) => Err(LuaEngine::map_lua_error(e)),
+        });
+        if let Some(audit) = audit {
+            audit.finish(&result);
+        }
+        result
+    }
+    
+    /// Check whether a function is registered
+    pub fn has_function(&self, function: &str) -> bool {
+        self.state.lock().unwrap().functions.contains_key(function)
+    }
+    
+    /// Delete a library and its functions (FUNCTION DELETE)
+    pub fn delete(&self, library: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.libraries.remove(library).is_none() {
+            return Err(FerrousError::LuaError("ERR Library not found".to_string()));
+        }
+        state.functions.retain(|_, registered| registered.library != library);
+        lua_triggers::remove_library(library);
+        let _ = state.lua.gc_collect();
+        Ok(())
+    }
+    
+    /// Delete all libraries (FUNCTION FLUSH)
+    pub fn flush(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.libraries.clear();
+        state.functions.clear();
+        lua_triggers::clear();
+        let _ = state.lua.gc_collect();
+    }
+    
+    /// Snapshot of loaded libraries, sorted by name
+    pub fn libraries(&self) -> Vec<FunctionLibrary> {
+        let state = self.state.lock().unwrap();
+        let mut libraries: Vec<FunctionLibrary> = state.libraries.values().cloned().collect();
+        libraries.sort_by(|a, b| a.name.cmp(&b.name));
+        libraries
+    }
+    
+    /// Total bytes used by the persistent functions Lua state
+    pub fn used_memory(&self) -> usize {
+        self.state.lock().unwrap().lua.used_memory()
+    }
+    
+    /// Build the `redis` table exposed to a library environment
+    fn create_redis_table(&self, state: &FunctionState) -> Result<Table> {
+        let lua = &state.lua;
+        let lua_err = |e: mlua::Error| FerrousError::LuaError(e.to_string());
+        let redis_table = lua.create_table().map_err(lua_err)?;
+        
+        for (name, is_pcall) in [("call", false), ("pcall", true)] {
+            let storage = self.storage.clone();
+            let db_index = state.db_index.clone();
+            let loading = state.loading.clone();
+            let function = lua.create_function(move |lua_ctx, cmd: MultiValue| {
+                if loading.load(Ordering::SeqCst) {
+                    return Err(mlua::Error::RuntimeError(
+                        "REDIS_CALL_ABORT:ERR redis.call is not allowed from the library body, only from registered functions".to_string()));
+                }
+                let limits = get_lua_engine(storage.clone())
+                    .map_err(|e| mlua::Error::RuntimeError(e.to_string()))?
+                    .reply_limits();
+                LuaEngine::execute_unified_redis_command(&storage, lua_ctx, cmd, db_index.load(Ordering::SeqCst), is_pcall, limits)
+            }).map_err(lua_err)?;
+            redis_table.set(name, function).map_err(lua_err)?;
+        }
+        
+        let storage = self.storage.clone();
+        let db_index = state.db_index.clone();
+        let loading = state.loading.clone();
+        let mcall = lua.create_function(move |lua_ctx, commands: Table| {
+            if loading.load(Ordering::SeqCst) {
+                return Err(mlua::Error::RuntimeError(
+                    "REDIS_CALL_ABORT:ERR redis.call is not allowed from the library body, only from registered functions".to_string()));
+            }
+            let limits = get_lua_engine(storage.clone())
+                .map_err(|e| mlua::Error::RuntimeError(e.to_string()))?
+                .reply_limits();
+            let db = db_index.load(Ordering::SeqCst);
+            LuaEngine::run_batch(lua_ctx, commands, |cmd| {
+                LuaEngine::execute_unified_redis_command(&storage, lua_ctx, cmd, db, false, limits)
+            })
+        }).map_err(lua_err)?;
+        redis_table.set("mcall", mcall).map_err(lua_err)?;
+        
+        let storage = self.storage.clone();
+        let db_index = state.db_index.clone();
+        let loading = state.loading.clone();
+        let call_array = lua.create_function(move |lua_ctx, cmd: MultiValue| {
+            if loading.load(Ordering::SeqCst) {
+                return Err(mlua::Error::RuntimeError(
+                    "REDIS_CALL_ABORT:ERR redis.call is not allowed from the library body, only from registered functions".to_string()));
+            }
+            let limits = get_lua_engine(storage.clone())
+                .map_err(|e| mlua::Error::RuntimeError(e.to_string()))?
+                .reply_limits();
+            let db = db_index.load(Ordering::SeqCst);
+            LuaEngine::call_array(lua_ctx, cmd, |cmd| {
+                LuaEngine::execute_unified_redis_command(&storage, lua_ctx, cmd, db, false, limits)
+            })
+        }).map_err(lua_err)?;
+        redis_table.set("call_array", call_array).map_err(lua_err)?;
+        
+        let db_index = state.db_index.clone();
+        let call_iter = lua_iter::create_call_iter(lua, self.storage.clone(), move || db_index.load(Ordering::SeqCst))
+            .map_err(lua_err)?;
+        redis_table.set("call_iter", call_iter).map_err(lua_err)?;
+        let db_index = state.db_index.clone();
+        let cursor = lua_iter::create_cursor(lua, self.storage.clone(), move || db_index.load(Ordering::SeqCst))
+            .map_err(lua_err)?;
+        redis_table.set("cursor", cursor).map_err(lua_err)?;
+        lua_log::register(lua, &redis_table).map_err(lua_err)?;
+        lua_checkpoint::register(lua, &redis_table).map_err(lua_err)?;
+        LuaEngine::register_reply_helpers(lua, &redis_table).map_err(lua_err)?;
+        LuaEngine::register_version(&redis_table).map_err(lua_err)?;
+        
+        let pending = state.pending.clone();
+        let loading = state.loading.clone();
+        let register_function = lua.create_function(move |_, args: MultiValue| {
+            if !loading.load(Ordering::SeqCst) {
+                return Err(mlua::Error::RuntimeError(
+                    "redis.register_function can only be called on FUNCTION LOAD command".to_string()));
+            }
+            let function = parse_register_function_args(args)?;
+            let mut pending = pending.lock().unwrap();
+            if pending.iter().any(|f| f.info.name == function.info.name) {
+                return Err(mlua::Error::RuntimeError(format!("Function {} already exists", function.info.name)));
+            }
+            pending.push(function);
+            Ok(())
+        }).map_err(lua_err)?;
+        redis_table.set("register_function", register_function).map_err(lua_err)?;
+        
+        let pending_triggers = state.pending_triggers.clone();
+        let loading = state.loading.clone();
+        let register_trigger = lua.create_function(move |_, spec: Table| {
+            if !loading.load(Ordering::SeqCst) {
+                return Err(mlua::Error::RuntimeError(
+                    "redis.register_trigger can only be called on FUNCTION LOAD command".to_string()));
+            }
+            pending_triggers.lock().unwrap().push(parse_register_trigger_args(spec)?);
+            Ok(())
+        }).map_err(lua_err)?;
+        redis_table.set("register_trigger", register_trigger).map_err(lua_err)?;
+        
+        Ok(redis_table)
+    }
+}
+
+/// Parse `#!lua name=<library>` from the first line, returning the name and the
+/// body with the shebang blanked out (so line numbers in errors stay accurate)
+fn parse_library_header(code: &str) -> Result<(String, String)> {
+    let (first_line, rest) = match code.find('\n') {
+        Some(pos) => (&code[..pos], &code[pos..]),
+        None => (code, ""),
+    };
+    
+    let shebang = match first_line.trim_end().strip_prefix("#!") {
+        Some(shebang) => shebang,
+        None => return Err(FerrousError::LuaError("ERR Missing library metadata".to_string())),
+    };
+    
+    let mut parts = shebang.split_whitespace();
+    let engine = parts.next().unwrap_or("");
+    if !engine.eq_ignore_ascii_case("lua") {
+        return Err(FerrousError::LuaError(format!("ERR Engine '{}' not found", engine)));
+    }
+    
+    let mut name = None;
+    for part in parts {
+        match part.strip_prefix("name=") {
+            Some(value) => name = Some(value.to_string()),
+            None => return Err(FerrousError::LuaError(format!("ERR Invalid metadata value given: {}", part))),
+        }
+    }
+    
+    let name = match name {
+        Some(name) => name,
+        None => return Err(FerrousError::LuaError("ERR Library name was not given".to_string())),
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(FerrousError::LuaError(
+            "ERR Library names can only contain letters, numbers, or underscores(_) and must be at least one character long".to_string()));
+    }
+    
+    Ok((name, rest.to_string()))
+}
+
+/// Parse `redis.register_function(name, callback)` or the table form
+/// `redis.register_function{function_name=..., callback=..., flags={...}, description=...}`
+fn parse_register_function_args(args: MultiValue) -> mlua::Result<PendingFunction> {
+    let args: Vec<LuaValue> = args.into_iter().collect();
+    let invalid = || mlua::Error::RuntimeError("wrong number of arguments to redis.register_function".to_string());
+    
+    let (name, callback, flags, description) = match args.as_slice() {
+        [LuaValue::String(name), LuaValue::Function(callback)] => {
+            (name.to_str()?.to_string(), callback.clone(), Vec::new(), None)
+        }
+        [LuaValue::Table(spec)] => {
+            let name: String = spec.get("function_name")
+                .map_err(|_| mlua::Error::RuntimeError("function_name argument given to redis.register_function must be a string".to_string()))?;
+            let callback: Function = spec.get("callback")
+                .map_err(|_| mlua::Error::RuntimeError("callback argument given to redis.register_function must be a function".to_string()))?;
+            let flags: Vec<String> = spec.get::<Option<Vec<String>>>("flags")?.unwrap_or_default();
+            let description: Option<String> = spec.get("description")?;
+            (name, callback, flags, description)
+        }
+        _ => return Err(invalid()),
+    };
+    
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(mlua::Error::RuntimeError(
+            "Function names can only contain letters, numbers, or underscores(_) and must be at least one character long".to_string()));
+    }
+    
+    for flag in &flags {
+        if !matches!(flag.as_str(), "no-writes" | "allow-oom" | "allow-stale" | "no-cluster" | "allow-cross-slot-keys") {
+            return Err(mlua::Error::RuntimeError(format!("unknown flag given: {}", flag)));
+        }
+    }
+    
+    Ok(PendingFunction {
+        info: FunctionInfo { name, description, flags },
+        callback,
+    })
+}
+
+/// Parse `redis.register_trigger{function_name=..., pattern=..., events={...}}`
+fn parse_register_trigger_args(spec: Table) -> mlua::Result<lua_triggers::Trigger> {
+    let function: String = spec.get("function_name")
+        .map_err(|_| mlua::Error::RuntimeError("function_name argument given to redis.register_trigger must be a string".to_string()))?;
+    let pattern: mlua::String = spec.get("pattern")
+        .map_err(|_| mlua::Error::RuntimeError("pattern argument given to redis.register_trigger must be a string".to_string()))?;
+    let events: Vec<String> = spec.get::<Option<Vec<String>>>("events")
+        .map_err(|_| mlua::Error::RuntimeError("events argument given to redis.register_trigger must be a list of strings".to_string()))?
+        .unwrap_or_default();
+    
+    Ok(lua_triggers::Trigger {
+        library: String::new(),
+        function,
+        pattern: pattern.as_bytes().to_vec(),
+        events: events.into_iter().map(|event| event.to_lowercase()).collect(),
+    })
+}
+
+/// Global function registry - initialized once per process
+static FUNCTION_REGISTRY: OnceLock<std::result::Result<Arc<FunctionRegistry>, String>> = OnceLock::new();
+
+/// Get the global function registry instance
+pub fn get_function_registry(storage: Arc<StorageEngine>) -> Result<Arc<FunctionRegistry>> {
+    let result = FUNCTION_REGISTRY.get_or_init(|| {
+        FunctionRegistry::new(storage.clone())
+            .map(Arc::new)
+            .map_err(|e| e.to_string())
+    });
+    
+    match result {
+        Ok(registry) => Ok(registry.clone()),
+        Err(e) => Err(FerrousError::LuaError(e.clone())),
+    }
+}
+
+/// The function registry, if it has been created
+pub fn function_registry() -> Option<Arc<FunctionRegistry>> {
+    FUNCTION_REGISTRY.get().and_then(|result| result.as_ref().ok().cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_library_state_persists_between_calls() {
+        let storage = StorageEngine::new_in_memory();
+        let registry = FunctionRegistry::new(storage.clone()).unwrap();
+        
+        let code = "#!lua name=counter\nlocal n = 0\nredis.register_function('bump', function(keys, args) n = n + 1 return n end)";
+        assert_eq!(registry.load(code, false).unwrap(), "counter");
+        
+        assert_eq!(registry.call("bump", vec![], vec![], 0).unwrap(), RespFrame::Integer(1));
+        assert_eq!(registry.call("bump", vec![], vec![], 0).unwrap(), RespFrame::Integer(2));
+        
+        // Reloading without REPLACE fails, with REPLACE resets library state
+        assert!(registry.load(code, false).is_err());
+        registry.load(code, true).unwrap();
+        assert_eq!(registry.call("bump", vec![], vec![], 0).unwrap(), RespFrame::Integer(1));
+    }
+    
+    #[test]
+    fn test_library_environments_are_isolated() {
+        let storage = StorageEngine::new_in_memory();
+        let registry = FunctionRegistry::new(storage.clone()).unwrap();
+        
+        registry.load("#!lua name=a\nshared = 'a'\nredis.register_function('get_a', function() return shared end)", false).unwrap();
+        registry.load("#!lua name=b\nredis.register_function('get_b', function() return tostring(shared) end)", false).unwrap();
+        
+        assert_eq!(registry.call("get_a", vec![], vec![], 0).unwrap(), RespFrame::from_string("a"));
+        assert_eq!(registry.call("get_b", vec![], vec![], 0).unwrap(), RespFrame::from_string("nil"));
+    }
+    
+    #[test]
+    fn test_keys_args_and_redis_call() {
+        let storage = StorageEngine::new_in_memory();
+        let registry = FunctionRegistry::new(storage.clone()).unwrap();
+        
+        registry.load("#!lua name=kv\nredis.register_function{function_name='setget', callback=function(keys, args)\n  redis.call('SET', keys[1], args[1])\n  return redis.call('GET', keys[1])\nend, flags={}}", false).unwrap();
+        
+        let result = registry.call("setget", vec![Arc::new(b"k".to_vec())], vec![Arc::new(b"v".to_vec())], 0).unwrap();
+        assert_eq!(result, RespFrame::from_string("v"));
+        assert_eq!(storage.get_string(0, b"k").unwrap(), Some(b"v".to_vec()));
+    }
+    
+    #[test]
+    fn test_load_errors() {
+        let storage = StorageEngine::new_in_memory();
+        let registry = FunctionRegistry::new(storage.clone()).unwrap();
+        
+        assert!(registry.load("return 1", false).unwrap_err().to_string().contains("Missing library metadata"));
+        assert!(registry.load("#!lua name=empty\nlocal x = 1", false).unwrap_err().to_string().contains("No functions registered"));
+        assert!(registry.load("#!lua name=bad\nredis.call('PING')", false).is_err());
+        
+        registry.load("#!lua name=one\nredis.register_function('f', function() return 1 end)", false).unwrap();
+        assert!(registry.load("#!lua name=two\nredis.register_function('f', function() return 2 end)", false)
+            .unwrap_err().to_string().contains("Function f already exists"));
+        
+        registry.delete("one").unwrap();
+        assert!(registry.call("f", vec![], vec![], 0).is_err());
+    }
+    
+    #[test]
+    fn test_fcall_ro_requires_no_writes_flag() {
+        let storage = StorageEngine::new_in_memory();
+        let registry = FunctionRegistry::new(storage.clone()).unwrap();
+        
+        registry.load("#!lua name=ro\n\
+            redis.register_function{function_name='reader', callback=function(keys) return redis.call('GET', keys[1]) end, flags={'no-writes'}}\n\
+            redis.register_function{function_name='sneaky', callback=function(keys) return redis.call('SET', keys[1], 'x') end, flags={'no-writes'}}\n\
+            redis.register_function('writer', function(keys) return redis.call('SET', keys[1], 'x') end)", false).unwrap();
+        
+        assert_eq!(registry.call_ro("reader", vec![Arc::new(b"k".to_vec())], vec![], 0).unwrap(), RespFrame::null_bulk());
+        assert!(registry.call_ro("writer", vec![Arc::new(b"k".to_vec())], vec![], 0)
+            .unwrap_err().to_string().contains("Can not execute a script with write flag using *_ro command"));
+        
+        // A no-writes function cannot write even through plain FCALL
+        assert!(registry.call("sneaky", vec![Arc::new(b"k".to_vec())], vec![], 0)
+            .unwrap_err().to_string().contains("Write commands are not allowed from read-only scripts"));
+        assert_eq!(storage.get_string(0, b"k").unwrap(), None);
+    }
+}
@@ -0,0 +1,85 @@
+//! Thread state invariants around script runs
+//!
+//! mlua owns the Lua stack: when an error unwinds out of a script it restores
+//! the stack top, call frames and protected-call boundaries itself. What the
+//! server adds is per-thread state set by scope guards around each run (budget,
+//! client, write denial, dirty keys, effects, log budget, VM stats, recording,
+//! audit, trigger deferral) and restored by their `Drop`, so every error return
+//! leaves the thread as the run found it, including in the long-lived FUNCTION
+//! state.
+//!
+//! Debug builds check this after every EVAL and FCALL: the state must match a
+//! snapshot taken before the run, or the run panics naming what leaked.
+//! Release builds skip the check.
+
+use crate::storage::lua_engine::ReadOnlyScript;
+use crate::storage::{lua_audit, lua_budget, lua_checkpoint, lua_dirty, lua_effects, lua_log, lua_record, lua_stats, lua_triggers};
+
+/// Per-thread scripting state that a finished run must leave unchanged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    budget: bool,
+    client: Option<u64>,
+    denied_writes: Option<&'static str>,
+    dirty: bool,
+    effects: bool,
+    log: bool,
+    stats: bool,
+    recording: bool,
+    audit: bool,
+    
+    /// Open defer scopes and trigger depth
+    triggers: (usize, usize),
+}
+
+impl Snapshot {
+    /// Capture the scripting state of this thread
+    pub fn take() -> Self {
+        Snapshot {
+            budget: lua_budget::is_running(),
+            client: lua_checkpoint::current_client(),
+            denied_writes: ReadOnlyScript::current(),
+            dirty: lua_dirty::is_tracking(),
+            effects: lua_effects::is_collecting(),
+            log: lua_log::is_limited(),
+            stats: lua_stats::is_sampling(),
+            recording: lua_record::is_recording(),
+            audit: lua_audit::is_auditing(),
+            triggers: lua_triggers::nesting(),
+        }
+    }
+}
+
+/// Checks, in debug builds, that one script run restores the thread state
+pub struct InvariantScope {
+    before: Option<Snapshot>,
+}
+
+impl InvariantScope {
+    /// Snapshot the thread state before a run (debug builds only)
+    pub fn begin() -> Self {
+        InvariantScope { before: cfg!(debug_assertions).then(Snapshot::take) }
+    }
+}
+
+impl Drop for InvariantScope {
+    fn drop(&mut self) {
+        let Some(before) = self.before.take() else { return };
+        if !std::thread::panicking() {
+            assert_eq!(Snapshot::take(), before, "script run left thread state behind");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::lua_log::ScriptLogScope;
+    
+    #[test]
+    #[should_panic(expected = "script run left thread state behind")]
+    fn test_leaked_scope_is_caught() {
+        let _invariants = InvariantScope::begin();
+        std::mem::forget(ScriptLogScope::begin());
+    }
+}
@@ -0,0 +1,374 @@
+//! Iterator-style bridge for large collections (`redis.call_iter`)
+//!
+//! `redis.call('SMEMBERS', key)` materializes the whole set as one Lua table,
+//! which is either rejected by the reply limits or balloons VM memory for big
+//! keys. `redis.call_iter` instead returns a Lua iterator that walks the key with
+//! the SCAN-family commands, fetching one chunk at a time:
+//!
+//! ```lua
+//! for member in redis.call_iter('SMEMBERS', KEYS[1]) do ... end
+//! for field, value in redis.call_iter('HGETALL', KEYS[1]) do ... end
+//! ```
+//!
+//! Supported: SMEMBERS, HGETALL, HKEYS, HVALS, KEYS, and the cursor commands
+//! themselves (SSCAN/HSCAN/ZSCAN key [MATCH p], SCAN [MATCH p] [TYPE t]).
+//! SCAN guarantees apply: elements present for the whole iteration are returned.
+//!
+//! `redis.cursor` exposes the same machinery as a userdata object for scripts
+//! that want batches rather than single elements, without tracking cursor
+//! strings themselves:
+//!
+//! ```lua
+//! local c = redis.cursor('SCAN', 'MATCH', 'user:*')
+//! while not c:done() do
+//!     for _, key in ipairs(c:next()) do ... end
+//! end
+//! ```
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use mlua::{Function, Lua, MultiValue, Result as LuaResult, UserData, UserDataMethods, Value as LuaValue};
+
+use crate::storage::commands::executor::{LuaCommandAdapter, Reply};
+use crate::storage::{lru, StorageEngine};
+
+/// Elements requested per cursor round trip
+const CHUNK_SIZE: usize = 512;
+
+/// Which parts of each scanned entry the iterator yields
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Projection {
+    /// Entries are single elements (SSCAN, SCAN)
+    Single,
+    /// Entries are pairs, yield both (HSCAN, ZSCAN, HGETALL)
+    Pair,
+    /// Entries are pairs, yield the first (HKEYS)
+    First,
+    /// Entries are pairs, yield the second (HVALS)
+    Second,
+}
+
+/// Cursor state behind one Lua iterator
+struct CursorIter {
+    /// Scan command and key, e.g. `["SSCAN", key]`
+    command: Vec<Vec<u8>>,
+    /// Options placed after the cursor (MATCH / TYPE)
+    options: Vec<Vec<u8>>,
+    projection: Projection,
+    /// Next cursor to request, `None` once the scan has completed
+    cursor: Option<Vec<u8>>,
+    /// Fetched elements not yet yielded
+    buffer: VecDeque<Vec<u8>>,
+}
+
+impl CursorIter {
+    /// Translate a `redis.call_iter` invocation into a cursor scan
+    fn new(args: Vec<Vec<u8>>) -> Result<Self, String> {
+        let name = args.first().map(|n| String::from_utf8_lossy(n).to_uppercase()).unwrap_or_default();
+        let arity_error = || format!("ERR wrong number of arguments for '{}' command", name.to_lowercase());
+        
+        let (command, options, projection): (Vec<&[u8]>, Vec<Vec<u8>>, Projection) = match name.as_str() {
+            "SMEMBERS" | "HGETALL" | "HKEYS" | "HVALS" => {
+                if args.len() != 2 {
+                    return Err(arity_error());
+                }
+                let (scan, projection) = match name.as_str() {
+                    "SMEMBERS" => (b"SSCAN".as_slice(), Projection::Single),
+                    "HGETALL" => (b"HSCAN".as_slice(), Projection::Pair),
+                    "HKEYS" => (b"HSCAN".as_slice(), Projection::First),
+                    _ => (b"HSCAN".as_slice(), Projection::Second),
+                };
+                (vec![scan, &args[1]], Vec::new(), projection)
+            }
+            "KEYS" => {
+                if args.len() != 2 {
+                    return Err(arity_error());
+                }
+                (vec![b"SCAN".as_slice()], vec![b"MATCH".to_vec(), args[1].clone()], Projection::Single)
+            }
+            "SSCAN" | "HSCAN" | "ZSCAN" => {
+                if args.len() < 2 {
+                    return Err(arity_error());
+                }
+                let projection = if name == "SSCAN" { Projection::Single } else { Projection::Pair };
+                (vec![&args[0], &args[1]], Self::parse_options(&args[2..])?, projection)
+            }
+            "SCAN" => (vec![&args[0]], Self::parse_options(&args[1..])?, Projection::Single),
+            "" => return Err("ERR No command specified".to_string()),
+            _ => return Err(format!("ERR redis.call_iter does not support '{}'", name)),
+        };
+        
+        Ok(CursorIter {
+            command: command.into_iter().map(|part| part.to_vec()).collect(),
+            options,
+            projection,
+            cursor: Some(b"0".to_vec()),
+            buffer: VecDeque::new(),
+        })
+    }
+    
+    /// Accept MATCH and TYPE; COUNT is owned by the iterator
+    fn parse_options(options: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, String> {
+        if !options.len().is_multiple_of(2) {
+            return Err("ERR syntax error".to_string());
+        }
+        for pair in options.chunks(2) {
+            match String::from_utf8_lossy(&pair[0]).to_uppercase().as_str() {
+                "MATCH" | "TYPE" => {}
+                _ => return Err("ERR syntax error".to_string()),
+            }
+        }
+        Ok(options.to_vec())
+    }
+    
+    /// Fetch chunks until elements are buffered or the scan completes
+    fn fill(&mut self, storage: &Arc<StorageEngine>, db: usize) -> Result<(), String> {
+        while self.buffer.is_empty() {
+            let cursor = match self.cursor.take() {
+                Some(cursor) => cursor,
+                None => return Ok(()),
+            };
+            
+            let mut args = self.command.clone();
+            args.push(cursor);
+            args.extend(self.options.iter().cloned());
+            args.push(b"COUNT".to_vec());
+            args.push(CHUNK_SIZE.to_string().into_bytes());
+            
+            let reply = LuaCommandAdapter::new(storage.clone())
+                .execute(args, db)
+                .map_err(|e| e.to_string())?;
+            
+            let (next, items) = match reply {
+                Reply::Array(mut parts) if parts.len() == 2 => {
+                    let items = parts.pop().unwrap();
+                    (parts.pop().unwrap(), items)
+                }
+                Reply::Error(e) => return Err(e),
+                _ => return Err("ERR unexpected reply from cursor command".to_string()),
+            };
+            
+            if let Reply::Bulk(next) = next {
+                if next.as_slice() != b"0" {
+                    self.cursor = Some(next.to_vec());
+                }
+            }
+            if let Reply::Array(items) = items {
+                self.buffer.extend(items.into_iter().filter_map(|item| match item {
+                    Reply::Bulk(bytes) => Some(bytes.to_vec()),
+                    _ => None,
+                }));
+            }
+        }
+        Ok(())
+    }
+    
+    /// Check whether every element has been fetched and handed out
+    fn is_done(&self) -> bool {
+        self.cursor.is_none() && self.buffer.is_empty()
+    }
+    
+    /// Number of buffer elements making up one entry
+    fn entry_width(&self) -> usize {
+        if self.projection == Projection::Single { 1 } else { 2 }
+    }
+    
+    /// Pop the values for the next iteration step
+    fn next_values(&mut self) -> Option<Vec<Vec<u8>>> {
+        match self.projection {
+            Projection::Single => self.buffer.pop_front().map(|v| vec![v]),
+            _ => {
+                let first = self.buffer.pop_front()?;
+                let second = self.buffer.pop_front().unwrap_or_default();
+                Some(match self.projection {
+                    Projection::First => vec![first],
+                    Projection::Second => vec![second],
+                    _ => vec![first, second],
+                })
+            }
+        }
+    }
+}
+
+/// Create the `redis.call_iter` function; `db` resolves the database of the running script
+pub fn create_call_iter<F>(lua: &Lua, storage: Arc<StorageEngine>, db: F) -> LuaResult<Function>
+where
+    F: Fn() -> usize + Send + Clone + 'static,
+{
+    lua.create_function(move |lua, cmd: MultiValue| {
+        let args = collect_args(cmd)?;
+        let mut iter = CursorIter::new(args.clone()).map_err(abort)?;
+        lru::record_access(&storage, db(), &args);
+        
+        // Fetch the first chunk eagerly so WRONGTYPE and similar errors surface at the call site
+        iter.fill(&storage, db()).map_err(abort)?;
+        
+        let state = RefCell::new(iter);
+        let storage = storage.clone();
+        let db = db.clone();
+        lua.create_function(move |lua, _: MultiValue| {
+            let mut iter = state.borrow_mut();
+            iter.fill(&storage, db()).map_err(abort)?;
+            match iter.next_values() {
+                Some(values) => values.into_iter()
+                    .map(|v| lua.create_string(&v).map(LuaValue::String))
+                    .collect::<LuaResult<MultiValue>>(),
+                None => Ok(MultiValue::from_iter([LuaValue::Nil])),
+            }
+        })
+    })
+}
+
+/// Cursor userdata returned by `redis.cursor`
+struct ScanCursor {
+    iter: CursorIter,
+    storage: Arc<StorageEngine>,
+    db: usize,
+}
+
+impl UserData for ScanCursor {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        // next([count]): the next batch as a flat table (field/value pairs for HSCAN and ZSCAN)
+        methods.add_method_mut("next", |lua, this, count: Option<usize>| {
+            let storage = this.storage.clone();
+            this.iter.fill(&storage, this.db).map_err(abort)?;
+            
+            let take = match count {
+                Some(count) => (count * this.iter.entry_width()).min(this.iter.buffer.len()),
+                None => this.iter.buffer.len(),
+            };
+            let batch = lua.create_table()?;
+            for (i, value) in this.iter.buffer.drain(..take).enumerate() {
+                batch.set(i + 1, lua.create_string(&value)?)?;
+            }
+            Ok(batch)
+        });
+        
+        methods.add_method("done", |_, this, ()| Ok(this.iter.is_done()));
+    }
+}
+
+/// Create the `redis.cursor` function; only the SCAN-family commands are accepted
+pub fn create_cursor<F>(lua: &Lua, storage: Arc<StorageEngine>, db: F) -> LuaResult<Function>
+where
+    F: Fn() -> usize + Send + 'static,
+{
+    lua.create_function(move |_, cmd: MultiValue| {
+        let args = collect_args(cmd)?;
+        let name = args.first().map(|n| String::from_utf8_lossy(n).to_uppercase()).unwrap_or_default();
+        if !matches!(name.as_str(), "SCAN" | "SSCAN" | "HSCAN" | "ZSCAN") {
+            return Err(abort(format!("ERR redis.cursor does not support '{}'", name)));
+        }
+        
+        let db = db();
+        let mut iter = CursorIter::new(args.clone()).map_err(abort)?;
+        lru::record_access(&storage, db, &args);
+        iter.fill(&storage, db).map_err(abort)?;
+        
+        Ok(ScanCursor { iter, storage: storage.clone(), db })
+    })
+}
+
+/// Convert redis.call-style arguments to bytes
+fn collect_args(cmd: MultiValue) -> LuaResult<Vec<Vec<u8>>> {
+    let mut args = Vec::with_capacity(cmd.len());
+    for value in cmd {
+        match value {
+            LuaValue::String(s) => args.push(s.as_bytes().to_vec()),
+            LuaValue::Integer(i) => args.push(i.to_string().into_bytes()),
+            LuaValue::Number(n) => args.push(n.to_string().into_bytes()),
+            _ => return Err(abort("ERR Invalid argument type".to_string())),
+        }
+    }
+    Ok(args)
+}
+
+/// Raise an error the same way redis.call does
+fn abort(message: String) -> mlua::Error {
+    let message = if message.starts_with("ERR ") || message.starts_with("WRONGTYPE") {
+        message
+    } else {
+        format!("ERR {}", message)
+    };
+    mlua::Error::RuntimeError(format!("REDIS_CALL_ABORT:{}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn lua_with_call_iter(storage: &Arc<StorageEngine>) -> Lua {
+        let lua = Lua::new();
+        let redis = lua.create_table().unwrap();
+        redis.set("call_iter", create_call_iter(&lua, storage.clone(), || 0).unwrap()).unwrap();
+        redis.set("cursor", create_cursor(&lua, storage.clone(), || 0).unwrap()).unwrap();
+        lua.globals().set("redis", redis).unwrap();
+        lua
+    }
+    
+    #[test]
+    fn test_iterates_large_set_in_chunks() {
+        let storage = StorageEngine::new_in_memory();
+        let members: Vec<Vec<u8>> = (0..2000).map(|i| format!("m{}", i).into_bytes()).collect();
+        storage.sadd(0, b"big".to_vec(), members).unwrap();
+        
+        let lua = lua_with_call_iter(&storage);
+        let count: i64 = lua.load("local n = 0 for m in redis.call_iter('SMEMBERS', 'big') do n = n + 1 end return n").eval().unwrap();
+        assert_eq!(count, 2000);
+    }
+    
+    #[test]
+    fn test_hash_projections() {
+        let storage = StorageEngine::new_in_memory();
+        storage.hset(0, b"h".to_vec(), vec![(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), b"2".to_vec())]).unwrap();
+        
+        let lua = lua_with_call_iter(&storage);
+        let pairs: String = lua.load("local out = {} for f, v in redis.call_iter('HGETALL', 'h') do out[#out + 1] = f .. '=' .. v end table.sort(out) return table.concat(out, ',')").eval().unwrap();
+        assert_eq!(pairs, "a=1,b=2");
+        let values: String = lua.load("local out = {} for v in redis.call_iter('HVALS', 'h') do out[#out + 1] = v end table.sort(out) return table.concat(out, ',')").eval().unwrap();
+        assert_eq!(values, "1,2");
+    }
+    
+    #[test]
+    fn test_errors_surface_at_call_site() {
+        let storage = StorageEngine::new_in_memory();
+        storage.set_string(0, b"s".to_vec(), b"v".to_vec()).unwrap();
+        
+        let lua = lua_with_call_iter(&storage);
+        assert!(lua.load("return redis.call_iter('SMEMBERS', 's')").exec().is_err());
+        assert!(lua.load("return redis.call_iter('GET', 's')").exec().is_err());
+        
+        // Missing keys iterate as empty
+        let count: i64 = lua.load("local n = 0 for m in redis.call_iter('SMEMBERS', 'nope') do n = n + 1 end return n").eval().unwrap();
+        assert_eq!(count, 0);
+    }
+    
+    #[test]
+    fn test_cursor_userdata_batches() {
+        let storage = StorageEngine::new_in_memory();
+        for i in 0..1200 {
+            storage.set_string(0, format!("user:{}", i).into_bytes(), b"v".to_vec()).unwrap();
+        }
+        storage.set_string(0, b"other".to_vec(), b"v".to_vec()).unwrap();
+        storage.hset(0, b"h".to_vec(), vec![(b"f".to_vec(), b"v".to_vec())]).unwrap();
+        
+        let lua = lua_with_call_iter(&storage);
+        let (keys, batches): (i64, i64) = lua.load(r#"
+            local c = redis.cursor('SCAN', 'MATCH', 'user:*')
+            local keys, batches = 0, 0
+            while not c:done() do
+                keys = keys + #c:next(100)
+                batches = batches + 1
+            end
+            return keys, batches
+        "#).eval().unwrap();
+        assert_eq!(keys, 1200);
+        assert!(batches >= 12);
+        
+        let pairs: String = lua.load("return table.concat(redis.cursor('HSCAN', 'h'):next(), ',')").eval().unwrap();
+        assert_eq!(pairs, "f,v");
+        assert!(lua.load("return redis.cursor('SMEMBERS', 'h')").exec().is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! redis.log for scripts
+//!
+//! `redis.log(level, message, ...)` writes to the server log at one of the
+//! `redis.LOG_DEBUG | LOG_VERBOSE | LOG_NOTICE | LOG_WARNING` levels. Lines below
+//! the server `loglevel` are dropped without counting, and each script
+//! invocation may write at most [`SCRIPT_LOG_LIMIT`] lines so a tight loop
+//! cannot flood the log; the number of suppressed lines is logged once when
+//! the script finishes.
+
+use std::cell::Cell;
+
+use mlua::{Function, Lua, MultiValue, Result as LuaResult, Table, Value as LuaValue};
+
+use crate::config::LogLevel;
+use crate::logging;
+
+/// Maximum log lines a single script invocation may write
+pub const SCRIPT_LOG_LIMIT: usize = 100;
+
+thread_local! {
+    /// Lines written and suppressed by the script running on this thread
+    static LINES: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+/// Log budget of one script invocation, reporting suppressed lines when dropped
+pub struct ScriptLogScope {
+    previous: Option<(usize, usize)>,
+}
+
+impl ScriptLogScope {
+    /// Start a fresh log budget for a script on this thread
+    pub fn begin() -> Self {
+        let previous = LINES.with(|lines| lines.replace(Some((0, 0))));
+        ScriptLogScope { previous }
+    }
+}
+
+impl Drop for ScriptLogScope {
+    fn drop(&mut self) {
+        if let Some((_, suppressed)) = LINES.with(|lines| lines.replace(self.previous)) {
+            if suppressed > 0 {
+                logging::log(LogLevel::Warning, &format!(
+                    "Script log rate limit reached: suppressed {} lines (limit {} per script)", suppressed, SCRIPT_LOG_LIMIT));
+            }
+        }
+    }
+}
+
+/// Whether a script log budget is open on this thread
+pub(crate) fn is_limited() -> bool {
+    LINES.with(|lines| lines.get().is_some())
+}
+
+/// Charge one line against the current script's budget, returning whether it may be written
+fn admit_line() -> bool {
+    LINES.with(|lines| match lines.get() {
+        Some((written, suppressed)) if written >= SCRIPT_LOG_LIMIT => {
+            lines.set(Some((written, suppressed + 1)));
+            false
+        }
+        Some((written, suppressed)) => {
+            lines.set(Some((written + 1, suppressed)));
+            true
+        }
+        None => true,
+    })
+}
+
+/// Create the redis.log function
+fn create_log_function(lua: &Lua) -> LuaResult<Function> {
+    lua.create_function(|_, args: MultiValue| {
+        let args: Vec<LuaValue> = args.into_iter().collect();
+        if args.len() < 2 {
+            return Err(mlua::Error::RuntimeError("redis.log() requires two arguments or more.".to_string()));
+        }
+        
+        let level = match &args[0] {
+            LuaValue::Integer(0) => LogLevel::Debug,
+            LuaValue::Integer(1) => LogLevel::Verbose,
+            LuaValue::Integer(2) => LogLevel::Notice,
+            LuaValue::Integer(3) => LogLevel::Warning,
+            LuaValue::Number(n) if (0.0..=3.0).contains(n) && n.fract() == 0.0 => {
+                [LogLevel::Debug, LogLevel::Verbose, LogLevel::Notice, LogLevel::Warning][*n as usize]
+            }
+            LuaValue::Integer(_) | LuaValue::Number(_) => {
+                return Err(mlua::Error::RuntimeError("Invalid debug level.".to_string()));
+            }
+            _ => return Err(mlua::Error::RuntimeError("First argument must be a number (log level).".to_string())),
+        };
+        
+        if !logging::enabled(level) || !admit_line() {
+            return Ok(());
+        }
+        
+        let mut parts = Vec::with_capacity(args.len() - 1);
+        for arg in &args[1..] {
+            match arg {
+                LuaValue::String(s) => parts.push(s.to_string_lossy()),
+                LuaValue::Integer(i) => parts.push(i.to_string()),
+                LuaValue::Number(n) => parts.push(n.to_string()),
+                _ => return Err(mlua::Error::RuntimeError("redis.log() arguments must be strings or numbers.".to_string())),
+            }
+        }
+        logging::log(level, &parts.join(" "));
+        Ok(())
+    })
+}
+
+/// Install redis.log and the LOG_* level constants into a redis table
+pub fn register(lua: &Lua, redis_table: &Table) -> LuaResult<()> {
+    redis_table.set("log", create_log_function(lua)?)?;
+    redis_table.set("LOG_DEBUG", 0)?;
+    redis_table.set("LOG_VERBOSE", 1)?;
+    redis_table.set("LOG_NOTICE", 2)?;
+    redis_table.set("LOG_WARNING", 3)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_log_budget_per_scope() {
+        let _scope = ScriptLogScope::begin();
+        for _ in 0..SCRIPT_LOG_LIMIT {
+            assert!(admit_line());
+        }
+        assert!(!admit_line());
+        
+        // A nested invocation gets its own budget
+        {
+            let _inner = ScriptLogScope::begin();
+            assert!(admit_line());
+        }
+        assert!(!admit_line());
+    }
+    
+    #[test]
+    fn test_log_argument_validation() {
+        let lua = Lua::new();
+        let redis = lua.create_table().unwrap();
+        register(&lua, &redis).unwrap();
+        lua.globals().set("redis", redis).unwrap();
+        
+        lua.load("redis.log(redis.LOG_DEBUG, 'filtered', 1)").exec().unwrap();
+        let err = lua.load("redis.log(7, 'x')").exec().unwrap_err().to_string();
+        assert!(err.contains("Invalid debug level."));
+        let err = lua.load("redis.log(redis.LOG_WARNING)").exec().unwrap_err().to_string();
+        assert!(err.contains("requires two arguments"));
+    }
+}
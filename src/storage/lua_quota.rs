@@ -0,0 +1,297 @@
+//! Per-user script quotas
+//!
+//! ACL users can be given script quotas with ACL SETUSER rules (0 leaves a
+//! quota unlimited, the default):
+//!
+//! - `script-max-concurrent=<n>`: scripts the user may run at the same time
+//! - `script-cpu-ms-per-minute=<ms>`: script run time per minute, counted in
+//!   fixed one-minute windows
+//! - `script-max-memory=<bytes>`: Lua heap one script of the user may use, on
+//!   top of `lua-memory-limit` (for FCALL, on top of what the shared library
+//!   state already holds)
+//!
+//! The server runs script commands inside a [`UserScope`] naming the client's
+//! user. EVAL and FCALL take a [`Checkout`] before running: it refuses the
+//! script when the user already runs as many as allowed or has used up the
+//! minute's run time, and otherwise hands the time left to the `lua_budget`
+//! hook, which stops the script once it is spent. The time a script ran is
+//! charged when the checkout ends. Scripts run outside a user scope (triggers,
+//! AOF replay, replicas) are not charged. ACL GETUSER and INFO clients report
+//! each user's usage.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::error::{FerrousError, Result, ScriptError};
+
+/// Length of a run time quota window
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Script quotas of one user (0 = unlimited)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// Scripts the user may run at the same time
+    pub max_concurrent: usize,
+    
+    /// Milliseconds of script run time per minute
+    pub cpu_ms_per_minute: u64,
+    
+    /// Bytes of Lua heap one script may use
+    pub max_memory: usize,
+}
+
+impl Quota {
+    /// Apply an ACL SETUSER rule such as `script-max-concurrent=2`
+    ///
+    /// Returns None when `rule` is not a quota rule, and an error when its
+    /// value is not a non-negative integer.
+    pub fn apply_rule(&mut self, rule: &str) -> Option<std::result::Result<(), String>> {
+        let (name, value) = rule.split_once('=')?;
+        let name = name.to_ascii_lowercase();
+        if !matches!(name.as_str(), "script-max-concurrent" | "script-cpu-ms-per-minute" | "script-max-memory") {
+            return None;
+        }
+        let Ok(value) = value.parse::<u64>() else {
+            return Some(Err(format!("'{}' needs a non-negative integer", name)));
+        };
+        match name.as_str() {
+            "script-max-concurrent" => self.max_concurrent = value as usize,
+            "script-cpu-ms-per-minute" => self.cpu_ms_per_minute = value,
+            _ => self.max_memory = value as usize,
+        }
+        Some(Ok(()))
+    }
+    
+    /// The rules that set this quota, for ACL LIST
+    pub fn rules(&self) -> Vec<String> {
+        [
+            ("script-max-concurrent", self.max_concurrent as u64),
+            ("script-cpu-ms-per-minute", self.cpu_ms_per_minute),
+            ("script-max-memory", self.max_memory as u64),
+        ].into_iter()
+            .filter(|(_, value)| *value > 0)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect()
+    }
+}
+
+/// Script usage of one user, as reported by ACL GETUSER and INFO clients
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Scripts running now
+    pub running: usize,
+    
+    /// Milliseconds of run time charged in the current window
+    pub cpu_ms: u64,
+    
+    /// Scripts started since the user was created
+    pub scripts: u64,
+    
+    /// Scripts refused for exceeding a quota
+    pub rejected: u64,
+}
+
+/// Run time charged in one quota window
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    started: Instant,
+    used: Duration,
+}
+
+/// Quota and usage of one user, shared by all of its clients
+#[derive(Debug)]
+pub struct UserQuota {
+    name: String,
+    quota: Mutex<Quota>,
+    running: AtomicUsize,
+    window: Mutex<Window>,
+    scripts: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl UserQuota {
+    /// Unlimited quotas for the user `name`
+    pub fn new(name: impl Into<String>) -> Self {
+        UserQuota {
+            name: name.into(),
+            quota: Mutex::new(Quota::default()),
+            running: AtomicUsize::new(0),
+            window: Mutex::new(Window { started: Instant::now(), used: Duration::ZERO }),
+            scripts: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+    
+    /// The user's quotas
+    pub fn quota(&self) -> Quota {
+        *self.quota.lock().unwrap()
+    }
+    
+    /// Replace the user's quotas, applying them from the next script on
+    pub fn set_quota(&self, quota: Quota) {
+        *self.quota.lock().unwrap() = quota;
+    }
+    
+    /// The user's script usage
+    pub fn usage(&self) -> Usage {
+        Usage {
+            running: self.running.load(Ordering::Relaxed),
+            cpu_ms: self.current_window().used.as_millis() as u64,
+            scripts: self.scripts.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+    
+    /// The current quota window, starting a new one once a minute has passed
+    fn current_window(&self) -> MutexGuard<'_, Window> {
+        let mut window = self.window.lock().unwrap();
+        if window.started.elapsed() >= WINDOW {
+            *window = Window { started: Instant::now(), used: Duration::ZERO };
+        }
+        window
+    }
+    
+    /// Count a script refused by a quota and build its error
+    fn reject(&self, reason: String) -> FerrousError {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        FerrousError::Script(ScriptError::QuotaExceeded(format!("user '{}' {}", self.name, reason)))
+    }
+}
+
+thread_local! {
+    /// User of the client whose command is running on this thread
+    static USER: RefCell<Option<Arc<UserQuota>>> = const { RefCell::new(None) };
+}
+
+/// Charges the scripts run on this thread to one user until dropped
+pub struct UserScope {
+    previous: Option<Arc<UserQuota>>,
+}
+
+impl UserScope {
+    /// Charge scripts run on this thread to `user`
+    pub fn enter(user: Arc<UserQuota>) -> Self {
+        UserScope { previous: USER.with(|current| current.replace(Some(user))) }
+    }
+}
+
+impl Drop for UserScope {
+    fn drop(&mut self) {
+        USER.with(|current| *current.borrow_mut() = self.previous.take());
+    }
+}
+
+/// The user scripts on this thread are charged to, if any
+fn current_user() -> Option<Arc<UserQuota>> {
+    USER.with(|current| current.borrow().clone())
+}
+
+/// One script run, admitted under and charged to the current user's quotas
+pub struct Checkout {
+    user: Option<Arc<UserQuota>>,
+    started: Instant,
+    cpu_allowance_ms: u64,
+}
+
+impl Checkout {
+    /// Admit a script about to run, or fail if it would exceed a quota
+    pub fn begin() -> Result<Self> {
+        let started = Instant::now();
+        let Some(user) = current_user() else {
+            return Ok(Checkout { user: None, started, cpu_allowance_ms: 0 });
+        };
+        let quota = user.quota();
+        
+        let running = user.running.fetch_add(1, Ordering::SeqCst) + 1;
+        if quota.max_concurrent > 0 && running > quota.max_concurrent {
+            user.running.fetch_sub(1, Ordering::SeqCst);
+            return Err(user.reject(format!("already runs {} of its {} concurrent scripts", running - 1, quota.max_concurrent)));
+        }
+        
+        let mut cpu_allowance_ms = 0;
+        if quota.cpu_ms_per_minute > 0 {
+            let used = user.current_window().used.as_millis() as u64;
+            if used >= quota.cpu_ms_per_minute {
+                user.running.fetch_sub(1, Ordering::SeqCst);
+                return Err(user.reject(format!("used its {} ms of script time for this minute", quota.cpu_ms_per_minute)));
+            }
+            cpu_allowance_ms = quota.cpu_ms_per_minute - used;
+        }
+        
+        user.scripts.fetch_add(1, Ordering::Relaxed);
+        Ok(Checkout { user: Some(user), started, cpu_allowance_ms })
+    }
+    
+    /// Script run time the user has left this minute, for the budget (0 = unlimited)
+    pub fn cpu_allowance_ms(&self) -> u64 {
+        self.cpu_allowance_ms
+    }
+}
+
+impl Drop for Checkout {
+    fn drop(&mut self) {
+        let Some(user) = self.user.take() else { return };
+        user.current_window().used += self.started.elapsed();
+        user.running.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Heap quota of one script of the current user (0 = unlimited)
+pub fn max_memory() -> usize {
+    current_user().map_or(0, |user| user.quota().max_memory)
+}
+
+/// Heap limit for a new script VM: the tighter of `global` and the current user's quota (0 = unlimited)
+pub fn memory_limit(global: usize) -> usize {
+    match (global, max_memory()) {
+        (0, quota) => quota,
+        (global, 0) => global,
+        (global, quota) => global.min(quota),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_quota_rules_round_trip() {
+        let mut quota = Quota::default();
+        assert_eq!(quota.apply_rule("script-max-concurrent=2"), Some(Ok(())));
+        assert_eq!(quota.apply_rule("SCRIPT-MAX-MEMORY=1048576"), Some(Ok(())));
+        assert!(matches!(quota.apply_rule("script-cpu-ms-per-minute=-1"), Some(Err(_))));
+        assert_eq!(quota.apply_rule(">password"), None);
+        assert_eq!(quota.rules(), ["script-max-concurrent=2", "script-max-memory=1048576"]);
+    }
+    
+    #[test]
+    fn test_checkout_enforces_concurrency_and_run_time() {
+        let user = Arc::new(UserQuota::new("alice"));
+        user.set_quota(Quota { max_concurrent: 1, cpu_ms_per_minute: 20, max_memory: 4096 });
+        let _scope = UserScope::enter(user.clone());
+        
+        let first = Checkout::begin().unwrap();
+        assert_eq!(first.cpu_allowance_ms(), 20);
+        let err = Checkout::begin().err().unwrap().to_string();
+        assert!(err.contains("user 'alice' already runs 1 of its 1 concurrent scripts"), "{}", err);
+        std::thread::sleep(Duration::from_millis(25));
+        drop(first);
+        
+        let err = Checkout::begin().err().unwrap().to_string();
+        assert!(err.contains("used its 20 ms of script time for this minute"), "{}", err);
+        let usage = user.usage();
+        assert_eq!((usage.running, usage.scripts, usage.rejected), (0, 1, 2));
+        assert!(usage.cpu_ms >= 25);
+        assert_eq!(memory_limit(0), 4096);
+        assert_eq!(memory_limit(1024), 1024);
+    }
+    
+    #[test]
+    fn test_no_user_is_unlimited() {
+        let checkout = Checkout::begin().unwrap();
+        assert_eq!(checkout.cpu_allowance_ms(), 0);
+        assert_eq!(memory_limit(1024), 1024);
+    }
+}
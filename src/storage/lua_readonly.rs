@@ -0,0 +1,282 @@
+//! Read-only script API tables
+//!
+//! FUNCTION libraries share one Lua state for the life of the server, so a
+//! library that replaced `redis.call` or hung a metatable on a shared table
+//! would change what every later call sees. The `redis` table is therefore
+//! handed to scripts as a proxy: reads go through `__index`, assignments raise
+//! `Attempt to modify a readonly table`, and `__metatable` keeps
+//! `getmetatable`/`setmetatable` away from the real table.
+//!
+//! KEYS and ARGV cannot be table proxies, since Lua 5.1 ignores `__len` on
+//! tables and `#KEYS` would always be 0. They are [`ReadOnlyArray`] userdata
+//! instead, which do honour `__len`. [`register`] wraps the base and `table`
+//! functions that take a table so they read through such a proxy as if it were
+//! the array itself, while `table.insert`, `table.remove`, `table.sort`,
+//! `rawset` and `setmetatable` refuse it. `rawset` also refuses the other
+//! protected tables, so it cannot plant fields behind a proxy's back.
+
+use mlua::{Function, Lua, MetaMethod, Result as LuaResult, Table, UserData, UserDataMethods, Value as LuaValue};
+
+/// Error raised by writes to a protected table
+pub const READONLY_MESSAGE: &str = "Attempt to modify a readonly table";
+
+/// Value `getmetatable` returns for a protected table
+const PROTECTED: &str = "protected";
+
+/// Metatable field marking a protected table, for the `rawset` replacement
+const MARKER: &str = "__readonly";
+
+/// Registry key of the Lua function building a [`ReadOnlyArray`]
+const ARRAY_CONSTRUCTOR: &str = "readonly_array";
+
+/// `__newindex` handler reporting the script line of the write
+const REFUSE_WRITE: &str = r#"
+local error, message = error, ...
+return function() error(message, 2) end
+"#;
+
+/// Proxy-aware replacements for the base and `table` functions that take a table
+///
+/// Returns the constructor for read-only arrays, which remembers each proxy's
+/// entries in a weak table, then the replaced globals and `table` functions.
+const PROXY_AWARE: &str = r#"
+local make, message, error, type, getmetatable, setmetatable, table = ...
+local entries_of = setmetatable({}, {__mode = "k"})
+
+local function array(entries)
+    local proxy = make(entries)
+    entries_of[proxy] = entries
+    return proxy
+end
+local function reading(f)
+    return function(t, ...) return f(entries_of[t] or t, ...) end
+end
+local function writing(f)
+    return function(t, ...)
+        if entries_of[t] then error(message, 2) end
+        return f(t, ...)
+    end
+end
+
+local base = {
+    type = function(...)
+        if entries_of[...] then return "table" end
+        return type(...)
+    end,
+    getmetatable = function(...)
+        if entries_of[...] then return "protected" end
+        return getmetatable(...)
+    end,
+    setmetatable = writing(setmetatable),
+}
+for _, name in ipairs({"next", "pairs", "ipairs", "rawget", "unpack"}) do
+    base[name] = reading(_G[name])
+end
+local library = {}
+for _, name in ipairs({"concat", "getn", "maxn", "foreach", "foreachi"}) do
+    if table[name] then library[name] = reading(table[name]) end
+end
+for _, name in ipairs({"insert", "remove", "sort"}) do
+    library[name] = writing(table[name])
+end
+return array, base, library
+"#;
+
+/// KEYS or ARGV as scripts see them: readable like an array, never modified
+///
+/// Built through [`read_only_array`] so the replaced library functions know
+/// the proxy.
+pub struct ReadOnlyArray {
+    entries: Table,
+    len: usize,
+}
+
+impl UserData for ReadOnlyArray {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_meta_method(MetaMethod::Index, |_, this, key: LuaValue| this.entries.raw_get::<LuaValue>(key));
+        methods.add_meta_method(MetaMethod::NewIndex, |lua, _, _: (LuaValue, LuaValue)| -> LuaResult<()> {
+            Err(mlua::Error::RuntimeError(at_caller(lua, READONLY_MESSAGE)))
+        });
+        methods.add_meta_method(MetaMethod::Len, |_, this, ()| Ok(this.len));
+        methods.add_meta_method(MetaMethod::ToString, |_, this, ()| Ok(format!("table: {:p}", this.entries.to_pointer())));
+    }
+}
+
+/// Prefix `message` with the script position that called into Rust, as `error(message, 2)` would
+fn at_caller(lua: &Lua, message: &str) -> String {
+    let position = lua.inspect_stack(1, |debug| {
+        let line = debug.current_line()?;
+        Some(format!("{}:{}: ", debug.source().short_src?, line))
+    });
+    format!("{}{}", position.flatten().unwrap_or_default(), message)
+}
+
+/// A new metatable protecting one table
+fn protected_metatable(lua: &Lua) -> LuaResult<Table> {
+    let refuse: Function = lua.load(REFUSE_WRITE).set_name("=readonly").call(READONLY_MESSAGE)?;
+    let meta = lua.create_table_with_capacity(0, 4)?;
+    meta.raw_set("__newindex", refuse)?;
+    meta.raw_set("__metatable", PROTECTED)?;
+    meta.raw_set(MARKER, true)?;
+    Ok(meta)
+}
+
+/// Wrap `table` in a proxy that can be read but not modified
+pub fn read_only(lua: &Lua, table: Table) -> LuaResult<Table> {
+    let meta = protected_metatable(lua)?;
+    meta.raw_set("__index", table)?;
+    let proxy = lua.create_table()?;
+    proxy.set_metatable(Some(meta))?;
+    Ok(proxy)
+}
+
+/// Wrap the array `entries` in a [`ReadOnlyArray`] (for KEYS and ARGV)
+///
+/// Needs [`register`] to have run on the state.
+pub fn read_only_array(lua: &Lua, entries: Table) -> LuaResult<LuaValue> {
+    let array: Function = lua.named_registry_value(ARRAY_CONSTRUCTOR)?;
+    array.call(entries)
+}
+
+/// The entries behind a [`ReadOnlyArray`], or the value itself
+///
+/// For Rust code converting script values, which would otherwise see userdata.
+pub fn unwrap(value: LuaValue) -> LuaValue {
+    let entries = match &value {
+        LuaValue::UserData(data) => data.borrow::<ReadOnlyArray>().map(|array| array.entries.clone()),
+        _ => return value,
+    };
+    entries.map_or(value, LuaValue::Table)
+}
+
+/// Make `table` read-only in place: its entries move behind a protected metatable
+///
+/// Used for the globals of the shared FUNCTION state, which cannot be swapped
+/// for a proxy. `pairs` over a frozen table finds nothing.
+pub fn freeze(lua: &Lua, table: &Table) -> LuaResult<()> {
+    let entries = lua.create_table()?;
+    for pair in table.pairs::<LuaValue, LuaValue>() {
+        let (key, value) = pair?;
+        entries.raw_set(key, value)?;
+    }
+    table.clear()?;
+    let meta = protected_metatable(lua)?;
+    meta.raw_set("__index", entries)?;
+    table.set_metatable(Some(meta))?;
+    Ok(())
+}
+
+/// The table behind a [`read_only`] proxy, for trusted setup code
+pub fn target(proxy: &Table) -> Option<Table> {
+    proxy.metatable()?.raw_get("__index").ok()
+}
+
+/// Check whether `table` was made read-only or locked
+fn is_protected(table: &Table) -> bool {
+    table.metatable().is_some_and(|meta| meta.raw_get(MARKER).unwrap_or(false))
+}
+
+/// Make the table functions in `globals` proxy-aware and `rawset` refuse protected tables
+///
+/// Run before `table` itself is made read-only.
+pub fn register(lua: &Lua, globals: &Table) -> LuaResult<()> {
+    let rawset = lua.create_function(|lua, (target, key, value): (LuaValue, LuaValue, LuaValue)| {
+        match target {
+            LuaValue::Table(table) if !is_protected(&table) => {
+                table.raw_set(key, value)?;
+                Ok(table)
+            }
+            LuaValue::Table(_) | LuaValue::UserData(_) => Err(mlua::Error::RuntimeError(at_caller(lua, READONLY_MESSAGE))),
+            other => Err(mlua::Error::RuntimeError(format!(
+                "bad argument #1 to 'rawset' (table expected, got {})", other.type_name()))),
+        }
+    })?;
+    globals.set("rawset", rawset)?;
+    
+    let make = lua.create_function(|lua, entries: Table| {
+        let len = entries.raw_len();
+        lua.create_userdata(ReadOnlyArray { entries, len })
+    })?;
+    let table: Table = globals.get("table")?;
+    let (array, base, library): (Function, Table, Table) = lua.load(PROXY_AWARE).set_name("=readonly").call((
+        make,
+        READONLY_MESSAGE,
+        globals.get::<Function>("error")?,
+        globals.get::<Function>("type")?,
+        globals.get::<Function>("getmetatable")?,
+        globals.get::<Function>("setmetatable")?,
+        table.clone(),
+    ))?;
+    for pair in base.pairs::<String, Function>() {
+        let (name, function) = pair?;
+        globals.set(name, function)?;
+    }
+    for pair in library.pairs::<String, Function>() {
+        let (name, function) = pair?;
+        table.set(name, function)?;
+    }
+    lua.set_named_registry_value(ARRAY_CONSTRUCTOR, array)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_proxy_reads_through_and_refuses_writes() {
+        let lua = Lua::new();
+        let api = lua.create_table().unwrap();
+        api.set("answer", 42).unwrap();
+        let proxy = read_only(&lua, api.clone()).unwrap();
+        lua.globals().set("api", proxy.clone()).unwrap();
+        register(&lua, &lua.globals()).unwrap();
+        
+        assert_eq!(lua.load("return api.answer").eval::<i64>().unwrap(), 42);
+        assert_eq!(lua.load("return getmetatable(api)").eval::<String>().unwrap(), PROTECTED);
+        for script in ["api.answer = 1", "api.extra = 1", "setmetatable(api, {})", "rawset(api, 'answer', 1)"] {
+            assert!(lua.load(script).exec().is_err(), "{} should fail", script);
+        }
+        assert_eq!(api.get::<i64>("answer").unwrap(), 42);
+        assert_eq!(target(&proxy).unwrap().to_pointer(), api.to_pointer());
+    }
+    
+    #[test]
+    fn test_frozen_table_keeps_reads() {
+        let lua = Lua::new();
+        let globals = lua.globals();
+        register(&lua, &globals).unwrap();
+        freeze(&lua, &globals).unwrap();
+        
+        assert_eq!(lua.load("return string.upper(type(_G))").eval::<String>().unwrap(), "TABLE");
+        for script in ["x = 1", "_G.print = nil", "rawset(_G, 'x', 1)", "setmetatable(_G, nil)"] {
+            assert!(lua.load(script).exec().is_err(), "{} should fail", script);
+        }
+        assert_eq!(lua.load("return getmetatable(_G)").eval::<String>().unwrap(), PROTECTED);
+    }
+    
+    #[test]
+    fn test_read_only_array_reads_like_a_table_and_refuses_writes() {
+        let lua = Lua::new();
+        register(&lua, &lua.globals()).unwrap();
+        let entries = lua.create_sequence_from(["a", "b", "c"]).unwrap();
+        let keys = read_only_array(&lua, entries.clone()).unwrap();
+        lua.globals().set("KEYS", keys.clone()).unwrap();
+        
+        let reads = r##"
+            local seen = {}
+            for i, key in ipairs(KEYS) do seen[#seen + 1] = i .. key end
+            for _, key in pairs(KEYS) do seen[#seen + 1] = key end
+            return table.concat({#KEYS, KEYS[2], type(KEYS), getmetatable(KEYS), table.concat(KEYS, ","),
+                select("#", unpack(KEYS)), rawget(KEYS, 3), table.getn(KEYS), next(KEYS), table.concat(seen)}, " ")
+        "##;
+        assert_eq!(lua.load(reads).eval::<String>().unwrap(), "3 b table protected a,b,c 3 c 3 1 1a2b3cabc");
+        for script in ["KEYS[1] = 'x'", "KEYS[4] = 'x'", "table.insert(KEYS, 'x')", "table.insert(KEYS, 1, 'x')",
+                       "table.remove(KEYS)", "table.sort(KEYS)", "rawset(KEYS, 1, 'x')", "setmetatable(KEYS, {})"] {
+            let err = lua.load(script).exec().unwrap_err().to_string();
+            assert!(err.contains(READONLY_MESSAGE), "{}: {}", script, err);
+        }
+        assert_eq!(entries.sequence_values::<String>().collect::<LuaResult<Vec<_>>>().unwrap(), ["a", "b", "c"]);
+        assert!(matches!(unwrap(keys), LuaValue::Table(table) if table.to_pointer() == entries.to_pointer()));
+        assert_eq!(lua.load("local t = {} table.insert(t, 1) return type(t) .. #t").eval::<String>().unwrap(), "table1");
+    }
+}
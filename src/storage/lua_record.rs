@@ -0,0 +1,390 @@
+//! Record/replay of script executions
+//!
+//! When `lua-record-dir` is set, every EVAL/EVALSHA captures its inputs (script,
+//! KEYS, ARGV, database, engine flags) and the reply of each redis.call it makes.
+//! Scripts that fail have this tape written to the directory. The `lua_replay`
+//! tool re-runs a tape offline: redis.call is answered from the tape instead of a
+//! live dataset, so a failure that depended on production data reproduces
+//! exactly, and the script can be stepped line by line.
+//!
+//! Tapes are RESP-encoded so they can be read back with the protocol parser.
+//! `redis.call_iter` and `redis.cursor` chunks are not captured; replaying them
+//! raises an error.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use mlua::{HookTriggers, Lua, MultiValue, Table, Value as LuaValue, VmState};
+use sha1::{Digest, Sha1};
+
+use crate::error::{FerrousError, Result};
+use crate::protocol::parser::parse_resp_frame;
+use crate::protocol::serializer::serialize_to_vec;
+use crate::protocol::RespFrame;
+use crate::storage::commands::executor::Reply;
+use crate::storage::lua_engine::{LuaCommandContext, LuaEngine, ReplyLimits};
+use crate::storage::{lua_readonly, StorageEngine};
+
+/// Format marker written as the first element of every tape
+const TAPE_MAGIC: &str = "ferrous-script-tape-1";
+
+thread_local! {
+    /// redis.call results captured for the script running on this thread
+    static RECORDING: RefCell<Option<Vec<RecordedCall>>> = const { RefCell::new(None) };
+}
+
+/// One redis.call made by a recorded script
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedCall {
+    /// Command and arguments as passed by the script
+    pub args: Vec<Vec<u8>>,
+    
+    /// Reply the command produced (command failures are stored as error replies)
+    pub reply: RespFrame,
+}
+
+/// Everything needed to re-run a script execution offline
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptTape {
+    pub script: String,
+    pub db: usize,
+    pub keys: Vec<Vec<u8>>,
+    pub args: Vec<Vec<u8>>,
+    pub limits: ReplyLimits,
+    pub utf8: bool,
+    pub deterministic: bool,
+    pub calls: Vec<RecordedCall>,
+    
+    /// Final result: the reply, or the error message returned to the client
+    pub outcome: std::result::Result<RespFrame, String>,
+}
+
+/// Captures redis.call results on this thread until finished or dropped
+pub struct Recording {
+    previous: Option<Vec<RecordedCall>>,
+}
+
+impl Recording {
+    /// Start capturing calls made on this thread
+    pub fn start() -> Self {
+        let previous = RECORDING.with(|r| r.borrow_mut().replace(Vec::new()));
+        Recording { previous }
+    }
+    
+    /// Stop capturing and return the calls made since `start`
+    pub fn finish(mut self) -> Vec<RecordedCall> {
+        let previous = self.previous.take();
+        RECORDING.with(|r| std::mem::replace(&mut *r.borrow_mut(), previous)).unwrap_or_default()
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        RECORDING.with(|r| *r.borrow_mut() = previous);
+    }
+}
+
+/// Check whether calls on this thread are being recorded
+pub fn is_recording() -> bool {
+    RECORDING.with(|r| r.borrow().is_some())
+}
+
+/// Record the result of a redis.call
+pub fn record_call(args: Vec<Vec<u8>>, result: &Result<Reply>) {
+    let reply = match result {
+        Ok(reply) => RespFrame::from(reply.clone()),
+        Err(e) => RespFrame::error(single_line(&e.to_string())),
+    };
+    RECORDING.with(|r| {
+        if let Some(calls) = r.borrow_mut().as_mut() {
+            calls.push(RecordedCall { args, reply });
+        }
+    });
+}
+
+/// Error frames cannot carry newlines
+fn single_line(message: &str) -> String {
+    message.replace(['\r', '\n'], " ")
+}
+
+fn bulk(bytes: &[u8]) -> RespFrame {
+    RespFrame::bulk_string(bytes)
+}
+
+fn bulk_array(items: &[Vec<u8>]) -> RespFrame {
+    RespFrame::array(items.iter().map(|item| bulk(item)).collect())
+}
+
+impl ScriptTape {
+    /// Encode the tape as a single RESP frame
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let outcome = match &self.outcome {
+            Ok(reply) => RespFrame::array(vec![bulk(b"ok"), reply.clone()]),
+            Err(message) => RespFrame::array(vec![bulk(b"err"), bulk(message.as_bytes())]),
+        };
+        let calls = self.calls.iter()
+            .map(|call| RespFrame::array(vec![bulk_array(&call.args), call.reply.clone()]))
+            .collect();
+        
+        serialize_to_vec(&RespFrame::array(vec![
+            bulk(TAPE_MAGIC.as_bytes()),
+            bulk(self.script.as_bytes()),
+            RespFrame::Integer(self.db as i64),
+            bulk_array(&self.keys),
+            bulk_array(&self.args),
+            RespFrame::array(vec![
+                RespFrame::Integer(self.limits.max_elements as i64),
+                RespFrame::Integer(self.limits.max_bytes as i64),
+                RespFrame::Integer(self.limits.truncate as i64),
+                RespFrame::Integer(self.utf8 as i64),
+                RespFrame::Integer(self.deterministic as i64),
+            ]),
+            RespFrame::array(calls),
+            outcome,
+        ]))
+    }
+    
+    /// Decode a tape written by `to_bytes`
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let invalid = || FerrousError::Internal("invalid script tape".to_string());
+        
+        let parts = match parse_resp_frame(data)? {
+            Some((RespFrame::Array(Some(parts)), _)) if parts.len() == 8 => parts,
+            _ => return Err(invalid()),
+        };
+        if bytes_of(&parts[0]).as_deref() != Some(TAPE_MAGIC.as_bytes()) {
+            return Err(invalid());
+        }
+        
+        let flags = integers_of(&parts[5]).filter(|f| f.len() == 5).ok_or_else(invalid)?;
+        let calls = match &parts[6] {
+            RespFrame::Array(Some(calls)) => calls.iter()
+                .map(|call| match call {
+                    RespFrame::Array(Some(pair)) if pair.len() == 2 => Some(RecordedCall {
+                        args: bytes_array_of(&pair[0])?,
+                        reply: pair[1].clone(),
+                    }),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(invalid)?,
+            _ => return Err(invalid()),
+        };
+        let outcome = match &parts[7] {
+            RespFrame::Array(Some(pair)) if pair.len() == 2 => match bytes_of(&pair[0]).as_deref() {
+                Some(b"ok") => Ok(pair[1].clone()),
+                Some(b"err") => Err(String::from_utf8_lossy(&bytes_of(&pair[1]).ok_or_else(invalid)?).to_string()),
+                _ => return Err(invalid()),
+            },
+            _ => return Err(invalid()),
+        };
+        
+        Ok(ScriptTape {
+            script: String::from_utf8_lossy(&bytes_of(&parts[1]).ok_or_else(invalid)?).to_string(),
+            db: match parts[2] {
+                RespFrame::Integer(db) if db >= 0 => db as usize,
+                _ => return Err(invalid()),
+            },
+            keys: bytes_array_of(&parts[3]).ok_or_else(invalid)?,
+            args: bytes_array_of(&parts[4]).ok_or_else(invalid)?,
+            limits: ReplyLimits {
+                max_elements: flags[0] as usize,
+                max_bytes: flags[1] as usize,
+                truncate: flags[2] != 0,
+            },
+            utf8: flags[3] != 0,
+            deterministic: flags[4] != 0,
+            calls,
+            outcome,
+        })
+    }
+    
+    /// Write the tape into `dir`, returning the file path
+    pub fn save_in(&self, dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+        let sha = hex::encode(Sha1::digest(self.script.as_bytes()));
+        let path = dir.join(format!("{}-{}.tape", sha, millis));
+        fs::write(&path, self.to_bytes()?)?;
+        Ok(path)
+    }
+    
+    /// Read a tape file
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+fn bytes_of(frame: &RespFrame) -> Option<Vec<u8>> {
+    match frame {
+        RespFrame::BulkString(Some(bytes)) => Some(bytes.to_vec()),
+        _ => None,
+    }
+}
+
+fn bytes_array_of(frame: &RespFrame) -> Option<Vec<Vec<u8>>> {
+    match frame {
+        RespFrame::Array(Some(items)) => items.iter().map(bytes_of).collect(),
+        _ => None,
+    }
+}
+
+fn integers_of(frame: &RespFrame) -> Option<Vec<i64>> {
+    match frame {
+        RespFrame::Array(Some(items)) => items.iter()
+            .map(|item| match item {
+                RespFrame::Integer(i) => Some(*i),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Re-run a tape, answering redis.call from the recorded replies
+///
+/// `on_line` is invoked before each executed source line. Returns the script's
+/// result, or an error if the script issued different commands than recorded.
+pub fn replay(tape: &ScriptTape, on_line: Option<Box<dyn Fn(usize) + Send>>) -> Result<RespFrame> {
+    let storage = StorageEngine::new_in_memory();
+    let engine = LuaEngine::new(storage.clone())?;
+    engine.set_reply_limits(tape.limits);
+    engine.set_utf8_enabled(tape.utf8);
+    engine.set_deterministic(tape.deterministic);
+    
+    let ctx = LuaCommandContext { db_index: tape.db, storage };
+    let lua = engine.create_lua_context(&ctx)?;
+    engine.setup_keys_and_args(&lua, &tape.keys, &tape.args)?;
+    
+    let lua_err = |e: mlua::Error| FerrousError::LuaError(e.to_string());
+    let pending = Arc::new(Mutex::new(tape.calls.iter().cloned().collect::<VecDeque<_>>()));
+    let divergence: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    let replay_divergence = divergence.clone();
+    let redis_proxy: mlua::Table = lua.globals().get("redis").map_err(lua_err)?;
+    let redis_table = lua_readonly::target(&redis_proxy).unwrap_or(redis_proxy);
+    
+    let limits = tape.limits;
+    let total = tape.calls.len();
+    let replay_call = move |lua_ctx: &Lua, cmd: MultiValue, is_pcall: bool| {
+        LuaEngine::run_redis_command(lua_ctx, cmd, is_pcall, limits, |args| {
+            let mut pending = pending.lock().unwrap();
+            let index = total - pending.len() + 1;
+            let message = match pending.pop_front() {
+                Some(call) if call.args == args => return Ok(Reply::from(call.reply)),
+                Some(call) => format!("call #{} issued {} but the tape has {}", index, describe(&args), describe(&call.args)),
+                None => format!("call #{} issued {} beyond the end of the tape", index, describe(&args)),
+            };
+            *replay_divergence.lock().unwrap() = Some(message.clone());
+            Err(FerrousError::LuaError(format!("ERR replay diverged: {}", message)))
+        })
+    };
+    
+    for (name, is_pcall) in [("call", false), ("pcall", true)] {
+        let replay_call = replay_call.clone();
+        let function = lua.create_function(move |lua_ctx, cmd: MultiValue| replay_call(lua_ctx, cmd, is_pcall))
+            .map_err(lua_err)?;
+        redis_table.set(name, function).map_err(lua_err)?;
+    }
+    
+    let batch_call = replay_call.clone();
+    let mcall = lua.create_function(move |lua_ctx, commands: Table| {
+        LuaEngine::run_batch(lua_ctx, commands, |cmd| batch_call(lua_ctx, cmd, false))
+    }).map_err(lua_err)?;
+    redis_table.set("mcall", mcall).map_err(lua_err)?;
+    
+    let call_array = lua.create_function(move |lua_ctx, cmd: MultiValue| {
+        LuaEngine::call_array(lua_ctx, cmd, |cmd| replay_call(lua_ctx, cmd, false))
+    }).map_err(lua_err)?;
+    redis_table.set("call_array", call_array).map_err(lua_err)?;
+    
+    for name in ["call_iter", "cursor"] {
+        let function = lua.create_function(move |_, _: MultiValue| -> mlua::Result<LuaValue> {
+            Err(mlua::Error::RuntimeError(format!("REDIS_CALL_ABORT:ERR redis.{} is not recorded and cannot be replayed", name)))
+        }).map_err(lua_err)?;
+        redis_table.set(name, function).map_err(lua_err)?;
+    }
+    
+    if let Some(on_line) = on_line {
+        lua.set_hook(HookTriggers::EVERY_LINE, move |_, debug| {
+            on_line(debug.current_line().unwrap_or(0));
+            Ok(VmState::Continue)
+        }).map_err(lua_err)?;
+    }
+    
+    let result = engine.run_script(&lua, &tape.script);
+    
+    if let Some(message) = divergence.lock().unwrap().take() {
+        return Err(FerrousError::LuaError(format!("ERR replay diverged: {}", message)));
+    }
+    result
+}
+
+/// Render a command for divergence messages
+fn describe(args: &[Vec<u8>]) -> String {
+    args.iter().map(|a| String::from_utf8_lossy(a).to_string()).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn record(storage: &Arc<StorageEngine>, script: &str, keys: Vec<Vec<u8>>) -> ScriptTape {
+        let engine = LuaEngine::new(storage.clone()).unwrap();
+        let ctx = LuaCommandContext { db_index: 0, storage: storage.clone() };
+        let recording = Recording::start();
+        let outcome = engine.eval(script, keys.iter().cloned().map(Arc::new).collect(), vec![], &ctx).map_err(|e| e.to_string());
+        ScriptTape {
+            script: script.to_string(),
+            db: 0,
+            keys,
+            args: vec![],
+            limits: engine.reply_limits(),
+            utf8: false,
+            deterministic: false,
+            calls: recording.finish(),
+            outcome,
+        }
+    }
+    
+    #[test]
+    fn test_tape_roundtrip_and_replay() {
+        let storage = StorageEngine::new_in_memory();
+        storage.set_string(0, b"k".to_vec(), b"41".to_vec()).unwrap();
+        let tape = record(&storage, "local v = redis.call('GET', KEYS[1]) return tonumber(v) + 1", vec![b"k".to_vec()]);
+        assert_eq!(tape.calls.len(), 1);
+        
+        let decoded = ScriptTape::from_bytes(&tape.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, tape);
+        
+        // The replay sees the recorded value even though its own dataset is empty
+        assert_eq!(replay(&decoded, None).unwrap(), RespFrame::Integer(42));
+    }
+    
+    #[test]
+    fn test_replay_reproduces_failure_and_steps_lines() {
+        let storage = StorageEngine::new_in_memory();
+        storage.set_string(0, b"k".to_vec(), b"not-a-number".to_vec()).unwrap();
+        let script = "local v = redis.call('GET', KEYS[1])\nreturn tonumber(v) + 1";
+        let tape = record(&storage, script, vec![b"k".to_vec()]);
+        let recorded_error = tape.outcome.clone().unwrap_err();
+        
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let seen = lines.clone();
+        let replayed = replay(&tape, Some(Box::new(move |line| seen.lock().unwrap().push(line)))).unwrap_err();
+        assert_eq!(replayed.to_string(), recorded_error);
+        assert_eq!(*lines.lock().unwrap(), vec![1, 2]);
+    }
+    
+    #[test]
+    fn test_replay_detects_divergence() {
+        let storage = StorageEngine::new_in_memory();
+        let mut tape = record(&storage, "return redis.call('GET', KEYS[1])", vec![b"a".to_vec()]);
+        tape.keys = vec![b"b".to_vec()];
+        assert!(replay(&tape, None).unwrap_err().to_string().contains("replay diverged"));
+    }
+}
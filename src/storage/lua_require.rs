@@ -0,0 +1,128 @@
+//! Shared helper libraries for scripts: SCRIPT LOADLIB and require()
+//!
+//! `SCRIPT LOADLIB <name> <source>` stores a Lua module on the server, and
+//! scripts load it with `local json = require('json')`, so helper code does not
+//! have to be pasted into every EVAL. There is no filesystem access: `require`
+//! only resolves names against the libraries loaded this way.
+//!
+//! A module runs once per script environment (once per EVAL, or once per
+//! FUNCTION library), in that environment, with its name as `...`; `require`
+//! returns the module's return value (`true` if it returned nothing) and later
+//! calls return the cached value. Loading a library again replaces it for
+//! subsequent requires.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use mlua::{ChunkMode, Function, Lua, LuaOptions, Result as LuaResult, StdLib, Table};
+
+use crate::error::{FerrousError, Result};
+
+/// Lua side of `require`: per-environment cache and cycle detection
+const REQUIRE: &str = r#"
+local module_chunk, generation, env = ...
+local error, pcall, type = error, pcall, type
+local LOADING = {}
+local loaded, loaded_generation = {}, nil
+return function(name)
+    if type(name) ~= 'string' then
+        error("bad argument #1 to 'require' (string expected)", 2)
+    end
+    if loaded_generation ~= generation() then
+        loaded, loaded_generation = {}, generation()
+    end
+    local value = loaded[name]
+    if value == LOADING then
+        error("loop or previous error loading module '" .. name .. "'", 2)
+    elseif value ~= nil then
+        return value
+    end
+    local chunk = module_chunk(name, env)
+    loaded[name] = LOADING
+    local ok, result = pcall(chunk, name)
+    if not ok then
+        loaded[name] = nil
+        error(result, 0)
+    end
+    if result == nil then
+        result = true
+    end
+    loaded[name] = result
+    return result
+end
+"#;
+
+/// Libraries loaded with SCRIPT LOADLIB, by name
+static LIBRARIES: RwLock<BTreeMap<String, Arc<str>>> = RwLock::new(BTreeMap::new());
+
+/// Bumped whenever the library set changes, invalidating cached modules
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Check whether a name is usable as a library name
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Store a library after checking that it compiles (SCRIPT LOADLIB)
+pub fn load_library(name: &str, source: &str) -> Result<()> {
+    if !is_valid_name(name) {
+        return Err(FerrousError::LuaError(
+            "ERR Library names can only contain letters, numbers, underscores(_) and dots(.)".to_string()));
+    }
+    
+    let lua = Lua::new_with(StdLib::NONE, LuaOptions::new()).map_err(|e| FerrousError::LuaError(e.to_string()))?;
+    if let Err(e) = lua.load(source).set_name(format!("={}", name)).set_mode(ChunkMode::Text).into_function() {
+        let message = match e {
+            mlua::Error::SyntaxError { message, .. } => message,
+            other => other.to_string(),
+        };
+        return Err(FerrousError::LuaError(format!("ERR Error compiling library '{}': {}", name, message)));
+    }
+    
+    LIBRARIES.write().unwrap().insert(name.to_string(), Arc::from(source));
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Source of a loaded library
+pub fn library(name: &str) -> Option<Arc<str>> {
+    LIBRARIES.read().unwrap().get(name).cloned()
+}
+
+/// Names of the loaded libraries, sorted
+pub fn libraries() -> Vec<String> {
+    LIBRARIES.read().unwrap().keys().cloned().collect()
+}
+
+/// Remove every library (SCRIPT FLUSH)
+pub fn flush() {
+    LIBRARIES.write().unwrap().clear();
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Create the `require` function for scripts running in `env`
+pub fn create_require(lua: &Lua, env: Table) -> LuaResult<Function> {
+    let module_chunk = lua.create_function(|lua, (name, env): (String, Table)| {
+        let source = library(&name)
+            .ok_or_else(|| mlua::Error::RuntimeError(format!("module '{}' not found", name)))?;
+        lua.load(&*source).set_name(format!("={}", name)).set_mode(ChunkMode::Text).set_environment(env).into_function()
+    })?;
+    let generation = lua.create_function(|_, ()| Ok(GENERATION.load(Ordering::SeqCst)))?;
+    
+    lua.load(REQUIRE).set_name("=require").call((module_chunk, generation, env))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_library_names() {
+        assert!(is_valid_name("json"));
+        assert!(is_valid_name("utils.validate_2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("../etc/passwd"));
+        assert!(!is_valid_name("my lib"));
+    }
+}
@@ -0,0 +1,127 @@
+//! Sandbox hardening beyond the removed libraries
+//!
+//! Dropping `io`, `os` and `package` is not enough on its own. Lua 5.1 runs
+//! precompiled bytecode without verifying it, so a crafted binary chunk can
+//! read and write memory outside the VM. Script sources are therefore only
+//! loaded as text, and `loadstring` refuses binary chunks.
+//!
+//! FUNCTION libraries also share one Lua state. Each library has its own
+//! environment, but the globals behind it and the standard library tables are
+//! shared, and `getfenv(0)`, `_G` and `getmetatable('').__index` all reach
+//! them. [`isolate_shared_state`] makes those read-only, points the shared
+//! string metatable at the read-only `string` and stops `setfenv` from
+//! replacing the thread environment, so one library cannot change what
+//! another sees. `tests/script_sandbox.rs` checks both.
+
+use mlua::{Function, Lua, Result as LuaResult, Table};
+
+use crate::storage::lua_readonly;
+
+/// Standard library tables shared by every FUNCTION library
+pub const SHARED_LIBRARIES: [&str; 4] = ["string", "table", "math", "coroutine"];
+
+/// `loadstring` that refuses precompiled chunks
+const TEXT_LOADSTRING: &str = r#"
+local loadstring, byte, type = ...
+return function(chunk, name)
+    if type(chunk) == "string" and byte(chunk, 1) == 27 then
+        return nil, "loading binary chunks is not allowed"
+    end
+    return loadstring(chunk, name)
+end
+"#;
+
+/// `setfenv` that cannot replace the thread environment
+const GUARDED_SETFENV: &str = r#"
+local setfenv, type, error = ...
+return function(target, env)
+    if target == 0 then
+        error("cannot change the environment of the running thread", 2)
+    end
+    if type(target) == "number" and target > 0 then
+        target = target + 1
+    end
+    local changed = setfenv(target, env)
+    return changed
+end
+"#;
+
+/// Replace `loadstring` in `globals` with a version that only accepts source text
+pub fn register(lua: &Lua, globals: &Table) -> LuaResult<()> {
+    let Some(loadstring) = globals.get::<Option<Function>>("loadstring")? else {
+        return Ok(());
+    };
+    let string: Table = globals.get("string")?;
+    let loader: Function = lua.load(TEXT_LOADSTRING).set_name("=loadstring")
+        .call((loadstring, string.get::<Function>("byte")?, globals.get::<Function>("type")?))?;
+    globals.set("loadstring", loader)
+}
+
+/// Make the globals, the shared library tables and the string metatable of a FUNCTION state read-only
+///
+/// Run last when setting up the state: nothing can be added to the globals afterwards.
+pub fn isolate_shared_state(lua: &Lua) -> LuaResult<()> {
+    let globals = lua.globals();
+    
+    let setfenv: Function = lua.load(GUARDED_SETFENV).set_name("=setfenv")
+        .call((globals.get::<Function>("setfenv")?, globals.get::<Function>("type")?, globals.get::<Function>("error")?))?;
+    globals.set("setfenv", setfenv)?;
+    
+    for name in SHARED_LIBRARIES {
+        if let Some(library) = globals.get::<Option<Table>>(name)? {
+            globals.set(name, lua_readonly::read_only(lua, library)?)?;
+        }
+    }
+    if let Some(string) = globals.get::<Option<Table>>("string")? {
+        share_string_metatable(lua, string)?;
+    }
+    lua_readonly::freeze(lua, &globals)
+}
+
+/// Give every string a metatable whose `__index` is `string`, as Lua 5.1 does
+///
+/// The metatable the string library installed points at the raw library table,
+/// so it is replaced with one routing `("abc"):upper()` through the read-only
+/// proxy. `getmetatable('')` returns a read-only view of it.
+fn share_string_metatable(lua: &Lua, string: Table) -> LuaResult<()> {
+    let view = lua.create_table()?;
+    view.raw_set("__index", string.clone())?;
+    let meta = lua.create_table()?;
+    meta.raw_set("__index", string)?;
+    meta.raw_set("__metatable", lua_readonly::read_only(lua, view)?)?;
+    lua.set_type_metatable::<mlua::String>(Some(meta));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_loadstring_refuses_bytecode() {
+        let lua = Lua::new();
+        register(&lua, &lua.globals()).unwrap();
+        
+        assert_eq!(lua.load("return loadstring('return 1 + 1')()").eval::<i64>().unwrap(), 2);
+        let (loaded, message): (bool, String) = lua
+            .load("local f, err = loadstring(string.dump(function() return 1 end)) return f ~= nil, err")
+            .eval().unwrap();
+        assert!(!loaded);
+        assert_eq!(message, "loading binary chunks is not allowed");
+    }
+    
+    #[test]
+    fn test_shared_state_is_read_only() {
+        let lua = Lua::new();
+        lua_readonly::register(&lua, &lua.globals()).unwrap();
+        isolate_shared_state(&lua).unwrap();
+        
+        for script in ["string.upper = nil", "math.pi = 3", "getmetatable('').__index = {}", "shared = 1", "setfenv(0, {})"] {
+            assert!(lua.load(script).exec().is_err(), "{} should fail", script);
+        }
+        assert_eq!(lua.load("return ('ok'):upper() .. math.max(1, 2)").eval::<String>().unwrap(), "OK2");
+        assert!(lua.load("return getmetatable('').__index == string").eval::<bool>().unwrap());
+        assert!(lua.load("return getmetatable('abc') == getmetatable('')").eval::<bool>().unwrap());
+        assert_eq!(lua.load("local function f() return x end setfenv(f, {x = 'env'}) return f()").eval::<String>().unwrap(), "env");
+    }
+}
@@ -0,0 +1,152 @@
+//! Startup self-test of the scripting subsystem (`--check-lua`)
+//!
+//! A short conformance suite run against a fresh engine and an in-memory
+//! database before the server accepts connections: arithmetic, strings,
+//! tables, pcall, truthiness and equality, metatables, cjson and a
+//! `redis.call` round trip. A build whose Lua VM or bridge is broken then
+//! fails at startup, where operators see it, instead of on the first EVAL in
+//! production.
+
+use std::sync::Arc;
+
+use crate::protocol::resp::{Bytes, RespFrame};
+use crate::storage::lua_engine::{LuaCommandContext, LuaEngine};
+use crate::storage::StorageEngine;
+
+/// One check: a script, its KEYS and ARGV, and the reply it must produce
+struct Check {
+    name: &'static str,
+    script: &'static str,
+    keys: &'static [&'static str],
+    args: &'static [&'static str],
+    expected: fn() -> RespFrame,
+}
+
+/// A check whose script failed or replied differently
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckFailure {
+    /// Name of the check
+    pub name: &'static str,
+    
+    /// What went wrong
+    pub detail: String,
+}
+
+fn bulk(s: &str) -> RespFrame {
+    RespFrame::BulkString(Some(Arc::new(s.as_bytes().to_vec())))
+}
+
+const CHECKS: &[Check] = &[
+    Check {
+        name: "arithmetic",
+        script: "return {7 * 6, 2 ^ 10, math.floor(7 / 2), 10 % 3, math.max(3, 9, 4)}",
+        keys: &[],
+        args: &[],
+        expected: || RespFrame::Array(Some([42, 1024, 3, 1, 9].into_iter().map(RespFrame::Integer).collect())),
+    },
+    Check {
+        name: "strings",
+        script: "return string.format('%s-%03d', 'id', 7) .. ('abc'):upper() .. string.rep('x', 2) .. select(2, string.gsub('a.b.c', '%.', '/'))",
+        keys: &[],
+        args: &[],
+        expected: || bulk("id-007ABCxx2"),
+    },
+    Check {
+        name: "tables",
+        script: "local t = {} for i = 1, 5 do table.insert(t, i) end table.remove(t, 1) table.sort(t, function(a, b) return a > b end) \
+            return {#t, table.concat(t, ',')}",
+        keys: &[],
+        args: &[],
+        expected: || RespFrame::Array(Some(vec![RespFrame::Integer(4), bulk("5,4,3,2")])),
+    },
+    Check {
+        name: "pcall",
+        script: "local ok, err = pcall(error, 'boom', 0) local ok2, err2 = pcall(error, {code = 7}) \
+            return {tostring(ok), err, err2.code}",
+        keys: &[],
+        args: &[],
+        expected: || RespFrame::Array(Some(vec![bulk("false"), bulk("boom"), RespFrame::Integer(7)])),
+    },
+    Check {
+        name: "truthiness",
+        script: "local n = 0 / 0 local t = setmetatable({}, {__eq = function() return true end}) \
+            return {tostring(not not (0 and '' and {})), tostring(n ~= n), tostring(1 == '1'), tostring(t == 1)}",
+        keys: &[],
+        args: &[],
+        expected: || RespFrame::Array(Some(vec![bulk("true"), bulk("true"), bulk("false"), bulk("false")])),
+    },
+    Check {
+        name: "metatables",
+        script: "local v = setmetatable({x = 1}, {__index = function(_, k) return k .. '!' end, \
+            __add = function(a, b) return a.x + b end}) return {v.y, v + 41}",
+        keys: &[],
+        args: &[],
+        expected: || RespFrame::Array(Some(vec![bulk("y!"), RespFrame::Integer(42)])),
+    },
+    Check {
+        name: "cjson",
+        script: "local t = cjson.decode(cjson.encode({name = 'ferrous', list = {1, 2, 3}})) return {t.name, #t.list}",
+        keys: &[],
+        args: &[],
+        expected: || RespFrame::Array(Some(vec![bulk("ferrous"), RespFrame::Integer(3)])),
+    },
+    Check {
+        name: "redis.call",
+        script: "redis.call('SET', KEYS[1], ARGV[1]) local err = redis.pcall('INCR', KEYS[1]) \
+            return {redis.call('GET', KEYS[1]), redis.call('DEL', KEYS[1]), type(err.err)}",
+        keys: &["selftest:key"],
+        args: &["value"],
+        expected: || RespFrame::Array(Some(vec![bulk("value"), RespFrame::Integer(1), bulk("string")])),
+    },
+];
+
+/// Run the suite against a fresh engine and in-memory database, returning the
+/// number of checks and the ones that failed
+pub fn run() -> (usize, Vec<CheckFailure>) {
+    (CHECKS.len(), run_checks(CHECKS))
+}
+
+fn run_checks(checks: &[Check]) -> Vec<CheckFailure> {
+    let storage = StorageEngine::new_in_memory();
+    let engine = match LuaEngine::new(storage.clone()) {
+        Ok(engine) => engine,
+        Err(e) => return vec![CheckFailure { name: "engine", detail: e.to_string() }],
+    };
+    let ctx = LuaCommandContext { db_index: 0, storage };
+    let bytes = |values: &[&str]| values.iter().map(|value| Bytes::new(value.as_bytes().to_vec())).collect();
+    
+    checks.iter()
+        .filter_map(|check| {
+            let detail = match engine.eval(check.script, bytes(check.keys), bytes(check.args), &ctx) {
+                Ok(reply) if reply == (check.expected)() => return None,
+                Ok(reply) => format!("expected {:?}, got {:?}", (check.expected)(), reply),
+                Err(e) => e.to_string(),
+            };
+            Some(CheckFailure { name: check.name, detail })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_suite_passes() {
+        let (count, failures) = run();
+        assert_eq!(count, CHECKS.len());
+        assert_eq!(failures, Vec::new());
+    }
+    
+    #[test]
+    fn test_failures_are_reported() {
+        let checks = [
+            Check { name: "wrong", script: "return 1", keys: &[], args: &[], expected: || RespFrame::Integer(2) },
+            Check { name: "broken", script: "return +", keys: &[], args: &[], expected: || RespFrame::Integer(1) },
+        ];
+        let failures = run_checks(&checks);
+        assert_eq!(failures.iter().map(|failure| failure.name).collect::<Vec<_>>(), ["wrong", "broken"]);
+        assert_eq!(failures[0].detail, "expected Integer(2), got Integer(1)");
+        assert!(failures[1].detail.contains("compiling"), "{}", failures[1].detail);
+    }
+}
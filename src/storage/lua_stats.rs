@@ -0,0 +1,212 @@
+//! Per-VM execution statistics for scripts
+//!
+//! Each script runs in a VM checked out for it (FCALL in the persistent
+//! functions VM), and a [`VmStatsScope`] accumulates counters for that run: Lua
+//! instructions executed, redis.call bridge crossings, peak Lua heap and peak
+//! call-stack depth. Instructions are counted with a count hook firing every
+//! [`INSTRUCTION_SAMPLE`] instructions, which is also when heap and stack
+//! depth are sampled and the script's budget is checked, so the hook stays off
+//! the per-instruction path.
+//!
+//! The stats of the most recently finished script are reported by
+//! `DEBUG LUA VMSTATS`; embedders can read them with [`last`] or take them
+//! from [`VmStatsScope::finish`] directly.
+//!
+//! MLua runs the stock Lua 5.1 interpreter, so counters that would need
+//! hooks inside the interpreter loop (table operations, string allocations,
+//! GC cycles) are not available.
+
+use std::cell::Cell;
+use std::sync::Mutex;
+
+use mlua::{HookTriggers, Lua, Result as LuaResult, VmState};
+
+use crate::protocol::RespFrame;
+use crate::storage::lua_budget;
+
+/// Instructions between two samples of the count hook
+pub const INSTRUCTION_SAMPLE: u32 = 1000;
+
+/// Counters accumulated by one script VM since it was checked out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VmStats {
+    /// Lua instructions executed, in multiples of [`INSTRUCTION_SAMPLE`]
+    pub instructions: u64,
+    
+    /// Commands issued through redis.call, redis.pcall and redis.mcall
+    pub redis_calls: u64,
+    
+    /// Largest Lua heap seen, in bytes
+    pub peak_memory: usize,
+    
+    /// Deepest Lua call stack seen, in frames
+    pub peak_stack_depth: usize,
+}
+
+impl VmStats {
+    /// Field/value pairs as returned by DEBUG LUA VMSTATS
+    pub fn to_resp(&self) -> RespFrame {
+        RespFrame::array(vec![
+            RespFrame::from_string("instructions"),
+            RespFrame::Integer(self.instructions as i64),
+            RespFrame::from_string("redis_calls"),
+            RespFrame::Integer(self.redis_calls as i64),
+            RespFrame::from_string("peak_memory"),
+            RespFrame::Integer(self.peak_memory as i64),
+            RespFrame::from_string("peak_stack_depth"),
+            RespFrame::Integer(self.peak_stack_depth as i64),
+        ])
+    }
+}
+
+thread_local! {
+    /// Stats of the script running on this thread
+    static CURRENT: Cell<Option<VmStats>> = const { Cell::new(None) };
+}
+
+/// Stats of the most recently finished script
+static LAST: Mutex<VmStats> = Mutex::new(VmStats {
+    instructions: 0,
+    redis_calls: 0,
+    peak_memory: 0,
+    peak_stack_depth: 0,
+});
+
+/// Stats collection for one script run, installed as a count hook on its VM
+pub struct VmStatsScope {
+    previous: Option<VmStats>,
+}
+
+impl VmStatsScope {
+    /// Start counting for a script about to run in `lua`
+    pub fn begin(lua: &Lua) -> LuaResult<Self> {
+        let initial = VmStats { peak_memory: lua.used_memory(), ..VmStats::default() };
+        let previous = CURRENT.with(|current| current.replace(Some(initial)));
+        let scope = VmStatsScope { previous };
+        
+        lua.set_hook(HookTriggers::new().every_nth_instruction(INSTRUCTION_SAMPLE), |lua, _| {
+            let depth = stack_depth(lua);
+            let mut instructions = 0;
+            update(|stats| {
+                stats.instructions += INSTRUCTION_SAMPLE as u64;
+                stats.peak_memory = stats.peak_memory.max(lua.used_memory());
+                stats.peak_stack_depth = stats.peak_stack_depth.max(depth);
+                instructions = stats.instructions;
+            });
+            lua_budget::check(instructions, depth)?;
+            Ok(VmState::Continue)
+        })?;
+        Ok(scope)
+    }
+    
+    /// Stop counting, publish the stats as the latest and return them
+    pub fn finish(self, lua: &Lua) -> VmStats {
+        lua.remove_hook();
+        update(|stats| stats.peak_memory = stats.peak_memory.max(lua.used_memory()));
+        let stats = CURRENT.with(|current| current.get()).unwrap_or_default();
+        *LAST.lock().unwrap() = stats;
+        stats
+    }
+}
+
+impl Drop for VmStatsScope {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.previous));
+    }
+}
+
+/// Whether a script's stats are being collected on this thread
+pub(crate) fn is_sampling() -> bool {
+    CURRENT.with(|current| current.get().is_some())
+}
+
+/// Apply `f` to the stats of the script running on this thread, if any
+fn update(f: impl FnOnce(&mut VmStats)) {
+    CURRENT.with(|current| {
+        if let Some(mut stats) = current.get() {
+            f(&mut stats);
+            current.set(Some(stats));
+        }
+    });
+}
+
+/// Count a command issued by the script running on this thread
+pub fn note_redis_call() {
+    update(|stats| stats.redis_calls += 1);
+}
+
+/// Number of active Lua call frames
+///
+/// Found by exponential then binary search over stack levels, so sampling a
+/// deeply recursive script stays cheap.
+fn stack_depth(lua: &Lua) -> usize {
+    let has_level = |level: usize| lua.inspect_stack(level, |_| ()).is_some();
+    if !has_level(0) {
+        return 0;
+    }
+    
+    let (mut low, mut high) = (0, 1);
+    while has_level(high) {
+        low = high;
+        high *= 2;
+    }
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if has_level(mid) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    low + 1
+}
+
+/// Stats of the most recently finished script
+pub fn last() -> VmStats {
+    *LAST.lock().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_scope_counts_instructions_and_depth() {
+        let lua = Lua::new();
+        let scope = VmStatsScope::begin(&lua).unwrap();
+        lua.load(r#"
+            local function recurse(n) if n == 0 then for i = 1, 5000 do end return 0 end return recurse(n - 1) + 1 end
+            recurse(20)
+        "#).exec().unwrap();
+        note_redis_call();
+        let stats = scope.finish(&lua);
+        
+        assert!(stats.instructions >= 5 * INSTRUCTION_SAMPLE as u64, "{:?}", stats);
+        assert!(stats.peak_stack_depth > 20, "{:?}", stats);
+        assert!(stats.peak_memory > 0);
+        assert_eq!(stats.redis_calls, 1);
+        
+        // Counting stops with the scope
+        note_redis_call();
+        assert_eq!(CURRENT.with(|current| current.get()), None);
+    }
+    
+    #[test]
+    fn test_stack_depth_matches_frame_count() {
+        let lua = Lua::new();
+        let depth = lua.create_function(|lua, ()| {
+            let mut frames = 0;
+            while lua.inspect_stack(frames, |_| ()).is_some() {
+                frames += 1;
+            }
+            Ok((stack_depth(lua), frames))
+        }).unwrap();
+        lua.globals().set("depth", depth).unwrap();
+        
+        for n in [0, 1, 7, 64, 300] {
+            let (found, frames): (usize, usize) = lua.load("local function f(n) if n == 0 then return depth() end local a, b = f(n - 1) return a, b end return f(...)")
+                .call(n).unwrap();
+            assert_eq!(found, frames);
+        }
+    }
+}
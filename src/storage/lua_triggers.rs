@@ -0,0 +1,275 @@
+//! Keyspace triggers for FUNCTION libraries
+//!
+//! A library can register one of its functions as a trigger on a key pattern
+//! while it is loaded:
+//!
+//! ```lua
+//! #!lua name=orders
+//! redis.register_function('cleanup', function(keys, args) ... end)
+//! redis.register_trigger{function_name='cleanup', pattern='orders:*', events={'expired', 'del'}}
+//! ```
+//!
+//! Successful writes (from clients and from scripts) raise one event per key
+//! named after the command in lowercase (`set`, `del`, `hset`, ...), and keys
+//! removed by expiry raise `expired`. A trigger with no events fires on all of
+//! them. The function runs through the FCALL path, with the same atomicity and
+//! limits, receiving the key as `KEYS[1]` and the event as `ARGV[1]`.
+//!
+//! Events are queued on the thread that raised them and dispatched once it is
+//! safe to run Lua: when the outermost script finishes, after each client
+//! command, and after each expiry pass. Writes made by a trigger raise events
+//! one level deeper; events at `lua-trigger-max-depth` are dropped, so a
+//! trigger that keeps re-triggering itself stops there.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::config::LogLevel;
+use crate::logging;
+use crate::protocol::RespFrame;
+use crate::pubsub::pattern_matches;
+use crate::storage::commands::flags;
+use crate::storage::commands::lua::script_error_message;
+use crate::storage::lua_functions::function_registry;
+use crate::storage::{lru, DatabaseIndex};
+
+/// Default depth at which events raised by triggers are dropped
+pub const DEFAULT_MAX_DEPTH: usize = 4;
+
+/// A function registered to run on keyspace events
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trigger {
+    /// Library that registered the trigger
+    pub library: String,
+    
+    /// Function run when the trigger fires
+    pub function: String,
+    
+    /// Glob-style key pattern
+    pub pattern: Vec<u8>,
+    
+    /// Events the trigger fires on (empty = all)
+    pub events: Vec<String>,
+}
+
+impl Trigger {
+    fn matches(&self, event: &str, key: &[u8]) -> bool {
+        (self.events.is_empty() || self.events.iter().any(|e| e == event))
+            && pattern_matches(&self.pattern, key)
+    }
+}
+
+/// A keyspace event waiting to be dispatched
+struct Event {
+    db: DatabaseIndex,
+    name: String,
+    key: Vec<u8>,
+    depth: usize,
+}
+
+/// Registered triggers of all libraries
+static TRIGGERS: RwLock<Vec<Trigger>> = RwLock::new(Vec::new());
+
+/// Set while any trigger is registered, so writes skip event collection otherwise
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Depth at which events are dropped
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_DEPTH);
+
+/// Trigger runs skipped at the depth limit
+static SUPPRESSED: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Events raised on this thread and not yet dispatched
+    static PENDING: RefCell<VecDeque<Event>> = const { RefCell::new(VecDeque::new()) };
+    
+    /// Open defer scopes (scripts running) on this thread
+    static DEFERRED: Cell<usize> = const { Cell::new(0) };
+    
+    /// Trigger depth of the code running on this thread (0 outside triggers)
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Replace the triggers registered by `library`
+pub fn set_library_triggers(library: &str, triggers: Vec<Trigger>) {
+    let mut all = TRIGGERS.write().unwrap();
+    all.retain(|trigger| trigger.library != library);
+    all.extend(triggers);
+    ACTIVE.store(!all.is_empty(), Ordering::SeqCst);
+}
+
+/// Remove the triggers registered by `library`
+pub fn remove_library(library: &str) {
+    set_library_triggers(library, Vec::new());
+}
+
+/// Remove every trigger
+pub fn clear() {
+    TRIGGERS.write().unwrap().clear();
+    ACTIVE.store(false, Ordering::SeqCst);
+}
+
+/// Registered triggers, in registration order
+pub fn triggers() -> Vec<Trigger> {
+    TRIGGERS.read().unwrap().clone()
+}
+
+/// Check whether any trigger is registered
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Change the depth at which events raised by triggers are dropped
+pub fn set_max_depth(depth: usize) {
+    MAX_DEPTH.store(depth, Ordering::Relaxed);
+}
+
+/// Depth at which events raised by triggers are dropped
+pub fn max_depth() -> usize {
+    MAX_DEPTH.load(Ordering::Relaxed)
+}
+
+/// Number of trigger runs skipped at the depth limit
+pub fn suppressed_events() -> u64 {
+    SUPPRESSED.load(Ordering::Relaxed)
+}
+
+/// Event name and keys a command would raise, if it is a write and triggers are registered
+///
+/// Taken before the command runs (which consumes its arguments) and passed to
+/// [`notify_keys`] once it succeeded.
+pub fn command_events<T: AsRef<[u8]>>(args: &[T]) -> Option<(String, Vec<Vec<u8>>)> {
+    if !is_active() {
+        return None;
+    }
+    let name = String::from_utf8_lossy(args.first()?.as_ref()).to_uppercase();
+    if !flags::is_write_command(&name) {
+        return None;
+    }
+    
+    let keys: Vec<Vec<u8>> = lru::command_keys(args).into_iter().map(|key| key.to_vec()).collect();
+    (!keys.is_empty()).then(|| (name.to_lowercase(), keys))
+}
+
+/// Queue the events of a client write that succeeded
+pub fn notify_frame_command(db: DatabaseIndex, parts: &[RespFrame]) {
+    if !is_active() {
+        return;
+    }
+    
+    let args: Vec<&[u8]> = parts.iter()
+        .filter_map(|part| match part {
+            RespFrame::BulkString(Some(bytes)) => Some(bytes.as_slice()),
+            _ => None,
+        })
+        .collect();
+    if let Some((event, keys)) = command_events(&args) {
+        notify_keys(db, &event, keys);
+    }
+}
+
+/// Queue an event for each key
+pub fn notify_keys(db: DatabaseIndex, event: &str, keys: Vec<Vec<u8>>) {
+    for key in keys {
+        notify(db, event, key);
+    }
+}
+
+/// Queue an event for dispatch on this thread
+pub fn notify(db: DatabaseIndex, event: &str, key: Vec<u8>) {
+    if !is_active() {
+        return;
+    }
+    let depth = DEPTH.with(|depth| depth.get());
+    PENDING.with(|pending| pending.borrow_mut().push_back(Event { db, name: event.to_string(), key, depth }));
+}
+
+/// Holds back dispatch on this thread while a script runs
+///
+/// Events raised inside the scope are dispatched when the outermost scope ends.
+pub struct DeferScope(());
+
+impl DeferScope {
+    /// Defer dispatch on this thread until the scope ends
+    pub fn begin() -> Self {
+        DEFERRED.with(|deferred| deferred.set(deferred.get() + 1));
+        DeferScope(())
+    }
+}
+
+impl Drop for DeferScope {
+    fn drop(&mut self) {
+        let open = DEFERRED.with(|deferred| {
+            deferred.set(deferred.get() - 1);
+            deferred.get()
+        });
+        if open == 0 {
+            dispatch();
+        }
+    }
+}
+
+/// Open defer scopes and the trigger depth on this thread
+pub(crate) fn nesting() -> (usize, usize) {
+    (DEFERRED.with(|deferred| deferred.get()), DEPTH.with(|depth| depth.get()))
+}
+
+/// Run the triggers of every event queued on this thread
+///
+/// Does nothing while a script is running here; its events are dispatched when
+/// it finishes.
+pub fn dispatch() {
+    if DEFERRED.with(|deferred| deferred.get()) > 0 {
+        return;
+    }
+    
+    while let Some(event) = PENDING.with(|pending| pending.borrow_mut().pop_front()) {
+        let matching: Vec<Trigger> = TRIGGERS.read().unwrap().iter()
+            .filter(|trigger| trigger.matches(&event.name, &event.key))
+            .cloned()
+            .collect();
+        if event.depth >= max_depth() {
+            SUPPRESSED.fetch_add(matching.len() as u64, Ordering::Relaxed);
+            continue;
+        }
+        for trigger in matching {
+            run(&trigger, &event);
+        }
+    }
+}
+
+fn run(trigger: &Trigger, event: &Event) {
+    let Some(registry) = function_registry() else { return };
+    let previous = DEPTH.with(|depth| depth.replace(event.depth + 1));
+    let result = registry.call(&trigger.function, vec![Arc::new(event.key.clone())], vec![Arc::new(event.name.clone().into_bytes())], event.db);
+    DEPTH.with(|depth| depth.set(previous));
+    
+    if let Err(e) = result {
+        logging::log(LogLevel::Warning, &format!(
+            "Trigger {} on '{}' event for key '{}' failed: {}",
+            trigger.function, event.name, String::from_utf8_lossy(&event.key), script_error_message(&e)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_trigger_matching() {
+        let trigger = Trigger {
+            library: "lib".to_string(),
+            function: "f".to_string(),
+            pattern: b"orders:*".to_vec(),
+            events: vec!["expired".to_string()],
+        };
+        assert!(trigger.matches("expired", b"orders:1"));
+        assert!(!trigger.matches("set", b"orders:1"));
+        assert!(!trigger.matches("expired", b"users:1"));
+        
+        let any = Trigger { events: Vec::new(), ..trigger };
+        assert!(any.matches("del", b"orders:2"));
+    }
+}
@@ -0,0 +1,235 @@
+//! Lua 5.3-style `utf8` library for the Lua 5.1 scripting engine
+//!
+//! Lua 5.1 strings are byte strings, so scripts handling unicode payloads have no
+//! safe way to count or slice characters. This provides the 5.3 `utf8` functions
+//! (`char`, `charpattern`, `codepoint`, `len`, `offset`). It is opt-in via the
+//! `lua-enable-utf8` config so strict 5.1 compatibility can keep it disabled.
+
+use mlua::{Lua, Result as LuaResult, String as LuaString, Table, Value as LuaValue, Variadic};
+
+/// Largest code point accepted by utf8.char
+const MAX_UNICODE: i64 = 0x10FFFF;
+
+/// Pattern matching exactly one UTF-8 byte sequence (Lua 5.1 patterns stop at a
+/// literal NUL, so `%z` stands in for the `\0` used by Lua 5.3)
+const CHAR_PATTERN: &[u8] = b"[%z\x01-\x7F\xC2-\xF4][\x80-\xBF]*";
+
+/// Create the `utf8` table
+pub fn create_utf8_table(lua: &Lua) -> LuaResult<Table> {
+    let utf8 = lua.create_table()?;
+    
+    utf8.set("charpattern", lua.create_string(CHAR_PATTERN)?)?;
+    
+    utf8.set("char", lua.create_function(|lua, codes: Variadic<i64>| {
+        let mut out = Vec::with_capacity(codes.len());
+        for (i, &code) in codes.iter().enumerate() {
+            if !(0..=MAX_UNICODE).contains(&code) {
+                return Err(bad_argument(i + 1, "char", "value out of range"));
+            }
+            encode(code as u32, &mut out);
+        }
+        lua.create_string(&out)
+    })?)?;
+    
+    utf8.set("codepoint", lua.create_function(|_, (s, i, j): (LuaString, Option<i64>, Option<i64>)| {
+        let bytes = s.as_bytes();
+        let len = bytes.len() as i64;
+        let start = relative_position(i.unwrap_or(1), len);
+        let end = relative_position(j.unwrap_or(start), len);
+        if start < 1 {
+            return Err(bad_argument(2, "codepoint", "out of range"));
+        }
+        if end > len {
+            return Err(bad_argument(3, "codepoint", "out of range"));
+        }
+        
+        let mut codes = Variadic::new();
+        let mut pos = (start - 1) as usize;
+        while pos < end as usize {
+            match decode(&bytes[pos..]) {
+                Some((code, width)) => {
+                    codes.push(code as i64);
+                    pos += width;
+                }
+                None => return Err(mlua::Error::RuntimeError("invalid UTF-8 code".to_string())),
+            }
+        }
+        Ok(codes)
+    })?)?;
+    
+    utf8.set("len", lua.create_function(|_, (s, i, j): (LuaString, Option<i64>, Option<i64>)| {
+        let bytes = s.as_bytes();
+        let len = bytes.len() as i64;
+        let start = relative_position(i.unwrap_or(1), len);
+        let end = relative_position(j.unwrap_or(-1), len);
+        if start < 1 || start > len + 1 {
+            return Err(bad_argument(2, "len", "initial position out of string"));
+        }
+        if end > len {
+            return Err(bad_argument(3, "len", "final position out of string"));
+        }
+        
+        // Returns the count, or nil plus the position of the first invalid byte
+        let mut count = 0i64;
+        let mut pos = (start - 1) as usize;
+        while pos < end as usize {
+            match decode(&bytes[pos..]) {
+                Some((_, width)) => {
+                    count += 1;
+                    pos += width;
+                }
+                None => return Ok((LuaValue::Nil, Some(pos as i64 + 1))),
+            }
+        }
+        Ok((LuaValue::Integer(count), None))
+    })?)?;
+    
+    utf8.set("offset", lua.create_function(|_, (s, n, i): (LuaString, i64, Option<i64>)| {
+        let bytes = s.as_bytes();
+        let len = bytes.len() as i64;
+        let default_start = if n >= 0 { 1 } else { len + 1 };
+        let start = relative_position(i.unwrap_or(default_start), len);
+        if start < 1 || start - 1 > len {
+            return Err(bad_argument(3, "offset", "position out of range"));
+        }
+        
+        let is_continuation = |pos: i64| pos < len && bytes[pos as usize] & 0xC0 == 0x80;
+        let mut pos = start - 1;
+        let mut n = n;
+        
+        if n == 0 {
+            // Find the start of the character containing byte i
+            while pos > 0 && is_continuation(pos) {
+                pos -= 1;
+            }
+            return Ok(Some(pos + 1));
+        }
+        
+        if is_continuation(pos) {
+            return Err(mlua::Error::RuntimeError("initial position is a continuation byte".to_string()));
+        }
+        
+        if n < 0 {
+            while n < 0 && pos > 0 {
+                pos -= 1;
+                while pos > 0 && is_continuation(pos) {
+                    pos -= 1;
+                }
+                n += 1;
+            }
+        } else {
+            n -= 1;
+            while n > 0 && pos < len {
+                pos += 1;
+                while is_continuation(pos) {
+                    pos += 1;
+                }
+                n -= 1;
+            }
+        }
+        
+        Ok(if n == 0 { Some(pos + 1) } else { None })
+    })?)?;
+    
+    Ok(utf8)
+}
+
+/// Translate a relative string position (negative counts from the end)
+fn relative_position(pos: i64, len: i64) -> i64 {
+    if pos >= 0 {
+        pos
+    } else if -pos > len {
+        0
+    } else {
+        len + pos + 1
+    }
+}
+
+/// Lua-style argument error
+fn bad_argument(arg: usize, function: &str, message: &str) -> mlua::Error {
+    mlua::Error::RuntimeError(format!("bad argument #{} to '{}' ({})", arg, function, message))
+}
+
+/// Append the UTF-8 encoding of a code point
+fn encode(code: u32, out: &mut Vec<u8>) {
+    match code {
+        0..=0x7F => out.push(code as u8),
+        0x80..=0x7FF => {
+            out.push(0xC0 | (code >> 6) as u8);
+            out.push(0x80 | (code & 0x3F) as u8);
+        }
+        0x800..=0xFFFF => {
+            out.push(0xE0 | (code >> 12) as u8);
+            out.push(0x80 | ((code >> 6) & 0x3F) as u8);
+            out.push(0x80 | (code & 0x3F) as u8);
+        }
+        _ => {
+            out.push(0xF0 | (code >> 18) as u8);
+            out.push(0x80 | ((code >> 12) & 0x3F) as u8);
+            out.push(0x80 | ((code >> 6) & 0x3F) as u8);
+            out.push(0x80 | (code & 0x3F) as u8);
+        }
+    }
+}
+
+/// Decode one UTF-8 sequence, returning the code point and its byte width.
+/// Rejects overlong encodings and code points above U+10FFFF.
+fn decode(bytes: &[u8]) -> Option<(u32, usize)> {
+    let first = *bytes.first()?;
+    let (width, initial, min) = match first {
+        0x00..=0x7F => return Some((first as u32, 1)),
+        0xC0..=0xDF => (2, (first & 0x1F) as u32, 0x80),
+        0xE0..=0xEF => (3, (first & 0x0F) as u32, 0x800),
+        0xF0..=0xF7 => (4, (first & 0x07) as u32, 0x10000),
+        _ => return None,
+    };
+    
+    if bytes.len() < width {
+        return None;
+    }
+    
+    let mut code = initial;
+    for &b in &bytes[1..width] {
+        if b & 0xC0 != 0x80 {
+            return None;
+        }
+        code = (code << 6) | (b & 0x3F) as u32;
+    }
+    
+    if code < min || code > MAX_UNICODE as u32 {
+        return None;
+    }
+    Some((code, width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn eval<T: mlua::FromLuaMulti>(script: &str) -> T {
+        let lua = Lua::new();
+        lua.globals().set("utf8", create_utf8_table(&lua).unwrap()).unwrap();
+        lua.load(script).eval().unwrap()
+    }
+    
+    #[test]
+    fn test_len_and_char() {
+        assert_eq!(eval::<i64>("return utf8.len('h\\195\\169llo')"), 5);
+        assert_eq!(eval::<String>("return utf8.char(72, 233, 8364, 128512)"), "Hé€😀");
+        assert_eq!(eval::<(Option<i64>, i64)>("return utf8.len('ab\\255c')"), (None, 3));
+    }
+    
+    #[test]
+    fn test_codepoint_and_offset() {
+        assert_eq!(eval::<(i64, i64)>("return utf8.codepoint('\\226\\130\\172x', 1, -1)"), (8364, 120));
+        assert_eq!(eval::<i64>("return utf8.offset('a\\226\\130\\172b', 3)"), 5);
+        assert_eq!(eval::<i64>("return utf8.offset('a\\226\\130\\172b', -1)"), 5);
+        assert_eq!(eval::<i64>("return utf8.offset('a\\226\\130\\172b', 0, 3)"), 2);
+        assert_eq!(eval::<Option<i64>>("return utf8.offset('ab', 5)"), None);
+    }
+    
+    #[test]
+    fn test_charpattern_matches_sequences() {
+        assert_eq!(eval::<i64>("local n = 0 for _ in string.gmatch('h\\195\\169!', utf8.charpattern) do n = n + 1 end return n"), 3);
+    }
+}
@@ -0,0 +1,81 @@
+//! Lua VM creation for scripts, with a failure counter for INFO
+//!
+//! Script VMs are created bare, given their memory limit, and only then load
+//! the standard library, so a tight `lua-memory-limit` or memory pressure
+//! surfaces as a failed stdlib load rather than an abort. A failed load fails
+//! the script with an error; it is not retried, since a retry would stall the
+//! event loop, and the VM is never built with fewer libraries than scripts
+//! expect. Failures are counted so INFO shows them.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use mlua::{Lua, LuaOptions, Result as LuaResult, StdLib};
+
+use crate::error::{FerrousError, Result};
+
+/// VMs that could not be created since startup
+static INIT_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// VMs that could not be created since startup, reported by INFO
+pub fn init_failures() -> u64 {
+    INIT_FAILURES.load(Ordering::Relaxed)
+}
+
+/// Libraries loaded into script VMs
+///
+/// `package` is left out: the sandbox removes it anyway, and loading it into a
+/// safe VM under memory pressure can abort inside mlua.
+fn script_stdlib() -> StdLib {
+    StdLib::ALL_SAFE ^ StdLib::PACKAGE
+}
+
+/// Create a script VM, applying the memory limit before the stdlib is loaded
+pub fn create_vm(memory_limit: usize, debug_library: bool) -> Result<Lua> {
+    let libs = if debug_library { script_stdlib() | StdLib::DEBUG } else { script_stdlib() };
+    init_vm(libs, &INIT_FAILURES, |libs| {
+        let lua = if libs.contains(StdLib::DEBUG) {
+            // SAFETY: `debug` is the only unsafe library loaded, and only when the
+            // operator opted in with lua-enable-debug-library
+            unsafe { Lua::unsafe_new_with(StdLib::NONE, LuaOptions::new()) }
+        } else {
+            Lua::new_with(StdLib::NONE, LuaOptions::new())?
+        };
+        if memory_limit > 0 {
+            lua.set_memory_limit(memory_limit)?;
+        }
+        lua.load_std_libs(libs)?;
+        Ok(lua)
+    })
+}
+
+/// Build a VM with `libs` through `build`, counting a failure in `failures`
+fn init_vm<F>(libs: StdLib, failures: &AtomicU64, build: F) -> Result<Lua>
+where
+    F: FnOnce(StdLib) -> LuaResult<Lua>,
+{
+    build(libs).map_err(|e| {
+        failures.fetch_add(1, Ordering::Relaxed);
+        FerrousError::LuaError(format!("ERR Failed to initialize Lua standard library: {}", e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_full_stdlib() {
+        let lua = create_vm(0, false).unwrap();
+        assert_eq!(lua.load("return type(os) .. type(string) .. type(coroutine)").eval::<String>().unwrap(), "tabletabletable");
+        assert_eq!(lua.load("return type(debug)").eval::<String>().unwrap(), "nil");
+    }
+    
+    #[test]
+    fn test_failure_is_counted() {
+        let failures = AtomicU64::new(0);
+        let result = init_vm(script_stdlib(), &failures, |_| Err(mlua::Error::runtime("not enough memory")));
+        
+        assert!(result.unwrap_err().to_string().starts_with("ERR Failed to initialize Lua standard library"));
+        assert_eq!(failures.load(Ordering::Relaxed), 1);
+    }
+}
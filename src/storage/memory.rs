@@ -1,19 +1,54 @@
 //! Memory management for storage engine
 //! 
-//! Tracks memory usage and implements eviction policies.
+//! Tracks memory usage and implements eviction policies, including the
+//! per-key access frequency counter the LFU policies rank keys by.
+//!
+//! Usage is byte-accurate for what the engine accounts: every key, value and
+//! TTL it stores is added when written and removed when deleted, so the total
+//! is what MEMORY USAGE would report summed over all keys. Writes are never
+//! refused here; the server checks [`MemoryManager::bytes_to_free`] before
+//! running a write and evicts or replies with an OOM error.
 
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::time::Duration;
+use crate::storage::value::EncodingLimits;
+
+/// Frequency a new key starts at, so it is not evicted before a second access
+pub const LFU_INIT_VAL: u8 = 5;
+
+/// lfu-log-factor unless configured
+pub const DEFAULT_LFU_LOG_FACTOR: u32 = 10;
+
+/// lfu-decay-time unless configured, in minutes
+pub const DEFAULT_LFU_DECAY_TIME: u64 = 1;
+
+/// Keys sampled per eviction round unless configured (Redis' maxmemory-samples)
+pub const DEFAULT_EVICTION_SAMPLES: usize = 5;
 
 /// Memory manager for tracking usage and eviction
+#[derive(Debug)]
 pub struct MemoryManager {
     /// Current memory usage in bytes
     used_memory: AtomicUsize,
     
-    /// Maximum memory limit (0 = no limit)
-    max_memory: usize,
+    /// Maximum memory limit (0 = no limit), changed by CONFIG SET maxmemory
+    max_memory: AtomicUsize,
+    
+    /// Eviction policy, changed by CONFIG SET maxmemory-policy
+    policy: RwLock<EvictionPolicy>,
+    
+    /// Keys sampled per eviction round, changed by CONFIG SET maxmemory-samples
+    samples: AtomicUsize,
     
-    /// Eviction policy
-    policy: EvictionPolicy,
+    /// Changed by CONFIG SET lfu-log-factor
+    lfu_log_factor: AtomicU32,
+    
+    /// Changed by CONFIG SET lfu-decay-time
+    lfu_decay_time: AtomicU64,
+    
+    /// Compact encoding thresholds, changed by CONFIG SET hash-max-listpack-entries and friends
+    encoding_limits: RwLock<EncodingLimits>,
 }
 
 /// Available eviction policies
@@ -36,6 +71,96 @@ pub enum EvictionPolicy {
     
     /// Remove keys with expire set according to TTL
     VolatileTtl,
+    
+    /// Remove any key according to LFU
+    AllKeysLfu,
+    
+    /// Remove keys with expire set according to LFU
+    VolatileLfu,
+}
+
+impl EvictionPolicy {
+    /// Whether keys are ranked by access frequency rather than idle time
+    pub fn is_lfu(&self) -> bool {
+        matches!(self, EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu)
+    }
+    
+    /// Whether only keys with a TTL may be evicted
+    pub fn is_volatile(&self) -> bool {
+        matches!(
+            self,
+            EvictionPolicy::VolatileLru | EvictionPolicy::VolatileRandom | EvictionPolicy::VolatileTtl | EvictionPolicy::VolatileLfu
+        )
+    }
+}
+
+/// Tuning of the LFU counter, as set by lfu-log-factor and lfu-decay-time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LfuParams {
+    /// Higher values make the counter saturate after more accesses
+    pub log_factor: u32,
+    
+    /// Idle minutes after which the counter decays by one (0 = never)
+    pub decay_time: u64,
+}
+
+impl Default for LfuParams {
+    fn default() -> Self {
+        LfuParams { log_factor: DEFAULT_LFU_LOG_FACTOR, decay_time: DEFAULT_LFU_DECAY_TIME }
+    }
+}
+
+/// Logarithmic access frequency counter, as Redis keeps for LFU eviction
+///
+/// The counter saturates at 255 and grows with probability
+/// `1 / ((counter - LFU_INIT_VAL) * log_factor + 1)`, so with the default
+/// factor it takes about a million accesses to reach the top. It decays by
+/// one for every `decay_time` minutes the key sat idle, which callers pass in
+/// from the key's access clock.
+pub struct AccessFrequency(AtomicU8);
+
+impl AccessFrequency {
+    /// Create a counter for a new key
+    pub fn new() -> Self {
+        AccessFrequency(AtomicU8::new(LFU_INIT_VAL))
+    }
+    
+    /// Counter value once decayed for the time the key sat idle
+    pub fn get(&self, idle: Duration, lfu: LfuParams) -> u8 {
+        let periods = (idle.as_secs() / 60).checked_div(lfu.decay_time).unwrap_or(0);
+        self.0.load(Ordering::Relaxed).saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+    
+    /// Set the counter, as of the last access
+    pub fn set(&self, counter: u8) {
+        self.0.store(counter, Ordering::Relaxed);
+    }
+    
+    /// Record an access made after the key sat idle for `idle`
+    pub fn touch(&self, idle: Duration, lfu: LfuParams) {
+        let counter = self.get(idle, lfu);
+        let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+        let grows = counter < u8::MAX && rand::random::<f64>() < 1.0 / (base * lfu.log_factor as f64 + 1.0);
+        self.0.store(if grows { counter + 1 } else { counter }, Ordering::Relaxed);
+    }
+}
+
+impl Default for AccessFrequency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for AccessFrequency {
+    fn clone(&self) -> Self {
+        AccessFrequency(AtomicU8::new(self.0.load(Ordering::Relaxed)))
+    }
+}
+
+impl std::fmt::Debug for AccessFrequency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AccessFrequency({})", self.0.load(Ordering::Relaxed))
+    }
 }
 
 impl MemoryManager {
@@ -43,37 +168,42 @@ impl MemoryManager {
     pub fn new(max_memory: usize, policy: EvictionPolicy) -> Self {
         MemoryManager {
             used_memory: AtomicUsize::new(0),
-            max_memory,
-            policy,
+            max_memory: AtomicUsize::new(max_memory),
+            policy: RwLock::new(policy),
+            samples: AtomicUsize::new(DEFAULT_EVICTION_SAMPLES),
+            lfu_log_factor: AtomicU32::new(DEFAULT_LFU_LOG_FACTOR),
+            lfu_decay_time: AtomicU64::new(DEFAULT_LFU_DECAY_TIME),
+            encoding_limits: RwLock::new(EncodingLimits::default()),
         }
     }
     
     /// Create memory manager with no limits
     pub fn unlimited() -> Self {
-        MemoryManager {
-            used_memory: AtomicUsize::new(0),
-            max_memory: 0,
-            policy: EvictionPolicy::NoEviction,
-        }
+        Self::with_policy(EvictionPolicy::NoEviction)
+    }
+    
+    /// Create memory manager with no limit that reports the configured policy
+    pub fn with_policy(policy: EvictionPolicy) -> Self {
+        Self::new(0, policy)
     }
     
     /// Add memory usage
-    pub fn add_memory(&self, bytes: usize) -> bool {
-        let old_usage = self.used_memory.fetch_add(bytes, Ordering::Relaxed);
-        let new_usage = old_usage.saturating_add(bytes);
-        
-        // Check if we exceeded the limit
-        if self.max_memory > 0 && new_usage > self.max_memory {
-            // For now, just track, TODO: implement eviction
-            false
-        } else {
-            true
-        }
+    pub fn add_memory(&self, bytes: usize) {
+        self.used_memory.fetch_add(bytes, Ordering::Relaxed);
     }
     
     /// Remove memory usage
     pub fn remove_memory(&self, bytes: usize) {
-        self.used_memory.fetch_sub(bytes, Ordering::Relaxed);
+        let _ = self.used_memory.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| Some(used.saturating_sub(bytes)));
+    }
+    
+    /// Account for a value that grew or shrank in place from `before` to `after` bytes
+    pub fn resize_memory(&self, before: usize, after: usize) {
+        if after > before {
+            self.add_memory(after - before);
+        } else {
+            self.remove_memory(before - after);
+        }
     }
     
     /// Get current memory usage
@@ -83,12 +213,68 @@ impl MemoryManager {
     
     /// Get maximum memory limit
     pub fn max_memory(&self) -> usize {
-        self.max_memory
+        self.max_memory.load(Ordering::Relaxed)
+    }
+    
+    /// Change the memory limit (0 = no limit)
+    pub fn set_max_memory(&self, bytes: usize) {
+        self.max_memory.store(bytes, Ordering::Relaxed);
+    }
+    
+    /// Bytes to release to get back under the limit, 0 when within it
+    pub fn bytes_to_free(&self) -> usize {
+        match self.max_memory() {
+            0 => 0,
+            max_memory => self.used_memory().saturating_sub(max_memory),
+        }
+    }
+    
+    /// Keys sampled per eviction round
+    pub fn samples(&self) -> usize {
+        self.samples.load(Ordering::Relaxed)
+    }
+    
+    /// Change the keys sampled per eviction round
+    pub fn set_samples(&self, samples: usize) {
+        self.samples.store(samples.max(1), Ordering::Relaxed);
+    }
+    
+    /// Current LFU counter tuning
+    pub fn lfu_params(&self) -> LfuParams {
+        LfuParams {
+            log_factor: self.lfu_log_factor.load(Ordering::Relaxed),
+            decay_time: self.lfu_decay_time.load(Ordering::Relaxed),
+        }
+    }
+    
+    /// Change how fast the LFU counter saturates
+    pub fn set_lfu_log_factor(&self, log_factor: u32) {
+        self.lfu_log_factor.store(log_factor, Ordering::Relaxed);
+    }
+    
+    /// Change the idle minutes per LFU counter decrement
+    pub fn set_lfu_decay_time(&self, minutes: u64) {
+        self.lfu_decay_time.store(minutes, Ordering::Relaxed);
+    }
+    
+    /// Current compact encoding thresholds
+    pub fn encoding_limits(&self) -> EncodingLimits {
+        *self.encoding_limits.read().unwrap()
+    }
+    
+    /// Change the compact encoding thresholds, for values written from now on
+    pub fn set_encoding_limits(&self, limits: EncodingLimits) {
+        *self.encoding_limits.write().unwrap() = limits;
     }
     
     /// Get eviction policy
     pub fn policy(&self) -> EvictionPolicy {
-        self.policy
+        *self.policy.read().unwrap()
+    }
+    
+    /// Change the eviction policy
+    pub fn set_policy(&self, policy: EvictionPolicy) {
+        *self.policy.write().unwrap() = policy;
     }
     
     /// Calculate approximate size of data in bytes
@@ -107,25 +293,77 @@ mod tests {
         
         assert_eq!(manager.used_memory(), 0);
         
-        assert!(manager.add_memory(50));
+        manager.add_memory(50);
         assert_eq!(manager.used_memory(), 50);
         
-        assert!(manager.add_memory(30));
+        manager.add_memory(30);
         assert_eq!(manager.used_memory(), 80);
+        assert_eq!(manager.bytes_to_free(), 0);
         
-        // This should exceed the limit
-        assert!(!manager.add_memory(30));
-        assert_eq!(manager.used_memory(), 110); // Still tracks it
+        // Going over the limit is tracked, for the server to evict
+        manager.add_memory(30);
+        assert_eq!(manager.used_memory(), 110);
+        assert_eq!(manager.bytes_to_free(), 10);
         
-        manager.remove_memory(60);
-        assert_eq!(manager.used_memory(), 50);
+        manager.resize_memory(60, 20);
+        assert_eq!(manager.used_memory(), 70);
+        manager.resize_memory(20, 40);
+        assert_eq!(manager.used_memory(), 90);
+        
+        // Releasing more than is accounted never wraps around
+        manager.remove_memory(1000);
+        assert_eq!(manager.used_memory(), 0);
     }
     
     #[test]
     fn test_unlimited_memory() {
         let manager = MemoryManager::unlimited();
         
-        assert!(manager.add_memory(1_000_000));
+        manager.add_memory(1_000_000);
         assert_eq!(manager.used_memory(), 1_000_000);
+        assert_eq!(manager.bytes_to_free(), 0);
+    }
+    
+    #[test]
+    fn test_access_frequency() {
+        let lfu = LfuParams::default();
+        let frequency = AccessFrequency::new();
+        assert_eq!(frequency.get(Duration::ZERO, lfu), LFU_INIT_VAL);
+        
+        // The first accesses above the initial value always count
+        frequency.touch(Duration::ZERO, lfu);
+        assert_eq!(frequency.get(Duration::ZERO, lfu), LFU_INIT_VAL + 1);
+        for _ in 0..10_000 {
+            frequency.touch(Duration::ZERO, lfu);
+        }
+        let counter = frequency.get(Duration::ZERO, lfu);
+        assert!(counter > LFU_INIT_VAL + 1 && counter < 100, "{}", counter);
+        
+        // Idle time decays the counter, one per period
+        let minute = Duration::from_secs(60);
+        assert_eq!(frequency.get(minute * 3, lfu), counter - 3);
+        assert_eq!(frequency.get(minute * 1000, lfu), 0);
+        assert!(EvictionPolicy::VolatileLfu.is_lfu() && !EvictionPolicy::AllKeysLru.is_lfu());
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_lfu_params() {
+        let manager = MemoryManager::unlimited();
+        assert_eq!(manager.lfu_params(), LfuParams::default());
+        
+        // Without a log factor every access counts; a longer period decays slower
+        manager.set_lfu_log_factor(0);
+        manager.set_lfu_decay_time(10);
+        let lfu = manager.lfu_params();
+        let frequency = AccessFrequency::new();
+        for _ in 0..100 {
+            frequency.touch(Duration::ZERO, lfu);
+        }
+        assert_eq!(frequency.get(Duration::ZERO, lfu), LFU_INIT_VAL + 100);
+        assert_eq!(frequency.get(Duration::from_secs(60 * 25), lfu), LFU_INIT_VAL + 98);
+        
+        // A decay time of 0 never decays
+        manager.set_lfu_decay_time(0);
+        assert_eq!(frequency.get(Duration::from_secs(86_400), manager.lfu_params()), LFU_INIT_VAL + 100);
+    }
+}
@@ -5,19 +5,49 @@
 
 pub mod engine;
 pub mod value;
+pub mod shared;
+pub mod lru;
 pub mod memory;
+pub mod listpack;
 pub mod skiplist;
+pub mod hyperloglog;
+pub mod geo;
 pub mod stream;
 pub mod consumer_groups;
 pub mod stream_integration_tests;
 pub mod rdb;
+pub mod rdb_compat;  // Ziplist, listpack, intset and LZF decoding for Redis dumps
 pub mod monitor;
 pub mod aof;
+pub mod atomic_file;
 pub mod commands;
 pub mod lua_cache;
 pub mod lua_engine;  // Single-threaded Lua execution engine
+pub mod lua_functions;  // Persistent environment for FUNCTION libraries
+pub mod lua_utf8;  // Opt-in Lua 5.3-style utf8 library
+pub mod lua_cjson;  // cjson.encode/decode with Redis-compatible conventions
+pub mod lua_bit;  // LuaBitOp bit library
+pub mod lua_iter;  // redis.call_iter chunked iteration over large collections
+pub mod lua_record;  // Record/replay tapes of failed scripts
+pub mod lua_dirty;  // Per-script dirty set for WATCH invalidation
+pub mod lua_log;  // redis.log with per-script rate limiting
+pub mod lua_require;  // SCRIPT LOADLIB helper libraries resolved by require()
+pub mod lua_checkpoint;  // redis.checkpoint cooperative yielding for long scripts
+pub mod lua_vm;  // VM creation with the memory limit applied before the stdlib
+pub mod lua_stats;  // Per-VM execution counters for DEBUG LUA VMSTATS
+pub mod lua_budget;  // Instruction and call-depth limits and kills for script runs
+pub mod lua_quota;  // Per-user script quotas for ACL users
+pub mod lua_readonly;  // Read-only redis table and KEYS/ARGV proxies
+pub mod lua_busy;  // BUSY replies and SCRIPT KILL while a script overruns lua-time-limit
+pub mod lua_effects;  // Script effects propagated to replicas and the AOF
+pub mod lua_audit;  // Audit log of script writes with size-based rotation
+pub mod lua_triggers;  // Keyspace event triggers for FUNCTION libraries
+pub mod lua_invariants;  // Debug-build checks that script runs restore thread state
+pub mod lua_sandbox;  // Text-only chunk loading and frozen shared state for FUNCTION libraries
+pub mod lua_selftest;  // Startup conformance smoke suite behind --check-lua
+pub mod script_engine;  // Pluggable script engine trait and registry
 
-pub use engine::{StorageEngine, GetResult};
+pub use engine::{StorageEngine, GetResult, Snapshot};
 pub use value::Value;
 pub use rdb::{RdbEngine, RdbConfig};
 pub use monitor::StorageMonitor;
@@ -16,6 +16,9 @@ pub struct StorageMonitor {
     /// Last check time
     last_check_time: Arc<RwLock<SystemTime>>,
     
+    /// Save rules as (seconds, changes), changed by CONFIG SET save
+    save_rules: Arc<RwLock<Vec<(u64, u64)>>>,
+    
     /// Monitor thread handle
     monitor_handle: Option<thread::JoinHandle<()>>,
 }
@@ -26,29 +29,34 @@ impl StorageMonitor {
         Self {
             changes_since_save: Arc::new(AtomicU64::new(0)),
             last_check_time: Arc::new(RwLock::new(SystemTime::now())),
+            save_rules: Arc::new(RwLock::new(Vec::new())),
             monitor_handle: None,
         }
     }
     
     /// Start monitoring with given configuration
+    ///
+    /// The thread runs even with auto-save off, so that save rules set later
+    /// with [`StorageMonitor::set_save_rules`] take effect.
     pub fn start(
         &mut self,
         storage: Arc<StorageEngine>,
         rdb_engine: Arc<RdbEngine>,
         config: RdbConfig,
     ) {
-        if !config.auto_save {
-            return;
+        if config.auto_save {
+            self.set_save_rules(config.save_rules);
         }
         
         let changes_since_save = Arc::clone(&self.changes_since_save);
         let last_check_time = Arc::clone(&self.last_check_time);
+        let save_rules = Arc::clone(&self.save_rules);
         
         let handle = thread::spawn(move || {
             Self::monitor_loop(
                 storage,
                 rdb_engine,
-                config,
+                save_rules,
                 changes_since_save,
                 last_check_time,
             );
@@ -62,16 +70,26 @@ impl StorageMonitor {
         self.changes_since_save.fetch_add(1, Ordering::Relaxed);
     }
     
+    /// Changes since the last save (rdb_changes_since_last_save)
+    pub fn changes(&self) -> u64 {
+        self.changes_since_save.load(Ordering::Relaxed)
+    }
+    
     /// Reset change counter
     pub fn reset_changes(&self) {
         self.changes_since_save.store(0, Ordering::Relaxed);
     }
     
+    /// Replace the save rules (none disables auto-save)
+    pub fn set_save_rules(&self, rules: Vec<(u64, u64)>) {
+        *self.save_rules.write().unwrap() = rules;
+    }
+    
     /// Monitor loop
     fn monitor_loop(
         storage: Arc<StorageEngine>,
         rdb_engine: Arc<RdbEngine>,
-        config: RdbConfig,
+        save_rules: Arc<RwLock<Vec<(u64, u64)>>>,
         changes_since_save: Arc<AtomicU64>,
         last_check_time: Arc<RwLock<SystemTime>>,
     ) {
@@ -82,7 +100,8 @@ impl StorageMonitor {
             let changes = changes_since_save.load(Ordering::Relaxed);
             
             // Check save rules
-            for &(seconds, min_changes) in &config.save_rules {
+            let rules = save_rules.read().unwrap().clone();
+            for (seconds, min_changes) in rules {
                 if changes >= min_changes {
                     let last_check = last_check_time.read().unwrap();
                     let elapsed = now.duration_since(*last_check)
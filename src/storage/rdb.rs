@@ -4,22 +4,49 @@
 //! Supports both blocking (SAVE) and background (BGSAVE) operations.
 
 use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
-use std::io::{self, Read, Write, BufWriter, BufReader};
-use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::{self, Read, Write, BufReader};
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock, Mutex};
-use std::time::{SystemTime, UNIX_EPOCH, Duration};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH, Duration, Instant};
 use std::thread;
 
 use crate::error::{FerrousError, Result};
-use crate::storage::{StorageEngine, Value, GetResult};
+use crate::storage::{atomic_file, lua_require, rdb_compat, script_engine, StorageEngine, Snapshot, Value};
+use crate::storage::lua_engine::{get_lua_engine, lua_engine};
 
 /// RDB file version (Redis 9 compatible)
 const RDB_VERSION: u16 = 9;
 
+/// Newest RDB file version that can be loaded (Redis 7.4)
+const RDB_MAX_LOAD_VERSION: u16 = 12;
+
 /// RDB magic string
 const RDB_MAGIC: &[u8] = b"REDIS";
 
+/// Version of the scripting aux fields written by this build
+///
+/// Bump it when their encoding changes: a loader skips scripting fields whose
+/// version is newer than its own, and builds that predate them skip every
+/// unknown aux field.
+const SCRIPTING_AUX_VERSION: u32 = 1;
+
+/// Aux field marking a file written by Ferrous, holding the crate version
+const AUX_FERROUS_VERSION: &str = "ferrous-ver";
+
+/// Aux field holding the scripting fields version
+const AUX_SCRIPTING_VERSION: &str = "ferrous-scripting-ver";
+
+/// Aux field holding the source of one FUNCTION library
+const AUX_FUNCTION: &str = "ferrous-function";
+
+/// Aux field holding the source of one script cached by SCRIPT LOAD
+const AUX_SCRIPT: &str = "ferrous-script";
+
+/// Aux field holding one SCRIPT LOADLIB library as `<name>\n<source>`
+const AUX_LUA_LIBRARY: &str = "ferrous-lualib";
+
 /// RDB opcodes
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
@@ -36,6 +63,18 @@ enum RdbOpcode {
     ResizeDb = 0xFB,
     /// Auxiliary field
     Aux = 0xFA,
+    /// LFU frequency of the next key
+    Freq = 0xF9,
+    /// LRU idle time of the next key
+    Idle = 0xF8,
+    /// Auxiliary data of a module
+    ModuleAux = 0xF7,
+    /// FUNCTION library in the pre-release Redis 7 format
+    FunctionPreGa = 0xF6,
+    /// FUNCTION library source
+    Function2 = 0xF5,
+    /// Cluster slot size hint
+    SlotInfo = 0xF4,
     
     /// String encoding
     String = 0x00,
@@ -47,8 +86,187 @@ enum RdbOpcode {
     ZSet = 0x03,
     /// Hash encoding
     Hash = 0x04,
-    /// Sorted set with ziplist encoding
+    /// Sorted set with binary scores
     ZSet2 = 0x05,
+    /// Module value
+    Module2 = 0x07,
+    /// Hash as a zipmap (Redis before 2.6)
+    HashZipmap = 0x09,
+    /// List as a ziplist
+    ListZiplist = 0x0A,
+    /// Set of integers as an intset
+    SetIntset = 0x0B,
+    /// Sorted set as a ziplist
+    ZSetZiplist = 0x0C,
+    /// Hash as a ziplist
+    HashZiplist = 0x0D,
+    /// List as a quicklist of ziplists
+    ListQuicklist = 0x0E,
+    /// Stream as listpacks
+    StreamListpacks = 0x0F,
+    /// Hash as a listpack
+    HashListpack = 0x10,
+    /// Sorted set as a listpack
+    ZSetListpack = 0x11,
+    /// List as a quicklist of listpacks
+    ListQuicklist2 = 0x12,
+    /// Stream as listpacks, with consumer group read counters
+    StreamListpacks2 = 0x13,
+    /// Set as a listpack
+    SetListpack = 0x14,
+    /// Stream as listpacks, with consumer active times
+    StreamListpacks3 = 0x15,
+    /// Hash with per-field expiration times, in a layout of our own
+    ///
+    /// Kept clear of Redis's type ids, which reach 0x19 with its own hash
+    /// field TTL types, and of the opcodes from 0xF4 up.
+    HashMetadata = 0xE0,
+}
+
+/// Convert a monotonic field deadline to a unix timestamp in milliseconds
+fn field_expire_unix_ms(expires_at: Instant) -> u64 {
+    let remaining = expires_at.saturating_duration_since(Instant::now());
+    (SystemTime::now() + remaining).duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+/// Lookup table for the CRC64 (Jones polynomial, reflected) Redis uses in RDB files and DUMP payloads
+const CRC64_TABLE: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x95AC9329AC4BC9B5 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Continue a CRC64 over more bytes
+fn crc64(mut crc: u64, data: &[u8]) -> u64 {
+    for &byte in data {
+        crc = CRC64_TABLE[((crc ^ byte as u64) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// RDB type a value is written as
+fn object_type(value: &Value) -> RdbOpcode {
+    match value {
+        Value::String(_) => RdbOpcode::String,
+        Value::List(_) => RdbOpcode::List,
+        Value::Set(_) => RdbOpcode::Set,
+        Value::Hash(hash) if hash.has_field_ttls() => RdbOpcode::HashMetadata,
+        Value::Hash(_) => RdbOpcode::Hash,
+        Value::SortedSet(_) => RdbOpcode::ZSet2,
+        Value::Stream(_) => RdbOpcode::List, // Streams use List opcode with marker
+    }
+}
+
+/// Scripting state saved in aux fields alongside the keyspace
+///
+/// Captured up front by cloning sources out of the registries, so a save holds
+/// no scripting lock while it writes and scripts keep running meanwhile.
+#[derive(Default)]
+struct ScriptingSnapshot {
+    /// Version of the fields read, 0 when none were present
+    version: u32,
+    
+    /// FUNCTION library sources, for any engine
+    functions: Vec<String>,
+    
+    /// SCRIPT LOAD sources
+    scripts: Vec<Arc<str>>,
+    
+    /// SCRIPT LOADLIB libraries by name
+    libraries: Vec<(String, Arc<str>)>,
+}
+
+impl ScriptingSnapshot {
+    /// Capture the current scripting state
+    fn capture() -> Self {
+        let functions = script_engine::registered_engines().iter()
+            .flat_map(|engine| engine.libraries())
+            .map(|library| library.code)
+            .collect();
+        let scripts = lua_engine().map(|engine| engine.cached_scripts()).unwrap_or_default();
+        let libraries = lua_require::libraries().into_iter()
+            .filter_map(|name| lua_require::library(&name).map(|source| (name, source)))
+            .collect();
+        
+        Self { version: SCRIPTING_AUX_VERSION, functions, scripts, libraries }
+    }
+    
+    fn is_empty(&self) -> bool {
+        self.functions.is_empty() && self.scripts.is_empty() && self.libraries.is_empty()
+    }
+    
+    /// Aux fields to write, or none if there is no scripting state
+    fn aux_fields(&self) -> Vec<(&'static str, String)> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        
+        let mut fields = vec![(AUX_SCRIPTING_VERSION, self.version.to_string())];
+        fields.extend(self.libraries.iter().map(|(name, source)| (AUX_LUA_LIBRARY, format!("{}\n{}", name, source))));
+        fields.extend(self.functions.iter().map(|code| (AUX_FUNCTION, code.clone())));
+        fields.extend(self.scripts.iter().map(|source| (AUX_SCRIPT, source.to_string())));
+        fields
+    }
+    
+    /// Collect a scripting aux field read from a file, ignoring any other
+    fn read_aux_field(&mut self, key: &[u8], value: Vec<u8>) {
+        let value = String::from_utf8_lossy(&value);
+        match std::str::from_utf8(key) {
+            Ok(AUX_SCRIPTING_VERSION) => self.version = value.parse().unwrap_or(u32::MAX),
+            Ok(AUX_FUNCTION) => self.functions.push(value.into_owned()),
+            Ok(AUX_SCRIPT) => self.scripts.push(Arc::from(value.as_ref())),
+            Ok(AUX_LUA_LIBRARY) => {
+                if let Some((name, source)) = value.split_once('\n') {
+                    self.libraries.push((name.to_string(), Arc::from(source)));
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    /// Load the collected state into the scripting engines
+    ///
+    /// Libraries go first so functions that require() them at load time find them.
+    fn restore(self, storage: &Arc<StorageEngine>) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        if self.version > SCRIPTING_AUX_VERSION {
+            eprintln!("RDB: Skipping scripting state saved with unsupported version {}", self.version);
+            return Ok(());
+        }
+        
+        for (name, source) in &self.libraries {
+            lua_require::load_library(name, source)
+                .map_err(|e| FerrousError::Io(format!("Failed to restore Lua library '{}': {}", name, e)))?;
+        }
+        for code in &self.functions {
+            script_engine::library_engine(storage, code)
+                .and_then(|engine| engine.load_library(code, true))
+                .map_err(|e| FerrousError::Io(format!("Failed to restore function library: {}", e)))?;
+        }
+        if !self.scripts.is_empty() {
+            let engine = get_lua_engine(storage.clone())?;
+            for source in &self.scripts {
+                engine.script_load(source)
+                    .map_err(|e| FerrousError::Io(format!("Failed to restore cached script: {}", e)))?;
+            }
+        }
+        
+        println!("RDB: Restored {} function libraries, {} scripts and {} Lua libraries",
+                 self.functions.len(), self.scripts.len(), self.libraries.len());
+        Ok(())
+    }
 }
 
 /// RDB persistence engine
@@ -62,6 +280,9 @@ pub struct RdbEngine {
     /// Last save time
     last_save_time: Arc<RwLock<Option<SystemTime>>>,
     
+    /// Whether the last save succeeded (rdb_last_bgsave_status)
+    last_save_ok: Arc<AtomicBool>,
+    
     /// Configuration
     config: RdbConfig,
 }
@@ -111,6 +332,7 @@ impl RdbEngine {
             file_path,
             bgsave_in_progress: Arc::new(Mutex::new(false)),
             last_save_time: Arc::new(RwLock::new(None)),
+            last_save_ok: Arc::new(AtomicBool::new(true)),
             config,
         }
     }
@@ -134,20 +356,18 @@ impl RdbEngine {
     
     /// Perform blocking save
     pub fn save(&self, storage: &Arc<StorageEngine>) -> Result<()> {
-        // Note: We don't check bgsave_in_progress here because save() can be called
-        // from within bgsave() thread. The caller is responsible for managing concurrency.
-        
-        // Create temporary file
-        let temp_path = self.file_path.with_extension("tmp");
-        
-        println!("RDB: Starting dump to {}", temp_path.display());
-        
-        // Write to temporary file
-        self.write_snapshot(storage, &temp_path)?;
+        // Note: We don't check bgsave_in_progress here. The caller is responsible for managing concurrency.
+        self.save_snapshot(&storage.snapshot(), &ScriptingSnapshot::capture())
+    }
+    
+    /// Write a dataset captured earlier to the dump file
+    fn save_snapshot(&self, snapshot: &Snapshot, scripting: &ScriptingSnapshot) -> Result<()> {
+        println!("RDB: Starting dump to {}", self.file_path.display());
         
-        // Atomic rename
-        std::fs::rename(&temp_path, &self.file_path)
-            .map_err(|e| FerrousError::Io(format!("Failed to rename RDB file: {}", e)))?;
+        // Write to a temporary file, fsync it and rename it over the dump
+        let result = atomic_file::write_atomically(&self.file_path, |writer| self.write_snapshot(snapshot, scripting, writer));
+        self.last_save_ok.store(result.is_ok(), Ordering::Relaxed);
+        result?;
         
         // Update last save time
         {
@@ -160,6 +380,10 @@ impl RdbEngine {
     }
     
     /// Perform background save
+    ///
+    /// The dump holds the dataset as of the BGSAVE: writes that follow keep the
+    /// old values for the snapshot instead of ending up in the file, and the
+    /// keys are copied on the background thread.
     pub fn bgsave(&self, storage: Arc<StorageEngine>) -> Result<()> {
         // Check if background save is already in progress
         {
@@ -173,12 +397,14 @@ impl RdbEngine {
         }
         
         let engine = self.clone();
+        let snapshot = storage.snapshot();
+        let scripting = ScriptingSnapshot::capture();
         
         // Spawn background thread
         thread::spawn(move || {
             println!("RDB: Background saving started");
             
-            match engine.save(&storage) {
+            match engine.save_snapshot(&snapshot, &scripting) {
                 Ok(_) => println!("RDB: Background saving terminated with success"),
                 Err(e) => eprintln!("RDB: Background saving error: {}", e),
             }
@@ -203,6 +429,11 @@ impl RdbEngine {
         *last_save
     }
     
+    /// Whether the last SAVE or BGSAVE succeeded
+    pub fn last_save_ok(&self) -> bool {
+        self.last_save_ok.load(Ordering::Relaxed)
+    }
+    
     /// Generate RDB bytes for replication
     pub fn generate_rdb_bytes(&self, storage: &Arc<StorageEngine>) -> Result<Vec<u8>> {
         let mut buffer = Vec::new();
@@ -213,6 +444,7 @@ impl RdbEngine {
         
         // Write metadata
         self.write_aux_field(&mut buffer, "redis-ver", env!("CARGO_PKG_VERSION"))?;
+        self.write_aux_field(&mut buffer, AUX_FERROUS_VERSION, env!("CARGO_PKG_VERSION"))?;
         self.write_aux_field(&mut buffer, "redis-bits", "64")?;
         self.write_aux_field(&mut buffer, "ctime", &SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -220,11 +452,15 @@ impl RdbEngine {
             .as_secs()
             .to_string())?;
         self.write_aux_field(&mut buffer, "used-mem", &storage.memory_usage().to_string())?;
+        for (key, value) in ScriptingSnapshot::capture().aux_fields() {
+            self.write_aux_field(&mut buffer, key, &value)?;
+        }
         
-        // Write databases
-        for db in 0..storage.database_count() {
-            let keys = storage.get_all_keys(db)?;
-            if keys.is_empty() {
+        // Write databases from one point-in-time copy
+        let snapshot = storage.snapshot();
+        for db in 0..snapshot.database_count() {
+            let entries = snapshot.database(db);
+            if entries.is_empty() {
                 continue;
             }
             
@@ -234,117 +470,114 @@ impl RdbEngine {
             
             // Resize DB opcode
             buffer.push(RdbOpcode::ResizeDb as u8);
-            self.write_length(&mut buffer, keys.len())?;
+            self.write_length(&mut buffer, entries.len())?;
             self.write_length(&mut buffer, 0)?; // No separate expires hash
             
             // Write all key-value pairs
-            for key in keys {
-                if let GetResult::Found(value) = storage.get(db, &key)? {
-                    // Check for expiration
-                    let expire_time = storage.ttl(db, &key)?
-                        .map(|ttl| SystemTime::now() + ttl);
-                    
-                    // Write expiration if present
-                    if let Some(expire) = expire_time {
-                        buffer.push(RdbOpcode::ExpireTimeMs as u8);
-                        let timestamp = expire.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
-                        buffer.extend_from_slice(&timestamp.to_le_bytes());
-                    }
-                    
-                    // Write value type
-                    match value {
-                        Value::String(_) => buffer.push(RdbOpcode::String as u8),
-                        Value::List(_) => buffer.push(RdbOpcode::List as u8),
-                        Value::Set(_) => buffer.push(RdbOpcode::Set as u8),
-                        Value::Hash(_) => buffer.push(RdbOpcode::Hash as u8),
-                        Value::SortedSet(_) => buffer.push(RdbOpcode::ZSet as u8),
-                        Value::Stream(_) => buffer.push(RdbOpcode::List as u8), // Streams use List opcode with marker
+            for entry in entries {
+                let (key, value) = (&entry.key, &entry.value);
+                
+                // Check for expiration
+                let expire_time = entry.ttl().map(|ttl| SystemTime::now() + ttl);
+                
+                // Write expiration if present
+                if let Some(expire) = expire_time {
+                    buffer.push(RdbOpcode::ExpireTimeMs as u8);
+                    let timestamp = expire.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+                    buffer.extend_from_slice(&timestamp.to_le_bytes());
+                }
+                
+                // Write value type
+                buffer.push(object_type(value) as u8);
+                
+                // Write key
+                self.write_length(&mut buffer, key.len())?;
+                buffer.extend_from_slice(key);
+                
+                // Write value
+                match value {
+                    Value::String(bytes) => {
+                        self.write_length(&mut buffer, bytes.len())?;
+                        buffer.extend_from_slice(bytes.as_ref());
                     }
-                    
-                    // Write key
-                    self.write_length(&mut buffer, key.len())?;
-                    buffer.extend_from_slice(&key);
-                    
-                    // Write value
-                    match value {
-                        Value::String(bytes) => {
-                            self.write_length(&mut buffer, bytes.len())?;
-                            buffer.extend_from_slice(bytes.as_ref());
+                    Value::List(list) => {
+                        self.write_length(&mut buffer, list.len())?;
+                        for item in list.iter() {
+                            self.write_length(&mut buffer, item.len())?;
+                            buffer.extend_from_slice(item);
                         }
-                        Value::List(list) => {
-                            self.write_length(&mut buffer, list.len())?;
-                            for item in list {
-                                self.write_length(&mut buffer, item.len())?;
-                                buffer.extend_from_slice(&item);
-                            }
+                    }
+                    Value::Set(set) => {
+                        self.write_length(&mut buffer, set.len())?;
+                        for member in set.members() {
+                            self.write_length(&mut buffer, member.len())?;
+                            buffer.extend_from_slice(&member);
                         }
-                        Value::Set(set) => {
-                            self.write_length(&mut buffer, set.len())?;
-                            for member in set {
-                                self.write_length(&mut buffer, member.len())?;
-                                buffer.extend_from_slice(&member);
+                    }
+                    Value::Hash(hash) => {
+                        let with_ttls = hash.has_field_ttls();
+                        self.write_length(&mut buffer, hash.len())?;
+                        for (field, value) in hash {
+                            self.write_length(&mut buffer, field.len())?;
+                            buffer.extend_from_slice(field);
+                            self.write_length(&mut buffer, value.len())?;
+                            buffer.extend_from_slice(value);
+                            if with_ttls {
+                                let expire_ms = hash.field_expiration(field).map(field_expire_unix_ms).unwrap_or(0);
+                                buffer.extend_from_slice(&expire_ms.to_le_bytes());
                             }
                         }
-                        Value::Hash(hash) => {
-                            self.write_length(&mut buffer, hash.len())?;
-                            for (field, value) in hash {
-                                self.write_length(&mut buffer, field.len())?;
-                                buffer.extend_from_slice(&field);
-                                self.write_length(&mut buffer, value.len())?;
-                                buffer.extend_from_slice(&value);
-                            }
+                    }
+                    Value::SortedSet(zset) => {
+                        let items = zset.items();
+                        self.write_length(&mut buffer, items.len())?;
+                        for (member, score) in items {
+                            self.write_length(&mut buffer, member.len())?;
+                            buffer.extend_from_slice(&member);
+                            buffer.extend_from_slice(&score.to_le_bytes());
                         }
-                        Value::SortedSet(skiplist) => {
-                            let items = skiplist.range_by_rank(0, skiplist.len() - 1).items;
-                            self.write_length(&mut buffer, items.len())?;
-                            for (member, score) in items {
-                                self.write_length(&mut buffer, member.len())?;
-                                buffer.extend_from_slice(&member);
-                                buffer.extend_from_slice(&score.to_le_bytes());
-                            }
+                    }
+                    Value::Stream(stream) => {
+                        // Get all stream entries for replication
+                        let range_result = stream.range(
+                            &crate::storage::stream::StreamId::min(),
+                            &crate::storage::stream::StreamId::max(),
+                            None,
+                            false
+                        );
+                        
+                        let entries = &range_result.entries;
+                        // Calculate correct total items: 1 marker + sum(2 + 2*field_count) for each entry
+                        let mut total_items = 1; // Stream marker
+                        for entry in entries {
+                            total_items += 2; // ID string + field count string
+                            total_items += entry.fields.len() * 2; // field-value pairs
                         }
-                        Value::Stream(stream) => {
-                            // Get all stream entries for replication
-                            let range_result = stream.range(
-                                &crate::storage::stream::StreamId::min(),
-                                &crate::storage::stream::StreamId::max(),
-                                None,
-                                false
-                            );
-                            
-                            let entries = &range_result.entries;
-                            // Calculate correct total items: 1 marker + sum(2 + 2*field_count) for each entry
-                            let mut total_items = 1; // Stream marker
-                            for entry in entries {
-                                total_items += 2; // ID string + field count string
-                                total_items += entry.fields.len() * 2; // field-value pairs
-                            }
-                            self.write_length(&mut buffer, total_items)?;
+                        self.write_length(&mut buffer, total_items)?;
+                        
+                        // Write stream marker
+                        let marker = b"__FERROUS_STREAM_MARKER__";
+                        self.write_length(&mut buffer, marker.len())?;
+                        buffer.extend_from_slice(marker);
+                        
+                        // Write each entry
+                        for entry in entries {
+                            // Write entry ID
+                            let id_str = entry.id.to_string();
+                            self.write_length(&mut buffer, id_str.len())?;
+                            buffer.extend_from_slice(id_str.as_bytes());
                             
-                            // Write stream marker
-                            let marker = b"__FERROUS_STREAM_MARKER__";
-                            self.write_length(&mut buffer, marker.len())?;
-                            buffer.extend_from_slice(marker);
+                            // Write field count
+                            let field_count_str = entry.fields.len().to_string();
+                            self.write_length(&mut buffer, field_count_str.len())?;
+                            buffer.extend_from_slice(field_count_str.as_bytes());
                             
-                            // Write each entry
-                            for entry in entries {
-                                // Write entry ID
-                                let id_str = entry.id.to_string();
-                                self.write_length(&mut buffer, id_str.len())?;
-                                buffer.extend_from_slice(id_str.as_bytes());
-                                
-                                // Write field count
-                                let field_count_str = entry.fields.len().to_string();
-                                self.write_length(&mut buffer, field_count_str.len())?;
-                                buffer.extend_from_slice(field_count_str.as_bytes());
-                                
-                                // Write field-value pairs
-                                for (field, value) in &entry.fields {
-                                    self.write_length(&mut buffer, field.len())?;
-                                    buffer.extend_from_slice(field);
-                                    self.write_length(&mut buffer, value.len())?;
-                                    buffer.extend_from_slice(value);
-                                }
+                            // Write field-value pairs
+                            for (field, value) in &entry.fields {
+                                self.write_length(&mut buffer, field.len())?;
+                                buffer.extend_from_slice(field);
+                                self.write_length(&mut buffer, value.len())?;
+                                buffer.extend_from_slice(value);
                             }
                         }
                     }
@@ -355,8 +588,8 @@ impl RdbEngine {
         // Write EOF
         buffer.push(RdbOpcode::Eof as u8);
         
-        // Write checksum (simplified)
-        let checksum: u64 = 0; // Real implementation would calculate CRC64
+        // Write checksum
+        let checksum = crc64(0, &buffer);
         buffer.extend_from_slice(&checksum.to_le_bytes());
         
         println!("RDB: Generated {} bytes for replication", buffer.len());
@@ -402,16 +635,9 @@ impl RdbEngine {
         Ok(())
     }
     
-    /// Write snapshot to file
-    fn write_snapshot(&self, storage: &Arc<StorageEngine>, path: &Path) -> Result<()> {
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(path)
-            .map_err(|e| FerrousError::Io(format!("Failed to create RDB file: {}", e)))?;
-        
-        let mut writer = RdbWriter::new(BufWriter::new(file));
+    /// Write snapshot to an open file
+    fn write_snapshot<W: Write>(&self, snapshot: &Snapshot, scripting: &ScriptingSnapshot, out: W) -> Result<()> {
+        let mut writer = RdbWriter::new(out);
         
         // Write header
         writer.write_header()?;
@@ -419,33 +645,23 @@ impl RdbEngine {
         // Write metadata
         writer.write_metadata()?;
         
+        // Write scripting state
+        writer.write_scripting(scripting)?;
+        
         // Write databases
-        for db_idx in 0..storage.database_count() {
-            // Get all keys from database
-            let keys = storage.get_all_keys(db_idx)?;
+        for db_idx in 0..snapshot.database_count() {
+            let entries = snapshot.database(db_idx);
             
-            if !keys.is_empty() {
+            if !entries.is_empty() {
                 // Write database selector
                 writer.write_db_selector(db_idx)?;
                 
                 // Write resize hint
-                writer.write_resize_db(keys.len(), keys.len())?;
+                writer.write_resize_db(entries.len(), entries.len())?;
                 
                 // Write each key-value pair
-                for key in keys {
-                    // Get value
-                    match storage.get(db_idx, &key)? {
-                        GetResult::Found(value) => {
-                            // Get TTL if any
-                            let ttl = storage.ttl(db_idx, &key)?;
-                            
-                            // Write key-value pair
-                            writer.write_key_value(&key, &value, ttl)?;
-                        }
-                        _ => {
-                            // Key doesn't exist or expired, skip
-                        }
-                    }
+                for entry in entries {
+                    writer.write_key_value(&entry.key, &entry.value, entry.ttl())?;
                 }
             }
         }
@@ -469,11 +685,64 @@ impl Clone for RdbEngine {
             file_path: self.file_path.clone(),
             bgsave_in_progress: Arc::clone(&self.bgsave_in_progress),
             last_save_time: Arc::clone(&self.last_save_time),
+            last_save_ok: Arc::clone(&self.last_save_ok),
             config: self.config.clone(),
         }
     }
 }
 
+/// Serialize a value as a DUMP payload
+///
+/// The payload is the value's RDB type and encoding followed by the RDB version
+/// (2 bytes, little-endian) and a CRC64 of everything before it, as Redis writes it.
+pub fn dump_payload(value: &Value) -> Vec<u8> {
+    let mut writer = RdbWriter::new(Vec::new());
+    writer.write_byte(object_type(value) as u8)
+        .and_then(|_| writer.write_object(value))
+        .expect("writing to a Vec cannot fail");
+    
+    let mut payload = writer.writer;
+    payload.extend_from_slice(&RDB_VERSION.to_le_bytes());
+    let checksum = crc64(0, &payload);
+    payload.extend_from_slice(&checksum.to_le_bytes());
+    payload
+}
+
+/// Check the footer of a DUMP payload, returning its RDB version and the value it holds
+pub fn verify_payload(payload: &[u8]) -> Option<(u16, &[u8])> {
+    let value_len = payload.len().checked_sub(10)?;
+    let (value, footer) = payload.split_at(value_len);
+    let version = u16::from_le_bytes([footer[0], footer[1]]);
+    let checksum = u64::from_le_bytes(footer[2..].try_into().unwrap());
+    
+    if version > RDB_MAX_LOAD_VERSION || checksum != crc64(0, &payload[..payload.len() - 8]) {
+        return None;
+    }
+    Some((version, value))
+}
+
+/// Store the value of a verified DUMP payload at a key that doesn't exist
+///
+/// Payloads of this build's RDB version are read the way Ferrous writes them, as
+/// Redis never used the type codes the two disagree on in that version. A value
+/// that doesn't parse fails with an I/O error and leaves the key unset.
+pub fn restore_payload(storage: &Arc<StorageEngine>, db: usize, key: Vec<u8>, version: u16, value: &[u8]) -> Result<()> {
+    let (&value_type, encoding) = value.split_first()
+        .ok_or_else(|| FerrousError::Io("Empty DUMP payload".to_string()))?;
+    
+    let mut reader = RdbReader::new(encoding);
+    reader.dialect = if version == RDB_VERSION { RdbDialect::Ferrous } else { RdbDialect::Redis };
+    
+    match reader.read_object(storage, db, key.clone(), value_type) {
+        Ok(Some(_)) if reader.reader.is_empty() => Ok(()),
+        stored => {
+            storage.delete(db, &key)?;
+            stored?;
+            Err(FerrousError::Io("Malformed DUMP payload".to_string()))
+        }
+    }
+}
+
 /// RDB file writer
 struct RdbWriter<W: Write> {
     writer: W,
@@ -506,6 +775,7 @@ impl<W: Write> RdbWriter<W> {
     fn write_metadata(&mut self) -> io::Result<()> {
         // Redis version
         self.write_aux("redis-ver", env!("CARGO_PKG_VERSION"))?;
+        self.write_aux(AUX_FERROUS_VERSION, env!("CARGO_PKG_VERSION"))?;
         
         // Creation time
         let now = SystemTime::now()
@@ -525,6 +795,14 @@ impl<W: Write> RdbWriter<W> {
         Ok(())
     }
     
+    /// Write scripting state as aux fields
+    fn write_scripting(&mut self, scripting: &ScriptingSnapshot) -> io::Result<()> {
+        for (key, value) in scripting.aux_fields() {
+            self.write_aux(key, &value)?;
+        }
+        Ok(())
+    }
+    
     /// Write database selector
     fn write_db_selector(&mut self, db: usize) -> io::Result<()> {
         self.write_byte(RdbOpcode::SelectDb as u8)?;
@@ -553,25 +831,26 @@ impl<W: Write> RdbWriter<W> {
             self.write_u64_le(expiry_ms)?;
         }
         
-        // Write value type and key
+        // Write value type and key, then the value
+        self.write_byte(object_type(value) as u8)?;
+        self.write_string(key)?;
+        self.write_object(value)
+    }
+    
+    /// Write a value in its RDB encoding, after its type
+    fn write_object(&mut self, value: &Value) -> io::Result<()> {
         match value {
             Value::String(bytes) => {
-                self.write_byte(RdbOpcode::String as u8)?;
-                self.write_string(key)?;
                 self.write_string(bytes)?;
             }
-            Value::SortedSet(skiplist) => {
-                self.write_byte(RdbOpcode::ZSet as u8)?;
-                self.write_string(key)?;
-                
+            Value::SortedSet(zset) => {
                 // Get all items and write them
-                let len = skiplist.len();
-                self.write_length(len)?;
+                self.write_length(zset.len())?;
                 
                 // Note: This is a suboptimal approach since we need to materialize
                 // all members in memory. A better approach would be to have a streaming
                 // iterator in the SkipList implementation.
-                let items = skiplist.range_by_rank(0, len - 1).items;
+                let items = zset.items();
                 
                 for (member, score) in items {
                     self.write_string(&member)?;
@@ -580,9 +859,6 @@ impl<W: Write> RdbWriter<W> {
             }
             Value::Stream(stream) => {
                 // Serialize streams properly by saving all entries
-                self.write_byte(RdbOpcode::List as u8)?;
-                self.write_string(key)?;
-                
                 // Use XRANGE to get all stream entries
                 let range_result = stream.range(
                     &crate::storage::stream::StreamId::min(),
@@ -621,33 +897,36 @@ impl<W: Write> RdbWriter<W> {
                 }
             }
             Value::List(list) => {
-                self.write_byte(RdbOpcode::List as u8)?;
-                self.write_string(key)?;
-                
                 // Write list length
                 self.write_length(list.len())?;
                 
                 // Write each list element
-                for item in list {
+                for item in list.iter() {
                     self.write_string(item)?;
                 }
             }
             Value::Set(set) => {
-                self.write_byte(RdbOpcode::Set as u8)?;
-                self.write_string(key)?;
-                
                 // Write set size
                 self.write_length(set.len())?;
                 
                 // Write each set member
-                for member in set {
-                    self.write_string(member)?;
+                for member in set.members() {
+                    self.write_string(&member)?;
                 }
             }
-            Value::Hash(hash) => {
-                self.write_byte(RdbOpcode::Hash as u8)?;
-                self.write_string(key)?;
+            Value::Hash(hash) if hash.has_field_ttls() => {
+                // Write hash size
+                self.write_length(hash.len())?;
                 
+                // Write each field-value pair followed by its expiry (0 = no TTL)
+                for (field, value) in hash {
+                    self.write_string(field)?;
+                    self.write_string(value)?;
+                    let expire_ms = hash.field_expiration(field).map(field_expire_unix_ms).unwrap_or(0);
+                    self.write_u64_le(expire_ms)?;
+                }
+            }
+            Value::Hash(hash) => {
                 // Write hash size
                 self.write_length(hash.len())?;
                 
@@ -670,7 +949,6 @@ impl<W: Write> RdbWriter<W> {
     
     /// Write CRC64 checksum
     fn write_checksum(&mut self) -> io::Result<()> {
-        // For now, write a dummy checksum
         self.write_u64_le(self.crc)?;
         Ok(())
     }
@@ -684,10 +962,7 @@ impl<W: Write> RdbWriter<W> {
     fn write_raw(&mut self, data: &[u8]) -> io::Result<()> {
         self.writer.write_all(data)?;
         self.bytes_written += data.len() as u64;
-        // Update CRC (simplified - real implementation would use CRC64)
-        for &byte in data {
-            self.crc = self.crc.wrapping_add(byte as u64);
-        }
+        self.crc = crc64(self.crc, data);
         Ok(())
     }
     
@@ -742,14 +1017,49 @@ impl<W: Write> RdbWriter<W> {
     }
 }
 
+/// Writer a file being read comes from
+///
+/// Ferrous and Redis disagree on two type codes: Ferrous writes sorted sets with
+/// binary scores under type 3, and hashes with field TTLs under type 24.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RdbDialect {
+    Ferrous,
+    Redis,
+}
+
+/// Name of a Redis module from its 64-bit type id
+///
+/// The top 54 bits hold nine 6-bit characters; the low 10 bits are the encoding version.
+fn module_name(id: u64) -> String {
+    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    (0..9).map(|i| CHARSET[((id >> (10 + (8 - i) * 6)) & 0x3F) as usize] as char).collect()
+}
+
+/// Current unix time in milliseconds
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
 /// RDB file reader
 struct RdbReader<R: Read> {
     reader: R,
+    
+    /// Format version from the header
+    version: u16,
+    
+    /// Writer of the file, known from its aux fields
+    dialect: RdbDialect,
+    
+    /// Scripting state read from aux fields, restored once the file is read
+    scripting: ScriptingSnapshot,
 }
 
 impl<R: Read> RdbReader<R> {
     fn new(reader: R) -> Self {
-        Self { reader }
+        Self { reader, version: 0, dialect: RdbDialect::Redis, scripting: ScriptingSnapshot::default() }
     }
     
     /// Load RDB file into storage engine
@@ -759,13 +1069,20 @@ impl<R: Read> RdbReader<R> {
         
         let mut current_db = 0;
         
+        // Expiry and access hints read for the next key, in any order
+        let mut expiry_ms = None;
+        let mut idle = None;
+        let mut frequency = None;
+        
         loop {
             let opcode = self.read_byte()?;
             
             match opcode {
                 op if op == RdbOpcode::Eof as u8 => {
-                    // Read checksum and we're done
-                    let _checksum = self.read_u64_le()?;
+                    // Read checksum (version 5 onwards) and we're done
+                    if self.version >= 5 {
+                        let _checksum = self.read_u64_le()?;
+                    }
                     break;
                 }
                 op if op == RdbOpcode::SelectDb as u8 => {
@@ -776,29 +1093,57 @@ impl<R: Read> RdbReader<R> {
                     let _db_size = self.read_length()?;
                     let _expires_size = self.read_length()?;
                 }
+                op if op == RdbOpcode::SlotInfo as u8 => {
+                    // Slot id, slot size and expires size (cluster hint, ignored)
+                    for _ in 0..3 {
+                        self.read_length()?;
+                    }
+                }
                 op if op == RdbOpcode::Aux as u8 => {
-                    // Read auxiliary field (we can ignore these)
-                    let _key = self.read_string()?;
-                    let _value = self.read_string()?;
+                    // Read auxiliary field (only the writer and scripting state are kept)
+                    let key = self.read_string()?;
+                    let value = self.read_string()?;
+                    self.read_dialect(&key, &value);
+                    self.scripting.read_aux_field(&key, value);
+                }
+                op if op == RdbOpcode::Function2 as u8 => {
+                    let code = self.read_string()?;
+                    self.scripting.functions.push(String::from_utf8_lossy(&code).into_owned());
+                }
+                op if op == RdbOpcode::FunctionPreGa as u8 => {
+                    return Err(FerrousError::Io("Pre-release Redis 7 function format is not supported".to_string()));
+                }
+                op if op == RdbOpcode::ModuleAux as u8 => {
+                    let id = self.read_u64_length()?;
+                    let _when_opcode = self.read_length()?;
+                    let _when = self.read_length()?;
+                    self.skip_module_value()?;
+                    eprintln!("RDB: Skipping aux data of module '{}'", module_name(id));
+                }
+                op if op == RdbOpcode::Idle as u8 => {
+                    idle = Some(Duration::from_secs(self.read_length()? as u64));
+                }
+                op if op == RdbOpcode::Freq as u8 => {
+                    frequency = Some(self.read_byte()?);
                 }
                 op if op == RdbOpcode::ExpireTimeMs as u8 => {
-                    // Read expiry time and then the key-value
-                    let expiry_ms = self.read_u64_le()?;
-                    self.read_key_value_with_expiry(storage, current_db, expiry_ms)?;
+                    expiry_ms = Some(self.read_u64_le()?);
                 }
                 op if op == RdbOpcode::ExpireTimeS as u8 => {
-                    // Read expiry time in seconds and then the key-value
-                    let expiry_s = self.read_u32_le()? as u64;
-                    self.read_key_value_with_expiry(storage, current_db, expiry_s * 1000)?;
+                    expiry_ms = Some(self.read_u32_le()? as u64 * 1000);
                 }
                 _ => {
                     // This is a value type opcode
-                    self.read_key_value_with_type(storage, current_db, opcode, None)?;
+                    let (expiry_ms, idle, frequency) = (expiry_ms.take(), idle.take(), frequency.take());
+                    if let Some(key) = self.read_key_value_with_type(storage, current_db, opcode)? {
+                        storage.set_access_stats(current_db, &key, idle, frequency)?;
+                        Self::apply_expiry(storage, current_db, &key, expiry_ms)?;
+                    }
                 }
             }
         }
         
-        Ok(())
+        std::mem::take(&mut self.scripting).restore(storage)
     }
     
     /// Read and verify header
@@ -815,59 +1160,84 @@ impl<R: Read> RdbReader<R> {
         
         // Parse version
         let version_str = String::from_utf8_lossy(&version);
-        let _version_num = version_str.parse::<u16>()
+        self.version = version_str.parse::<u16>()
             .map_err(|_| FerrousError::Io("Invalid RDB version".to_string()))?;
+        if self.version == 0 || self.version > RDB_MAX_LOAD_VERSION {
+            return Err(FerrousError::Io(format!("Can't handle RDB format version {}", self.version)));
+        }
         
         Ok(())
     }
     
-    /// Read key-value with expiry
-    fn read_key_value_with_expiry(&mut self, storage: &Arc<StorageEngine>, db: usize, expiry_ms: u64) -> Result<()> {
-        let value_type = self.read_byte()?;
-        
-        let now_ms = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
-        
-        let ttl = if expiry_ms > now_ms {
-            Some(Duration::from_millis(expiry_ms - now_ms))
-        } else {
-            None // Already expired
+    /// Tell Ferrous files from Redis ones by their aux fields
+    ///
+    /// Files from before the `ferrous-ver` field carry the crate version, still 0.x,
+    /// as `redis-ver`.
+    fn read_dialect(&mut self, key: &[u8], value: &[u8]) {
+        let ferrous = match key {
+            k if k == AUX_FERROUS_VERSION.as_bytes() => true,
+            b"redis-ver" => value.starts_with(b"0."),
+            _ => false,
+        };
+        if ferrous {
+            self.dialect = RdbDialect::Ferrous;
+        }
+    }
+    
+    /// Give a loaded key its expiry, dropping it if that has already passed
+    fn apply_expiry(storage: &Arc<StorageEngine>, db: usize, key: &[u8], expiry_ms: Option<u64>) -> Result<()> {
+        let Some(expiry_ms) = expiry_ms else {
+            return Ok(());
         };
         
-        self.read_key_value_with_type(storage, db, value_type, ttl)
+        let now_ms = unix_ms();
+        if expiry_ms > now_ms {
+            storage.expire(db, key, Duration::from_millis(expiry_ms - now_ms))?;
+        } else {
+            storage.delete(db, key)?;
+        }
+        Ok(())
     }
     
     /// Read key-value with known type
-    fn read_key_value_with_type(&mut self, storage: &Arc<StorageEngine>, db: usize, value_type: u8, ttl: Option<Duration>) -> Result<()> {
+    fn read_key_value_with_type(&mut self, storage: &Arc<StorageEngine>, db: usize, value_type: u8) -> Result<Option<Vec<u8>>> {
+        let key = self.read_string()?;
+        self.read_object(storage, db, key, value_type)
+    }
+    
+    /// Read a value of known type and store it at a key
+    ///
+    /// Returns the key stored, or None when nothing was (empty values, skipped module types).
+    fn read_object(&mut self, storage: &Arc<StorageEngine>, db: usize, key: Vec<u8>, value_type: u8) -> Result<Option<Vec<u8>>> {
         match value_type {
             op if op == RdbOpcode::String as u8 => {
-                let key = self.read_string()?;
                 let value = self.read_string()?;
-                
-                if let Some(ttl) = ttl {
-                    storage.set_string_ex(db, key, value, ttl)?;
-                } else {
-                    storage.set_string(db, key, value)?;
-                }
+                storage.set_string(db, key.clone(), value)?;
             }
-            op if op == RdbOpcode::ZSet as u8 || op == RdbOpcode::ZSet2 as u8 => {
-                let key = self.read_string()?;
+            op if (op == RdbOpcode::ZSet as u8 && self.dialect == RdbDialect::Ferrous) || op == RdbOpcode::ZSet2 as u8 => {
                 let count = self.read_length()?;
                 
+                let mut members = Vec::new();
                 for _ in 0..count {
                     let member = self.read_string()?;
                     let score = self.read_f64()?;
-                    storage.zadd(db, key.clone(), member, score)?;
+                    members.push((member, score));
                 }
+                return store_zset(storage, db, key, members);
+            }
+            op if op == RdbOpcode::ZSet as u8 => {
+                // Redis sorted sets with scores written as text
+                let count = self.read_length()?;
                 
-                if let Some(ttl) = ttl {
-                    storage.expire(db, &key, ttl)?;
+                let mut members = Vec::new();
+                for _ in 0..count {
+                    let member = self.read_string()?;
+                    let score = self.read_string_score()?;
+                    members.push((member, score));
                 }
+                return store_zset(storage, db, key, members);
             }
             op if op == RdbOpcode::List as u8 => {
-                let key = self.read_string()?;
                 let count = self.read_length()?;
                 
                 // Check if this is a stream marker
@@ -917,31 +1287,20 @@ impl<R: Read> RdbReader<R> {
                                 let _ = storage.xadd_with_id(db, key.clone(), stream_id, fields);
                             }
                         }
-                        
-                        if let Some(ttl) = ttl {
-                            storage.expire(db, &key, ttl)?;
-                        }
-                        return Ok(());
                     } else {
                         // Regular list - first element already read
-                        storage.rpush(db, key.clone(), vec![first_element])?;
-                        
-                        // Read remaining list elements
+                        let mut elements = vec![first_element];
                         for _ in 1..count {
-                            let element = self.read_string()?;
-                            storage.rpush(db, key.clone(), vec![element])?;
+                            elements.push(self.read_string()?);
                         }
+                        return store_list(storage, db, key, elements);
                     }
                 } else {
                     // Empty list - do nothing
-                }
-                
-                if let Some(ttl) = ttl {
-                    storage.expire(db, &key, ttl)?;
+                    return Ok(None);
                 }
             }
             op if op == RdbOpcode::Set as u8 => {
-                let key = self.read_string()?;
                 let count = self.read_length()?;
                 
                 // Read all set members
@@ -949,14 +1308,9 @@ impl<R: Read> RdbReader<R> {
                 for _ in 0..count {
                     members.push(self.read_string()?);
                 }
-                storage.sadd(db, key.clone(), members)?;
-                
-                if let Some(ttl) = ttl {
-                    storage.expire(db, &key, ttl)?;
-                }
+                return store_set(storage, db, key, members);
             }
             op if op == RdbOpcode::Hash as u8 => {
-                let key = self.read_string()?;
                 let count = self.read_length()?;
                 
                 // Read all hash field-value pairs
@@ -966,11 +1320,112 @@ impl<R: Read> RdbReader<R> {
                     let value = self.read_string()?;
                     field_values.push((field, value));
                 }
+                return store_hash(storage, db, key, field_values);
+            }
+            op if op == RdbOpcode::HashMetadata as u8 && self.dialect == RdbDialect::Ferrous => {
+                let count = self.read_length()?;
+                
+                let now_ms = unix_ms();
+                
+                // Read field-value pairs with expiry, dropping fields that already expired
+                let mut field_values = Vec::new();
+                let mut field_ttls = Vec::new();
+                for _ in 0..count {
+                    let field = self.read_string()?;
+                    let value = self.read_string()?;
+                    let expire_ms = self.read_u64_le()?;
+                    
+                    if expire_ms != 0 {
+                        if expire_ms <= now_ms {
+                            continue;
+                        }
+                        field_ttls.push((field.clone(), Duration::from_millis(expire_ms - now_ms)));
+                    }
+                    field_values.push((field, value));
+                }
+                
+                if field_values.is_empty() {
+                    return Ok(None);
+                }
                 storage.hset(db, key.clone(), field_values)?;
                 
-                if let Some(ttl) = ttl {
-                    storage.expire(db, &key, ttl)?;
+                for (field, remaining) in field_ttls {
+                    storage.hexpire(db, &key, &[field], Instant::now() + remaining, None)?;
+                }
+            }
+            op if op == RdbOpcode::Module2 as u8 => {
+                let id = self.read_u64_length()?;
+                self.skip_module_value()?;
+                eprintln!("RDB: Skipping key '{}' of module type '{}'",
+                          String::from_utf8_lossy(&key), module_name(id));
+                return Ok(None);
+            }
+            op if op == RdbOpcode::HashZipmap as u8 => {
+                let pairs = rdb_compat::zipmap_pairs(&self.read_string()?)?;
+                return store_hash(storage, db, key, pairs);
+            }
+            op if op == RdbOpcode::ListZiplist as u8 => {
+                let elements = rdb_compat::ziplist_entries(&self.read_string()?)?;
+                return store_list(storage, db, key, elements);
+            }
+            op if op == RdbOpcode::SetIntset as u8 => {
+                let members = rdb_compat::intset_members(&self.read_string()?)?;
+                return store_set(storage, db, key, members);
+            }
+            op if op == RdbOpcode::SetListpack as u8 => {
+                let members = rdb_compat::listpack_entries(&self.read_string()?)?;
+                return store_set(storage, db, key, members);
+            }
+            op if op == RdbOpcode::ZSetZiplist as u8 || op == RdbOpcode::ZSetListpack as u8 => {
+                let blob = self.read_string()?;
+                let entries = if op == RdbOpcode::ZSetZiplist as u8 {
+                    rdb_compat::ziplist_entries(&blob)?
+                } else {
+                    rdb_compat::listpack_entries(&blob)?
+                };
+                let members = rdb_compat::pairs(entries, "sorted set")?.into_iter()
+                    .map(|(member, score)| Ok((member, rdb_compat::parse_score(&score)?)))
+                    .collect::<Result<_>>()?;
+                return store_zset(storage, db, key, members);
+            }
+            op if op == RdbOpcode::HashZiplist as u8 || op == RdbOpcode::HashListpack as u8 => {
+                let blob = self.read_string()?;
+                let entries = if op == RdbOpcode::HashZiplist as u8 {
+                    rdb_compat::ziplist_entries(&blob)?
+                } else {
+                    rdb_compat::listpack_entries(&blob)?
+                };
+                return store_hash(storage, db, key, rdb_compat::pairs(entries, "hash")?);
+            }
+            op if op == RdbOpcode::ListQuicklist as u8 => {
+                let nodes = self.read_length()?;
+                
+                let mut elements = Vec::new();
+                for _ in 0..nodes {
+                    elements.extend(rdb_compat::ziplist_entries(&self.read_string()?)?);
                 }
+                return store_list(storage, db, key, elements);
+            }
+            op if op == RdbOpcode::ListQuicklist2 as u8 => {
+                let nodes = self.read_length()?;
+                
+                // Each node is a listpack, or a single large element stored plain
+                let mut elements = Vec::new();
+                for _ in 0..nodes {
+                    let container = self.read_length()?;
+                    let blob = self.read_string()?;
+                    match container {
+                        1 => elements.push(blob),
+                        2 => elements.extend(rdb_compat::listpack_entries(&blob)?),
+                        _ => return Err(FerrousError::Io(format!("Unknown quicklist node container: {}", container))),
+                    }
+                }
+                return store_list(storage, db, key, elements);
+            }
+            op if op == RdbOpcode::StreamListpacks as u8
+                || op == RdbOpcode::StreamListpacks2 as u8
+                || op == RdbOpcode::StreamListpacks3 as u8 => {
+                return self.read_stream(storage, db, key, op);
             }
             _ => {
                 // Skip unknown types for now
@@ -978,7 +1433,83 @@ impl<R: Read> RdbReader<R> {
             }
         }
         
-        Ok(())
+        Ok(Some(key))
+    }
+    
+    /// Read a Redis stream, keeping its entries
+    ///
+    /// Consumer groups are read past and dropped with a warning.
+    fn read_stream(&mut self, storage: &Arc<StorageEngine>, db: usize, key: Vec<u8>, value_type: u8) -> Result<Option<Vec<u8>>> {
+        let nodes = self.read_length()?;
+        let mut entries = Vec::new();
+        for _ in 0..nodes {
+            let master_id = self.read_string()?;
+            let listpack = self.read_string()?;
+            entries.extend(rdb_compat::stream_entries(&master_id, &listpack)?);
+        }
+        
+        // Length and last ID, then first ID, max deleted ID and entries added (v2 onwards)
+        let v2 = value_type >= RdbOpcode::StreamListpacks2 as u8;
+        let v3 = value_type >= RdbOpcode::StreamListpacks3 as u8;
+        for _ in 0..if v2 { 8 } else { 3 } {
+            self.read_length()?;
+        }
+        
+        let groups = self.read_length()?;
+        for _ in 0..groups {
+            let _name = self.read_string()?;
+            
+            // Last delivered ID, and entries read (v2 onwards)
+            for _ in 0..if v2 { 3 } else { 2 } {
+                self.read_length()?;
+            }
+            
+            // Pending entries: ID, delivery time, delivery count
+            let pending = self.read_length()?;
+            for _ in 0..pending {
+                self.skip(16 + 8)?;
+                self.read_length()?;
+            }
+            
+            // Consumers: name, seen time, active time (v3 onwards), pending IDs
+            let consumers = self.read_length()?;
+            for _ in 0..consumers {
+                let _name = self.read_string()?;
+                self.skip(if v3 { 16 } else { 8 })?;
+                let pending = self.read_length()?;
+                self.skip(pending * 16)?;
+            }
+        }
+        if groups > 0 {
+            eprintln!("RDB: Skipping {} consumer groups of stream '{}'", groups, String::from_utf8_lossy(&key));
+        }
+        
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        for ((ms, seq), fields) in entries {
+            let id = crate::storage::stream::StreamId::new(ms, seq);
+            storage.xadd_with_id(db, key.clone(), id, fields.into_iter().collect())?;
+        }
+        Ok(Some(key))
+    }
+    
+    /// Read past the opcodes of a module value, up to its EOF opcode
+    fn skip_module_value(&mut self) -> Result<()> {
+        loop {
+            match self.read_length()? {
+                0 => return Ok(()),
+                1 | 2 => {
+                    self.read_u64_length()?;
+                }
+                3 => self.skip(4)?,
+                4 => self.skip(8)?,
+                5 => {
+                    self.read_string()?;
+                }
+                opcode => return Err(FerrousError::Io(format!("Unknown module value opcode: {}", opcode))),
+            }
+        }
     }
     
     /// Read a single byte
@@ -994,30 +1525,91 @@ impl<R: Read> RdbReader<R> {
             .map_err(|e| FerrousError::Io(e.to_string()))
     }
     
+    /// Read past a number of bytes
+    fn skip(&mut self, len: usize) -> Result<()> {
+        let skipped = io::copy(&mut (&mut self.reader).take(len as u64), &mut io::sink())
+            .map_err(|e| FerrousError::Io(e.to_string()))?;
+        if skipped != len as u64 {
+            return Err(FerrousError::Io("Unexpected end of RDB file".to_string()));
+        }
+        Ok(())
+    }
+    
     /// Read length encoding
     fn read_length(&mut self) -> Result<usize> {
+        Ok(self.read_u64_length()? as usize)
+    }
+    
+    /// Read length encoding, keeping the full 64 bits (module ids)
+    fn read_u64_length(&mut self) -> Result<u64> {
         let first = self.read_byte()?;
-        
+        self.read_length_after(first)?
+            .ok_or_else(|| FerrousError::Io("Invalid length encoding".to_string()))
+    }
+    
+    /// Finish a length encoding whose first byte was read
+    ///
+    /// Returns None for the special encodings strings may use instead of a length.
+    fn read_length_after(&mut self, first: u8) -> Result<Option<u64>> {
         match first >> 6 {
-            0 => Ok(first as usize),
+            0 => Ok(Some(first as u64)),
             1 => {
                 let second = self.read_byte()?;
-                Ok((((first & 0x3F) as usize) << 8) | (second as usize))
+                Ok(Some((((first & 0x3F) as u64) << 8) | (second as u64)))
             }
-            2 => {
-                let len = self.read_u32_be()?;
-                Ok(len as usize)
+            3 => Ok(None),
+            _ if first == 0x80 => Ok(Some(self.read_u32_be()? as u64)),
+            _ if first == 0x81 => {
+                let mut buf = [0u8; 8];
+                self.read_exact(&mut buf)?;
+                Ok(Some(u64::from_be_bytes(buf)))
             }
             _ => Err(FerrousError::Io("Invalid length encoding".to_string())),
         }
     }
     
     /// Read string
+    ///
+    /// Redis may store a string as an integer or LZF-compressed; both come back as plain bytes.
     fn read_string(&mut self) -> Result<Vec<u8>> {
-        let len = self.read_length()?;
-        let mut buf = vec![0u8; len];
-        self.read_exact(&mut buf)?;
-        Ok(buf)
+        let first = self.read_byte()?;
+        if let Some(len) = self.read_length_after(first)? {
+            let mut buf = vec![0u8; len as usize];
+            self.read_exact(&mut buf)?;
+            return Ok(buf);
+        }
+        
+        match first & 0x3F {
+            0 => Ok((self.read_byte()? as i8).to_string().into_bytes()),
+            1 => {
+                let mut buf = [0u8; 2];
+                self.read_exact(&mut buf)?;
+                Ok(i16::from_le_bytes(buf).to_string().into_bytes())
+            }
+            2 => Ok((self.read_u32_le()? as i32).to_string().into_bytes()),
+            3 => {
+                let compressed_len = self.read_length()?;
+                let len = self.read_length()?;
+                let mut compressed = vec![0u8; compressed_len];
+                self.read_exact(&mut compressed)?;
+                rdb_compat::lzf_decompress(&compressed, len)
+            }
+            encoding => Err(FerrousError::Io(format!("Unknown string encoding: {}", encoding))),
+        }
+    }
+    
+    /// Read a sorted set score written as text, with special lengths for NaN and infinities
+    fn read_string_score(&mut self) -> Result<f64> {
+        match self.read_byte()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => {
+                let mut buf = vec![0u8; len as usize];
+                self.read_exact(&mut buf)?;
+                rdb_compat::parse_score(&buf)
+            }
+        }
     }
     
     /// Read 32-bit little-endian integer
@@ -1049,6 +1641,44 @@ impl<R: Read> RdbReader<R> {
     }
 }
 
+/// Store a loaded list, unless it is empty
+fn store_list(storage: &Arc<StorageEngine>, db: usize, key: Vec<u8>, elements: Vec<Vec<u8>>) -> Result<Option<Vec<u8>>> {
+    if elements.is_empty() {
+        return Ok(None);
+    }
+    storage.rpush(db, key.clone(), elements)?;
+    Ok(Some(key))
+}
+
+/// Store a loaded set, unless it is empty
+fn store_set(storage: &Arc<StorageEngine>, db: usize, key: Vec<u8>, members: Vec<Vec<u8>>) -> Result<Option<Vec<u8>>> {
+    if members.is_empty() {
+        return Ok(None);
+    }
+    storage.sadd(db, key.clone(), members)?;
+    Ok(Some(key))
+}
+
+/// Store a loaded hash, unless it is empty
+fn store_hash(storage: &Arc<StorageEngine>, db: usize, key: Vec<u8>, field_values: Vec<(Vec<u8>, Vec<u8>)>) -> Result<Option<Vec<u8>>> {
+    if field_values.is_empty() {
+        return Ok(None);
+    }
+    storage.hset(db, key.clone(), field_values)?;
+    Ok(Some(key))
+}
+
+/// Store a loaded sorted set, unless it is empty
+fn store_zset(storage: &Arc<StorageEngine>, db: usize, key: Vec<u8>, members: Vec<(Vec<u8>, f64)>) -> Result<Option<Vec<u8>>> {
+    if members.is_empty() {
+        return Ok(None);
+    }
+    for (member, score) in members {
+        storage.zadd(db, key.clone(), member, score)?;
+    }
+    Ok(Some(key))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1087,4 +1717,231 @@ mod tests {
         // Cleanup
         std::fs::remove_file("test.rdb").ok();
     }
+    
+    #[test]
+    fn test_rdb_hash_field_ttls() {
+        let config = RdbConfig {
+            filename: "test_hash_ttl.rdb".to_string(),
+            ..Default::default()
+        };
+        
+        let engine = RdbEngine::new(config);
+        let storage = StorageEngine::new();
+        
+        storage.hset(0, b"h".to_vec(), vec![(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), b"2".to_vec())]).unwrap();
+        storage.hexpire(0, b"h", &[b"a"], Instant::now() + Duration::from_secs(100), None).unwrap();
+        
+        engine.save(&storage).unwrap();
+        storage.flush_db(0).unwrap();
+        engine.load(&storage).unwrap();
+        
+        let ttls = storage.hpttl(0, b"h", &[b"a", b"b"]).unwrap();
+        assert!(ttls[0] > 90_000 && ttls[0] <= 100_000);
+        assert_eq!(ttls[1], -1);
+        assert_eq!(storage.hget(0, b"h", b"b").unwrap(), Some(b"2".to_vec()));
+        
+        std::fs::remove_file("test_hash_ttl.rdb").ok();
+    }
+    
+    #[test]
+    fn test_rdb_key_expiration() {
+        let config = RdbConfig {
+            filename: "test_key_ttl.rdb".to_string(),
+            ..Default::default()
+        };
+        
+        let engine = RdbEngine::new(config);
+        let storage = StorageEngine::new();
+        
+        storage.set_string(0, b"s".to_vec(), b"v".to_vec()).unwrap();
+        storage.set_string(0, b"plain".to_vec(), b"v".to_vec()).unwrap();
+        storage.hset(0, b"h".to_vec(), vec![(b"f".to_vec(), b"1".to_vec())]).unwrap();
+        storage.expire_at(0, b"s", Instant::now() + Duration::from_secs(100), &[]).unwrap();
+        storage.expire_at(0, b"h", Instant::now() + Duration::from_secs(200), &[]).unwrap();
+        
+        engine.save(&storage).unwrap();
+        storage.flush_db(0).unwrap();
+        engine.load(&storage).unwrap();
+        
+        let ttls: Vec<i64> = [&b"s"[..], b"h", b"plain"].iter().map(|key| storage.pttl(0, key).unwrap()).collect();
+        assert!(ttls[0] > 90_000 && ttls[0] <= 100_000);
+        assert!(ttls[1] > 190_000 && ttls[1] <= 200_000);
+        assert_eq!(ttls[2], -1);
+        
+        std::fs::remove_file("test_key_ttl.rdb").ok();
+    }
+    
+    #[test]
+    fn test_rdb_failed_save_keeps_dump() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = StorageEngine::new();
+        storage.set_string(0, b"k".to_vec(), b"v".to_vec()).unwrap();
+        
+        let engine = RdbEngine::new(RdbConfig { dir: dir.path().to_string_lossy().into_owned(), ..Default::default() });
+        engine.save(&storage).unwrap();
+        assert!(engine.last_save_ok());
+        let saved = std::fs::read(dir.path().join("dump.rdb")).unwrap();
+        
+        // A save into a missing directory fails and reports it, leaving nothing behind
+        let missing = RdbEngine::new(RdbConfig { dir: dir.path().join("missing").to_string_lossy().into_owned(), ..Default::default() });
+        assert!(missing.save(&storage).is_err());
+        assert!(!missing.last_save_ok());
+        
+        // Saving again replaces the dump with no temporary file left in the directory
+        storage.set_string(0, b"k2".to_vec(), b"v".to_vec()).unwrap();
+        engine.save(&storage).unwrap();
+        assert_ne!(std::fs::read(dir.path().join("dump.rdb")).unwrap(), saved);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+    
+    #[test]
+    fn test_rdb_hyperloglogs() {
+        let config = RdbConfig {
+            filename: "test_hll.rdb".to_string(),
+            ..Default::default()
+        };
+        
+        let engine = RdbEngine::new(config);
+        let storage = StorageEngine::new();
+        
+        // One sparse and one dense HyperLogLog
+        let elements: Vec<Vec<u8>> = (0..5000).map(|i| format!("e{}", i).into_bytes()).collect();
+        storage.pfadd(0, b"sparse".to_vec(), &elements[..10]).unwrap();
+        storage.pfadd(0, b"dense".to_vec(), &elements).unwrap();
+        let counts = (storage.pfcount(0, &[b"sparse"]).unwrap(), storage.pfcount(0, &[b"dense"]).unwrap());
+        
+        engine.save(&storage).unwrap();
+        storage.flush_db(0).unwrap();
+        engine.load(&storage).unwrap();
+        
+        assert_eq!((storage.pfcount(0, &[b"sparse"]).unwrap(), storage.pfcount(0, &[b"dense"]).unwrap()), counts);
+        assert!(!storage.pfadd(0, b"dense".to_vec(), &elements[..100]).unwrap());
+        
+        std::fs::remove_file("test_hll.rdb").ok();
+    }
+    
+    #[test]
+    fn test_rdb_bgsave_keeps_the_dataset_as_of_the_command() {
+        let config = RdbConfig {
+            filename: "test_bgsave_snapshot.rdb".to_string(),
+            ..Default::default()
+        };
+        
+        let engine = RdbEngine::new(config);
+        let storage = StorageEngine::new();
+        storage.set_string(0, b"key".to_vec(), b"before".to_vec()).unwrap();
+        for i in 0..300 {
+            storage.zadd(0, b"zset".to_vec(), format!("m{}", i).into_bytes(), i as f64).unwrap();
+        }
+        
+        // Writes racing the background save don't reach the dump, including
+        // changes made in place to a skiplist-encoded sorted set
+        engine.bgsave(Arc::clone(&storage)).unwrap();
+        storage.set_string(0, b"key".to_vec(), b"after".to_vec()).unwrap();
+        storage.set_string(0, b"late".to_vec(), b"value".to_vec()).unwrap();
+        for i in 300..400 {
+            storage.zadd(0, b"zset".to_vec(), format!("m{}", i).into_bytes(), i as f64).unwrap();
+        }
+        storage.zrem(0, b"zset", b"m0").unwrap();
+        while engine.is_bgsave_in_progress() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(engine.last_save_ok());
+        
+        let restored = StorageEngine::new();
+        engine.load(&restored).unwrap();
+        assert_eq!(restored.get_string(0, b"key").unwrap(), Some(b"before".to_vec()));
+        assert_eq!(restored.get_string(0, b"late").unwrap(), None);
+        assert_eq!(restored.zcard(0, b"zset").unwrap(), 300);
+        assert_eq!(restored.zscore(0, b"zset", b"m0").unwrap(), Some(0.0));
+        assert_eq!(storage.zcard(0, b"zset").unwrap(), 399);
+        
+        // Cleanup
+        std::fs::remove_file("test_bgsave_snapshot.rdb").ok();
+    }
+    
+    #[test]
+    fn test_rdb_load_redis_dump() {
+        // A Redis 7 dump using the compact encodings, written out by hand
+        let mut rdb = b"REDIS0011".to_vec();
+        let aux = |rdb: &mut Vec<u8>, key: &str, value: &str| {
+            rdb.push(0xFA);
+            rdb.push(key.len() as u8);
+            rdb.extend_from_slice(key.as_bytes());
+            rdb.push(value.len() as u8);
+            rdb.extend_from_slice(value.as_bytes());
+        };
+        aux(&mut rdb, "redis-ver", "7.2.4");
+        aux(&mut rdb, "redis-bits", "64");
+        
+        // Module aux data: id, when opcode, when, then an unsigned int and EOF
+        rdb.extend_from_slice(&[0xF7, 0x81, 0, 0, 0, 0, 0, 0, 0x04, 0x01, 0x02, 0x02, 0x02, 0x05, 0x00]);
+        rdb.extend_from_slice(&[0xFE, 0x00, 0xFB, 0x08, 0x01]);
+        
+        // Integer-encoded and LZF-compressed strings
+        rdb.extend_from_slice(&[0x00, 0x01, b'n', 0xC1, 0x39, 0x30]);
+        rdb.extend_from_slice(&[0x00, 0x01, b'z', 0xC3, 0x05, 0x0A, 0x00, b'a', 0xE0, 0x00, 0x00]);
+        
+        // Expiry followed by an LRU hint, and a key that has already expired
+        let expires = (unix_ms() + 100_000).to_le_bytes();
+        rdb.push(0xFC);
+        rdb.extend_from_slice(&expires);
+        rdb.extend_from_slice(&[0xF8, 0x05, 0x00, 0x01, b'e', 0x01, b'v']);
+        rdb.push(0xFC);
+        rdb.extend_from_slice(&1000u64.to_le_bytes());
+        rdb.extend_from_slice(&[0x00, 0x04, b'g', b'o', b'n', b'e', 0x01, b'v']);
+        
+        // Intset {1, 2}
+        rdb.extend_from_slice(&[0x0B, 0x01, b's', 0x0C, 2, 0, 0, 0, 2, 0, 0, 0, 1, 0, 2, 0]);
+        
+        // Listpack hash {f: v, n: 7}
+        rdb.extend_from_slice(&[0x10, 0x01, b'h', 0x12, 0, 0, 0, 0, 0, 0]);
+        rdb.extend_from_slice(&[0x81, b'f', 0x02, 0x81, b'v', 0x02, 0x81, b'n', 0x02, 0x07, 0x01, 0xFF]);
+        
+        // Quicklist of a plain node "big" and a packed node [1, "x"]
+        rdb.extend_from_slice(&[0x12, 0x01, b'l', 0x02, 0x01, 0x03, b'b', b'i', b'g']);
+        rdb.extend_from_slice(&[0x02, 0x0C, 0, 0, 0, 0, 0, 0, 0x01, 0x01, 0x81, b'x', 0x02, 0xFF]);
+        
+        // Sorted set with text scores, one of them infinite
+        rdb.extend_from_slice(&[0x03, 0x02, b'z', b's', 0x02, 0x01, b'a', 0x03, b'1', b'.', b'5', 0x01, b'b', 0xFE]);
+        
+        // Key of a module type, skipped
+        rdb.extend_from_slice(&[0x07, 0x01, b'm', 0x81, 0, 0, 0, 0, 0, 0, 0x04, 0x01]);
+        rdb.extend_from_slice(&[0x05, 0x02, b'h', b'i', 0x04, 0, 0, 0, 0, 0, 0, 0xF0, 0x3F, 0x00]);
+        
+        rdb.push(0xFF);
+        rdb.extend_from_slice(&[0; 8]);
+        
+        let storage = StorageEngine::new();
+        RdbReader::new(&rdb[..]).load_into(&storage).unwrap();
+        
+        assert_eq!(storage.get_string(0, b"n").unwrap(), Some(b"12345".to_vec()));
+        assert_eq!(storage.get_string(0, b"z").unwrap(), Some(b"aaaaaaaaaa".to_vec()));
+        let ttl = storage.pttl(0, b"e").unwrap();
+        assert!(ttl > 90_000 && ttl <= 100_000);
+        assert!(!storage.exists(0, b"gone").unwrap());
+        let mut members = storage.smembers(0, b"s").unwrap();
+        members.sort();
+        assert_eq!(members, vec![b"1".to_vec(), b"2".to_vec()]);
+        assert_eq!(storage.hget(0, b"h", b"n").unwrap(), Some(b"7".to_vec()));
+        assert_eq!(storage.lrange(0, b"l", 0, -1).unwrap(), vec![b"big".to_vec(), b"1".to_vec(), b"x".to_vec()]);
+        assert_eq!(storage.zscore(0, b"zs", b"a").unwrap(), Some(1.5));
+        assert_eq!(storage.zscore(0, b"zs", b"b").unwrap(), Some(f64::INFINITY));
+        assert!(!storage.exists(0, b"m").unwrap());
+    }
+    
+    #[test]
+    fn test_rdb_files_are_marked_as_ferrous() {
+        let storage = StorageEngine::new();
+        storage.zadd(0, b"z".to_vec(), b"a".to_vec(), 2.5).unwrap();
+        
+        let bytes = RdbEngine::new(RdbConfig::default()).generate_rdb_bytes(&storage).unwrap();
+        storage.flush_db(0).unwrap();
+        let mut reader = RdbReader::new(&bytes[..]);
+        reader.load_into(&storage).unwrap();
+        
+        // Type 3 is read with Ferrous's binary scores, not Redis's text ones
+        assert_eq!(reader.dialect, RdbDialect::Ferrous);
+        assert_eq!(storage.zscore(0, b"z", b"a").unwrap(), Some(2.5));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,416 @@
+//! Decoders for the compact encodings of Redis RDB files
+//!
+//! Redis 6 and 7 dumps keep small collections as ziplists, listpacks, intsets or
+//! zipmaps serialized inside a string, and may LZF-compress any string. The RDB
+//! reader loads those strings as usual and hands them to these decoders, which
+//! turn them back into plain elements. Integers stored in a compact encoding come
+//! back as their decimal strings, the way Redis replies with them.
+
+use crate::error::{FerrousError, Result};
+
+/// Error for a compact encoding that doesn't parse
+fn corrupt(what: &str) -> FerrousError {
+    FerrousError::Io(format!("Corrupt {} in RDB file", what))
+}
+
+/// Reads a blob front to back, failing on a truncated one
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+    what: &'static str,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8], what: &'static str) -> Self {
+        Self { data, pos: 0, what }
+    }
+    
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self.pos.checked_add(len)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| corrupt(self.what))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+    
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+    
+    /// Little-endian signed integer of `len` bytes
+    fn int_le(&mut self, len: usize) -> Result<i64> {
+        let bytes = self.take(len)?;
+        let mut buf = [0u8; 8];
+        buf[..len].copy_from_slice(bytes);
+        
+        // Sign-extend from the top bit of the last byte
+        if bytes[len - 1] & 0x80 != 0 {
+            buf[len..].fill(0xFF);
+        }
+        Ok(i64::from_le_bytes(buf))
+    }
+    
+    fn u32_le(&mut self) -> Result<u32> {
+        Ok(self.int_le(4)? as u32)
+    }
+}
+
+/// Decompress an LZF-compressed string of `expected_len` bytes
+pub fn lzf_decompress(input: &[u8], expected_len: usize) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(expected_len);
+    let mut input = Cursor::new(input, "LZF string");
+    
+    while input.pos < input.data.len() {
+        let ctrl = input.byte()? as usize;
+        if ctrl < 32 {
+            // Literal run of ctrl + 1 bytes
+            out.extend_from_slice(input.take(ctrl + 1)?);
+            continue;
+        }
+        
+        // Back reference: length in the top 3 bits, extended by a byte when all set
+        let mut len = ctrl >> 5;
+        if len == 7 {
+            len += input.byte()? as usize;
+        }
+        let offset = ((ctrl & 0x1F) << 8) + input.byte()? as usize + 1;
+        let start = out.len().checked_sub(offset).ok_or_else(|| corrupt("LZF string"))?;
+        
+        // Byte by byte: the reference may overlap the bytes it produces
+        for i in 0..len + 2 {
+            out.push(out[start + i]);
+        }
+    }
+    
+    if out.len() != expected_len {
+        return Err(corrupt("LZF string"));
+    }
+    Ok(out)
+}
+
+/// Elements of a ziplist (lists, hashes and sorted sets before Redis 7)
+pub fn ziplist_entries(blob: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut zl = Cursor::new(blob, "ziplist");
+    
+    // zlbytes, zltail, zllen
+    zl.take(10)?;
+    
+    let mut entries = Vec::new();
+    loop {
+        // Length of the previous entry: one byte, or 0xFE and four more
+        match zl.byte()? {
+            0xFF => break,
+            0xFE => {
+                zl.take(4)?;
+            }
+            _ => {}
+        }
+        
+        let encoding = zl.byte()?;
+        let entry = match encoding >> 6 {
+            0 => zl.take((encoding & 0x3F) as usize)?.to_vec(),
+            1 => {
+                let len = (((encoding & 0x3F) as usize) << 8) | zl.byte()? as usize;
+                zl.take(len)?.to_vec()
+            }
+            2 => {
+                let len = u32::from_be_bytes(zl.take(4)?.try_into().unwrap()) as usize;
+                zl.take(len)?.to_vec()
+            }
+            _ => {
+                let value = match encoding {
+                    0xC0 => zl.int_le(2)?,
+                    0xD0 => zl.int_le(4)?,
+                    0xE0 => zl.int_le(8)?,
+                    0xF0 => zl.int_le(3)?,
+                    0xFE => zl.int_le(1)?,
+                    0xF1..=0xFD => (encoding & 0x0F) as i64 - 1,
+                    _ => return Err(corrupt("ziplist")),
+                };
+                value.to_string().into_bytes()
+            }
+        };
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Bytes of the back-length that follows a listpack entry of `len` bytes
+fn listpack_backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+/// Elements of a listpack (Redis 7 compact encoding, also used by streams)
+pub fn listpack_entries(blob: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut lp = Cursor::new(blob, "listpack");
+    
+    // Total bytes, number of elements
+    lp.take(6)?;
+    
+    let mut entries = Vec::new();
+    loop {
+        let start = lp.pos;
+        let encoding = lp.byte()?;
+        let entry = if encoding == 0xFF {
+            break;
+        } else if encoding & 0x80 == 0 {
+            // 7-bit unsigned integer
+            (encoding as i64).to_string().into_bytes()
+        } else if encoding & 0xC0 == 0x80 {
+            lp.take((encoding & 0x3F) as usize)?.to_vec()
+        } else if encoding & 0xE0 == 0xC0 {
+            // 13-bit signed integer
+            let raw = (((encoding & 0x1F) as i64) << 8) | lp.byte()? as i64;
+            let value = if raw >= 1 << 12 { raw - (1 << 13) } else { raw };
+            value.to_string().into_bytes()
+        } else if encoding & 0xF0 == 0xE0 {
+            let len = (((encoding & 0x0F) as usize) << 8) | lp.byte()? as usize;
+            lp.take(len)?.to_vec()
+        } else {
+            match encoding {
+                0xF0 => {
+                    let len = lp.u32_le()? as usize;
+                    lp.take(len)?.to_vec()
+                }
+                0xF1 => lp.int_le(2)?.to_string().into_bytes(),
+                0xF2 => lp.int_le(3)?.to_string().into_bytes(),
+                0xF3 => lp.int_le(4)?.to_string().into_bytes(),
+                0xF4 => lp.int_le(8)?.to_string().into_bytes(),
+                _ => return Err(corrupt("listpack")),
+            }
+        };
+        lp.take(listpack_backlen_size(lp.pos - start))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Members of an intset (sets of integers)
+pub fn intset_members(blob: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut set = Cursor::new(blob, "intset");
+    let width = set.u32_le()? as usize;
+    if !matches!(width, 2 | 4 | 8) {
+        return Err(corrupt("intset"));
+    }
+    let len = set.u32_le()? as usize;
+    
+    (0..len).map(|_| Ok(set.int_le(width)?.to_string().into_bytes())).collect()
+}
+
+/// Field-value pairs of a zipmap (hashes written by Redis before 2.6)
+pub fn zipmap_pairs(blob: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut zm = Cursor::new(blob, "zipmap");
+    
+    // Element count, unreliable past 253
+    zm.byte()?;
+    
+    let mut pairs = Vec::new();
+    loop {
+        let field_len = match zm.byte()? {
+            0xFF => break,
+            0xFE => zm.u32_le()? as usize,
+            len => len as usize,
+        };
+        let field = zm.take(field_len)?.to_vec();
+        let value_len = match zm.byte()? {
+            0xFE => zm.u32_le()? as usize,
+            0xFF => return Err(corrupt("zipmap")),
+            len => len as usize,
+        };
+        let free = zm.byte()? as usize;
+        let value = zm.take(value_len)?.to_vec();
+        zm.take(free)?;
+        pairs.push((field, value));
+    }
+    Ok(pairs)
+}
+
+/// Pair up the flattened field-value (or member-score) elements of a compact encoding
+pub fn pairs(entries: Vec<Vec<u8>>, what: &str) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    if !entries.len().is_multiple_of(2) {
+        return Err(corrupt(what));
+    }
+    let mut entries = entries.into_iter();
+    let mut pairs = Vec::new();
+    while let (Some(first), Some(second)) = (entries.next(), entries.next()) {
+        pairs.push((first, second));
+    }
+    Ok(pairs)
+}
+
+/// Parse a score stored as text, as sorted sets in ziplists and listpacks are
+pub fn parse_score(bytes: &[u8]) -> Result<f64> {
+    let text = std::str::from_utf8(bytes).map_err(|_| corrupt("sorted set score"))?;
+    match text {
+        "inf" | "+inf" => Ok(f64::INFINITY),
+        "-inf" => Ok(f64::NEG_INFINITY),
+        _ => text.parse().map_err(|_| corrupt("sorted set score")),
+    }
+}
+
+/// One entry of a stream: ID (milliseconds, sequence) and its field-value pairs
+pub type StreamEntry = ((u64, u64), Vec<(Vec<u8>, Vec<u8>)>);
+
+/// Live entries of one stream node: a listpack keyed by the node's master ID
+///
+/// The listpack starts with the master entry (entry count, deleted count, the
+/// master fields, a terminating 0). Each entry then holds its flags, its ID as
+/// deltas from the master ID, either its own fields and values or just values
+/// for the master fields, and its element count.
+pub fn stream_entries(master_id: &[u8], listpack: &[u8]) -> Result<Vec<StreamEntry>> {
+    const DELETED: i64 = 1;
+    const SAME_FIELDS: i64 = 2;
+    
+    let master: [u8; 16] = master_id.try_into().map_err(|_| corrupt("stream node"))?;
+    let master_ms = u64::from_be_bytes(master[..8].try_into().unwrap());
+    let master_seq = u64::from_be_bytes(master[8..].try_into().unwrap());
+    
+    let elements = listpack_entries(listpack)?;
+    let mut elements = elements.into_iter();
+    let next_int = |elements: &mut std::vec::IntoIter<Vec<u8>>| -> Result<i64> {
+        elements.next()
+            .and_then(|element| std::str::from_utf8(&element).ok()?.parse().ok())
+            .ok_or_else(|| corrupt("stream node"))
+    };
+    
+    let count = next_int(&mut elements)? + next_int(&mut elements)?;
+    let master_fields: Vec<Vec<u8>> = (0..next_int(&mut elements)?)
+        .map(|_| elements.next().ok_or_else(|| corrupt("stream node")))
+        .collect::<Result<_>>()?;
+    next_int(&mut elements)?;
+    
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let flags = next_int(&mut elements)?;
+        let id = (
+            master_ms.wrapping_add(next_int(&mut elements)? as u64),
+            master_seq.wrapping_add(next_int(&mut elements)? as u64),
+        );
+        let fields: Vec<(Vec<u8>, Vec<u8>)> = if flags & SAME_FIELDS != 0 {
+            master_fields.iter()
+                .map(|field| Ok((field.clone(), elements.next().ok_or_else(|| corrupt("stream node"))?)))
+                .collect::<Result<_>>()?
+        } else {
+            (0..next_int(&mut elements)?)
+                .map(|_| match (elements.next(), elements.next()) {
+                    (Some(field), Some(value)) => Ok((field, value)),
+                    _ => Err(corrupt("stream node")),
+                })
+                .collect::<Result<_>>()?
+        };
+        
+        // Element count, for walking the listpack backwards
+        next_int(&mut elements)?;
+        if flags & DELETED == 0 {
+            entries.push((id, fields));
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn strings(entries: &[&str]) -> Vec<Vec<u8>> {
+        entries.iter().map(|entry| entry.as_bytes().to_vec()).collect()
+    }
+    
+    #[test]
+    fn test_lzf_decompress() {
+        // "aaaaaaaaaa": literal 'a', then a back reference of length 9 at offset 1
+        assert_eq!(lzf_decompress(&[0x00, b'a', 0xE0, 0x00, 0x00], 10).unwrap(), b"aaaaaaaaaa");
+        assert_eq!(lzf_decompress(&[0x01, b'h', b'i'], 2).unwrap(), b"hi");
+        assert!(lzf_decompress(&[0x01, b'h', b'i'], 3).is_err());
+        assert!(lzf_decompress(&[0x20, 0x05], 3).is_err());
+    }
+    
+    #[test]
+    fn test_ziplist_entries() {
+        // ["a", "12345", 7, -2, 300] as written by Redis 6
+        let mut zl = vec![0; 10];
+        zl.extend_from_slice(&[0x00, 0x01, b'a']);
+        zl.extend_from_slice(&[0x03, 0x05, b'1', b'2', b'3', b'4', b'5']);
+        zl.extend_from_slice(&[0x07, 0xF8]);
+        zl.extend_from_slice(&[0x02, 0xFE, 0xFE]);
+        zl.extend_from_slice(&[0x03, 0xC0, 0x2C, 0x01]);
+        zl.push(0xFF);
+        assert_eq!(ziplist_entries(&zl).unwrap(), strings(&["a", "12345", "7", "-2", "300"]));
+        assert!(ziplist_entries(&zl[..zl.len() - 3]).is_err());
+    }
+    
+    #[test]
+    fn test_listpack_entries() {
+        // ["ab", 5, -100, 1000000] as written by Redis 7
+        let mut lp = vec![0; 6];
+        lp.extend_from_slice(&[0x82, b'a', b'b', 0x03]);
+        lp.extend_from_slice(&[0x05, 0x01]);
+        lp.extend_from_slice(&[0xDF, 0x9C, 0x02]);
+        lp.extend_from_slice(&[0xF2, 0x40, 0x42, 0x0F, 0x04]);
+        lp.push(0xFF);
+        assert_eq!(listpack_entries(&lp).unwrap(), strings(&["ab", "5", "-100", "1000000"]));
+        assert!(listpack_entries(&[0, 0, 0, 0, 0, 0, 0xF9, 0xFF]).is_err());
+    }
+    
+    #[test]
+    fn test_intset_and_zipmap() {
+        let mut set = vec![2, 0, 0, 0, 3, 0, 0, 0];
+        set.extend_from_slice(&[0xFF, 0xFF, 0x01, 0x00, 0x10, 0x27]);
+        assert_eq!(intset_members(&set).unwrap(), strings(&["-1", "1", "10000"]));
+        assert!(intset_members(&[3, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        
+        let zm = [0x01, 0x01, b'f', 0x02, 0x01, b'v', b'1', 0x00, 0xFF];
+        assert_eq!(zipmap_pairs(&zm).unwrap(), vec![(b"f".to_vec(), b"v1".to_vec())]);
+    }
+    
+    #[test]
+    fn test_scores_and_pairs() {
+        assert_eq!(parse_score(b"1.5").unwrap(), 1.5);
+        assert_eq!(parse_score(b"-inf").unwrap(), f64::NEG_INFINITY);
+        assert!(parse_score(b"x").is_err());
+        assert_eq!(pairs(strings(&["a", "1", "b", "2"]), "hash").unwrap().len(), 2);
+        assert!(pairs(strings(&["a"]), "hash").is_err());
+    }
+    
+    #[test]
+    fn test_stream_entries() {
+        // Master 1000-0 with field "f": entry 1000-0 {f: a}, deleted 1001-0, 1002-1 {g: b}
+        let mut lp = vec![0; 6];
+        let mut push = |entry: &[u8]| {
+            if let Ok(small @ 0..=127) = std::str::from_utf8(entry).unwrap_or("x").parse::<u8>() {
+                lp.extend_from_slice(&[small, 0x01]);
+            } else {
+                lp.push(0x80 | entry.len() as u8);
+                lp.extend_from_slice(entry);
+                lp.push(entry.len() as u8 + 1);
+            }
+        };
+        for element in ["2", "1", "1", "f", "0"] {
+            push(element.as_bytes());
+        }
+        for element in ["2", "0", "0", "a", "4"] {
+            push(element.as_bytes());
+        }
+        for element in ["3", "1", "0", "z", "4"] {
+            push(element.as_bytes());
+        }
+        for element in ["0", "2", "1", "1", "g", "b", "7"] {
+            push(element.as_bytes());
+        }
+        lp.push(0xFF);
+        
+        let mut master = 1000u64.to_be_bytes().to_vec();
+        master.extend_from_slice(&0u64.to_be_bytes());
+        let entries = stream_entries(&master, &lp).unwrap();
+        assert_eq!(entries, vec![
+            ((1000, 0), vec![(b"f".to_vec(), b"a".to_vec())]),
+            ((1002, 1), vec![(b"g".to_vec(), b"b".to_vec())]),
+        ]);
+    }
+}
@@ -0,0 +1,218 @@
+//! Pluggable script engines
+//!
+//! EVAL, FCALL and FUNCTION dispatch through the [`ScriptEngine`] trait rather
+//! than calling the Lua runtime directly. Lua is the built-in engine (named
+//! `LUA`, as in FUNCTION LIST) and is always registered first; embedders can
+//! add engines with [`register_engine`]. FUNCTION LOAD picks the engine from
+//! the library's `#!<engine> name=<library>` shebang, and FCALL runs whichever
+//! engine registered the function.
+//!
+//! EVAL and EVALSHA always run Lua, as in Redis.
+
+use std::sync::{Arc, RwLock};
+
+use crate::config::ScriptingConfig;
+use crate::error::{FerrousError, Result};
+use crate::protocol::resp::{Bytes, RespFrame};
+use crate::storage::lua_engine::{get_lua_engine, LuaCommandContext, LuaEngine};
+use crate::storage::lua_functions::{get_function_registry, FunctionLibrary, FunctionRegistry};
+use crate::storage::{lua_checkpoint, lua_triggers, StorageEngine};
+
+/// A scripting runtime that can evaluate scripts and, optionally, host function libraries
+///
+/// Only `name`, `compile` and `eval` are required. Engines without function
+/// support keep the defaults, which report that no library or function exists.
+pub trait ScriptEngine: Send + Sync {
+    /// Engine name used in library shebangs and FUNCTION LIST/STATS (e.g. `LUA`)
+    fn name(&self) -> &str;
+
+    /// Check that a script compiles, without running or caching it
+    fn compile(&self, source: &str) -> Result<()>;
+
+    /// Run a script with its KEYS and ARGV in the caller's database
+    fn eval(&self, source: &str, keys: Vec<Bytes>, args: Vec<Bytes>, ctx: &LuaCommandContext) -> Result<RespFrame>;
+
+    /// Apply the scripting resource limits and options from the server configuration
+    fn configure(&self, _config: &ScriptingConfig) {}
+
+    /// Load a function library (FUNCTION LOAD), returning its name
+    fn load_library(&self, _code: &str, _replace: bool) -> Result<String> {
+        Err(FerrousError::LuaError(format!("ERR Engine '{}' does not support functions", self.name())))
+    }
+
+    /// Check whether this engine registered a function
+    fn has_function(&self, _function: &str) -> bool {
+        false
+    }
+
+    /// Call a registered function; `read_only` is set for FCALL_RO
+    fn call_function(&self, _function: &str, _keys: Vec<Bytes>, _args: Vec<Bytes>, _db_index: usize, _read_only: bool) -> Result<RespFrame> {
+        Err(FerrousError::LuaError("ERR Function not found".to_string()))
+    }
+
+    /// Loaded libraries, sorted by name
+    fn libraries(&self) -> Vec<FunctionLibrary> {
+        Vec::new()
+    }
+
+    /// Delete a library (FUNCTION DELETE)
+    fn delete_library(&self, _library: &str) -> Result<()> {
+        Err(FerrousError::LuaError("ERR Library not found".to_string()))
+    }
+
+    /// Delete all libraries (FUNCTION FLUSH)
+    fn flush_libraries(&self) {}
+
+    /// Bytes used by the engine's persistent function state
+    fn used_memory(&self) -> usize {
+        0
+    }
+}
+
+/// The built-in Lua engine: per-call VMs for EVAL, the persistent registry for functions
+pub struct LuaScriptEngine {
+    eval: Arc<LuaEngine>,
+    functions: Arc<FunctionRegistry>,
+}
+
+impl LuaScriptEngine {
+    /// Wrap the process-wide Lua engine and function registry
+    pub fn new(storage: &Arc<StorageEngine>) -> Result<Self> {
+        Ok(LuaScriptEngine {
+            eval: get_lua_engine(storage.clone())?,
+            functions: get_function_registry(storage.clone())?,
+        })
+    }
+}
+
+impl ScriptEngine for LuaScriptEngine {
+    fn name(&self) -> &str {
+        "LUA"
+    }
+
+    fn compile(&self, source: &str) -> Result<()> {
+        self.eval.compile(source).map(|_| ())
+    }
+
+    fn eval(&self, source: &str, keys: Vec<Bytes>, args: Vec<Bytes>, ctx: &LuaCommandContext) -> Result<RespFrame> {
+        self.eval.eval(source, keys, args, ctx)
+    }
+
+    fn configure(&self, config: &ScriptingConfig) {
+        self.eval.set_reply_limits(config.reply_limits);
+        self.eval.set_utf8_enabled(config.enable_utf8);
+        self.eval.set_deterministic(config.deterministic);
+        self.eval.set_record_dir(config.record_dir.clone());
+        self.eval.set_time_limit_ms(config.time_limit_ms);
+        self.eval.set_max_instructions(config.max_instructions);
+        self.eval.set_max_call_depth(config.max_call_depth);
+        self.eval.set_memory_limit(config.memory_limit);
+        self.eval.set_debug_library_enabled(config.enable_debug_library);
+        self.eval.set_max_nesting_depth(config.max_nesting_depth);
+        lua_triggers::set_max_depth(config.trigger_max_depth);
+        lua_checkpoint::configure(config.checkpoints, config.checkpoint_interval_ms);
+    }
+
+    fn load_library(&self, code: &str, replace: bool) -> Result<String> {
+        self.functions.load(code, replace)
+    }
+
+    fn has_function(&self, function: &str) -> bool {
+        self.functions.has_function(function)
+    }
+
+    fn call_function(&self, function: &str, keys: Vec<Bytes>, args: Vec<Bytes>, db_index: usize, read_only: bool) -> Result<RespFrame> {
+        if read_only {
+            self.functions.call_ro(function, keys, args, db_index)
+        } else {
+            self.functions.call(function, keys, args, db_index)
+        }
+    }
+
+    fn libraries(&self) -> Vec<FunctionLibrary> {
+        self.functions.libraries()
+    }
+
+    fn delete_library(&self, library: &str) -> Result<()> {
+        self.functions.delete(library)
+    }
+
+    fn flush_libraries(&self) {
+        self.functions.flush();
+    }
+
+    fn used_memory(&self) -> usize {
+        self.functions.used_memory()
+    }
+}
+
+/// Engines registered by embedders, in registration order (Lua is added on first use)
+static ENGINES: RwLock<Vec<Arc<dyn ScriptEngine>>> = RwLock::new(Vec::new());
+
+/// Register an additional script engine
+///
+/// Fails if an engine with the same name (compared case-insensitively) exists.
+pub fn register_engine(storage: &Arc<StorageEngine>, engine: Arc<dyn ScriptEngine>) -> Result<()> {
+    ensure_lua(storage)?;
+    let mut engines = ENGINES.write().unwrap();
+    if engines.iter().any(|existing| existing.name().eq_ignore_ascii_case(engine.name())) {
+        return Err(FerrousError::LuaError(format!("ERR Engine '{}' already registered", engine.name())));
+    }
+    engines.push(engine);
+    Ok(())
+}
+
+/// Register the Lua engine if nothing has been registered yet
+fn ensure_lua(storage: &Arc<StorageEngine>) -> Result<()> {
+    if !ENGINES.read().unwrap().is_empty() {
+        return Ok(());
+    }
+
+    let lua = LuaScriptEngine::new(storage)?;
+    let mut engines = ENGINES.write().unwrap();
+    if engines.is_empty() {
+        engines.push(Arc::new(lua));
+    }
+    Ok(())
+}
+
+/// All registered engines, Lua first
+pub fn engines(storage: &Arc<StorageEngine>) -> Result<Vec<Arc<dyn ScriptEngine>>> {
+    ensure_lua(storage)?;
+    Ok(ENGINES.read().unwrap().clone())
+}
+
+/// Engines registered so far, without creating the built-in Lua engine
+pub fn registered_engines() -> Vec<Arc<dyn ScriptEngine>> {
+    ENGINES.read().unwrap().clone()
+}
+
+/// Look up an engine by name (case-insensitive)
+pub fn get_engine(storage: &Arc<StorageEngine>, name: &str) -> Result<Option<Arc<dyn ScriptEngine>>> {
+    Ok(engines(storage)?.into_iter().find(|engine| engine.name().eq_ignore_ascii_case(name)))
+}
+
+/// The engine that runs EVAL and EVALSHA
+pub fn eval_engine(storage: &Arc<StorageEngine>) -> Result<Arc<dyn ScriptEngine>> {
+    ensure_lua(storage)?;
+    Ok(ENGINES.read().unwrap()[0].clone())
+}
+
+/// The engine named by a library's `#!<engine> name=<library>` shebang
+pub fn library_engine(storage: &Arc<StorageEngine>, code: &str) -> Result<Arc<dyn ScriptEngine>> {
+    let first_line = code.lines().next().unwrap_or("");
+    let name = match first_line.strip_prefix("#!") {
+        Some(shebang) => shebang.split_whitespace().next().unwrap_or(""),
+        None => return Err(FerrousError::LuaError("ERR Missing library metadata".to_string())),
+    };
+
+    match get_engine(storage, name)? {
+        Some(engine) => Ok(engine),
+        None => Err(FerrousError::LuaError(format!("ERR Engine '{}' not found", name))),
+    }
+}
+
+/// The engine that registered a function, if any
+pub fn function_engine(storage: &Arc<StorageEngine>, function: &str) -> Result<Option<Arc<dyn ScriptEngine>>> {
+    Ok(engines(storage)?.into_iter().find(|engine| engine.has_function(function)))
+}
@@ -0,0 +1,90 @@
+//! Shared small-integer objects
+//!
+//! Counters and flags are overwhelmingly small non-negative integers. Instead of
+//! allocating a fresh buffer per key, string values that are the canonical decimal
+//! form of 0..SHARED_INTEGERS share a single immutable buffer, like Redis' shared
+//! integer objects.
+
+use std::sync::{Arc, OnceLock};
+
+/// Number of shared integer objects (0..SHARED_INTEGERS)
+pub const SHARED_INTEGERS: usize = 10000;
+
+/// Reference count reported by OBJECT REFCOUNT for shared objects (Redis uses INT_MAX)
+pub const SHARED_REFCOUNT: i64 = i32::MAX as i64;
+
+static SHARED_INTEGER_TABLE: OnceLock<Vec<Arc<Vec<u8>>>> = OnceLock::new();
+
+fn table() -> &'static [Arc<Vec<u8>>] {
+    SHARED_INTEGER_TABLE.get_or_init(|| {
+        (0..SHARED_INTEGERS).map(|n| Arc::new(n.to_string().into_bytes())).collect()
+    })
+}
+
+/// Get the shared buffer for an integer, if it is in the shared range
+pub fn shared_integer(n: i64) -> Option<Arc<Vec<u8>>> {
+    if n >= 0 && (n as usize) < SHARED_INTEGERS {
+        Some(Arc::clone(&table()[n as usize]))
+    } else {
+        None
+    }
+}
+
+/// Parse bytes as the canonical decimal form of a shared integer
+fn parse_shared(bytes: &[u8]) -> Option<usize> {
+    if bytes.is_empty() || bytes.len() > 4 || (bytes.len() > 1 && bytes[0] == b'0') {
+        return None;
+    }
+    let mut n = 0usize;
+    for &b in bytes {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        n = n * 10 + (b - b'0') as usize;
+    }
+    Some(n)
+}
+
+/// Intern a string value: returns the shared buffer for small integers, otherwise wraps the bytes
+pub fn intern(bytes: Vec<u8>) -> Arc<Vec<u8>> {
+    match parse_shared(&bytes) {
+        Some(n) => Arc::clone(&table()[n]),
+        None => Arc::new(bytes),
+    }
+}
+
+/// Intern a string value already held in a shared buffer, keeping that buffer
+/// unless it is a small integer
+pub fn intern_shared(bytes: Arc<Vec<u8>>) -> Arc<Vec<u8>> {
+    match parse_shared(&bytes) {
+        Some(n) => Arc::clone(&table()[n]),
+        None => bytes,
+    }
+}
+
+/// Check whether a buffer is one of the shared integer objects
+pub fn is_shared(bytes: &Arc<Vec<u8>>) -> bool {
+    match parse_shared(bytes) {
+        Some(n) => Arc::ptr_eq(bytes, &table()[n]),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_intern_shares_small_integers() {
+        let a = intern(b"42".to_vec());
+        let b = intern(b"42".to_vec());
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(is_shared(&a));
+        
+        // Non-canonical and out-of-range values are not shared
+        assert!(!is_shared(&intern(b"042".to_vec())));
+        assert!(!is_shared(&intern(b"10000".to_vec())));
+        assert!(!is_shared(&intern(b"-1".to_vec())));
+        assert!(!is_shared(&Arc::new(b"42".to_vec())));
+    }
+}
@@ -10,6 +10,7 @@ use std::cmp::Ordering;
 use std::fmt::{self, Debug};
 use std::collections::HashMap;
 use std::borrow::Borrow;
+use std::ops::Bound;
 use rand::Rng;
 
 /// Maximum number of levels in the skip list
@@ -287,6 +288,55 @@ where
         RangeResult { items }
     }
 
+    /// Get a range of elements by key (ZRANGEBYLEX) - O(log n + k) operation
+    ///
+    /// Like Redis, this assumes every element has the same score, so that key
+    /// order is list order; with mixed scores the result is unspecified.
+    pub fn range_by_key<Q>(&self, min: Bound<&Q>, max: Bound<&Q>) -> RangeResult<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let below_min = |key: &K| match min {
+            Bound::Included(min) => key.borrow() < min,
+            Bound::Excluded(min) => key.borrow() <= min,
+            Bound::Unbounded => false,
+        };
+        let within_max = |key: &K| match max {
+            Bound::Included(max) => key.borrow() <= max,
+            Bound::Excluded(max) => key.borrow() < max,
+            Bound::Unbounded => true,
+        };
+        
+        let inner = self.inner.read().unwrap();
+        let mut items = Vec::new();
+        let mut current = inner.head;
+        
+        unsafe {
+            // Skip to the first element at or above min using skiplist traversal
+            for i in (0..=inner.level).rev() {
+                while let Some(next) = (&(*current).forward)[i] {
+                    if below_min(&(*next).key) {
+                        current = next;
+                    } else {
+                        break;
+                    }
+                }
+            }
+            
+            // Collect elements up to max
+            while let Some(next) = (&(*current).forward)[0] {
+                if !within_max(&(*next).key) {
+                    break;
+                }
+                items.push(((*next).key.clone(), (*next).value.clone()));
+                current = next;
+            }
+        }
+        
+        RangeResult { items }
+    }
+
     /// Get the number of elements
     pub fn len(&self) -> usize {
         self.inner.read().unwrap().length
@@ -677,4 +727,21 @@ mod tests {
         assert_eq!(list.get_by_rank(1), Some((b"b".to_vec(), 1.0)));
         assert_eq!(list.get_by_rank(2), Some((b"c".to_vec(), 1.0)));
     }
+
+    #[test]
+    fn test_range_by_key() {
+        let list: SkipList<Vec<u8>, f64> = SkipList::new();
+        for key in ["e", "a", "c", "b", "d"] {
+            list.insert(key.as_bytes().to_vec(), 0.0);
+        }
+        let keys = |min: Bound<&[u8]>, max: Bound<&[u8]>| -> Vec<Vec<u8>> {
+            list.range_by_key(min, max).items.into_iter().map(|(key, _)| key).collect()
+        };
+        
+        assert_eq!(keys(Bound::Included(b"b"), Bound::Excluded(b"d")), [b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(keys(Bound::Excluded(b"b"), Bound::Included(b"d")), [b"c".to_vec(), b"d".to_vec()]);
+        assert_eq!(keys(Bound::Unbounded, Bound::Included(b"a")), [b"a".to_vec()]);
+        assert_eq!(keys(Bound::Included(b"dd"), Bound::Unbounded), [b"e".to_vec()]);
+        assert!(keys(Bound::Included(b"c"), Bound::Excluded(b"c")).is_empty());
+    }
 }
\ No newline at end of file
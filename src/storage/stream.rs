@@ -393,6 +393,11 @@ impl Stream {
         data.range_after(after_id, count)
     }
     
+    /// ID of the last entry added, which XREAD's `$` stands for
+    pub fn last_id(&self) -> StreamId {
+        self.data.lock().unwrap().last_id
+    }
+    
     pub fn first_entry(&self) -> Option<StreamEntry> {
         let data = self.data.lock().unwrap();
         data.entries.first().cloned()
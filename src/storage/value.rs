@@ -1,35 +1,255 @@
 //! Value types for storage engine
 //! 
 //! Defines all Redis-compatible data types and their operations.
+//!
+//! Lists, sets, hashes and sorted sets start in a compact encoding (a
+//! [`Listpack`], or an intset for sets of integers) and convert to their
+//! general one once they outgrow the [`EncodingLimits`], as in Redis. They
+//! never convert back, except when reloaded.
 
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Bound;
 use std::time::{Instant, Duration};
 use std::sync::Arc;
+use crate::storage::listpack::Listpack;
 use crate::storage::skiplist::SkipList;
 use crate::storage::stream::Stream;
+use crate::storage::shared;
+use crate::storage::lru::AccessClock;
+use crate::storage::memory::{AccessFrequency, LfuParams, MemoryManager};
 
 /// All possible Redis value types
 #[derive(Debug, Clone)]
 pub enum Value {
-    /// String value (bytes)
-    String(Vec<u8>),
+    /// String value (bytes, small integers share a buffer via `shared::intern`)
+    String(Arc<Vec<u8>>),
     
     /// List value (ordered collection)
-    List(VecDeque<Vec<u8>>),
+    List(ListValue),
     
     /// Set value (unordered unique collection)
-    Set(HashSet<Vec<u8>>),
+    Set(SetValue),
     
-    /// Hash value (field-value pairs)
-    Hash(HashMap<Vec<u8>, Vec<u8>>),
+    /// Hash value (field-value pairs with optional per-field expiration)
+    Hash(HashValue),
     
-    /// Sorted set value using skip list implementation
-    SortedSet(Arc<SkipList<Vec<u8>, f64>>),
+    /// Sorted set value (members ordered by score)
+    SortedSet(ZSetValue),
     
     /// Stream value for time-series data - direct storage for integrated architecture
     Stream(Stream),
 }
 
+/// Longest string Redis stores inline with its object header
+const EMBSTR_MAX_LEN: usize = 44;
+
+/// Sizes past which collections leave their compact encoding, as set by
+/// hash-max-listpack-entries and the other `*-max-*` parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingLimits {
+    /// Most fields a hash keeps in a listpack
+    pub hash_max_listpack_entries: usize,
+    
+    /// Longest field or value a hash keeps in a listpack
+    pub hash_max_listpack_value: usize,
+    
+    /// Most elements a list keeps in a listpack when positive; when negative,
+    /// most bytes, from 4KB for -1 to 64KB for -5
+    pub list_max_listpack_size: i64,
+    
+    /// Most members an all-integer set keeps in an intset
+    pub set_max_intset_entries: usize,
+    
+    /// Most members a set keeps in a listpack
+    pub set_max_listpack_entries: usize,
+    
+    /// Longest member a set keeps in a listpack
+    pub set_max_listpack_value: usize,
+    
+    /// Most members a sorted set keeps in a listpack
+    pub zset_max_listpack_entries: usize,
+    
+    /// Longest member a sorted set keeps in a listpack
+    pub zset_max_listpack_value: usize,
+}
+
+/// Bytes held by a string value's buffer
+pub fn string_memory_usage(bytes: &Arc<Vec<u8>>) -> usize {
+    // Shared integers are owned by the shared table, not the key
+    if shared::is_shared(bytes) {
+        0
+    } else {
+        MemoryManager::calculate_size(bytes)
+    }
+}
+
+/// Longest string read as a float by INCRBYFLOAT and HINCRBYFLOAT, as in Redis
+const MAX_FLOAT_CHARS: usize = 5 * 1024;
+
+/// Parse a float operand of INCRBYFLOAT and HINCRBYFLOAT
+///
+/// Accepts what Redis does: decimal and exponent forms and infinities, but
+/// not NaN nor surrounding spaces.
+pub fn parse_float(bytes: &[u8]) -> Option<f64> {
+    if bytes.len() > MAX_FLOAT_CHARS {
+        return None;
+    }
+    std::str::from_utf8(bytes).ok()?.parse::<f64>().ok().filter(|value| !value.is_nan())
+}
+
+/// Significant digits an f64 holds exactly through a decimal round trip
+const FLOAT_DIGITS: i32 = 15;
+
+/// Format the result of INCRBYFLOAT and HINCRBYFLOAT
+///
+/// Follows Redis's `%.17Lg` with trailing zeros trimmed: fixed notation, so
+/// 5.0e3 becomes "5000", unless the exponent is below -4 or at least 17, where
+/// 1e300 becomes "1e+300". Redis sums in long double, so its 17 digits do not
+/// show the binary error of 0.1 + 0.2; an f64 only carries 15 clean ones, so
+/// the digits are rounded there and the sum reads "0.3" as it does in Redis.
+pub fn format_float(value: f64) -> String {
+    // -0 prints as 0
+    let value = value + 0.0;
+    // The exponent after rounding, so 9.9999999999999999 counts as 1e1
+    let scientific = format!("{:.*e}", (FLOAT_DIGITS - 1) as usize, value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    
+    if !(-4..17).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        return format!("{}e{}{:02}", trim_fraction(mantissa), sign, exponent.abs());
+    }
+    let decimals = (FLOAT_DIGITS - 1 - exponent).max(0) as usize;
+    trim_fraction(&format!("{:.*}", decimals, value)).to_string()
+}
+
+/// Strip trailing zeros after the decimal point, and the point if none remain
+fn trim_fraction(digits: &str) -> &str {
+    if digits.contains('.') {
+        digits.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        digits
+    }
+}
+
+/// Total `size` of a collection's `len` items, extrapolated from the first
+/// `samples` of them (0 sizes every item)
+fn sampled_size<I: Iterator>(items: I, len: usize, samples: usize, size: impl Fn(I::Item) -> usize) -> usize {
+    if samples == 0 || len <= samples {
+        return items.map(size).sum();
+    }
+    let sampled: usize = items.take(samples).map(size).sum();
+    (sampled as f64 / samples as f64 * len as f64) as usize
+}
+
+/// List value: a listpack while small, then a deque of elements (what
+/// Redis reports as a quicklist)
+#[derive(Debug, Clone)]
+pub struct ListValue {
+    repr: ListRepr,
+}
+
+#[derive(Debug, Clone)]
+enum ListRepr {
+    Listpack(Listpack),
+    
+    /// Elements, with the bytes they hold
+    Quicklist(VecDeque<Vec<u8>>, usize),
+}
+
+/// Set value: an intset while it holds few integers, a listpack while it is
+/// small, then a hash table
+#[derive(Debug, Clone)]
+pub struct SetValue {
+    repr: SetRepr,
+}
+
+#[derive(Debug, Clone)]
+enum SetRepr {
+    /// Members, sorted
+    Intset(Vec<i64>),
+    
+    Listpack(Listpack),
+    
+    /// Members, with the bytes they hold
+    Hashtable(HashSet<Vec<u8>>, usize),
+}
+
+/// Hash value with optional per-field TTLs (Redis 7.4 hash field expiration)
+///
+/// Field expirations live alongside the fields themselves so that every code path
+/// that clones or persists a hash (GET-style reads, RDB, replication) carries them.
+/// Overwriting or removing a field always clears its TTL, matching Redis semantics.
+#[derive(Debug, Clone, Default)]
+pub struct HashValue {
+    /// Field-value pairs
+    fields: HashFields,
+    
+    /// Expiration deadlines for fields that have a TTL
+    field_expires: HashMap<Vec<u8>, Instant>,
+    
+    /// Bytes held by the field TTLs, and by the fields and values once they
+    /// are in a table, kept up to date by every mutation so the hash is sized
+    /// without walking it
+    bytes: usize,
+}
+
+#[derive(Debug, Clone)]
+enum HashFields {
+    /// Fields and values, alternating, in insertion order
+    Listpack(Listpack),
+    
+    Hashtable(HashMap<Vec<u8>, Vec<u8>>),
+}
+
+/// Sorted set value: a listpack while small, then a skip list
+#[derive(Debug, Clone)]
+pub struct ZSetValue {
+    repr: ZSetRepr,
+}
+
+#[derive(Debug, Clone)]
+enum ZSetRepr {
+    /// Members and their scores (as little-endian `f64`s), alternating,
+    /// ordered by score then member
+    Listpack(Listpack),
+    
+    SkipList(Arc<SkipList<Vec<u8>, f64>>),
+}
+
+/// Bytes a field and its value take in a hash
+fn field_size(field: &[u8], value: &[u8]) -> usize {
+    MemoryManager::calculate_size(field) + MemoryManager::calculate_size(value)
+}
+
+/// Bytes a field TTL takes in a hash
+fn field_expiration_size(field: &[u8]) -> usize {
+    MemoryManager::calculate_size(field) + std::mem::size_of::<Instant>()
+}
+
+/// Condition flags for conditional expiration (NX | XX | GT | LT)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpireCondition {
+    /// Only set when there is no current expiration
+    Nx,
+    /// Only set when there is a current expiration
+    Xx,
+    /// Only set when the new expiration is later than the current one
+    Gt,
+    /// Only set when the new expiration is earlier than the current one
+    Lt,
+}
+
+/// End of a list that LMOVE, LMPOP and their blocking forms pop from or push to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListEnd {
+    /// Head of the list
+    Left,
+    /// Tail of the list
+    Right,
+}
+
 /// Value type enumeration
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ValueType {
@@ -41,6 +261,20 @@ pub enum ValueType {
     Stream,
 }
 
+impl ValueType {
+    /// Name reported by TYPE and matched by SCAN TYPE
+    pub fn name(self) -> &'static str {
+        match self {
+            ValueType::String => "string",
+            ValueType::List => "list",
+            ValueType::Set => "set",
+            ValueType::Hash => "hash",
+            ValueType::SortedSet => "zset",
+            ValueType::Stream => "stream",
+        }
+    }
+}
+
 /// String encoding optimization
 #[derive(Debug, Clone, Copy)]
 pub enum StringEncoding {
@@ -62,7 +296,10 @@ pub struct ValueMetadata {
     pub created_at: Instant,
     
     /// Last access time for LRU
-    pub last_accessed: Instant,
+    pub last_accessed: AccessClock,
+    
+    /// Access frequency for LFU
+    pub access_frequency: AccessFrequency,
     
     /// String encoding type
     pub encoding: StringEncoding,
@@ -91,14 +328,59 @@ impl Value {
         }
     }
     
+    /// Name of this value's encoding (OBJECT ENCODING)
+    ///
+    /// Strings are stored one way; this reports the encoding Redis would pick
+    /// for them. Collections report the encoding they are actually in.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Value::String(bytes) => match std::str::from_utf8(bytes) {
+                Ok(s) if bytes.len() <= 20 && s.parse::<i64>().is_ok() => "int",
+                _ if bytes.len() <= EMBSTR_MAX_LEN => "embstr",
+                _ => "raw",
+            },
+            Value::List(list) => list.encoding(),
+            Value::Set(set) => set.encoding(),
+            Value::Hash(hash) => hash.encoding(),
+            Value::SortedSet(zset) => zset.encoding(),
+            Value::Stream(_) => "stream",
+        }
+    }
+    
+    /// Approximate bytes held by this value, not counting its key
+    ///
+    /// Lists and sets in their general encoding with more than `samples`
+    /// elements are extrapolated from the first `samples` of them, as MEMORY
+    /// USAGE does; 0 gives their running total. Compact encodings are sized
+    /// exactly, and hashes, sorted sets and streams keep their own total.
+    pub fn memory_usage(&self, samples: usize) -> usize {
+        match self {
+            Value::String(bytes) => string_memory_usage(bytes),
+            Value::List(list) => list.memory_usage(samples),
+            Value::Set(set) => set.memory_usage(samples),
+            Value::Hash(hash) => hash.memory_usage(),
+            Value::SortedSet(zset) => zset.memory_usage(),
+            Value::Stream(stream) => stream.memory_usage(),
+        }
+    }
+    
     /// Create a string value from bytes
     pub fn string<T: Into<Vec<u8>>>(data: T) -> Self {
-        Value::String(data.into())
+        Value::String(shared::intern(data.into()))
+    }
+    
+    /// Create a string value from a buffer that is already shared, such as a
+    /// request argument, without copying it
+    pub fn shared_string(bytes: Arc<Vec<u8>>) -> Self {
+        Value::String(shared::intern_shared(bytes))
     }
     
     /// Create an integer string value
     pub fn integer(n: i64) -> Self {
-        Value::String(n.to_string().into_bytes())
+        match shared::shared_integer(n) {
+            Some(bytes) => Value::String(bytes),
+            None => Value::String(Arc::new(n.to_string().into_bytes())),
+        }
     }
     
     /// Try to parse string value as integer
@@ -124,22 +406,22 @@ impl Value {
     
     /// Create an empty list
     pub fn empty_list() -> Self {
-        Value::List(VecDeque::new())
+        Value::List(ListValue::new())
     }
     
     /// Create an empty set
     pub fn empty_set() -> Self {
-        Value::Set(HashSet::new())
+        Value::Set(SetValue::new())
     }
     
     /// Create an empty hash
     pub fn empty_hash() -> Self {
-        Value::Hash(HashMap::new())
+        Value::Hash(HashValue::new())
     }
     
     /// Create an empty sorted set
     pub fn empty_sorted_set() -> Self {
-        Value::SortedSet(Arc::new(SkipList::new()))
+        Value::SortedSet(ZSetValue::new())
     }
     
     /// Create an empty stream value for size calculation
@@ -148,6 +430,800 @@ impl Value {
     }
 }
 
+impl EncodingLimits {
+    /// Whether a list of `len` elements taking `bytes` in a listpack may stay in it
+    fn list_fits(&self, len: usize, bytes: usize) -> bool {
+        match self.list_max_listpack_size {
+            size if size > 0 => len <= size as usize,
+            size => bytes <= 4096 << (size.unsigned_abs().clamp(1, 5) - 1),
+        }
+    }
+}
+
+impl Default for EncodingLimits {
+    /// Redis' defaults
+    fn default() -> Self {
+        EncodingLimits {
+            hash_max_listpack_entries: 128,
+            hash_max_listpack_value: 64,
+            list_max_listpack_size: -2,
+            set_max_intset_entries: 512,
+            set_max_listpack_entries: 128,
+            set_max_listpack_value: 64,
+            zset_max_listpack_entries: 128,
+            zset_max_listpack_value: 64,
+        }
+    }
+}
+
+impl ListValue {
+    /// Create an empty list
+    pub fn new() -> Self {
+        ListValue { repr: ListRepr::Listpack(Listpack::new()) }
+    }
+    
+    /// Number of elements
+    pub fn len(&self) -> usize {
+        match &self.repr {
+            ListRepr::Listpack(listpack) => listpack.len(),
+            ListRepr::Quicklist(items, _) => items.len(),
+        }
+    }
+    
+    /// Check if the list has no elements
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Name of the encoding (OBJECT ENCODING)
+    pub fn encoding(&self) -> &'static str {
+        match &self.repr {
+            ListRepr::Listpack(_) => "listpack",
+            ListRepr::Quicklist(..) => "quicklist",
+        }
+    }
+    
+    /// Bytes held by the list, extrapolated from `samples` elements once it
+    /// left the listpack (0 for the exact total)
+    pub fn memory_usage(&self, samples: usize) -> usize {
+        match &self.repr {
+            ListRepr::Listpack(listpack) => listpack.memory_usage(),
+            ListRepr::Quicklist(items, bytes) => {
+                let elements = if samples == 0 {
+                    *bytes
+                } else {
+                    sampled_size(items.iter(), items.len(), samples, |item| MemoryManager::calculate_size(item))
+                };
+                elements + std::mem::size_of::<VecDeque<Vec<u8>>>()
+            }
+        }
+    }
+    
+    /// Iterate over the elements, head first
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &[u8]> + '_> {
+        match &self.repr {
+            ListRepr::Listpack(listpack) => Box::new(listpack.iter()),
+            ListRepr::Quicklist(items, _) => Box::new(items.iter().map(Vec::as_slice)),
+        }
+    }
+    
+    /// Element at `index` from the head
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        match &self.repr {
+            ListRepr::Listpack(listpack) => listpack.get(index),
+            ListRepr::Quicklist(items, _) => items.get(index).map(Vec::as_slice),
+        }
+    }
+    
+    /// Add an element at one end
+    pub fn push(&mut self, end: ListEnd, element: Vec<u8>, limits: EncodingLimits) {
+        match &mut self.repr {
+            ListRepr::Listpack(listpack) => {
+                match end {
+                    ListEnd::Left => listpack.push_front(&element),
+                    ListEnd::Right => listpack.push_back(&element),
+                }
+                self.fit(limits);
+            }
+            ListRepr::Quicklist(items, bytes) => {
+                *bytes += MemoryManager::calculate_size(&element);
+                match end {
+                    ListEnd::Left => items.push_front(element),
+                    ListEnd::Right => items.push_back(element),
+                }
+            }
+        }
+    }
+    
+    /// Remove an element from one end
+    pub fn pop(&mut self, end: ListEnd) -> Option<Vec<u8>> {
+        match &mut self.repr {
+            ListRepr::Listpack(listpack) => match end {
+                ListEnd::Left => listpack.pop_front(),
+                ListEnd::Right => listpack.pop_back(),
+            },
+            ListRepr::Quicklist(items, bytes) => {
+                let element = match end {
+                    ListEnd::Left => items.pop_front(),
+                    ListEnd::Right => items.pop_back(),
+                }?;
+                *bytes -= MemoryManager::calculate_size(&element);
+                Some(element)
+            }
+        }
+    }
+    
+    /// Replace the element at `index`. Returns false if there is none.
+    pub fn set(&mut self, index: usize, element: Vec<u8>, limits: EncodingLimits) -> bool {
+        match &mut self.repr {
+            ListRepr::Listpack(listpack) => {
+                if !listpack.replace(index, &element) {
+                    return false;
+                }
+                self.fit(limits);
+                true
+            }
+            ListRepr::Quicklist(items, bytes) => match items.get_mut(index) {
+                Some(item) => {
+                    *bytes = *bytes + MemoryManager::calculate_size(&element) - MemoryManager::calculate_size(item);
+                    *item = element;
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+    
+    /// Keep only the elements `keep` accepts, given their index
+    pub fn retain(&mut self, mut keep: impl FnMut(usize, &[u8]) -> bool) {
+        match &mut self.repr {
+            ListRepr::Listpack(listpack) => listpack.retain(keep),
+            ListRepr::Quicklist(items, bytes) => {
+                let mut index = 0;
+                items.retain(|item| {
+                    let kept = keep(index, item);
+                    if !kept {
+                        *bytes -= MemoryManager::calculate_size(item);
+                    }
+                    index += 1;
+                    kept
+                });
+            }
+        }
+    }
+    
+    /// Leave the listpack if it outgrew list-max-listpack-size
+    fn fit(&mut self, limits: EncodingLimits) {
+        if let ListRepr::Listpack(listpack) = &self.repr {
+            if !limits.list_fits(listpack.len(), listpack.bytes()) {
+                let items: VecDeque<Vec<u8>> = listpack.iter().map(<[u8]>::to_vec).collect();
+                let bytes = items.iter().map(|item| MemoryManager::calculate_size(item)).sum();
+                self.repr = ListRepr::Quicklist(items, bytes);
+            }
+        }
+    }
+}
+
+/// Value of a set member an intset can hold: an integer in its canonical form,
+/// so that the member reads back unchanged
+fn intset_member(member: &[u8]) -> Option<i64> {
+    let digits = member.strip_prefix(b"-").unwrap_or(member);
+    if digits.is_empty() || digits.len() > 19 || !digits.iter().all(u8::is_ascii_digit) || (digits[0] == b'0' && member.len() > 1) {
+        return None;
+    }
+    std::str::from_utf8(member).ok()?.parse().ok()
+}
+
+impl SetValue {
+    /// Create an empty set
+    pub fn new() -> Self {
+        SetValue { repr: SetRepr::Intset(Vec::new()) }
+    }
+    
+    /// Number of members
+    pub fn len(&self) -> usize {
+        match &self.repr {
+            SetRepr::Intset(members) => members.len(),
+            SetRepr::Listpack(listpack) => listpack.len(),
+            SetRepr::Hashtable(members, _) => members.len(),
+        }
+    }
+    
+    /// Check if the set has no members
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Name of the encoding (OBJECT ENCODING)
+    pub fn encoding(&self) -> &'static str {
+        match &self.repr {
+            SetRepr::Intset(_) => "intset",
+            SetRepr::Listpack(_) => "listpack",
+            SetRepr::Hashtable(..) => "hashtable",
+        }
+    }
+    
+    /// Bytes held by the set, extrapolated from `samples` members once it is
+    /// a hash table (0 for the exact total)
+    pub fn memory_usage(&self, samples: usize) -> usize {
+        match &self.repr {
+            SetRepr::Intset(members) => members.len() * std::mem::size_of::<i64>() + std::mem::size_of::<Vec<i64>>(),
+            SetRepr::Listpack(listpack) => listpack.memory_usage(),
+            SetRepr::Hashtable(members, bytes) => {
+                let elements = if samples == 0 {
+                    *bytes
+                } else {
+                    sampled_size(members.iter(), members.len(), samples, |member| MemoryManager::calculate_size(member))
+                };
+                elements + std::mem::size_of::<HashSet<Vec<u8>>>()
+            }
+        }
+    }
+    
+    /// Check if `member` is in the set
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match &self.repr {
+            SetRepr::Intset(members) => intset_member(member).is_some_and(|n| members.binary_search(&n).is_ok()),
+            SetRepr::Listpack(listpack) => listpack.find(member).is_some(),
+            SetRepr::Hashtable(members, _) => members.contains(member),
+        }
+    }
+    
+    /// Iterate over the members
+    pub fn members(&self) -> Box<dyn Iterator<Item = Vec<u8>> + '_> {
+        match &self.repr {
+            SetRepr::Intset(members) => Box::new(members.iter().map(|n| n.to_string().into_bytes())),
+            SetRepr::Listpack(listpack) => Box::new(listpack.iter().map(<[u8]>::to_vec)),
+            SetRepr::Hashtable(members, _) => Box::new(members.iter().cloned()),
+        }
+    }
+    
+    /// Add a member. Returns false if it was already there.
+    pub fn insert(&mut self, member: Vec<u8>, limits: EncodingLimits) -> bool {
+        if let SetRepr::Intset(members) = &mut self.repr {
+            match intset_member(&member).map(|n| (n, members.binary_search(&n))) {
+                Some((_, Ok(_))) => return false,
+                Some((n, Err(at))) if members.len() < limits.set_max_intset_entries => {
+                    members.insert(at, n);
+                    return true;
+                }
+                // Past the intset, the set stays compact only if it fits a listpack
+                _ => {
+                    let compact = members.len() < limits.set_max_listpack_entries
+                        && member.len() <= limits.set_max_listpack_value
+                        && members.iter().all(|n| n.to_string().len() <= limits.set_max_listpack_value);
+                    let listpack = members.iter().fold(Listpack::new(), |mut listpack, n| {
+                        listpack.push_back(n.to_string().as_bytes());
+                        listpack
+                    });
+                    self.repr = SetRepr::Listpack(listpack);
+                    if !compact {
+                        self.convert_to_hashtable();
+                    }
+                }
+            }
+        }
+        
+        if let SetRepr::Listpack(listpack) = &mut self.repr {
+            if listpack.find(&member).is_some() {
+                return false;
+            }
+            if listpack.len() < limits.set_max_listpack_entries && member.len() <= limits.set_max_listpack_value {
+                listpack.push_back(&member);
+                return true;
+            }
+            self.convert_to_hashtable();
+        }
+        
+        match &mut self.repr {
+            SetRepr::Hashtable(members, bytes) => {
+                let size = MemoryManager::calculate_size(&member);
+                let added = members.insert(member);
+                if added {
+                    *bytes += size;
+                }
+                added
+            }
+            _ => unreachable!("compact sets are handled above"),
+        }
+    }
+    
+    /// Remove a member. Returns false if it wasn't there.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match &mut self.repr {
+            SetRepr::Intset(members) => {
+                match intset_member(member).and_then(|n| members.binary_search(&n).ok()) {
+                    Some(at) => {
+                        members.remove(at);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            SetRepr::Listpack(listpack) => match listpack.find(member) {
+                Some(index) => listpack.remove(index).is_some(),
+                None => false,
+            },
+            SetRepr::Hashtable(members, bytes) => {
+                let removed = members.remove(member);
+                if removed {
+                    *bytes -= MemoryManager::calculate_size(member);
+                }
+                removed
+            }
+        }
+    }
+    
+    /// Move the members of a listpack to a hash table
+    fn convert_to_hashtable(&mut self) {
+        if let SetRepr::Listpack(listpack) = &self.repr {
+            let members: HashSet<Vec<u8>> = listpack.iter().map(<[u8]>::to_vec).collect();
+            let bytes = members.iter().map(|member| MemoryManager::calculate_size(member)).sum();
+            self.repr = SetRepr::Hashtable(members, bytes);
+        }
+    }
+}
+
+impl HashValue {
+    /// Create an empty hash
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Number of fields in the hash
+    pub fn len(&self) -> usize {
+        match &self.fields {
+            HashFields::Listpack(listpack) => listpack.len() / 2,
+            HashFields::Hashtable(fields) => fields.len(),
+        }
+    }
+    
+    /// Check if the hash has no fields
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Name of the encoding (OBJECT ENCODING)
+    pub fn encoding(&self) -> &'static str {
+        match &self.fields {
+            HashFields::Listpack(_) if self.has_field_ttls() => "listpackex",
+            HashFields::Listpack(_) => "listpack",
+            HashFields::Hashtable(_) => "hashtable",
+        }
+    }
+    
+    /// Get a field value
+    pub fn get(&self, field: &[u8]) -> Option<&[u8]> {
+        match &self.fields {
+            HashFields::Listpack(listpack) => listpack.pairs().find(|(name, _)| *name == field).map(|(_, value)| value),
+            HashFields::Hashtable(fields) => fields.get(field).map(Vec::as_slice),
+        }
+    }
+    
+    /// Check if a field exists
+    pub fn contains_key(&self, field: &[u8]) -> bool {
+        self.get(field).is_some()
+    }
+    
+    /// Bytes held by the fields, values and field TTLs
+    pub fn memory_usage(&self) -> usize {
+        match &self.fields {
+            HashFields::Listpack(listpack) => self.bytes + listpack.memory_usage(),
+            HashFields::Hashtable(_) => self.bytes + std::mem::size_of::<HashMap<Vec<u8>, Vec<u8>>>(),
+        }
+    }
+    
+    /// Set a field value, clearing any TTL on the field. Returns the previous value.
+    pub fn insert(&mut self, field: Vec<u8>, value: Vec<u8>, limits: EncodingLimits) -> Option<Vec<u8>> {
+        self.clear_field_expiration(&field);
+        
+        if let HashFields::Listpack(listpack) = &mut self.fields {
+            let fits = field.len() <= limits.hash_max_listpack_value && value.len() <= limits.hash_max_listpack_value;
+            match listpack.pairs().position(|(name, _)| name == field.as_slice()) {
+                Some(pair) if fits => {
+                    let previous = listpack.get(2 * pair + 1).map(<[u8]>::to_vec);
+                    listpack.replace(2 * pair + 1, &value);
+                    return previous;
+                }
+                None if fits && listpack.len() / 2 < limits.hash_max_listpack_entries => {
+                    listpack.push_back(&field);
+                    listpack.push_back(&value);
+                    return None;
+                }
+                _ => self.convert_to_hashtable(),
+            }
+        }
+        
+        let HashFields::Hashtable(fields) = &mut self.fields else {
+            unreachable!("compact hashes are handled above");
+        };
+        let added = field_size(&field, &value);
+        let field_bytes = MemoryManager::calculate_size(&field);
+        let previous = fields.insert(field, value);
+        self.bytes += added;
+        if let Some(previous) = &previous {
+            self.bytes -= field_bytes + MemoryManager::calculate_size(previous);
+        }
+        previous
+    }
+    
+    /// Remove a field and its TTL. Returns the removed value.
+    pub fn remove(&mut self, field: &[u8]) -> Option<Vec<u8>> {
+        self.clear_field_expiration(field);
+        match &mut self.fields {
+            HashFields::Listpack(listpack) => {
+                let pair = listpack.pairs().position(|(name, _)| name == field)?;
+                listpack.remove(2 * pair);
+                listpack.remove(2 * pair)
+            }
+            HashFields::Hashtable(fields) => {
+                let removed = fields.remove(field);
+                if let Some(value) = &removed {
+                    self.bytes -= field_size(field, value);
+                }
+                removed
+            }
+        }
+    }
+    
+    /// Iterate over field-value pairs
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&[u8], &[u8])> + '_> {
+        match &self.fields {
+            HashFields::Listpack(listpack) => Box::new(listpack.pairs()),
+            HashFields::Hashtable(fields) => Box::new(fields.iter().map(|(field, value)| (field.as_slice(), value.as_slice()))),
+        }
+    }
+    
+    /// Iterate over field names
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.iter().map(|(field, _)| field)
+    }
+    
+    /// Iterate over field values
+    pub fn values(&self) -> impl Iterator<Item = &[u8]> {
+        self.iter().map(|(_, value)| value)
+    }
+    
+    /// Check if any field carries a TTL
+    pub fn has_field_ttls(&self) -> bool {
+        !self.field_expires.is_empty()
+    }
+    
+    /// Get the expiration deadline of a field, if it has one
+    pub fn field_expiration(&self, field: &[u8]) -> Option<Instant> {
+        self.field_expires.get(field).copied()
+    }
+    
+    /// Set the expiration deadline of an existing field. Returns false if the field doesn't exist.
+    pub fn set_field_expiration(&mut self, field: &[u8], expires_at: Instant) -> bool {
+        if !self.contains_key(field) {
+            return false;
+        }
+        if self.field_expires.insert(field.to_vec(), expires_at).is_none() {
+            self.bytes += field_expiration_size(field);
+        }
+        true
+    }
+    
+    /// Remove the TTL of a field. Returns true if the field had a TTL.
+    pub fn clear_field_expiration(&mut self, field: &[u8]) -> bool {
+        if self.field_expires.is_empty() || self.field_expires.remove(field).is_none() {
+            return false;
+        }
+        self.bytes -= field_expiration_size(field);
+        true
+    }
+    
+    /// Remove all fields whose TTL has elapsed. Returns the number of fields removed.
+    pub fn purge_expired_fields(&mut self) -> usize {
+        if self.field_expires.is_empty() {
+            return 0;
+        }
+        
+        let now = Instant::now();
+        let expired: Vec<Vec<u8>> = self.field_expires.iter()
+            .filter(|(_, expires_at)| **expires_at <= now)
+            .map(|(field, _)| field.clone())
+            .collect();
+        
+        for field in &expired {
+            self.remove(field);
+        }
+        
+        expired.len()
+    }
+    
+    /// Move the fields of a listpack to a hash table
+    fn convert_to_hashtable(&mut self) {
+        if let HashFields::Listpack(listpack) = &self.fields {
+            let fields: HashMap<Vec<u8>, Vec<u8>> = listpack.pairs()
+                .map(|(field, value)| (field.to_vec(), value.to_vec()))
+                .collect();
+            self.bytes += fields.iter().map(|(field, value)| field_size(field, value)).sum::<usize>();
+            self.fields = HashFields::Hashtable(fields);
+        }
+    }
+}
+
+/// Score stored in a sorted set listpack
+fn listpack_score(entry: &[u8]) -> f64 {
+    f64::from_le_bytes(entry.try_into().expect("sorted set listpack scores are 8 bytes"))
+}
+
+/// Order of sorted set members: by score, NaN last, then by member, as in the skip list
+fn score_order(score: f64, member: &[u8], other_score: f64, other_member: &[u8]) -> Ordering {
+    score.partial_cmp(&other_score)
+        .unwrap_or_else(|| score.is_nan().cmp(&other_score.is_nan()))
+        .then_with(|| member.cmp(other_member))
+}
+
+impl ZSetValue {
+    /// Create an empty sorted set
+    pub fn new() -> Self {
+        ZSetValue { repr: ZSetRepr::Listpack(Listpack::new()) }
+    }
+    
+    /// Number of members
+    pub fn len(&self) -> usize {
+        match &self.repr {
+            ZSetRepr::Listpack(listpack) => listpack.len() / 2,
+            ZSetRepr::SkipList(skiplist) => skiplist.len(),
+        }
+    }
+    
+    /// Check if the sorted set has no members
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Name of the encoding (OBJECT ENCODING)
+    pub fn encoding(&self) -> &'static str {
+        match &self.repr {
+            ZSetRepr::Listpack(_) => "listpack",
+            ZSetRepr::SkipList(_) => "skiplist",
+        }
+    }
+    
+    /// Bytes held by the members and scores
+    pub fn memory_usage(&self) -> usize {
+        match &self.repr {
+            ZSetRepr::Listpack(listpack) => listpack.memory_usage(),
+            ZSetRepr::SkipList(skiplist) => skiplist.memory_usage(),
+        }
+    }
+    
+    /// Members and scores of a listpack, in order
+    fn listpack_items(listpack: &Listpack) -> impl Iterator<Item = (&[u8], f64)> {
+        listpack.pairs().map(|(member, score)| (member, listpack_score(score)))
+    }
+    
+    /// Score of a member
+    pub fn get_score(&self, member: &[u8]) -> Option<f64> {
+        match &self.repr {
+            ZSetRepr::Listpack(listpack) => Self::listpack_items(listpack).find(|(name, _)| *name == member).map(|(_, score)| score),
+            ZSetRepr::SkipList(skiplist) => skiplist.get_score(member),
+        }
+    }
+    
+    /// 0-based position of a member in score order
+    pub fn get_rank(&self, member: &[u8]) -> Option<usize> {
+        match &self.repr {
+            ZSetRepr::Listpack(listpack) => listpack.pairs().position(|(name, _)| name == member),
+            ZSetRepr::SkipList(skiplist) => skiplist.get_rank(member),
+        }
+    }
+    
+    /// Add a member or change its score. Returns the previous score.
+    pub fn insert(&mut self, member: Vec<u8>, score: f64, limits: EncodingLimits) -> Option<f64> {
+        if let ZSetRepr::Listpack(listpack) = &mut self.repr {
+            let previous = match listpack.pairs().position(|(name, _)| name == member.as_slice()) {
+                Some(pair) => {
+                    listpack.remove(2 * pair);
+                    listpack.remove(2 * pair).map(|score| listpack_score(&score))
+                }
+                None => None,
+            };
+            
+            if listpack.len() / 2 < limits.zset_max_listpack_entries && member.len() <= limits.zset_max_listpack_value {
+                let at = Self::listpack_items(listpack)
+                    .position(|(name, other)| score_order(other, name, score, &member) == Ordering::Greater)
+                    .unwrap_or(listpack.len() / 2);
+                listpack.insert(2 * at, &member);
+                listpack.insert(2 * at + 1, &score.to_le_bytes());
+                return previous;
+            }
+            
+            let skiplist = SkipList::new();
+            for (name, score) in Self::listpack_items(listpack) {
+                skiplist.insert(name.to_vec(), score);
+            }
+            skiplist.insert(member, score);
+            self.repr = ZSetRepr::SkipList(Arc::new(skiplist));
+            return previous;
+        }
+        
+        match self.owned_skiplist() {
+            Some(skiplist) => skiplist.insert(member, score),
+            None => unreachable!("compact sorted sets are handled above"),
+        }
+    }
+    
+    /// Remove a member. Returns its score.
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        match &mut self.repr {
+            ZSetRepr::Listpack(listpack) => {
+                let pair = listpack.pairs().position(|(name, _)| name == member)?;
+                listpack.remove(2 * pair);
+                listpack.remove(2 * pair).map(|score| listpack_score(&score))
+            }
+            ZSetRepr::SkipList(_) => self.owned_skiplist()?.remove(member),
+        }
+    }
+    
+    /// The skip list, copied first if a clone of this set still shares it
+    ///
+    /// Clones share the skip list, which changes in place, so a set about to
+    /// change takes its own copy unless it is the only holder.
+    fn owned_skiplist(&mut self) -> Option<&SkipList<Vec<u8>, f64>> {
+        let ZSetRepr::SkipList(skiplist) = &mut self.repr else { return None };
+        if Arc::strong_count(skiplist) > 1 {
+            let copy = SkipList::new();
+            for (member, score) in skiplist.get_all_items() {
+                copy.insert(member, score);
+            }
+            *skiplist = Arc::new(copy);
+        }
+        Some(skiplist)
+    }
+    
+    /// Members from rank `start` to `stop` (inclusive), in score order
+    pub fn range_by_rank(&self, start: usize, stop: usize) -> Vec<(Vec<u8>, f64)> {
+        match &self.repr {
+            ZSetRepr::Listpack(listpack) => Self::listpack_items(listpack)
+                .skip(start)
+                .take((stop + 1).saturating_sub(start))
+                .map(|(member, score)| (member.to_vec(), score))
+                .collect(),
+            ZSetRepr::SkipList(skiplist) => skiplist.range_by_rank(start, stop).items,
+        }
+    }
+    
+    /// Members scored from `min` to `max` (inclusive), in score order
+    pub fn range_by_score(&self, min: f64, max: f64) -> Vec<(Vec<u8>, f64)> {
+        match &self.repr {
+            ZSetRepr::Listpack(listpack) => Self::listpack_items(listpack)
+                .skip_while(|(_, score)| *score < min)
+                .take_while(|(_, score)| *score <= max)
+                .map(|(member, score)| (member.to_vec(), score))
+                .collect(),
+            ZSetRepr::SkipList(skiplist) => skiplist.range_by_score(min, max).items,
+        }
+    }
+    
+    /// Members between two lex bounds, assuming they share one score
+    pub fn range_by_key(&self, min: Bound<&[u8]>, max: Bound<&[u8]>) -> Vec<(Vec<u8>, f64)> {
+        match &self.repr {
+            ZSetRepr::Listpack(listpack) => Self::listpack_items(listpack)
+                .skip_while(|(member, _)| match min {
+                    Bound::Included(min) => *member < min,
+                    Bound::Excluded(min) => *member <= min,
+                    Bound::Unbounded => false,
+                })
+                .take_while(|(member, _)| match max {
+                    Bound::Included(max) => *member <= max,
+                    Bound::Excluded(max) => *member < max,
+                    Bound::Unbounded => true,
+                })
+                .map(|(member, score)| (member.to_vec(), score))
+                .collect(),
+            ZSetRepr::SkipList(skiplist) => skiplist.range_by_key(min, max).items,
+        }
+    }
+    
+    /// Every member with its score, in score order
+    pub fn items(&self) -> Vec<(Vec<u8>, f64)> {
+        self.range_by_rank(0, usize::MAX - 1)
+    }
+}
+
+impl ListEnd {
+    /// Parse LEFT or RIGHT (case-insensitive)
+    pub fn parse(end: &[u8]) -> Option<Self> {
+        match end.to_ascii_uppercase().as_slice() {
+            b"LEFT" => Some(ListEnd::Left),
+            b"RIGHT" => Some(ListEnd::Right),
+            _ => None,
+        }
+    }
+    
+    /// Argument naming this end, as LMOVE and LMPOP take it
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ListEnd::Left => "LEFT",
+            ListEnd::Right => "RIGHT",
+        }
+    }
+}
+
+impl ExpireCondition {
+    /// Parse a condition flag (case-insensitive)
+    pub fn parse(flag: &[u8]) -> Option<Self> {
+        match flag.to_ascii_uppercase().as_slice() {
+            b"NX" => Some(ExpireCondition::Nx),
+            b"XX" => Some(ExpireCondition::Xx),
+            b"GT" => Some(ExpireCondition::Gt),
+            b"LT" => Some(ExpireCondition::Lt),
+            _ => None,
+        }
+    }
+    
+    /// Parse the trailing flags of the EXPIRE family, which may be combined (XX GT).
+    /// Errors carry the Redis message without the "ERR " prefix.
+    pub fn parse_flags<T: AsRef<[u8]>>(flags: &[T]) -> std::result::Result<Vec<Self>, String> {
+        let mut conditions = Vec::new();
+        for flag in flags {
+            match Self::parse(flag.as_ref()) {
+                Some(condition) if !conditions.contains(&condition) => conditions.push(condition),
+                Some(_) => {}
+                None => return Err(format!("Unsupported option {}", String::from_utf8_lossy(flag.as_ref()))),
+            }
+        }
+        if conditions.contains(&ExpireCondition::Nx) && conditions.len() > 1 {
+            return Err("NX and XX, GT or LT options at the same time are not compatible".to_string());
+        }
+        if conditions.contains(&ExpireCondition::Gt) && conditions.contains(&ExpireCondition::Lt) {
+            return Err("GT and LT options at the same time are not compatible".to_string());
+        }
+        Ok(conditions)
+    }
+    
+    /// Check whether a new deadline may replace the current one.
+    /// A missing expiration counts as infinite TTL, so GT never applies and LT always does.
+    pub fn allows(&self, current: Option<Instant>, new_deadline: Instant) -> bool {
+        match (self, current) {
+            (ExpireCondition::Nx, current) => current.is_none(),
+            (ExpireCondition::Xx, current) => current.is_some(),
+            (ExpireCondition::Gt, Some(current)) => new_deadline > current,
+            (ExpireCondition::Gt, None) => false,
+            (ExpireCondition::Lt, Some(current)) => new_deadline < current,
+            (ExpireCondition::Lt, None) => true,
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a HashValue {
+    type Item = (&'a [u8], &'a [u8]);
+    type IntoIter = Box<dyn Iterator<Item = (&'a [u8], &'a [u8])> + 'a>;
+    
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Default for ListValue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for SetValue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for HashFields {
+    fn default() -> Self {
+        HashFields::Listpack(Listpack::new())
+    }
+}
+
+impl Default for ZSetValue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ValueMetadata {
     /// Create new metadata for a value
     pub fn new() -> Self {
@@ -155,7 +1231,8 @@ impl ValueMetadata {
         ValueMetadata {
             expires_at: None,
             created_at: now,
-            last_accessed: now,
+            last_accessed: AccessClock::new(),
+            access_frequency: AccessFrequency::new(),
             encoding: StringEncoding::Raw,
         }
     }
@@ -166,7 +1243,8 @@ impl ValueMetadata {
         ValueMetadata {
             expires_at: Some(now + expires_in),
             created_at: now,
-            last_accessed: now,
+            last_accessed: AccessClock::new(),
+            access_frequency: AccessFrequency::new(),
             encoding: StringEncoding::Raw,
         }
     }
@@ -178,9 +1256,10 @@ impl ValueMetadata {
             .unwrap_or(false)
     }
     
-    /// Update last access time
-    pub fn touch(&mut self) {
-        self.last_accessed = Instant::now();
+    /// Update last access time and access frequency
+    pub fn touch(&self, lfu: LfuParams) {
+        self.access_frequency.touch(self.last_accessed.idle_time(), lfu);
+        self.last_accessed.touch();
     }
     
     /// Set expiration time
@@ -216,9 +1295,9 @@ impl StoredValue {
         self.metadata.is_expired()
     }
     
-    /// Touch this value (update access time)
-    pub fn touch(&mut self) {
-        self.metadata.touch();
+    /// Touch this value (update access time and frequency)
+    pub fn touch(&self, lfu: LfuParams) {
+        self.metadata.touch(lfu);
     }
 }
 
@@ -230,7 +1309,7 @@ impl Default for ValueMetadata {
 
 impl Default for Value {
     fn default() -> Self {
-        Value::SortedSet(Arc::new(SkipList::new()))
+        Value::empty_sorted_set()
     }
 }
 
@@ -260,13 +1339,116 @@ mod tests {
         assert!(stored.is_expired());
     }
     
+    #[test]
+    fn test_hash_field_expiration() {
+        let mut hash = HashValue::new();
+        let limits = EncodingLimits::default();
+        hash.insert(b"a".to_vec(), b"1".to_vec(), limits);
+        hash.insert(b"b".to_vec(), b"2".to_vec(), limits);
+        
+        assert!(hash.set_field_expiration(b"a", Instant::now()));
+        assert!(!hash.set_field_expiration(b"missing", Instant::now()));
+        assert!(hash.has_field_ttls());
+        
+        assert_eq!(hash.purge_expired_fields(), 1);
+        assert!(!hash.contains_key(b"a"));
+        assert!(!hash.has_field_ttls());
+        
+        // Overwriting a field clears its TTL
+        hash.set_field_expiration(b"b", Instant::now() + Duration::from_secs(60));
+        hash.insert(b"b".to_vec(), b"3".to_vec(), limits);
+        assert_eq!(hash.field_expiration(b"b"), None);
+    }
+    
+    #[test]
+    fn test_float_parse_and_format() {
+        assert_eq!(parse_float(b"1.5e2"), Some(150.0));
+        assert_eq!(parse_float(b"-inf"), Some(f64::NEG_INFINITY));
+        assert_eq!(parse_float(b"nan"), None);
+        assert_eq!(parse_float(b" 1"), None);
+        assert_eq!(parse_float(b""), None);
+        
+        assert_eq!(format_float(3.0), "3");
+        assert_eq!(format_float(-0.0), "0");
+        assert_eq!(format_float(0.1 + 0.2), "0.3");
+        assert_eq!(format_float(1.5e16), "15000000000000000");
+        assert_eq!(format_float(1e17), "1e+17");
+        assert_eq!(format_float(1e300), "1e+300");
+        assert_eq!(format_float(-1.25e-300), "-1.25e-300");
+        assert_eq!(format_float(0.0001), "0.0001");
+        assert_eq!(format_float(0.00001), "1e-05");
+    }
+    
+    #[test]
+    fn test_compact_encodings_convert() {
+        let limits = EncodingLimits { set_max_intset_entries: 3, set_max_listpack_entries: 4, ..EncodingLimits::default() };
+        
+        // A set of integers is an intset until a non-integer or too many members
+        let mut set = SetValue::new();
+        for member in ["3", "1", "2"] {
+            assert!(set.insert(member.as_bytes().to_vec(), limits));
+        }
+        assert!(!set.insert(b"2".to_vec(), limits));
+        assert_eq!(set.encoding(), "intset");
+        assert_eq!(set.members().collect::<Vec<_>>(), vec![b"1".to_vec(), b"2".to_vec(), b"3".to_vec()]);
+        set.insert(b"01".to_vec(), limits);
+        assert_eq!(set.encoding(), "listpack");
+        set.insert(b"x".to_vec(), limits);
+        assert_eq!(set.encoding(), "hashtable");
+        assert!(set.contains(b"01") && set.contains(b"3"));
+        
+        // A hash converts on a long value
+        let mut hash = HashValue::new();
+        hash.insert(b"f".to_vec(), b"v".to_vec(), limits);
+        assert_eq!(hash.encoding(), "listpack");
+        hash.insert(b"g".to_vec(), vec![b'x'; 65], limits);
+        assert_eq!(hash.encoding(), "hashtable");
+        assert_eq!(hash.get(b"f"), Some(&b"v"[..]));
+        
+        // A sorted set keeps its order in either encoding
+        let limits = EncodingLimits { zset_max_listpack_entries: 3, ..limits };
+        let mut zset = ZSetValue::new();
+        for (member, score) in [("c", 2.0), ("a", 2.0), ("b", 1.0)] {
+            zset.insert(member.as_bytes().to_vec(), score, limits);
+        }
+        assert_eq!(zset.encoding(), "listpack");
+        let order = |zset: &ZSetValue| zset.items().into_iter().map(|(member, _)| member).collect::<Vec<_>>();
+        assert_eq!(order(&zset), vec![b"b".to_vec(), b"a".to_vec(), b"c".to_vec()]);
+        assert_eq!(zset.insert(b"a".to_vec(), 0.5, limits), Some(2.0));
+        assert_eq!(zset.get_rank(b"a"), Some(0));
+        zset.insert(b"d".to_vec(), 0.0, limits);
+        assert_eq!(zset.encoding(), "skiplist");
+        assert_eq!(order(&zset), vec![b"d".to_vec(), b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+        
+        // A list converts by size: here by element count
+        let limits = EncodingLimits { list_max_listpack_size: 2, ..limits };
+        let mut list = ListValue::new();
+        list.push(ListEnd::Right, b"b".to_vec(), limits);
+        list.push(ListEnd::Left, b"a".to_vec(), limits);
+        assert_eq!(list.encoding(), "listpack");
+        list.push(ListEnd::Right, b"c".to_vec(), limits);
+        assert_eq!(list.encoding(), "quicklist");
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&b"a"[..], b"b", b"c"]);
+    }
+    
+    #[test]
+    fn test_expire_flags() {
+        assert_eq!(ExpireCondition::parse_flags::<&[u8]>(&[]), Ok(vec![]));
+        assert_eq!(ExpireCondition::parse_flags(&[b"xx", b"GT"]), Ok(vec![ExpireCondition::Xx, ExpireCondition::Gt]));
+        assert_eq!(ExpireCondition::parse_flags(&[b"NX", b"GT"]).unwrap_err(),
+            "NX and XX, GT or LT options at the same time are not compatible");
+        assert_eq!(ExpireCondition::parse_flags(&[b"GT", b"LT"]).unwrap_err(),
+            "GT and LT options at the same time are not compatible");
+        assert_eq!(ExpireCondition::parse_flags(&[b"KEEPTTL"]).unwrap_err(), "Unsupported option KEEPTTL");
+    }
+    
     #[test]
     fn test_touch() {
-        let mut stored = StoredValue::new(Value::string("test"));
-        let initial_access = stored.metadata.last_accessed;
+        let stored = StoredValue::new(Value::string("test"));
+        let initial_access = stored.metadata.last_accessed.clone();
         
         std::thread::sleep(Duration::from_millis(1));
-        stored.touch();
+        stored.touch(LfuParams::default());
         
         assert!(stored.metadata.last_accessed > initial_access);
     }
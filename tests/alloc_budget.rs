@@ -0,0 +1,190 @@
+//! Allocation budgets for scripting hot paths
+//!
+//! Counts heap allocations (Rust and Lua heap, since the VM allocates through
+//! the global allocator) for canonical script operations and fails when one
+//! exceeds its budget, so hot-path regressions show up in CI rather than in
+//! production latency. Enabled with `cargo test --features alloc-profile`.
+//!
+//! Each operation runs inside a real EVAL. The per-operation cost is the
+//! difference between running the loop N times and zero times, divided by N,
+//! which cancels out the fixed cost of setting up the script context.
+
+#![cfg(feature = "alloc-profile")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::Arc;
+use ferrous::storage::engine::StorageEngine;
+use ferrous::storage::lua_engine::{LuaEngine, LuaCommandContext};
+
+/// System allocator that counts allocations made by the current thread
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    // try_with: allocations can happen while thread-locals are being torn down
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+    
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc_zeroed(layout)
+    }
+    
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+    
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Iterations per measurement
+const ITERATIONS: u64 = 2000;
+
+/// Allocations allowed per redis.call (argument collection, parsing, execution, reply conversion)
+const BRIDGE_BUDGET: f64 = 25.0;
+
+fn allocations() -> u64 {
+    ALLOCATIONS.with(|count| count.get())
+}
+
+/// Average allocations per loop iteration of `body`, run with `n` bound to the iteration count
+fn allocations_per_op(body: &str) -> f64 {
+    let storage = StorageEngine::new_in_memory();
+    storage.set_string(0, b"key".to_vec(), b"value".to_vec()).unwrap();
+    let engine = LuaEngine::new(storage.clone()).unwrap();
+    let ctx = LuaCommandContext { db_index: 0, storage };
+    let script = format!("local n = tonumber(ARGV[1])\n{}\nreturn 1", body);
+    
+    let mut measure = |n: u64| {
+        let before = allocations();
+        engine.eval(&script, vec![Arc::new(b"key".to_vec())], vec![Arc::new(n.to_string().into_bytes())], &ctx).unwrap();
+        allocations() - before
+    };
+    
+    // Warm up lazily initialized state (script caches, shared objects)
+    measure(ITERATIONS);
+    
+    let baseline = measure(0);
+    let loaded = measure(ITERATIONS);
+    loaded.saturating_sub(baseline) as f64 / ITERATIONS as f64
+}
+
+fn assert_budget(name: &str, body: &str, budget: f64) {
+    let per_op = allocations_per_op(body);
+    println!("{:<24} {:>8.2} allocations/op (budget {})", name, per_op, budget);
+    assert!(per_op <= budget, "{} allocates {:.2} times per operation, budget is {}", name, per_op, budget);
+}
+
+#[test]
+fn test_table_insert_budget() {
+    // Amortized array growth only
+    assert_budget("table insert", "local t = {} for i = 1, n do t[#t + 1] = i end", 0.1);
+}
+
+#[test]
+fn test_string_concat_budget() {
+    // The new string plus the VM's concatenation buffer
+    assert_budget("string concat", "local s = '' for i = 1, n do s = s .. 'x' end", 2.1);
+}
+
+#[test]
+fn test_function_call_budget() {
+    // Calls of Lua functions must not touch the heap
+    assert_budget("function call", "local function f(a) return a end for i = 1, n do f(i) end", 0.01);
+}
+
+#[test]
+fn test_redis_call_round_trip_budget() {
+    assert_budget("redis.call round-trip", "for i = 1, n do redis.call('GET', KEYS[1]) end", BRIDGE_BUDGET);
+}
+
+#[test]
+fn test_redis_mcall_batch_budget() {
+    // Batching must not cost more per command than individual calls
+    // Includes building the {'GET', key} table for each entry
+    assert_budget("redis.mcall per command",
+                  "local batch = {} for i = 1, n do batch[i] = {'GET', KEYS[1]} end redis.mcall(batch)",
+                  BRIDGE_BUDGET + 3.0);
+}
+
+#[test]
+fn test_eval_argument_budget() {
+    use ferrous::protocol::RespFrame;
+    use ferrous::storage::commands::lua::handle_eval;
+    
+    // ARGV entries are shared with the request frame and copied once, into their Lua strings:
+    // the string plus the C closure mlua pushes to create it under the memory limit
+    let storage = StorageEngine::new_in_memory();
+    let bulk = |bytes: &[u8]| RespFrame::BulkString(Some(Arc::new(bytes.to_vec())));
+    let eval = |argc: u64| {
+        let mut parts = vec![bulk(b"EVAL"), bulk(b"return #ARGV"), bulk(b"0")];
+        parts.extend((0..argc).map(|i| bulk(format!("argument-{}", i).as_bytes())));
+        let before = allocations();
+        handle_eval(&storage, &parts).unwrap();
+        allocations() - before
+    };
+    
+    eval(ITERATIONS);
+    let baseline = eval(0);
+    let loaded = eval(ITERATIONS);
+    let per_op = loaded.saturating_sub(baseline) as f64 / ITERATIONS as f64;
+    println!("{:<24} {:>8.2} allocations/op (budget 2.1)", "EVAL argument", per_op);
+    assert!(per_op <= 2.1, "each EVAL argument allocates {:.2} times, budget is 2.1", per_op);
+}
+
+#[test]
+fn test_reply_serialization_budget() {
+    use ferrous::protocol::RespFrame;
+    use ferrous::protocol::serializer::{serialize_resp_frame, ScratchBuffer};
+    
+    // Once the connection's scratch buffer has warmed up, writing replies must not allocate
+    let reply = RespFrame::Array(Some(vec![
+        RespFrame::Integer(-42),
+        RespFrame::from_string("value"),
+        RespFrame::BulkString(None),
+    ]));
+    let mut scratch = ScratchBuffer::default();
+    serialize_resp_frame(&reply, &mut *scratch).unwrap();
+    scratch.recycle();
+    
+    let before = allocations();
+    for _ in 0..ITERATIONS {
+        serialize_resp_frame(&reply, &mut *scratch).unwrap();
+        scratch.recycle();
+    }
+    let per_op = (allocations() - before) as f64 / ITERATIONS as f64;
+    println!("{:<24} {:>8.2} allocations/op (budget 0)", "reply serialization", per_op);
+    assert_eq!(per_op, 0.0, "serializing a reply allocated {:.2} times", per_op);
+}
+
+#[test]
+fn test_shared_reply_budget() {
+    use ferrous::protocol::{shared, RespFrame};
+    
+    // +OK, +PONG and +QUEUED come from shared buffers
+    let _ = (shared::ok(), shared::pong(), shared::queued());
+    
+    let before = allocations();
+    for _ in 0..ITERATIONS {
+        std::hint::black_box((RespFrame::ok(), shared::pong(), shared::queued()));
+    }
+    let per_op = (allocations() - before) as f64 / ITERATIONS as f64;
+    println!("{:<24} {:>8.2} allocations/op (budget 0)", "shared status replies", per_op);
+    assert_eq!(per_op, 0.0, "building a status reply allocated {:.2} times", per_op);
+}
@@ -0,0 +1,80 @@
+#!/bin/bash
+# End-to-end client library test harness
+#
+# Boots a fresh Ferrous server on a random free port and drives it through
+# real client libraries (redis-py and node-redis). The scenarios cover the
+# reply shapes client libraries depend on: EVAL/EVALSHA conversions, error
+# prefixes, pipelining, pub/sub and blocking commands.
+#
+# Usage: ./tests/e2e/run_e2e.sh [--skip-build]
+#
+# Prerequisites:
+#   pip install redis
+#   (cd tests/e2e && npm install)    # optional, node-redis suite is skipped otherwise
+
+set -e
+
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+ROOT_DIR="$(cd "$SCRIPT_DIR/../.." && pwd)"
+SERVER="$ROOT_DIR/target/release/ferrous"
+
+if [[ "$1" != "--skip-build" ]]; then
+    echo "Building release server..."
+    (cd "$ROOT_DIR" && cargo build --release --bin ferrous)
+fi
+
+# Pick a random free port so the harness never collides with a dev server on 6379
+PORT=$(python3 -c "import socket; s = socket.socket(); s.bind(('127.0.0.1', 0)); print(s.getsockname()[1]); s.close()")
+LOG_FILE="/tmp/ferrous-e2e-$PORT.log"
+
+echo "Starting server on port $PORT (log: $LOG_FILE)"
+"$SERVER" --port "$PORT" > "$LOG_FILE" 2>&1 &
+SERVER_PID=$!
+trap 'kill $SERVER_PID 2>/dev/null || true' EXIT
+
+# Wait for the listener instead of sleeping a fixed amount
+for _ in $(seq 1 50); do
+    if python3 -c "import socket; socket.create_connection(('127.0.0.1', $PORT), timeout=0.2).close()" 2>/dev/null; then
+        break
+    fi
+    sleep 0.1
+done
+
+if ! kill -0 $SERVER_PID 2>/dev/null; then
+    echo "❌ Server failed to start"
+    tail -20 "$LOG_FILE"
+    exit 1
+fi
+
+echo "✅ Server running on port $PORT"
+echo ""
+
+FAILED=0
+
+echo "========================================="
+echo "redis-py scenarios"
+echo "========================================="
+if python3 -c "import redis" 2>/dev/null; then
+    python3 "$SCRIPT_DIR/test_e2e_redis_py.py" --port "$PORT" || FAILED=1
+else
+    echo "❌ redis-py not installed (pip install redis)"
+    FAILED=1
+fi
+echo ""
+
+echo "========================================="
+echo "node-redis scenarios"
+echo "========================================="
+if command -v node > /dev/null && [[ -d "$SCRIPT_DIR/node_modules/redis" ]]; then
+    node "$SCRIPT_DIR/test_e2e_node_redis.mjs" --port "$PORT" || FAILED=1
+else
+    echo "⚠️  node-redis not installed, skipping (cd tests/e2e && npm install)"
+fi
+echo ""
+
+if [[ $FAILED -ne 0 ]]; then
+    echo "❌ End-to-end tests failed"
+    exit 1
+fi
+
+echo "✅ End-to-end tests passed"
@@ -0,0 +1,141 @@
+#!/usr/bin/env node
+/**
+ * End-to-end scenarios driven through node-redis (v4)
+ * Mirrors test_e2e_redis_py.py so reply-shape regressions show up in both
+ * client libraries. Started by run_e2e.sh against a fresh server.
+ */
+
+import assert from 'node:assert/strict';
+import { createClient, ErrorReply } from 'redis';
+
+const portArg = process.argv.indexOf('--port');
+const port = portArg > 0 ? Number(process.argv[portArg + 1]) : 6379;
+
+const newClient = async () => {
+    const client = createClient({ socket: { host: '127.0.0.1', port } });
+    client.on('error', () => {});
+    await client.connect();
+    return client;
+};
+
+const expectError = async (promise, expected) => {
+    await assert.rejects(promise, (err) => {
+        assert.ok(err instanceof ErrorReply, `expected an error reply, got ${err}`);
+        assert.ok(err.message.includes(expected), err.message);
+        return true;
+    });
+};
+
+const sleep = (ms) => new Promise((resolve) => setTimeout(resolve, ms));
+
+const scenarios = {
+    'EVAL replies map onto node-redis types': async (client) => {
+        assert.equal(await client.eval('return 42'), 42);
+        assert.equal(await client.eval("return 'hello'"), 'hello');
+        assert.equal(await client.eval('return nil'), null);
+        assert.deepEqual(await client.eval("return {1, 'two', {3}, nil, 5}"), [1, 'two', [3]]);
+        assert.deepEqual(
+            await client.eval('return {KEYS[1], ARGV[1]}', { keys: ['k'], arguments: ['a'] }),
+            ['k', 'a'],
+        );
+    },
+
+    'SCRIPT LOAD, EVALSHA and NOSCRIPT behave like Redis': async (client) => {
+        const sha = await client.scriptLoad('return ARGV[1] .. ARGV[1]');
+        assert.equal(sha.length, 40);
+        assert.deepEqual(await client.scriptExists(sha), [true]);
+        assert.equal(await client.evalSha(sha, { arguments: ['ab'] }), 'abab');
+        await expectError(client.evalSha('f'.repeat(40)), 'NOSCRIPT');
+    },
+
+    'error prefixes and messages match Redis': async (client) => {
+        await client.set('e2e:str', 'v');
+        await expectError(client.lPush('e2e:str', 'x'), 'WRONGTYPE');
+        await expectError(client.sendCommand(['NOSUCHCMD']), 'unknown command');
+        await expectError(client.sendCommand(['GET']), 'wrong number of arguments');
+        await expectError(client.eval("error('boom')"), 'boom');
+        assert.equal(await client.ping(), 'PONG');
+    },
+
+    'pipelined replies come back complete and in order': async (client) => {
+        const pipeline = client.multi();
+        for (let i = 0; i < 200; i++) {
+            pipeline.set(`e2e:pipe:${i}`, String(i));
+            pipeline.get(`e2e:pipe:${i}`);
+        }
+        pipeline.eval('return #ARGV', { arguments: ['a', 'b', 'c'] });
+        const results = await pipeline.execAsPipeline();
+        assert.equal(results.length, 401);
+        assert.equal(results[1], '0');
+        assert.equal(results[399], '199');
+        assert.equal(results[400], 3);
+
+        // Commands issued concurrently are auto-pipelined onto one socket
+        const replies = await Promise.all([
+            client.incr('e2e:counter'),
+            client.lPush('e2e:str', 'x').catch((err) => err),
+            client.incr('e2e:counter'),
+        ]);
+        assert.equal(replies[0], 1);
+        assert.ok(replies[1] instanceof ErrorReply);
+        assert.equal(replies[2], 2);
+
+        const transaction = await client.multi()
+            .incr('e2e:counter')
+            .eval("return redis.call('GET', KEYS[1])", { keys: ['e2e:counter'] })
+            .exec();
+        assert.deepEqual(transaction, [3, '3']);
+    },
+
+    'pub/sub delivers channel and pattern messages': async (client) => {
+        const subscriber = await newClient();
+        const received = [];
+        await subscriber.subscribe('e2e:chan', (message, channel) => received.push({ channel, message }));
+        await subscriber.pSubscribe('e2e:pat:*', (message, channel) => received.push({ channel, message }));
+
+        assert.equal(await client.publish('e2e:chan', 'one'), 1);
+        assert.equal(await client.publish('e2e:pat:x', 'two'), 1);
+
+        for (let i = 0; i < 50 && received.length < 2; i++) {
+            await sleep(100);
+        }
+        assert.deepEqual(received, [
+            { channel: 'e2e:chan', message: 'one' },
+            { channel: 'e2e:pat:x', message: 'two' },
+        ]);
+        await subscriber.quit();
+    },
+
+    'BLPOP times out with nil and wakes up on push': async (client) => {
+        const blocker = await newClient();
+        await client.del('e2e:queue');
+
+        const start = Date.now();
+        assert.equal(await blocker.blPop('e2e:queue', 1), null);
+        assert.ok(Date.now() - start >= 900);
+
+        const popped = blocker.blPop('e2e:queue', 5);
+        await sleep(300);
+        await client.rPush('e2e:queue', 'job');
+        assert.deepEqual(await popped, { key: 'e2e:queue', element: 'job' });
+        await blocker.quit();
+    },
+};
+
+const client = await newClient();
+await client.flushAll();
+
+let failed = 0;
+for (const [name, scenario] of Object.entries(scenarios)) {
+    try {
+        await scenario(client);
+        console.log(`✅ ${name}`);
+    } catch (err) {
+        failed++;
+        console.log(`❌ ${name}: ${err.message}`);
+    }
+}
+
+await client.quit();
+console.log(`\nnode-redis: ${Object.keys(scenarios).length - failed}/${Object.keys(scenarios).length} scenarios passed`);
+process.exit(failed === 0 ? 0 : 1);
@@ -0,0 +1,185 @@
+#!/usr/bin/env python3
+"""
+End-to-end scenarios driven through redis-py
+Checks the reply shapes the client library parses: script conversions, error
+classes, pipelining, pub/sub and blocking commands. Started by run_e2e.sh
+against a fresh server on a random port.
+"""
+
+import argparse
+import sys
+import threading
+import time
+
+import redis
+
+
+class RedisPyScenarios:
+    def __init__(self, host='127.0.0.1', port=6379):
+        self.host = host
+        self.port = port
+        self.r = redis.Redis(host=host, port=port, decode_responses=True)
+
+    def client(self, **kwargs):
+        return redis.Redis(host=self.host, port=self.port, decode_responses=True, **kwargs)
+
+    def test_eval_reply_conversion(self):
+        """EVAL replies map onto the Python types redis-py produces for Redis"""
+        assert self.r.eval("return 42", 0) == 42
+        assert self.r.eval("return 'hello'", 0) == "hello"
+        assert self.r.eval("return nil", 0) is None
+        assert self.r.eval("return true", 0) == 1
+        # Arrays stop at the first nil, nested tables become nested lists
+        assert self.r.eval("return {1, 'two', {3}, nil, 5}", 0) == [1, "two", [3]]
+        assert self.r.eval("return {KEYS[1], ARGV[1]}", 1, "k", "a") == ["k", "a"]
+
+    def test_evalsha_roundtrip(self):
+        """SCRIPT LOAD, EVALSHA and NOSCRIPT behave like Redis"""
+        sha = self.r.script_load("return ARGV[1] .. ARGV[1]")
+        assert len(sha) == 40
+        assert self.r.script_exists(sha) == [True]
+        assert self.r.evalsha(sha, 0, "ab") == "abab"
+
+        try:
+            self.r.evalsha("f" * 40, 0)
+            raise AssertionError("EVALSHA of an unknown script should fail")
+        except redis.exceptions.NoScriptError:
+            pass
+
+        # redis-py's Script helper relies on the NOSCRIPT fallback to EVAL
+        self.r.script_flush()
+        script = self.r.register_script("return redis.call('SET', KEYS[1], ARGV[1])")
+        assert script(keys=["e2e:script"], args=["v"]) == "OK"
+        assert self.r.get("e2e:script") == "v"
+
+    def test_error_classes(self):
+        """Error prefixes map to redis-py's exception classes"""
+        self.r.set("e2e:str", "v")
+        try:
+            self.r.lpush("e2e:str", "x")
+            raise AssertionError("LPUSH on a string should fail")
+        except redis.exceptions.ResponseError as e:
+            assert str(e).startswith("WRONGTYPE"), str(e)
+
+        for args, expected in [
+            (("NOSUCHCMD",), "unknown command"),
+            (("GET",), "wrong number of arguments"),
+            (("INCR", "e2e:str"), "not an integer"),
+        ]:
+            try:
+                self.r.execute_command(*args)
+                raise AssertionError(f"{args[0]} should fail")
+            except redis.exceptions.ResponseError as e:
+                assert expected in str(e), str(e)
+
+        try:
+            self.r.eval("error('boom')", 0)
+            raise AssertionError("a script error should be returned as an error reply")
+        except redis.exceptions.ResponseError as e:
+            assert "boom" in str(e), str(e)
+
+        # The connection stays usable after error replies
+        assert self.r.ping()
+
+    def test_pipelining(self):
+        """Pipelined replies come back complete and in order"""
+        pipe = self.r.pipeline(transaction=False)
+        for i in range(200):
+            pipe.set(f"e2e:pipe:{i}", i)
+            pipe.get(f"e2e:pipe:{i}")
+        pipe.eval("return #ARGV", 0, "a", "b", "c")
+        pipe.lpush("e2e:str", "x")  # an error in the middle must not desync the stream
+        pipe.ping()
+        results = pipe.execute(raise_on_error=False)
+
+        assert len(results) == 403
+        assert results[0] is True and results[1] == "0"
+        assert results[398] is True and results[399] == "199"
+        assert results[400] == 3
+        assert isinstance(results[401], redis.exceptions.ResponseError)
+        assert results[402] is True
+
+        pipe = self.r.pipeline(transaction=True)
+        pipe.incr("e2e:counter")
+        pipe.incr("e2e:counter")
+        pipe.eval("return redis.call('GET', KEYS[1])", 1, "e2e:counter")
+        assert pipe.execute() == [1, 2, "2"]
+
+    def test_pubsub(self):
+        """Subscription confirmations and messages keep the RESP2 shape"""
+        pubsub = self.client().pubsub()
+        pubsub.subscribe("e2e:chan")
+        pubsub.psubscribe("e2e:pat:*")
+
+        confirmations = []
+        deadline = time.time() + 5
+        while len(confirmations) < 2 and time.time() < deadline:
+            message = pubsub.get_message(timeout=0.5)
+            if message:
+                confirmations.append(message)
+        assert [m["type"] for m in confirmations] == ["subscribe", "psubscribe"], confirmations
+
+        assert self.r.publish("e2e:chan", "one") == 1
+        assert self.r.publish("e2e:pat:x", "two") == 1
+
+        received = []
+        deadline = time.time() + 5
+        while len(received) < 2 and time.time() < deadline:
+            message = pubsub.get_message(timeout=0.5)
+            if message:
+                received.append(message)
+
+        assert received[0]["type"] == "message" and received[0]["data"] == "one", received
+        assert received[1]["type"] == "pmessage" and received[1]["pattern"] == "e2e:pat:*", received
+        assert received[1]["channel"] == "e2e:pat:x" and received[1]["data"] == "two", received
+        pubsub.close()
+
+    def test_blocking_commands(self):
+        """BLPOP times out with nil and wakes up when another client pushes"""
+        blocker = self.client(socket_timeout=10)
+        self.r.delete("e2e:queue")
+
+        start = time.time()
+        assert blocker.blpop("e2e:queue", timeout=1) is None
+        assert time.time() - start >= 0.9
+
+        def push_later():
+            time.sleep(0.3)
+            self.client().rpush("e2e:queue", "job")
+
+        pusher = threading.Thread(target=push_later)
+        pusher.start()
+        assert blocker.blpop("e2e:queue", timeout=5) == ("e2e:queue", "job")
+        pusher.join()
+
+    def run(self):
+        self.r.flushall()
+        tests = [
+            self.test_eval_reply_conversion,
+            self.test_evalsha_roundtrip,
+            self.test_error_classes,
+            self.test_pipelining,
+            self.test_pubsub,
+            self.test_blocking_commands,
+        ]
+
+        failed = 0
+        for test in tests:
+            try:
+                test()
+                print(f"✅ {test.__doc__}")
+            except Exception as e:
+                failed += 1
+                print(f"❌ {test.__doc__}: {type(e).__name__}: {e}")
+
+        print(f"\nredis-py: {len(tests) - failed}/{len(tests)} scenarios passed")
+        return failed == 0
+
+
+if __name__ == "__main__":
+    parser = argparse.ArgumentParser()
+    parser.add_argument("--host", default="127.0.0.1")
+    parser.add_argument("--port", type=int, default=6379)
+    args = parser.parse_args()
+
+    sys.exit(0 if RedisPyScenarios(args.host, args.port).run() else 1)
@@ -118,7 +118,7 @@ fn test_lua_redis_type_conversion() {
         ("return true", "true->1"),
         ("return false", "false->nil"),
         ("return 42", "integer"),
-        ("return 3.14", "float->string"),
+        ("return 3.14", "float->integer"),
         ("return 'string'", "string"),
         ("return {1, 2, 3}", "array"),
         ("return {}", "empty_table->nil"),
@@ -167,4 +167,61 @@ fn test_concurrent_script_execution() {
             _ => panic!("Expected integer result"),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Test the SCRIPT LOAD / EVALSHA / SCRIPT EXISTS / SCRIPT FLUSH lifecycle without a server
+#[test]
+fn test_evalsha_script_cache_lifecycle() {
+    use ferrous::storage::commands::lua::handle_lua_command;
+    
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    let bulk = |s: &str| RespFrame::BulkString(Some(Arc::new(s.as_bytes().to_vec())));
+    
+    let script = "return {KEYS[1], ARGV[1], 'evalsha-lifecycle'}";
+    let sha1 = match handle_lua_command(&storage, "script", &[bulk("SCRIPT"), bulk("LOAD"), bulk(script)]).unwrap() {
+        RespFrame::BulkString(Some(bytes)) => String::from_utf8(bytes.to_vec()).unwrap(),
+        other => panic!("Expected SHA1 from SCRIPT LOAD, got {:?}", other),
+    };
+    assert_eq!(sha1.len(), 40);
+    
+    // SHA1s are matched case-insensitively, like Redis
+    let parts = [bulk("EVALSHA"), bulk(&sha1.to_uppercase()), bulk("1"), bulk("k"), bulk("v")];
+    assert_eq!(handle_lua_command(&storage, "evalsha", &parts).unwrap(), RespFrame::Array(Some(vec![
+        bulk("k"), bulk("v"), bulk("evalsha-lifecycle"),
+    ])));
+    
+    let unknown = "0000000000000000000000000000000000000000";
+    let exists = [bulk("SCRIPT"), bulk("EXISTS"), bulk(&sha1), bulk(unknown)];
+    assert_eq!(handle_lua_command(&storage, "script", &exists).unwrap(),
+        RespFrame::Array(Some(vec![RespFrame::Integer(1), RespFrame::Integer(0)])));
+    
+    match handle_lua_command(&storage, "evalsha", &[bulk("EVALSHA"), bulk(unknown), bulk("0")]).unwrap() {
+        RespFrame::Error(message) => assert!(message.starts_with(b"NOSCRIPT ")),
+        other => panic!("Expected NOSCRIPT, got {:?}", other),
+    }
+    
+    assert_eq!(handle_lua_command(&storage, "script", &[bulk("SCRIPT"), bulk("FLUSH")]).unwrap(), RespFrame::ok());
+    assert_eq!(handle_lua_command(&storage, "script", &exists).unwrap(),
+        RespFrame::Array(Some(vec![RespFrame::Integer(0), RespFrame::Integer(0)])));
+}
+
+/// Test that status replies seen by redis.call go back to the client as status replies
+#[test]
+fn test_redis_call_status_replies_reach_the_client() {
+    use ferrous::protocol::serializer::serialize_to_vec;
+    use ferrous::storage::commands::lua::handle_lua_command;
+    
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    let bulk = |s: &str| RespFrame::BulkString(Some(Arc::new(s.as_bytes().to_vec())));
+    
+    for (script, reply) in [
+        ("return redis.call('PING')", "+PONG\r\n"),
+        ("return redis.pcall('PING')", "+PONG\r\n"),
+        ("return redis.call('SET', 'k', 'v')", "+OK\r\n"),
+        ("return redis.call('TYPE', 'k')", "+string\r\n"),
+        ("local pong = redis.call('PING') return type(pong) .. ':' .. pong.ok", "$10\r\ntable:PONG\r\n"),
+    ] {
+        let result = handle_lua_command(&storage, "eval", &[bulk("EVAL"), bulk(script), bulk("0")]).unwrap();
+        assert_eq!(String::from_utf8(serialize_to_vec(&result).unwrap()).unwrap(), reply, "{}", script);
+    }
+}
@@ -153,8 +153,8 @@ def send_commands_during_pause():
     # Wait a moment for pause to take effect
     time.sleep(1)
     
-    # Try a command that should be rejected during pause
-    print("Sending GET command during PAUSE (should be rejected)...")
+    # Try a command that should wait for the pause to end
+    print("Sending GET command during PAUSE (should be held until the pause ends)...")
     resp = redis_command("*2\r\n$3\r\nGET\r\n$4\r\ntest\r\n")
     print(f"Response: {decode_resp(resp)}")
     
@@ -0,0 +1,73 @@
+//! Keyspace triggers registered by FUNCTION libraries
+//!
+//! Kept in its own test binary: the function registry and the trigger table are
+//! process-wide, and the triggers here must write to this test's storage.
+
+use std::sync::Arc;
+use std::time::Duration;
+use ferrous::storage::engine::StorageEngine;
+use ferrous::storage::commands::lua::{handle_eval, handle_function};
+use ferrous::storage::lua_triggers;
+use ferrous::protocol::resp::RespFrame;
+
+const LIBRARY: &str = r#"#!lua name=triggerlib
+redis.register_function('count_event', function(keys, args)
+    redis.call('INCR', 'trig:count:' .. args[1])
+end)
+redis.register_function('bounce', function(keys)
+    redis.call('INCR', 'trig:bounces')
+    redis.call('SET', keys[1], 'again')
+end)
+redis.register_trigger{function_name='count_event', pattern='trig:item:*', events={'set', 'del', 'expired'}}
+redis.register_trigger{function_name='bounce', pattern='trig:loop', events={'SET'}}
+"#;
+
+fn bulk(s: &str) -> RespFrame {
+    RespFrame::BulkString(Some(Arc::new(s.as_bytes().to_vec())))
+}
+
+fn eval(storage: &Arc<StorageEngine>, script: &str) -> RespFrame {
+    handle_eval(storage, &[bulk("EVAL"), bulk(script), bulk("0")]).unwrap()
+}
+
+fn counter(storage: &Arc<StorageEngine>, key: &str) -> Option<Vec<u8>> {
+    storage.get_string(0, key.as_bytes()).unwrap()
+}
+
+#[test]
+fn test_triggers_fire_on_writes_and_expiry() {
+    let storage = StorageEngine::new_in_memory();
+    assert_eq!(handle_function(&storage, &[bulk("FUNCTION"), bulk("LOAD"), bulk(LIBRARY)]).unwrap(), bulk("triggerlib"));
+    assert_eq!(lua_triggers::triggers().len(), 2);
+    
+    // Writes from a script fire once the script has finished, one event per key
+    eval(&storage, "redis.call('SET', 'trig:item:1', 'a') redis.call('SET', 'trig:other', 'b') redis.call('DEL', 'trig:item:1')");
+    assert_eq!(counter(&storage, "trig:count:set"), Some(b"1".to_vec()));
+    assert_eq!(counter(&storage, "trig:count:del"), Some(b"1".to_vec()));
+    
+    // Keys removed by expiry raise 'expired' and run at the next dispatch point
+    storage.set_string_ex(0, b"trig:item:ttl".to_vec(), b"v".to_vec(), Duration::from_millis(1)).unwrap();
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(counter(&storage, "trig:item:ttl"), None);
+    lua_triggers::dispatch();
+    assert_eq!(counter(&storage, "trig:count:expired"), Some(b"1".to_vec()));
+    
+    // A trigger that re-triggers itself stops at the depth limit
+    let suppressed = lua_triggers::suppressed_events();
+    eval(&storage, "redis.call('SET', 'trig:loop', 'start')");
+    assert_eq!(counter(&storage, "trig:bounces"), Some(lua_triggers::max_depth().to_string().into_bytes()));
+    assert_eq!(lua_triggers::suppressed_events(), suppressed + 1);
+    
+    // Triggers may only name functions of their own library
+    let bad = "#!lua name=badlib\nredis.register_function('f', function() end)\nredis.register_trigger{function_name='missing', pattern='*'}";
+    match handle_function(&storage, &[bulk("FUNCTION"), bulk("LOAD"), bulk(bad)]).unwrap() {
+        RespFrame::Error(message) => assert_eq!(&message[..], b"ERR Trigger function missing is not registered by this library"),
+        other => panic!("Expected trigger error, got {:?}", other),
+    }
+    
+    // Deleting the library removes its triggers
+    assert_eq!(handle_function(&storage, &[bulk("FUNCTION"), bulk("DELETE"), bulk("triggerlib")]).unwrap(), RespFrame::ok());
+    assert!(!lua_triggers::is_active());
+    eval(&storage, "redis.call('SET', 'trig:item:2', 'a')");
+    assert_eq!(counter(&storage, "trig:count:set"), Some(b"1".to_vec()));
+}
@@ -5,7 +5,22 @@
 use std::sync::Arc;
 use ferrous::storage::engine::StorageEngine;
 use ferrous::storage::commands::lua::handle_eval;
-use ferrous::protocol::resp::RespFrame;
+use ferrous::storage::lua_checkpoint;
+use ferrous::storage::lua_engine::{LuaEngine, LuaCommandContext, ReplyLimits};
+use ferrous::protocol::resp::{Bytes, RespFrame};
+
+/// A script engine over an empty store, and a context running in database 0
+fn script_env() -> (Arc<StorageEngine>, LuaEngine, LuaCommandContext) {
+    let storage = StorageEngine::new_in_memory();
+    let engine = LuaEngine::new(storage.clone()).unwrap();
+    let ctx = LuaCommandContext { db_index: 0, storage: storage.clone() };
+    (storage, engine, ctx)
+}
+
+/// A bulk string reply
+fn bulk(s: &str) -> RespFrame {
+    RespFrame::BulkString(Some(Arc::new(s.as_bytes().to_vec())))
+}
 
 /// Test Redis EVAL command compatibility
 #[test]
@@ -103,6 +118,361 @@ fn test_redis_keys_argv_compatibility() {
     }
 }
 
+/// Test that KEYS and ARGV carry arbitrary bytes unchanged
+#[test]
+fn test_keys_argv_are_binary_safe() {
+    let (storage, engine, ctx) = script_env();
+    let key = b"bin\xff\x00key".to_vec();
+    let value = vec![0u8, 159, 146, 150, 255, b'\n'];
+    
+    let script = "redis.call('SET', KEYS[1], ARGV[1]) return {#KEYS[1], #ARGV[1], KEYS[1], ARGV[1], #KEYS, #ARGV}";
+    let result = engine.eval(script, vec![Arc::new(key.clone())], vec![Arc::new(value.clone())], &ctx).unwrap();
+    assert_eq!(result, RespFrame::Array(Some(vec![
+        RespFrame::Integer(key.len() as i64),
+        RespFrame::Integer(value.len() as i64),
+        RespFrame::BulkString(Some(Arc::new(key.clone()))),
+        RespFrame::BulkString(Some(Arc::new(value.clone()))),
+        RespFrame::Integer(1),
+        RespFrame::Integer(1),
+    ])));
+    assert_eq!(storage.get_string(0, &key).unwrap(), Some(value));
+    
+    // Missing entries are nil
+    let result = engine.eval("return {KEYS[1] == nil, ARGV[1] == nil}", vec![], vec![], &ctx).unwrap();
+    assert_eq!(result, RespFrame::Array(Some(vec![RespFrame::Integer(1), RespFrame::Integer(1)])));
+}
+
+/// Test the restricted os library and its deterministic mode
+#[test]
+fn test_restricted_os_library() {
+    let (_, engine, ctx) = script_env();
+    let eval = |script: &str| engine.eval(script, vec![], vec![], &ctx);
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64;
+    
+    // Only clock, time and date are exposed
+    let result = eval("local names = {} for name in pairs(os) do names[#names + 1] = name end table.sort(names) return names").unwrap();
+    let names = ["clock", "date", "time"].iter().map(|n| RespFrame::BulkString(Some(Arc::new(n.as_bytes().to_vec())))).collect();
+    assert_eq!(result, RespFrame::Array(Some(names)));
+    match eval("return os.time()").unwrap() {
+        RespFrame::Integer(time) => assert!((time - now).abs() <= 5),
+        other => panic!("Expected integer time, got {:?}", other),
+    }
+    assert_eq!(eval("return os.clock() >= 0").unwrap(), RespFrame::Integer(1));
+    assert_eq!(eval("return os.date('!%Y-%m-%d %H:%M:%S %%', 86400)").unwrap(),
+        RespFrame::BulkString(Some(Arc::new(b"1970-01-02 00:00:00 %".to_vec()))));
+    assert_eq!(eval("return os.date('!*t', 0).year").unwrap(), RespFrame::Integer(1970));
+    
+    // Conversions outside the C89 strftime set are rejected
+    let err = eval("return os.date('%Ez')").unwrap_err();
+    assert!(err.to_string().contains("invalid conversion specifier '%E'"), "{}", err);
+    
+    // Deterministic mode keeps wall-clock reads out of replicated scripts
+    engine.set_deterministic(true);
+    for script in ["return os.time()", "return os.date()"] {
+        let err = eval(script).unwrap_err();
+        assert!(err.to_string().contains("not available in deterministic mode, use redis.call('TIME')"), "{}", err);
+    }
+    assert_eq!(eval("return type(os.clock())").unwrap(), RespFrame::BulkString(Some(Arc::new(b"number".to_vec()))));
+}
+
+/// Test version constants and the ferrous.features table scripts use for feature detection
+#[test]
+fn test_version_and_feature_detection() {
+    use ferrous::network::admin_commands::handle_lolwut;
+    use ferrous::storage::commands::monitor::{REDIS_VERSION, REDIS_VERSION_NUM};
+    
+    let (_, engine, ctx) = script_env();
+    
+    let result = engine.eval("return {redis.REDIS_VERSION, redis.REDIS_VERSION_NUM, ferrous.version}", vec![], vec![], &ctx).unwrap();
+    assert_eq!(result, RespFrame::Array(Some(vec![
+        bulk(REDIS_VERSION), RespFrame::Integer(REDIS_VERSION_NUM), bulk(env!("CARGO_PKG_VERSION")),
+    ])));
+    
+    // Features follow the engine configuration; absent libraries are reported as false
+    let features = "local f = ferrous.features return {f.utf8, f.require, f.cjson, f.bit, f.no_such_feature == nil}";
+    let result = engine.eval(features, vec![], vec![], &ctx).unwrap();
+    assert_eq!(result, RespFrame::Array(Some(vec![
+        RespFrame::Integer(0), RespFrame::Integer(1), RespFrame::Integer(1), RespFrame::Integer(1), RespFrame::Integer(1),
+    ])));
+    engine.set_utf8_enabled(true);
+    assert_eq!(engine.eval("return ferrous.features.utf8 and utf8 ~= nil", vec![], vec![], &ctx).unwrap(), RespFrame::Integer(1));
+    
+    let banner = format!("Ferrous ver. {} (Redis ver. {})\n", env!("CARGO_PKG_VERSION"), REDIS_VERSION);
+    assert_eq!(handle_lolwut(&[bulk("LOLWUT")]).unwrap(), bulk(&banner));
+    assert_eq!(handle_lolwut(&[bulk("LOLWUT"), bulk("VERSION"), bulk("5")]).unwrap(), bulk(&banner));
+    assert!(handle_lolwut(&[bulk("LOLWUT"), bulk("NOPE")]).unwrap().is_error());
+}
+
+/// Test cjson encoding and decoding with Redis's conventions
+#[test]
+fn test_cjson_library() {
+    let (_, engine, ctx) = script_env();
+    let eval = |script: &str| engine.eval(script, vec![], vec![], &ctx);
+    
+    // Arrays, the empty table, and numbers at Lua's precision
+    assert_eq!(eval("return cjson.encode({1, 'two', true, {}})").unwrap(), bulk("[1,\"two\",true,{}]"));
+    assert_eq!(eval("return cjson.encode({1/3, 1e15, -0.5})").unwrap(), bulk("[0.33333333333333,1e+15,-0.5]"));
+    assert_eq!(eval("return cjson.encode({a = {b = cjson.null}})").unwrap(), bulk("{\"a\":{\"b\":null}}"));
+    assert_eq!(eval("return cjson.encode({[1] = 'x', [3] = 'y'})").unwrap(), bulk("[\"x\",null,\"y\"]"));
+    
+    // Round trip through decode, with null decoding to cjson.null
+    let script = r#"
+        local t = cjson.decode('{"name":"f\\u00e9e","list":[1,2.5,null],"nested":{"ok":false}}')
+        return {t.name, t.list[2] * 2, tostring(t.list[3] == cjson.null), tostring(t.nested.ok), #t.list}
+    "#;
+    assert_eq!(eval(script).unwrap(), RespFrame::Array(Some(vec![
+        bulk("f\u{e9}e"), RespFrame::Integer(5), bulk("true"), bulk("false"), RespFrame::Integer(3),
+    ])));
+    
+    // Rejected inputs
+    for (script, message) in [
+        ("return cjson.encode({[1] = 1, [100] = 2})", "Cannot serialise table: excessively sparse array"),
+        ("return cjson.encode({math.huge})", "Cannot serialise number: must not be NaN or Inf"),
+        ("return cjson.encode(print)", "Cannot serialise function: type not supported"),
+        ("local t = {} for i = 1, 1000 do t = {t} end return cjson.encode(t)", "Cannot serialise, excessive nesting (1001)"),
+        ("return cjson.decode('[1,')", "Expected value but found T_END at character 4"),
+        ("return cjson.decode('{\"a\" 1}')", "Expected colon but found T_NUMBER at character 6"),
+        ("return cjson.decode(string.rep('[', 1001))", "Found too many nested data structures (1001) at character 1001"),
+    ] {
+        let err = eval(script).unwrap_err();
+        assert!(err.to_string().contains(message), "{}: {}", script, err);
+    }
+}
+
+/// Test the bit library against LuaBitOp results, and redis.sha1hex
+#[test]
+fn test_bit_library_and_sha1hex() {
+    let (_, engine, ctx) = script_env();
+    let eval = |script: &str| engine.eval(script, vec![], vec![], &ctx);
+    
+    let script = r#"
+        return {
+            bit.band(0xff, 0x0f, 0x3), bit.bor(1, 2, 4), bit.bxor(5, 3), bit.bnot(0),
+            bit.lshift(1, 31), bit.rshift(-1, 28), bit.arshift(-256, 4), bit.rol(0x80000000, 1),
+            bit.tobit(0xffffffff + 2), bit.tohex(-1), bit.tohex(255, -4), bit.tohex(bit.bswap(0x12345678)),
+        }
+    "#;
+    assert_eq!(eval(script).unwrap(), RespFrame::Array(Some(vec![
+        RespFrame::Integer(3), RespFrame::Integer(7), RespFrame::Integer(6), RespFrame::Integer(-1),
+        RespFrame::Integer(-2147483648), RespFrame::Integer(15), RespFrame::Integer(-16), RespFrame::Integer(1),
+        RespFrame::Integer(1), bulk("ffffffff"), bulk("00FF"), bulk("78563412"),
+    ])));
+    
+    let err = eval("return bit.band(1, {})").unwrap_err();
+    assert!(err.to_string().contains("bad argument #2 to 'band' (number expected, got table)"), "{}", err);
+    
+    assert_eq!(eval("return redis.sha1hex('')").unwrap(), bulk("da39a3ee5e6b4b0d3255bfef95601890afd80709"));
+}
+
+/// Test commands called with thousands of unpacked arguments
+#[test]
+fn test_large_argument_lists() {
+    let (_, engine, ctx) = script_env();
+    let eval = |script: &str| engine.eval(script, vec![], vec![], &ctx);
+    
+    // Thousands of arguments pass through redis.call in one call
+    let script = r#"
+        local args = {'MSET'}
+        for i = 1, 3000 do args[#args + 1] = 'big:' .. i args[#args + 1] = i end
+        redis.call(unpack(args))
+        local keys = {}
+        for i = 1, 7000 do keys[i] = 'big:' .. i end
+        return redis.call('DEL', unpack(keys))
+    "#;
+    assert_eq!(eval(script).unwrap(), RespFrame::Integer(3000));
+    
+    // Past Lua's C stack limit unpack fails cleanly, and chunked calls work
+    let err = eval("local t = {} for i = 1, 20000 do t[i] = 'k' end return redis.call('DEL', unpack(t))").unwrap_err();
+    assert!(err.to_string().contains("too many results to unpack"), "{}", err);
+    let script = r#"
+        local keys = {}
+        for i = 1, 20000 do keys[i] = 'big:' .. i end
+        redis.call('SET', 'big:15000', 'v')
+        local deleted = 0
+        for i = 1, #keys, 5000 do deleted = deleted + redis.call('DEL', unpack(keys, i, math.min(i + 4999, #keys))) end
+        return deleted
+    "#;
+    assert_eq!(eval(script).unwrap(), RespFrame::Integer(1));
+}
+
+/// Test that runaway scripts are terminated by their execution budget
+#[test]
+fn test_script_budgets() {
+    let (_, engine, ctx) = script_env();
+    let eval = |script: &str| engine.eval(script, vec![], vec![], &ctx);
+    
+    // Running past lua-time-limit only makes a script busy; it still completes
+    engine.set_time_limit_ms(1);
+    let script = "local t = {} for i = 1, 200000 do t[i % 100] = i end return t[0]";
+    assert_eq!(eval(script).unwrap(), RespFrame::Integer(200000));
+    
+    // An infinite loop ends at the instruction limit, even when it catches errors
+    engine.set_max_instructions(100_000);
+    for script in ["local n = 0 while true do n = n + 1 end", "while true do pcall(function() while true do end end) end"] {
+        let err = eval(script).unwrap_err();
+        assert_eq!(err.to_string(), "ERR Script exceeded its instruction limit of 100000");
+    }
+    assert_eq!(eval("local n = 0 for i = 1, 1000 do n = n + i end return n").unwrap(), RespFrame::Integer(500500));
+    engine.set_max_instructions(0);
+    
+    engine.set_max_call_depth(50);
+    let err = eval("local function f(n) return f(n + 1) + 1 end return f(1)").unwrap_err();
+    assert_eq!(err.to_string(), "ERR Script exceeded its call depth limit of 50");
+    let script = "local function f(n) if n == 0 then return 0 end return f(n - 1) + 1 end return f(20)";
+    assert_eq!(eval(script).unwrap(), RespFrame::Integer(20));
+    
+    // pcall still catches ordinary errors
+    assert_eq!(eval("return tostring(pcall(error, 'boom'))").unwrap(), RespFrame::BulkString(Some(Arc::new(b"false".to_vec()))));
+}
+
+/// Test that scripts cannot tamper with the redis table, KEYS or ARGV
+#[test]
+fn test_script_api_tables_are_protected() {
+    use ferrous::storage::commands::lua::{handle_fcall_with_db, handle_function};
+    
+    let (storage, engine, ctx) = script_env();
+    let eval = |script: &str| engine.eval(script, vec![Arc::new(b"k1".to_vec()), Arc::new(b"k2".to_vec())], vec![Arc::new(b"a".to_vec())], &ctx);
+    
+    for script in [
+        "redis.call = function() return 'pwned' end",
+        "redis.injected = true",
+        "setmetatable(redis, {})",
+        "rawset(redis, 'call', print)",
+        "KEYS[#KEYS + 1] = 'extra'",
+        "KEYS[1] = 'x'",
+        "ARGV[1] = 'zz'",
+        "table.insert(KEYS, 'x')",
+        "table.insert(ARGV, 1, 'x')",
+        "table.remove(ARGV)",
+        "table.sort(KEYS)",
+        "setmetatable(ARGV, {__index = function() return 'forged' end})",
+        "rawset(ARGV, 5, 'forged')",
+        "rawset(KEYS, 1, 'forged')",
+    ] {
+        let err = eval(script).unwrap_err().to_string();
+        assert!(err.contains("readonly table") || err.contains("protected metatable"), "{}: {}", script, err);
+    }
+    let err = eval("local n = 1\nKEYS[n] = 'x'").unwrap_err().to_string();
+    assert!(err.contains(":2: Attempt to modify a readonly table"), "{}", err);
+    
+    // Reads are unaffected, including length, unpack and ipairs on KEYS and ARGV
+    let script = r#"
+        pcall(function() redis.call = nil end)
+        pcall(function() KEYS[1] = 'x' end)
+        local n = 0
+        for _ in ipairs(KEYS) do n = n + 1 end
+        return {redis.call('PING'), #KEYS, n, table.concat({unpack(KEYS)}, ','), getmetatable(redis), type(ARGV), KEYS, cjson.encode(ARGV)}
+    "#;
+    let expected = vec![RespFrame::simple_string("PONG"), RespFrame::Integer(2), RespFrame::Integer(2), bulk("k1,k2"), bulk("protected"), bulk("table"),
+                        RespFrame::Array(Some(vec![bulk("k1"), bulk("k2")])), bulk(r#"["a"]"#)];
+    assert_eq!(eval(script).unwrap(), RespFrame::Array(Some(expected)));
+    
+    // A function library shares its Lua state across calls, so a tampering call must not poison the next
+    let library = r#"#!lua name=tamperlib
+redis.register_function('tamper', function()
+    return tostring(pcall(function() redis.call = function() return 'pwned' end end))
+end)
+redis.register_function('tamperping', function() return redis.call('PING') end)
+redis.register_function('tamperkeys', function(keys)
+    return tostring(pcall(table.insert, keys, 'x')) .. #keys
+end)
+"#;
+    assert_eq!(handle_function(&storage, &[bulk("FUNCTION"), bulk("LOAD"), bulk(library)]).unwrap(), bulk("tamperlib"));
+    assert_eq!(handle_fcall_with_db(&storage, &[bulk("FCALL"), bulk("tamper"), bulk("0")], 0).unwrap(), bulk("false"));
+    assert_eq!(handle_fcall_with_db(&storage, &[bulk("FCALL"), bulk("tamperping"), bulk("0")], 0).unwrap(), RespFrame::simple_string("PONG"));
+    assert_eq!(handle_fcall_with_db(&storage, &[bulk("FCALL"), bulk("tamperkeys"), bulk("1"), bulk("k")], 0).unwrap(), bulk("false1"));
+}
+
+/// Test that scripts replicate the writes they made rather than the script itself
+#[test]
+fn test_script_effects_are_collected() {
+    use ferrous::storage::lua_effects::EffectsScope;
+    
+    let (_, engine, ctx) = script_env();
+    let command = |words: &[&str]| RespFrame::Array(Some(words.iter().map(|w| RespFrame::BulkString(Some(Arc::new(w.as_bytes().to_vec())))).collect()));
+    let effects_of = |script: &str| {
+        let effects = EffectsScope::begin();
+        let _ = engine.eval(script, vec![], vec![], &ctx);
+        effects.finish()
+    };
+    
+    // Reads and failed writes are left out; several writes are wrapped in MULTI/EXEC
+    let script = r#"
+        redis.call('SET', 'counter', '1')
+        redis.call('GET', 'counter')
+        redis.pcall('LPUSH', 'counter', 'x')
+        redis.call('INCR', 'counter')
+    "#;
+    let expected = vec![command(&["MULTI"]), command(&["SET", "counter", "1"]), command(&["INCR", "counter"]), command(&["EXEC"])];
+    assert_eq!(effects_of(script), expected);
+    
+    // A script that fails after writing still propagates what it wrote
+    assert_eq!(effects_of("redis.call('SET', 'partial', '1') error('boom')"), vec![command(&["SET", "partial", "1"])]);
+    assert!(effects_of("return redis.call('GET', 'partial')").is_empty());
+}
+
+/// Test string.gmatch iterators: captures, empty matches, and Lua 5.1's literal '^'
+#[test]
+fn test_string_gmatch() {
+    let (_, engine, ctx) = script_env();
+    let eval = |script: &str| engine.eval(script, vec![], vec![], &ctx).unwrap();
+    
+    // Captures and position captures, with each iterator keeping its own position
+    let script = r#"
+        local out = {}
+        for k, v in string.gmatch('a=1, b=2', '(%w+)=(%w+)') do out[#out + 1] = k .. v end
+        for p, w in string.gmatch('hi you', '()(%a+)') do out[#out + 1] = p .. w end
+        local it = string.gmatch('x y', '%a')
+        out[#out + 1] = it() .. it() .. tostring((it()))
+        return out
+    "#;
+    assert_eq!(eval(script), RespFrame::Array(Some(vec![
+        bulk("a1"), bulk("b2"), bulk("1hi"), bulk("4you"), bulk("xynil"),
+    ])));
+    
+    // Empty matches advance one character at a time instead of looping forever
+    assert_eq!(eval("local n = 0 for m in string.gmatch('abc', 'x*') do n = n + 1 end return n"), RespFrame::Integer(4));
+    
+    // As in Lua 5.1, a leading '^' is not an anchor in gmatch but a literal character
+    assert_eq!(eval("local n = 0 for m in string.gmatch('aaa', '^a') do n = n + 1 end return n"), RespFrame::Integer(0));
+    assert_eq!(eval("local out = '' for m in string.gmatch('^a^a', '^a') do out = out .. m end return out"), bulk("^a^a"));
+}
+
+/// Test table.insert/remove borders: keys in the hash part count, __len does not (Lua 5.1)
+#[test]
+fn test_table_insert_remove_borders() {
+    let (_, engine, ctx) = script_env();
+    let eval = |script: &str| engine.eval(script, vec![], vec![], &ctx).unwrap();
+    let integers = |values: &[i64]| RespFrame::Array(Some(values.iter().map(|v| RespFrame::Integer(*v)).collect()));
+    
+    // A sequence built in the hash part: insert appends after its border, remove pops it
+    let script = r#"
+        local t = {[1] = 10, [2] = 20, [3] = 30}
+        table.insert(t, 40)
+        local popped = table.remove(t)
+        table.insert(t, 1, 5)
+        return {#t, popped, t[1], t[4], table.remove(t, 1), #t}
+    "#;
+    assert_eq!(eval(script), integers(&[4, 40, 5, 30, 5, 3]));
+    
+    // The array part continues into the hash part when large indexes were assigned in order
+    let script = r#"
+        local t = {1, 2, 3}
+        for i = 4, 100 do t[i] = i end
+        table.insert(t, 101)
+        return {#t, table.remove(t), table.remove(t), #t}
+    "#;
+    assert_eq!(eval(script), integers(&[101, 101, 100, 99]));
+    
+    // __len is ignored for tables, as in Redis's Lua 5.1
+    let script = r#"
+        local t = setmetatable({1, 2}, {__len = function() return 10 end})
+        table.insert(t, 3)
+        return {#t, t[3], table.remove(t), #t}
+    "#;
+    assert_eq!(eval(script), integers(&[3, 3, 3, 2]));
+}
+
 /// Test Redis Lua sandboxing compliance
 #[test] 
 fn test_redis_sandboxing_compliance() {
@@ -176,17 +546,87 @@ fn test_allowed_stdlib_functions() {
     }
 }
 
+/// Test Lua 5.1 patterns in string.find, string.match, string.gmatch and string.gsub
+#[test]
+fn test_lua_pattern_matching() {
+    let (_, engine, ctx) = script_env();
+    
+    let cases = [
+        // Character classes, sets, quantifiers and anchors
+        ("return string.match('order:42:paid', '^(%a+):(%d+):(%w+)$')", bulk("order")),
+        ("return select(2, string.match('order:42:paid', '^(%a+):(%d+)'))", bulk("42")),
+        ("return string.find('  key = value', '[%w_]+%s*=')", RespFrame::Integer(3)),
+        ("return string.match('a-b-c', '^[^-]*-(.-)$')", bulk("b-c")),
+        ("return string.match('hello', '^l')", RespFrame::BulkString(None)),
+        ("return string.find('a.b', '.', 1, true)", RespFrame::Integer(2)),
+        // Position captures, back-references, balanced matches and frontiers
+        ("return string.match('hello', '()ll()')", RespFrame::Integer(3)),
+        ("return string.match('abcd xyyx', '(%a)(%a)%2%1')", bulk("x")),
+        ("return string.match('f(a(b)c) d', '%b()')", bulk("(a(b)c)")),
+        ("return (string.gsub('THE (quick) fox', '%f[%a]%a+', function(w) return w:lower() end))", bulk("the (quick) fox")),
+        // gmatch iteration and gsub with table, function and capture replacements
+        ("local t = {} for k, v in string.gmatch('a=1, b=2', '(%w+)=(%w+)') do t[#t + 1] = k .. v end return table.concat(t, ',')", bulk("a1,b2")),
+        ("return (string.gsub('$name is $age', '%$(%w+)', {name = 'ann', age = 7}))", bulk("ann is 7")),
+        ("return (string.gsub('hello world', '(%w+)', '<%1>'))", bulk("<hello> <world>")),
+        ("return select(2, string.gsub('aaa', 'a', 'b', 2))", RespFrame::Integer(2)),
+    ];
+    for (script, expected) in cases {
+        assert_eq!(engine.eval(script, vec![], vec![], &ctx).unwrap(), expected, "{}", script);
+    }
+    
+    // Malformed patterns are script errors
+    assert!(engine.eval("return string.find('x', '[a')", vec![], vec![], &ctx).is_err());
+    assert!(engine.eval("return string.match('x', '%')", vec![], vec![], &ctx).is_err());
+}
+
+/// Test the coroutine library: values passed through resume and yield, status and wrap
+#[test]
+fn test_coroutines() {
+    let (_, engine, ctx) = script_env();
+    
+    let cases = [
+        // Arguments of resume come out of yield, and values of yield out of resume
+        ("local co = coroutine.create(function(a, b) local c = coroutine.yield(a + b) return c * 2 end) \
+          local _, sum = coroutine.resume(co, 1, 2) local _, double = coroutine.resume(co, 10) \
+          return {sum, double}", RespFrame::Array(Some(vec![RespFrame::Integer(3), RespFrame::Integer(20)]))),
+        // Status through the life of a coroutine, and running inside and outside one
+        ("local co co = coroutine.create(function() return coroutine.status(co) end) \
+          local before = coroutine.status(co) local _, inside = coroutine.resume(co) \
+          return {before, inside, coroutine.status(co)}",
+            RespFrame::Array(Some(vec![bulk("suspended"), bulk("running"), bulk("dead")]))),
+        ("local co = coroutine.create(function() return coroutine.running() ~= nil end) \
+          return select(2, coroutine.resume(co))", RespFrame::Integer(1)),
+        ("return coroutine.running() == nil", RespFrame::Integer(1)),
+        // wrap as a generator, and errors reported by resume instead of raised
+        ("local gen = coroutine.wrap(function() for i = 1, 3 do coroutine.yield(i) end end) \
+          return gen() + gen() + gen()", RespFrame::Integer(6)),
+        ("local ok, err = coroutine.resume(coroutine.create(function() error('boom') end)) \
+          return {tostring(ok), string.match(err, 'boom')}", RespFrame::Array(Some(vec![bulk("false"), bulk("boom")]))),
+        ("local co = coroutine.create(function() end) coroutine.resume(co) \
+          return select(2, coroutine.resume(co))", bulk("cannot resume dead coroutine")),
+        // redis.call works from inside a coroutine
+        ("local co = coroutine.wrap(function() redis.call('SET', 'k', 'v') coroutine.yield(redis.call('GET', 'k')) end) \
+          return co()", bulk("v")),
+    ];
+    for (script, expected) in cases {
+        assert_eq!(engine.eval(script, vec![], vec![], &ctx).unwrap(), expected, "{}", script);
+    }
+    
+    // Yielding outside a coroutine is a script error
+    assert!(engine.eval("coroutine.yield(1)", vec![], vec![], &ctx).is_err());
+}
+
 /// Test redis.call and redis.pcall functionality
 #[test]
 fn test_redis_call_functions() {
     let storage = Arc::new(StorageEngine::new_in_memory());
     
-    // Test redis.call with PING (returns "PONG")
+    // Test redis.call with PING (returns +PONG)
     let parts = create_eval_parts("return redis.call('ping')", 0, &[], &[]);
     let result = handle_eval(&storage, &parts).unwrap();
     
     match result {
-        RespFrame::BulkString(Some(bytes)) => {
+        RespFrame::SimpleString(bytes) => {
             assert_eq!(bytes.as_ref(), b"PONG");
         }
         _ => panic!("Expected redis.call to work"),
@@ -197,13 +637,739 @@ fn test_redis_call_functions() {
     let result = handle_eval(&storage, &parts).unwrap();
     
     match result {
-        RespFrame::BulkString(Some(bytes)) => {
+        RespFrame::SimpleString(bytes) => {
             assert_eq!(bytes.as_ref(), b"PONG");
         }
         _ => panic!("Expected redis.pcall to work"),
     }
 }
 
+/// Test redis.error_reply, redis.status_reply, redis.sha1hex and redis.pcall error tables
+#[test]
+fn test_redis_reply_helpers() {
+    let (storage, engine, ctx) = script_env();
+    storage.set_string(0, b"str".to_vec(), b"v".to_vec()).unwrap();
+    let eval = |script: &str| engine.eval(script, vec![], vec![], &ctx).unwrap();
+    
+    assert_eq!(eval("return redis.error_reply('MYERR custom failure')"), RespFrame::error("MYERR custom failure"));
+    assert_eq!(eval("return redis.error_reply('no class')"), RespFrame::error("ERR no class"));
+    assert_eq!(eval("return redis.status_reply('FINE')"), RespFrame::simple_string("FINE"));
+    assert_eq!(eval("return redis.error_reply('x').err"), RespFrame::BulkString(Some(Arc::new(b"x".to_vec()))));
+    assert_eq!(eval("return redis.sha1hex('')"), RespFrame::BulkString(Some(Arc::new(b"da39a3ee5e6b4b0d3255bfef95601890afd80709".to_vec()))));
+    
+    // Status replies are {ok = ...} tables and pcall errors are {err = ...} tables
+    assert_eq!(eval("return redis.call('SET', 'k', 'v').ok"), RespFrame::BulkString(Some(Arc::new(b"OK".to_vec()))));
+    assert_eq!(eval("return redis.pcall('LPUSH', 'str', 'x')"),
+        RespFrame::error("WRONGTYPE Operation against a key holding the wrong kind of value"));
+    assert_eq!(eval("local r = redis.pcall('LPUSH', 'str', 'x') return type(r.err)"),
+        RespFrame::BulkString(Some(Arc::new(b"string".to_vec()))));
+}
+
+/// Test the redis.call reply size guard in error and truncate modes
+#[test]
+fn test_redis_call_reply_limits() {
+    let (storage, engine, ctx) = script_env();
+    for i in 0..10 {
+        storage.rpush(0, b"biglist".to_vec(), vec![format!("item{}", i).into_bytes()]).unwrap();
+    }
+    
+    engine.set_reply_limits(ReplyLimits { max_elements: 5, max_bytes: 1024, truncate: false });
+    
+    // redis.call raises, redis.pcall returns an error reply table
+    let result = engine.eval("return redis.call('LRANGE', 'biglist', 0, -1)", vec![], vec![], &ctx);
+    assert!(matches!(result, Err(e) if e.to_string().contains("reply size limit")));
+    let result = engine.eval("return redis.pcall('LRANGE', 'biglist', 0, -1).err ~= nil", vec![], vec![], &ctx).unwrap();
+    assert_eq!(result, RespFrame::Integer(1));
+    
+    // Small replies are unaffected
+    let result = engine.eval("return #redis.call('LRANGE', 'biglist', 0, 2)", vec![], vec![], &ctx).unwrap();
+    assert_eq!(result, RespFrame::Integer(3));
+    
+    // Truncate mode keeps what fits (the array itself counts as one element) and flags the table
+    engine.set_reply_limits(ReplyLimits { max_elements: 5, max_bytes: 1024, truncate: true });
+    let script = "local r = redis.call('LRANGE', 'biglist', 0, -1) return {#r, tostring(r.truncated)}";
+    let result = engine.eval(script, vec![], vec![], &ctx).unwrap();
+    assert_eq!(result, RespFrame::Array(Some(vec![
+        RespFrame::Integer(4),
+        RespFrame::BulkString(Some(Arc::new(b"true".to_vec()))),
+    ])));
+}
+
+#[test]
+fn test_utf8_library_is_opt_in() {
+    let (_, engine, ctx) = script_env();
+    
+    // Strict Lua 5.1 by default
+    let result = engine.eval("return type(utf8)", vec![], vec![], &ctx).unwrap();
+    assert_eq!(result, RespFrame::BulkString(Some(Arc::new(b"nil".to_vec()))));
+    
+    engine.set_utf8_enabled(true);
+    let result = engine.eval("return utf8.len(ARGV[1])", vec![], vec![Arc::new("héllo wörld".as_bytes().to_vec())], &ctx).unwrap();
+    assert_eq!(result, RespFrame::Integer(11));
+}
+
+/// Test metamethods that re-enter their own table and call into Redis
+#[test]
+fn test_reentrant_metamethods() {
+    let (storage, engine, ctx) = script_env();
+    storage.set_string(0, b"fallback".to_vec(), b"from-redis".to_vec()).unwrap();
+    
+    // __index handler that reads and writes its own table while it is being indexed
+    let script = r#"
+        local t = {}
+        setmetatable(t, {__index = function(self, k)
+            local cached = rawget(self, '_' .. k)
+            if cached then return cached end
+            rawset(self, '_' .. k, k .. '!')
+            return self[k]
+        end})
+        return t.a
+    "#;
+    let result = engine.eval(script, vec![], vec![], &ctx).unwrap();
+    assert_eq!(result, RespFrame::BulkString(Some(Arc::new(b"a!".to_vec()))));
+    
+    // Metamethods may call back into the server
+    let script = r#"
+        local t = setmetatable({}, {__index = function(_, k) return redis.call('GET', k) end})
+        return t.fallback
+    "#;
+    let result = engine.eval(script, vec![], vec![], &ctx).unwrap();
+    assert_eq!(result, RespFrame::BulkString(Some(Arc::new(b"from-redis".to_vec()))));
+    
+    // Unbounded self-recursion is reported as a script error, never a panic
+    let script = "local t = setmetatable({}, {}) getmetatable(t).__index = function(self, k) return self[k] end return t.x";
+    assert!(engine.eval(script, vec![], vec![], &ctx).is_err());
+}
+
+#[test]
+fn test_reentrant_stdlib_callbacks() {
+    let (storage, engine, ctx) = script_env();
+    storage.set_string(0, b"suffix".to_vec(), b"!".to_vec()).unwrap();
+    let eval = |script: &str| engine.eval(script, vec![], vec![], &ctx);
+    
+    // Sort comparators that themselves run pcall (catching errors) and gsub with function replacements
+    let script = r#"
+        local items = {}
+        for i = 1, 200 do items[i] = 'k' .. ((i * 37) % 200) end
+        table.sort(items, function(a, b)
+            local ok = pcall(function() error('inner') end)
+            assert(not ok)
+            local na = tonumber((string.gsub(a, '%a', function(c) return '' end)))
+            local nb = tonumber((string.gsub(b, '%a', function(c) return '' end)))
+            return na < nb
+        end)
+        return {items[1], items[200]}
+    "#;
+    assert_eq!(eval(script).unwrap(), RespFrame::Array(Some(vec![
+        RespFrame::BulkString(Some(Arc::new(b"k0".to_vec()))),
+        RespFrame::BulkString(Some(Arc::new(b"k199".to_vec()))),
+    ])));
+    
+    // Lua -> C -> Lua several levels deep, calling back into the server at the bottom
+    let script = r#"
+        local function nest(depth)
+            if depth == 0 then return redis.call('GET', 'suffix') end
+            local out
+            table.sort({2, 1}, function(a, b)
+                out = out or string.gsub('x', 'x', function() return nest(depth - 1) end)
+                return a < b
+            end)
+            return out
+        end
+        return nest(20)
+    "#;
+    assert_eq!(eval(script).unwrap(), RespFrame::BulkString(Some(Arc::new(b"!".to_vec()))));
+    
+    // Errors cross every C boundary back to the nearest pcall, and the VM stays usable afterwards
+    let script = r#"
+        local ok, err = pcall(function()
+            table.sort({3, 2, 1}, function(a, b)
+                return string.gsub('x', 'x', function() error('deep failure') end) and a < b
+            end)
+        end)
+        assert(not ok)
+        local after = string.gsub('abc', '%a', function(c) return c:upper() end)
+        return {tostring(err):find('deep failure') ~= nil and 1 or 0, after}
+    "#;
+    assert_eq!(eval(script).unwrap(), RespFrame::Array(Some(vec![
+        RespFrame::Integer(1),
+        RespFrame::BulkString(Some(Arc::new(b"ABC".to_vec()))),
+    ])));
+    
+    // Uncaught errors from nested callbacks fail the script; unbounded nesting is an error, not a crash
+    assert!(eval("table.sort({2, 1}, function() string.gsub('x', 'x', function() error('boom') end) end)").is_err());
+    assert!(eval("local function f() return (string.gsub('x', 'x', f)) end return f()").is_err());
+    assert_eq!(eval("return 1").unwrap(), RespFrame::Integer(1));
+}
+
+#[test]
+fn test_deterministic_iteration_matches_across_replicas() {
+    // Each storage engine hashes with its own random seed, so HGETALL returns the
+    // fields in a different order on master and replica
+    let master = StorageEngine::new_in_memory();
+    let replica = StorageEngine::new_in_memory();
+    for i in 0..50 {
+        let field = format!("field{}", i).into_bytes();
+        master.hset(0, b"h".to_vec(), vec![(field.clone(), b"1".to_vec())]).unwrap();
+        replica.hset(0, b"h".to_vec(), vec![(field, b"1".to_vec())]).unwrap();
+    }
+    
+    // Builds a table from HGETALL (insertion order differs per node) and records pairs() order
+    let script = r#"
+        local h = redis.call('HGETALL', KEYS[1])
+        local t = {}
+        for i = 1, #h, 2 do t[h[i]] = h[i + 1] end
+        t[1], t[2], t[true] = 'one', 'two', 'yes'
+        for k in pairs(t) do redis.call('RPUSH', 'trace', tostring(k)) end
+        return math.random(1000000)
+    "#;
+    
+    let mut traces = Vec::new();
+    let mut randoms = Vec::new();
+    for storage in [&master, &replica] {
+        let engine = LuaEngine::new(storage.clone()).unwrap();
+        engine.set_deterministic(true);
+        let ctx = LuaCommandContext { db_index: 0, storage: storage.clone() };
+        randoms.push(engine.eval(script, vec![Arc::new(b"h".to_vec())], vec![], &ctx).unwrap());
+        traces.push(storage.lrange(0, b"trace", 0, -1).unwrap());
+    }
+    
+    assert_eq!(traces[0].len(), 53);
+    assert_eq!(traces[0], traces[1]);
+    assert_eq!(&traces[0][..3], &[b"1".to_vec(), b"2".to_vec(), b"field0".to_vec()]);
+    assert_eq!(traces[0].last().unwrap(), b"true");
+    assert_eq!(randoms[0], randoms[1]);
+}
+
+/// Test that array replies are hole-free sequences and redis.call_array enforces the shape
+#[test]
+fn test_redis_call_array_sequences() {
+    let (storage, engine, ctx) = script_env();
+    storage.set_string(0, b"a".to_vec(), b"1".to_vec()).unwrap();
+    storage.set_string(0, b"c".to_vec(), b"3".to_vec()).unwrap();
+    
+    // Missing keys are false, so # and ipairs see every position
+    let script = r#"
+        local values = redis.call('MGET', 'a', 'missing', 'c')
+        local seen = {}
+        for i, value in ipairs(values) do seen[i] = tostring(value) end
+        return {#values, table.concat(seen, ',')}
+    "#;
+    let result = engine.eval(script, vec![], vec![], &ctx).unwrap();
+    assert_eq!(result, RespFrame::Array(Some(vec![RespFrame::Integer(3), RespFrame::from_string("1,false,3")])));
+    
+    let script = r#"
+        local values = redis.call_array('MGET', 'missing', 'c')
+        local empty = redis.call_array('LRANGE', 'nolist', 0, -1)
+        return {#values, tostring(values[1]), values[2], #empty}
+    "#;
+    let result = engine.eval(script, vec![], vec![], &ctx).unwrap();
+    assert_eq!(result, RespFrame::Array(Some(vec![
+        RespFrame::Integer(2), RespFrame::from_string("false"), RespFrame::from_string("3"), RespFrame::Integer(0),
+    ])));
+    
+    // Replies that are not arrays are refused instead of being iterated
+    for (script, kind) in [
+        ("return redis.call_array('GET', 'a')", "GET returned a bulk string reply"),
+        ("return redis.call_array('SET', 'a', '2')", "SET returned a status reply"),
+        ("return redis.call_array('INCR', 'a')", "INCR returned an integer reply"),
+    ] {
+        let error = engine.eval(script, vec![], vec![], &ctx).unwrap_err().to_string();
+        assert!(error.contains(&format!("redis.call_array expected an array reply but {}", kind)), "{}: {}", script, error);
+    }
+    assert!(engine.eval("return redis.call_array('HGET', 'a', 'f')", vec![], vec![], &ctx).unwrap_err().to_string().contains("WRONGTYPE"));
+}
+
+/// Test SETEX/PSETEX/SETNX through redis.call with Redis argument validation
+#[test]
+fn test_legacy_string_commands() {
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    
+    let script = r#"
+        local ex = redis.call('SETEX', KEYS[1], 100, 'a')
+        local px = redis.call('PSETEX', KEYS[2], 100000, 'b')
+        local nx1 = redis.call('SETNX', KEYS[3], 'c')
+        local nx2 = redis.call('SETNX', KEYS[3], 'd')
+        return {ex, px, nx1, nx2, redis.call('TTL', KEYS[1]), redis.call('GET', KEYS[3])}
+    "#;
+    let parts = create_eval_parts(script, 3, &["ex", "px", "nx"], &[]);
+    match handle_eval(&storage, &parts).unwrap() {
+        RespFrame::Array(Some(items)) => {
+            assert_eq!(items[0], RespFrame::ok());
+            assert_eq!(items[2], RespFrame::Integer(1));
+            assert_eq!(items[3], RespFrame::Integer(0));
+            assert!(matches!(items[4], RespFrame::Integer(ttl) if ttl > 0 && ttl <= 100));
+            assert_eq!(items[5], RespFrame::BulkString(Some(Arc::new(b"c".to_vec()))));
+        }
+        other => panic!("Unexpected reply: {:?}", other),
+    }
+    
+    // Argument validation matches Redis word for word
+    let cases = [
+        ("return redis.call('SETEX', 'k', 0, 'v')", "ERR invalid expire time in 'setex' command"),
+        ("return redis.call('PSETEX', 'k', -5, 'v')", "ERR invalid expire time in 'psetex' command"),
+        ("return redis.call('SETEX', 'k', 'soon', 'v')", "ERR value is not an integer or out of range"),
+        ("return redis.call('SETEX', 'k', 10)", "ERR wrong number of arguments for 'setex' command"),
+        ("return redis.call('SETNX', 'k')", "ERR wrong number of arguments for 'setnx' command"),
+        ("return redis.call('SET', 'k', 'v', 'EX', 0)", "ERR invalid expire time in 'set' command"),
+    ];
+    for (script, expected) in cases {
+        match handle_eval(&storage, &create_eval_parts(script, 0, &[], &[])).unwrap() {
+            RespFrame::Error(msg) => assert_eq!(String::from_utf8_lossy(&msg), expected, "script: {}", script),
+            other => panic!("Expected error for {}, got {:?}", script, other),
+        }
+    }
+}
+
+/// Test the bitmap commands through redis.call
+#[test]
+fn test_bitmap_commands() {
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    
+    let script = r#"
+        redis.call('SET', KEYS[1], 'foobar')
+        redis.call('SET', KEYS[2], 'abcdef')
+        return {
+            redis.call('BITCOUNT', KEYS[1]),
+            redis.call('BITCOUNT', KEYS[1], 5, 30, 'BIT'),
+            redis.call('BITOP', 'AND', KEYS[3], KEYS[1], KEYS[2]),
+            redis.call('GET', KEYS[3]),
+            redis.call('SETBIT', KEYS[4], 7, 1),
+            redis.call('GETBIT', KEYS[4], 7),
+            redis.call('BITPOS', KEYS[4], 1),
+            redis.call('BITPOS', KEYS[1], 0, 2, -1),
+            redis.call('BITOP', 'OR', KEYS[3], 'missing'),
+            redis.call('EXISTS', KEYS[3]),
+        }
+    "#;
+    let parts = create_eval_parts(script, 4, &["a", "b", "and", "bits"], &[]);
+    let integer = |n| RespFrame::Integer(n);
+    match handle_eval(&storage, &parts).unwrap() {
+        RespFrame::Array(Some(items)) => assert_eq!(items, vec![
+            integer(26), integer(17), integer(6), RespFrame::BulkString(Some(Arc::new(b"`bc`ab".to_vec()))),
+            integer(0), integer(1), integer(7), integer(16), integer(0), integer(0),
+        ]),
+        other => panic!("Unexpected reply: {:?}", other),
+    }
+    
+    // Argument validation matches Redis word for word
+    let cases = [
+        ("return redis.call('SETBIT', 'k', 4294967296, 1)", "ERR bit offset is not an integer or out of range"),
+        ("return redis.call('BITOP', 'NOT', 'd', 'a', 'b')", "ERR BITOP NOT must be called with a single source key."),
+        ("return redis.call('BITCOUNT', 'k', 0)", "ERR syntax error"),
+        ("return redis.call('BITPOS', 'k', 2)", "ERR The bit argument must be 1 or 0."),
+    ];
+    for (script, expected) in cases {
+        match handle_eval(&storage, &create_eval_parts(script, 0, &[], &[])).unwrap() {
+            RespFrame::Error(msg) => assert_eq!(String::from_utf8_lossy(&msg), expected, "script: {}", script),
+            other => panic!("Expected error for {}, got {:?}", script, other),
+        }
+    }
+}
+
+/// Test the GEO commands through redis.call
+#[test]
+fn test_geo_commands() {
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    
+    let script = r#"
+        redis.call('GEOADD', KEYS[1], 13.361389, 38.115556, 'Palermo', 15.087269, 37.502669, 'Catania')
+        local pos = redis.call('GEOPOS', KEYS[1], 'Palermo')[1]
+        return {
+            redis.call('GEODIST', KEYS[1], 'Palermo', 'Catania', 'km'),
+            pos[1], pos[2],
+            table.concat(redis.call('GEOSEARCH', KEYS[1], 'FROMLONLAT', 15, 37, 'BYRADIUS', 200, 'km', 'DESC'), ','),
+            redis.call('GEOSEARCHSTORE', KEYS[2], KEYS[1], 'FROMMEMBER', 'Catania', 'BYBOX', 100, 100, 'km', 'STOREDIST'),
+            redis.call('ZSCORE', KEYS[2], 'Catania'),
+        }
+    "#;
+    let parts = create_eval_parts(script, 2, &["Sicily", "near"], &[]);
+    match handle_eval(&storage, &parts).unwrap() {
+        RespFrame::Array(Some(items)) => assert_eq!(items, vec![
+            bulk("166.2742"), bulk("13.36138933897018433"), bulk("38.11555639549629859"),
+            bulk("Palermo,Catania"), RespFrame::Integer(1), bulk("0"),
+        ]),
+        other => panic!("Unexpected reply: {:?}", other),
+    }
+    
+    let cases = [
+        ("return redis.call('GEOADD', 'g', 181, 0, 'm')", "ERR invalid longitude,latitude pair 181.000000,0.000000"),
+        ("return redis.call('GEODIST', 'g', 'a', 'b', 'yards')", "ERR unsupported unit provided. please use M, KM, FT, MI"),
+        ("return redis.call('GEOSEARCH', 'g', 'FROMLONLAT', 0, 0, 'BYRADIUS', -1, 'm')", "ERR radius cannot be negative"),
+    ];
+    for (script, expected) in cases {
+        match handle_eval(&storage, &create_eval_parts(script, 0, &[], &[])).unwrap() {
+            RespFrame::Error(msg) => assert_eq!(String::from_utf8_lossy(&msg), expected, "script: {}", script),
+            other => panic!("Expected error for {}, got {:?}", script, other),
+        }
+    }
+}
+
+/// Test the EXPIRE family with NX/XX/GT/LT through redis.call
+#[test]
+fn test_expire_family() {
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    
+    let script = r#"
+        redis.call('SET', KEYS[1], 'v')
+        local results = {
+            redis.call('EXPIRE', KEYS[1], 100, 'XX'),
+            redis.call('EXPIRE', KEYS[1], 100, 'NX'),
+            redis.call('EXPIRE', KEYS[1], 50, 'GT'),
+            redis.call('PEXPIRE', KEYS[1], 200000, 'GT'),
+            redis.call('EXPIRE', KEYS[1], 300, 'lt'),
+            redis.call('EXPIREAT', KEYS[1], 4102444800),
+            redis.call('EXPIRETIME', KEYS[1]),
+            redis.call('PEXPIRETIME', KEYS[1]),
+            redis.call('PERSIST', KEYS[1]),
+            redis.call('EXPIRETIME', KEYS[1]),
+            redis.call('EXPIRETIME', 'missing'),
+            redis.call('PEXPIREAT', KEYS[1], 1),
+            redis.call('EXISTS', KEYS[1]),
+        }
+        return results
+    "#;
+    let parts = create_eval_parts(script, 1, &["session"], &[]);
+    let expected = [0, 1, 0, 1, 0, 1, 4102444800, 4102444800000, 1, -1, -2, 1, 0];
+    assert_eq!(handle_eval(&storage, &parts).unwrap(), RespFrame::Array(Some(expected.into_iter().map(RespFrame::Integer).collect())));
+    
+    let cases = [
+        ("return redis.call('EXPIRE', 'k', 10, 'NX', 'GT')", "ERR NX and XX, GT or LT options at the same time are not compatible"),
+        ("return redis.call('EXPIRE', 'k', 10, 'GT', 'LT')", "ERR GT and LT options at the same time are not compatible"),
+        ("return redis.call('EXPIRE', 'k', 10, 'KEEPTTL')", "ERR Unsupported option KEEPTTL"),
+        ("return redis.call('EXPIRE', 'k', '9223372036854775807')", "ERR invalid expire time in 'expire' command"),
+    ];
+    for (script, expected) in cases {
+        match handle_eval(&storage, &create_eval_parts(script, 0, &[], &[])).unwrap() {
+            RespFrame::Error(msg) => assert_eq!(String::from_utf8_lossy(&msg), expected, "{}", script),
+            other => panic!("Expected error for {}, got {:?}", script, other),
+        }
+    }
+    
+    // Replicas receive the absolute deadline, or DEL once the key is gone
+    use ferrous::storage::commands::strings::propagated_expire;
+    storage.set_string(0, b"k".to_vec(), b"v".to_vec()).unwrap();
+    handle_eval(&storage, &create_eval_parts("return redis.call('EXPIREAT', 'k', 4102444800)", 0, &[], &[])).unwrap();
+    let args = |words: &[&str]| words.iter().map(|word| word.as_bytes().to_vec()).collect::<Vec<_>>();
+    assert_eq!(propagated_expire(&storage, 0, &args(&["expire", "k", "100"])), Some(args(&["PEXPIREAT", "k", "4102444800000"])));
+    assert_eq!(propagated_expire(&storage, 0, &args(&["PEXPIRE", "missing", "100"])), Some(args(&["DEL", "missing"])));
+    assert_eq!(propagated_expire(&storage, 0, &args(&["SET", "k", "v"])), None);
+    
+    // Field TTLs likewise reach replicas as HPEXPIREAT
+    use ferrous::storage::commands::hashes::propagated_field_expire;
+    let propagated = propagated_field_expire(&args(&["hexpire", "h", "100", "NX", "FIELDS", "1", "f"])).unwrap();
+    let deadline: i64 = String::from_utf8(propagated[2].clone()).unwrap().parse().unwrap();
+    let now_ms = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as i64;
+    assert!((now_ms + 99_000..=now_ms + 100_000).contains(&deadline));
+    assert_eq!(propagated[..2], args(&["HPEXPIREAT", "h"])[..]);
+    assert_eq!(propagated[3..], args(&["NX", "FIELDS", "1", "f"])[..]);
+    assert_eq!(propagated_field_expire(&args(&["HEXPIREAT", "h", "4102444800", "FIELDS", "1", "f"])),
+               Some(args(&["HPEXPIREAT", "h", "4102444800000", "FIELDS", "1", "f"])));
+    assert_eq!(propagated_field_expire(&args(&["HPEXPIREAT", "h", "1", "FIELDS", "1", "f"])), None);
+}
+
+/// Test that scripts read their own writes and invalidate WATCH on the keys they wrote
+#[test]
+fn test_script_writes_invalidate_watch() {
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    storage.set_string(0, b"typed".to_vec(), b"v".to_vec()).unwrap();
+    
+    let written = storage.register_watch(0, b"written").unwrap();
+    let failed = storage.register_watch(0, b"typed").unwrap();
+    let untouched = storage.register_watch(0, b"other").unwrap();
+    
+    let script = r#"
+        redis.call('SET', KEYS[1], 'a')
+        redis.call('APPEND', KEYS[1], 'b')
+        redis.pcall('LPUSH', KEYS[2], 'x')
+        return redis.call('GET', KEYS[1])
+    "#;
+    let parts = create_eval_parts(script, 2, &["written", "typed"], &[]);
+    assert_eq!(handle_eval(&storage, &parts).unwrap(), RespFrame::BulkString(Some(Arc::new(b"ab".to_vec()))));
+    
+    assert!(storage.was_modified_since(0, b"written", written).unwrap());
+    assert!(!storage.was_modified_since(0, b"typed", failed).unwrap());
+    assert!(!storage.was_modified_since(0, b"other", untouched).unwrap());
+    
+    for key in [b"written".as_slice(), b"typed", b"other"] {
+        storage.unregister_watch(0, key).unwrap();
+    }
+}
+
+/// Test that EVAL_RO rejects write commands but serves reads
+#[test]
+fn test_eval_ro_rejects_writes() {
+    use ferrous::storage::commands::lua::handle_eval_ro_with_db;
+    
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    storage.set_string(0, b"k".to_vec(), b"v".to_vec()).unwrap();
+    
+    let parts = create_eval_parts("return redis.call('GET', KEYS[1])", 1, &["k"], &[]);
+    assert_eq!(handle_eval_ro_with_db(&storage, &parts, 0).unwrap(), RespFrame::BulkString(Some(Arc::new(b"v".to_vec()))));
+    
+    let parts = create_eval_parts("return redis.call('DEL', KEYS[1])", 1, &["k"], &[]);
+    match handle_eval_ro_with_db(&storage, &parts, 0).unwrap() {
+        RespFrame::Error(msg) => assert_eq!(String::from_utf8_lossy(&msg), "ERR Write commands are not allowed from read-only scripts."),
+        other => panic!("Expected read-only error, got {:?}", other),
+    }
+    assert!(storage.get_string(0, b"k").unwrap().is_some());
+    
+    // The same script runs normally through EVAL afterwards
+    assert_eq!(handle_eval(&storage, &parts).unwrap(), RespFrame::Integer(1));
+}
+
+/// Test SORT with external weights and hash-field projection from a script
+#[test]
+fn test_sort_join_from_script() {
+    use ferrous::storage::commands::lua::handle_eval_ro_with_db;
+    
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    let setup = r#"
+        for i, score in ipairs({5, 1, 3}) do
+            redis.call('RPUSH', 'users', i)
+            redis.call('SET', 'score_' .. i, score)
+            redis.call('HSET', 'user_' .. i, 'name', 'u' .. i)
+        end
+        return redis.call('SORT', 'users', 'BY', 'score_*', 'DESC', 'GET', 'user_*->name', 'STORE', 'ranked')
+    "#;
+    assert_eq!(handle_eval(&storage, &create_eval_parts(setup, 0, &[], &[])).unwrap(), RespFrame::Integer(3));
+    assert_eq!(storage.lrange(0, b"ranked", 0, -1).unwrap(), vec![b"u1".to_vec(), b"u3".to_vec(), b"u2".to_vec()]);
+    
+    // SORT_RO is allowed from read-only scripts, SORT is not
+    let parts = create_eval_parts("return redis.call('SORT_RO', 'users', 'BY', 'score_*', 'LIMIT', 0, 1)", 0, &[], &[]);
+    match handle_eval_ro_with_db(&storage, &parts, 0).unwrap() {
+        RespFrame::Array(Some(items)) => assert_eq!(items, vec![RespFrame::BulkString(Some(Arc::new(b"2".to_vec())))]),
+        other => panic!("Unexpected reply: {:?}", other),
+    }
+    let parts = create_eval_parts("return redis.call('SORT', 'users')", 0, &[], &[]);
+    assert!(matches!(handle_eval_ro_with_db(&storage, &parts, 0).unwrap(), RespFrame::Error(_)));
+}
+
+/// Test that SCRIPT LOAD compiles up front and EVAL runs the cached bytecode
+#[test]
+fn test_script_load_compiles_ahead() {
+    let (_, engine, ctx) = script_env();
+    
+    // Syntax errors are reported at load time with their line number
+    let err = engine.script_load("local x = 1\nlocal y = = 2").unwrap_err();
+    assert_eq!(err.to_string(), "ERR Error compiling script: user_script:2: unexpected symbol near '='");
+    assert!(engine.compile("return 1 +").is_err());
+    
+    // Expression and block scripts both run from the precompiled chunk
+    engine.script_load("ARGV[1]").unwrap();
+    let result = engine.eval("ARGV[1]", vec![], vec![Arc::new(b"hello".to_vec())], &ctx).unwrap();
+    assert_eq!(result, RespFrame::BulkString(Some(Arc::new(b"hello".to_vec()))));
+    
+    let script = "redis.call('SET', KEYS[1], 'v')\nreturn redis.call('GET', KEYS[1])";
+    engine.script_load(script).unwrap();
+    let result = engine.eval(script, vec![Arc::new(b"k".to_vec())], vec![], &ctx).unwrap();
+    assert_eq!(result, RespFrame::BulkString(Some(Arc::new(b"v".to_vec()))));
+    
+    // Runtime errors carry the same chunk name whether precompiled or not
+    engine.flush_precompiled();
+    let err = engine.eval("local t = nil\nreturn t.x", vec![], vec![], &ctx).unwrap_err();
+    assert!(err.to_string().contains("user_script:2:"), "{}", err);
+}
+
+/// Test that script writes are audited with their outcome, including scripts that fail halfway
+#[test]
+fn test_script_writes_are_audited() {
+    use ferrous::storage::lua_audit;
+    use sha1::Digest;
+    
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("scripts.audit");
+    lua_audit::configure(Some(path.clone()), 0).unwrap();
+    
+    let storage = StorageEngine::new_in_memory();
+    let engine = LuaEngine::new(storage.clone()).unwrap();
+    let ctx = LuaCommandContext { db_index: 2, storage: storage.clone() };
+    let script = "redis.call('SET', 'audited', 'v')\nredis.call('GET', 'audited')\nredis.pcall('LPUSH', 'audited', 'x')\nerror('boom')";
+    assert!(engine.eval(script, vec![], vec![], &ctx).is_err());
+    
+    lua_audit::configure(None, 0).unwrap();
+    
+    // Other tests may run scripts while auditing is on; keep this script's lines
+    let sha = hex::encode(sha1::Sha1::digest(script.as_bytes()));
+    let log = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = log.lines().filter(|line| line.split(' ').nth(1) == Some(sha.as_str())).collect();
+    assert_eq!(lines.len(), 3, "{}", log);
+    assert!(lines[0].ends_with(" db=2 OK \"SET\" \"audited\" \"v\""), "{}", lines[0]);
+    assert!(lines[1].contains(" db=2 err=\"WRONGTYPE"), "{}", lines[1]);
+    assert!(lines[2].contains(" script error: "), "{}", lines[2]);
+}
+
+/// Test that runaway nesting and recursion fail with stack overflow errors instead of crashing
+#[test]
+fn test_deep_nesting_reports_stack_overflow() {
+    let (_, engine, ctx) = script_env();
+    
+    let nested = "local t = {} for i = 1, ARGV[1] do t = {t} end return t";
+    assert!(engine.eval(nested, vec![], vec![Arc::new(b"100".to_vec())], &ctx).is_ok());
+    let err = engine.eval(nested, vec![], vec![Arc::new(b"100000".to_vec())], &ctx).unwrap_err();
+    assert!(err.to_string().contains("stack overflow"), "{}", err);
+    
+    // A table containing itself is cut off by the same limit
+    let err = engine.eval("local t = {1} t[2] = t return t", vec![], vec![], &ctx).unwrap_err();
+    assert!(err.to_string().contains("stack overflow"), "{}", err);
+    
+    engine.set_max_nesting_depth(10);
+    assert!(engine.eval(nested, vec![], vec![Arc::new(b"20".to_vec())], &ctx).is_err());
+    
+    // Lua-level recursion and metamethod chains are bounded by the VM itself
+    let err = engine.eval("local function f(n) return f(n + 1) + 1 end return f(1)", vec![], vec![], &ctx).unwrap_err();
+    assert!(err.to_string().contains("stack overflow"), "{}", err);
+    let script = "local t = setmetatable({}, {}) getmetatable(t).__index = function(s, k) return s[k] end return t.x";
+    let err = engine.eval(script, vec![], vec![], &ctx).unwrap_err();
+    assert!(err.to_string().contains("stack overflow"), "{}", err);
+}
+
+/// Test that returned numbers are truncated to integers exactly like Redis
+#[test]
+fn test_number_results_truncate_like_redis() {
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    
+    let cases = [
+        ("return 3.99", 3),
+        ("return -3.99", -3),
+        ("return -0.5", 0),
+        ("return 2^53", 9_007_199_254_740_992),
+        ("return 1e20", i64::MIN),
+        ("return -1e20", i64::MIN),
+        ("return 1/0", i64::MIN),
+        ("return -1/0", i64::MIN),
+        ("return 0/0", i64::MIN),
+    ];
+    for (script, expected) in cases {
+        let parts = create_eval_parts(script, 0, &[], &[]);
+        assert_eq!(handle_eval(&storage, &parts).unwrap(), RespFrame::Integer(expected), "{}", script);
+    }
+    
+    // Nested numbers follow the same rule
+    let parts = create_eval_parts("return {1.5, {-2.5}}", 0, &[], &[]);
+    assert_eq!(handle_eval(&storage, &parts).unwrap(), RespFrame::Array(Some(vec![
+        RespFrame::Integer(1),
+        RespFrame::Array(Some(vec![RespFrame::Integer(-2)])),
+    ])));
+    
+    // tostring keeps the fractional part
+    let parts = create_eval_parts("return tostring(3.99)", 0, &[], &[]);
+    assert_eq!(handle_eval(&storage, &parts).unwrap(), RespFrame::BulkString(Some(Arc::new(b"3.99".to_vec()))));
+}
+
+/// Test that unconvertible values anywhere in a result fail the whole reply cleanly
+#[test]
+fn test_unconvertible_results_fail_cleanly() {
+    let (_, engine, ctx) = script_env();
+    
+    let values = [
+        ("function", "function() end"),
+        ("userdata", "newproxy()"),
+        ("thread", "coroutine.create(function() end)"),
+    ];
+    let shapes = [
+        "return V",
+        "return {1, V}",
+        "return {'a', {2, {V, 3}}, 'b'}",
+        "return {{{{{{{{V}}}}}}}}",
+    ];
+    
+    for (type_name, value) in values {
+        for shape in shapes {
+            let script = shape.replace('V', value);
+            let err = engine.eval(&script, vec![], vec![], &ctx).unwrap_err();
+            assert_eq!(err.to_string(), format!("ERR Error running script: cannot convert a Lua {} to a reply", type_name), "{}", script);
+        }
+    }
+    
+    // An __index that raises while the reply is collected is an error, not a short reply
+    let script = "return setmetatable({1, 2}, {__index = function() error('bad index') end})";
+    let err = engine.eval(script, vec![], vec![], &ctx).unwrap_err();
+    assert!(err.to_string().contains("bad index"), "{}", err);
+}
+
+/// Test that an embedder-registered engine serves FUNCTION LOAD/LIST and FCALL next to Lua
+#[test]
+fn test_custom_script_engine_registration() {
+    use std::sync::Mutex;
+    use ferrous::error::{FerrousError, Result};
+    use ferrous::storage::commands::lua::{handle_fcall_with_db, handle_function};
+    use ferrous::storage::lua_functions::{FunctionInfo, FunctionLibrary};
+    use ferrous::storage::script_engine::{self, ScriptEngine};
+    
+    /// Libraries are `#!echo name=<lib> <function>`; calling the function echoes its ARGV
+    struct EchoEngine {
+        libraries: Mutex<Vec<FunctionLibrary>>,
+    }
+    
+    impl ScriptEngine for EchoEngine {
+        fn name(&self) -> &str {
+            "ECHO"
+        }
+        
+        fn compile(&self, _source: &str) -> Result<()> {
+            Ok(())
+        }
+        
+        fn eval(&self, source: &str, _keys: Vec<Bytes>, _args: Vec<Bytes>, _ctx: &LuaCommandContext) -> Result<RespFrame> {
+            Ok(RespFrame::from_string(source.to_string()))
+        }
+        
+        fn load_library(&self, code: &str, _replace: bool) -> Result<String> {
+            let mut words = code.split_whitespace().skip(1);
+            let name = words.next().and_then(|w| w.strip_prefix("name=")).unwrap_or("").to_string();
+            let function = words.next().ok_or_else(|| FerrousError::LuaError("ERR no function".to_string()))?;
+            self.libraries.lock().unwrap().push(FunctionLibrary {
+                name: name.clone(),
+                code: code.to_string(),
+                functions: vec![FunctionInfo { name: function.to_string(), description: None, flags: Vec::new() }],
+                memory: 0,
+            });
+            Ok(name)
+        }
+        
+        fn has_function(&self, function: &str) -> bool {
+            self.libraries().iter().any(|l| l.functions.iter().any(|f| f.name == function))
+        }
+        
+        fn call_function(&self, _function: &str, _keys: Vec<Bytes>, args: Vec<Bytes>, _db_index: usize, _read_only: bool) -> Result<RespFrame> {
+            Ok(RespFrame::Array(Some(args.into_iter().map(|a| RespFrame::BulkString(Some(a))).collect())))
+        }
+        
+        fn libraries(&self) -> Vec<FunctionLibrary> {
+            self.libraries.lock().unwrap().clone()
+        }
+    }
+    
+    let storage = StorageEngine::new_in_memory();
+    
+    script_engine::register_engine(&storage, Arc::new(EchoEngine { libraries: Mutex::new(Vec::new()) })).unwrap();
+    assert!(script_engine::register_engine(&storage, Arc::new(EchoEngine { libraries: Mutex::new(Vec::new()) })).is_err());
+    assert_eq!(script_engine::engines(&storage).unwrap()[0].name(), "LUA");
+    
+    let load = handle_function(&storage, &[bulk("FUNCTION"), bulk("LOAD"), bulk("#!echo name=echolib echofn")]).unwrap();
+    assert_eq!(load, bulk("echolib"));
+    
+    let reply = handle_fcall_with_db(&storage, &[bulk("FCALL"), bulk("echofn"), bulk("0"), bulk("a"), bulk("b")], 0).unwrap();
+    assert_eq!(reply, RespFrame::Array(Some(vec![bulk("a"), bulk("b")])));
+    
+    let list = handle_function(&storage, &[bulk("FUNCTION"), bulk("LIST"), bulk("LIBRARYNAME"), bulk("echolib")]).unwrap();
+    match list {
+        RespFrame::Array(Some(libraries)) => match &libraries[0] {
+            RespFrame::Array(Some(entry)) => assert_eq!(entry[3], bulk("ECHO")),
+            other => panic!("Expected library entry, got {:?}", other),
+        },
+        other => panic!("Expected library list, got {:?}", other),
+    }
+    
+    // Unknown engines are still rejected, and EVAL keeps running Lua
+    match handle_function(&storage, &[bulk("FUNCTION"), bulk("LOAD"), bulk("#!wasm name=x")]).unwrap() {
+        RespFrame::Error(message) => assert_eq!(&message[..], b"ERR Engine 'wasm' not found"),
+        other => panic!("Expected engine error, got {:?}", other),
+    }
+    assert_eq!(handle_eval(&storage, &create_eval_parts("return 1", 0, &[], &[])).unwrap(), RespFrame::Integer(1));
+}
+
 /// Test complex Lua scripts like Redis would encounter
 #[test]
 fn test_complex_lua_scenarios() {
@@ -251,6 +1417,31 @@ fn test_complex_lua_scenarios() {
     }
 }
 
+/// Test redis.mcall batches with redis.call semantics
+#[test]
+fn test_redis_mcall_batches() {
+    let (storage, engine, ctx) = script_env();
+    
+    let script = r#"
+        local replies = redis.mcall({{'SET', KEYS[1], 'v'}, {'GET', KEYS[1]}, {'GET', 'missing'}, {'INCR', 'n'}})
+        return {#replies, replies[2], tostring(replies[3]), replies[4]}
+    "#;
+    let result = engine.eval(script, vec![Arc::new(b"k".to_vec())], vec![], &ctx).unwrap();
+    assert_eq!(result, RespFrame::Array(Some(vec![
+        RespFrame::Integer(4),
+        RespFrame::BulkString(Some(Arc::new(b"v".to_vec()))),
+        RespFrame::BulkString(Some(Arc::new(b"false".to_vec()))),
+        RespFrame::Integer(1),
+    ])));
+    
+    // A failing command aborts the script like redis.call, after earlier commands ran
+    let script = "return redis.mcall({{'INCR', 'n'}, {'LPUSH', KEYS[1], 'x'}, {'INCR', 'n'}})";
+    assert!(engine.eval(script, vec![Arc::new(b"k".to_vec())], vec![], &ctx).is_err());
+    assert_eq!(storage.get_string(0, b"n").unwrap(), Some(b"2".to_vec()));
+    
+    assert!(engine.eval("return redis.mcall({'GET'})", vec![], vec![], &ctx).is_err());
+}
+
 /// Test error handling in Lua scripts
 #[test]
 fn test_lua_error_handling() {
@@ -269,8 +1460,9 @@ fn test_lua_error_handling() {
     let parts = create_eval_parts("error('test error')", 0, &[], &[]);
     let result = handle_eval(&storage, &parts).unwrap();
     
+    // One line, without mlua's stack traceback
     match result {
-        RespFrame::Error(_) => {}, // Expected
+        RespFrame::Error(msg) => assert_eq!(msg.as_slice(), b"ERR Error running script: user_script:1: test error"),
         _ => panic!("Expected runtime error"),
     }
 }
@@ -319,6 +1511,169 @@ fn test_redis_edge_cases() {
     }
 }
 
+/// Servicer standing in for the server at script checkpoints
+struct RecordingCheckpoints {
+    clients: std::sync::Mutex<Vec<Option<u64>>>,
+}
+
+impl lua_checkpoint::CheckpointService for RecordingCheckpoints {
+    fn service(&self, current: Option<u64>) -> usize {
+        self.clients.lock().unwrap().push(current);
+        2
+    }
+}
+
+/// Test redis.checkpoint: a no-op unless enabled, then rate-limited by the interval
+#[test]
+fn test_script_checkpoints() {
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    let service = Arc::new(RecordingCheckpoints { clients: std::sync::Mutex::new(Vec::new()) });
+    lua_checkpoint::set_service(service.clone());
+    let checkpoint = |script: &str| handle_eval(&storage, &create_eval_parts(script, 0, &[], &[])).unwrap();
+    
+    // Disabled by default: scripts may call it unconditionally
+    assert_eq!(checkpoint("return redis.checkpoint()"), RespFrame::Integer(0));
+    assert!(service.clients.lock().unwrap().is_empty());
+    
+    // Enabled, the running client is excluded from service
+    lua_checkpoint::configure(true, 0);
+    {
+        let _client = lua_checkpoint::ClientScope::begin(7);
+        assert_eq!(checkpoint("return redis.checkpoint() + redis.checkpoint()"), RespFrame::Integer(4));
+    }
+    assert_eq!(*service.clients.lock().unwrap(), vec![Some(7), Some(7)]);
+    
+    // Within the interval of the last yield, checkpoints return at once
+    lua_checkpoint::configure(true, 60_000);
+    assert_eq!(checkpoint("for i = 1, 100 do redis.checkpoint() end return redis.checkpoint()"), RespFrame::Integer(0));
+    assert_eq!(service.clients.lock().unwrap().len(), 2);
+    assert_eq!(lua_checkpoint::served_commands(), 4);
+    
+    lua_checkpoint::configure(false, lua_checkpoint::DEFAULT_INTERVAL_MS);
+}
+
+/// Test SCRIPT LOADLIB libraries resolved by require() from EVAL and FUNCTION libraries
+#[test]
+fn test_script_loadlib_and_require() {
+    use ferrous::storage::commands::lua::{handle_fcall_with_db, handle_function, handle_script_loadlib};
+    
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    let loadlib = |name: &str, source: &str| handle_script_loadlib(&[bulk("SCRIPT"), bulk("LOADLIB"), bulk(name), bulk(source)]);
+    let eval = |script: &str| handle_eval(&storage, &create_eval_parts(script, 0, &[], &[])).unwrap();
+    let error = |reply: RespFrame| match reply {
+        RespFrame::Error(message) => String::from_utf8_lossy(&message).into_owned(),
+        other => panic!("Expected error, got {:?}", other),
+    };
+    
+    let counter = "loads = (loads or 0) + 1\nreturn {double = function(n) return n * 2 end, name = ...}";
+    assert_eq!(loadlib("req.math", counter), RespFrame::ok());
+    
+    // A module runs once per script and can use the script's environment
+    assert_eq!(eval("local m = require('req.math') require('req.math') return {m.double(21), m.name, loads}"),
+        RespFrame::Array(Some(vec![RespFrame::Integer(42), bulk("req.math"), RespFrame::Integer(1)])));
+    assert_eq!(loadlib("req.uses_redis", "return redis.sha1hex('')"), RespFrame::ok());
+    assert_eq!(eval("return require('req.uses_redis')"), bulk("da39a3ee5e6b4b0d3255bfef95601890afd80709"));
+    
+    // Replacing a library takes effect for later scripts; modules returning nothing yield true
+    assert_eq!(loadlib("req.math", "local x = 1"), RespFrame::ok());
+    assert_eq!(eval("return require('req.math')"), RespFrame::Integer(1));
+    
+    // Libraries can require each other, but not in a loop
+    assert_eq!(loadlib("req.loop_a", "return require('req.loop_b')"), RespFrame::ok());
+    assert_eq!(loadlib("req.loop_b", "return require('req.loop_a')"), RespFrame::ok());
+    assert!(error(eval("return require('req.loop_a')")).contains("loop or previous error loading module 'req.loop_a'"));
+    
+    // Only loaded libraries resolve; names and sources are checked at load time
+    assert!(error(eval("return require('os')")).contains("module 'os' not found"));
+    assert!(error(loadlib("../etc/passwd", "return 1")).contains("Library names can only contain"));
+    assert!(error(loadlib("req.broken", "return {")).starts_with("ERR Error compiling library 'req.broken'"));
+    
+    // FUNCTION libraries require into their own environment
+    assert_eq!(loadlib("req.greet", "return function(who) return 'hello ' .. who end"), RespFrame::ok());
+    let library = "#!lua name=reqlib\nlocal greet = require('req.greet')\nredis.register_function('req_greet', function(keys, args) return greet(args[1]) end)";
+    assert_eq!(handle_function(&storage, &[bulk("FUNCTION"), bulk("LOAD"), bulk(library)]).unwrap(), bulk("reqlib"));
+    let fcall = [bulk("FCALL"), bulk("req_greet"), bulk("0"), bulk("world")];
+    assert_eq!(handle_fcall_with_db(&storage, &fcall, 0).unwrap(), bulk("hello world"));
+}
+
+/// Test DEBUG SCRIPTHEALTH and the probe behind it
+#[test]
+fn test_script_health_probe() {
+    use std::time::Duration;
+    use ferrous::storage::commands::debug::handle_debug;
+    
+    let (storage, engine, ctx) = script_env();
+    
+    // The probe replies its latency in microseconds
+    match handle_debug(&[bulk("DEBUG"), bulk("scripthealth")], &storage).unwrap() {
+        RespFrame::Integer(latency) => assert!(latency >= 0),
+        other => panic!("expected a latency, got {:?}", other),
+    }
+    assert!(matches!(handle_debug(&[bulk("DEBUG"), bulk("SCRIPTHEALTH"), bulk("x")], &storage).unwrap(), RespFrame::Error(_)));
+    
+    // Missing the deadline makes the scripting subsystem unhealthy
+    assert!(engine.probe(&ctx, Duration::from_secs(5)).is_ok());
+    let err = engine.probe(&ctx, Duration::ZERO).unwrap_err().to_string();
+    assert!(err.contains("deadline"), "unexpected error: {}", err);
+}
+
+/// Test LPOS, LMOVE and LMPOP from scripts, and that their blocking forms are refused
+#[test]
+fn test_script_list_positions_moves_and_pops() {
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    let eval = |script: &str| handle_eval(&storage, &create_eval_parts(script, 0, &[], &[])).unwrap();
+    
+    eval("redis.call('RPUSH', 'queue', 'a', 'b', 'a', 'c')");
+    assert_eq!(eval("return redis.call('LPOS', 'queue', 'a', 'RANK', -1)"), RespFrame::Integer(2));
+    assert_eq!(
+        eval("return redis.call('LPOS', 'queue', 'a', 'COUNT', 0)"),
+        RespFrame::Array(Some(vec![RespFrame::Integer(0), RespFrame::Integer(2)]))
+    );
+    assert_eq!(eval("return redis.call('LPOS', 'queue', 'z')"), RespFrame::BulkString(None));
+    match eval("return redis.call('LPOS', 'queue', 'a', 'RANK', 0)") {
+        RespFrame::Error(bytes) => assert!(String::from_utf8_lossy(&bytes).contains("RANK can't be zero")),
+        other => panic!("Expected RANK error, got {:?}", other),
+    }
+    
+    assert_eq!(eval("return redis.call('LMOVE', 'queue', 'done', 'LEFT', 'RIGHT')"), bulk("a"));
+    assert_eq!(eval("return redis.call('RPOPLPUSH', 'queue', 'done')"), bulk("c"));
+    assert_eq!(
+        eval("return redis.call('LMPOP', 2, 'missing', 'done', 'RIGHT', 'COUNT', 5)"),
+        RespFrame::Array(Some(vec![bulk("done"), RespFrame::Array(Some(vec![bulk("a"), bulk("c")]))]))
+    );
+    assert_eq!(eval("return redis.call('EXISTS', 'done')"), RespFrame::Integer(0));
+    
+    for script in ["return redis.call('BLMOVE', 'queue', 'done', 'LEFT', 'LEFT', 0)",
+                   "return redis.call('BLMPOP', 0, 1, 'queue', 'LEFT')"] {
+        match eval(script) {
+            RespFrame::Error(bytes) => assert!(String::from_utf8_lossy(&bytes).contains("blocking command is not allowed")),
+            other => panic!("Expected blocking command error, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_script_set_membership_and_intersection_size() {
+    let storage = Arc::new(StorageEngine::new_in_memory());
+    let eval = |script: &str| handle_eval(&storage, &create_eval_parts(script, 0, &[], &[])).unwrap();
+    let integers = |values: &[i64]| RespFrame::Array(Some(values.iter().map(|&v| RespFrame::Integer(v)).collect()));
+    
+    eval("redis.call('SADD', 'flags', 'a', 'b', 'c') redis.call('SADD', 'seen', 'b', 'c', 'd')");
+    assert_eq!(eval("return redis.call('SMISMEMBER', 'flags', 'a', 'd', 'c')"), integers(&[1, 0, 1]));
+    assert_eq!(eval("return redis.call('SMISMEMBER', 'missing', 'a')"), integers(&[0]));
+    assert_eq!(eval("return redis.call('SINTERCARD', 2, 'flags', 'seen')"), RespFrame::Integer(2));
+    assert_eq!(eval("return redis.call('SINTERCARD', 2, 'flags', 'seen', 'LIMIT', 1)"), RespFrame::Integer(1));
+    match eval("return redis.call('SINTERCARD', 0, 'flags')") {
+        RespFrame::Error(bytes) => assert!(String::from_utf8_lossy(&bytes).contains("numkeys should be greater than 0")),
+        other => panic!("Expected numkeys error, got {:?}", other),
+    }
+    
+    // An explicit count always replies with an array, negative counts may repeat members
+    assert_eq!(eval("return #redis.call('SRANDMEMBER', 'flags', -5)"), RespFrame::Integer(5));
+    assert_eq!(eval("return type(redis.call('SRANDMEMBER', 'flags', 1))"), RespFrame::BulkString(Some(Arc::new(b"table".to_vec()))));
+    assert_eq!(eval("return redis.call('SRANDMEMBER', 'missing')"), RespFrame::BulkString(None));
+}
+
 /// Helper function to create EVAL command parts
 fn create_eval_parts(script: &str, num_keys: i64, keys: &[&str], args: &[&str]) -> Vec<RespFrame> {
     let mut parts = vec![
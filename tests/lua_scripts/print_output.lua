@@ -0,0 +1,7 @@
+-- printed output is checked against print_output.out with --capture-output
+print('values', 1, true, nil)
+for i = 1, 3 do
+    print(string.rep('*', i))
+end
+print(cjson.encode({1, 2}))
+return 'PASS'
@@ -0,0 +1,10 @@
+-- string library behaviour scripts rely on
+assert(string.format('%05.1f', 3.14159) == '003.1')
+assert(('a,b,,c'):gsub(',', ';') == 'a;b;;c')
+assert(select(2, ('a,b,,c'):gsub(',', ';')) == 3)
+assert(('hello'):find('l') == 3)
+assert(('key:42'):match('^key:(%d+)$') == '42')
+assert(string.rep('ab', 3) == 'ababab')
+assert(('Ferrous'):upper() == 'FERROUS')
+assert(#string.char(0, 255) == 2)
+return 'PASS'
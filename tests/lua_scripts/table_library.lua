@@ -0,0 +1,10 @@
+-- table library behaviour scripts rely on
+local t = {3, 1, 2}
+table.sort(t)
+assert(table.concat(t, ',') == '1,2,3')
+table.insert(t, 1, 0)
+assert(t[1] == 0 and #t == 4)
+assert(table.remove(t) == 3)
+assert(table.getn(t) == 3)
+assert(select('#', unpack({1, 2, 3})) == 3)
+return 'PASS'
@@ -0,0 +1,77 @@
+//! Scripting state saved in RDB aux fields
+//!
+//! Kept in its own test binary: the script cache, the function registry and
+//! the SCRIPT LOADLIB libraries are process-wide.
+
+use std::sync::Arc;
+use ferrous::storage::{RdbConfig, RdbEngine, StorageEngine};
+use ferrous::storage::commands::lua::{handle_fcall_with_db, handle_function, handle_script_load, handle_script_loadlib};
+use ferrous::storage::lua_engine::get_lua_engine;
+use ferrous::storage::lua_require;
+use ferrous::protocol::resp::RespFrame;
+
+const LIBRARY: &str = r#"#!lua name=rdblib
+local helpers = require('rdbhelpers')
+redis.register_function('greet', function(keys, args) return helpers.greet(args[1]) end)
+"#;
+
+fn bulk(s: &str) -> RespFrame {
+    RespFrame::BulkString(Some(Arc::new(s.as_bytes().to_vec())))
+}
+
+fn rdb_engine(filename: &str) -> RdbEngine {
+    RdbEngine::new(RdbConfig {
+        filename: filename.to_string(),
+        dir: std::env::temp_dir().to_string_lossy().into_owned(),
+        ..Default::default()
+    })
+}
+
+fn flush_scripting(storage: &Arc<StorageEngine>) {
+    assert_eq!(handle_function(storage, &[bulk("FUNCTION"), bulk("FLUSH")]).unwrap(), RespFrame::ok());
+    get_lua_engine(storage.clone()).unwrap().flush_precompiled();
+    lua_require::flush();
+}
+
+#[test]
+fn test_scripting_state_survives_restart() {
+    let storage = StorageEngine::new_in_memory();
+    let helpers = "local M = {} function M.greet(name) return 'hello ' .. name end return M";
+    assert_eq!(handle_script_loadlib(&[bulk("SCRIPT"), bulk("LOADLIB"), bulk("rdbhelpers"), bulk(helpers)]), RespFrame::ok());
+    assert_eq!(handle_function(&storage, &[bulk("FUNCTION"), bulk("LOAD"), bulk(LIBRARY)]).unwrap(), bulk("rdblib"));
+    let (sha1, _) = handle_script_load(&storage, &[bulk("LOAD"), bulk("return 'cached'")]).unwrap();
+    storage.set_string(0, b"key".to_vec(), b"value".to_vec()).unwrap();
+    
+    let engine = rdb_engine("ferrous_test_scripting.rdb");
+    engine.save(&storage).unwrap();
+    flush_scripting(&storage);
+    assert!(!get_lua_engine(storage.clone()).unwrap().script_exists(&sha1));
+    
+    // Loading restores libraries, functions and cached scripts along with the keys
+    engine.load(&storage).unwrap();
+    let fcall = [bulk("FCALL"), bulk("greet"), bulk("0"), bulk("rdb")];
+    assert_eq!(handle_fcall_with_db(&storage, &fcall, 0).unwrap(), bulk("hello rdb"));
+    assert!(get_lua_engine(storage.clone()).unwrap().script_exists(&sha1));
+    assert!(lua_require::library("rdbhelpers").is_some());
+    assert_eq!(storage.get_string(0, b"key").unwrap(), Some(b"value".to_vec()));
+    
+    // Fields from a newer scripting version are skipped, not misread
+    flush_scripting(&storage);
+    let mut rdb = b"REDIS0009".to_vec();
+    for (key, value) in [("ferrous-scripting-ver", "99"), ("ferrous-script", "return 'future'")] {
+        rdb.push(0xFA);
+        rdb.push(key.len() as u8);
+        rdb.extend_from_slice(key.as_bytes());
+        rdb.push(value.len() as u8);
+        rdb.extend_from_slice(value.as_bytes());
+    }
+    rdb.push(0xFF);
+    rdb.extend_from_slice(&[0; 8]);
+    let path = std::env::temp_dir().join("ferrous_test_scripting_future.rdb");
+    std::fs::write(&path, rdb).unwrap();
+    rdb_engine("ferrous_test_scripting_future.rdb").load(&storage).unwrap();
+    assert!(get_lua_engine(storage.clone()).unwrap().cached_scripts().is_empty());
+    
+    std::fs::remove_file(std::env::temp_dir().join("ferrous_test_scripting.rdb")).ok();
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,70 @@
+//! Scripts failing at random points leave no state behind
+//!
+//! Kept in its own test binary: the budgets set here apply to the process-wide
+//! engine that FCALL uses.
+
+use std::sync::Arc;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use ferrous::storage::engine::StorageEngine;
+use ferrous::storage::commands::lua::{handle_eval, handle_fcall_with_db, handle_function};
+use ferrous::storage::lua_engine::get_lua_engine;
+use ferrous::storage::lua_invariants::Snapshot;
+use ferrous::storage::lua_stats;
+use ferrous::protocol::resp::RespFrame;
+
+/// Mixes commands, error replies, pcall, recursion and allocation so failures land everywhere
+const BODY: &str = r#"
+local function walk(n) if n == 0 then return 0 end return 1 + walk(n - 1) end
+local total = 0
+for i = 1, 200 do
+    redis.call('INCR', KEYS[1])
+    pcall(function() error('boom') end)
+    redis.pcall('HSET', KEYS[1], 'f', 'v')
+    total = total + walk(20) + #string.rep('x', i % 10)
+    redis.call('SET', KEYS[2], tostring(total))
+end
+return total
+"#;
+
+/// What BODY returns when it runs to completion
+const TOTAL: i64 = 4900;
+
+fn bulk(s: &str) -> RespFrame {
+    RespFrame::BulkString(Some(Arc::new(s.as_bytes().to_vec())))
+}
+
+#[test]
+fn test_errors_at_random_points_restore_state() {
+    let storage = StorageEngine::new_in_memory();
+    let engine = get_lua_engine(storage.clone()).unwrap();
+    let library = format!("#!lua name=chaos\nredis.register_function('churn', function(KEYS, ARGV)\n{}\nend)", BODY);
+    assert_eq!(handle_function(&storage, &[bulk("FUNCTION"), bulk("LOAD"), bulk(&library)]).unwrap(), bulk("chaos"));
+    
+    let eval = || handle_eval(&storage, &[bulk("EVAL"), bulk(BODY), bulk("2"), bulk("chaos:n"), bulk("chaos:total")]).unwrap();
+    let fcall = || handle_fcall_with_db(&storage, &[bulk("FCALL"), bulk("churn"), bulk("2"), bulk("chaos:n"), bulk("chaos:total")], 0).unwrap();
+    
+    assert_eq!(eval(), RespFrame::Integer(TOTAL));
+    let instructions = lua_stats::last().instructions;
+    let before = Snapshot::take();
+    
+    let mut rng = StdRng::seed_from_u64(1515);
+    for round in 0..100 {
+        // Fail at a random instruction, sometimes at a random call depth instead
+        engine.set_max_instructions(rng.gen_range(1..=instructions));
+        engine.set_max_call_depth(if rng.gen_bool(0.2) { rng.gen_range(1..=24) } else { 0 });
+        let reply = if round % 2 == 0 { eval() } else { fcall() };
+        match &reply {
+            RespFrame::Integer(TOTAL) => {}
+            RespFrame::Error(message) => assert!(String::from_utf8_lossy(message).contains("exceeded"), "round {}: {:?}", round, reply),
+            other => panic!("round {}: unexpected reply {:?}", round, other),
+        }
+        
+        // Thread state is restored and both the fresh and the shared VM keep working
+        assert_eq!(Snapshot::take(), before, "round {}", round);
+        engine.set_max_instructions(0);
+        engine.set_max_call_depth(0);
+        assert_eq!(fcall(), RespFrame::Integer(TOTAL), "round {}", round);
+        assert_eq!(eval(), RespFrame::Integer(TOTAL), "round {}", round);
+    }
+}
@@ -0,0 +1,115 @@
+//! Truthiness and equality conformance of the two script states
+//!
+//! EVAL scripts run in a fresh VM per call while FUNCTION libraries share one
+//! long-lived state with read-only globals. Both must follow Lua 5.1: only
+//! `nil` and `false` are falsey, NaN is unequal to itself, and values of
+//! different types are never equal, even with an `__eq` metamethod. The matrix
+//! below runs in both states so the two cannot drift apart. Kept in its own
+//! test binary: FUNCTION libraries live in one process-wide registry.
+
+use std::sync::Arc;
+use ferrous::storage::engine::StorageEngine;
+use ferrous::storage::commands::lua::{handle_eval, handle_fcall_with_db, handle_function};
+use ferrous::protocol::resp::RespFrame;
+
+/// Values and whether a condition treats them as true
+const TRUTHINESS: &[(&str, bool)] = &[
+    ("nil", false),
+    ("false", false),
+    ("true", true),
+    ("0", true),
+    ("-0", true),
+    ("0 / 0", true),
+    ("''", true),
+    ("'0'", true),
+    ("'false'", true),
+    ("{}", true),
+    ("print", true),
+    ("coroutine.create(function() end)", true),
+];
+
+/// Comparisons and their result
+const EQUALITY: &[(&str, bool)] = &[
+    ("0 / 0 == 0 / 0", false),
+    ("(function() local n = 0 / 0 return n ~= n end)()", true),
+    ("(function() local n = 0 / 0 return rawequal(n, n) end)()", false),
+    ("0 == -0", true),
+    ("1 == 1.0", true),
+    ("1 == '1'", false),
+    ("'1' + 0 == 1", true),
+    ("0 == false", false),
+    ("nil == false", false),
+    ("'' == false", false),
+    ("'abc' == 'ab' .. 'c'", true),
+    ("{} == {}", false),
+    ("(function() local t = {} return t == t end)()", true),
+    ("(function() local m = {__eq = function() return true end} \
+        return setmetatable({}, m) == setmetatable({}, m) end)()", true),
+    ("(function() local m = {__eq = function() return true end} return setmetatable({}, m) == 1 end)()", false),
+    ("(function() local m = {__eq = function() return true end} return setmetatable({}, m) == 'x' end)()", false),
+    ("(function() local m = {__eq = function() return true end} \
+        return rawequal(setmetatable({}, m), setmetatable({}, m)) end)()", false),
+];
+
+fn bulk(s: &str) -> RespFrame {
+    RespFrame::BulkString(Some(Arc::new(s.as_bytes().to_vec())))
+}
+
+/// A script returning one `true`/`false` word per matrix entry
+fn matrix_script() -> String {
+    let truthiness = TRUTHINESS.iter().map(|(value, _)| format!("tostring(not not ({}))", value));
+    let equality = EQUALITY.iter().map(|(comparison, _)| format!("tostring({})", comparison));
+    format!("return table.concat({{{}}}, ' ')", truthiness.chain(equality).collect::<Vec<_>>().join(", "))
+}
+
+/// The reply [`matrix_script`] must produce
+fn expected_reply() -> RespFrame {
+    let words: Vec<String> = TRUTHINESS.iter().chain(EQUALITY).map(|(_, expected)| expected.to_string()).collect();
+    bulk(&words.join(" "))
+}
+
+/// Name each matrix entry whose word in `reply` differs from the expected one
+fn mismatches(reply: &RespFrame) -> Vec<String> {
+    let bytes = match reply {
+        RespFrame::BulkString(Some(bytes)) => bytes,
+        RespFrame::Error(message) => return vec![String::from_utf8_lossy(message).into_owned()],
+        other => return vec![format!("unexpected reply {:?}", other)],
+    };
+    let words: Vec<String> = String::from_utf8_lossy(bytes).split(' ').map(str::to_string).collect();
+    TRUTHINESS.iter().chain(EQUALITY).zip(words.iter().map(String::as_str).chain(std::iter::repeat("missing")))
+        .filter(|((_, expected), got)| expected.to_string() != *got)
+        .map(|((case, expected), got)| format!("{}: expected {}, got {}", case, expected, got))
+        .collect()
+}
+
+#[test]
+fn test_eval_matches_the_matrix() {
+    let storage = StorageEngine::new_in_memory();
+    let reply = handle_eval(&storage, &[bulk("EVAL"), bulk(&matrix_script()), bulk("0")]).unwrap();
+    assert!(mismatches(&reply).is_empty(), "EVAL: {:?}", mismatches(&reply));
+    assert_eq!(reply, expected_reply());
+}
+
+#[test]
+fn test_functions_match_the_matrix() {
+    let storage = StorageEngine::new_in_memory();
+    let code = format!("#!lua name=conformance\nredis.register_function('conformance', function() {} end)", matrix_script());
+    let loaded = handle_function(&storage, &[bulk("FUNCTION"), bulk("LOAD"), bulk(&code)]).unwrap();
+    assert_eq!(loaded, bulk("conformance"));
+    
+    let reply = handle_fcall_with_db(&storage, &[bulk("FCALL"), bulk("conformance"), bulk("0")], 0).unwrap();
+    assert!(mismatches(&reply).is_empty(), "FCALL: {:?}", mismatches(&reply));
+    assert_eq!(reply, expected_reply());
+}
+
+#[test]
+fn test_replies_convert_booleans_alike() {
+    let storage = StorageEngine::new_in_memory();
+    let script = "return {true, false, 0, ''}";
+    let eval = handle_eval(&storage, &[bulk("EVAL"), bulk(script), bulk("0")]).unwrap();
+    
+    let code = format!("#!lua name=booleans\nredis.register_function('booleans', function() {} end)", script);
+    handle_function(&storage, &[bulk("FUNCTION"), bulk("LOAD"), bulk(&code)]).unwrap();
+    let fcall = handle_fcall_with_db(&storage, &[bulk("FCALL"), bulk("booleans"), bulk("0")], 0).unwrap();
+    assert_eq!(eval, fcall);
+}
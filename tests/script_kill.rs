@@ -0,0 +1,53 @@
+//! SCRIPT KILL and busy scripts
+//!
+//! Kept in its own test binary: the busy servicer is process-wide.
+
+use std::sync::{Arc, Mutex};
+use ferrous::error::{FerrousError, ScriptError};
+use ferrous::storage::StorageEngine;
+use ferrous::storage::commands::lua::handle_script_kill;
+use ferrous::storage::lua_busy::{self, BusyService};
+use ferrous::storage::lua_engine::{LuaCommandContext, LuaEngine};
+use ferrous::protocol::resp::RespFrame;
+
+/// Sends SCRIPT KILL each time the busy script services other clients
+struct Killer {
+    replies: Mutex<Vec<RespFrame>>,
+}
+
+impl BusyService for Killer {
+    fn service(&self, _current: Option<u64>) -> usize {
+        let kill = [RespFrame::bulk_string("SCRIPT"), RespFrame::bulk_string("KILL")];
+        self.replies.lock().unwrap().push(handle_script_kill(&kill));
+        1
+    }
+}
+
+#[test]
+fn test_script_kill() {
+    let storage = StorageEngine::new_in_memory();
+    let engine = LuaEngine::new(storage.clone()).unwrap();
+    let ctx = LuaCommandContext { db_index: 0, storage: storage.clone() };
+    let killer = Arc::new(Killer { replies: Mutex::new(Vec::new()) });
+    lua_busy::set_service(killer.clone());
+    let kill = [RespFrame::bulk_string("SCRIPT"), RespFrame::bulk_string("KILL")];
+    
+    // With no busy script there is nothing to kill
+    assert!(matches!(handle_script_kill(&kill), RespFrame::Error(e) if e.starts_with(b"NOTBUSY ")));
+    
+    // A busy script is killed at its next check, even when it catches errors
+    engine.set_time_limit_ms(50);
+    for script in ["while true do end", "while true do pcall(function() while true do end end) end"] {
+        let err = engine.eval(script, vec![], vec![], &ctx).unwrap_err();
+        assert!(matches!(err, FerrousError::Script(ScriptError::Killed)));
+    }
+    assert!(killer.replies.lock().unwrap().iter().all(|reply| *reply == RespFrame::ok()));
+    
+    // A script that already wrote cannot be killed and runs to completion
+    killer.replies.lock().unwrap().clear();
+    let script = "redis.call('SET', 'k', 'v') local n = 0 for i = 1, 1e7 do n = n + 1 end return n";
+    assert_eq!(engine.eval(script, vec![], vec![], &ctx).unwrap(), RespFrame::Integer(10_000_000));
+    let replies = killer.replies.lock().unwrap();
+    assert!(!replies.is_empty());
+    assert!(replies.iter().all(|reply| matches!(reply, RespFrame::Error(e) if e.starts_with(b"UNKILLABLE "))));
+}
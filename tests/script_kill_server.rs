@@ -0,0 +1,66 @@
+//! SCRIPT KILL from clients that connect while a script is busy
+//!
+//! Kept in its own test binary: the server installs the process-wide busy
+//! servicer.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use ferrous::{Config, Server};
+
+/// Send a command and read its one-line reply
+fn command(stream: &mut BufReader<TcpStream>, args: &[&str]) -> String {
+    let mut request = format!("*{}\r\n", args.len());
+    for arg in args {
+        request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+    }
+    stream.get_mut().write_all(request.as_bytes()).unwrap();
+    read_line(stream)
+}
+
+fn read_line(stream: &mut BufReader<TcpStream>) -> String {
+    let mut line = String::new();
+    stream.read_line(&mut line).unwrap();
+    line.trim_end().to_string()
+}
+
+fn connect(port: u16) -> BufReader<TcpStream> {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)) {
+            stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+            return BufReader::new(stream);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("server did not start on port {}", port);
+}
+
+#[test]
+fn test_script_kill_from_new_connection() {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = Config::default();
+    config.network.bind_addr = "127.0.0.1".to_string();
+    config.network.port = port;
+    config.network.password = Some("secret".to_string());
+    config.rdb.dir = dir.path().to_string_lossy().to_string();
+    config.scripting.time_limit_ms = 100;
+    thread::spawn(move || Server::from_config(config).unwrap().run());
+    
+    let mut runner = connect(port);
+    assert_eq!(command(&mut runner, &["AUTH", "secret"]), "+OK");
+    let request = "*3\r\n$4\r\nEVAL\r\n$17\r\nwhile true do end\r\n$1\r\n0\r\n";
+    runner.get_mut().write_all(request.as_bytes()).unwrap();
+    thread::sleep(Duration::from_millis(300));
+    
+    // A client connecting after the script went busy authenticates and kills it
+    let mut killer = connect(port);
+    assert_eq!(command(&mut killer, &["PING"]), "-NOAUTH Authentication required");
+    assert_eq!(command(&mut killer, &["AUTH", "secret"]), "+OK");
+    assert!(command(&mut killer, &["GET", "k"]).starts_with("-BUSY "));
+    assert_eq!(command(&mut killer, &["SCRIPT", "KILL"]), "+OK");
+    
+    assert!(read_line(&mut runner).starts_with("-KILLED "));
+    assert_eq!(command(&mut killer, &["PING"]), "+PONG");
+}
@@ -0,0 +1,94 @@
+//! Per-user script quotas enforced for clients authenticated as ACL users
+//!
+//! Kept in its own test binary: the server installs the process-wide busy
+//! servicer.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use ferrous::{Config, Server};
+
+/// Send a command and read its reply, arrays flattened into one line
+fn command(stream: &mut BufReader<TcpStream>, args: &[&str]) -> String {
+    let mut request = format!("*{}\r\n", args.len());
+    for arg in args {
+        request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+    }
+    stream.get_mut().write_all(request.as_bytes()).unwrap();
+    read_reply(stream)
+}
+
+fn read_reply(stream: &mut BufReader<TcpStream>) -> String {
+    let mut line = String::new();
+    stream.read_line(&mut line).unwrap();
+    let line = line.trim_end().to_string();
+    match (line.chars().next(), line[1..].parse::<usize>()) {
+        (Some('$'), Ok(len)) => {
+            let mut body = vec![0; len + 2];
+            stream.read_exact(&mut body).unwrap();
+            String::from_utf8_lossy(&body[..len]).into_owned()
+        }
+        (Some('*'), Ok(len)) => (0..len).map(|_| read_reply(stream)).collect::<Vec<_>>().join(" "),
+        _ => line,
+    }
+}
+
+fn connect(port: u16) -> BufReader<TcpStream> {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)) {
+            stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+            return BufReader::new(stream);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("server did not start on port {}", port);
+}
+
+#[test]
+fn test_script_quotas_follow_the_acl_user() {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = Config::default();
+    config.network.bind_addr = "127.0.0.1".to_string();
+    config.network.port = port;
+    config.network.password = Some("secret".to_string());
+    config.rdb.dir = dir.path().to_string_lossy().to_string();
+    thread::spawn(move || Server::from_config(config).unwrap().run());
+    
+    let mut admin = connect(port);
+    assert_eq!(command(&mut admin, &["AUTH", "secret"]), "+OK");
+    let rules = ["ACL", "SETUSER", "alice", "on", ">pw", "~*", "+@all",
+                 "script-max-concurrent=1", "script-cpu-ms-per-minute=200", "script-max-memory=1000000"];
+    assert_eq!(command(&mut admin, &rules), "+OK");
+    assert!(command(&mut admin, &["ACL", "SETUSER", "default", "nopass"]).starts_with("-ERR Error in ACL SETUSER modifier 'nopass'"));
+    
+    let mut alice = connect(port);
+    assert!(command(&mut alice, &["AUTH", "alice", "wrong"]).starts_with("-WRONGPASS "));
+    assert_eq!(command(&mut alice, &["AUTH", "alice", "pw"]), "+OK");
+    assert_eq!(command(&mut alice, &["ACL", "WHOAMI"]), "alice");
+    
+    // The heap quota caps the script's VM, and a script is stopped once the minute's time is spent
+    assert_eq!(command(&mut alice, &["EVAL", "return 1", "0"]), ":1");
+    assert!(command(&mut alice, &["EVAL", "return #string.rep('x', 4000000)", "0"]).starts_with("-OOM "));
+    let stopped = command(&mut alice, &["EVAL", "while true do end", "0"]);
+    assert!(stopped.starts_with("-ERR Script exceeded its per-user script time allowance of "), "{}", stopped);
+    assert_eq!(command(&mut alice, &["EVAL", "return 1", "0"]),
+               "-ERR Script quota exceeded: user 'alice' used its 200 ms of script time for this minute");
+    assert_eq!(command(&mut admin, &["EVAL", "return 1", "0"]), ":1");
+    
+    let user = command(&mut admin, &["ACL", "GETUSER", "alice"]);
+    assert!(user.contains("script-quota max-concurrent :1 cpu-ms-per-minute :200 max-memory :1000000"), "{}", user);
+    assert!(user.contains("script-usage running :0 cpu-ms-this-minute :"), "{}", user);
+    assert!(user.ends_with("scripts :3 rejected :1"), "{}", user);
+    let clients = command(&mut admin, &["INFO", "clients"]);
+    assert!(clients.contains("script_user_alice:running=0,scripts=3,rejected=1,cpu_ms="), "{}", clients);
+    assert!(!clients.contains("script_user_default"), "{}", clients);
+    
+    // Deleting the user ends its sessions
+    assert_eq!(command(&mut admin, &["ACL", "DELUSER", "alice"]), ":1");
+    thread::sleep(Duration::from_millis(200));
+    let mut line = String::new();
+    alice.get_mut().write_all(b"PING\r\n").ok();
+    assert_eq!(alice.read_line(&mut line).unwrap_or(0), 0, "{}", line);
+}
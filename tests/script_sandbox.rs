@@ -0,0 +1,177 @@
+//! Script sandbox escape tests
+//!
+//! The checklist below is every global and standard library entry an EVAL
+//! script can reach. Adding a library or a global fails
+//! `test_eval_environment_matches_checklist` until the checklist is updated,
+//! so widening the sandbox is always a reviewed change. Kept in its own test
+//! binary: FUNCTION libraries live in one process-wide registry.
+
+use std::sync::Arc;
+use ferrous::storage::engine::StorageEngine;
+use ferrous::storage::commands::lua::{handle_eval, handle_fcall_with_db, handle_function};
+use ferrous::protocol::resp::RespFrame;
+
+/// Globals of an EVAL script with the default configuration
+const GLOBALS: &str = "ARGV KEYS _G _VERSION assert bit cjson collectgarbage coroutine error ferrous gcinfo getfenv \
+    getmetatable ipairs loadstring math newproxy next os pairs pcall print rawequal rawget rawset redis require select \
+    setfenv setmetatable string table tonumber tostring type unpack xpcall";
+
+/// Entries of the standard library tables scripts can reach
+const LIBRARIES: &[(&str, &str)] = &[
+    ("os", "clock date time"),
+    ("string", "byte char dump find format gfind gmatch gsub len lower match rep reverse sub upper"),
+    ("table", "concat foreach foreachi getn insert maxn remove setn sort"),
+    ("math", "abs acos asin atan atan2 ceil cos cosh deg exp floor fmod frexp huge ldexp log log10 max min mod \
+        modf pi pow rad random randomseed sin sinh sqrt tan tanh"),
+    ("coroutine", "create resume running status wrap yield"),
+];
+
+/// Expressions that would reach the host if the sandbox leaked
+const HOST_ACCESS: &[&str] = &[
+    "io", "debug", "package", "module", "dofile", "loadfile", "load",
+    "os.execute", "os.getenv", "os.remove", "os.rename", "os.exit", "os.tmpname", "os.difftime", "os.setlocale",
+];
+
+fn bulk(s: &str) -> RespFrame {
+    RespFrame::BulkString(Some(Arc::new(s.as_bytes().to_vec())))
+}
+
+fn eval(storage: &Arc<StorageEngine>, script: &str) -> RespFrame {
+    handle_eval(storage, &[bulk("EVAL"), bulk(script), bulk("0")]).unwrap()
+}
+
+fn eval_string(storage: &Arc<StorageEngine>, script: &str) -> String {
+    match eval(storage, script) {
+        RespFrame::BulkString(Some(bytes)) => String::from_utf8_lossy(&bytes).into_owned(),
+        other => panic!("{} returned {:?}", script, other),
+    }
+}
+
+fn function_load(storage: &Arc<StorageEngine>, code: &str) -> RespFrame {
+    handle_function(storage, &[bulk("FUNCTION"), bulk("LOAD"), bulk(code)]).unwrap()
+}
+
+fn fcall(storage: &Arc<StorageEngine>, function: &str) -> RespFrame {
+    handle_fcall_with_db(storage, &[bulk("FCALL"), bulk(function), bulk("0")], 0).unwrap()
+}
+
+fn is_error(frame: &RespFrame) -> bool {
+    matches!(frame, RespFrame::Error(_))
+}
+
+#[test]
+fn test_eval_environment_matches_checklist() {
+    let storage = StorageEngine::new_in_memory();
+    let keys = |table: &str| eval_string(&storage, &format!(
+        "local t = {{}} for k in pairs({}) do t[#t + 1] = k end table.sort(t) return table.concat(t, ' ')", table));
+    let words = |list: &str| list.split_whitespace().collect::<Vec<_>>().join(" ");
+    
+    assert_eq!(keys("_G"), words(GLOBALS), "script globals changed");
+    for (library, entries) in LIBRARIES {
+        assert_eq!(keys(library), words(entries), "{} library changed", library);
+    }
+}
+
+#[test]
+fn test_host_access_is_unavailable() {
+    let storage = StorageEngine::new_in_memory();
+    for expression in HOST_ACCESS {
+        assert_eq!(eval_string(&storage, &format!("return type({})", expression)), "nil", "{} is reachable", expression);
+    }
+    for module in ["io", "os", "debug", "package", "ffi"] {
+        assert!(is_error(&eval(&storage, &format!("return require('{}')", module))), "require('{}') loaded", module);
+    }
+    
+    // The environment and process are not exposed through the remaining libraries
+    assert_eq!(eval_string(&storage, "return type(os.date('*t'))"), "table");
+    assert_eq!(eval_string(&storage, "return tostring(getfenv(0).io)"), "nil");
+}
+
+#[test]
+fn test_bytecode_is_refused() {
+    let storage = StorageEngine::new_in_memory();
+    let bytecode = match eval(&storage, "return string.dump(function() return 'escaped' end)") {
+        RespFrame::BulkString(Some(bytes)) => bytes,
+        other => panic!("string.dump returned {:?}", other),
+    };
+    assert_eq!(bytecode[0], 27);
+    
+    let reply = handle_eval(&storage, &[bulk("EVAL"), RespFrame::BulkString(Some(bytecode)), bulk("0")]).unwrap();
+    assert!(is_error(&reply), "binary chunk ran as a script: {:?}", reply);
+    assert_eq!(
+        eval_string(&storage, "local f, err = loadstring(string.dump(function() return 1 end)) return tostring(f) .. ': ' .. err"),
+        "nil: loading binary chunks is not allowed");
+    assert_eq!(eval_string(&storage, "return loadstring('return \"text\"')()"), "text");
+}
+
+#[test]
+fn test_eval_state_does_not_persist() {
+    let storage = StorageEngine::new_in_memory();
+    eval(&storage, "leaked = 'x' string.leaked = 'x' getmetatable('').__index.leaked = 'x' getfenv(0).also = 'x' return 1");
+    assert_eq!(
+        eval_string(&storage, "return tostring(leaked) .. tostring(string.leaked) .. tostring(('s').leaked) .. tostring(also)"),
+        "nilnilnilnil");
+}
+
+#[test]
+fn test_function_libraries_cannot_reach_each_other() {
+    let storage = StorageEngine::new_in_memory();
+    let attempts = [
+        "string.upper = function() return 'pwned' end",
+        "table.insert = nil",
+        "math.max = function() return -1 end",
+        "getfenv(0).tostring = function() return 'pwned' end",
+        "getfenv(0).leaked = 'x'",
+        "_G.leaked = 'x'",
+        "rawset(_G, 'leaked', 'x')",
+        "rawset(string, 'leaked', 'x')",
+        "getmetatable('').__index.lower = function() return 'pwned' end",
+        "getmetatable('').__index = {}",
+        "setmetatable(_G, nil)",
+        "setfenv(0, {leaked = 'x'})",
+        "setfenv(print, {leaked = 'x'}) leaked = getfenv(print).leaked getfenv(0).leaked = leaked",
+        "loadstring('leaked = 1')()",
+        "loadstring(string.dump(function() end))()",
+    ];
+    for (i, attempt) in attempts.iter().enumerate() {
+        let code = format!("#!lua name=attack{}\n{}\nredis.register_function('attack{}', function() return 1 end)", i, attempt, i);
+        assert!(is_error(&function_load(&storage, &code)), "library ran: {}", attempt);
+    }
+    
+    // Functions are refused the same way when called
+    let code = "#!lua name=late\nredis.register_function('late', function() getfenv(0).leaked = 'x' return 1 end)";
+    assert_eq!(function_load(&storage, code), bulk("late"));
+    assert!(is_error(&fcall(&storage, "late")));
+    
+    let code = "#!lua name=victim\nredis.register_function('victim', function()\n\
+        return ('a'):upper() .. ('B'):lower() .. math.max(1, 2) .. type(table.insert) .. tostring(leaked) .. tostring(string.leaked)\n\
+        end)";
+    assert_eq!(function_load(&storage, code), bulk("victim"));
+    assert_eq!(fcall(&storage, "victim"), bulk("Ab2functionnilnil"));
+    
+    // Library-level state stays in the library's own environment
+    let code = "#!lua name=own\ncounter = 0\nlocal function bump() counter = counter + 1 return counter end\n\
+        redis.register_function('own', bump)";
+    assert_eq!(function_load(&storage, code), bulk("own"));
+    assert_eq!(fcall(&storage, "own"), RespFrame::Integer(1));
+    assert_eq!(fcall(&storage, "own"), RespFrame::Integer(2));
+    
+    let code = "#!lua name=host\nredis.register_function('host', function()\n\
+        return tostring(io) .. tostring(debug) .. tostring(package) .. tostring(os.execute) .. tostring(os.getenv) .. tostring(loadfile)\n\
+        end)";
+    assert_eq!(function_load(&storage, code), bulk("host"));
+    assert_eq!(fcall(&storage, "host"), bulk("nilnilnilnilnilnil"));
+}
+
+#[test]
+fn test_strings_share_the_string_metatable() {
+    let storage = StorageEngine::new_in_memory();
+    let script = "local meta = getmetatable('') \
+        return tostring(meta.__index == string) .. ' ' .. tostring(getmetatable('abc') == meta) .. ' ' \
+        .. ('abc'):upper() .. ('%d-%s'):format(1, 'x') .. ('a,b'):gsub(',', ';')";
+    assert_eq!(eval(&storage, script), bulk("true true ABC1-xa;b"));
+    
+    let code = format!("#!lua name=strings\nredis.register_function('strings', function() {} end)", script);
+    assert_eq!(function_load(&storage, &code), bulk("strings"));
+    assert_eq!(fcall(&storage, "strings"), bulk("true true ABC1-xa;b"));
+}